@@ -21,6 +21,40 @@ enum AudioConversion {
         return floatSamples
     }
 
+    /// Convert 24-bit packed PCM samples to 32-bit float samples
+    /// - Parameter pcmData: Raw PCM data (3 bytes per sample, little-endian)
+    /// - Returns: Array of float samples normalized to [-1.0, 1.0]
+    static func pcm24ToFloat(_ pcmData: Data) -> [Float] {
+        let bytes = [UInt8](pcmData)
+        return stride(from: 0, to: bytes.count - bytes.count % 3, by: 3).map { i in
+            // Shifted up into an Int32 and back down to sign-extend
+            let value = (Int32(bytes[i]) << 8 | Int32(bytes[i + 1]) << 16 | Int32(bytes[i + 2]) << 24) >> 8
+            return Float(value) / 8388608.0
+        }
+    }
+
+    /// Read 32-bit float samples
+    /// - Parameter data: Raw float data (little-endian)
+    /// - Returns: Array of float samples
+    static func float32Samples(_ data: Data) -> [Float] {
+        let bytes = [UInt8](data)
+        return stride(from: 0, to: bytes.count - bytes.count % 4, by: 4).map { i in
+            Float(bitPattern: UInt32(bytes[i]) | UInt32(bytes[i + 1]) << 8 | UInt32(bytes[i + 2]) << 16 | UInt32(bytes[i + 3]) << 24)
+        }
+    }
+
+    /// Average interleaved channels into mono
+    /// - Parameters:
+    ///   - samples: Interleaved float samples
+    ///   - channels: Channel count
+    /// - Returns: One sample per frame
+    static func downmix(_ samples: [Float], channels: Int) -> [Float] {
+        guard channels > 1 else { return samples }
+        return stride(from: 0, to: samples.count - samples.count % channels, by: channels).map { i in
+            samples[i..<i + channels].reduce(0, +) / Float(channels)
+        }
+    }
+
     /// Convert 32-bit float samples to 16-bit PCM data
     /// - Parameter floatSamples: Float samples in range [-1.0, 1.0]
     /// - Returns: Raw PCM data (Int16, little-endian)
@@ -255,34 +255,20 @@ class AudioManager: ObservableObject {
 
     // MARK: - Playback (from network)
 
-    func playAudio(data: Data) {
+    /// Mono samples as NetworkManager decodes them (16-bit, 24-bit and float
+    /// streams all arrive as floats, so none is rounded on the way)
+    func playAudio(samples: [Float]) {
         guard isRunning else {
             return
         }
 
-        let frameCount = data.count / 2  // 16-bit = 2 bytes per sample
+        let frameCount = samples.count
         guard frameCount > 0 else { return }
 
-        // Convert to float samples directly into jitter buffer
-        var rmsLevel: Float = 0
+        let rmsLevel = AudioConversion.calculateRMS(samples)
 
         jitterBufferLock.lock()
-        let insertOffset = jitterBuffer.count
-        jitterBuffer.append(contentsOf: repeatElement(Float(0), count: frameCount))
-
-        data.withUnsafeBytes { ptr in
-            if let samples = ptr.bindMemory(to: Int16.self).baseAddress {
-                for i in 0..<frameCount {
-                    jitterBuffer[insertOffset + i] = Float(samples[i]) / 32768.0
-                }
-                // Calculate RMS for level meter using the samples we just wrote
-                jitterBuffer.withUnsafeBufferPointer { bufPtr in
-                    if let base = bufPtr.baseAddress {
-                        vDSP_rmsqv(base + insertOffset, 1, &rmsLevel, vDSP_Length(frameCount))
-                    }
-                }
-            }
-        }
+        jitterBuffer.append(contentsOf: samples)
 
         DispatchQueue.main.async {
             self.pcAudioLevel = rmsLevel
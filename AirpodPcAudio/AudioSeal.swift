@@ -0,0 +1,162 @@
+import CryptoKit
+import Foundation
+
+/// Optional protection for audio datagrams, the Swift side of
+/// windows/core/src/seal.rs: authenticated with HMAC-SHA256, or encrypted as well
+/// with ChaCha20-Poly1305. The hello offers "seal=mac|aead kx=<X25519 key>"
+/// and the ack answers with our key, signed with the rest of it, so only the
+/// PC that checked our identity gets the keys. A sealed datagram is
+/// [0xBC][mode][UInt64 LE counter][body][16-byte tag].
+enum AudioSeal {
+    static let overhead = headerSize + tagSize
+    private static let headerSize = 10
+    private static let tagSize = 16
+    private static let magic: UInt8 = 0xBC
+    private static let replayWindow: UInt64 = 64
+    private static let info = Data("BBSEAL1".utf8)
+
+    enum Mode: UInt8 {
+        case off = 0, mac = 1, aead = 2
+
+        init(_ text: Substring) {
+            switch text {
+            case "mac": self = .mac
+            case "aead": self = .aead
+            default: self = .off
+            }
+        }
+
+        var name: String {
+            switch self {
+            case .off: return "off"
+            case .mac: return "mac"
+            case .aead: return "aead"
+            }
+        }
+
+        var label: String {
+            switch self {
+            case .off: return "Not protected"
+            case .mac: return "Authenticated (HMAC-SHA256)"
+            case .aead: return "Encrypted (ChaCha20-Poly1305)"
+            }
+        }
+    }
+
+    /// Our sealer and opener for the PC's `pcKey`, salted with the hello's
+    /// 16-byte nonce. Nil for a key that would leave nothing secret.
+    static func ends(_ mode: Mode, ours: Curve25519.KeyAgreement.PrivateKey, pcKey: Data, nonce: Data) -> (Sealer, Opener)? {
+        guard mode != .off,
+              let theirs = try? Curve25519.KeyAgreement.PublicKey(rawRepresentation: pcKey),
+              let shared = try? ours.sharedSecretFromKeyAgreement(with: theirs),
+              shared.withUnsafeBytes({ $0.contains { $0 != 0 } }) else { return nil }
+        let keys = shared.hkdfDerivedSymmetricKey(using: SHA256.self, salt: nonce,
+                                                  sharedInfo: info + pcKey + ours.publicKey.rawRepresentation,
+                                                  outputByteCount: 64)
+            .withUnsafeBytes { Data($0) }
+        // PC → phone first, then phone → PC
+        return (Sealer(mode: mode, key: SymmetricKey(data: keys.suffix(32))),
+                Opener(mode: mode, key: SymmetricKey(data: keys.prefix(32))))
+    }
+
+    private static func nonce(_ counter: UInt64) -> ChaChaPoly.Nonce {
+        var bytes = Data(count: 4)
+        withUnsafeBytes(of: counter.littleEndian) { bytes.append(contentsOf: $0) }
+        return try! ChaChaPoly.Nonce(data: bytes)
+    }
+
+    private static func mac(_ data: Data, key: SymmetricKey) -> Data {
+        Data(HMAC<SHA256>.authenticationCode(for: data, using: key)).prefix(tagSize)
+    }
+
+    /// Seals what we send
+    struct Sealer {
+        let mode: Mode
+        let key: SymmetricKey
+        private var counter: UInt64 = 0
+
+        init(mode: Mode, key: SymmetricKey) {
+            self.mode = mode
+            self.key = key
+        }
+
+        mutating func seal(_ datagram: Data) -> Data {
+            var header = Data([magic, mode.rawValue])
+            withUnsafeBytes(of: counter.littleEndian) { header.append(contentsOf: $0) }
+            var packet = header
+            if mode == .aead, let box = try? ChaChaPoly.seal(datagram, using: key, nonce: nonce(counter), authenticating: header) {
+                packet.append(box.ciphertext)
+                packet.append(box.tag)
+            } else {
+                packet.append(datagram)
+                packet.append(mac(packet, key: key))
+            }
+            counter += 1
+            return packet
+        }
+    }
+
+    /// Opens what the PC sent
+    struct Opener {
+        let mode: Mode
+        let key: SymmetricKey
+        private var newest: UInt64?
+        // Bit n: newest - n has been opened
+        private var seen: UInt64 = 0
+
+        init(mode: Mode, key: SymmetricKey) {
+            self.mode = mode
+            self.key = key
+        }
+
+        /// The datagram inside `packet`; nil when it wasn't sealed with this
+        /// key and mode, or has been opened before
+        mutating func open(_ packet: Data) -> Data? {
+            let bytes = [UInt8](packet)
+            guard bytes.count >= overhead, bytes[0] == magic, bytes[1] == mode.rawValue else { return nil }
+            let counter = (0..<8).reduce(UInt64(0)) { $0 | UInt64(bytes[2 + $1]) << (8 * $1) }
+            guard fresh(counter) else { return nil }
+            let header = Data(bytes[..<headerSize])
+            let body = Data(bytes[headerSize..<bytes.count - tagSize])
+            let tag = Data(bytes[(bytes.count - tagSize)...])
+            let opened: Data?
+            if mode == .aead {
+                opened = (try? ChaChaPoly.SealedBox(nonce: nonce(counter), ciphertext: body, tag: tag))
+                    .flatMap { try? ChaChaPoly.open($0, using: key, authenticating: header) }
+            } else {
+                let expected = mac(header + body, key: key)
+                opened = zip(expected, tag).reduce(0) { $0 | ($1.0 ^ $1.1) } == 0 ? body : nil
+            }
+            if opened != nil {
+                remember(counter)
+            }
+            return opened
+        }
+
+        private func fresh(_ counter: UInt64) -> Bool {
+            guard let newest = newest, counter <= newest else { return true }
+            let behind = newest - counter
+            return behind < replayWindow && seen & (1 << behind) == 0
+        }
+
+        private mutating func remember(_ counter: UInt64) {
+            if let newest = newest, counter <= newest {
+                seen |= 1 << (newest - counter)
+                return
+            }
+            let ahead = newest.map { counter - $0 } ?? replayWindow
+            seen = ahead >= replayWindow ? 1 : (seen << ahead) | 1
+            newest = counter
+        }
+    }
+
+    /// Microseconds opening a packet takes, a moving average over the last hundred or so
+    struct Cost {
+        private(set) var microseconds: Double?
+
+        mutating func record(since start: Date) {
+            let elapsed = Date().timeIntervalSince(start) * 1_000_000
+            microseconds = microseconds.map { $0 + (elapsed - $0) / 100 } ?? elapsed
+        }
+    }
+}
@@ -31,8 +31,8 @@ struct ContentView: View {
         }
 
         // Wire up network -> audio (weak to break retain cycle)
-        networkManager.onAudioReceived = { [weak audioManager] data in
-            audioManager?.playAudio(data: data)
+        networkManager.onAudioReceived = { [weak audioManager] samples in
+            audioManager?.playAudio(samples: samples)
         }
     }
 }
@@ -126,18 +126,18 @@ struct ConnectionView: View {
             // Connect Button
             Button(action: toggleConnection) {
                 HStack {
-                    Image(systemName: networkManager.isConnected ? "stop.fill" : "play.fill")
-                    Text(networkManager.isConnected ? "Disconnect" : "Connect")
+                    Image(systemName: networkManager.isActive ? "stop.fill" : "play.fill")
+                    Text(networkManager.isActive ? "Disconnect" : "Connect")
                 }
                 .font(.headline)
                 .foregroundColor(.white)
                 .frame(maxWidth: .infinity)
                 .padding()
-                .background(networkManager.isConnected ? Color.red : Color.blue)
+                .background(networkManager.isActive ? Color.red : Color.blue)
                 .cornerRadius(12)
             }
             .padding(.horizontal)
-            .disabled(pcStore.selectedPC == nil && !networkManager.isConnected)
+            .disabled(pcStore.selectedPC == nil && !networkManager.isActive)
 
             Spacer()
 
@@ -177,7 +177,7 @@ struct ConnectionView: View {
     }
 
     private func toggleConnection() {
-        if networkManager.isConnected {
+        if networkManager.isActive {
             networkManager.disconnect()
             audioManager.stop()
         } else {
@@ -186,8 +186,16 @@ struct ConnectionView: View {
             // Start audio engine FIRST, before network
             do {
                 try audioManager.start()
-                print("📱 Audio engine started, now connecting to \(pc.name) (\(pc.ipAddress))...")
-                networkManager.connect(to: pc.ipAddress)
+                if pc.usesClassroom, let group = pc.classroomGroup {
+                    print("📱 Audio engine started, now joining classroom group \(group)...")
+                    networkManager.listen(toGroup: group)
+                } else if pc.usesInternetMode, let server = pc.rendezvousServer, let code = pc.sessionCode {
+                    print("📱 Audio engine started, now joining session \(code) via \(server)...")
+                    networkManager.connect(viaRendezvous: server, code: code)
+                } else {
+                    print("📱 Audio engine started, now connecting to \(pc.name) (\(pc.ipAddress))...")
+                    networkManager.connect(to: pc.ipAddress, vpn: pc.vpnMode ?? false)
+                }
             } catch {
                 print("❌ Failed to start audio: \(error)")
             }
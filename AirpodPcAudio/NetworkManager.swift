@@ -1,3 +1,4 @@
+import CryptoKit
 import Foundation
 import Network
 
@@ -5,15 +6,107 @@ class NetworkManager: ObservableObject {
     private var connection: NWConnection?
     private var listener: NWListener?
     private var incomingConnection: NWConnection?  // Single connection for receiving PC audio
+    private var probeConnection: NWConnection?     // PC "Test connection" run from another port
+    private var rendezvousConnection: NWConnection? // Internet mode: waiting for the server to pair us
+    private var castGroup: NWConnectionGroup?      // Classroom mode: the PC's multicast group
+    private var keepaliveTimer: DispatchSourceTimer?
     private let queue = DispatchQueue(label: "network", qos: .userInteractive)
 
     @Published var isConnected = false
     @Published var statusMessage = "Disconnected"
+    @Published var isRendezvousPending = false
+
+    var isActive: Bool { isConnected || isRendezvousPending }
 
     // Ports
     private let sendPort: UInt16 = 4810    // PC listens here (receives mic audio)
     private let receivePort: UInt16 = 4811 // iPhone listens here (receives PC audio)
 
+    // Connection test packets start with this tag and are echoed back unchanged
+    static let probeMagic = Data("BBPROBE1".utf8)
+
+    static func isProbe(_ data: Data) -> Bool {
+        data.count >= probeMagic.count && data.prefix(probeMagic.count) == probeMagic
+    }
+
+    // During a session the PC sends a clock probe every second (stage 255). The
+    // echo carries when we got it and when we sent it back, in our monotonic
+    // microseconds, so the PC can tell the two directions' delays apart
+    // (windows/src/clock.rs).
+    private static let clockStage: UInt8 = 0xFF
+    private static let probeHeaderSize = 21
+
+    static func echo(_ probe: Data, receivedAt: UInt64, repliedAt: UInt64) -> Data {
+        var echo = [UInt8](probe)
+        guard echo.count >= probeHeaderSize + 16, echo[8] == clockStage else { return probe }
+        for (at, value) in [(probeHeaderSize, receivedAt), (probeHeaderSize + 8, repliedAt)] {
+            withUnsafeBytes(of: value.littleEndian) { echo.replaceSubrange(at..<at + 8, with: $0) }
+        }
+        return Data(echo)
+    }
+
+    private static var monotonicMicros: UInt64 {
+        DispatchTime.now().uptimeNanoseconds / 1000
+    }
+
+    private static func echoProbe(_ probe: Data, on connection: NWConnection) {
+        let received = monotonicMicros
+        connection.send(content: echo(probe, receivedAt: received, repliedAt: monotonicMicros), completion: .idempotent)
+    }
+
+    // Internet mode (see windows/src/rendezvous.rs, messages in protocol.rs). Punches open the NAT mapping
+    // and keep it alive; neither they nor server messages are ever played.
+    static let punchMagic = Data("BBPUNCH1".utf8)
+    private static let rendezvousPrefix = Data("BBRV1 ".utf8)
+    private static let rendezvousPort: UInt16 = 4820
+
+    /// The server passes audio between us and the PC from now on, because the punch didn't get through
+    static func isRelaying(_ data: Data) -> Bool {
+        String(data: data, encoding: .utf8)?.trimmingCharacters(in: .whitespacesAndNewlines) == "BBRV1 RELAYING"
+    }
+
+    static func isControl(_ data: Data) -> Bool {
+        data.starts(with: punchMagic) || data.starts(with: rendezvousPrefix) || data.starts(with: testPrefix)
+            || data.starts(with: pairPrefix)
+    }
+
+    // With two PCs streaming to this phone, the one our mic reaches answers each
+    // ack with "BBPAIR1 <PC id>" (windows/core/src/protocol.rs). Another PC's hellos
+    // and audio are then ignored, so both directions stay with the same PC. All
+    // nil with PCs from before PC ids.
+    private static let pairPrefix = Data("BBPAIR1 ".utf8)
+    private var micPC: String?       // where our mic audio arrives
+    private var listeningPC: String? // whose hellos the incoming connection carries
+    private var probePC: String?     // whose hellos the other connection carries
+
+    static func pairedPC(in data: Data) -> String? {
+        guard data.starts(with: pairPrefix),
+              let id = String(data: data.dropFirst(pairPrefix.count), encoding: .utf8),
+              id.count == 32, id.allSatisfy(\.isHexDigit) else { return nil }
+        return id
+    }
+
+    private var isCrossed: Bool {
+        guard let mic = micPC, let listening = listeningPC else { return false }
+        return mic != listening
+    }
+
+    // The PC's "Play test sound on phone" marks its chime with "BBTEST1 <id>"; we
+    // report the loudest level played in the second after it (windows/src/test_sound.rs)
+    private static let testPrefix = Data("BBTEST1 ".utf8)
+    private var testSound: (id: UInt32, peak: Float)?
+
+    static func testMarker(in data: Data) -> UInt32? {
+        guard data.starts(with: testPrefix), let text = String(data: data.dropFirst(testPrefix.count), encoding: .utf8) else {
+            return nil
+        }
+        return UInt32(text)
+    }
+
+    static func testReport(id: UInt32, peakDb: Int) -> Data {
+        Data("BBTEST1 PLAYED \(id) peak=\(peakDb)".utf8)
+    }
+
     // Debug stats
     private var rxPacketCount = 0
     private var rxByteCount = 0
@@ -21,59 +114,262 @@ class NetworkManager: ObservableObject {
     private var txByteCount = 0
     private var lastStatsTime = Date()
     private var nonZeroSamples = 0
+    private var lastAudioReceived = Date.distantPast
+    private var lastSent = Date.distantPast
+    private var maxPayload = 1400
+
+    // Codecs agreed with the PC (see StreamCodec.swift); nil coders mean PCM
+    // (16-bit, 24-bit or float, per `formats`).
+    // Receiving runs on `queue` and sending on the main thread, hence the lock.
+    private var formats: StreamFormats?
+    private var encoder: OpusEncoder?
+    private var decoder: OpusDecoder?
+    private var packer: RedundantFrames.Packer?
+    private var unpacker: RedundantFrames.Unpacker?
+    // Framed audio (see PacketFrame): our numbering going up, the PC's coming down
+    private var sendSequence: UInt32 = 0
+    private var frameStart = Date()
+    private var loss = PacketFrame.LossCounter()
+    // Forward error correction (see ParityFrames), once the PC asked for it and we agreed
+    private var parityEncoder: ParityFrames.Encoder?
+    private var parityDecoder: ParityFrames.Decoder?
+    // Audio protection (see AudioSeal): our exchange key, kept while the hellos carry
+    // the same PC key and nonce so every ack agrees on the same keys, and the ends made from it
+    private var sealKey = Curve25519.KeyAgreement.PrivateKey()
+    private var sealPeer: String?
+    private var sealer: AudioSeal.Sealer?
+    private var opener: AudioSeal.Opener?
+    private var sealCost = AudioSeal.Cost()
+    private var refused = 0
+    private let codecLock = NSLock()
 
     // Callback when audio data received from PC
-    var onAudioReceived: ((Data) -> Void)?
+    var onAudioReceived: (([Float]) -> Void)?
 
     deinit {
         disconnect()
     }
 
-    func connect(to host: String) {
+    func connect(to host: String, vpn: Bool = false) {
         disconnect()
 
-        // Create UDP connection to PC
-        let endpoint = NWEndpoint.hostPort(host: NWEndpoint.Host(host), port: NWEndpoint.Port(rawValue: sendPort)!)
-        connection = NWConnection(to: endpoint, using: .udp)
+        // Tailscale/WireGuard tunnels run at an MTU of 1280 and drop idle NAT mappings
+        maxPayload = vpn ? 1200 : 1400
+        openSendConnection(to: NWEndpoint.Host(host))
+        if vpn {
+            startKeepalive(every: 1) { [weak self] in
+                guard let self = self, Date().timeIntervalSince(self.lastSent) >= 1 else { return }
+                self.lastSent = Date()
+                self.connection?.send(content: Self.punchMagic, completion: .idempotent)
+            }
+        }
 
-        connection?.stateUpdateHandler = { [weak self] state in
+        // Start listener for incoming PC audio
+        startListener()
+    }
+
+    /// Join a session on the rendezvous server, then talk to the PC directly through
+    /// the NAT mappings both sides opened. Everything is sent from the receive port,
+    /// which is the mapping the server tells the PC about. If the punch doesn't get
+    /// through, the PC asks the server to relay and we send to the server instead.
+    func connect(viaRendezvous server: String, code: String) {
+        disconnect()
+
+        let parts = server.split(separator: ":", maxSplits: 1)
+        let port = parts.count > 1 ? UInt16(parts[1]) ?? Self.rendezvousPort : Self.rendezvousPort
+        let endpoint = NWEndpoint.hostPort(host: NWEndpoint.Host(String(parts.first ?? "")),
+                                           port: NWEndpoint.Port(rawValue: port)!)
+        let rendezvous = NWConnection(to: endpoint, using: receivePortParameters())
+        rendezvousConnection = rendezvous
+        rendezvous.stateUpdateHandler = { [weak self] state in
+            guard case .failed(let error) = state else { return }
             DispatchQueue.main.async {
-                switch state {
-                case .ready:
-                    self?.isConnected = true
-                    self?.statusMessage = "Connected to \(host)"
-                    print("Connected to \(host):\(self?.sendPort ?? 0)")
-                case .failed(let error):
-                    self?.isConnected = false
-                    self?.statusMessage = "Failed: \(error.localizedDescription)"
-                    print("Connection failed: \(error)")
-                case .cancelled:
-                    self?.isConnected = false
-                    self?.statusMessage = "Disconnected"
-                case .waiting(let error):
-                    self?.statusMessage = "Waiting: \(error.localizedDescription)"
-                default:
-                    break
+                guard self?.rendezvousConnection === rendezvous, self?.isRendezvousPending == true else { return }
+                self?.isRendezvousPending = false
+                self?.statusMessage = "Rendezvous failed: \(error.localizedDescription)"
+            }
+        }
+        rendezvous.start(queue: queue)
+        rendezvousReceive(rendezvous)
+
+        let register = Data("BBRV1 REGISTER \(code) phone".utf8)
+        startKeepalive(every: 3) { [weak self] in
+            guard self?.rendezvousConnection === rendezvous else { return }
+            rendezvous.send(content: register, completion: .idempotent)
+        }
+
+        DispatchQueue.main.async { [weak self] in
+            self?.isRendezvousPending = true
+            self?.statusMessage = "Waiting for PC (session \(code))..."
+        }
+    }
+
+    private func rendezvousReceive(_ rendezvous: NWConnection) {
+        rendezvous.receiveMessage { [weak self] data, _, _, error in
+            guard let self = self, self.rendezvousConnection === rendezvous else { return }
+            // The server repeats the peer while registrations cross; the first one counts
+            if self.connection == nil, let data = data, let text = String(data: data, encoding: .utf8),
+               text.hasPrefix("BBRV1 PEER "),
+               let peer = Self.parseHostPort(String(text.dropFirst("BBRV1 PEER ".count))) {
+                print("🤝 Peer from rendezvous: \(peer.host):\(peer.port)")
+                // Still listening here, for the server saying it relays
+                self.openSendConnection(to: peer.host, port: peer.port, fromReceivePort: true)
+                self.startKeepalive(every: 2) { [weak self] in
+                    self?.connection?.send(content: Self.punchMagic, completion: .idempotent)
                 }
+            } else if let data = data, Self.isRelaying(data), case let .hostPort(host, port) = rendezvous.endpoint {
+                print("🔁 Punch failed, relaying through \(host)")
+                // The send connection takes over this endpoint; the keepalive now keeps the server's mapping
+                self.rendezvousConnection = nil
+                rendezvous.cancel()
+                self.openSendConnection(to: host, port: port.rawValue, fromReceivePort: true)
+                return
+            }
+            if error == nil {
+                self.rendezvousReceive(rendezvous)
             }
         }
+    }
 
-        connection?.start(queue: queue)
+    private static func parseHostPort(_ text: String) -> (host: NWEndpoint.Host, port: UInt16)? {
+        let trimmed = text.trimmingCharacters(in: .whitespacesAndNewlines)
+        guard let colon = trimmed.lastIndex(of: ":"),
+              let port = UInt16(trimmed[trimmed.index(after: colon)...]) else { return nil }
+        return (NWEndpoint.Host(String(trimmed[..<colon])), port)
+    }
 
-        // Start listener for incoming PC audio
-        startListener()
+    private func receivePortParameters() -> NWParameters {
+        let params = NWParameters.udp
+        params.allowLocalEndpointReuse = true
+        params.requiredLocalEndpoint = .hostPort(host: "0.0.0.0", port: NWEndpoint.Port(rawValue: receivePort)!)
+        return params
+    }
+
+    private func startKeepalive(every seconds: Double, _ tick: @escaping () -> Void) {
+        keepaliveTimer?.cancel()
+        let timer = DispatchSource.makeTimerSource(queue: queue)
+        timer.schedule(deadline: .now(), repeating: seconds)
+        timer.setEventHandler(handler: tick)
+        timer.resume()
+        keepaliveTimer = timer
+    }
+
+    private func openSendConnection(to host: NWEndpoint.Host, port: UInt16? = nil, fromReceivePort: Bool = false) {
+        connection?.cancel()
+
+        // Create UDP connection to PC
+        let endpoint = NWEndpoint.hostPort(host: host, port: NWEndpoint.Port(rawValue: port ?? sendPort)!)
+        let newConnection = NWConnection(to: endpoint, using: fromReceivePort ? receivePortParameters() : .udp)
+        connection = newConnection
+
+        if fromReceivePort {
+            // Packets from the PC's mapped address land on this connection rather than
+            // the listener, so it carries audio both ways
+            newConnection.stateUpdateHandler = { [weak self] state in
+                if case .ready = state {
+                    for _ in 0..<5 {
+                        newConnection.send(content: Self.punchMagic, completion: .idempotent)
+                    }
+                    self?.receiveLoop(newConnection)
+                }
+                self?.handleSendState(state, of: newConnection, host: host)
+            }
+            newConnection.start(queue: queue)
+            return
+        }
+
+        newConnection.stateUpdateHandler = { [weak self] state in
+            if case .ready = state {
+                self?.pairLoop(newConnection)
+            }
+            self?.handleSendState(state, of: newConnection, host: host)
+        }
+
+        newConnection.start(queue: queue)
+    }
+
+    /// The PC answers our acks on the send connection, naming itself
+    private func pairLoop(_ connection: NWConnection) {
+        connection.receiveMessage { [weak self] data, _, _, error in
+            if let data = data, let pc = NetworkManager.pairedPC(in: data) {
+                self?.pair(with: pc)
+            }
+            if error == nil, self?.connection === connection {
+                self?.pairLoop(connection)
+            }
+        }
+    }
+
+    private func pair(with pc: String) {
+        guard micPC != pc else { return }
+        print("🔗 Mic audio reaches PC \(pc.prefix(8))")
+        micPC = pc
+    }
+
+    private func handleSendState(_ state: NWConnection.State, of newConnection: NWConnection, host: NWEndpoint.Host) {
+        DispatchQueue.main.async { [weak self] in
+            // A replaced connection's late "cancelled" must not mark the new one down
+            guard self?.connection === newConnection else { return }
+            switch state {
+            case .ready:
+                self?.isRendezvousPending = false
+                self?.isConnected = true
+                self?.statusMessage = "Connected to \(host)"
+                print("Connected to \(host)")
+            case .failed(let error):
+                self?.isRendezvousPending = false
+                self?.isConnected = false
+                self?.statusMessage = "Failed: \(error.localizedDescription)"
+                print("Connection failed: \(error)")
+            case .cancelled:
+                self?.isConnected = false
+                self?.statusMessage = "Disconnected"
+            case .waiting(let error):
+                self?.statusMessage = "Waiting: \(error.localizedDescription)"
+            default:
+                break
+            }
+        }
     }
 
     func disconnect() {
+        keepaliveTimer?.cancel()
+        keepaliveTimer = nil
+        rendezvousConnection?.cancel()
+        rendezvousConnection = nil
         connection?.cancel()
         connection = nil
         incomingConnection?.cancel()
         incomingConnection = nil
+        probeConnection?.cancel()
+        probeConnection = nil
         listener?.cancel()
         listener = nil
+        castGroup?.cancel()
+        castGroup = nil
+        micPC = nil
+        listeningPC = nil
+        probePC = nil
+
+        codecLock.lock()
+        formats = nil
+        encoder = nil
+        decoder = nil
+        packer = nil
+        unpacker = nil
+        loss = PacketFrame.LossCounter()
+        parityEncoder = nil
+        parityDecoder = nil
+        sealPeer = nil
+        sealer = nil
+        opener = nil
+        sealCost = AudioSeal.Cost()
+        refused = 0
+        codecLock.unlock()
 
         DispatchQueue.main.async { [weak self] in
             self?.isConnected = false
+            self?.isRendezvousPending = false
             self?.statusMessage = "Disconnected"
         }
     }
@@ -83,23 +379,253 @@ class NetworkManager: ObservableObject {
     func sendAudio(_ data: Data) {
         guard isConnected, let connection = connection else { return }
 
-        // Chunk data to avoid UDP fragmentation (max ~1400 bytes per packet)
-        let chunkSize = 1400
+        codecLock.lock()
+        var opusPackets = encoder?.encode(data)
+        if var red = packer, let packets = opusPackets {
+            opusPackets = packets.map { red.pack($0) }
+            packer = red
+        }
+        let framed = formats?.framed == true
+        let sealed = sealer != nil
+        codecLock.unlock()
+
+        lastSent = Date()
+        if let packets = opusPackets {
+            for packet in packets {
+                sendFramed(packet, framed: framed, on: connection)
+            }
+            return
+        }
+
+        // Chunk data to avoid UDP fragmentation (max ~1400 bytes per packet, less over a VPN)
+        var chunkSize = framed ? maxPayload - PacketFrame.headerSize : maxPayload
+        if sealed {
+            chunkSize -= AudioSeal.overhead
+        }
         var offset = 0
         while offset < data.count {
             let end = min(offset + chunkSize, data.count)
-            let chunk = data.subdata(in: offset..<end)
-            txPacketCount += 1
-            txByteCount += chunk.count
-            connection.send(content: chunk, completion: .contentProcessed { error in
-                if let error = error {
-                    print("Send error: \(error)")
-                }
-            })
+            sendFramed(data.subdata(in: offset..<end), framed: framed, on: connection)
             offset = end
         }
     }
 
+    /// Frames and seals a payload, and sends the parity packet right behind it
+    /// when it completes a group
+    private func sendFramed(_ payload: Data, framed: Bool, on connection: NWConnection) {
+        let packet = frame(payload, framed: framed)
+        send(seal(packet), on: connection)
+        codecLock.lock()
+        let parity = parityEncoder?.push(packet)
+        codecLock.unlock()
+        if let parity = parity {
+            send(seal(parity), on: connection)
+        }
+    }
+
+    /// The header goes on only once the PC agreed to it; sending runs on the main thread only
+    private func frame(_ payload: Data, framed: Bool) -> Data {
+        guard framed else { return payload }
+        let ticks = UInt32(truncatingIfNeeded: Int64(Date().timeIntervalSince(frameStart) * 48000))
+        sendSequence &+= 1
+        return PacketFrame.wrap(payload, sequence: sendSequence &- 1, timestamp: ticks)
+    }
+
+    /// Sealed once the PC agreed to it, bare otherwise
+    private func seal(_ packet: Data) -> Data {
+        codecLock.lock()
+        defer { codecLock.unlock() }
+        guard var sealer = sealer else { return packet }
+        let start = Date()
+        let sealed = sealer.seal(packet)
+        sealCost.record(since: start)
+        self.sealer = sealer
+        return sealed
+    }
+
+    private func send(_ packet: Data, on connection: NWConnection) {
+        txPacketCount += 1
+        txByteCount += packet.count
+        connection.send(content: packet, completion: .contentProcessed { error in
+            if let error = error {
+                print("Send error: \(error)")
+            }
+        })
+    }
+
+    // MARK: - Codec handshake
+
+    /// Set up the offered codecs (falling back to PCM for any we can't run) and
+    /// tell the PC what we picked. The PC repeats the hello, so this is idempotent.
+    private func answerHello(_ data: Data) {
+        guard let offer = StreamHandshake.parseOffer(data) else { return }
+        let pc = StreamHandshake.pcId(in: data)
+        if pc != listeningPC {
+            listeningPC = pc
+            if isCrossed {
+                print("🔀 Ignoring PC \(pc?.prefix(8) ?? ""), our mic goes to PC \(micPC?.prefix(8) ?? "")")
+            }
+        }
+        guard !isCrossed else { return }
+
+        let nonce = StreamHandshake.nonce(in: data)
+        var offer = offer
+        offer.seal = setUpSeal(offer.seal, pcKey: StreamHandshake.exchangeKey(in: data), nonce: nonce)
+        let agreed = setUpCodecs(for: offer)
+        let ack = StreamHandshake.ack(agreed, pc: pc, nonce: nonce, signingKey: PeerIdentity.signingKey,
+                                      exchangeKey: sealKey.publicKey.rawRepresentation)
+        connection?.send(content: ack, completion: .idempotent)
+    }
+
+    /// The seal we agree to: the offered one once the PC's key and nonce give us
+    /// ends, otherwise off. A new PC key or nonce gets a new key of ours.
+    private func setUpSeal(_ mode: AudioSeal.Mode, pcKey: Data?, nonce: String?) -> AudioSeal.Mode {
+        codecLock.lock()
+        defer { codecLock.unlock() }
+        guard mode != .off, let pcKey = pcKey, let nonce = nonce else {
+            (sealPeer, sealer, opener) = (nil, nil, nil)
+            return .off
+        }
+        let peer = "\(mode.name) \(StreamHandshake.hex(pcKey)) \(nonce)"
+        if peer != sealPeer {
+            sealKey = Curve25519.KeyAgreement.PrivateKey()
+            let ends = AudioSeal.ends(mode, ours: sealKey, pcKey: pcKey, nonce: StreamHandshake.unhex(nonce))
+            (sealPeer, sealer, opener) = (peer, ends?.0, ends?.1)
+            sealCost = AudioSeal.Cost()
+            refused = 0
+            print("🔒 Audio protection: \((ends == nil ? AudioSeal.Mode.off : mode).label)")
+        }
+        return sealer == nil ? .off : mode
+    }
+
+    /// Coders for the offered formats, falling back to PCM for any we can't run.
+    /// Returns what we'll actually use.
+    private func setUpCodecs(for offer: StreamFormats) -> StreamFormats {
+        codecLock.lock()
+        defer { codecLock.unlock() }
+        if formats?.down != offer.down || formats?.ptime != offer.ptime || decoder == nil {
+            decoder = OpusDecoder(format: offer.down, ptime: offer.ptime)
+        }
+        if formats?.up != offer.up || encoder == nil {
+            encoder = OpusEncoder(format: offer.up)
+        }
+        let agreed = StreamFormats(down: decoder != nil || offer.down.codec.isLinear ? offer.down : .pcmMono,
+                                   up: encoder == nil ? .pcmMono : offer.up,
+                                   redundancy: offer.redundancy,
+                                   framed: offer.framed,
+                                   seal: offer.seal,
+                                   ptime: decoder == nil ? 20 : offer.ptime,
+                                   fec: offer.framed ? offer.fec : 0)
+        if agreed != formats {
+            print("🎚️ Codecs: down \(agreed.down) in \(agreed.ptime) ms packets, up \(agreed.up), \(agreed.redundancy) redundant frames\(agreed.framed ? ", framed" : "")\(agreed.fec > 0 ? ", parity every \(agreed.fec)" : "")")
+            loss = PacketFrame.LossCounter()
+            parityEncoder = agreed.fec > 0 ? ParityFrames.Encoder(group: agreed.fec) : nil
+            parityDecoder = agreed.fec > 0 ? ParityFrames.Decoder(group: agreed.fec) : nil
+            // Redundancy only wraps Opus; a fresh packer/unpacker restarts the sequence
+            packer = encoder != nil && agreed.redundancy > 0 ? RedundantFrames.Packer(depth: agreed.redundancy) : nil
+            unpacker = decoder != nil && agreed.redundancy > 0 ? RedundantFrames.Unpacker() : nil
+        }
+        formats = agreed
+        return agreed
+    }
+
+    /// Mono samples for AudioManager from a received packet; nil for one that
+    /// doesn't open once the PC agreed to seal audio
+    private func decodeAudio(_ packet: Data) -> [Float]? {
+        codecLock.lock()
+        defer { codecLock.unlock() }
+        var data = packet
+        if let seal = formats?.seal, seal != .off {
+            let start = Date()
+            guard let opened = opener?.open(packet) else {
+                refused += 1
+                return nil
+            }
+            sealCost.record(since: start)
+            data = opened
+        }
+        if formats?.framed == true, let frame = PacketFrame.unwrap(data) {
+            loss.record(frame.sequence)
+        }
+        // With FEC, audio after a gap waits for the parity that may rebuild it
+        guard var parity = parityDecoder else { return decodePacket(data) }
+        let packets = parity.receive(data)
+        parityDecoder = parity
+        return packets.flatMap(decodePacket)
+    }
+
+    /// Mono samples from one opened packet; codecLock is held
+    private func decodePacket(_ packet: Data) -> [Float] {
+        var data = packet
+        if formats?.framed == true, let frame = PacketFrame.unwrap(data) {
+            data = frame.payload
+        }
+        guard let decoder = decoder else { return (formats?.down ?? .pcmMono).linearSamples(data) }
+        guard var red = unpacker else { return AudioConversion.pcmToFloat(decoder.decode(data)) }
+        let frames = red.unpack(data)
+        unpacker = red
+        return AudioConversion.pcmToFloat(frames.reduce(into: Data()) { $0.append(decoder.decode($1)) })
+    }
+
+    // MARK: - Classroom multicast (PC audio only)
+
+    static let castPort: UInt16 = 4830
+
+    /// Join the PC's classroom group ("group" or "group:port", see
+    /// windows/src/multicast.rs) and play what it sends. Nothing goes back: no
+    /// ack and no mic. The PC announces the format once a second, and audio
+    /// before the first announcement is dropped. Needs the multicast networking
+    /// entitlement.
+    func listen(toGroup address: String) {
+        disconnect()
+
+        let trimmed = address.trimmingCharacters(in: .whitespaces)
+        let (host, port) = Self.parseHostPort(trimmed) ?? (NWEndpoint.Host(trimmed), Self.castPort)
+        let group: NWMulticastGroup
+        do {
+            group = try NWMulticastGroup(for: [.hostPort(host: host, port: NWEndpoint.Port(rawValue: port)!)])
+        } catch {
+            statusMessage = "Invalid multicast group: \(error.localizedDescription)"
+            return
+        }
+
+        let cast = NWConnectionGroup(with: group, using: .udp)
+        castGroup = cast
+        cast.setReceiveHandler(maximumMessageSize: 65535, rejectOversizedMessages: true) { [weak self] _, data, _ in
+            guard let self = self, let data = data, !data.isEmpty else { return }
+            self.codecLock.lock()
+            let current = self.formats
+            self.codecLock.unlock()
+            if let announced = StreamHandshake.parseCast(data) {
+                if announced != current {
+                    print("📡 Classroom stream: \(announced.down), \(announced.redundancy) redundant frames")
+                }
+                _ = self.setUpCodecs(for: announced)
+            } else if current != nil, !NetworkManager.isControl(data), let samples = self.decodeAudio(data) {
+                self.lastAudioReceived = Date()
+                self.onAudioReceived?(samples)
+            }
+        }
+        cast.stateUpdateHandler = { [weak self] state in
+            DispatchQueue.main.async {
+                guard self?.castGroup === cast else { return }
+                switch state {
+                case .ready:
+                    self?.isConnected = true
+                    self?.statusMessage = "Listening to \(host):\(port)"
+                case .failed(let error):
+                    self?.isConnected = false
+                    self?.statusMessage = "Failed: \(error.localizedDescription)"
+                case .waiting(let error):
+                    self?.statusMessage = "Waiting: \(error.localizedDescription)"
+                default:
+                    break
+                }
+            }
+        }
+        cast.start(queue: queue)
+    }
+
     // MARK: - Receive (PC audio to iPhone)
 
     private func startListener() {
@@ -126,14 +652,23 @@ class NetworkManager: ObservableObject {
     }
 
     private func handleIncomingConnection(_ connection: NWConnection) {
-        // Only accept one incoming connection - ignore subsequent ones
+        // Only accept one incoming audio connection - later ones may only run the connection test
         if incomingConnection != nil {
-            connection.cancel()
+            probeConnection?.cancel()
+            probeConnection = connection
+            probePC = nil
+            connection.stateUpdateHandler = { [weak self] state in
+                if case .ready = state {
+                    self?.echoLoop(connection)
+                }
+            }
+            connection.start(queue: queue)
             return
         }
 
         print("🔗 Incoming connection from: \(connection.endpoint)")
         incomingConnection = connection
+        listeningPC = nil
 
         connection.stateUpdateHandler = { [weak self] state in
             print("   Connection state: \(state)")
@@ -154,30 +689,44 @@ class NetworkManager: ObservableObject {
 
     private func receiveLoop(_ connection: NWConnection) {
         connection.receiveMessage { [weak self] data, _, _, error in
-            if let data = data, !data.isEmpty {
+            if let data = data, NetworkManager.isProbe(data) {
+                // Connection test or clock probe from the PC: echo it back, never play it
+                NetworkManager.echoProbe(data, on: connection)
+            } else if let data = data, let pc = NetworkManager.pairedPC(in: data) {
+                self?.pair(with: pc)
+            } else if let data = data, StreamHandshake.isHello(data) {
+                self?.answerHello(data)
+            } else if let data = data, let id = NetworkManager.testMarker(in: data) {
+                self?.startTestSound(id)
+            } else if let data = data, NetworkManager.isControl(data) {
+                // Internet mode punch/keepalive from the PC
+            } else if self?.isCrossed == true {
+                // Another PC's stream while our mic goes elsewhere: not played
+            } else if let packet = data, !packet.isEmpty, let samples = self?.decodeAudio(packet) {
+                self?.lastAudioReceived = Date()
+                if let test = self?.testSound, let peak = samples.map({ abs($0) }).max() {
+                    self?.testSound = (test.id, max(test.peak, peak))
+                }
                 self?.rxPacketCount += 1
-                self?.rxByteCount += data.count
+                self?.rxByteCount += packet.count
 
                 // Count non-zero samples to detect silence
-                data.withUnsafeBytes { ptr in
-                    if let samples = ptr.bindMemory(to: Int16.self).baseAddress {
-                        let count = data.count / 2
-                        for i in 0..<count {
-                            if samples[i] != 0 {
-                                self?.nonZeroSamples += 1
-                            }
-                        }
-                    }
-                }
+                self?.nonZeroSamples += samples.filter { $0 != 0 }.count
 
                 // Log stats every second
                 let now = Date()
                 if let lastTime = self?.lastStatsTime, now.timeIntervalSince(lastTime) >= 1.0 {
-                    let samples = data.count / 2
-                    let preview = data.prefix(16).map { String(format: "%02X", $0) }.joined(separator: " ")
-                    print("📦 RX: \(self?.rxPacketCount ?? 0) pkts, \(self?.rxByteCount ?? 0) bytes | Last: \(data.count)B (\(samples) samples)")
+                    let preview = packet.prefix(16).map { String(format: "%02X", $0) }.joined(separator: " ")
+                    print("📦 RX: \(self?.rxPacketCount ?? 0) pkts, \(self?.rxByteCount ?? 0) bytes | Last: \(packet.count)B (\(samples.count) samples)")
                     print("   Non-zero samples: \(self?.nonZeroSamples ?? 0) | Preview: \(preview)")
                     print("📤 TX: \(self?.txPacketCount ?? 0) pkts, \(self?.txByteCount ?? 0) bytes")
+                    if let self = self, self.formats?.framed == true {
+                        print("   Lost from PC since the handshake: \(self.loss.lost) pkts, \(self.parityDecoder?.recovered ?? 0) rebuilt from parity")
+                    }
+                    if let self = self, let seal = self.formats?.seal, seal != .off {
+                        let cost = self.sealCost.microseconds.map { String(format: "%.1f", $0) } ?? "-"
+                        print("   \(seal.label): \(cost) µs a packet, \(self.refused) refused")
+                    }
 
                     self?.rxPacketCount = 0
                     self?.rxByteCount = 0
@@ -187,7 +736,7 @@ class NetworkManager: ObservableObject {
                     self?.lastStatsTime = now
                 }
 
-                self?.onAudioReceived?(data)
+                self?.onAudioReceived?(samples)
             }
             if let error = error {
                 print("❌ Receive error: \(error)")
@@ -196,4 +745,72 @@ class NetworkManager: ObservableObject {
             }
         }
     }
+
+    /// Runs on `queue`, like the receive loop that measures the peak
+    private func startTestSound(_ id: UInt32) {
+        testSound = (id, 0)
+        queue.asyncAfter(deadline: .now() + 1) { [weak self] in
+            guard let self = self, let test = self.testSound, test.id == id else { return }
+            self.testSound = nil
+            let peakDb = max(Int((20 * log10(max(test.peak, 1e-6))).rounded()), -120)
+            print("🔔 Test sound \(id) played at \(peakDb) dBFS")
+            self.connection?.send(content: Self.testReport(id: id, peakDb: peakDb), completion: .idempotent)
+        }
+    }
+
+    private func echoLoop(_ connection: NWConnection) {
+        connection.receiveMessage { [weak self] data, _, _, error in
+            guard let self = self else { return }
+            if let data = data, NetworkManager.isProbe(data) {
+                NetworkManager.echoProbe(data, on: connection)
+            } else if let data = data, StreamHandshake.isHello(data) {
+                self.probePC = StreamHandshake.pcId(in: data)
+                if self.isCrossed, self.probePC == self.micPC {
+                    // The PC our mic reaches, while the incoming connection is another one's
+                    self.adoptIncomingConnection(connection, followSend: false)
+                    self.answerHello(data)
+                    self.receiveLoop(connection)
+                    return
+                }
+            } else if let data = data, !data.isEmpty, !NetworkManager.isControl(data),
+                      self.probePC == nil || self.micPC == nil || self.probePC == self.micPC,
+                      Date().timeIntervalSince(self.lastAudioReceived) > 1.0,
+                      let samples = self.decodeAudio(data) {
+                // Audio from a new address while the old one went quiet: the PC changed
+                // networks (e.g. Ethernet to Wi-Fi), so follow it. Sealed audio has to open.
+                self.adoptIncomingConnection(connection)
+                self.lastAudioReceived = Date()
+                self.onAudioReceived?(samples)
+                self.receiveLoop(connection)
+                return
+            }
+            if error == nil {
+                self.echoLoop(connection)
+            }
+        }
+    }
+
+    private func adoptIncomingConnection(_ connection: NWConnection, followSend: Bool = true) {
+        print(followSend ? "🔀 PC moved to \(connection.endpoint)" : "🔀 Listening to \(connection.endpoint), where our mic goes")
+        incomingConnection?.cancel()
+        incomingConnection = connection
+        listeningPC = probePC
+        if probeConnection === connection {
+            probeConnection = nil
+            probePC = nil
+        }
+        connection.stateUpdateHandler = { [weak self] state in
+            switch state {
+            case .failed, .cancelled:
+                if self?.incomingConnection === connection {
+                    self?.incomingConnection = nil
+                }
+            default:
+                break
+            }
+        }
+        if followSend, case .hostPort(let host, _) = connection.endpoint {
+            openSendConnection(to: host)
+        }
+    }
 }
@@ -0,0 +1,54 @@
+import Foundation
+import Network
+
+/// A PC announcing itself on this network (windows/src/mdns.rs)
+struct DiscoveredPC: Identifiable, Equatable {
+    var name: String
+    var address: String
+
+    var id: String { address }
+}
+
+/// Browses for PCs with Settings > Discovery turned on. The TXT record carries
+/// the name and IPv4 address, so a found PC can be saved without resolving it.
+final class PCBrowser: ObservableObject {
+    @Published private(set) var pcs: [DiscoveredPC] = []
+
+    private var browser: NWBrowser?
+    private let queue = DispatchQueue(label: "BudBridge.PCBrowser")
+
+    func start() {
+        guard browser == nil else { return }
+        let browser = NWBrowser(for: .bonjourWithTXTRecord(type: "_budbridge._udp", domain: nil), using: .udp)
+        browser.browseResultsChangedHandler = { [weak self] results, _ in
+            let found = results.compactMap(Self.discovered).sorted { $0.name < $1.name }
+            DispatchQueue.main.async {
+                self?.pcs = found
+            }
+        }
+        browser.stateUpdateHandler = { state in
+            if case .failed(let error) = state {
+                print("PC browser failed: \(error)")
+            }
+        }
+        browser.start(queue: queue)
+        self.browser = browser
+    }
+
+    func stop() {
+        browser?.cancel()
+        browser = nil
+        pcs = []
+    }
+
+    private static func discovered(_ result: NWBrowser.Result) -> DiscoveredPC? {
+        guard case .bonjour(let txt) = result.metadata, let address = txt["addr"], !address.isEmpty else {
+            return nil
+        }
+        var name = txt["name"] ?? ""
+        if name.isEmpty, case .service(let service, _, _, _) = result.endpoint {
+            name = service
+        }
+        return DiscoveredPC(name: name, address: address)
+    }
+}
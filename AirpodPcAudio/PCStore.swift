@@ -4,12 +4,27 @@ struct SavedPC: Identifiable, Codable, Equatable {
     var id: UUID
     var name: String
     var ipAddress: String
+    // Internet mode: rendezvous server ("host" or "host:port") and the session code shown on the PC
+    var rendezvousServer: String?
+    var sessionCode: String?
+    // Reached over Tailscale or another VPN: smaller packets and keepalives
+    var vpnMode: Bool?
+    // Classroom mode: the PC's multicast group ("group" or "group:port"), listened to instead of connecting
+    var classroomGroup: String?
 
     init(id: UUID = UUID(), name: String, ipAddress: String) {
         self.id = id
         self.name = name
         self.ipAddress = ipAddress
     }
+
+    var usesInternetMode: Bool {
+        !(rendezvousServer ?? "").isEmpty && !(sessionCode ?? "").isEmpty
+    }
+
+    var usesClassroom: Bool {
+        !(classroomGroup ?? "").isEmpty
+    }
 }
 
 class PCStore: ObservableObject {
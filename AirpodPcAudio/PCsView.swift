@@ -2,11 +2,14 @@ import SwiftUI
 
 struct PCsView: View {
     @ObservedObject var pcStore: PCStore
+    @StateObject private var browser = PCBrowser()
 
     @State private var newName = ""
     @State private var newIP = ""
     @State private var editingPC: SavedPC?
     @State private var showingEditSheet = false
+    @State private var keyFingerprint = PeerIdentity.fingerprint(PeerIdentity.signingKey.publicKey)
+    @State private var confirmingNewKey = false
     @FocusState private var focusedField: Field?
 
     private enum Field {
@@ -24,8 +27,8 @@ struct PCsView: View {
                         .submitLabel(.next)
                         .onSubmit { focusedField = .ip }
 
-                    TextField("IP Address (e.g., 192.168.1.100)", text: $newIP)
-                        .keyboardType(.decimalPad)
+                    TextField("IP or hostname (e.g., 192.168.1.100)", text: $newIP)
+                        .keyboardType(.numbersAndPunctuation)
                         .textInputAutocapitalization(.never)
                         .autocorrectionDisabled()
                         .focused($focusedField, equals: .ip)
@@ -49,7 +52,8 @@ struct PCsView: View {
                                 VStack(alignment: .leading, spacing: 4) {
                                     Text(pc.name)
                                         .font(.headline)
-                                    Text(pc.ipAddress)
+                                    Text(pc.usesClassroom ? "Classroom · \(pc.classroomGroup ?? "")"
+                                         : pc.usesInternetMode ? "Internet · \(pc.sessionCode ?? "")" : pc.ipAddress)
                                         .font(.caption)
                                         .foregroundColor(.secondary)
                                 }
@@ -84,6 +88,39 @@ struct PCsView: View {
                     }
                 }
 
+                // PCs announcing themselves that aren't saved yet
+                let discovered = browser.pcs.filter { found in
+                    !pcStore.pcs.contains { $0.ipAddress == found.address }
+                }
+                if !discovered.isEmpty {
+                    Section {
+                        ForEach(discovered) { found in
+                            Button {
+                                pcStore.add(name: found.name, ipAddress: found.address)
+                            } label: {
+                                HStack {
+                                    VStack(alignment: .leading, spacing: 4) {
+                                        Text(found.name)
+                                            .font(.headline)
+                                            .foregroundColor(.primary)
+                                        Text(found.address)
+                                            .font(.caption)
+                                            .foregroundColor(.secondary)
+                                    }
+
+                                    Spacer()
+
+                                    Image(systemName: "plus.circle")
+                                }
+                            }
+                        }
+                    } header: {
+                        Text("On This Network")
+                    } footer: {
+                        Text("PCs with Settings > Discovery turned on. Tap one to save it.")
+                    }
+                }
+
                 // iPhone IP section
                 Section("My iPhone") {
                     HStack {
@@ -98,9 +135,30 @@ struct PCsView: View {
                                 .foregroundColor(.red)
                         }
                     }
+
+                    // What the PC shows for this phone's key, to compare when it warns or re-pairs
+                    VStack(alignment: .leading, spacing: 4) {
+                        Text("Identity Key")
+                        Text(keyFingerprint)
+                            .font(.system(.caption, design: .monospaced))
+                            .foregroundColor(.secondary)
+                    }
+
+                    Button("Make a New Key") {
+                        confirmingNewKey = true
+                    }
+                    .confirmationDialog("Make a new identity key?", isPresented: $confirmingNewKey, titleVisibility: .visible) {
+                        Button("Make a New Key", role: .destructive) {
+                            keyFingerprint = PeerIdentity.fingerprint(PeerIdentity.reset().publicKey)
+                        }
+                    } message: {
+                        Text("For when this iPhone changes hands or its key may have leaked. Every PC will refuse it until you click Re-pair for it there.")
+                    }
                 }
             }
             .navigationTitle("PCs")
+            .onAppear { browser.start() }
+            .onDisappear { browser.stop() }
             .toolbar {
                 ToolbarItemGroup(placement: .keyboard) {
                     Spacer()
@@ -141,11 +199,47 @@ struct EditPCView: View {
                         .textInputAutocapitalization(.words)
                         .focused($isFocused)
 
-                    TextField("IP Address", text: $pc.ipAddress)
-                        .keyboardType(.decimalPad)
+                    TextField("IP or hostname", text: $pc.ipAddress)
+                        .keyboardType(.numbersAndPunctuation)
+                        .textInputAutocapitalization(.never)
+                        .autocorrectionDisabled()
+                        .focused($isFocused)
+                }
+
+                Section {
+                    Toggle("VPN mode", isOn: Binding(get: { pc.vpnMode ?? false },
+                                                     set: { pc.vpnMode = $0 }))
+                } footer: {
+                    Text("For Tailscale, ZeroTier or WireGuard: smaller packets and keepalives so the stream survives tunnel MTUs and idle timeouts. Use the PC's VPN address or MagicDNS name above, and enable VPN mode on the PC too.")
+                }
+
+                Section {
+                    TextField("Rendezvous server (host or host:port)", text: optional($pc.rendezvousServer))
+                        .keyboardType(.URL)
+                        .textInputAutocapitalization(.never)
+                        .autocorrectionDisabled()
+                        .focused($isFocused)
+
+                    TextField("Session code", text: optional($pc.sessionCode))
+                        .textInputAutocapitalization(.characters)
+                        .autocorrectionDisabled()
+                        .focused($isFocused)
+                } header: {
+                    Text("Internet Mode")
+                } footer: {
+                    Text("Copy both from the PC's Settings > Internet Mode. When set, the PC is reached through the server instead of its IP address, e.g. over cellular.")
+                }
+
+                Section {
+                    TextField("Multicast group (e.g. 239.66.66.1)", text: optional($pc.classroomGroup))
+                        .keyboardType(.numbersAndPunctuation)
                         .textInputAutocapitalization(.never)
                         .autocorrectionDisabled()
                         .focused($isFocused)
+                } header: {
+                    Text("Classroom Stream")
+                } footer: {
+                    Text("Copy from the PC's Settings > Classroom Multicast. When set, Connect only listens: every phone on the group hears the PC, and no mic audio is sent.")
                 }
             }
             .navigationTitle("Edit PC")
@@ -158,6 +252,9 @@ struct EditPCView: View {
                 }
                 ToolbarItem(placement: .confirmationAction) {
                     Button("Save") {
+                        pc.rendezvousServer = pc.rendezvousServer?.trimmingCharacters(in: .whitespaces)
+                        pc.sessionCode = pc.sessionCode?.trimmingCharacters(in: .whitespaces).uppercased()
+                        pc.classroomGroup = pc.classroomGroup?.trimmingCharacters(in: .whitespaces)
                         pcStore.update(pc)
                         isPresented = false
                     }
@@ -172,6 +269,11 @@ struct EditPCView: View {
             }
         }
     }
+
+    private func optional(_ binding: Binding<String?>) -> Binding<String> {
+        Binding(get: { binding.wrappedValue ?? "" },
+                set: { binding.wrappedValue = $0.isEmpty ? nil : $0 })
+    }
 }
 
 #Preview {
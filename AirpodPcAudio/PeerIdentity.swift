@@ -0,0 +1,78 @@
+import CryptoKit
+import Foundation
+import Security
+
+/// This iPhone's identity: an Ed25519 key made on first use and kept in the
+/// Keychain. It signs the codec ack, and the PC remembers the public key and
+/// warns when the phone at a saved address answers with a different one
+/// (windows/core/src/identity.rs).
+enum PeerIdentity {
+    private static let account = "BudBridge identity key"
+    private static let lock = NSLock()
+    private static var cached: Curve25519.Signing.PrivateKey?
+
+    /// Loaded, or created, on first use
+    static var signingKey: Curve25519.Signing.PrivateKey {
+        lock.lock()
+        defer { lock.unlock() }
+        if let key = cached {
+            return key
+        }
+        let key = load() ?? create()
+        cached = key
+        return key
+    }
+
+    /// Replace the key, when the phone changes hands or the key may have leaked.
+    /// PCs that knew the old one refuse this phone until they're re-paired.
+    @discardableResult
+    static func reset() -> Curve25519.Signing.PrivateKey {
+        lock.lock()
+        defer { lock.unlock() }
+        SecItemDelete([kSecClass as String: kSecClassGenericPassword, kSecAttrAccount as String: account] as CFDictionary)
+        let key = create()
+        cached = key
+        return key
+    }
+
+    /// The first 16 bytes of SHA-256 over the public key, grouped as the PC
+    /// shows them: "65b6 0673 d6ed ..."
+    static func fingerprint(_ publicKey: Curve25519.Signing.PublicKey) -> String {
+        let hex = StreamHandshake.hex(Data(SHA256.hash(data: publicKey.rawRepresentation).prefix(16)))
+        return stride(from: 0, to: hex.count, by: 4)
+            .map { start -> String in
+                let from = hex.index(hex.startIndex, offsetBy: start)
+                return String(hex[from..<hex.index(from, offsetBy: 4)])
+            }
+            .joined(separator: " ")
+    }
+
+    private static func load() -> Curve25519.Signing.PrivateKey? {
+        let query: [String: Any] = [
+            kSecClass as String: kSecClassGenericPassword,
+            kSecAttrAccount as String: account,
+            kSecReturnData as String: true,
+        ]
+        var item: CFTypeRef?
+        guard SecItemCopyMatching(query as CFDictionary, &item) == errSecSuccess, let data = item as? Data else {
+            return nil
+        }
+        return try? Curve25519.Signing.PrivateKey(rawRepresentation: data)
+    }
+
+    private static func create() -> Curve25519.Signing.PrivateKey {
+        let key = Curve25519.Signing.PrivateKey()
+        let add: [String: Any] = [
+            kSecClass as String: kSecClassGenericPassword,
+            kSecAttrAccount as String: account,
+            kSecValueData as String: key.rawRepresentation,
+            // Not in backups: a restored phone is a new identity the PC should notice
+            kSecAttrAccessible as String: kSecAttrAccessibleAfterFirstUnlockThisDeviceOnly,
+        ]
+        let status = SecItemAdd(add as CFDictionary, nil)
+        if status != errSecSuccess {
+            print("⚠️ Could not save the identity key: \(status)")
+        }
+        return key
+    }
+}
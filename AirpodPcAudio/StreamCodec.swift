@@ -0,0 +1,612 @@
+import AVFoundation
+import CryptoKit
+
+/// Codec, channel count and bitrate for one direction, written "opus/2/128"
+/// (the Swift side of windows/core/src/protocol.rs; docs/protocol-vectors.txt has
+/// golden vectors both sides are tested against)
+struct StreamFormat: Equatable, CustomStringConvertible {
+    /// pcm24 is packed 24-bit and f32 is 32-bit float, both little-endian
+    enum Codec: String {
+        case pcm, pcm24, f32, opus
+
+        /// Uncompressed codecs, which need no decoder
+        var isLinear: Bool { self != .opus }
+    }
+
+    let codec: Codec
+    let channels: Int
+    let bitrateKbps: Int
+
+    static let pcmMono = StreamFormat(codec: .pcm, channels: 1, bitrateKbps: 768)
+
+    init(codec: Codec, channels: Int, bitrateKbps: Int) {
+        self.codec = codec
+        self.channels = channels
+        self.bitrateKbps = bitrateKbps
+    }
+
+    init?(_ text: Substring) {
+        let parts = text.split(separator: "/")
+        guard parts.count == 3,
+              let codec = Codec(rawValue: String(parts[0])),
+              let channels = Int(parts[1]), (1...2).contains(channels),
+              let bitrate = Int(parts[2]) else { return nil }
+        self.init(codec: codec, channels: channels, bitrateKbps: bitrate)
+    }
+
+    var description: String { "\(codec.rawValue)/\(channels)/\(bitrateKbps)" }
+
+    /// Mono samples for AudioManager from an uncompressed payload
+    func linearSamples(_ data: Data) -> [Float] {
+        let samples: [Float]
+        switch codec {
+        case .pcm24: samples = AudioConversion.pcm24ToFloat(data)
+        case .f32: samples = AudioConversion.float32Samples(data)
+        case .pcm, .opus: samples = AudioConversion.pcmToFloat(data)
+        }
+        return AudioConversion.downmix(samples, channels: channels)
+    }
+}
+
+/// Down is PC → iPhone, up is iPhone → PC. `redundancy` is how many earlier
+/// frames each Opus packet repeats (see RedundantFrames below). `framed` audio
+/// carries a sequence and timestamp header both ways (see PacketFrame), and
+/// `seal` protects it both ways (see AudioSeal). `ptime` is the milliseconds
+/// in each Opus packet going down; the PC's battery saver asks for 40. `fec` is
+/// how many framed packets each parity packet covers, both ways (see ParityFrames).
+struct StreamFormats: Equatable {
+    let down: StreamFormat
+    let up: StreamFormat
+    var redundancy = 0
+    var framed = false
+    var seal = AudioSeal.Mode.off
+    var ptime = 20
+    var fec = 0
+}
+
+/// The PC offers codecs in a hello; we answer with what we'll actually use.
+/// Until then, and if nothing is offered, both directions are PCM mono.
+enum StreamHandshake {
+    static let helloMagic = Data("BBHELLO1".utf8)
+
+    static func isHello(_ data: Data) -> Bool {
+        data.starts(with: helloMagic)
+    }
+
+    static let maxRedundancy = 3
+    /// Opus frame lengths the PC may ask for going down besides 20 ms
+    static let longerPtimes = [40, 60]
+
+    /// Formats offered in "BBHELLO1 down=opus/2/128 up=opus/1/32 red=2 frame=1"; nil for an ack
+    static func parseOffer(_ data: Data) -> StreamFormats? {
+        guard let text = String(data: data, encoding: .utf8) else { return nil }
+        let fields = text.split(separator: " ")
+        guard fields.first == "BBHELLO1", fields.count > 1, fields[1] != "ACK" else { return nil }
+        var down: StreamFormat?
+        var up: StreamFormat?
+        var redundancy = 0
+        var framed = false
+        var seal = AudioSeal.Mode.off
+        var ptime = 20
+        var fec = 0
+        for field in fields.dropFirst() {
+            if field.hasPrefix("down=") { down = StreamFormat(field.dropFirst(5)) }
+            if field.hasPrefix("up=") { up = StreamFormat(field.dropFirst(3)) }
+            if field.hasPrefix("red=") { redundancy = min(Int(field.dropFirst(4)) ?? 0, maxRedundancy) }
+            if field == "frame=1" { framed = true }
+            if field.hasPrefix("seal=") { seal = AudioSeal.Mode(field.dropFirst(5)) }
+            if field.hasPrefix("ptime="), let ms = Int(field.dropFirst(6)), longerPtimes.contains(ms) { ptime = ms }
+            if field.hasPrefix("fec="), let group = Int(field.dropFirst(4)), ParityFrames.groups.contains(group) { fec = group }
+        }
+        guard let down = down, let up = up else { return nil }
+        return StreamFormats(down: down, up: up, redundancy: redundancy, framed: framed, seal: seal, ptime: ptime, fec: fec)
+    }
+
+    static let castPrefix = Data("BBCAST1 ".utf8)
+
+    /// Formats in a classroom multicast announcement, "BBCAST1 down=opus/1/64 red=1".
+    /// Nothing goes back to a multicast group, so `up` stays PCM mono.
+    static func parseCast(_ data: Data) -> StreamFormats? {
+        guard data.starts(with: castPrefix), let text = String(data: data, encoding: .utf8) else { return nil }
+        var down: StreamFormat?
+        var redundancy = 0
+        for field in text.split(separator: " ").dropFirst() {
+            if field.hasPrefix("down=") { down = StreamFormat(field.dropFirst(5)) }
+            if field.hasPrefix("red=") { redundancy = min(Int(field.dropFirst(4)) ?? 0, maxRedundancy) }
+        }
+        guard let down = down else { return nil }
+        return StreamFormats(down: down, up: .pcmMono, redundancy: redundancy)
+    }
+
+    /// The nonce a hello asks us to sign, as sent: 32 hex digits
+    static func nonce(in data: Data) -> String? {
+        hexField("nonce", in: data)
+    }
+
+    /// The PC a hello names, as sent: 32 hex digits. Nil from PCs before PC ids.
+    static func pcId(in data: Data) -> String? {
+        hexField("pc", in: data)
+    }
+
+    /// The PC's X25519 key a hello offers for sealing audio, 32 bytes
+    static func exchangeKey(in data: Data) -> Data? {
+        hexField("kx", digits: 64, in: data).map(unhex)
+    }
+
+    private static func hexField(_ name: String, digits: Int = 32, in data: Data) -> String? {
+        guard let text = String(data: data, encoding: .utf8),
+              let field = text.split(separator: " ").first(where: { $0.hasPrefix(name + "=") }) else { return nil }
+        let value = field.dropFirst(name.count + 1)
+        guard value.count == digits, value.allSatisfy(\.isHexDigit) else { return nil }
+        return String(value)
+    }
+
+    /// The ack echoes the PC id, so a PC can tell we answered another PC's hello.
+    /// With a nonce it echoes that too, adds our identity key and signs
+    /// everything before " sig=", so the PC knows which phone answered. Agreeing
+    /// to a seal adds our exchange key, which the signature then covers.
+    static func ack(_ formats: StreamFormats, pc: String? = nil, nonce: String? = nil,
+                    signingKey: Curve25519.Signing.PrivateKey? = nil, exchangeKey: Data? = nil) -> Data {
+        var text = "BBHELLO1 ACK down=\(formats.down) up=\(formats.up)"
+        if formats.redundancy > 0 {
+            text += " red=\(formats.redundancy)"
+        }
+        if formats.ptime != 20 {
+            text += " ptime=\(formats.ptime)"
+        }
+        if formats.framed {
+            text += " frame=1"
+        }
+        // Parity needs the frame header
+        if formats.framed && formats.fec > 0 {
+            text += " fec=\(formats.fec)"
+        }
+        if formats.seal != .off, let key = exchangeKey {
+            text += " seal=\(formats.seal.name) kx=\(hex(key))"
+        }
+        if let pc = pc {
+            text += " pc=\(pc)"
+        }
+        if let nonce = nonce, let key = signingKey {
+            text += " nonce=\(nonce) id=\(hex(key.publicKey.rawRepresentation))"
+            if let signature = try? key.signature(for: Data(text.utf8)) {
+                text += " sig=\(hex(signature))"
+            }
+        }
+        return Data(text.utf8)
+    }
+
+    static func hex(_ data: Data) -> String {
+        data.map { String(format: "%02x", $0) }.joined()
+    }
+
+    /// The bytes of a field already checked to be an even number of hex digits
+    static func unhex(_ text: String) -> Data {
+        let digits = Array(text)
+        return Data(stride(from: 0, to: digits.count, by: 2).compactMap { UInt8(String(digits[$0...$0 + 1]), radix: 16) })
+    }
+}
+
+/// RTP RED style redundancy for Opus packets, matching windows/core/src/protocol.rs:
+/// [UInt16 LE sequence of the newest frame][UInt8 frame count], then per frame,
+/// oldest first, [UInt16 LE length][Opus frame]. A lost packet's frame arrives
+/// again in the next one.
+enum RedundantFrames {
+    private static let maxPacket = 1200
+
+    struct Packer {
+        let depth: Int
+        private var history: [Data] = []
+        private var sequence: UInt16 = 0
+
+        init(depth: Int) {
+            self.depth = depth
+        }
+
+        mutating func pack(_ frame: Data) -> Data {
+            history.append(frame)
+            if history.count > depth + 1 {
+                history.removeFirst(history.count - depth - 1)
+            }
+            sequence &+= 1
+
+            // Newest first until the packet is full
+            var count = 0
+            var size = 3
+            for frame in history.reversed() {
+                if count > 0 && size + 2 + frame.count > RedundantFrames.maxPacket { break }
+                size += 2 + frame.count
+                count += 1
+            }
+
+            var packet = Data(capacity: size)
+            packet.append(UInt8(sequence & 0xFF))
+            packet.append(UInt8(sequence >> 8))
+            packet.append(UInt8(count))
+            for frame in history.suffix(count) {
+                packet.append(UInt8(frame.count & 0xFF))
+                packet.append(UInt8(frame.count >> 8))
+                packet.append(frame)
+            }
+            return packet
+        }
+    }
+
+    struct Unpacker {
+        private var last: UInt16?
+
+        /// Frames not played yet, oldest first; empty for late duplicates
+        mutating func unpack(_ packet: Data) -> [Data] {
+            let bytes = [UInt8](packet)
+            guard bytes.count >= 3 else { return [] }
+            let newest = UInt16(bytes[0]) | UInt16(bytes[1]) << 8
+            let count = Int(bytes[2])
+            var frames: [Data] = []
+            var offset = 3
+            for _ in 0..<count {
+                guard offset + 2 <= bytes.count else { return [] }
+                let length = Int(bytes[offset]) | Int(bytes[offset + 1]) << 8
+                offset += 2
+                guard offset + length <= bytes.count else { return [] }
+                frames.append(Data(bytes[offset..<offset + length]))
+                offset += length
+            }
+
+            guard let last = last else {
+                self.last = newest
+                return Array(frames.suffix(1))
+            }
+            let fresh = frames.enumerated().filter { index, _ in
+                let sequence = newest &- UInt16(count - 1 - index)
+                return Int16(bitPattern: sequence &- last) > 0
+            }.map { $0.element }
+            if !fresh.isEmpty {
+                self.last = newest
+            }
+            return fresh
+        }
+    }
+}
+
+/// The header on audio once the ack agrees to frame=1, matching windows/core/src/protocol.rs:
+/// [0xBB][version 1][UInt32 LE sequence][UInt32 LE timestamp][UInt16 LE payload length].
+/// The timestamp counts 48 kHz ticks since the sender started.
+enum PacketFrame {
+    static let headerSize = 12
+    private static let magic: UInt8 = 0xBB
+    private static let version: UInt8 = 1
+
+    static func wrap(_ payload: Data, sequence: UInt32, timestamp: UInt32) -> Data {
+        var packet = Data(capacity: headerSize + payload.count)
+        packet.append(contentsOf: [magic, version])
+        withUnsafeBytes(of: sequence.littleEndian) { packet.append(contentsOf: $0) }
+        withUnsafeBytes(of: timestamp.littleEndian) { packet.append(contentsOf: $0) }
+        withUnsafeBytes(of: UInt16(payload.count).littleEndian) { packet.append(contentsOf: $0) }
+        packet.append(payload)
+        return packet
+    }
+
+    /// Sequence, timestamp and payload; nil for a bare packet or one whose length doesn't match
+    static func unwrap(_ packet: Data) -> (sequence: UInt32, timestamp: UInt32, payload: Data)? {
+        let bytes = [UInt8](packet)
+        guard bytes.count >= headerSize, bytes[0] == magic, bytes[1] == version else { return nil }
+        let field = { (offset: Int, size: Int) in
+            (0..<size).reduce(UInt32(0)) { $0 | UInt32(bytes[offset + $1]) << (8 * $1) }
+        }
+        guard Int(field(10, 2)) == bytes.count - headerSize else { return nil }
+        return (field(2, 4), field(6, 4), Data(bytes[headerSize...]))
+    }
+
+    /// Packets the sequence skipped over. One turning up late still counts, so
+    /// this errs high when the network reorders.
+    struct LossCounter {
+        private(set) var lost = 0
+        private var next: UInt32?
+
+        mutating func record(_ sequence: UInt32) {
+            let ahead = next.map { sequence &- $0 } ?? 0
+            guard ahead < UInt32.max / 2 else { return }
+            lost += Int(ahead)
+            next = sequence &+ 1
+        }
+    }
+}
+
+/// Forward error correction once the ack agrees to fec=N, matching windows/core/src/fec.rs.
+/// After every N framed packets comes a parity packet, their XOR with headers,
+/// each zero-padded to the longest:
+/// [0xBB][0xFE][UInt32 LE sequence of the first][UInt8 count][XOR of the packets].
+/// Any one lost packet of the N is the parity XORed with the others, header and all.
+enum ParityFrames {
+    static let headerSize = 7
+    private static let magic: [UInt8] = [0xBB, 0xFE]
+    /// Packets per parity packet the PC may ask for
+    static let groups = 2...10
+
+    struct Encoder {
+        let group: Int
+        private var first: UInt32 = 0
+        private var count = 0
+        private var parity: [UInt8] = []
+
+        init(group: Int) {
+            self.group = max(group, 1)
+        }
+
+        /// Adds a framed packet as sent, before sealing; returns the parity packet
+        /// to send after it once it completes a group
+        mutating func push(_ packet: Data) -> Data? {
+            guard let frame = PacketFrame.unwrap(packet) else { return nil }
+            // A packet numbered but never sent starts the group again
+            if count == 0 || frame.sequence != first &+ UInt32(count) {
+                first = frame.sequence
+                count = 0
+                parity = []
+            }
+            ParityFrames.xor(&parity, [UInt8](packet))
+            count += 1
+            guard count >= group else { return nil }
+            var parityPacket = Data(ParityFrames.magic)
+            withUnsafeBytes(of: first.littleEndian) { parityPacket.append(contentsOf: $0) }
+            parityPacket.append(UInt8(count))
+            parityPacket.append(contentsOf: parity)
+            count = 0
+            return parityPacket
+        }
+    }
+
+    /// Puts received audio back in order, rebuilding what the parity can. What
+    /// comes after a gap waits for the group's parity; a gap it can't fill (two
+    /// lost, or the parity too) is skipped once a packet from past the group arrives.
+    struct Decoder {
+        let group: Int
+        /// Packets rebuilt from parity after they were lost
+        private(set) var recovered = 0
+        private var next: UInt32?
+        private var held: [(sequence: UInt32, packet: Data)] = []   // after a gap, in order
+        private var recent: [(sequence: UInt32, packet: Data)] = [] // what a parity packet may need
+        /// A sequence this far behind is a PC that started over, not a late packet
+        private static let restart: UInt32 = 1000
+
+        init(group: Int) {
+            self.group = max(group, 1)
+        }
+
+        /// Audio packets ready to play, in order, for an opened packet, audio or
+        /// parity. Bare ones pass straight through.
+        mutating func receive(_ packet: Data) -> [Data] {
+            let bytes = [UInt8](packet)
+            if bytes.count >= ParityFrames.headerSize, Array(bytes[0..<2]) == ParityFrames.magic {
+                let first = (0..<4).reduce(UInt32(0)) { $0 | UInt32(bytes[2 + $1]) << (8 * $1) }
+                guard let rebuilt = rebuild(first: first, count: Int(bytes[6]),
+                                            parity: Array(bytes[ParityFrames.headerSize...])) else { return [] }
+                recovered += 1
+                return accept(rebuilt.sequence, rebuilt.packet)
+            }
+            guard let frame = PacketFrame.unwrap(packet) else { return [packet] }
+            return accept(frame.sequence, packet)
+        }
+
+        private mutating func accept(_ sequence: UInt32, _ packet: Data) -> [Data] {
+            guard let next = next else {
+                remember(sequence, packet)
+                self.next = sequence &+ 1
+                return [packet]
+            }
+            let behind = next &- sequence
+            if behind > Decoder.restart && behind < UInt32.max / 2 {
+                // What was held is older than anything the PC sends now
+                let recovered = self.recovered
+                self = Decoder(group: group)
+                self.recovered = recovered
+                return accept(sequence, packet)
+            }
+            // Late or a duplicate: its turn has passed
+            if behind != 0 && behind < UInt32.max / 2 { return [] }
+            remember(sequence, packet)
+            if behind == 0 && held.isEmpty {
+                self.next = sequence &+ 1
+                return [packet]
+            }
+            if !held.contains(where: { $0.sequence == sequence }) {
+                held.append((sequence, packet))
+                held.sort { $0.sequence &- next < $1.sequence &- next }
+            }
+            return release()
+        }
+
+        /// What's held up to the next gap, skipping a gap the group's parity
+        /// should have filled by now
+        private mutating func release() -> [Data] {
+            guard var next = next else { return [] }
+            var ready: [Data] = []
+            while let first = held.first {
+                if first.sequence == next {
+                    ready.append(held.removeFirst().packet)
+                    next = next &+ 1
+                    continue
+                }
+                guard let newest = held.last, newest.sequence &- next >= UInt32(group) else { break }
+                next = first.sequence
+            }
+            self.next = next
+            return ready
+        }
+
+        private mutating func remember(_ sequence: UInt32, _ packet: Data) {
+            guard !recent.contains(where: { $0.sequence == sequence }) else { return }
+            recent.append((sequence, packet))
+            if recent.count > 2 * group {
+                recent.removeFirst(recent.count - 2 * group)
+            }
+        }
+
+        /// The one packet of a group that's missing and still wanted, rebuilt
+        private func rebuild(first: UInt32, count: Int, parity: [UInt8]) -> (sequence: UInt32, packet: Data)? {
+            guard let next = next else { return nil }
+            let missing = (0..<count).map { first &+ UInt32($0) }.filter { sequence in
+                !recent.contains { $0.sequence == sequence }
+            }
+            guard missing.count == 1, missing[0] &- next < UInt32.max / 2 else { return nil }
+            var rebuilt = parity
+            for entry in recent where entry.sequence &- first < UInt32(count) {
+                ParityFrames.xor(&rebuilt, [UInt8](entry.packet))
+            }
+            // Its own header says how much of the padded XOR is the packet
+            guard rebuilt.count >= PacketFrame.headerSize else { return nil }
+            let length = PacketFrame.headerSize + (Int(rebuilt[10]) | Int(rebuilt[11]) << 8)
+            guard length <= rebuilt.count else { return nil }
+            let packet = Data(rebuilt[..<length])
+            guard PacketFrame.unwrap(packet)?.sequence == missing[0] else { return nil }
+            return (missing[0], packet)
+        }
+    }
+
+    private static func xor(_ parity: inout [UInt8], _ packet: [UInt8]) {
+        if parity.count < packet.count {
+            parity += [UInt8](repeating: 0, count: packet.count - parity.count)
+        }
+        for (i, byte) in packet.enumerated() {
+            parity[i] ^= byte
+        }
+    }
+}
+
+/// Opus via AudioToolbox. Frames are 20 ms at 48 kHz unless the PC asked for
+/// longer ones going down, one per packet.
+private func opusFormat(channels: Int, ptime: Int = 20) -> AVAudioFormat? {
+    var description = AudioStreamBasicDescription(
+        mSampleRate: 48000,
+        mFormatID: kAudioFormatOpus,
+        mFormatFlags: 0,
+        mBytesPerPacket: 0,
+        mFramesPerPacket: UInt32(48 * ptime),
+        mBytesPerFrame: 0,
+        mChannelsPerFrame: UInt32(channels),
+        mBitsPerChannel: 0,
+        mReserved: 0
+    )
+    return AVAudioFormat(streamDescription: &description)
+}
+
+private func int16Format(channels: Int) -> AVAudioFormat? {
+    AVAudioFormat(commonFormat: .pcmFormatInt16, sampleRate: 48000, channels: AVAudioChannelCount(channels), interleaved: true)
+}
+
+/// Mono Int16 PCM (what AudioManager captures) in, Opus packets out
+final class OpusEncoder {
+    private let converter: AVAudioConverter
+    private let pcmFormat: AVAudioFormat
+    private let opus: AVAudioFormat
+    private let channels: Int
+    private var pending: [Int16] = []
+    private let frameSize = 960
+
+    init?(format: StreamFormat) {
+        guard format.codec == .opus,
+              let pcmFormat = int16Format(channels: format.channels),
+              let opus = opusFormat(channels: format.channels),
+              let converter = AVAudioConverter(from: pcmFormat, to: opus) else { return nil }
+        converter.bitRate = format.bitrateKbps * 1000
+        self.converter = converter
+        self.pcmFormat = pcmFormat
+        self.opus = opus
+        self.channels = format.channels
+    }
+
+    func encode(_ pcm: Data) -> [Data] {
+        pcm.withUnsafeBytes { ptr in
+            pending.append(contentsOf: ptr.bindMemory(to: Int16.self))
+        }
+
+        var packets: [Data] = []
+        while pending.count >= frameSize {
+            let frame = pending.prefix(frameSize)
+            pending.removeFirst(frameSize)
+            guard let input = AVAudioPCMBuffer(pcmFormat: pcmFormat, frameCapacity: AVAudioFrameCount(frameSize)),
+                  let samples = input.int16ChannelData?[0] else { continue }
+            input.frameLength = AVAudioFrameCount(frameSize)
+            // Mono mic duplicated into each channel
+            for (i, sample) in frame.enumerated() {
+                for channel in 0..<channels {
+                    samples[i * channels + channel] = sample
+                }
+            }
+
+            let output = AVAudioCompressedBuffer(format: opus, packetCapacity: 1,
+                                                 maximumPacketSize: max(converter.maximumOutputPacketSize, 1275))
+            var supplied = false
+            var error: NSError?
+            let status = converter.convert(to: output, error: &error) { _, inputStatus in
+                if supplied {
+                    inputStatus.pointee = .noDataNow
+                    return nil
+                }
+                supplied = true
+                inputStatus.pointee = .haveData
+                return input
+            }
+            if status != .error, output.byteLength > 0 {
+                packets.append(Data(bytes: output.data, count: Int(output.byteLength)))
+            }
+        }
+        return packets
+    }
+}
+
+/// Opus packets in, mono Int16 PCM (what AudioManager plays) out
+final class OpusDecoder {
+    private let converter: AVAudioConverter
+    private let pcmFormat: AVAudioFormat
+    private let opus: AVAudioFormat
+    private let channels: Int
+
+    init?(format: StreamFormat, ptime: Int = 20) {
+        guard format.codec == .opus,
+              let pcmFormat = int16Format(channels: format.channels),
+              let opus = opusFormat(channels: format.channels, ptime: ptime),
+              let converter = AVAudioConverter(from: opus, to: pcmFormat) else { return nil }
+        self.converter = converter
+        self.pcmFormat = pcmFormat
+        self.opus = opus
+        self.channels = format.channels
+    }
+
+    func decode(_ packet: Data) -> Data {
+        let input = AVAudioCompressedBuffer(format: opus, packetCapacity: 1, maximumPacketSize: packet.count)
+        packet.withUnsafeBytes { ptr in
+            input.data.copyMemory(from: ptr.baseAddress!, byteCount: packet.count)
+        }
+        input.byteLength = UInt32(packet.count)
+        input.packetCount = 1
+        input.packetDescriptions?.pointee = AudioStreamPacketDescription(
+            mStartOffset: 0, mVariableFramesInPacket: 0, mDataByteSize: UInt32(packet.count))
+
+        // Opus packets hold at most 120 ms
+        guard let output = AVAudioPCMBuffer(pcmFormat: pcmFormat, frameCapacity: 5760) else { return Data() }
+        var supplied = false
+        var error: NSError?
+        let status = converter.convert(to: output, error: &error) { _, inputStatus in
+            if supplied {
+                inputStatus.pointee = .noDataNow
+                return nil
+            }
+            supplied = true
+            inputStatus.pointee = .haveData
+            return input
+        }
+        guard status != .error, let samples = output.int16ChannelData?[0] else { return Data() }
+
+        // Playback is mono: the AirPods mic keeps them on the mono Bluetooth (HFP) route
+        let frames = Int(output.frameLength)
+        var mono = [Int16](repeating: 0, count: frames)
+        for i in 0..<frames {
+            var sum = 0
+            for channel in 0..<channels {
+                sum += Int(samples[i * channels + channel])
+            }
+            mono[i] = Int16(sum / channels)
+        }
+        return mono.withUnsafeBufferPointer { Data(buffer: $0) }
+    }
+}
@@ -50,6 +50,33 @@ final class AudioConversionTests: XCTestCase {
         XCTAssertEqual(result.count, 0)
     }
 
+    // MARK: - 24-bit and Float Transport
+
+    func testPCM24ToFloat_signExtends() {
+        // 0x400000 = half max, 0xC00000 = minus half, 0x7FFFFF = max
+        let pcmData = Data([0x00, 0x00, 0x40, 0x00, 0x00, 0xC0, 0xFF, 0xFF, 0x7F])
+        let result = AudioConversion.pcm24ToFloat(pcmData)
+
+        XCTAssertEqual(result.count, 3)
+        XCTAssertEqual(result[0], 0.5, accuracy: 0.000001)
+        XCTAssertEqual(result[1], -0.5, accuracy: 0.000001)
+        XCTAssertLessThan(result[2], 1.0)
+        XCTAssertGreaterThan(result[2], 0.99999)
+    }
+
+    func testFloat32Samples_littleEndian() {
+        var data = Data()
+        for value: Float in [0.25, -0.125] {
+            withUnsafeBytes(of: value.bitPattern.littleEndian) { data.append(contentsOf: $0) }
+        }
+        XCTAssertEqual(AudioConversion.float32Samples(data), [0.25, -0.125])
+    }
+
+    func testDownmix_averagesChannels() {
+        XCTAssertEqual(AudioConversion.downmix([0.5, 0.25, -1.0, 1.0], channels: 2), [0.375, 0.0])
+        XCTAssertEqual(AudioConversion.downmix([0.5, 0.25], channels: 1), [0.5, 0.25])
+    }
+
     // MARK: - Float to PCM Conversion
 
     func testFloatToPCM_silence() {
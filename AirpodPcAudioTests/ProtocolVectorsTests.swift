@@ -0,0 +1,249 @@
+import CryptoKit
+import XCTest
+@testable import AirpodPcAudio
+
+/// Checks the Swift side of the wire format against docs/protocol-vectors.txt,
+/// the same golden vectors windows/core/src/protocol.rs is tested against
+final class ProtocolVectorsTests: XCTestCase {
+
+    private static let vectors: [String: Data] = {
+        let url = URL(fileURLWithPath: #filePath)
+            .deletingLastPathComponent()
+            .deletingLastPathComponent()
+            .appendingPathComponent("docs/protocol-vectors.txt")
+        guard let text = try? String(contentsOf: url, encoding: .utf8) else { return [:] }
+        var vectors: [String: Data] = [:]
+        for line in text.split(separator: "\n") where !line.hasPrefix("#") {
+            let parts = line.split(separator: " ")
+            guard parts.count == 2 else { continue }
+            let hex = Array(parts[1])
+            let bytes = stride(from: 0, to: hex.count, by: 2).compactMap { UInt8(String(hex[$0...$0 + 1]), radix: 16) }
+            vectors[String(parts[0])] = Data(bytes)
+        }
+        return vectors
+    }()
+
+    private func vector(_ name: String) -> Data {
+        guard let data = Self.vectors[name] else {
+            XCTFail("No vector named \(name)")
+            return Data()
+        }
+        return data
+    }
+
+    private func format(_ text: String) -> StreamFormat {
+        StreamFormat(Substring(text))!
+    }
+
+    // MARK: - Handshake
+
+    func testHello_parsesOffers() {
+        let opus = StreamHandshake.parseOffer(vector("hello.opus"))
+        XCTAssertEqual(opus, StreamFormats(down: format("opus/2/128"), up: format("opus/1/32"), framed: true))
+
+        let redundant = StreamHandshake.parseOffer(vector("hello.redundant"))
+        XCTAssertEqual(redundant, StreamFormats(down: format("pcm24/1/1152"), up: format("opus/1/16"), redundancy: 2, framed: true))
+
+        let longer = StreamHandshake.parseOffer(vector("hello.ptime"))
+        XCTAssertEqual(longer, StreamFormats(down: format("opus/2/128"), up: format("opus/1/32"), framed: true, ptime: 40))
+
+        let corrected = StreamHandshake.parseOffer(vector("hello.fec"))
+        XCTAssertEqual(corrected, StreamFormats(down: format("opus/2/128"), up: format("opus/1/32"), framed: true, fec: 4))
+
+        XCTAssertTrue(StreamHandshake.isHello(vector("ack.opus_down")))
+        XCTAssertNil(StreamHandshake.parseOffer(vector("ack.opus_down")))
+    }
+
+    func testAck_matchesVectors() {
+        let opusDown = StreamFormats(down: format("opus/2/128"), up: .pcmMono)
+        XCTAssertEqual(StreamHandshake.ack(opusDown), vector("ack.opus_down"))
+
+        let redundant = StreamFormats(down: format("f32/1/1536"), up: format("opus/1/32"), redundancy: 2)
+        XCTAssertEqual(StreamHandshake.ack(redundant), vector("ack.redundant"))
+
+        let framed = StreamFormats(down: format("opus/2/128"), up: format("opus/1/32"), redundancy: 1, framed: true)
+        XCTAssertEqual(StreamHandshake.ack(framed), vector("ack.framed"))
+
+        let longer = StreamFormats(down: format("opus/2/128"), up: format("opus/1/32"), framed: true, ptime: 40)
+        XCTAssertEqual(StreamHandshake.ack(longer), vector("ack.ptime"))
+
+        let corrected = StreamFormats(down: format("opus/2/128"), up: format("opus/1/32"), framed: true, fec: 4)
+        XCTAssertEqual(StreamHandshake.ack(corrected), vector("ack.fec"))
+    }
+
+    func testSignedAck_matchesVectors() throws {
+        let nonce = StreamHandshake.nonce(in: vector("hello.nonce"))
+        XCTAssertEqual(nonce, "000102030405060708090a0b0c0d0e0f")
+        XCTAssertEqual(StreamHandshake.parseOffer(vector("hello.nonce")), StreamFormats(down: .pcmMono, up: .pcmMono, framed: true))
+
+        // Ed25519 signatures from CryptoKit are randomized, so compare what is
+        // signed and check that both signatures verify
+        let key = try Curve25519.Signing.PrivateKey(rawRepresentation: Data(1...32))
+        XCTAssertEqual(key.publicKey.rawRepresentation, vector("identity.key"))
+        let ours = String(decoding: StreamHandshake.ack(StreamFormats(down: .pcmMono, up: .pcmMono), nonce: nonce, signingKey: key), as: UTF8.self)
+        let theirs = String(decoding: vector("ack.signed"), as: UTF8.self)
+        for ack in [ours, theirs] {
+            let parts = ack.components(separatedBy: " sig=")
+            XCTAssertEqual(parts.count, 2)
+            XCTAssertEqual(parts[0], theirs.components(separatedBy: " sig=")[0])
+            let hex = Array(parts[1])
+            let signature = Data(stride(from: 0, to: hex.count, by: 2).compactMap { UInt8(String(hex[$0...$0 + 1]), radix: 16) })
+            XCTAssertTrue(key.publicKey.isValidSignature(signature, for: Data(parts[0].utf8)))
+        }
+
+        let fingerprint = PeerIdentity.fingerprint(key.publicKey)
+        XCTAssertEqual(fingerprint.replacingOccurrences(of: " ", with: ""), StreamHandshake.hex(vector("identity.fingerprint")))
+    }
+
+    func testPcIds_matchVectors() {
+        let pc = StreamHandshake.pcId(in: vector("hello.pc"))
+        XCTAssertEqual(pc, "101112131415161718191a1b1c1d1e1f")
+        XCTAssertEqual(StreamHandshake.nonce(in: vector("hello.pc")), "000102030405060708090a0b0c0d0e0f")
+        XCTAssertEqual(StreamHandshake.ack(StreamFormats(down: .pcmMono, up: .pcmMono), pc: pc), vector("ack.pc"))
+
+        XCTAssertEqual(NetworkManager.pairedPC(in: vector("pair")), pc)
+        XCTAssertTrue(NetworkManager.isControl(vector("pair")))
+
+        // PCs from before PC ids leave it out
+        XCTAssertNil(StreamHandshake.pcId(in: vector("hello.nonce")))
+    }
+
+    func testSealedAudio_matchesVectors() throws {
+        let pc = try Curve25519.KeyAgreement.PrivateKey(rawRepresentation: vector("seal.pc_secret"))
+        let phone = try Curve25519.KeyAgreement.PrivateKey(rawRepresentation: vector("seal.phone_secret"))
+        let hello = vector("hello.seal")
+        let offer = try XCTUnwrap(StreamHandshake.parseOffer(hello))
+        XCTAssertEqual(offer, StreamFormats(down: .pcmMono, up: .pcmMono, framed: true, seal: .aead))
+        XCTAssertEqual(StreamHandshake.exchangeKey(in: hello), pc.publicKey.rawRepresentation)
+        XCTAssertEqual(StreamHandshake.ack(offer, exchangeKey: phone.publicKey.rawRepresentation), vector("ack.seal"))
+        // Without a seal agreed the key stays out
+        XCTAssertEqual(StreamHandshake.parseOffer(vector("hello.nonce"))?.seal, .off)
+        XCTAssertEqual(StreamHandshake.ack(StreamFormats(down: .pcmMono, up: .pcmMono), pc: "101112131415161718191a1b1c1d1e1f",
+                                           exchangeKey: phone.publicKey.rawRepresentation), vector("ack.pc"))
+
+        let nonce = StreamHandshake.unhex(try XCTUnwrap(StreamHandshake.nonce(in: hello)))
+        for (mode, down, up) in [(AudioSeal.Mode.mac, "seal.mac", "seal.up.mac"), (.aead, "seal.aead", "seal.up.aead")] {
+            var (sealer, opener) = try XCTUnwrap(AudioSeal.ends(mode, ours: phone, pcKey: pc.publicKey.rawRepresentation, nonce: nonce))
+            XCTAssertEqual(opener.open(vector(down)), Data([0xAA, 0xBB]))
+            XCTAssertNil(opener.open(vector(down)), "a replay opens")
+            XCTAssertEqual(sealer.seal(Data([0xCC, 0xDD])), vector(up))
+        }
+    }
+
+    func testCastAnnouncements_parse() {
+        let opus = StreamHandshake.parseCast(vector("cast.opus"))
+        XCTAssertEqual(opus, StreamFormats(down: format("opus/1/64"), up: .pcmMono, redundancy: 1))
+        XCTAssertEqual(StreamHandshake.parseCast(vector("cast.pcm")), StreamFormats(down: .pcmMono, up: .pcmMono))
+        XCTAssertNil(StreamHandshake.parseCast(vector("hello.opus")))
+        XCTAssertFalse(StreamHandshake.isHello(vector("cast.opus")))
+    }
+
+    // MARK: - Redundancy
+
+    func testRedundantFrames_matchVectors() {
+        var packer = RedundantFrames.Packer(depth: 2)
+        XCTAssertEqual(packer.pack(Data([0xAA, 0xBB])), vector("red.first"))
+
+        packer = RedundantFrames.Packer(depth: 2)
+        _ = packer.pack(Data([0xA1]))
+        _ = packer.pack(Data([0xB1, 0xB2]))
+        XCTAssertEqual(packer.pack(Data([0xC1, 0xC2, 0xC3])), vector("red.third"))
+
+        // After packet 1, packet 3 brings back the lost frame 2 as well
+        var unpacker = RedundantFrames.Unpacker()
+        _ = unpacker.unpack(vector("red.first"))
+        XCTAssertEqual(unpacker.unpack(vector("red.third")), [Data([0xB1, 0xB2]), Data([0xC1, 0xC2, 0xC3])])
+    }
+
+    // MARK: - Framing
+
+    func testPacketFrame_matchesVector() {
+        let packet = vector("frame.audio")
+        XCTAssertEqual(PacketFrame.wrap(Data([0xAA, 0xBB]), sequence: 0x01020304, timestamp: 0x05060708), packet)
+        let frame = PacketFrame.unwrap(packet)
+        XCTAssertEqual(frame?.sequence, 0x01020304)
+        XCTAssertEqual(frame?.timestamp, 0x05060708)
+        XCTAssertEqual(frame?.payload, Data([0xAA, 0xBB]))
+        XCTAssertNil(PacketFrame.unwrap(packet.dropLast()))
+
+        var loss = PacketFrame.LossCounter()
+        [7, 8, 11, 9].forEach { loss.record($0) }
+        XCTAssertEqual(loss.lost, 2)
+    }
+
+    // MARK: - Error correction
+
+    func testParityFrames_matchVector() {
+        let first = PacketFrame.wrap(Data([0xAA, 0xBB]), sequence: 0, timestamp: 0)
+        let second = PacketFrame.wrap(Data([0xCC]), sequence: 1, timestamp: 960)
+        var encoder = ParityFrames.Encoder(group: 2)
+        XCTAssertNil(encoder.push(first))
+        XCTAssertEqual(encoder.push(second), vector("fec.parity"))
+
+        // The second comes back from the first and the parity
+        var decoder = ParityFrames.Decoder(group: 2)
+        XCTAssertEqual(decoder.receive(first), [first])
+        XCTAssertEqual(decoder.receive(vector("fec.parity")), [second])
+        XCTAssertEqual(decoder.recovered, 1)
+    }
+
+    func testParityFrames_holdAudioAfterAGapUntilTheParity() {
+        let packets = (0..<8).map { PacketFrame.wrap(Data(repeating: UInt8($0), count: 1 + $0 % 3), sequence: UInt32($0), timestamp: 0) }
+        var encoder = ParityFrames.Encoder(group: 4)
+        var decoder = ParityFrames.Decoder(group: 4)
+        var played: [Data] = []
+        for (sequence, packet) in packets.enumerated() {
+            let parity = encoder.push(packet)
+            if sequence != 1 && sequence != 6 {
+                played += decoder.receive(packet)
+            }
+            if let parity = parity {
+                played += decoder.receive(parity)
+            }
+        }
+        XCTAssertEqual(played, packets)
+        XCTAssertEqual(decoder.recovered, 2)
+    }
+
+    // MARK: - Samples
+
+    func testSamples_matchVectors() {
+        let pcm = AudioConversion.pcmToFloat(vector("samples.pcm"))
+        XCTAssertEqual(pcm[0], 0.5, accuracy: 0.0001)
+        XCTAssertEqual(pcm[1], -0.5, accuracy: 0.0001)
+
+        let pcm24 = AudioConversion.pcm24ToFloat(vector("samples.pcm24"))
+        XCTAssertEqual(pcm24[0], 0.5, accuracy: 0.000001)
+        XCTAssertEqual(pcm24[1], -0.5, accuracy: 0.000001)
+
+        XCTAssertEqual(AudioConversion.float32Samples(vector("samples.f32")), [0.5, -0.25])
+    }
+
+    // MARK: - Control frames
+
+    func testControlFrames_areRecognized() {
+        XCTAssertTrue(NetworkManager.isProbe(vector("probe.header")))
+
+        // Clock probes get our stamps after the header; link test probes go back as they came
+        var clock = vector("probe.clock")
+        clock.replaceSubrange(21..<37, with: Data(count: 16))
+        XCTAssertEqual(NetworkManager.echo(clock, receivedAt: 5_000_000, repliedAt: 5_000_250), vector("probe.clock"))
+        var link = vector("probe.header")
+        link.append(Data(count: 16))
+        XCTAssertEqual(NetworkManager.echo(link, receivedAt: 1, repliedAt: 2), link)
+        XCTAssertEqual(vector("punch"), NetworkManager.punchMagic)
+        XCTAssertTrue(NetworkManager.isRelaying(vector("rendezvous.relaying")))
+        XCTAssertFalse(NetworkManager.isRelaying(vector("rendezvous.peer")))
+        for name in ["punch", "rendezvous.register", "rendezvous.peer", "rendezvous.relay", "rendezvous.relaying", "test.marker"] {
+            XCTAssertTrue(NetworkManager.isControl(vector(name)), name)
+        }
+        XCTAssertFalse(NetworkManager.isControl(vector("hello.opus")))
+        XCTAssertFalse(NetworkManager.isProbe(vector("punch")))
+    }
+
+    func testTestSound_matchesVectors() {
+        XCTAssertEqual(NetworkManager.testMarker(in: vector("test.marker")), 7)
+        XCTAssertNil(NetworkManager.testMarker(in: vector("test.played")))
+        XCTAssertEqual(NetworkManager.testReport(id: 7, peakDb: -12), vector("test.played"))
+    }
+}
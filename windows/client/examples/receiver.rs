@@ -0,0 +1,140 @@
+// Plays a BudBridge stream on a cpal output device, standing in for the iPhone:
+// add this machine's address as a device on the PC and press Connect. Prints
+// what the handshake agreed and, once a second, what arrived, so it doubles as
+// a conformance check for senders.
+//
+//   cargo run -p budbridge-client --example receiver -- [--port 4811] [--device <name>] [--list]
+
+use anyhow::{anyhow, Result};
+use budbridge_client::{Event, JitterBuffer, Receiver, DEFAULT_PORT, SAMPLE_RATE};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, SampleRate, StreamConfig};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+fn main() -> Result<()> {
+    let mut port = DEFAULT_PORT;
+    let mut device_name: Option<String> = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => port = args.next().and_then(|p| p.parse().ok()).ok_or_else(|| anyhow!("--port needs a number"))?,
+            "--device" => device_name = Some(args.next().ok_or_else(|| anyhow!("--device needs a name"))?),
+            "--list" => {
+                for device in cpal::default_host().output_devices()? {
+                    println!("{}", device.name()?);
+                }
+                return Ok(());
+            }
+            other => return Err(anyhow!("unknown argument {} (try --port, --device or --list)", other)),
+        }
+    }
+
+    let host = cpal::default_host();
+    let device = match &device_name {
+        Some(name) => host
+            .output_devices()?
+            .find(|d| d.name().is_ok_and(|n| n == *name))
+            .ok_or_else(|| anyhow!("no output device named {:?}; --list shows them", name))?,
+        None => host.default_output_device().ok_or_else(|| anyhow!("no default output device"))?,
+    };
+
+    let mut receiver = Receiver::bind(port)?;
+    let config = output_config(&device)?;
+    let stream = play(&device, &config, receiver.buffer())?;
+    stream.play()?;
+    println!(
+        "Listening on {}, playing on {} at {} Hz",
+        receiver.local_addr()?,
+        device.name()?,
+        config.sample_rate.0
+    );
+
+    let (mut packets, mut frames) = (0u64, 0usize);
+    let mut last_report = Instant::now();
+    loop {
+        match receiver.receive()? {
+            Some(Event::Hello(hello)) => println!("Hello: acked {}", hello.ack()),
+            Some(Event::Audio(decoded)) => {
+                packets += 1;
+                frames += decoded;
+            }
+            Some(Event::Control) | None => {}
+        }
+        if last_report.elapsed() >= Duration::from_secs(1) {
+            let buffer = receiver.buffer();
+            let buffer = buffer.lock().unwrap();
+            println!(
+                "{}: {} packets, {} ms of audio | lost {}, recovered {} | queued {} ms, underruns {}, dropped {} frames",
+                receiver.format(),
+                packets,
+                frames * 1000 / SAMPLE_RATE as usize,
+                receiver.lost_packets(),
+                receiver.recovered_frames(),
+                buffer.queued_ms(),
+                buffer.underruns(),
+                buffer.dropped_frames()
+            );
+            (packets, frames) = (0, 0);
+            last_report = Instant::now();
+        }
+    }
+}
+
+/// 48 kHz if the device takes it, so nothing needs resampling; else its default
+fn output_config(device: &Device) -> Result<StreamConfig> {
+    let native = device.supported_output_configs()?.find(|range| {
+        range.sample_format() == cpal::SampleFormat::F32
+            && (range.min_sample_rate().0..=range.max_sample_rate().0).contains(&SAMPLE_RATE)
+    });
+    Ok(match native {
+        Some(range) => range.with_sample_rate(SampleRate(SAMPLE_RATE)).into(),
+        None => device.default_output_config()?.into(),
+    })
+}
+
+/// Pulls stereo from the jitter buffer, resampling linearly when the device
+/// doesn't run at 48 kHz, and spreads it over the device's channels
+fn play(device: &Device, config: &StreamConfig, buffer: Arc<Mutex<JitterBuffer>>) -> Result<cpal::Stream> {
+    let channels = config.channels as usize;
+    let step = SAMPLE_RATE as f64 / config.sample_rate.0 as f64;
+    let mut pulled: Vec<f32> = Vec::new();
+    let mut phase = 0.0f64;
+    let mut last = [0.0f32; 2];
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+            let frames = data.len() / channels;
+            // Input frames this block spans, plus one to interpolate towards
+            let needed = (phase + frames as f64 * step).floor() as usize + 1;
+            pulled.resize(needed * 2, 0.0);
+            pulled[..2].copy_from_slice(&last);
+            buffer.lock().unwrap().pull(&mut pulled[2..]);
+            for (i, out) in data.chunks_mut(channels).enumerate() {
+                let position = phase + i as f64 * step;
+                let (index, frac) = (position.floor() as usize, position.fract() as f32);
+                let at = |channel: usize| {
+                    let a = pulled[index * 2 + channel];
+                    let b = pulled[(index + 1).min(needed - 1) * 2 + channel];
+                    a + (b - a) * frac
+                };
+                let (left, right) = (at(0), at(1));
+                match out {
+                    [mono] => *mono = (left + right) * 0.5,
+                    [l, r, rest @ ..] => {
+                        (*l, *r) = (left, right);
+                        rest.fill(0.0);
+                    }
+                    [] => {}
+                }
+            }
+            let end = phase + frames as f64 * step;
+            let consumed = end.floor() as usize;
+            phase = end - consumed as f64;
+            last.copy_from_slice(&pulled[consumed.min(needed - 1) * 2..][..2]);
+        },
+        |e| eprintln!("Playback error: {}", e),
+        None,
+    )?;
+    Ok(stream)
+}
@@ -0,0 +1,113 @@
+// Audio payloads to 48 kHz interleaved stereo float. PCM is little-endian
+// 16-bit, packed 24-bit or 32-bit float, interleaved when stereo. Opus is one
+// 20 ms frame per datagram, or with redundancy a RED packet:
+//
+//   [u16 LE sequence of the newest frame][u8 frame count]
+//   then for each frame, oldest first: [u16 LE length][Opus frame]
+//
+// Frames already played are skipped, so a redundant copy only fills in for a
+// packet that was lost. The RED unpacking is budbridge-core's, the same code
+// the PC reads the phone's packets with.
+
+use budbridge_core::protocol::{Codec, RedUnpacker, StreamFormat};
+use anyhow::{anyhow, Result};
+use audiopus::coder::Decoder as OpusDecoder;
+use audiopus::packet::Packet;
+use audiopus::{Channels, MutSignals, SampleRate};
+
+const MAX_OPUS_FRAME: usize = 5760; // 120 ms, the longest frame a decoder may be handed
+
+pub struct Decoder {
+    format: StreamFormat,
+    opus: Option<OpusDecoder>,
+    red: Option<RedUnpacker>,
+}
+
+impl Decoder {
+    /// `redundancy` only applies to Opus
+    pub fn new(format: StreamFormat, redundancy: u8) -> Result<Self> {
+        let opus = match format.codec {
+            Codec::Opus => {
+                let channels = if format.channels == 2 { Channels::Stereo } else { Channels::Mono };
+                Some(OpusDecoder::new(SampleRate::Hz48000, channels).map_err(|e| anyhow!("Opus decoder: {}", e))?)
+            }
+            _ => None,
+        };
+        let red = (opus.is_some() && redundancy > 0).then(RedUnpacker::default);
+        Ok(Self { format, opus, red })
+    }
+
+    pub fn format(&self) -> StreamFormat {
+        self.format
+    }
+
+    /// Frames restored from redundant copies after their own packet was lost
+    pub fn recovered_frames(&self) -> u64 {
+        self.red.as_ref().map_or(0, |red| red.recovered())
+    }
+
+    /// Stereo samples; empty if the payload couldn't be decoded or was a late duplicate
+    pub fn decode(&mut self, payload: &[u8]) -> Vec<f32> {
+        let channels = self.format.channels as usize;
+        let samples = match (&mut self.opus, &mut self.red) {
+            (None, _) => unpack_samples(self.format.codec, payload),
+            (Some(opus), None) => decode_opus(opus, payload, channels),
+            (Some(opus), Some(red)) => {
+                red.unpack(payload).into_iter().flat_map(|frame| decode_opus(opus, frame, channels)).collect()
+            }
+        };
+        if channels == 2 {
+            samples
+        } else {
+            samples.iter().flat_map(|&s| [s, s]).collect()
+        }
+    }
+}
+
+fn unpack_samples(codec: Codec, payload: &[u8]) -> Vec<f32> {
+    match codec {
+        Codec::Pcm24 => payload
+            .chunks_exact(3)
+            .map(|s| (i32::from_le_bytes([0, s[0], s[1], s[2]]) >> 8) as f32 / 8_388_608.0)
+            .collect(),
+        Codec::Float => payload.chunks_exact(4).map(|s| f32::from_le_bytes([s[0], s[1], s[2], s[3]])).collect(),
+        Codec::Pcm | Codec::Opus => {
+            payload.chunks_exact(2).map(|s| i16::from_le_bytes([s[0], s[1]]) as f32 / 32768.0).collect()
+        }
+    }
+}
+
+fn decode_opus(opus: &mut OpusDecoder, frame: &[u8], channels: usize) -> Vec<f32> {
+    let mut out = vec![0f32; MAX_OPUS_FRAME * channels];
+    let decoded = Packet::try_from(frame)
+        .ok()
+        .zip(MutSignals::try_from(&mut out[..]).ok())
+        .and_then(|(packet, signals)| opus.decode_float(Some(packet), signals, false).ok());
+    match decoded {
+        Some(frames) => {
+            out.truncate(frames * channels);
+            out
+        }
+        None => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::vector;
+
+    #[test]
+    fn decodes_the_vector_payloads() {
+        let decode = |format: &str, name: &str| {
+            Decoder::new(StreamFormat::parse(format).unwrap(), 0).unwrap().decode(&vector(name))
+        };
+        // Mono comes out doubled to stereo
+        let pcm = decode("pcm/1/768", "samples.pcm");
+        assert_eq!(pcm.len(), 4);
+        assert!((pcm[0] - 0.5).abs() < 0.001 && pcm[0] == pcm[1] && (pcm[2] + 0.5).abs() < 0.001);
+        let pcm24 = decode("pcm24/2/2304", "samples.pcm24");
+        assert!((pcm24[0] - 0.5).abs() < 0.0001 && (pcm24[1] + 0.5).abs() < 0.0001);
+        assert_eq!(decode("f32/2/3072", "samples.f32"), [0.5, -0.25]);
+    }
+}
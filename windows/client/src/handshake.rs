@@ -0,0 +1,92 @@
+// The PC's codec hello and this receiver's ack. The ack agrees to everything
+// offered: every codec decodes here, and nothing goes up, so the up format is
+// only echoed. It isn't signed; the PC treats it like an app from before
+// identity keys.
+//
+//   PC -> receiver   "BBHELLO1 down=opus/2/128 up=opus/1/32 red=1 uprate=any frame=1 nonce=<hex> pc=<hex>"
+//   receiver -> PC   "BBHELLO1 ACK down=opus/2/128 up=opus/1/32 red=1 frame=1 pc=<hex>"
+
+use budbridge_core::protocol::{StreamFormat, MAX_REDUNDANCY};
+
+const HELLO_PREFIX: &str = "BBHELLO1 ";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hello {
+    pub down: StreamFormat,
+    pub up: StreamFormat,
+    /// Earlier frames repeated in each Opus packet
+    pub redundancy: u8,
+    /// Audio datagrams carry a sequence number and timestamp header
+    pub framed: bool,
+    /// The PC's install id, echoed so it can tell its acks from another PC's
+    pub pc: Option<String>,
+}
+
+impl Hello {
+    /// None for anything but a hello, acks included
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let text = std::str::from_utf8(data).ok()?.strip_prefix(HELLO_PREFIX)?;
+        if text.starts_with("ACK") {
+            return None;
+        }
+        let (mut down, mut up, mut redundancy, mut framed, mut pc) = (None, None, 0, false, None);
+        for field in text.split_whitespace() {
+            match field.split_once('=') {
+                Some(("down", value)) => down = StreamFormat::parse(value),
+                Some(("up", value)) => up = StreamFormat::parse(value),
+                Some(("red", value)) => redundancy = value.parse().unwrap_or(0).min(MAX_REDUNDANCY),
+                Some(("frame", value)) => framed = value == "1",
+                Some(("pc", value)) => pc = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        Some(Self { down: down?, up: up?, redundancy, framed, pc })
+    }
+
+    pub fn ack(&self) -> String {
+        let mut text = format!("{}ACK down={} up={}", HELLO_PREFIX, self.down, self.up);
+        if self.redundancy > 0 {
+            text.push_str(&format!(" red={}", self.redundancy));
+        }
+        if self.framed {
+            text.push_str(" frame=1");
+        }
+        if let Some(pc) = &self.pc {
+            text.push_str(&format!(" pc={}", pc));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use budbridge_core::protocol::Codec;
+    use crate::tests::vector;
+
+    fn format(text: &str) -> StreamFormat {
+        StreamFormat::parse(text).unwrap()
+    }
+
+    #[test]
+    fn answers_the_vector_hellos() {
+        let hello = Hello::parse(&vector("hello.opus")).unwrap();
+        assert_eq!(hello.down, StreamFormat { codec: Codec::Opus, channels: 2, bitrate_kbps: 128 });
+        assert_eq!(hello.up.to_string(), "opus/1/32");
+        assert_eq!((hello.redundancy, hello.framed), (0, true));
+
+        let redundant = Hello::parse(&vector("hello.redundant")).unwrap();
+        assert_eq!((redundant.down.codec, redundant.redundancy), (Codec::Pcm24, 2));
+
+        let named = Hello::parse(&vector("hello.pc")).unwrap();
+        assert_eq!(Hello { framed: false, ..named }.ack().as_bytes(), vector("ack.pc"));
+        let float = Hello { down: format("f32/1/1536"), up: format("opus/1/32"), framed: false, ..redundant };
+        assert_eq!(float.ack().as_bytes(), vector("ack.redundant"));
+        let framed = Hello { down: format("opus/2/128"), up: format("opus/1/32"), redundancy: 1, ..hello };
+        assert_eq!(framed.ack().as_bytes(), vector("ack.framed"));
+
+        // The phone's ack to it isn't a hello
+        assert_eq!(Hello::parse(&vector("ack.pc")), None);
+        assert_eq!(Hello::parse(&vector("cast.opus")), None);
+    }
+}
@@ -0,0 +1,124 @@
+// Evens out packet arrival for playback. Decoded audio queues here and the
+// audio callback pulls fixed blocks. Playback waits until the target depth is
+// queued, plays silence whenever the queue runs dry and waits again, and drops
+// the oldest audio when the queue grows past the maximum, so latency stays
+// bounded after a burst. The iPhone app does the same.
+
+use std::collections::VecDeque;
+
+/// Interleaved stereo at 48 kHz
+const SAMPLES_PER_MS: usize = 96;
+
+pub struct JitterBuffer {
+    queue: VecDeque<f32>,
+    target: usize,
+    max: usize,
+    playing: bool,
+    underruns: u64,
+    dropped: u64,
+}
+
+impl JitterBuffer {
+    /// Playback starts once `target_ms` is queued; past `max_ms` the oldest audio goes
+    pub fn new(target_ms: usize, max_ms: usize) -> Self {
+        let target = target_ms * SAMPLES_PER_MS;
+        Self {
+            queue: VecDeque::new(),
+            target,
+            max: (max_ms * SAMPLES_PER_MS).max(target),
+            playing: false,
+            underruns: 0,
+            dropped: 0,
+        }
+    }
+
+    pub fn push(&mut self, stereo: &[f32]) {
+        self.queue.extend(stereo);
+        let excess = self.queue.len().saturating_sub(self.max);
+        if excess > 0 {
+            self.queue.drain(..excess);
+            self.dropped += excess as u64 / 2;
+        }
+    }
+
+    /// Fills `out` with the next audio, or silence while priming
+    pub fn pull(&mut self, out: &mut [f32]) {
+        if !self.playing && self.queue.len() >= self.target.max(out.len()) {
+            self.playing = true;
+        }
+        if !self.playing {
+            out.fill(0.0);
+            return;
+        }
+        let available = self.queue.len().min(out.len());
+        for (slot, sample) in out.iter_mut().zip(self.queue.drain(..available)) {
+            *slot = sample;
+        }
+        if available < out.len() {
+            out[available..].fill(0.0);
+            self.playing = false;
+            self.underruns += 1;
+        }
+    }
+
+    pub fn queued_ms(&self) -> usize {
+        self.queue.len() / SAMPLES_PER_MS
+    }
+
+    /// Times playback ran dry and had to wait for the target depth again
+    pub fn underruns(&self) -> u64 {
+        self.underruns
+    }
+
+    /// Stereo frames thrown away to keep latency down
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped
+    }
+
+    pub fn clear(&mut self) {
+        self.queue.clear();
+        self.playing = false;
+    }
+}
+
+impl Default for JitterBuffer {
+    /// 40 ms before playing, at most 200 ms behind
+    fn default() -> Self {
+        Self::new(40, 200)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn primes_then_plays_and_recovers_from_underruns() {
+        let mut buffer = JitterBuffer::new(1, 2);
+        let mut out = [1.0f32; 48];
+
+        // Half a millisecond isn't enough to start
+        buffer.push(&[0.5; 48]);
+        buffer.pull(&mut out);
+        assert_eq!(out, [0.0; 48]);
+
+        buffer.push(&[0.5; 48]);
+        buffer.pull(&mut out);
+        assert_eq!(out, [0.5; 48]);
+        buffer.pull(&mut out);
+        assert_eq!(out, [0.5; 48]);
+
+        // Dry: silence, counted, and priming again
+        buffer.pull(&mut out);
+        assert_eq!((out, buffer.underruns()), ([0.0; 48], 1));
+        buffer.push(&[0.25; 48]);
+        buffer.pull(&mut out);
+        assert_eq!(out, [0.0; 48]);
+
+        // A burst is cut back to the maximum, oldest first
+        buffer.push(&[0.75; 240]);
+        assert_eq!((buffer.queued_ms(), buffer.dropped_frames()), (2, 48));
+        buffer.pull(&mut out);
+        assert_eq!(out, [0.75; 48]);
+    }
+}
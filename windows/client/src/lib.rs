@@ -0,0 +1,47 @@
+// The receiving end of a BudBridge stream, for building your own receivers (a
+// Raspberry Pi by the stereo, say) in Rust. It does what the iPhone app does
+// with PC → phone audio: answers the PC's codec hello, decodes PCM or Opus
+// (with redundant frames), and smooths arrival times with a jitter buffer. It
+// sends no mic audio back, so the PC hears silence from it.
+//
+// The wire format is budbridge-core's protocol.rs, used here without its capture
+// feature, so formats, framing and RED are the PC's own code. The tests here
+// check against the same docs/protocol-vectors.txt as the PC and the phone.
+//
+//   let mut receiver = Receiver::bind(DEFAULT_PORT)?;
+//   let buffer = receiver.buffer();  // pull from this in the audio callback
+//   loop { receiver.receive()?; }
+//
+// examples/receiver.rs is the whole thing, playing through cpal.
+
+mod decode;
+mod handshake;
+mod jitter;
+mod receiver;
+
+pub use decode::Decoder;
+pub use handshake::Hello;
+pub use jitter::JitterBuffer;
+pub use receiver::{Event, Receiver};
+pub use budbridge_core::protocol::{unframe, Codec, FrameHeader, StreamFormat};
+
+/// Where the PC sends audio and hellos
+pub const DEFAULT_PORT: u16 = 4811;
+/// Where the PC takes acks (and mic audio) from the phone
+pub const PC_PORT: u16 = 4810;
+/// Everything decodes to 48 kHz interleaved stereo float
+pub const SAMPLE_RATE: u32 = 48_000;
+
+#[cfg(test)]
+mod tests {
+    const VECTORS: &str = include_str!("../../../docs/protocol-vectors.txt");
+
+    pub fn vector(name: &str) -> Vec<u8> {
+        let hex = VECTORS
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+            .unwrap_or_else(|| panic!("no vector named {}", name));
+        (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+    }
+}
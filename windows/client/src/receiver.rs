@@ -0,0 +1,146 @@
+// One UDP session with a PC. Everything arrives on one port: hellos, which are
+// acked to the PC's receive port and switch the decoder; link test and clock
+// probes, echoed straight back; and audio, decoded into the jitter buffer.
+// Before any hello, audio is taken as 16-bit PCM mono from anyone, like old
+// PCs send. After one, only from that PC, unframed if the hello offered framing.
+
+use crate::decode::Decoder;
+use crate::handshake::Hello;
+use crate::jitter::JitterBuffer;
+use crate::PC_PORT;
+use anyhow::Result;
+use budbridge_core::protocol::{self, Probe, StreamFormat, CLOCK_PROBE_SIZE, CLOCK_STAGE, PROBE_HEADER_SIZE};
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Pairing and multicast announcements: never audio, and nothing a plain receiver
+/// answers. Test markers, NAT punches and rendezvous frames are protocol::is_control.
+const IGNORED: [&[u8]; 2] = [b"BBPAIR1 ", b"BBCAST1 "];
+/// How long receive() waits for a datagram
+const POLL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    /// A PC offered a session and was acked; audio from it follows in `Hello::down`
+    Hello(Hello),
+    /// Stereo frames added to the jitter buffer
+    Audio(usize),
+    /// A probe was echoed or a control frame ignored
+    Control,
+}
+
+pub struct Receiver {
+    socket: UdpSocket,
+    pc_port: u16,
+    pc: Option<IpAddr>,
+    decoder: Decoder,
+    framed: bool,
+    // The sequence number expected next, once framed audio arrives
+    next_seq: Option<u32>,
+    lost: u64,
+    buffer: Arc<Mutex<JitterBuffer>>,
+    started: Instant,
+    recv_buf: Vec<u8>,
+}
+
+impl Receiver {
+    /// Listens on `port` (DEFAULT_PORT is where the PC sends); 0 picks a free one
+    pub fn bind(port: u16) -> Result<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0", port))?;
+        socket.set_read_timeout(Some(POLL))?;
+        Ok(Self {
+            socket,
+            pc_port: PC_PORT,
+            pc: None,
+            decoder: Decoder::new(StreamFormat::PCM_MONO, 0)?,
+            framed: false,
+            next_seq: None,
+            lost: 0,
+            buffer: Arc::new(Mutex::new(JitterBuffer::default())),
+            started: Instant::now(),
+            recv_buf: vec![0; 65536],
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Where acks go, if the PC doesn't use the usual receive port
+    pub fn set_pc_port(&mut self, port: u16) {
+        self.pc_port = port;
+    }
+
+    /// The jitter buffer to pull playback from, shared with the audio callback
+    pub fn buffer(&self) -> Arc<Mutex<JitterBuffer>> {
+        self.buffer.clone()
+    }
+
+    pub fn format(&self) -> StreamFormat {
+        self.decoder.format()
+    }
+
+    /// Frames restored from redundant copies after their own packet was lost
+    pub fn recovered_frames(&self) -> u64 {
+        self.decoder.recovered_frames()
+    }
+
+    /// Framed datagrams skipped over by the sequence numbers; late ones still count
+    pub fn lost_packets(&self) -> u64 {
+        self.lost
+    }
+
+    /// Handles one datagram, waiting up to 100 ms for it; None when nothing came
+    /// or it came from another PC
+    pub fn receive(&mut self) -> Result<Option<Event>> {
+        let (len, src) = match self.socket.recv_from(&mut self.recv_buf) {
+            Ok(received) => received,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let received_us = self.started.elapsed().as_micros() as u64;
+        let data = &mut self.recv_buf[..len];
+
+        if let Some(hello) = Hello::parse(data) {
+            self.decoder = Decoder::new(hello.down, hello.redundancy)?;
+            self.framed = hello.framed;
+            self.next_seq = None;
+            self.buffer.lock().unwrap().clear();
+            self.pc = Some(src.ip());
+            self.socket.send_to(hello.ack().as_bytes(), (src.ip(), self.pc_port))?;
+            return Ok(Some(Event::Hello(hello)));
+        }
+        if let Some(probe) = Probe::parse(data) {
+            if probe.stage == CLOCK_STAGE && data.len() >= CLOCK_PROBE_SIZE {
+                let replied_us = self.started.elapsed().as_micros() as u64;
+                data[PROBE_HEADER_SIZE..PROBE_HEADER_SIZE + 8].copy_from_slice(&received_us.to_le_bytes());
+                data[PROBE_HEADER_SIZE + 8..CLOCK_PROBE_SIZE].copy_from_slice(&replied_us.to_le_bytes());
+            }
+            self.socket.send_to(data, src)?;
+            return Ok(Some(Event::Control));
+        }
+        if protocol::is_control(data) || IGNORED.iter().any(|prefix| data.starts_with(prefix)) {
+            return Ok(Some(Event::Control));
+        }
+        if self.pc.is_some_and(|pc| pc != src.ip()) {
+            return Ok(None);
+        }
+
+        let payload = match protocol::unframe(data).filter(|_| self.framed) {
+            Some((header, payload)) => {
+                let ahead = self.next_seq.map_or(0, |next| header.seq.wrapping_sub(next));
+                if ahead < u32::MAX / 2 {
+                    self.lost += ahead as u64;
+                    self.next_seq = Some(header.seq.wrapping_add(1));
+                }
+                payload
+            }
+            None => &data[..],
+        };
+        let samples = self.decoder.decode(payload);
+        self.buffer.lock().unwrap().push(&samples);
+        Ok(Some(Event::Audio(samples.len() / 2)))
+    }
+}
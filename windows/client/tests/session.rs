@@ -0,0 +1,65 @@
+// A whole session over loopback, with the test playing the PC: hello and ack,
+// framed Opus audio into the jitter buffer, and a clock probe echoed with stamps.
+
+use audiopus::coder::Encoder;
+use audiopus::{Application, Channels, SampleRate};
+use budbridge_client::{Event, Receiver, StreamFormat};
+use std::net::UdpSocket;
+use std::time::Duration;
+
+#[test]
+fn plays_what_a_pc_sends() {
+    let mut receiver = Receiver::bind(0).unwrap();
+    let to_receiver = ("127.0.0.1", receiver.local_addr().unwrap().port());
+    // The PC's receive port, where acks go, and its sending socket
+    let pc_receive = UdpSocket::bind("127.0.0.1:0").unwrap();
+    pc_receive.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    receiver.set_pc_port(pc_receive.local_addr().unwrap().port());
+    let pc_send = UdpSocket::bind("127.0.0.1:0").unwrap();
+    pc_send.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+
+    let pc = "101112131415161718191a1b1c1d1e1f";
+    let hello = format!("BBHELLO1 down=opus/2/128 up=opus/1/32 uprate=any frame=1 nonce=000102030405060708090a0b0c0d0e0f pc={}", pc);
+    pc_send.send_to(hello.as_bytes(), to_receiver).unwrap();
+    assert!(matches!(receiver.receive().unwrap(), Some(Event::Hello(_))));
+    assert_eq!(receiver.format(), StreamFormat::parse("opus/2/128").unwrap());
+    let mut ack = [0u8; 256];
+    let len = pc_receive.recv(&mut ack).unwrap();
+    assert_eq!(std::str::from_utf8(&ack[..len]).unwrap(), format!("BBHELLO1 ACK down=opus/2/128 up=opus/1/32 frame=1 pc={}", pc));
+
+    // 100 ms of a 440 Hz tone as five 20 ms Opus frames, the third lost on the way
+    let encoder = Encoder::new(SampleRate::Hz48000, Channels::Stereo, Application::Audio).unwrap();
+    let tone: Vec<f32> = (0..4800)
+        .flat_map(|i| {
+            let s = (i as f32 * 440.0 * std::f32::consts::TAU / 48000.0).sin() * 0.25;
+            [s, s]
+        })
+        .collect();
+    for (seq, frame) in tone.chunks(1920).enumerate().filter(|&(seq, _)| seq != 2) {
+        let mut opus = [0u8; 1275];
+        let len = encoder.encode_float(frame, &mut opus).unwrap();
+        let mut packet = vec![0xBB, 1];
+        packet.extend_from_slice(&(seq as u32).to_le_bytes());
+        packet.extend_from_slice(&(seq as u32 * 960).to_le_bytes());
+        packet.extend_from_slice(&(len as u16).to_le_bytes());
+        packet.extend_from_slice(&opus[..len]);
+        pc_send.send_to(&packet, to_receiver).unwrap();
+        assert_eq!(receiver.receive().unwrap(), Some(Event::Audio(960)));
+    }
+    assert_eq!(receiver.lost_packets(), 1);
+    let buffer = receiver.buffer();
+    let mut out = vec![0f32; 7680];
+    buffer.lock().unwrap().pull(&mut out);
+    assert!(out.iter().any(|s| s.abs() > 0.1));
+
+    // A clock probe comes back with the receiver's stamps filled in
+    let mut probe = [0u8; 37];
+    probe[..8].copy_from_slice(b"BBPROBE1");
+    probe[8] = 0xFF;
+    pc_send.send_to(&probe, to_receiver).unwrap();
+    assert_eq!(receiver.receive().unwrap(), Some(Event::Control));
+    let mut echo = [0u8; 64];
+    let len = pc_send.recv(&mut echo).unwrap();
+    assert_eq!(len, 37);
+    assert!(echo[21..37].iter().any(|&b| b != 0));
+}
@@ -0,0 +1,71 @@
+// Capture for programs embedding the core: an input device, from the system or
+// a test's MockBackend (backend.rs), resampled to 48 kHz and re-blocked into
+// 10 ms frames of interleaved stereo float (framer.rs), ready for Bridge::send.
+// The app's own capture callback adds its processing chain, mixing and meters on
+// top of the same steps.
+
+use crate::backend::{AudioBackend, CpalBackend, InputCallback};
+use crate::convert;
+use crate::framer::{self, Framer};
+use crate::protocol::NATIVE_RATE;
+use crate::resample::{Quality, Resampler};
+use anyhow::Result;
+use cpal::StreamConfig;
+use crossbeam_channel::{bounded, Receiver, Sender};
+
+/// Stops capturing when dropped
+pub struct AudioCapture<B: AudioBackend = CpalBackend> {
+    _stream: B::Stream,
+    frames: Receiver<Vec<f32>>,
+    device: String,
+    config: StreamConfig,
+}
+
+impl AudioCapture {
+    /// Captures the system's input device with this name, or the default one
+    pub fn start(device: Option<&str>) -> Result<Self> {
+        Self::start_with(&CpalBackend, device)
+    }
+}
+
+impl<B: AudioBackend> AudioCapture<B> {
+    /// Captures a device of `backend`
+    pub fn start_with(backend: &B, device: Option<&str>) -> Result<Self> {
+        let (tx, frames) = bounded(framer::QUEUE_FRAMES);
+        let opened = backend.open_input(device, &mut |config| processor(tx.clone(), config))?;
+        Ok(Self { _stream: opened.stream, frames, device: opened.device, config: opened.config })
+    }
+
+    /// 10 ms frames of 48 kHz interleaved stereo. New ones are dropped while
+    /// framer::QUEUE_FRAMES wait untaken.
+    pub fn frames(&self) -> &Receiver<Vec<f32>> {
+        &self.frames
+    }
+
+    pub fn device_name(&self) -> &str {
+        &self.device
+    }
+
+    /// What the device was opened with, before resampling
+    pub fn config(&self) -> &StreamConfig {
+        &self.config
+    }
+}
+
+/// The first two channels (or mono doubled), at 48 kHz, in fixed frames
+fn processor(tx: Sender<Vec<f32>>, config: &StreamConfig) -> InputCallback {
+    let channels = config.channels.max(1) as usize;
+    let rate = config.sample_rate.0;
+    let mut resampler = (rate != NATIVE_RATE).then(|| Resampler::new(2, rate, NATIVE_RATE, Quality::default()));
+    let mut framer = Framer::new(framer::FRAME_MS);
+    Box::new(move |data: &[f32]| {
+        let stereo = convert::to_stereo(data, channels);
+        let stereo = match &mut resampler {
+            Some(resampler) => resampler.process(&stereo),
+            None => stereo,
+        };
+        for frame in framer.push(&stereo) {
+            let _ = tx.try_send(frame);
+        }
+    })
+}
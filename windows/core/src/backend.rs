@@ -0,0 +1,247 @@
+// Where capture comes from: the devices to pick from, and a stream calling back
+// with interleaved float audio. CpalBackend is the system's audio through cpal,
+// opened with capture.rs's fallback ladder. MockBackend has made-up devices that
+// only produce audio when a test asks, the same samples every run, so the path
+// from capture to the wire can be tested on a machine without sound hardware.
+//
+// AudioCapture runs on either. The app's capture callback still talks to cpal
+// itself, for loopback and its per-process capture.
+
+use crate::capture;
+use anyhow::{anyhow, Result};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{BufferSize, SampleRate, StreamConfig};
+use std::sync::{Arc, Mutex};
+
+/// Takes one buffer of interleaved samples from the device's thread
+pub type InputCallback = Box<dyn FnMut(&[f32]) + Send + 'static>;
+
+/// A running stream, and what it ended up on
+pub struct Opened<S> {
+    pub stream: S,
+    pub device: String,
+    pub config: StreamConfig,
+}
+
+pub trait AudioBackend {
+    /// Keeps the audio coming until dropped
+    type Stream;
+
+    fn input_devices(&self) -> Result<Vec<String>>;
+
+    fn default_input_device(&self) -> Option<String>;
+
+    /// Starts capturing `device` (None for the default). `callback` is asked for a
+    /// callback for each config tried, since a device may refuse the first.
+    fn open_input(
+        &self,
+        device: Option<&str>,
+        callback: &mut dyn FnMut(&StreamConfig) -> InputCallback,
+    ) -> Result<Opened<Self::Stream>>;
+}
+
+/// The system's audio devices
+#[derive(Default)]
+pub struct CpalBackend;
+
+impl AudioBackend for CpalBackend {
+    type Stream = cpal::Stream;
+
+    fn input_devices(&self) -> Result<Vec<String>> {
+        Ok(cpal::default_host().input_devices()?.map(|d| d.name().unwrap_or_else(|_| "Unknown".to_string())).collect())
+    }
+
+    fn default_input_device(&self) -> Option<String> {
+        cpal::default_host().default_input_device().and_then(|d| d.name().ok())
+    }
+
+    fn open_input(
+        &self,
+        device: Option<&str>,
+        callback: &mut dyn FnMut(&StreamConfig) -> InputCallback,
+    ) -> Result<Opened<cpal::Stream>> {
+        let host = cpal::default_host();
+        let device = match device {
+            Some(name) => host
+                .input_devices()?
+                .find(|d| d.name().is_ok_and(|n| n == name))
+                .ok_or_else(|| anyhow!("No input device named {}", name))?,
+            None => host.default_input_device().ok_or_else(|| anyhow!("No default input device"))?,
+        };
+        let preferred: StreamConfig = device.default_input_config()?.into();
+        let (device, config, stream) = capture::open(
+            &host,
+            device,
+            preferred,
+            false,
+            |device, config| {
+                let mut process = callback(config);
+                // A device that goes away just stops the audio
+                let on_error = |_: cpal::StreamError| {};
+                Ok(device.build_input_stream(config, move |data: &[f32], _: &cpal::InputCallbackInfo| process(data), on_error, None)?)
+            },
+            |_| {},
+        )?;
+        stream.play()?;
+        let device = device.name().unwrap_or_else(|_| "Unknown".to_string());
+        Ok(Opened { stream, device, config })
+    }
+}
+
+/// Made-up input devices for tests. Clones share the devices and open streams,
+/// so a test keeps one to drive audio through what it handed the other to.
+#[derive(Clone, Default)]
+pub struct MockBackend {
+    inner: Arc<Mutex<MockState>>,
+}
+
+#[derive(Default)]
+struct MockState {
+    devices: Vec<(String, StreamConfig)>,
+    streams: Vec<MockInput>,
+    next_id: u64,
+}
+
+struct MockInput {
+    id: u64,
+    channels: usize,
+    sample_rate: u32,
+    position: u64, // frames delivered so far
+    callback: InputCallback,
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a device; the first one added is the default
+    pub fn with_device(self, name: &str, channels: u16, sample_rate: u32) -> Self {
+        let config = StreamConfig { channels, sample_rate: SampleRate(sample_rate), buffer_size: BufferSize::Default };
+        self.inner.lock().unwrap().devices.push((name.to_string(), config));
+        self
+    }
+
+    /// The sample every mock device produces at `frame` on `channel`: a 440 Hz
+    /// tone at -6 dBFS on the first channel, the same at half that on the
+    /// others, so a test can tell channels apart
+    pub fn sample(frame: u64, channel: usize, sample_rate: u32) -> f32 {
+        let tone = (frame as f64 * 440.0 * std::f64::consts::TAU / sample_rate as f64).sin() as f32 * 0.5;
+        if channel == 0 {
+            tone
+        } else {
+            tone / 2.0
+        }
+    }
+
+    /// Calls every open stream back with its next `frames` frames, in buffers of
+    /// up to 10 ms as a driver would, on the calling thread
+    pub fn deliver(&self, frames: usize) {
+        let mut state = self.inner.lock().unwrap();
+        for input in &mut state.streams {
+            let chunk = (input.sample_rate / 100).max(1) as usize;
+            let mut left = frames;
+            while left > 0 {
+                let n = left.min(chunk);
+                let buffer: Vec<f32> = (input.position..input.position + n as u64)
+                    .flat_map(|frame| (0..input.channels).map(move |channel| (frame, channel)))
+                    .map(|(frame, channel)| Self::sample(frame, channel, input.sample_rate))
+                    .collect();
+                (input.callback)(&buffer);
+                input.position += n as u64;
+                left -= n;
+            }
+        }
+    }
+
+    /// Streams opened and not yet dropped
+    pub fn open_streams(&self) -> usize {
+        self.inner.lock().unwrap().streams.len()
+    }
+}
+
+/// Stops the mock device's callbacks when dropped
+pub struct MockStream {
+    id: u64,
+    inner: Arc<Mutex<MockState>>,
+}
+
+impl Drop for MockStream {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.inner.lock() {
+            state.streams.retain(|input| input.id != self.id);
+        }
+    }
+}
+
+impl AudioBackend for MockBackend {
+    type Stream = MockStream;
+
+    fn input_devices(&self) -> Result<Vec<String>> {
+        Ok(self.inner.lock().unwrap().devices.iter().map(|(name, _)| name.clone()).collect())
+    }
+
+    fn default_input_device(&self) -> Option<String> {
+        self.inner.lock().unwrap().devices.first().map(|(name, _)| name.clone())
+    }
+
+    fn open_input(
+        &self,
+        device: Option<&str>,
+        callback: &mut dyn FnMut(&StreamConfig) -> InputCallback,
+    ) -> Result<Opened<MockStream>> {
+        let mut state = self.inner.lock().unwrap();
+        let (name, config) = match device {
+            Some(name) => state.devices.iter().find(|(n, _)| n == name).ok_or_else(|| anyhow!("No input device named {}", name))?,
+            None => state.devices.first().ok_or_else(|| anyhow!("No default input device"))?,
+        }
+        .clone();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.streams.push(MockInput {
+            id,
+            channels: config.channels as usize,
+            sample_rate: config.sample_rate.0,
+            position: 0,
+            callback: callback(&config),
+        });
+        Ok(Opened { stream: MockStream { id, inner: self.inner.clone() }, device: name, config })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mock_devices_deliver_the_same_audio_every_time() {
+        let backend = MockBackend::new().with_device("Mic", 2, 48_000).with_device("Line in", 1, 44_100);
+        assert_eq!(backend.input_devices().unwrap(), ["Mic", "Line in"]);
+        assert_eq!(backend.default_input_device().as_deref(), Some("Mic"));
+        assert!(backend.open_input(Some("Webcam"), &mut |_| Box::new(|_| {})).is_err());
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let opened = backend
+            .open_input(None, &mut |config| {
+                assert_eq!(config.channels, 2);
+                let sink = sink.clone();
+                Box::new(move |data| sink.lock().unwrap().push(data.to_vec()))
+            })
+            .unwrap();
+        assert_eq!(opened.device, "Mic");
+
+        // 25 ms comes as two full 10 ms buffers and the rest
+        backend.deliver(1200);
+        let buffers = received.lock().unwrap().clone();
+        assert_eq!(buffers.iter().map(Vec::len).collect::<Vec<_>>(), [960, 960, 480]);
+        let samples: Vec<f32> = buffers.concat();
+        assert_eq!(samples[2 * 100], MockBackend::sample(100, 0, 48_000));
+        assert_eq!(samples[2 * 100 + 1], samples[2 * 100] / 2.0);
+
+        drop(opened.stream);
+        assert_eq!(backend.open_streams(), 0);
+        backend.deliver(480);
+        assert_eq!(received.lock().unwrap().len(), 3);
+    }
+}
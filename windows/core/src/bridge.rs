@@ -0,0 +1,544 @@
+// A session with one phone over a Transport (UdpTransport, or a FramedStream
+// such as the app's Bluetooth link): the hello until the phone acks it, the
+// identity check on its answer, the keys for audio protection, and the agreed
+// codecs both ways. Captured audio goes in through send; the phone's mic
+// comes out of poll as events. Nothing here blocks or spawns threads, so the
+// caller's loop decides how often to poll.
+//
+// Audio is only taken from the phone: where the hello goes, or wherever a known
+// identity key answers it from. Anyone else is dropped unparsed, and reported
+// once the phone has gone quiet, since it may be the phone at a new address. A
+// phone that answers another PC's hello is "crossed": it's listening there while
+// its mic comes here, so its audio is refused until it answers ours.
+//
+// The app's network loop runs on this too. What it adds goes through Hooks (rate
+// limiting, the access log, clock probe echoes) and the methods for a standby
+// target, a relay (move_to) and the route (rebind).
+
+use crate::codec::{Decoder, Encoder};
+use crate::fec;
+use crate::identity::{self, Verdict};
+use crate::protocol::{self, FrameWriter, Nonce, PcId, PublicKey, Session, StreamFormat};
+use crate::seal::{self, ExchangeKey, KeyExchange, Opener, Sealer};
+use crate::standby::{self, Standby};
+use crate::transport::Transport;
+use anyhow::{anyhow, Result};
+use std::collections::VecDeque;
+use std::mem;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+/// Hellos are repeated this often until the phone answers
+pub const HELLO_INTERVAL: Duration = Duration::from_secs(1);
+/// Unanswered hellos before settling for PCM mono
+pub const HELLO_ATTEMPTS: u32 = 5;
+/// Once the phone has answered, repeat the hello so a restarted app gets back in step
+pub const HELLO_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+/// Bytes of audio per datagram, which fits a Wi-Fi MTU with room for headers
+const DEFAULT_MAX_PAYLOAD: usize = 1400;
+/// Most datagrams read per poll before sending gets a turn
+const RECV_BATCH: usize = 64;
+/// Only this long after the phone went quiet may another sender be the phone moved
+const PEER_MOVE_SILENCE: Duration = Duration::from_secs(3);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// Which phone answered, against the key given to set_known_key
+    Identity(Verdict),
+    /// The phone acked the hello; audio both ways now uses what it agreed to
+    Agreed(Session),
+    /// Protection was agreed: whether its keys are in place. Without them (the
+    /// exchange key wasn't signed by the phone's identity) audio is refused.
+    Sealed(bool),
+    /// A datagram of the phone's audio got in: its size, and its sequence
+    /// number when framed
+    Arrived { src: SocketAddr, len: usize, seq: Option<u32> },
+    /// 48 kHz mono from the phone's mic
+    Audio(Vec<i16>),
+    /// No answer to any hello: audio stays PCM mono, unframed and in the clear
+    NoAnswer,
+    /// The phone started (true) or stopped answering another PC's hellos
+    Crossed(bool),
+    /// Where the phone's audio comes from, or an address that may be it after a move
+    PeerAt(IpAddr),
+    /// A control datagram from the phone, e.g. a played test sound
+    Control(Vec<u8>),
+    /// The standby failed its identity check and is left out
+    StandbyRefused(String),
+    /// Reading the link failed; the session goes on
+    ReceiveFailed(String),
+}
+
+/// What a caller adds to the session. Every method defaults to doing nothing.
+pub trait Hooks {
+    /// Whether to look at a datagram from `src` at all, e.g. under a rate limit
+    fn admit(&mut self, _src: SocketAddr) -> bool {
+        true
+    }
+    /// A datagram from the phone the caller handles itself, e.g. a clock probe echo
+    fn intercept(&mut self, _packet: &[u8]) -> bool {
+        false
+    }
+    /// Every datagram read, and whether it was let in
+    fn received(&mut self, _src: SocketAddr, _len: usize, _accepted: bool) {}
+    /// Every datagram of audio sent, parity included
+    fn sent(&mut self, _len: usize) {}
+}
+
+pub struct NoHooks;
+
+impl Hooks for NoHooks {}
+
+pub struct Bridge {
+    link: Box<dyn Transport>,
+    session: Session,
+    pc: PcId,
+    phone: SocketAddr,
+    peer_ip: IpAddr,
+    known_key: Option<PublicKey>,
+    nonce: Nonce,
+    exchange: KeyExchange,
+    hello: String,
+    hellos_left: u32,
+    last_hello: Option<Instant>,
+    agreed: Option<Session>,
+    identity: Option<Verdict>,
+    crossed: bool,
+    last_accepted: Instant,
+    last_src: Option<IpAddr>,
+    standby: Option<Standby>,
+    keepalive: Option<Duration>,
+    last_sent: Instant,
+    encoder: Encoder,
+    decoder: Decoder,
+    frames: FrameWriter,
+    // Parity both ways, once the phone agrees to FEC
+    fec: Option<(fec::Encoder, fec::Decoder)>,
+    seal_ends: Option<(Sealer, Opener)>,
+    phone_kx: Option<ExchangeKey>,
+    seal_stats: seal::Stats,
+    max_payload: usize,
+    events: VecDeque<Event>,
+    // Set while events read in one poll are still being handed out
+    draining: bool,
+    recv_buf: Vec<u8>,
+}
+
+impl Bridge {
+    /// Offers `session` to the phone behind `link`, naming this PC `pc`. Both
+    /// directions are PCM mono until the phone answers.
+    pub fn new(link: impl Transport + 'static, session: Session, pc: PcId) -> Result<Self> {
+        // A v4 UUID is 122 random bits, plenty for a nonce
+        let nonce = *uuid::Uuid::new_v4().as_bytes();
+        let exchange = KeyExchange::generate();
+        let hello = protocol::hello(session, Some(&nonce), Some(&pc), Some(exchange.public()));
+        let phone = link.phone();
+        let now = Instant::now();
+        Ok(Self {
+            link: Box::new(link),
+            session,
+            pc,
+            phone,
+            peer_ip: phone.ip(),
+            known_key: None,
+            nonce,
+            exchange,
+            hello,
+            hellos_left: HELLO_ATTEMPTS,
+            last_hello: None,
+            agreed: None,
+            identity: None,
+            crossed: false,
+            last_accepted: now,
+            last_src: None,
+            standby: None,
+            keepalive: None,
+            last_sent: now,
+            encoder: Encoder::new(StreamFormat::PCM_MONO, 0)?,
+            decoder: Decoder::new(StreamFormat::PCM_MONO, 0, protocol::NATIVE_RATE)?,
+            frames: FrameWriter::new(now),
+            fec: None,
+            seal_ends: None,
+            phone_kx: None,
+            seal_stats: seal::Stats::new(session.seal),
+            max_payload: DEFAULT_MAX_PAYLOAD,
+            events: VecDeque::new(),
+            draining: false,
+            recv_buf: vec![0; 65536],
+        })
+    }
+
+    /// The identity key this phone answered with before. A different key, or
+    /// none, fails the next poll instead of being trusted.
+    pub fn set_known_key(&mut self, key: Option<PublicKey>) {
+        self.known_key = key;
+    }
+
+    /// Smaller datagrams for paths with a lower MTU, e.g. 1200 through a VPN
+    pub fn set_max_payload(&mut self, bytes: usize) {
+        self.max_payload = bytes;
+    }
+
+    /// Something goes to the phone at least this often, to keep NAT mappings
+    /// open through silence. The phone ignores punch packets.
+    pub fn set_keepalive(&mut self, interval: Option<Duration>) {
+        self.keepalive = interval;
+    }
+
+    /// A second device to keep warm alongside the phone (see standby.rs)
+    pub fn set_standby(&mut self, addr: SocketAddr, key: Option<PublicKey>) {
+        self.standby = Some(Standby::new(addr, key, self.session, &self.pc));
+    }
+
+    /// Where audio goes
+    pub fn phone(&self) -> SocketAddr {
+        self.phone
+    }
+
+    /// What the phone agreed to, once it has answered
+    pub fn agreed(&self) -> Option<Session> {
+        self.agreed
+    }
+
+    /// The phone's identity verdict, also after poll failed on it
+    pub fn identity(&self) -> Option<Verdict> {
+        self.identity
+    }
+
+    pub fn standby_status(&self) -> Option<standby::Status> {
+        self.standby.as_ref().map(|s| s.status(Instant::now()))
+    }
+
+    pub fn seal_stats(&self) -> &seal::Stats {
+        &self.seal_stats
+    }
+
+    /// Datagrams rebuilt from parity this session
+    pub fn fec_recovered(&self) -> u64 {
+        self.fec.as_ref().map_or(0, |(_, decoder)| decoder.recovered())
+    }
+
+    /// Frames rebuilt from redundant copies this session
+    pub fn red_recovered(&self) -> u64 {
+        self.decoder.recovered_frames()
+    }
+
+    pub fn link(&mut self) -> &mut dyn Transport {
+        &mut *self.link
+    }
+
+    fn sealing(&self) -> seal::Mode {
+        self.agreed.map_or(seal::Mode::Off, |a| a.seal)
+    }
+
+    fn set_coders(&mut self, agreed: Session) -> Result<()> {
+        self.encoder = Encoder::new(agreed.formats.down, agreed.redundancy)?;
+        self.encoder.set_ptime(agreed.down_ptime());
+        self.decoder = Decoder::new(agreed.formats.up, agreed.redundancy, agreed.up_sample_rate())?;
+        self.fec = agreed.fec_group().map(|group| (fec::Encoder::new(group), fec::Decoder::new(group)));
+        Ok(())
+    }
+
+    /// Sends a datagram outside the audio stream, e.g. a clock probe
+    pub fn send_control(&mut self, packet: &[u8]) -> Result<()> {
+        self.link.send_to(packet, self.phone)?;
+        Ok(())
+    }
+
+    /// Sends audio to `addr` from now on, e.g. a relay, and starts the hellos over
+    pub fn move_to(&mut self, addr: SocketAddr) {
+        (self.phone, self.peer_ip) = (addr, addr.ip());
+        self.hellos_left = HELLO_ATTEMPTS;
+        self.last_hello = None;
+    }
+
+    /// Swaps the phone and a ready standby, keeping the one left warm. False
+    /// when there's no standby or it isn't ready.
+    pub fn switch_to_standby(&mut self) -> Result<bool> {
+        let now = Instant::now();
+        let next = match self.standby.take() {
+            Some(next) if next.status(now) == standby::Status::Ready => next,
+            other => {
+                self.standby = other;
+                return Ok(false);
+            }
+        };
+        let agreed = next.agreed.unwrap_or(self.session);
+        self.set_coders(agreed)?;
+        // Its keys come from the ack to the hello sent right away
+        (self.seal_ends, self.phone_kx) = (None, None);
+        let previous = Standby::warm(
+            self.phone,
+            self.known_key,
+            self.nonce,
+            mem::replace(&mut self.exchange, next.exchange),
+            mem::replace(&mut self.hello, next.hello),
+            self.agreed.replace(agreed),
+            mem::replace(&mut self.identity, next.identity),
+            now,
+        );
+        (self.phone, self.peer_ip, self.known_key, self.nonce) = (next.addr, next.addr.ip(), next.key, next.nonce);
+        self.standby = Some(previous);
+        self.hellos_left = 0;
+        self.last_hello = (agreed.seal == seal::Mode::Off).then_some(now);
+        self.last_accepted = now;
+        self.crossed = false;
+        Ok(true)
+    }
+
+    /// Encodes captured audio (48 kHz interleaved stereo float) and sends what's
+    /// ready. Returns the datagrams sent, parity included.
+    pub fn send(&mut self, stereo: &[f32]) -> Result<usize> {
+        self.send_with(stereo, &mut NoHooks)
+    }
+
+    pub fn send_with(&mut self, stereo: &[f32], hooks: &mut dyn Hooks) -> Result<usize> {
+        let sealing = self.sealing();
+        let framed = self.agreed.is_some_and(|a| a.framed);
+        // Asked-for protection holds audio back until the phone answers, and a
+        // phone that agreed to it gets nothing unsealed
+        let holding = self.session.seal != seal::Mode::Off && self.hellos_left > 0 && self.agreed.is_none();
+        let payloads = self.encoder.encode(stereo);
+        let mut header_size = if framed { protocol::FRAME_HEADER_SIZE } else { 0 };
+        if sealing != seal::Mode::Off {
+            header_size += seal::OVERHEAD;
+        }
+        let chunk_size = self.encoder.chunk_size(self.max_payload - header_size);
+        let mut sent = 0;
+        for chunk in payloads.iter().flat_map(|p| p.chunks(chunk_size)) {
+            let packet = if framed { self.frames.wrap(chunk, Instant::now()) } else { chunk.to_vec() };
+            // Parity goes out right behind the datagram that completes its group
+            let parity = self.fec.as_mut().and_then(|(encoder, _)| encoder.push(&packet));
+            for packet in std::iter::once(packet).chain(parity) {
+                let packet = match &mut self.seal_ends {
+                    Some((sealer, _)) => {
+                        let started = Instant::now();
+                        let sealed = sealer.seal(&packet);
+                        self.seal_stats.record(started.elapsed());
+                        sealed
+                    }
+                    None if sealing == seal::Mode::Off && !holding => packet,
+                    None => continue,
+                };
+                hooks.sent(self.link.send_to(&packet, self.phone)?);
+                self.last_sent = Instant::now();
+                sent += 1;
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Sends what's due and reads what arrived, then hands out the events that
+    /// brought one at a time. None once they're all out.
+    pub fn poll(&mut self) -> Result<Option<Event>> {
+        self.poll_with(&mut NoHooks)
+    }
+
+    pub fn poll_with(&mut self, hooks: &mut dyn Hooks) -> Result<Option<Event>> {
+        if let Some(event) = self.events.pop_front() {
+            return Ok(Some(event));
+        }
+        if mem::take(&mut self.draining) {
+            return Ok(None);
+        }
+        self.send_due();
+        for _ in 0..RECV_BATCH {
+            let (len, src) = match self.link.try_recv(&mut self.recv_buf) {
+                Ok(Some(received)) => received,
+                Ok(None) => break,
+                Err(e) => {
+                    self.events.push_back(Event::ReceiveFailed(e.to_string()));
+                    break;
+                }
+            };
+            if !hooks.admit(src) {
+                hooks.received(src, len, false);
+                continue;
+            }
+            let mut packet = mem::take(&mut self.recv_buf);
+            let received = self.receive(&mut packet[..len], src, hooks);
+            self.recv_buf = packet;
+            received?;
+        }
+        self.draining = !self.events.is_empty();
+        Ok(self.events.pop_front())
+    }
+
+    /// Hellos to the phone and the standby, and the keepalive, when they're due
+    fn send_due(&mut self) {
+        let now = Instant::now();
+        let interval = if self.hellos_left > 0 { HELLO_INTERVAL } else { HELLO_REFRESH_INTERVAL };
+        let due = self.last_hello.is_none_or(|t| now.duration_since(t) >= interval);
+        // A phone listening elsewhere keeps getting hellos, so it can find this PC
+        if due && (self.hellos_left > 0 || self.crossed || self.agreed.is_some()) {
+            // A lost hello is sent again with the next
+            let _ = self.link.send_to(self.hello.as_bytes(), self.phone);
+            self.last_hello = Some(now);
+            if self.hellos_left == 1 && self.agreed.is_none() {
+                self.events.push_back(Event::NoAnswer);
+            }
+            self.hellos_left = self.hellos_left.saturating_sub(1);
+        }
+        if let Some(warm) = &mut self.standby {
+            let addr = warm.addr;
+            if let Some(hello) = warm.hello_due(now) {
+                let _ = self.link.send_to(hello.as_bytes(), addr);
+            }
+        }
+        if self.keepalive.is_some_and(|interval| now.duration_since(self.last_sent) >= interval) {
+            let _ = self.link.send_to(protocol::PUNCH_MAGIC, self.phone);
+            self.last_sent = now;
+        }
+    }
+
+    fn receive(&mut self, packet: &mut [u8], src: SocketAddr, hooks: &mut dyn Hooks) -> Result<()> {
+        let len = packet.len();
+        let from_phone = src.ip() == self.peer_ip;
+        // The standby answers hellos too; its own audio waits until it's switched to
+        if !from_phone && self.standby.as_ref().is_some_and(|s| s.addr.ip() == src.ip()) {
+            hooks.received(src, len, true);
+            let ours = protocol::pc_id(packet).is_none_or(|pc| pc == self.pc);
+            if let Some(standby) = self.standby.as_mut().filter(|_| protocol::is_hello(packet) && ours) {
+                if let Err(e) = standby.answer(packet, Instant::now()) {
+                    self.events.push_back(Event::StandbyRefused(format!("{} {}", src, e)));
+                }
+            }
+        } else if protocol::is_hello(packet) && from_phone && protocol::pc_id(packet).is_some_and(|pc| pc != self.pc) {
+            // The reply names this PC so the phone can follow
+            hooks.received(src, len, false);
+            let _ = self.link.reply_to(protocol::pair(&self.pc).as_bytes(), src);
+            if !self.crossed {
+                self.crossed = true;
+                self.events.push_back(Event::Crossed(true));
+            }
+        } else if protocol::is_hello(packet) {
+            self.answer(packet, src, hooks)?;
+        } else if !from_phone {
+            hooks.received(src, len, false);
+            if self.last_accepted.elapsed() >= PEER_MOVE_SILENCE && self.last_src != Some(src.ip()) {
+                self.last_src = Some(src.ip());
+                self.events.push_back(Event::PeerAt(src.ip()));
+            }
+        } else if hooks.intercept(packet) {
+            hooks.received(src, len, true);
+        } else if protocol::is_control(packet) {
+            hooks.received(src, len, true);
+            self.events.push_back(Event::Control(packet.to_vec()));
+        } else if self.crossed {
+            hooks.received(src, len, false);
+        } else {
+            self.receive_audio(packet, src, hooks);
+        }
+        Ok(())
+    }
+
+    /// The phone's ack: who signed it, what it agreed to, and its exchange key
+    fn answer(&mut self, packet: &[u8], src: SocketAddr, hooks: &mut dyn Hooks) -> Result<()> {
+        let verdict = self
+            .identity
+            .is_none()
+            .then(|| protocol::ack_identity(packet, &self.nonce).map(|offered| identity::check(self.known_key, offered)));
+        let accepted = match &verdict {
+            None => src.ip() == self.peer_ip,
+            // A key we know may answer from wherever the phone's route back is
+            Some(Ok(Verdict::Known)) => true,
+            // Anything else only counts from where the hello went
+            Some(_) if src.ip() != self.peer_ip => false,
+            Some(Err(e)) => {
+                hooks.received(src, packet.len(), false);
+                return Err(anyhow!("the phone's answer failed its identity check: {}", e));
+            }
+            Some(Ok(verdict)) => !verdict.is_alarm(),
+        };
+        hooks.received(src, packet.len(), accepted);
+        if let Some(Ok(verdict)) = verdict.filter(|_| src.ip() == self.peer_ip || accepted) {
+            self.identity = Some(verdict);
+            match verdict {
+                Verdict::Changed(_) => return Err(anyhow!("the phone answered with a different identity key than before")),
+                Verdict::Missing => return Err(anyhow!("the phone answered without the identity key it had before")),
+                _ => self.peer_ip = src.ip(),
+            }
+            self.events.push_back(Event::Identity(verdict));
+        }
+        if accepted && protocol::pc_id(packet).is_some() {
+            let _ = self.link.reply_to(protocol::pair(&self.pc).as_bytes(), src);
+            if mem::take(&mut self.crossed) {
+                self.events.push_back(Event::Crossed(false));
+            }
+        }
+
+        // The phone answers every hello; only a change needs new coders
+        let agreed = protocol::parse_ack(packet).filter(|_| accepted);
+        if let Some(agreed) = agreed.filter(|a| self.agreed != Some(*a)) {
+            self.set_coders(agreed)?;
+            if self.sealing() != agreed.seal {
+                (self.seal_ends, self.phone_kx) = (None, None);
+            }
+            self.agreed = Some(agreed);
+            self.events.push_back(Event::Agreed(agreed));
+        }
+
+        // The exchange key only counts in an ack signed by the identity accepted
+        // above. The phone keeps its key while our hellos carry ours, so a
+        // different one means its app restarted.
+        let sealing = self.sealing();
+        let offered = protocol::exchange_key(packet).filter(|_| accepted && sealing != seal::Mode::Off);
+        if let Some(offered) = offered.filter(|key| self.phone_kx != Some(*key)) {
+            let signer = protocol::ack_identity(packet, &self.nonce).ok().flatten();
+            let trusted = match self.identity {
+                Some(Verdict::Known) => self.known_key,
+                Some(Verdict::FirstUse(key)) => Some(key),
+                _ => None,
+            };
+            self.seal_ends = signer
+                .filter(|signer| Some(*signer) == trusted)
+                .and_then(|_| self.exchange.session(sealing, &offered, &self.nonce));
+            self.phone_kx = Some(offered);
+            self.events.push_back(Event::Sealed(self.seal_ends.is_some()));
+        }
+        Ok(())
+    }
+
+    /// Opens, unframes and decodes one datagram of the phone's mic, or what a
+    /// parity datagram rebuilt and let through
+    fn receive_audio(&mut self, packet: &mut [u8], src: SocketAddr, hooks: &mut dyn Hooks) {
+        let len = packet.len();
+        // Once protection is agreed, only what opens with the phone's key is heard
+        let datagram = if self.sealing() == seal::Mode::Off {
+            Some(&packet[..])
+        } else {
+            let started = Instant::now();
+            let opened = self.seal_ends.as_mut().and_then(|(_, opener)| opener.open(packet));
+            match opened {
+                Some(_) => self.seal_stats.record(started.elapsed()),
+                None => self.seal_stats.rejected += 1,
+            }
+            opened
+        };
+        let Some(datagram) = datagram else {
+            hooks.received(src, len, false);
+            return;
+        };
+        hooks.received(src, len, true);
+        self.last_accepted = Instant::now();
+        if self.last_src != Some(src.ip()) {
+            self.last_src = Some(src.ip());
+            self.events.push_back(Event::PeerAt(src.ip()));
+        }
+        let framed = self.agreed.is_some_and(|a| a.framed);
+        let seq = protocol::unframe(datagram).filter(|_| framed).map(|(header, _)| header.seq);
+        self.events.push_back(Event::Arrived { src, len, seq });
+        // With FEC, audio after a gap waits for the parity that may rebuild it
+        let datagrams = match &mut self.fec {
+            Some((_, decoder)) => decoder.receive(datagram),
+            None => vec![datagram.to_vec()],
+        };
+        for datagram in datagrams {
+            let payload = match protocol::unframe(&datagram).filter(|_| framed) {
+                Some((_, payload)) => payload,
+                None => &datagram[..],
+            };
+            self.events.push_back(Event::Audio(self.decoder.decode(payload)));
+        }
+    }
+}
@@ -0,0 +1,118 @@
+// Opening the capture stream. Some drivers refuse even the config they report as
+// their default, so before giving up this walks a ladder: the preferred config,
+// then the supported config nearest to it on the same device, then the system's
+// default device. Each attempt is logged, and the final error lists them all.
+
+use anyhow::{anyhow, Result};
+use cpal::traits::{DeviceTrait, HostTrait};
+use cpal::{Device, Host, SampleFormat, SampleRate, Stream, StreamConfig, SupportedStreamConfigRange};
+
+/// The supported config closest to `preferred`: float samples (what the capture
+/// callback takes), the nearest sample rate, then the nearest channel count
+pub fn nearest_config(
+    ranges: impl IntoIterator<Item = SupportedStreamConfigRange>,
+    preferred: &StreamConfig,
+) -> Option<StreamConfig> {
+    let want = preferred.sample_rate.0;
+    ranges
+        .into_iter()
+        .filter(|range| range.sample_format() == SampleFormat::F32)
+        .map(|range| {
+            let rate = want.clamp(range.min_sample_rate().0, range.max_sample_rate().0);
+            range.with_sample_rate(SampleRate(rate)).config()
+        })
+        .min_by_key(|config| (config.sample_rate.0.abs_diff(want), config.channels.abs_diff(preferred.channels)))
+}
+
+/// The stream `build` made, with the device and config it was made on.
+/// `loopback` captures an output device, so its configs are output configs.
+pub fn open(
+    host: &Host,
+    device: Device,
+    preferred: StreamConfig,
+    loopback: bool,
+    mut build: impl FnMut(&Device, &StreamConfig) -> Result<Stream>,
+    log: impl Fn(&str),
+) -> Result<(Device, StreamConfig, Stream)> {
+    let name = |device: &Device| device.name().unwrap_or_else(|_| "Unknown".to_string());
+    let mut failures = Vec::new();
+    let mut attempt = |device: &Device, config: &StreamConfig, step: &str| {
+        let what = format!("{} on {} ({} Hz, {} ch)", step, name(device), config.sample_rate.0, config.channels);
+        match build(device, config) {
+            Ok(stream) => {
+                log(&format!("Capture: {} opened", what));
+                Some(stream)
+            }
+            Err(e) => {
+                log(&format!("Capture: {} failed: {}", what, e));
+                failures.push(format!("{}: {}", what, e));
+                None
+            }
+        }
+    };
+
+    if let Some(stream) = attempt(&device, &preferred, "preferred config") {
+        return Ok((device, preferred, stream));
+    }
+
+    let ranges: Result<Vec<_>, _> = if loopback {
+        device.supported_output_configs().map(Iterator::collect)
+    } else {
+        device.supported_input_configs().map(Iterator::collect)
+    };
+    match ranges.map(|ranges| nearest_config(ranges, &preferred)) {
+        Ok(Some(config)) if config != preferred => {
+            if let Some(stream) = attempt(&device, &config, "nearest supported config") {
+                return Ok((device, config, stream));
+            }
+        }
+        Ok(_) => log("Capture: no other float config to try on this device"),
+        Err(e) => log(&format!("Capture: could not list the device's configs: {}", e)),
+    }
+
+    let fallback = if loopback { host.default_output_device() } else { host.default_input_device() };
+    let fallback_config = fallback.as_ref().and_then(|device| {
+        let config = if loopback { device.default_output_config() } else { device.default_input_config() };
+        config.ok().map(StreamConfig::from)
+    });
+    match fallback.zip(fallback_config) {
+        Some((fallback, config)) if name(&fallback) != name(&device) || config != preferred => {
+            if let Some(stream) = attempt(&fallback, &config, "default device") {
+                return Ok((fallback, config, stream));
+            }
+        }
+        Some(_) => log("Capture: the default device is the one that failed"),
+        None => log("Capture: no default device to fall back to"),
+    }
+
+    Err(anyhow!("Could not open {} for capture. Tried:\n{}", name(&device), failures.join("\n")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpal::SupportedBufferSize;
+
+    fn range(channels: u16, min: u32, max: u32, format: SampleFormat) -> SupportedStreamConfigRange {
+        let buffer = SupportedBufferSize::Range { min: 64, max: 4096 };
+        SupportedStreamConfigRange::new(channels, SampleRate(min), SampleRate(max), buffer, format)
+    }
+
+    #[test]
+    fn picks_the_nearest_float_config() {
+        let preferred = StreamConfig { channels: 2, sample_rate: SampleRate(48_000), buffer_size: cpal::BufferSize::Default };
+        let ranges = [
+            range(2, 48_000, 48_000, SampleFormat::I16),
+            range(1, 44_100, 48_000, SampleFormat::F32),
+            range(2, 44_100, 44_100, SampleFormat::F32),
+            range(8, 48_000, 96_000, SampleFormat::F32),
+        ];
+        // Rate comes first: stereo at 44.1 kHz loses to mono at 48 kHz
+        let config = nearest_config(ranges, &preferred).unwrap();
+        assert_eq!((config.sample_rate.0, config.channels), (48_000, 1));
+
+        assert_eq!(nearest_config(ranges[2..3].to_vec(), &preferred).unwrap().sample_rate.0, 44_100);
+        // Without any float config there is nothing to try
+        assert_eq!(nearest_config(ranges[..1].to_vec(), &preferred), None);
+    }
+}
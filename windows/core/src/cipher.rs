@@ -0,0 +1,99 @@
+// The primitives behind audio protection (seal.rs): ChaCha20-Poly1305 as RFC
+// 8439 puts them together, and HMAC and HKDF over SHA-256, all from the
+// RustCrypto crates. This only shapes them the way seal.rs calls them; the
+// tests still check each against its RFC's vectors.
+
+use chacha20poly1305::aead::AeadInPlace;
+use chacha20poly1305::{ChaCha20Poly1305, KeyInit};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+pub const TAG_SIZE: usize = 16;
+pub const NONCE_SIZE: usize = 12;
+pub type Key = [u8; 32];
+pub type Nonce = [u8; NONCE_SIZE];
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn hmac(key: &[u8], parts: &[&[u8]]) -> HmacSha256 {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(key).expect("HMAC takes keys of any length");
+    for part in parts {
+        mac.update(part);
+    }
+    mac
+}
+
+pub fn hmac_sha256(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    hmac(key, parts).finalize().into_bytes().into()
+}
+
+/// Whether `tag` is the start of the HMAC of `data`, compared in constant time
+pub fn verify_hmac_sha256(key: &[u8], data: &[u8], tag: &[u8]) -> bool {
+    hmac(key, &[data]).verify_truncated_left(tag).is_ok()
+}
+
+/// RFC 5869 extract and expand, filling `out` (at most 255 hash lengths)
+pub fn hkdf_sha256(salt: &[u8], ikm: &[u8], info: &[u8], out: &mut [u8]) {
+    Hkdf::<Sha256>::new(Some(salt), ikm).expand(info, out).expect("HKDF output of at most 255 hash lengths");
+}
+
+/// ChaCha20-Poly1305: encrypts `data` in place and returns the tag
+pub fn seal(key: &Key, nonce: &Nonce, aad: &[u8], data: &mut [u8]) -> [u8; TAG_SIZE] {
+    ChaCha20Poly1305::new(key.into())
+        .encrypt_in_place_detached(nonce.into(), aad, data)
+        .expect("datagrams are far below the 256 GiB limit")
+        .into()
+}
+
+/// Decrypts `data` in place if `tag` matches; otherwise leaves it as it came
+pub fn open(key: &Key, nonce: &Nonce, aad: &[u8], data: &mut [u8], tag: &[u8]) -> bool {
+    let Ok(tag) = <[u8; TAG_SIZE]>::try_from(tag) else {
+        return false;
+    };
+    ChaCha20Poly1305::new(key.into()).decrypt_in_place_detached(nonce.into(), aad, data, &tag.into()).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(text: &str) -> Vec<u8> {
+        let text: String = text.split_whitespace().collect();
+        (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn matches_the_rfc_vectors() {
+        // RFC 4231 test case 2 and RFC 5869 test case 1
+        assert_eq!(
+            hmac_sha256(b"Jefe", &[b"what do ya ", b"want for nothing?"]).to_vec(),
+            hex("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843")
+        );
+        let truncated = &hex("5bdcc146bf60754e6a042426089575c7");
+        assert!(verify_hmac_sha256(b"Jefe", b"what do ya want for nothing?", truncated));
+        assert!(!verify_hmac_sha256(b"Jefe", b"what do ya want for nothing!", truncated));
+        let mut okm = [0u8; 42];
+        hkdf_sha256(&hex("000102030405060708090a0b0c"), &[0x0b; 22], &hex("f0f1f2f3f4f5f6f7f8f9"), &mut okm);
+        assert_eq!(
+            okm.to_vec(),
+            hex("3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865")
+        );
+
+        // RFC 8439 2.8.2
+        let key: Key = hex("808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f").try_into().unwrap();
+        let nonce: Nonce = hex("070000004041424344454647").try_into().unwrap();
+        let aad = hex("50515253c0c1c2c3c4c5c6c7");
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+        let mut data = plaintext.to_vec();
+        let tag = seal(&key, &nonce, &aad, &mut data);
+        assert_eq!(data[..16], hex("d31a8d34648e60db7b86afbc53ef7ec2"));
+        assert_eq!(tag.to_vec(), hex("1ae10b594f09e26a7e902ecbd0600691"));
+
+        let mut tampered = data.clone();
+        tampered[3] ^= 1;
+        assert!(!open(&key, &nonce, &aad, &mut tampered, &tag));
+        assert!(open(&key, &nonce, &aad, &mut data, &tag));
+        assert_eq!(data, plaintext);
+    }
+}
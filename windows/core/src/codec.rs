@@ -0,0 +1,309 @@
+// Audio encoding and decoding for each direction. Music goes down (PC → phone)
+// and voice comes up (phone → PC), so each direction gets its own codec, channel
+// count and bitrate, agreed in the handshake (see protocol.rs for the wire format).
+//
+// Opus frames are 20 ms, one per datagram, made from two of the capture's fixed
+// 10 ms frames (see framer.rs); the battery saver can agree on 40 or 60 ms
+// going down (see set_ptime). Packets already in flight when the
+// formats switch are decoded with the wrong codec, which can cause a short
+// glitch right after connecting.
+//
+// Besides 16-bit PCM, the PC → phone direction can carry 24-bit packed PCM
+// or 32-bit float. The capture path is float throughout, so these keep what
+// 16 bits would round off, at 1.5 and 2 times the bandwidth. Datagrams always
+// hold whole sample frames.
+//
+// Uncompressed audio from the phone may come at its mic's own sample rate (see
+// the uprate field in protocol.rs); the decoder resamples it to 48 kHz by linear
+// interpolation, which is plenty for a voice mic.
+
+use anyhow::{anyhow, Result};
+use audiopus::coder::{Decoder as OpusDecoder, Encoder as OpusEncoder};
+use audiopus::packet::Packet;
+use audiopus::{Application, Bitrate, Channels, MutSignals, SampleRate};
+use crate::convert;
+use crate::protocol::{self, Codec, RedPacker, RedUnpacker, StreamFormat};
+
+const OPUS_FRAME: usize = 960; // 20 ms at 48 kHz, per channel
+const MAX_OPUS_PACKET: usize = 1275;
+const MAX_OPUS_FRAME: usize = 5760; // 120 ms, the longest frame a decoder may be handed
+
+fn opus_channels(channels: u8) -> Channels {
+    if channels == 2 {
+        Channels::Stereo
+    } else {
+        Channels::Mono
+    }
+}
+
+/// Turns captured audio (48 kHz interleaved float stereo) into packet payloads
+pub struct Encoder {
+    format: StreamFormat,
+    opus: Option<OpusEncoder>,
+    red: Option<RedPacker>,
+    pending: Vec<f32>,
+    frame: usize, // samples per channel in each Opus frame
+}
+
+impl Encoder {
+    /// `redundancy` only applies to Opus
+    pub fn new(format: StreamFormat, redundancy: u8) -> Result<Self> {
+        let opus = match format.codec {
+            Codec::Pcm | Codec::Pcm24 | Codec::Float => None,
+            Codec::Opus => {
+                // Voice-sized streams get the speech-tuned mode
+                let application = if format.channels == 1 && format.bitrate_kbps <= 32 {
+                    Application::Voip
+                } else {
+                    Application::Audio
+                };
+                let mut encoder = OpusEncoder::new(SampleRate::Hz48000, opus_channels(format.channels), application)
+                    .map_err(|e| anyhow!("Opus encoder: {}", e))?;
+                encoder
+                    .set_bitrate(Bitrate::BitsPerSecond(format.bitrate_kbps as i32 * 1000))
+                    .map_err(|e| anyhow!("Opus encoder: {}", e))?;
+                Some(encoder)
+            }
+        };
+        let red = (opus.is_some() && redundancy > 0).then(|| RedPacker::new(redundancy));
+        Ok(Self { format, opus, red, pending: Vec::new(), frame: OPUS_FRAME })
+    }
+
+    /// Opus frames of `ms` (one of protocol::PTIMES) instead of 20 ms
+    pub fn set_ptime(&mut self, ms: u32) {
+        self.frame = protocol::NATIVE_RATE as usize / 1000 * ms as usize;
+    }
+
+    /// Payloads ready to send. PCM comes back as one buffer for the caller to
+    /// split (see chunk_size); Opus as one packet per frame.
+    pub fn encode(&mut self, stereo: &[f32]) -> Vec<Vec<u8>> {
+        let samples = if self.format.channels == 2 { stereo.to_vec() } else { convert::downmix(stereo) };
+        let Some(opus) = &self.opus else {
+            return vec![protocol::pack_samples(self.format.codec, &samples)];
+        };
+
+        self.pending.extend_from_slice(&samples);
+        let frame_len = self.frame * self.format.channels as usize;
+        let mut packets = Vec::new();
+        while self.pending.len() >= frame_len {
+            let mut packet = vec![0u8; MAX_OPUS_PACKET];
+            if let Ok(len) = opus.encode_float(&self.pending[..frame_len], &mut packet) {
+                packet.truncate(len);
+                packets.push(match &mut self.red {
+                    Some(red) => red.pack(packet),
+                    None => packet,
+                });
+            }
+            self.pending.drain(..frame_len);
+        }
+        packets
+    }
+
+    /// The largest datagram up to `max_payload` that holds whole sample frames
+    pub fn chunk_size(&self, max_payload: usize) -> usize {
+        let frame = self.format.frame_bytes().unwrap_or(1);
+        max_payload / frame * frame
+    }
+}
+
+/// Turns received payloads into 48 kHz mono for playback
+pub struct Decoder {
+    format: StreamFormat,
+    opus: Option<OpusDecoder>,
+    red: Option<RedUnpacker>,
+    resampler: Option<Resampler>,
+}
+
+impl Decoder {
+    /// `redundancy` only applies to Opus, `sample_rate` only to uncompressed audio
+    pub fn new(format: StreamFormat, redundancy: u8, sample_rate: u32) -> Result<Self> {
+        let opus = match format.codec {
+            Codec::Pcm | Codec::Pcm24 | Codec::Float => None,
+            Codec::Opus => Some(
+                OpusDecoder::new(SampleRate::Hz48000, opus_channels(format.channels))
+                    .map_err(|e| anyhow!("Opus decoder: {}", e))?,
+            ),
+        };
+        let red = (opus.is_some() && redundancy > 0).then(RedUnpacker::default);
+        let resampler = (opus.is_none() && sample_rate != protocol::NATIVE_RATE).then(|| Resampler::new(sample_rate));
+        Ok(Self { format, opus, red, resampler })
+    }
+
+    /// Frames restored from redundant copies after their own packet was lost
+    pub fn recovered_frames(&self) -> u64 {
+        self.red.as_ref().map_or(0, RedUnpacker::recovered)
+    }
+
+    /// Decoded samples; empty if the packet couldn't be decoded or was a late duplicate
+    pub fn decode(&mut self, payload: &[u8]) -> Vec<i16> {
+        let channels = self.format.channels as usize;
+        let samples: Vec<i16> = match (&mut self.opus, &mut self.red) {
+            (None, _) => protocol::unpack_samples(self.format.codec, payload),
+            (Some(opus), None) => decode_opus(opus, payload, channels),
+            (Some(opus), Some(red)) => red
+                .unpack(payload)
+                .into_iter()
+                .flat_map(|frame| decode_opus(opus, frame, channels))
+                .collect(),
+        };
+        let mono = if self.format.channels == 2 { convert::downmix_i16(&samples) } else { samples };
+        match &mut self.resampler {
+            Some(resampler) => resampler.process(&mono),
+            None => mono,
+        }
+    }
+}
+
+/// Mono audio at another rate to 48 kHz, carrying the position across packets
+/// so their joins don't click
+struct Resampler {
+    step: f64, // input samples per output sample
+    position: f64,
+    previous: i16,
+}
+
+impl Resampler {
+    fn new(from_rate: u32) -> Self {
+        Self { step: from_rate as f64 / protocol::NATIVE_RATE as f64, position: 0.0, previous: 0 }
+    }
+
+    fn process(&mut self, input: &[i16]) -> Vec<i16> {
+        let Some(&last) = input.last() else {
+            return Vec::new();
+        };
+        // Position 0 is the last sample of the previous packet, 1 the first of this one
+        let sample = |i: usize| if i == 0 { self.previous } else { input[i - 1] } as f64;
+        let mut out = Vec::with_capacity((input.len() as f64 / self.step) as usize + 1);
+        while self.position < input.len() as f64 {
+            let index = self.position as usize;
+            let fraction = self.position - index as f64;
+            out.push((sample(index) + (sample(index + 1) - sample(index)) * fraction).round() as i16);
+            self.position += self.step;
+        }
+        self.position -= input.len() as f64;
+        self.previous = last;
+        out
+    }
+}
+
+fn decode_opus(opus: &mut OpusDecoder, frame: &[u8], channels: usize) -> Vec<i16> {
+    let mut out = vec![0i16; MAX_OPUS_FRAME * channels];
+    let decoded = Packet::try_from(frame)
+        .ok()
+        .zip(MutSignals::try_from(&mut out[..]).ok())
+        .and_then(|(packet, signals)| opus.decode(Some(packet), signals, false).ok());
+    match decoded {
+        Some(frames) => {
+            out.truncate(frames * channels);
+            out
+        }
+        None => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 100 ms of a 440 Hz tone, stereo
+    fn tone() -> Vec<f32> {
+        (0..4800)
+            .flat_map(|i| {
+                let s = (i as f32 * 440.0 * std::f32::consts::TAU / 48000.0).sin() * 0.25;
+                [s, s]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn opus_round_trips_asymmetric_formats() {
+        let stereo = tone();
+        for format in [StreamFormat::DOWN_CHOICES[3], StreamFormat::UP_CHOICES[3]] {
+            let mut encoder = Encoder::new(format, 0).unwrap();
+            let mut decoder = Decoder::new(format, 0, protocol::NATIVE_RATE).unwrap();
+            let packets = encoder.encode(&stereo);
+            assert_eq!(packets.len(), 5);
+            let decoded: Vec<i16> = packets.iter().flat_map(|p| decoder.decode(p)).collect();
+            assert_eq!(decoded.len(), 4800);
+            assert!(decoded.iter().any(|s| s.abs() > 1000));
+        }
+    }
+
+    #[test]
+    fn longer_frames_make_fewer_packets() {
+        let format = StreamFormat::DOWN_CHOICES[4];
+        let mut encoder = Encoder::new(format, 0).unwrap();
+        encoder.set_ptime(40);
+        let client_format = budbridge_client::StreamFormat::parse(&format.to_string()).unwrap();
+        let mut client = budbridge_client::Decoder::new(client_format, 0).unwrap();
+        let packets = encoder.encode(&[tone(), tone()].concat());
+        assert_eq!(packets.len(), 5);
+        let decoded: Vec<f32> = packets.iter().flat_map(|p| client.decode(p)).collect();
+        assert_eq!(decoded.len(), 1920 * 2 * 5);
+    }
+
+    #[test]
+    fn redundancy_fills_in_lost_packets() {
+        let format = StreamFormat::UP_CHOICES[1];
+        let mut encoder = Encoder::new(format, 2).unwrap();
+        let mut decoder = Decoder::new(format, 2, protocol::NATIVE_RATE).unwrap();
+        let packets = encoder.encode(&vec![0.03; 960 * 2 * 6]);
+        assert_eq!(packets.len(), 6);
+
+        // Packets 2 and 3 (of 0..6) are lost; packet 4 carries both again
+        let decoded: Vec<i16> = [0, 1, 4, 5, 5].iter().flat_map(|&i| decoder.decode(&packets[i])).collect();
+        assert_eq!(decoded.len(), 960 * 6);
+        assert_eq!(decoder.recovered_frames(), 2);
+    }
+
+    #[test]
+    fn the_client_crate_plays_what_the_encoder_sends() {
+        // budbridge-client in the phone's place, packets 2 and 3 lost
+        for format in [StreamFormat::DOWN_CHOICES[4], StreamFormat::DOWN_CHOICES[5]] {
+            let mut encoder = Encoder::new(format, 2).unwrap();
+            let client_format = budbridge_client::StreamFormat::parse(&format.to_string()).unwrap();
+            let mut client = budbridge_client::Decoder::new(client_format, 2).unwrap();
+            let packets = encoder.encode(&tone());
+            let decoded: Vec<f32> = [0, 1, 4].iter().flat_map(|&i| client.decode(&packets[i])).collect();
+            assert_eq!(decoded.len(), 960 * 2 * 5);
+            assert!(decoded.iter().any(|s| s.abs() > 0.1));
+        }
+    }
+
+    #[test]
+    fn pcm_at_the_mic_rate_is_resampled_to_48k() {
+        // 100 ms of the tone at 24 kHz, sent as 10 ms packets
+        let tone_24k: Vec<f32> = tone().into_iter().step_by(4).collect();
+        let payload = protocol::pack_samples(Codec::Pcm, &tone_24k);
+        let mut decoder = Decoder::new(StreamFormat::PCM_MONO, 0, 24_000).unwrap();
+        let decoded: Vec<i16> = payload.chunks(480).flat_map(|p| decoder.decode(p)).collect();
+        assert_eq!(decoded.len(), 4800);
+
+        // Every other output sample lands on an input sample, one behind
+        let expected = tone().into_iter().step_by(2).map(convert::to_i16);
+        let error = decoded.iter().skip(2).zip(expected).map(|(a, b)| (a - b).abs()).max();
+        assert!(error.unwrap() < 400, "{:?}", error);
+    }
+
+    #[test]
+    fn high_resolution_pcm_keeps_whole_frames_per_datagram() {
+        let stereo = tone();
+        for format in [StreamFormat::DOWN_CHOICES[1], StreamFormat::DOWN_CHOICES[2]] {
+            let mut encoder = Encoder::new(format, 0).unwrap();
+            let mut decoder = Decoder::new(format, 0, protocol::NATIVE_RATE).unwrap();
+            let chunk = encoder.chunk_size(1400);
+            assert_eq!(chunk % format.frame_bytes().unwrap(), 0);
+            let payload = encoder.encode(&stereo).remove(0);
+            assert_eq!(payload.len(), 4800 * format.frame_bytes().unwrap());
+            let decoded: Vec<i16> = payload.chunks(chunk).flat_map(|p| decoder.decode(p)).collect();
+            assert_eq!(decoded.len(), 4800);
+            let expected = stereo.iter().step_by(2).map(|&s| convert::to_i16(s));
+            assert!(decoded.iter().zip(expected).all(|(a, b)| (a - b).abs() <= 1));
+        }
+
+        // Uncompressed rates are fixed by the format, and headers come on top
+        let format = StreamFormat::parse("f32/2/0").unwrap();
+        assert_eq!(format.bitrate_kbps, 3072);
+        assert!(format.network_kbps(0, 1400) > format.bitrate_kbps as u32);
+    }
+}
@@ -0,0 +1,150 @@
+// Sample conversions between the float audio that capture, processing and the
+// encoders work in and the integer samples on the wire and in the outputs. Full
+// scale is 32768 for 16 bits and 8388608 for 24, as the iPhone decodes them:
+// i16::MIN is exactly -1.0, the largest positive sample is one step short of 1.0,
+// and every integer sample survives a round trip through float. Going to integers
+// rounds to the nearest step and clips at both ends. NaN, from a filter that went
+// unstable, comes out as silence rather than a full-scale click.
+
+const I16_SCALE: f32 = 32768.0;
+const I24_SCALE: f32 = 8_388_608.0;
+const I24_MAX: i32 = (1 << 23) - 1;
+
+/// `sample` within [-1, 1], NaN as silence
+pub fn clamp(sample: f32) -> f32 {
+    if sample.is_nan() {
+        0.0
+    } else {
+        sample.clamp(-1.0, 1.0)
+    }
+}
+
+pub fn to_i16(sample: f32) -> i16 {
+    // The cast saturates, so 1.0 clips to i16::MAX
+    (clamp(sample) * I16_SCALE).round() as i16
+}
+
+pub fn from_i16(sample: i16) -> f32 {
+    sample as f32 / I16_SCALE
+}
+
+/// A 24-bit sample in the low bits of an i32
+pub fn to_i24(sample: f32) -> i32 {
+    ((clamp(sample) * I24_SCALE).round() as i32).min(I24_MAX)
+}
+
+pub fn from_i24(sample: i32) -> f32 {
+    sample as f32 / I24_SCALE
+}
+
+/// Interleaved stereo from `channels`-channel audio: the first two channels, or
+/// mono doubled. A partial frame at the end is dropped.
+pub fn to_stereo(samples: &[f32], channels: usize) -> Vec<f32> {
+    samples
+        .chunks_exact(channels.max(1))
+        .flat_map(|frame| [frame[0], frame.get(1).copied().unwrap_or(frame[0])])
+        .collect()
+}
+
+/// Interleaved stereo to mono, (L+R)/2. Nothing is clamped; see to_i16.
+pub fn downmix(stereo: &[f32]) -> Vec<f32> {
+    stereo.chunks_exact(2).map(|frame| (frame[0] + frame[1]) / 2.0).collect()
+}
+
+/// Interleaved 16-bit stereo to mono, rounding toward zero either side
+pub fn downmix_i16(stereo: &[i16]) -> Vec<i16> {
+    stereo
+        .chunks_exact(2)
+        .map(|frame| ((frame[0] as i32 + frame[1] as i32) / 2) as i16)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn full_scale_reaches_both_ends() {
+        assert_eq!(to_i16(-1.0), i16::MIN);
+        assert_eq!(to_i16(1.0), i16::MAX);
+        assert_eq!((to_i16(f32::NEG_INFINITY), to_i16(f32::INFINITY)), (i16::MIN, i16::MAX));
+        assert_eq!((to_i16(-1.5), to_i16(1.5)), (i16::MIN, i16::MAX));
+        assert_eq!((to_i16(0.5), to_i16(-0.5)), (16384, -16384));
+        assert_eq!(to_i16(f32::NAN), 0);
+
+        assert_eq!((to_i24(-1.0), to_i24(1.0)), (-I24_MAX - 1, I24_MAX));
+        assert_eq!((to_i24(0.5), to_i24(-0.5)), (1 << 22, -(1 << 22)));
+        assert_eq!(to_i24(f32::NAN), 0);
+        assert_eq!((from_i16(i16::MIN), from_i24(-I24_MAX - 1)), (-1.0, -1.0));
+    }
+
+    #[test]
+    fn rounds_to_the_nearest_step() {
+        let step = 1.0 / I16_SCALE;
+        assert_eq!((to_i16(0.4 * step), to_i16(0.6 * step)), (0, 1));
+        assert_eq!((to_i16(-0.4 * step), to_i16(-0.6 * step)), (0, -1));
+        // Just under full scale is the top step, not one below it
+        assert_eq!(to_i16(1.0 - 0.6 * step), i16::MAX);
+    }
+
+    #[test]
+    fn every_16_bit_sample_round_trips() {
+        for sample in i16::MIN..=i16::MAX {
+            assert_eq!(to_i16(from_i16(sample)), sample);
+        }
+    }
+
+    #[test]
+    fn downmixes_and_widens_channels() {
+        assert_eq!(downmix_i16(&[i16::MIN, i16::MIN, i16::MAX, i16::MAX, i16::MIN, i16::MAX]), [i16::MIN, i16::MAX, 0]);
+        assert_eq!(downmix(&[0.5, -0.5, 1.0, 0.5]), [0.0, 0.75]);
+        assert_eq!(to_stereo(&[0.1, 0.2], 1), [0.1, 0.1, 0.2, 0.2]);
+        assert_eq!(to_stereo(&[0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7], 3), [0.1, 0.2, 0.4, 0.5]);
+        assert_eq!(to_stereo(&[0.1], 0), [0.1, 0.1]);
+    }
+
+    proptest! {
+        #[test]
+        fn every_24_bit_sample_round_trips(sample in -I24_MAX - 1..=I24_MAX) {
+            prop_assert_eq!(to_i24(from_i24(sample)), sample);
+        }
+
+        #[test]
+        fn conversion_is_within_half_a_step(sample in -1.0f32..=1.0) {
+            let error = (from_i16(to_i16(sample)) - sample).abs();
+            let clipped = sample > from_i16(i16::MAX);
+            prop_assert!(clipped || error <= 0.5 / I16_SCALE, "{} off by {}", sample, error);
+        }
+
+        #[test]
+        fn clipping_is_symmetric_inside_full_scale(sample in -from_i16(i16::MAX)..=from_i16(i16::MAX)) {
+            prop_assert_eq!(to_i16(-sample), -to_i16(sample));
+            prop_assert_eq!(to_i24(-sample), -to_i24(sample));
+        }
+
+        #[test]
+        fn conversion_never_reverses_order(a in any::<f32>(), b in any::<f32>()) {
+            prop_assume!(!a.is_nan() && !b.is_nan());
+            let (low, high) = if a <= b { (a, b) } else { (b, a) };
+            prop_assert!(to_i16(low) <= to_i16(high));
+            prop_assert!(to_i24(low) <= to_i24(high));
+        }
+
+        #[test]
+        fn mixes_stay_between_their_channels(left in any::<i16>(), right in any::<i16>()) {
+            let mono = downmix_i16(&[left, right])[0];
+            prop_assert!(left.min(right) <= mono && mono <= left.max(right));
+            let mono = downmix(&[from_i16(left), from_i16(right)])[0];
+            prop_assert!(from_i16(left.min(right)) <= mono && mono <= from_i16(left.max(right)));
+        }
+
+        #[test]
+        fn stereo_has_two_samples_a_frame(channels in 1usize..8, frames in 0usize..64) {
+            let samples: Vec<f32> = (0..channels * frames).map(|i| i as f32).collect();
+            let stereo = to_stereo(&samples, channels);
+            prop_assert_eq!(stereo.len(), frames * 2);
+            prop_assert!(stereo.chunks_exact(2).zip(samples.chunks_exact(channels)).all(|(s, f)| s[0] == f[0]));
+        }
+    }
+}
@@ -0,0 +1,141 @@
+// How captured stereo becomes mono. Everything downstream is mono in the end
+// (PCM mono, the iPhone's HFP route, RTSP and AES67), and the usual (L+R)/2 mix
+// silences anything whose channels are phase-inverted copies of each other, which
+// some games do for "wide" effects. PhaseMonitor notices that happening so the UI
+// can suggest taking one channel instead.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+const WINDOW_FRAMES: usize = 48_000; // 1 s
+const MIN_STEREO_ENERGY: f64 = 1e-5; // about -50 dBFS: quieter is just silence
+const MAX_MONO_RATIO: f64 = 0.01; // mono 20 dB below the channels means they cancel
+const WINDOWS_TO_WARN: u32 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum DownmixMode {
+    #[default]
+    Mix,
+    Left,
+    Right,
+}
+
+impl DownmixMode {
+    pub const ALL: [DownmixMode; 3] = [DownmixMode::Mix, DownmixMode::Left, DownmixMode::Right];
+
+    pub fn parse(text: &str) -> Self {
+        match text {
+            "left" => Self::Left,
+            "right" => Self::Right,
+            _ => Self::Mix,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Mix => "mix",
+            Self::Left => "left",
+            Self::Right => "right",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Mix => "Mix left and right",
+            Self::Left => "Left channel only",
+            Self::Right => "Right channel only",
+        }
+    }
+
+    pub fn load(atomic: &AtomicU8) -> Self {
+        Self::ALL.get(atomic.load(Ordering::Relaxed) as usize).copied().unwrap_or_default()
+    }
+
+    pub fn store(self, atomic: &AtomicU8) {
+        atomic.store(self as u8, Ordering::Relaxed);
+    }
+
+    /// Copy the chosen channel over the other, in interleaved stereo, so the
+    /// later (L+R)/2 downmix yields just that channel
+    pub fn apply(self, stereo: &mut [f32]) {
+        for frame in stereo.chunks_exact_mut(2) {
+            match self {
+                Self::Mix => return,
+                Self::Left => frame[1] = frame[0],
+                Self::Right => frame[0] = frame[1],
+            }
+        }
+    }
+}
+
+/// Watches interleaved stereo for channels that cancel out when mixed
+#[derive(Default)]
+pub struct PhaseMonitor {
+    stereo_energy: f64,
+    mono_energy: f64,
+    frames: usize,
+    cancelling_windows: u32,
+}
+
+impl PhaseMonitor {
+    pub fn push(&mut self, stereo: &[f32]) {
+        for frame in stereo.chunks_exact(2) {
+            let left = frame[0] as f64;
+            let right = frame[1] as f64;
+            self.stereo_energy += (left * left + right * right) / 2.0;
+            self.mono_energy += ((left + right) / 2.0).powi(2);
+            self.frames += 1;
+            if self.frames == WINDOW_FRAMES {
+                let stereo = self.stereo_energy / WINDOW_FRAMES as f64;
+                let mono = self.mono_energy / WINDOW_FRAMES as f64;
+                if stereo >= MIN_STEREO_ENERGY && mono < stereo * MAX_MONO_RATIO {
+                    self.cancelling_windows += 1;
+                } else {
+                    self.cancelling_windows = 0;
+                }
+                self.stereo_energy = 0.0;
+                self.mono_energy = 0.0;
+                self.frames = 0;
+            }
+        }
+    }
+
+    /// The mix has been near silent for a while although the channels aren't
+    pub fn cancelling(&self) -> bool {
+        self.cancelling_windows >= WINDOWS_TO_WARN
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(seconds: usize, right_sign: f32) -> Vec<f32> {
+        (0..seconds * 48_000)
+            .flat_map(|i| {
+                let s = (i as f32 * 440.0 * std::f32::consts::TAU / 48_000.0).sin() * 0.25;
+                [s, s * right_sign]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn warns_only_about_inverted_channels() {
+        let mut monitor = PhaseMonitor::default();
+        monitor.push(&tone(3, 1.0));
+        assert!(!monitor.cancelling());
+
+        monitor.push(&tone(3, -1.0));
+        assert!(monitor.cancelling());
+
+        monitor.push(&vec![0.0; 3 * 2 * 48_000]);
+        assert!(!monitor.cancelling());
+    }
+
+    #[test]
+    fn single_channel_modes_survive_the_mix() {
+        let mut samples = tone(1, -1.0);
+        DownmixMode::Left.apply(&mut samples);
+        let mixed = crate::convert::downmix(&samples);
+        assert!(mixed.iter().any(|&s| s.abs() > 0.2));
+    }
+}
@@ -0,0 +1,277 @@
+// Forward error correction for lossy Wi-Fi, once the ack agrees to "fec=N" (see
+// protocol.rs). After every N framed audio datagrams the sender adds a parity
+// datagram, the XOR of those N with their frame headers, each padded with
+// zeros to the longest:
+//
+//   [u8 0xBB][u8 0xFE][u32 LE sequence of the first][u8 count][XOR of the datagrams]
+//
+// Any one datagram of the group that goes missing is the parity XORed with the
+// others, and its own header, XORed in too, gives back its sequence, timestamp
+// and length. Two lost in one group stay lost. It costs one datagram in N (25%
+// at 4) and works for every codec, where redundancy repeats whole Opus frames.
+// The parity is sealed like any other datagram.
+//
+// Audio is played as it arrives, so the Decoder holds back what comes after a
+// gap until the group's parity fills it, then hands both on in order. After a
+// loss that delays the audio by up to a group, which the playback buffer takes
+// as a burst. A gap the parity can't fill (two losses, or the parity lost too) is
+// skipped once a datagram from past its group arrives.
+
+use crate::protocol;
+use std::collections::VecDeque;
+
+const PARITY_MAGIC: [u8; 2] = [0xBB, 0xFE];
+pub const PARITY_HEADER_SIZE: usize = 2 + 4 + 1;
+/// A sequence this far behind is a sender that started over, not a late datagram
+const RESTART: u32 = 1000;
+
+/// Builds the parity datagrams for what's sent
+pub struct Encoder {
+    group: u32,
+    first: u32,
+    count: u32,
+    parity: Vec<u8>,
+}
+
+impl Encoder {
+    /// A parity datagram after every `group` audio datagrams
+    pub fn new(group: u8) -> Self {
+        Self { group: group.max(1) as u32, first: 0, count: 0, parity: Vec::new() }
+    }
+
+    /// Adds a framed datagram as sent, before sealing; returns the parity
+    /// datagram to send after it once it completes a group
+    pub fn push(&mut self, datagram: &[u8]) -> Option<Vec<u8>> {
+        let (header, _) = protocol::unframe(datagram)?;
+        // A datagram numbered but never sent starts the group again
+        if self.count == 0 || header.seq != self.first.wrapping_add(self.count) {
+            self.first = header.seq;
+            self.count = 0;
+            self.parity.clear();
+        }
+        xor_into(&mut self.parity, datagram);
+        self.count += 1;
+        if self.count < self.group {
+            return None;
+        }
+        let mut packet = Vec::with_capacity(PARITY_HEADER_SIZE + self.parity.len());
+        packet.extend_from_slice(&PARITY_MAGIC);
+        packet.extend_from_slice(&self.first.to_le_bytes());
+        packet.push(self.count as u8);
+        packet.extend_from_slice(&self.parity);
+        self.count = 0;
+        Some(packet)
+    }
+}
+
+/// Puts received audio back in order, rebuilding what the parity can
+pub struct Decoder {
+    group: u32,
+    next: Option<u32>,                // the sequence to hand on next
+    held: Vec<(u32, Vec<u8>)>,        // arrived after a gap, in sequence order
+    recent: VecDeque<(u32, Vec<u8>)>, // what a parity datagram may need
+    recovered: u64,
+}
+
+impl Decoder {
+    pub fn new(group: u8) -> Self {
+        Self { group: group.max(1) as u32, next: None, held: Vec::new(), recent: VecDeque::new(), recovered: 0 }
+    }
+
+    /// Datagrams rebuilt from parity after they were lost
+    pub fn recovered(&self) -> u64 {
+        self.recovered
+    }
+
+    /// Takes an opened datagram, audio or parity, and returns the audio
+    /// datagrams ready to play, in order. Bare ones pass straight through.
+    pub fn receive(&mut self, datagram: &[u8]) -> Vec<Vec<u8>> {
+        if let Some((first, count, parity)) = parse_parity(datagram) {
+            return match self.rebuild(first, count, parity) {
+                Some((seq, rebuilt)) => {
+                    self.recovered += 1;
+                    self.accept(seq, rebuilt)
+                }
+                None => Vec::new(),
+            };
+        }
+        match protocol::unframe(datagram) {
+            Some((header, _)) => self.accept(header.seq, datagram.to_vec()),
+            None => vec![datagram.to_vec()],
+        }
+    }
+
+    fn accept(&mut self, seq: u32, datagram: Vec<u8>) -> Vec<Vec<u8>> {
+        let Some(next) = self.next else {
+            self.remember(seq, &datagram);
+            self.next = Some(seq.wrapping_add(1));
+            return vec![datagram];
+        };
+        let behind = next.wrapping_sub(seq);
+        if behind > RESTART && behind < u32::MAX / 2 {
+            // The sender started over: what was held is older than anything now
+            *self = Self { recovered: self.recovered, ..Self::new(self.group as u8) };
+            return self.accept(seq, datagram);
+        }
+        // Late or a duplicate: its turn has passed
+        if behind != 0 && behind < u32::MAX / 2 {
+            return Vec::new();
+        }
+        self.remember(seq, &datagram);
+        if behind == 0 && self.held.is_empty() {
+            self.next = Some(seq.wrapping_add(1));
+            return vec![datagram];
+        }
+        let ahead = |s: u32| s.wrapping_sub(next);
+        if let Err(at) = self.held.binary_search_by_key(&ahead(seq), |(s, _)| ahead(*s)) {
+            self.held.insert(at, (seq, datagram));
+        }
+        self.release()
+    }
+
+    /// Hands on what's held up to the next gap, and skips a gap the group's
+    /// parity should have filled by now
+    fn release(&mut self) -> Vec<Vec<u8>> {
+        let mut ready = Vec::new();
+        let Some(mut next) = self.next else {
+            return ready;
+        };
+        while let Some((first, _)) = self.held.first() {
+            if *first == next {
+                ready.push(self.held.remove(0).1);
+                next = next.wrapping_add(1);
+                continue;
+            }
+            let newest = self.held.last().map_or(next, |(s, _)| *s);
+            if newest.wrapping_sub(next) < self.group {
+                break;
+            }
+            next = *first;
+        }
+        self.next = Some(next);
+        ready
+    }
+
+    fn remember(&mut self, seq: u32, datagram: &[u8]) {
+        if self.recent.iter().any(|(s, _)| *s == seq) {
+            return;
+        }
+        self.recent.push_back((seq, datagram.to_vec()));
+        while self.recent.len() > 2 * self.group as usize {
+            self.recent.pop_front();
+        }
+    }
+
+    /// The one datagram of a group that's missing and still wanted, rebuilt
+    fn rebuild(&self, first: u32, count: u32, parity: &[u8]) -> Option<(u32, Vec<u8>)> {
+        let next = self.next?;
+        let mut missing = (0..count).map(|i| first.wrapping_add(i)).filter(|s| !self.recent.iter().any(|(r, _)| r == s));
+        let seq = missing.next()?;
+        if missing.next().is_some() || seq.wrapping_sub(next) >= u32::MAX / 2 {
+            return None;
+        }
+        let mut rebuilt = parity.to_vec();
+        for (_, datagram) in self.recent.iter().filter(|(s, _)| s.wrapping_sub(first) < count) {
+            xor_into(&mut rebuilt, datagram);
+        }
+        // Its own header says how much of the padded XOR is the datagram
+        let length = protocol::FRAME_HEADER_SIZE + u16::from_le_bytes([*rebuilt.get(10)?, *rebuilt.get(11)?]) as usize;
+        rebuilt.truncate(length);
+        let (header, _) = protocol::unframe(&rebuilt)?;
+        (header.seq == seq).then_some((seq, rebuilt))
+    }
+}
+
+/// First sequence, count and XOR of a parity datagram
+fn parse_parity(datagram: &[u8]) -> Option<(u32, u32, &[u8])> {
+    let (header, parity) = datagram.split_first_chunk::<PARITY_HEADER_SIZE>()?;
+    if header[..2] != PARITY_MAGIC {
+        return None;
+    }
+    let first = u32::from_le_bytes([header[2], header[3], header[4], header[5]]);
+    Some((first, header[6] as u32, parity))
+}
+
+fn xor_into(parity: &mut Vec<u8>, datagram: &[u8]) {
+    if parity.len() < datagram.len() {
+        parity.resize(datagram.len(), 0);
+    }
+    for (p, byte) in parity.iter_mut().zip(datagram) {
+        *p ^= byte;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{frame, unframe, FrameHeader};
+
+    fn datagram(seq: u32) -> Vec<u8> {
+        // Lengths differ, so rebuilding has to get the padding right
+        frame(FrameHeader { seq, timestamp: seq * 960 }, &vec![seq as u8; 1 + seq as usize % 3])
+    }
+
+    fn sequences(datagrams: &[Vec<u8>]) -> Vec<u32> {
+        datagrams.iter().map(|d| unframe(d).unwrap().0.seq).collect()
+    }
+
+    /// Sends 0..count through an encoder of `group`, dropping `lost`
+    fn run(group: u8, count: u32, lost: &[u32], lose_parity: &[u32]) -> (Vec<u32>, u64) {
+        let mut encoder = Encoder::new(group);
+        let mut decoder = Decoder::new(group);
+        let mut played = Vec::new();
+        for seq in 0..count {
+            let sent = datagram(seq);
+            let parity = encoder.push(&sent);
+            if !lost.contains(&seq) {
+                played.extend(decoder.receive(&sent));
+            }
+            if let Some(parity) = parity.filter(|_| !lose_parity.contains(&seq)) {
+                played.extend(decoder.receive(&parity));
+            }
+        }
+        (sequences(&played), decoder.recovered())
+    }
+
+    #[test]
+    fn one_loss_a_group_comes_back_in_order() {
+        let (played, recovered) = run(4, 12, &[1, 7, 8], &[]);
+        assert_eq!(played, (0..12).collect::<Vec<_>>());
+        assert_eq!(recovered, 3);
+
+        // The last of a group, the first of the next, and one from every group
+        let (played, recovered) = run(4, 12, &[3, 4, 10], &[]);
+        assert_eq!(played, (0..12).collect::<Vec<_>>());
+        assert_eq!(recovered, 3);
+    }
+
+    #[test]
+    fn gaps_parity_cant_fill_are_skipped() {
+        // Two lost in one group: the rest of it waits for the parity, then moves on
+        let (played, recovered) = run(4, 12, &[5, 6], &[]);
+        assert_eq!(played, [0, 1, 2, 3, 4, 7, 8, 9, 10, 11]);
+        assert_eq!(recovered, 0);
+
+        // The parity lost too: skipped once the next group gets going
+        let (played, _) = run(4, 12, &[5], &[7]);
+        assert_eq!(played, [0, 1, 2, 3, 4, 6, 7, 8, 9, 10, 11]);
+    }
+
+    #[test]
+    fn reordering_and_restarts_keep_the_audio_going() {
+        let mut decoder = Decoder::new(4);
+        assert_eq!(sequences(&decoder.receive(&datagram(0))), [0]);
+        assert!(decoder.receive(&datagram(2)).is_empty());
+        assert_eq!(sequences(&decoder.receive(&datagram(1))), [1, 2]);
+        // Late, and a duplicate
+        assert!(decoder.receive(&datagram(1)).is_empty());
+
+        // The phone's app restarted and counts from 0 again
+        let mut decoder = Decoder::new(4);
+        decoder.receive(&datagram(5000));
+        assert_eq!(sequences(&decoder.receive(&datagram(0))), [0]);
+
+        // Bare audio isn't touched
+        assert_eq!(decoder.receive(&[1, 2, 3]), [vec![1, 2, 3]]);
+    }
+}
@@ -0,0 +1,70 @@
+// Capture arrives in whatever buffer size the driver picks (441 samples, 1024,
+// 10 ms, sometimes 100 ms on loopback), and it differs between devices. The
+// framer re-blocks it into fixed frames of interleaved stereo before anything
+// else sees it, so downmixing, the processing chain, the encoder and packetizing
+// always get the same size: two frames make one 20 ms Opus frame, and a 16-bit
+// mono frame fits one datagram.
+//
+// Samples that don't fill a frame wait for the next callback, which adds up to
+// one frame of latency.
+
+use crate::protocol::NATIVE_RATE;
+
+pub const FRAME_MS: usize = 10;
+/// Room in the channel to the network thread: a 100 ms driver buffer's worth
+/// of frames, with some to spare
+pub const QUEUE_FRAMES: usize = 16;
+
+pub struct Framer {
+    frame_len: usize,
+    pending: Vec<f32>,
+}
+
+impl Framer {
+    /// Frames of `frame_ms` of 48 kHz stereo
+    pub fn new(frame_ms: usize) -> Self {
+        let frame_len = NATIVE_RATE as usize / 1000 * frame_ms * 2;
+        Self { frame_len, pending: Vec::with_capacity(2 * frame_len) }
+    }
+
+    /// Buffers `stereo` and returns the frames it completed, oldest first
+    pub fn push(&mut self, stereo: &[f32]) -> Vec<Vec<f32>> {
+        self.pending.extend_from_slice(stereo);
+        let frames: Vec<Vec<f32>> = self.pending.chunks_exact(self.frame_len).map(<[f32]>::to_vec).collect();
+        self.pending.drain(..frames.len() * self.frame_len);
+        frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reblocks_any_buffer_size_into_whole_frames() {
+        let mut framer = Framer::new(FRAME_MS);
+        let frame_len = 960;
+        let mut next = 0.0;
+        let mut buffer = |len: usize| -> Vec<f32> {
+            (0..len)
+                .map(|_| {
+                    next += 1.0;
+                    next
+                })
+                .collect()
+        };
+
+        // Less than a frame waits; the rest of it completes one
+        assert!(framer.push(&buffer(882)).is_empty());
+        let frames = framer.push(&buffer(2048));
+        assert_eq!(frames.len(), 3);
+        assert!(frames.iter().all(|f| f.len() == frame_len));
+
+        // Nothing is lost or reordered across callbacks
+        assert_eq!(frames[0][0], 1.0);
+        assert_eq!(frames[2][frame_len - 1], (3 * frame_len) as f32);
+        let frames = framer.push(&buffer(frame_len * 10));
+        assert_eq!(frames.len(), 10);
+        assert_eq!(frames[0][0], (3 * frame_len + 1) as f32);
+    }
+}
@@ -0,0 +1,72 @@
+// Which phone answered Connect. Every phone keeps an Ed25519 key for good and
+// signs its codec ack with it (see protocol.rs). Like SSH's known_hosts, a saved
+// device remembers the key it answered with the first time, and a different key
+// later ends the session with a warning: either the phone was reset or BudBridge
+// reinstalled on it, or something else on the network is answering in its place.
+//
+// This proves who answered the hello. Audio packets themselves are neither signed
+// nor encrypted.
+
+use crate::protocol::{self, PublicKey};
+use sha2::{Digest, Sha256};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Verdict {
+    /// The key the device answered with before
+    Known,
+    /// Nothing remembered yet: this key is trusted from now on
+    FirstUse(PublicKey),
+    /// No key known and none offered: an app from before identities
+    Unsigned,
+    /// A different key than before
+    Changed(PublicKey),
+    /// No key from a device that used to have one
+    Missing,
+}
+
+impl Verdict {
+    /// Whether the session has to stop until the user decides
+    pub fn is_alarm(&self) -> bool {
+        matches!(self, Verdict::Changed(_) | Verdict::Missing)
+    }
+}
+
+pub fn check(known: Option<PublicKey>, offered: Option<PublicKey>) -> Verdict {
+    match (known, offered) {
+        (Some(known), Some(offered)) if known == offered => Verdict::Known,
+        (Some(_), Some(offered)) => Verdict::Changed(offered),
+        (Some(_), None) => Verdict::Missing,
+        (None, Some(offered)) => Verdict::FirstUse(offered),
+        (None, None) => Verdict::Unsigned,
+    }
+}
+
+/// For comparing keys by eye with what the phone shows, e.g. "65b6 0673 d6ed ..."
+pub fn fingerprint(key: &PublicKey) -> String {
+    let digest = Sha256::digest(key);
+    digest[..16].chunks(2).map(protocol::to_hex).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trusts_the_first_key_and_flags_any_other() {
+        let (a, b) = ([1u8; 32], [2u8; 32]);
+        assert_eq!(check(None, Some(a)), Verdict::FirstUse(a));
+        assert_eq!(check(Some(a), Some(a)), Verdict::Known);
+        assert_eq!(check(Some(a), Some(b)), Verdict::Changed(b));
+        assert_eq!(check(Some(a), None), Verdict::Missing);
+        assert_eq!(check(None, None), Verdict::Unsigned);
+        assert!(check(Some(a), Some(b)).is_alarm() && !check(None, Some(b)).is_alarm());
+    }
+
+    #[test]
+    fn fingerprint_matches_the_vector() {
+        let vectors = include_str!("../../../docs/protocol-vectors.txt");
+        let hex = |name: &str| vectors.lines().find_map(|l| l.strip_prefix(name)?.strip_prefix(' ')).unwrap();
+        let key: PublicKey = protocol::parse_hex(hex("identity.key")).unwrap();
+        assert_eq!(fingerprint(&key).replace(' ', ""), hex("identity.fingerprint"));
+    }
+}
@@ -0,0 +1,53 @@
+// The sending end of a BudBridge stream, for embedding the PC side in your own
+// tools; budbridge-client is the receiving end. The wire format (protocol.rs),
+// the codecs, phone identities and audio protection live here, shared with the
+// egui app, along with a session any program can run:
+//
+//   let capture = AudioCapture::start(None)?;  // 10 ms stereo frames at 48 kHz
+//   let link = UdpTransport::connect("192.168.1.20:4811", PC_PORT)?;
+//   let mut bridge = Bridge::new(link, Session::default(), pc_id)?;
+//   loop {
+//       for frame in capture.frames().try_iter() { bridge.send(&frame)?; }
+//       while let Some(event) = bridge.poll()? { /* Agreed, Audio, ... */ }
+//   }
+//
+// The app's network loop is a Bridge too, with its rate limit, access log and
+// clock probes plugged in through Hooks.
+//
+// Capture (AudioCapture and the backends, on cpal) is the default "capture"
+// feature; budbridge-client turns it off and uses only the wire format.
+
+#[cfg(feature = "capture")]
+pub mod backend;
+#[cfg(feature = "capture")]
+pub mod capture;
+pub mod cipher;
+pub mod codec;
+pub mod convert;
+pub mod downmix;
+pub mod fec;
+pub mod framer;
+pub mod identity;
+pub mod protocol;
+pub mod resample;
+pub mod seal;
+pub mod standby;
+
+#[cfg(feature = "capture")]
+mod audio;
+mod bridge;
+mod transport;
+mod udp;
+
+#[cfg(feature = "capture")]
+pub use audio::AudioCapture;
+#[cfg(feature = "capture")]
+pub use backend::{AudioBackend, CpalBackend, MockBackend};
+pub use bridge::{Bridge, Event, Hooks, NoHooks, HELLO_ATTEMPTS, HELLO_INTERVAL, HELLO_REFRESH_INTERVAL};
+pub use transport::{FramedStream, Transport, STREAM_PEER};
+pub use udp::UdpTransport;
+
+/// Where the PC takes acks and mic audio from the phone
+pub const PC_PORT: u16 = 4810;
+/// Where the phone takes hellos and audio
+pub const PHONE_PORT: u16 = 4811;
@@ -0,0 +1,996 @@
+// The wire format shared with the iPhone app, in one place: the codec handshake,
+// audio payloads, RED framing, link test probes and rendezvous/punch control
+// frames. Everything else builds or reads packets through here.
+// docs/protocol-vectors.txt holds golden vectors for each message; the tests
+// below, the budbridge-client crate (windows/client) and
+// AirpodPcAudioTests/ProtocolVectorsTests.swift all check against it, so no
+// side can drift without a failing test.
+//
+// Codec handshake. The PC offers a format per direction in a hello and the phone
+// acknowledges what it will actually use (it may fall back to PCM). Until the ack
+// arrives, and with older apps that never answer, both directions stay 16-bit PCM
+// mono:
+//
+//   PC -> phone   "BBHELLO1 down=opus/2/128 up=opus/1/32"
+//   phone -> PC   "BBHELLO1 ACK down=opus/2/128 up=opus/1/32"
+//
+// The hello also carries a fresh 16-byte nonce. The phone echoes it in the ack,
+// adds its Ed25519 identity key and signs everything before " sig=" (see
+// identity.rs), so the PC knows which phone answered:
+//
+//   "BBHELLO1 down=pcm/1/768 up=pcm/1/768 nonce=<32 hex>"
+//   "BBHELLO1 ACK down=pcm/1/768 up=pcm/1/768 nonce=<32 hex> id=<64 hex> sig=<128 hex>"
+//
+// The hello also names the PC: a random id made once per install ("pc=<32 hex>"),
+// which the phone echoes in its ack. With two PCs streaming to one phone, an ack
+// naming the other PC means the phone is listening there while its mic comes
+// here. Each ack is answered from the receive port with the id of the PC the
+// phone's mic audio actually reaches, so the phone can keep both directions
+// with the same PC and ignore the other one:
+//
+//   PC -> phone   "BBHELLO1 down=pcm/1/768 up=pcm/1/768 uprate=any nonce=<32 hex> pc=<32 hex>"
+//   phone -> PC   "BBHELLO1 ACK down=pcm/1/768 up=pcm/1/768 pc=<32 hex> nonce=<32 hex> id=<64 hex> sig=<128 hex>"
+//   PC -> phone   "BBPAIR1 <32 hex>"
+//
+// The hello also says the PC takes uncompressed audio up at any sample rate
+// ("uprate=any"). A phone whose mic runs at something other than 48 kHz (the
+// AirPods mic over HFP is 16 or 24 kHz) may then send it as is and say so in the
+// ack ("uprate=24000"), and the PC resamples. Without the field it's 48 kHz, and
+// Opus always decodes at 48 kHz whatever it was encoded from:
+//
+//   "BBHELLO1 ACK down=pcm/1/768 up=pcm/1/768 uprate=24000"
+//
+// For lossy links the hello can also ask for redundancy ("red=2"). Each Opus
+// packet then repeats up to that many earlier frames (RTP RED style):
+//
+//   [u16 LE sequence of the newest frame][u8 frame count]
+//   then for each frame, oldest first: [u16 LE length][Opus frame]
+//
+// Audio payloads. PCM is little-endian 16-bit ("pcm"), packed 24-bit ("pcm24")
+// or 32-bit float ("f32"), interleaved when stereo, and never wrapped in RED.
+// Opus is one 20 ms frame per datagram.
+//
+// To send fewer datagrams (the battery saver, see battery.rs) the hello can ask
+// for longer Opus frames going down ("ptime=40", 40 or 60 ms). A phone that
+// acks the same field decodes them; without it they stay 20 ms:
+//
+//   "BBHELLO1 down=opus/2/128 up=opus/1/32 ptime=40 uprate=any frame=1"
+//   "BBHELLO1 ACK down=opus/2/128 up=opus/1/32 ptime=40 frame=1"
+//
+// Every hello also offers framed audio ("frame=1", the header version). A phone
+// that acks it with the same field puts a 12-byte header in front of every
+// audio datagram, both ways, so each side can see loss and reordering. The
+// sequence counts datagrams from 0 and the timestamp is the send time in 48 kHz
+// ticks since the session started; both wrap. Without the field, audio stays
+// bare, as older apps send it:
+//
+//   [u8 0xBB][u8 version 1][u32 LE sequence][u32 LE timestamp][u16 LE payload length]
+//   then the payload
+//
+// For lossy Wi-Fi the hello can also ask for forward error correction
+// ("fec=4", after the frame field, as it needs the header). A phone that acks
+// the same field sends, and takes, a parity datagram after every that many
+// framed audio datagrams, both ways, from which any one lost datagram of the
+// group is rebuilt (see fec.rs):
+//
+//   "BBHELLO1 down=opus/2/128 up=opus/1/32 uprate=any frame=1 fec=4"
+//   "BBHELLO1 ACK down=opus/2/128 up=opus/1/32 frame=1 fec=4"
+//
+//   [u8 0xBB][u8 0xFE][u32 LE sequence of the first][u8 count][XOR of the datagrams]
+//
+// With audio protection on, the hello also offers a seal ("seal=aead") and an
+// X25519 key ("kx=<64 hex>"). A phone that takes it acks the same mode with its
+// own key, before the signature, and from then on audio datagrams both ways
+// are wrapped as seal.rs describes. Without the fields, audio stays in the clear.
+//
+// Test sound markers, on the audio port and never played. The PC sends one with
+// the first packet of its test chime; the phone answers with the loudest level
+// (whole dBFS) it handed to playback in the second after (see test_sound.rs):
+//
+//   PC -> phone   "BBTEST1 <id>"
+//   phone -> PC   "BBTEST1 PLAYED <id> peak=<dBFS>"
+//
+// Link test probes, echoed back unchanged by the phone:
+//
+//   "BBPROBE1" | stage u8 | seq u32 LE | sent_at_us u64 LE | padding
+//
+// During a session the PC also sends a clock probe every second (see clock.rs):
+// stage 255 and 16 zero bytes after the header, where the phone writes when it
+// got the probe and when it echoed it, in its own monotonic microseconds. Apps
+// from before that echo the zeros back, which still gives the round trip:
+//
+//   "BBPROBE1" | 255 | seq u32 LE | sent_at_us u64 LE | received_us u64 LE | replied_us u64 LE
+//
+// Classroom multicast (see multicast.rs). The PC sends its stream to a group
+// rather than one phone, with no handshake: once a second it announces the
+// format on the group, and phones that joined decode audio by the latest one
+// and drop what came before it. Nothing is sent back:
+//
+//   PC -> group   "BBCAST1 down=opus/1/64 red=1"
+//
+// Internet mode control frames, on the audio port and never played:
+//
+//   "BBPUNCH1"                        NAT punch and keepalive
+//   -> "BBRV1 REGISTER <code> pc"     to the rendezvous server
+//   <- "BBRV1 PEER <ip:port>"         from it, once the phone registered too
+//   -> "BBRV1 RELAY <code> pc"        when no audio came through the punch
+//   <- "BBRV1 RELAYING"               to both ends; audio then goes to the server
+
+use crate::convert;
+use crate::seal::{self, ExchangeKey};
+use anyhow::{anyhow, bail, Result};
+use ed25519_dalek::{Signature, VerifyingKey};
+use std::collections::VecDeque;
+use std::fmt;
+use std::net::SocketAddr;
+use std::ops::RangeInclusive;
+use std::time::Instant;
+
+pub const HELLO_MAGIC: &[u8; 8] = b"BBHELLO1";
+pub const MAX_REDUNDANCY: u8 = 3;
+/// Audio datagrams per parity datagram the hello may ask for
+pub const FEC_GROUPS: RangeInclusive<u8> = 2..=10;
+/// Opus bitrates libopus accepts
+pub const OPUS_KBPS: RangeInclusive<u16> = 6..=510;
+pub const NATIVE_RATE: u32 = 48_000;
+/// Opus frame lengths the hello may ask for going down, in ms; 20 is the default
+pub const PTIMES: [u32; 3] = [20, 40, 60];
+const UP_RATES: std::ops::RangeInclusive<u32> = 8_000..=48_000;
+// Redundant frames are left out rather than exceed this (fits a VPN tunnel)
+const RED_MAX_PACKET: usize = 1200;
+const PROBE_MAGIC: &[u8; 8] = b"BBPROBE1";
+pub const PROBE_HEADER_SIZE: usize = 8 + 1 + 4 + 8;
+pub const CLOCK_STAGE: u8 = 0xFF;
+pub const CLOCK_PROBE_SIZE: usize = PROBE_HEADER_SIZE + 16;
+pub const PUNCH_MAGIC: &[u8; 8] = b"BBPUNCH1";
+const TEST_PREFIX: &str = "BBTEST1 ";
+const PAIR_PREFIX: &str = "BBPAIR1 ";
+const RENDEZVOUS_PREFIX: &str = "BBRV1 ";
+const CAST_PREFIX: &str = "BBCAST1 ";
+// Not ASCII 'B', so a framed datagram can't be taken for a control frame
+const FRAME_MAGIC: u8 = 0xBB;
+const FRAME_VERSION: u8 = 1;
+pub const FRAME_HEADER_SIZE: usize = 12;
+
+/// An Ed25519 public key
+pub type PublicKey = [u8; 32];
+pub type Nonce = [u8; 16];
+/// This install of the PC app, as named in hellos
+pub type PcId = [u8; 16];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Codec {
+    Pcm,
+    Pcm24,
+    Float,
+    Opus,
+}
+
+impl Codec {
+    /// Bytes per sample of the uncompressed codecs
+    fn sample_bytes(&self) -> Option<usize> {
+        match self {
+            Codec::Pcm => Some(2),
+            Codec::Pcm24 => Some(3),
+            Codec::Float => Some(4),
+            Codec::Opus => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StreamFormat {
+    pub codec: Codec,
+    pub channels: u8,
+    pub bitrate_kbps: u16,
+}
+
+impl StreamFormat {
+    /// What every version of the app speaks
+    pub const PCM_MONO: Self = Self { codec: Codec::Pcm, channels: 1, bitrate_kbps: 768 };
+
+    /// Choices offered for PC → phone
+    pub const DOWN_CHOICES: [Self; 6] = [
+        Self::PCM_MONO,
+        Self { codec: Codec::Pcm24, channels: 1, bitrate_kbps: 1152 },
+        Self { codec: Codec::Float, channels: 1, bitrate_kbps: 1536 },
+        Self { codec: Codec::Opus, channels: 2, bitrate_kbps: 256 },
+        Self { codec: Codec::Opus, channels: 2, bitrate_kbps: 128 },
+        Self { codec: Codec::Opus, channels: 1, bitrate_kbps: 64 },
+    ];
+
+    /// Choices offered for phone → PC
+    pub const UP_CHOICES: [Self; 4] = [
+        Self::PCM_MONO,
+        Self { codec: Codec::Opus, channels: 1, bitrate_kbps: 64 },
+        Self { codec: Codec::Opus, channels: 1, bitrate_kbps: 32 },
+        Self { codec: Codec::Opus, channels: 1, bitrate_kbps: 16 },
+    ];
+
+    /// Parse the "opus/2/128" form used in settings, profiles and the handshake
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut parts = text.trim().split('/');
+        let codec = match parts.next()? {
+            "pcm" => Codec::Pcm,
+            "pcm24" => Codec::Pcm24,
+            "f32" => Codec::Float,
+            "opus" => Codec::Opus,
+            _ => return None,
+        };
+        let channels: u8 = parts.next()?.parse().ok().filter(|c| (1..=2).contains(c))?;
+        let bitrate_kbps: u16 = parts.next()?.parse().ok()?;
+        match codec.sample_bytes() {
+            // Raw PCM only exists at its natural rate
+            Some(bytes) => Some(Self { codec, channels, bitrate_kbps: 384 * (bytes * channels as usize) as u16 }),
+            None if OPUS_KBPS.contains(&bitrate_kbps) => Some(Self { codec, channels, bitrate_kbps }),
+            None => None,
+        }
+    }
+
+    /// Bytes of one sample frame (every channel), for uncompressed codecs
+    pub fn frame_bytes(&self) -> Option<usize> {
+        self.codec.sample_bytes().map(|bytes| bytes * self.channels as usize)
+    }
+
+    /// What the stream takes on the network, in kbps: the audio, the redundant
+    /// Opus frames, and 28 bytes of IP and UDP header per datagram
+    pub fn network_kbps(&self, redundancy: u8, max_payload: usize) -> u32 {
+        let (audio, packets_per_second) = match self.codec {
+            Codec::Opus => (self.bitrate_kbps as u32 * (1 + redundancy as u32), 50),
+            _ => {
+                let bytes_per_second = self.bitrate_kbps as usize * 1000 / 8;
+                (self.bitrate_kbps as u32, bytes_per_second.div_ceil(max_payload) as u32)
+            }
+        };
+        audio + packets_per_second * 28 * 8 / 1000
+    }
+
+    pub fn label(&self) -> String {
+        let channels = if self.channels == 2 { "stereo" } else { "mono" };
+        match self.codec {
+            Codec::Pcm => format!("PCM {} (uncompressed, {} kbps)", channels, self.bitrate_kbps),
+            Codec::Pcm24 => format!("PCM 24-bit {} ({} kbps)", channels, self.bitrate_kbps),
+            Codec::Float => format!("32-bit float {} ({} kbps)", channels, self.bitrate_kbps),
+            Codec::Opus => format!("Opus {} {} kbps", channels, self.bitrate_kbps),
+        }
+    }
+}
+
+impl fmt::Display for StreamFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let codec = match self.codec {
+            Codec::Pcm => "pcm",
+            Codec::Pcm24 => "pcm24",
+            Codec::Float => "f32",
+            Codec::Opus => "opus",
+        };
+        write!(f, "{}/{}/{}", codec, self.channels, self.bitrate_kbps)
+    }
+}
+
+/// The pair a profile configures and the handshake agrees on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Formats {
+    pub down: StreamFormat, // PC → phone
+    pub up: StreamFormat,   // phone → PC
+}
+
+impl Formats {
+    /// What an unanswered hello leaves both sides on
+    pub const LEGACY: Self = Self { down: StreamFormat::PCM_MONO, up: StreamFormat::PCM_MONO };
+}
+
+impl Default for Formats {
+    fn default() -> Self {
+        Self::LEGACY
+    }
+}
+
+/// Everything the hello offers and the ack confirms
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Session {
+    pub formats: Formats,
+    pub redundancy: u8, // earlier frames repeated in each Opus packet
+    /// What the phone's uncompressed audio is sampled at, if not 48 kHz
+    pub up_rate: Option<u32>,
+    /// Audio datagrams carry a frame header both ways
+    pub framed: bool,
+    /// Audio datagrams are sealed both ways
+    pub seal: seal::Mode,
+    /// Length of the Opus frames going down, if not 20 ms
+    pub ptime: Option<u32>,
+    /// Audio datagrams per parity datagram both ways, 0 for none
+    pub fec: u8,
+}
+
+impl Session {
+    /// The sample rate phone → PC audio arrives at before any resampling
+    pub fn up_sample_rate(&self) -> u32 {
+        match self.formats.up.codec {
+            Codec::Opus => NATIVE_RATE,
+            _ => self.up_rate.unwrap_or(NATIVE_RATE),
+        }
+    }
+
+    /// Milliseconds of audio in each Opus datagram going down
+    pub fn down_ptime(&self) -> u32 {
+        self.ptime.unwrap_or(PTIMES[0])
+    }
+
+    /// Audio datagrams per parity datagram, once FEC is agreed; it needs the frame header
+    pub fn fec_group(&self) -> Option<u8> {
+        (self.framed && self.fec > 0).then_some(self.fec)
+    }
+
+    /// What the session takes on the network (down, up) in kbps, as
+    /// StreamFormat::network_kbps gives it but with fewer headers for longer
+    /// frames, and a parity datagram as big as the audio's for every `fec`
+    pub fn network_kbps(&self, max_payload: usize) -> (u32, u32) {
+        let headers = |ptime: u32| 1000 / ptime * 28 * 8 / 1000;
+        let mut down = self.formats.down.network_kbps(self.redundancy, max_payload);
+        if self.formats.down.codec == Codec::Opus {
+            down = down - headers(PTIMES[0]) + headers(self.down_ptime());
+        }
+        let up = self.formats.up.network_kbps(self.redundancy, max_payload);
+        let parity = |kbps: u32| if self.fec > 0 { kbps.div_ceil(self.fec as u32) } else { 0 };
+        (down + parity(down), up + parity(up))
+    }
+}
+
+/// `kx` is this session's exchange key, offered when `session.seal` is on
+pub fn hello(session: Session, nonce: Option<&Nonce>, pc: Option<&PcId>, kx: Option<&ExchangeKey>) -> String {
+    let mut text = format!("BBHELLO1 down={} up={}", session.formats.down, session.formats.up);
+    // Left out when off, which is also what phones without RED support assume
+    if session.redundancy > 0 {
+        text.push_str(&format!(" red={}", session.redundancy));
+    }
+    if let Some(ptime) = session.ptime {
+        text.push_str(&format!(" ptime={}", ptime));
+    }
+    text.push_str(&format!(" uprate=any frame={}", FRAME_VERSION));
+    if session.fec > 0 {
+        text.push_str(&format!(" fec={}", session.fec));
+    }
+    if let Some(kx) = kx.filter(|_| session.seal != seal::Mode::Off) {
+        text.push_str(&format!(" seal={} kx={}", session.seal.as_str(), to_hex(kx)));
+    }
+    if let Some(nonce) = nonce {
+        text.push_str(&format!(" nonce={}", to_hex(nonce)));
+    }
+    if let Some(pc) = pc {
+        text.push_str(&format!(" pc={}", to_hex(pc)));
+    }
+    text
+}
+
+pub fn is_hello(data: &[u8]) -> bool {
+    data.starts_with(HELLO_MAGIC)
+}
+
+/// The PC a hello or ack names; None from PCs and apps from before PC ids
+pub fn pc_id(data: &[u8]) -> Option<PcId> {
+    let text = std::str::from_utf8(data).ok()?;
+    text.split_whitespace().find_map(|field| parse_hex(field.strip_prefix("pc=")?))
+}
+
+/// The answer to an ack: which PC the phone's mic audio reaches
+pub fn pair(pc: &PcId) -> String {
+    format!("{}{}", PAIR_PREFIX, to_hex(pc))
+}
+
+/// What the phone's acknowledgement agreed to
+pub fn parse_ack(data: &[u8]) -> Option<Session> {
+    let text = std::str::from_utf8(data).ok()?.strip_prefix("BBHELLO1 ACK ")?;
+    let mut down = None;
+    let mut up = None;
+    let mut redundancy = 0;
+    let mut up_rate = None;
+    let mut framed = false;
+    let mut seal = seal::Mode::Off;
+    let mut ptime = None;
+    let mut fec = 0;
+    for field in text.split_whitespace() {
+        match field.split_once('=') {
+            Some(("down", value)) => down = StreamFormat::parse(value),
+            Some(("up", value)) => up = StreamFormat::parse(value),
+            Some(("red", value)) => redundancy = value.parse().unwrap_or(0).min(MAX_REDUNDANCY),
+            Some(("uprate", value)) => up_rate = value.parse().ok().filter(|r| UP_RATES.contains(r) && *r != NATIVE_RATE),
+            Some(("frame", value)) => framed = value.parse() == Ok(FRAME_VERSION),
+            Some(("seal", value)) => seal = seal::Mode::parse(value),
+            Some(("ptime", value)) => ptime = value.parse().ok().filter(|ms| PTIMES[1..].contains(ms)),
+            Some(("fec", value)) => fec = value.parse().ok().filter(|n| FEC_GROUPS.contains(n)).unwrap_or(0),
+            _ => {}
+        }
+    }
+    Some(Session { formats: Formats { down: down?, up: up? }, redundancy, up_rate, framed, seal, ptime, fec })
+}
+
+/// The exchange key a hello or ack offers for sealing audio
+pub fn exchange_key(data: &[u8]) -> Option<ExchangeKey> {
+    let text = std::str::from_utf8(data).ok()?;
+    text.split_whitespace().find_map(|field| parse_hex(field.strip_prefix("kx=")?))
+}
+
+/// The phone's identity key from a signed ack, once the signature and the echoed
+/// nonce check out. None when the ack isn't signed (apps from before identities).
+pub fn ack_identity(data: &[u8], nonce: &Nonce) -> Result<Option<PublicKey>> {
+    let text = std::str::from_utf8(data)?;
+    let Some((signed, signature)) = text.rsplit_once(" sig=") else {
+        return Ok(None);
+    };
+    let field = |name: &str| signed.split_whitespace().find_map(|f| f.strip_prefix(name)?.strip_prefix('='));
+    let key: PublicKey = field("id").and_then(parse_hex).ok_or_else(|| anyhow!("the ack has no identity key"))?;
+    if field("nonce") != Some(to_hex(nonce).as_str()) {
+        bail!("the ack answers a different hello");
+    }
+    let signature: [u8; 64] = parse_hex(signature.trim()).ok_or_else(|| anyhow!("the ack's signature is malformed"))?;
+    VerifyingKey::from_bytes(&key)?
+        .verify_strict(signed.as_bytes(), &Signature::from_bytes(&signature))
+        .map_err(|_| anyhow!("the ack's signature doesn't match its identity key"))?;
+    Ok(Some(key))
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn parse_hex<const N: usize>(text: &str) -> Option<[u8; N]> {
+    if text.len() != 2 * N || !text.is_ascii() {
+        return None;
+    }
+    let mut bytes = [0u8; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+/// PCM payload bytes for float samples (Opus has its own encoder)
+pub fn pack_samples(codec: Codec, samples: &[f32]) -> Vec<u8> {
+    match codec {
+        Codec::Pcm24 => samples
+            .iter()
+            .flat_map(|&s| {
+                let [low, middle, high, _] = convert::to_i24(s).to_le_bytes();
+                [low, middle, high]
+            })
+            .collect(),
+        Codec::Float => samples.iter().flat_map(|s| s.to_le_bytes()).collect(),
+        Codec::Pcm | Codec::Opus => samples.iter().flat_map(|&s| convert::to_i16(s).to_le_bytes()).collect(),
+    }
+}
+
+/// 16-bit samples from a PCM payload, for playback
+pub fn unpack_samples(codec: Codec, payload: &[u8]) -> Vec<i16> {
+    match codec {
+        Codec::Pcm24 => payload
+            .chunks_exact(3)
+            .map(|chunk| {
+                let sample = i32::from_le_bytes([0, chunk[0], chunk[1], chunk[2]]) >> 8;
+                convert::to_i16(convert::from_i24(sample))
+            })
+            .collect(),
+        Codec::Float => payload
+            .chunks_exact(4)
+            .map(|chunk| convert::to_i16(f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])))
+            .collect(),
+        Codec::Pcm | Codec::Opus => payload
+            .chunks_exact(2)
+            .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]))
+            .collect(),
+    }
+}
+
+/// What a framed datagram says about itself
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameHeader {
+    pub seq: u32,
+    pub timestamp: u32,
+}
+
+pub fn frame(header: FrameHeader, payload: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(FRAME_HEADER_SIZE + payload.len());
+    packet.extend_from_slice(&[FRAME_MAGIC, FRAME_VERSION]);
+    packet.extend_from_slice(&header.seq.to_le_bytes());
+    packet.extend_from_slice(&header.timestamp.to_le_bytes());
+    packet.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+    packet.extend_from_slice(payload);
+    packet
+}
+
+/// The header and payload of a framed datagram; None for a bare one, or one
+/// whose length doesn't match
+pub fn unframe(data: &[u8]) -> Option<(FrameHeader, &[u8])> {
+    let (header, payload) = data.split_first_chunk::<FRAME_HEADER_SIZE>()?;
+    if header[..2] != [FRAME_MAGIC, FRAME_VERSION] || u16::from_le_bytes([header[10], header[11]]) as usize != payload.len() {
+        return None;
+    }
+    let seq = u32::from_le_bytes([header[2], header[3], header[4], header[5]]);
+    let timestamp = u32::from_le_bytes([header[6], header[7], header[8], header[9]]);
+    Some((FrameHeader { seq, timestamp }, payload))
+}
+
+/// Numbers outgoing audio datagrams and stamps them with the send time
+pub struct FrameWriter {
+    next: u32,
+    started: Instant,
+}
+
+impl FrameWriter {
+    pub fn new(now: Instant) -> Self {
+        Self { next: 0, started: now }
+    }
+
+    pub fn wrap(&mut self, payload: &[u8], now: Instant) -> Vec<u8> {
+        let ticks = now.duration_since(self.started).as_secs_f64() * NATIVE_RATE as f64;
+        let header = FrameHeader { seq: self.next, timestamp: ticks as u64 as u32 };
+        self.next = self.next.wrapping_add(1);
+        frame(header, payload)
+    }
+}
+
+/// Wraps each new frame together with the ones before it
+pub struct RedPacker {
+    depth: usize,
+    history: VecDeque<Vec<u8>>, // newest last
+    sequence: u16,
+}
+
+impl RedPacker {
+    pub fn new(depth: u8) -> Self {
+        Self { depth: depth.min(MAX_REDUNDANCY) as usize, history: VecDeque::new(), sequence: 0 }
+    }
+
+    pub fn pack(&mut self, frame: Vec<u8>) -> Vec<u8> {
+        self.history.push_back(frame);
+        while self.history.len() > self.depth + 1 {
+            self.history.pop_front();
+        }
+        self.sequence = self.sequence.wrapping_add(1);
+
+        // Newest first until the packet is full, then written oldest first
+        let mut count = 0;
+        let mut size = 3;
+        for frame in self.history.iter().rev() {
+            if count > 0 && size + 2 + frame.len() > RED_MAX_PACKET {
+                break;
+            }
+            size += 2 + frame.len();
+            count += 1;
+        }
+        let mut packet = Vec::with_capacity(size);
+        packet.extend_from_slice(&self.sequence.to_le_bytes());
+        packet.push(count as u8);
+        for frame in self.history.iter().skip(self.history.len() - count) {
+            packet.extend_from_slice(&(frame.len() as u16).to_le_bytes());
+            packet.extend_from_slice(frame);
+        }
+        packet
+    }
+}
+
+/// Picks the frames in a packet that haven't been played yet
+#[derive(Default)]
+pub struct RedUnpacker {
+    last: Option<u16>,
+    recovered: u64,
+}
+
+impl RedUnpacker {
+    /// Frames restored from redundant copies after their own packet was lost
+    pub fn recovered(&self) -> u64 {
+        self.recovered
+    }
+
+    pub fn unpack<'a>(&mut self, packet: &'a [u8]) -> Vec<&'a [u8]> {
+        let Some((header, mut rest)) = packet.split_first_chunk::<3>() else {
+            return Vec::new();
+        };
+        let newest = u16::from_le_bytes([header[0], header[1]]);
+        let count = header[2] as usize;
+        let mut frames = Vec::with_capacity(count);
+        for _ in 0..count {
+            let Some((len, tail)) = rest.split_first_chunk::<2>() else {
+                return Vec::new();
+            };
+            let len = u16::from_le_bytes(*len) as usize;
+            if tail.len() < len {
+                return Vec::new();
+            }
+            frames.push(&tail[..len]);
+            rest = &tail[len..];
+        }
+
+        let Some(last) = self.last else {
+            // Nothing to recover yet; start with the newest frame
+            self.last = Some(newest);
+            return frames.pop().into_iter().collect();
+        };
+        // Ahead of the last played frame by 1..=32767, counting with wraparound
+        let unplayed = |age: usize| (newest.wrapping_sub(age as u16).wrapping_sub(last) as i16) > 0;
+        let fresh: Vec<&[u8]> = frames
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| unplayed(count - 1 - i))
+            .map(|(_, frame)| *frame)
+            .collect();
+        if !fresh.is_empty() {
+            self.recovered += fresh.len() as u64 - 1;
+            self.last = Some(newest);
+        }
+        fresh
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Probe {
+    pub stage: u8,
+    pub seq: u32,
+    pub sent_at_us: u64,
+}
+
+impl Probe {
+    /// Fill in the header at the start of `packet` (at least PROBE_HEADER_SIZE long)
+    pub fn write(&self, packet: &mut [u8]) {
+        packet[..8].copy_from_slice(PROBE_MAGIC);
+        packet[8] = self.stage;
+        packet[9..13].copy_from_slice(&self.seq.to_le_bytes());
+        packet[13..PROBE_HEADER_SIZE].copy_from_slice(&self.sent_at_us.to_le_bytes());
+    }
+
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < PROBE_HEADER_SIZE || !data.starts_with(PROBE_MAGIC) {
+            return None;
+        }
+        Some(Self {
+            stage: data[8],
+            seq: u32::from_le_bytes(data[9..13].try_into().ok()?),
+            sent_at_us: u64::from_le_bytes(data[13..PROBE_HEADER_SIZE].try_into().ok()?),
+        })
+    }
+}
+
+/// The phone's receive and reply times from an echoed clock probe; None when
+/// the app left them at zero
+pub fn clock_stamps(packet: &[u8]) -> Option<(u64, u64)> {
+    let stamps = packet.get(PROBE_HEADER_SIZE..CLOCK_PROBE_SIZE)?;
+    let received = u64::from_le_bytes(stamps[..8].try_into().ok()?);
+    let replied = u64::from_le_bytes(stamps[8..].try_into().ok()?);
+    (received != 0 || replied != 0).then_some((received, replied))
+}
+
+/// What the PC sends the rendezvous server to join session `code`
+pub fn register(code: &str) -> String {
+    format!("{}REGISTER {} pc", RENDEZVOUS_PREFIX, code)
+}
+
+/// The phone's public address, from the rendezvous server's answer
+pub fn parse_peer(data: &[u8]) -> Option<SocketAddr> {
+    let text = std::str::from_utf8(data).ok()?;
+    text.strip_prefix(RENDEZVOUS_PREFIX)?.strip_prefix("PEER ")?.trim().parse().ok()
+}
+
+/// Asks the rendezvous server to pass audio between us and the phone
+pub fn relay(code: &str) -> String {
+    format!("{}RELAY {} pc", RENDEZVOUS_PREFIX, code)
+}
+
+/// The rendezvous server's word that it relays from now on
+pub fn is_relaying(data: &[u8]) -> bool {
+    data.strip_prefix(RENDEZVOUS_PREFIX.as_bytes()).is_some_and(|rest| rest.trim_ascii_end() == b"RELAYING")
+}
+
+pub fn test_marker(id: u32) -> String {
+    format!("{}{}", TEST_PREFIX, id)
+}
+
+/// The marker id and peak level (dBFS) from the phone's test sound report
+pub fn parse_test_played(data: &[u8]) -> Option<(u32, i32)> {
+    let text = std::str::from_utf8(data).ok()?.strip_prefix(TEST_PREFIX)?.strip_prefix("PLAYED ")?;
+    let (id, peak) = text.split_once(' ')?;
+    Some((id.parse().ok()?, peak.strip_prefix("peak=")?.trim().parse().ok()?))
+}
+
+/// The classroom multicast announcement of what the group's audio is
+pub fn cast_announce(format: StreamFormat, redundancy: u8) -> String {
+    let mut text = format!("{}down={}", CAST_PREFIX, format);
+    if redundancy > 0 && format.codec == Codec::Opus {
+        text.push_str(&format!(" red={}", redundancy));
+    }
+    text
+}
+
+/// The format and redundancy a classroom announcement names. Only phones read
+/// these; the PC has it to check the vectors.
+#[cfg(test)]
+pub fn parse_cast(data: &[u8]) -> Option<(StreamFormat, u8)> {
+    let text = std::str::from_utf8(data).ok()?.strip_prefix(CAST_PREFIX)?;
+    let mut format = None;
+    let mut redundancy = 0;
+    for field in text.split_whitespace() {
+        match field.split_once('=') {
+            Some(("down", value)) => format = StreamFormat::parse(value),
+            Some(("red", value)) => redundancy = value.parse().unwrap_or(0).min(MAX_REDUNDANCY),
+            _ => {}
+        }
+    }
+    Some((format?, redundancy))
+}
+
+/// Rendezvous, punch and test sound traffic that shares the audio port
+pub fn is_control(data: &[u8]) -> bool {
+    data.starts_with(PUNCH_MAGIC)
+        || data.starts_with(RENDEZVOUS_PREFIX.as_bytes())
+        || data.starts_with(TEST_PREFIX.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VECTORS: &str = include_str!("../../../docs/protocol-vectors.txt");
+
+    fn vector(name: &str) -> Vec<u8> {
+        let hex = VECTORS
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+            .unwrap_or_else(|| panic!("no vector named {}", name));
+        (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+    }
+
+    fn format(text: &str) -> StreamFormat {
+        StreamFormat::parse(text).unwrap()
+    }
+
+    #[test]
+    fn formats_round_trip_through_text() {
+        for format in StreamFormat::DOWN_CHOICES.iter().chain(&StreamFormat::UP_CHOICES) {
+            assert_eq!(StreamFormat::parse(&format.to_string()), Some(*format));
+        }
+        assert_eq!(StreamFormat::parse("opus/3/128"), None);
+        assert_eq!(StreamFormat::parse("opus/2/9999"), None);
+        assert_eq!(StreamFormat::parse("flac/2/900"), None);
+    }
+
+    #[test]
+    fn hello_matches_the_vectors() {
+        let opus = Session { formats: Formats { down: format("opus/2/128"), up: format("opus/1/32") }, ..Default::default() };
+        assert_eq!(hello(opus, None, None, None).as_bytes(), vector("hello.opus"));
+        let redundant = Session {
+            formats: Formats { down: format("pcm24/1/0"), up: format("opus/1/16") },
+            redundancy: 2,
+            ..Default::default()
+        };
+        assert_eq!(hello(redundant, None, None, None).as_bytes(), vector("hello.redundant"));
+        let longer = Session { ptime: Some(40), ..opus };
+        assert_eq!(hello(longer, None, None, None).as_bytes(), vector("hello.ptime"));
+        let corrected = Session { fec: 4, ..opus };
+        assert_eq!(hello(corrected, None, None, None).as_bytes(), vector("hello.fec"));
+        assert!(is_hello(&vector("hello.opus")) && is_hello(&vector("ack.opus_down")));
+    }
+
+    #[test]
+    fn the_client_crate_acks_what_the_hello_offers() {
+        let session = Session {
+            formats: Formats { down: format("opus/2/128"), up: format("opus/1/32") },
+            redundancy: 2,
+            up_rate: None,
+            framed: true,
+            seal: seal::Mode::Off,
+            ptime: None,
+            fec: 0,
+        };
+        let pc = [0x10; 16];
+        let offered = budbridge_client::Hello::parse(hello(session, Some(&[0; 16]), Some(&pc), None).as_bytes()).unwrap();
+        let ack = offered.ack();
+        assert_eq!(parse_ack(ack.as_bytes()), Some(session));
+        assert_eq!(pc_id(ack.as_bytes()), Some(pc));
+        assert_eq!(ack_identity(ack.as_bytes(), &[0; 16]).unwrap(), None);
+    }
+
+    #[test]
+    fn ack_matches_the_vectors() {
+        let session = parse_ack(&vector("ack.opus_down")).unwrap();
+        assert_eq!(session.formats.down, StreamFormat { codec: Codec::Opus, channels: 2, bitrate_kbps: 128 });
+        assert_eq!(session.formats.up, StreamFormat::PCM_MONO);
+        assert_eq!(session.redundancy, 0);
+
+        let session = parse_ack(&vector("ack.redundant")).unwrap();
+        assert_eq!(session.formats, Formats { down: format("f32/1/0"), up: format("opus/1/32") });
+        assert_eq!(session.redundancy, 2);
+
+        // The phone's mic rate, when it sends uncompressed audio unresampled
+        let session = parse_ack(&vector("ack.up_rate")).unwrap();
+        assert_eq!((session.up_rate, session.up_sample_rate()), (Some(24_000), 24_000));
+        assert_eq!(parse_ack(&vector("ack.opus_down")).unwrap().up_sample_rate(), NATIVE_RATE);
+
+        // Framed audio only once the phone says so
+        let session = parse_ack(&vector("ack.framed")).unwrap();
+        assert_eq!((session.framed, session.redundancy), (true, 1));
+        assert!(!parse_ack(&vector("ack.opus_down")).unwrap().framed);
+        assert!(!parse_ack(b"BBHELLO1 ACK down=pcm/1/768 up=pcm/1/768 frame=2").unwrap().framed);
+
+        // Longer Opus frames down only once the phone says so, and only lengths Opus has
+        let session = parse_ack(&vector("ack.ptime")).unwrap();
+        assert_eq!((session.ptime, session.down_ptime()), (Some(40), 40));
+        assert_eq!(parse_ack(&vector("ack.framed")).unwrap().down_ptime(), 20);
+        assert_eq!(parse_ack(b"BBHELLO1 ACK down=pcm/1/768 up=pcm/1/768 ptime=50").unwrap().ptime, None);
+
+        // Parity needs the frame header, and a group size from the range
+        let session = parse_ack(&vector("ack.fec")).unwrap();
+        assert_eq!((session.fec, session.fec_group()), (4, Some(4)));
+        assert_eq!(parse_ack(b"BBHELLO1 ACK down=pcm/1/768 up=pcm/1/768 fec=4").unwrap().fec_group(), None);
+        assert_eq!(parse_ack(b"BBHELLO1 ACK down=pcm/1/768 up=pcm/1/768 frame=1 fec=40").unwrap().fec, 0);
+        assert_eq!(parse_ack(&vector("ack.framed")).unwrap().fec_group(), None);
+
+        // A hello is not an ack
+        assert!(parse_ack(&vector("hello.opus")).is_none());
+    }
+
+    #[test]
+    fn signed_ack_matches_the_vectors() {
+        let nonce: Nonce = std::array::from_fn(|i| i as u8);
+        assert_eq!(hello(Session::default(), Some(&nonce), None, None).as_bytes(), vector("hello.nonce"));
+
+        let ack = vector("ack.signed");
+        assert_eq!(parse_ack(&ack), Some(Session::default()));
+        let key: PublicKey = vector("identity.key").try_into().unwrap();
+        assert_eq!(ack_identity(&ack, &nonce).unwrap(), Some(key));
+
+        // An answer to another hello, or with anything changed, is refused
+        assert!(ack_identity(&ack, &[9; 16]).is_err());
+        let mut tampered = ack.clone();
+        let at = ack.windows(3).position(|w| w == b"768").unwrap();
+        tampered[at] = b'6';
+        assert!(ack_identity(&tampered, &nonce).is_err());
+
+        // Apps from before identities don't sign
+        assert_eq!(ack_identity(&vector("ack.opus_down"), &nonce).unwrap(), None);
+    }
+
+    #[test]
+    fn sealed_audio_matches_the_vectors() {
+        let pc = seal::KeyExchange::from_secret(vector("seal.pc_secret").try_into().unwrap());
+        let phone = seal::KeyExchange::from_secret(vector("seal.phone_secret").try_into().unwrap());
+        let nonce: Nonce = std::array::from_fn(|i| i as u8);
+        let session = Session { seal: seal::Mode::Aead, ..Default::default() };
+        assert_eq!(hello(session, Some(&nonce), None, Some(pc.public())).as_bytes(), vector("hello.seal"));
+        // Offered only with protection on
+        assert_eq!(hello(Session::default(), Some(&nonce), None, Some(pc.public())).as_bytes(), vector("hello.nonce"));
+
+        let ack = vector("ack.seal");
+        assert_eq!(parse_ack(&ack), Some(Session { framed: true, ..session }));
+        assert_eq!(exchange_key(&ack), Some(*phone.public()));
+        assert_eq!(parse_ack(&vector("ack.framed")).unwrap().seal, seal::Mode::Off);
+
+        for (mode, down, up) in [(seal::Mode::Mac, "seal.mac", "seal.up.mac"), (seal::Mode::Aead, "seal.aead", "seal.up.aead")] {
+            let (mut sealer, mut opener) = pc.session(mode, phone.public(), &nonce).unwrap();
+            assert_eq!(sealer.seal(&[0xAA, 0xBB]), vector(down));
+            assert_eq!(opener.open(&mut vector(up)), Some(&[0xCC, 0xDD][..]));
+        }
+    }
+
+    #[test]
+    fn pc_ids_match_the_vectors() {
+        let nonce: Nonce = std::array::from_fn(|i| i as u8);
+        let pc: PcId = std::array::from_fn(|i| 0x10 + i as u8);
+        assert_eq!(hello(Session::default(), Some(&nonce), Some(&pc), None).as_bytes(), vector("hello.pc"));
+        assert_eq!(pc_id(&vector("hello.pc")), Some(pc));
+        assert_eq!(pc_id(&vector("ack.pc")), Some(pc));
+        assert_eq!(parse_ack(&vector("ack.pc")), Some(Session::default()));
+        assert_eq!(pair(&pc).as_bytes(), vector("pair"));
+
+        // Older PCs and apps leave it out
+        assert_eq!(pc_id(&vector("hello.nonce")), None);
+        assert_eq!(pc_id(&vector("ack.signed")), None);
+    }
+
+    #[test]
+    fn test_sound_markers_match_the_vectors() {
+        assert_eq!(test_marker(7).as_bytes(), vector("test.marker"));
+        assert_eq!(parse_test_played(&vector("test.played")), Some((7, -12)));
+        assert!(is_control(&vector("test.marker")) && is_control(&vector("test.played")));
+        assert_eq!(parse_test_played(&vector("test.marker")), None);
+    }
+
+    #[test]
+    fn red_framing_matches_the_vectors() {
+        let mut packer = RedPacker::new(2);
+        assert_eq!(packer.pack(vec![0xAA, 0xBB]), vector("red.first"));
+        let mut packer = RedPacker::new(2);
+        packer.pack(vec![0xA1]);
+        packer.pack(vec![0xB1, 0xB2]);
+        assert_eq!(packer.pack(vec![0xC1, 0xC2, 0xC3]), vector("red.third"));
+
+        // Fresh receivers start from the newest frame; then packet 1 is followed by 3
+        let third = vector("red.third");
+        assert_eq!(RedUnpacker::default().unpack(&third), vec![&[0xC1, 0xC2, 0xC3][..]]);
+        let mut unpacker = RedUnpacker::default();
+        unpacker.unpack(&vector("red.first"));
+        assert_eq!(unpacker.unpack(&third), vec![&[0xB1, 0xB2][..], &[0xC1, 0xC2, 0xC3][..]]);
+        assert_eq!(unpacker.recovered(), 1);
+    }
+
+    #[test]
+    fn samples_match_the_vectors() {
+        assert_eq!(pack_samples(Codec::Pcm, &[0.5, -0.5]), vector("samples.pcm"));
+        assert_eq!(pack_samples(Codec::Pcm24, &[0.5, -0.5]), vector("samples.pcm24"));
+        assert_eq!(pack_samples(Codec::Float, &[0.5, -0.25]), vector("samples.f32"));
+        for (codec, name) in [(Codec::Pcm, "samples.pcm"), (Codec::Pcm24, "samples.pcm24")] {
+            assert_eq!(unpack_samples(codec, &vector(name)), vec![16384, -16384]);
+        }
+        assert_eq!(unpack_samples(Codec::Float, &vector("samples.f32")), vec![16384, -8192]);
+    }
+
+    #[test]
+    fn audio_frames_match_the_vectors() {
+        let header = FrameHeader { seq: 0x0102_0304, timestamp: 0x0506_0708 };
+        let packet = vector("frame.audio");
+        assert_eq!(frame(header, &[0xAA, 0xBB]), packet);
+        assert_eq!(unframe(&packet), Some((header, &[0xAA, 0xBB][..])));
+
+        // Bare audio, a cut-off datagram and control frames aren't framed
+        assert_eq!(unframe(&vector("samples.pcm")), None);
+        assert_eq!(unframe(&packet[..packet.len() - 1]), None);
+        assert_eq!(unframe(&vector("punch")), None);
+
+        let start = Instant::now();
+        let mut writer = FrameWriter::new(start);
+        writer.wrap(&[], start);
+        let (second, _) = unframe(&writer.wrap(&[1], start + std::time::Duration::from_millis(20))).unwrap();
+        assert_eq!(second, FrameHeader { seq: 1, timestamp: 960 });
+    }
+
+    #[test]
+    fn parity_matches_the_vectors() {
+        let first = frame(FrameHeader { seq: 0, timestamp: 0 }, &[0xAA, 0xBB]);
+        let second = frame(FrameHeader { seq: 1, timestamp: 960 }, &[0xCC]);
+        let mut encoder = crate::fec::Encoder::new(2);
+        assert_eq!(encoder.push(&first), None);
+        assert_eq!(encoder.push(&second), Some(vector("fec.parity")));
+
+        // Either datagram comes back from the other and the parity
+        let mut decoder = crate::fec::Decoder::new(2);
+        assert_eq!(decoder.receive(&first), [first]);
+        assert_eq!(decoder.receive(&vector("fec.parity")), [second]);
+        assert_eq!(decoder.recovered(), 1);
+    }
+
+    #[test]
+    fn control_frames_match_the_vectors() {
+        let probe = Probe { stage: 2, seq: 0x0102_0304, sent_at_us: 0x0102_0304_0506_0708 };
+        let mut packet = [0u8; 1200];
+        probe.write(&mut packet);
+        assert_eq!(packet[..PROBE_HEADER_SIZE], vector("probe.header"));
+        assert_eq!(Probe::parse(&packet), Some(probe));
+        assert_eq!(Probe::parse(&packet[..PROBE_HEADER_SIZE - 1]), None);
+
+        let clock = vector("probe.clock");
+        assert_eq!(Probe::parse(&clock), Some(Probe { stage: CLOCK_STAGE, seq: 1, sent_at_us: 1000 }));
+        assert_eq!(clock_stamps(&clock), Some((5_000_000, 5_000_250)));
+        let mut unstamped = [0u8; CLOCK_PROBE_SIZE];
+        Probe { stage: CLOCK_STAGE, seq: 1, sent_at_us: 1000 }.write(&mut unstamped);
+        assert_eq!(clock_stamps(&unstamped), None);
+
+        assert_eq!(PUNCH_MAGIC[..], vector("punch"));
+        assert_eq!(register("AB12CD34").as_bytes(), vector("rendezvous.register"));
+        assert_eq!(parse_peer(&vector("rendezvous.peer")), Some("203.0.113.7:4810".parse().unwrap()));
+        assert_eq!(relay("AB12CD34").as_bytes(), vector("rendezvous.relay"));
+        assert!(is_relaying(&vector("rendezvous.relaying")) && !is_relaying(&vector("rendezvous.peer")));
+        let rendezvous = ["punch", "rendezvous.register", "rendezvous.peer", "rendezvous.relay", "rendezvous.relaying"];
+        assert!(rendezvous.iter().all(|name| is_control(&vector(name))));
+        assert!(!is_control(&vector("hello.opus")));
+    }
+
+    #[test]
+    fn cast_announcements_match_the_vectors() {
+        assert_eq!(cast_announce(format("opus/1/64"), 1).as_bytes(), vector("cast.opus"));
+        assert_eq!(parse_cast(&vector("cast.opus")), Some((format("opus/1/64"), 1)));
+        // Redundancy only wraps Opus
+        assert_eq!(cast_announce(StreamFormat::PCM_MONO, 1).as_bytes(), vector("cast.pcm"));
+        assert_eq!(parse_cast(&vector("cast.pcm")), Some((StreamFormat::PCM_MONO, 0)));
+        assert_eq!(parse_cast(&vector("hello.opus")), None);
+        assert_eq!(parse_cast(b"BBCAST1 red=1"), None);
+    }
+}
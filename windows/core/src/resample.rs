@@ -0,0 +1,197 @@
+// Sample rate conversion for devices that don't run at 48 kHz, in either
+// direction: capture at 44.1 or 96 kHz to the 48 kHz everything else uses, and
+// the iPhone's 48 kHz to whatever the playback device runs at. Each output
+// sample is a weighted sum of the input around its position. "Fast" weighs the
+// two neighbours linearly; the others use a Blackman-windowed sinc, cut off
+// below the lower of the two Nyquist rates so downsampling doesn't alias. The
+// weights come from a table of phases, interpolated between them.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Positions between two input samples that have weights of their own
+const PHASES: usize = 128;
+/// Cutoff as a share of the lower Nyquist rate, leaving room for the roll-off
+const PASSBAND: f64 = 0.92;
+
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum Quality {
+    Fast,
+    #[default]
+    Balanced,
+    Best,
+}
+
+impl Quality {
+    pub const ALL: [Quality; 3] = [Quality::Fast, Quality::Balanced, Quality::Best];
+
+    pub fn parse(text: &str) -> Self {
+        match text {
+            "fast" => Self::Fast,
+            "best" => Self::Best,
+            _ => Self::Balanced,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Fast => "fast",
+            Self::Balanced => "balanced",
+            Self::Best => "best",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Fast => "Fast (linear)",
+            Self::Balanced => "Balanced (32-tap sinc)",
+            Self::Best => "Best (96-tap sinc)",
+        }
+    }
+
+    /// Input samples each output sample is made from
+    fn taps(self) -> usize {
+        match self {
+            Self::Fast => 2,
+            Self::Balanced => 32,
+            Self::Best => 96,
+        }
+    }
+
+    pub fn load(atomic: &AtomicU8) -> Self {
+        Self::ALL.get(atomic.load(Ordering::Relaxed) as usize).copied().unwrap_or_default()
+    }
+
+    pub fn store(self, atomic: &AtomicU8) {
+        atomic.store(self as u8, Ordering::Relaxed);
+    }
+}
+
+/// Interleaved audio from one rate to another, carrying its history across
+/// buffers so their joins don't click
+pub struct Resampler {
+    channels: usize,
+    step: f64, // input frames per output frame
+    taps: usize,
+    // PHASES + 1 rows of `taps` weights, each summing to 1
+    table: Vec<f32>,
+    // Input not yet used up, interleaved, and where the next output falls in it
+    history: Vec<f32>,
+    position: f64,
+    weights: Vec<f32>,
+}
+
+impl Resampler {
+    pub fn new(channels: usize, from_rate: u32, to_rate: u32, quality: Quality) -> Self {
+        let step = from_rate as f64 / to_rate as f64;
+        let taps = quality.taps();
+        let half = taps / 2;
+        let cutoff = PASSBAND * (1.0 / step).min(1.0);
+        let weight = |t: f64| match quality {
+            Quality::Fast => (1.0 - t.abs()).max(0.0),
+            _ => {
+                let x = t / half as f64;
+                let window = 0.42 + 0.5 * (std::f64::consts::PI * x).cos() + 0.08 * (std::f64::consts::TAU * x).cos();
+                let sinc = if t == 0.0 { 1.0 } else { (std::f64::consts::PI * cutoff * t).sin() / (std::f64::consts::PI * cutoff * t) };
+                if x.abs() < 1.0 { cutoff * sinc * window } else { 0.0 }
+            }
+        };
+        let mut table = Vec::with_capacity((PHASES + 1) * taps);
+        for phase in 0..=PHASES {
+            let fraction = phase as f64 / PHASES as f64;
+            // Tap i is the input sample i + 1 - half frames from the position's floor
+            let row: Vec<f64> = (0..taps).map(|i| weight((i as f64 + 1.0 - half as f64) - fraction)).collect();
+            let sum: f64 = row.iter().sum();
+            table.extend(row.iter().map(|w| (w / sum) as f32));
+        }
+        Self {
+            channels: channels.max(1),
+            step,
+            taps,
+            table,
+            // Silence before the first sample, so it starts at the left of the window
+            history: vec![0.0; (half - 1) * channels.max(1)],
+            position: (half - 1) as f64,
+            weights: vec![0.0; taps],
+        }
+    }
+
+    /// Input frames that make about `frames` output frames
+    pub fn input_for(&self, frames: usize) -> usize {
+        (frames as f64 * self.step).ceil() as usize
+    }
+
+    /// Converts `input`; output waits on the window's half a window of lookahead
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        let channels = self.channels;
+        let half = self.taps / 2;
+        self.history.extend_from_slice(input);
+        let frames = self.history.len() / channels;
+        let mut out = Vec::with_capacity((input.len() as f64 / self.step) as usize + channels);
+        while (self.position as usize) + half < frames {
+            let base = self.position as usize;
+            let phase = (self.position - base as f64) * PHASES as f64;
+            let (row, blend) = (phase as usize, (phase - phase.floor()) as f32);
+            let (a, b) = (&self.table[row * self.taps..][..self.taps], &self.table[(row + 1) * self.taps..][..self.taps]);
+            for (w, (a, b)) in self.weights.iter_mut().zip(a.iter().zip(b)) {
+                *w = a + (b - a) * blend;
+            }
+            let first = (base + 1 - half) * channels;
+            for channel in 0..channels {
+                let samples = self.history[first + channel..].iter().step_by(channels);
+                out.push(samples.zip(&self.weights).map(|(s, w)| s * w).sum());
+            }
+            self.position += self.step;
+        }
+        // Keep what the next output's window still reaches back to
+        let used = (self.position as usize + 1).saturating_sub(half).min(frames);
+        self.history.drain(..used * channels);
+        self.position -= used as f64;
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(frequency: f64, rate: u32, frames: usize) -> Vec<f32> {
+        (0..frames).map(|i| (std::f64::consts::TAU * frequency * i as f64 / rate as f64).sin() as f32 * 0.5).collect()
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0f32, |m, s| m.max(s.abs()))
+    }
+
+    #[test]
+    fn converts_44k1_to_48k_in_pieces_without_changing_the_tone() {
+        let input = tone(1000.0, 44_100, 44_100);
+        let mut resampler = Resampler::new(1, 44_100, 48_000, Quality::Balanced);
+        let out: Vec<f32> = input.chunks(441).flat_map(|chunk| resampler.process(chunk)).collect();
+        // All but the window's lookahead
+        assert!((47_980..=48_000).contains(&out.len()), "{}", out.len());
+
+        // Past the start, it matches a 1 kHz tone made at 48 kHz
+        let expected = tone(1000.0, 48_000, 48_000);
+        let error = out[1000..40_000].iter().zip(&expected[1000..]).fold(0.0f32, |m, (a, b)| m.max((a - b).abs()));
+        assert!(error < 0.01, "{}", error);
+    }
+
+    #[test]
+    fn sinc_keeps_out_what_would_alias_when_downsampling() {
+        // 30 kHz is above the 24 kHz that 48 kHz can hold; linear lets it fold to 18 kHz
+        let input: Vec<f32> = tone(30_000.0, 96_000, 9600).into_iter().flat_map(|s| [s, s]).collect();
+        let aliased = Resampler::new(2, 96_000, 48_000, Quality::Fast).process(&input);
+        let filtered = Resampler::new(2, 96_000, 48_000, Quality::Best).process(&input);
+        assert!(peak(&aliased[200..]) > 0.1, "{}", peak(&aliased[200..]));
+        assert!(peak(&filtered[200..]) < 0.01, "{}", peak(&filtered[200..]));
+        assert_eq!(filtered.len() % 2, 0);
+    }
+
+    #[test]
+    fn quality_round_trips_through_its_setting() {
+        for quality in Quality::ALL {
+            assert_eq!(Quality::parse(quality.as_str()), quality);
+        }
+        assert_eq!(Quality::parse(""), Quality::Balanced);
+    }
+}
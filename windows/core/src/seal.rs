@@ -0,0 +1,334 @@
+// Optional protection for audio datagrams, traded against CPU time on the
+// receiving end, which matters on a phone's battery: off; authenticated
+// (HMAC-SHA256), so nobody else on the network can inject or change audio; or
+// encrypted as well (ChaCha20-Poly1305), so nobody can listen either. The
+// primitives are in cipher.rs.
+//
+// The hello offers the mode with an X25519 key made for the session, and the
+// phone answers with one of its own inside the ack its identity key signs (see
+// protocol.rs), so only the phone the PC checked ends up with the keys.
+// HKDF-SHA256 over the shared secret, salted with the hello's nonce, gives a
+// key each way:
+//
+//   PC -> phone   "BBHELLO1 ... frame=1 seal=aead kx=<64 hex> nonce=<32 hex> pc=<32 hex>"
+//   phone -> PC   "BBHELLO1 ACK ... frame=1 seal=aead kx=<64 hex> pc=<32 hex> nonce=... id=... sig=..."
+//
+//   HKDF(salt = nonce, secret, info = "BBSEAL1" | PC's kx | phone's kx), 64 bytes:
+//   the PC -> phone key, then the phone -> PC key
+//
+// The phone keeps its key for as long as the hellos carry the same one, so
+// every ack to a session's hellos agrees on the same keys. A sealed datagram
+// wraps what would otherwise be sent, framed or bare:
+//
+//   [u8 0xBC][u8 mode: 1 HMAC, 2 AEAD][u64 LE counter][body][16-byte tag]
+//
+// With HMAC the body is the datagram as it was and the tag is the first 16
+// bytes of HMAC-SHA256 over everything before it. With AEAD the body is
+// encrypted under the nonce [0 0 0 0][counter LE], with the 10-byte header as
+// associated data. Each side counts its datagrams from 0; a counter seen
+// before, or 64 or more behind the newest, is a replay and dropped. Hellos,
+// probes and other control frames stay in the clear.
+
+use crate::cipher::{self, Key, TAG_SIZE};
+use crate::protocol::Nonce;
+use curve25519_dalek::montgomery::MontgomeryPoint;
+use sha2::{Digest, Sha256};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+// Not ASCII 'B' either, nor the frame header's 0xBB
+const MAGIC: u8 = 0xBC;
+const HEADER_SIZE: usize = 10;
+/// Bytes sealing adds to a datagram
+pub const OVERHEAD: usize = HEADER_SIZE + TAG_SIZE;
+const REPLAY_WINDOW: u64 = 64;
+const INFO: &[u8] = b"BBSEAL1";
+/// The packet the settings measure each mode on
+pub const MEASURE_SIZE: usize = 1000;
+const MEASURE_ROUNDS: u32 = 200;
+
+/// An X25519 public key
+pub type ExchangeKey = [u8; 32];
+
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum Mode {
+    #[default]
+    Off,
+    Mac,
+    Aead,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 3] = [Mode::Off, Mode::Mac, Mode::Aead];
+
+    pub fn parse(text: &str) -> Self {
+        match text {
+            "mac" => Self::Mac,
+            "aead" => Self::Aead,
+            _ => Self::Off,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Mac => "mac",
+            Self::Aead => "aead",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Off => "Off",
+            Self::Mac => "Authenticate (HMAC-SHA256)",
+            Self::Aead => "Encrypt (ChaCha20-Poly1305)",
+        }
+    }
+}
+
+/// This side's half of the exchange, made fresh for each session
+#[derive(Clone)]
+pub struct KeyExchange {
+    secret: [u8; 32],
+    public: ExchangeKey,
+}
+
+impl KeyExchange {
+    /// Two v4 UUIDs give 244 random bits, hashed into the secret
+    pub fn generate() -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(uuid::Uuid::new_v4().as_bytes());
+        hasher.update(uuid::Uuid::new_v4().as_bytes());
+        Self::from_secret(hasher.finalize().into())
+    }
+
+    pub fn from_secret(secret: [u8; 32]) -> Self {
+        Self { secret, public: MontgomeryPoint::mul_base_clamped(secret).to_bytes() }
+    }
+
+    pub fn public(&self) -> &ExchangeKey {
+        &self.public
+    }
+
+    /// The PC's ends, once the phone answered the hello with `phone`. None for
+    /// a key that would make the secret guessable (a low-order point).
+    pub fn session(&self, mode: Mode, phone: &ExchangeKey, nonce: &Nonce) -> Option<(Sealer, Opener)> {
+        let [to_phone, to_pc] = self.keys(&self.public, phone, phone, nonce)?;
+        Some((Sealer::new(mode, to_phone), Opener::new(mode, to_pc)))
+    }
+
+    /// The phone's ends, answering the PC's `pc` key
+    #[cfg(test)]
+    pub fn answer(&self, mode: Mode, pc: &ExchangeKey, nonce: &Nonce) -> Option<(Sealer, Opener)> {
+        let [to_phone, to_pc] = self.keys(pc, &self.public, pc, nonce)?;
+        Some((Sealer::new(mode, to_pc), Opener::new(mode, to_phone)))
+    }
+
+    fn keys(&self, pc: &ExchangeKey, phone: &ExchangeKey, theirs: &ExchangeKey, nonce: &Nonce) -> Option<[Key; 2]> {
+        let shared = MontgomeryPoint(*theirs).mul_clamped(self.secret).to_bytes();
+        if shared == [0; 32] {
+            return None;
+        }
+        let mut okm = [0u8; 64];
+        cipher::hkdf_sha256(nonce, &shared, &[INFO, pc, phone].concat(), &mut okm);
+        Some([okm[..32].try_into().unwrap(), okm[32..].try_into().unwrap()])
+    }
+}
+
+fn aead_nonce(counter: u64) -> cipher::Nonce {
+    let mut nonce = [0u8; cipher::NONCE_SIZE];
+    nonce[4..].copy_from_slice(&counter.to_le_bytes());
+    nonce
+}
+
+/// Seals what this side sends
+pub struct Sealer {
+    mode: Mode,
+    key: Key,
+    counter: u64,
+}
+
+impl Sealer {
+    fn new(mode: Mode, key: Key) -> Self {
+        Self { mode, key, counter: 0 }
+    }
+
+    pub fn seal(&mut self, datagram: &[u8]) -> Vec<u8> {
+        let mut packet = Vec::with_capacity(datagram.len() + OVERHEAD);
+        packet.extend_from_slice(&[MAGIC, self.mode as u8]);
+        packet.extend_from_slice(&self.counter.to_le_bytes());
+        packet.extend_from_slice(datagram);
+        let tag = match self.mode {
+            Mode::Aead => {
+                let (header, body) = packet.split_at_mut(HEADER_SIZE);
+                cipher::seal(&self.key, &aead_nonce(self.counter), header, body)
+            }
+            _ => cipher::hmac_sha256(&self.key, &[&packet])[..TAG_SIZE].try_into().unwrap(),
+        };
+        packet.extend_from_slice(&tag);
+        self.counter += 1;
+        packet
+    }
+}
+
+/// Opens what the other side sent
+pub struct Opener {
+    mode: Mode,
+    key: Key,
+    newest: Option<u64>,
+    // Bit n: newest - n has been opened
+    seen: u64,
+}
+
+impl Opener {
+    fn new(mode: Mode, key: Key) -> Self {
+        Self { mode, key, newest: None, seen: 0 }
+    }
+
+    /// The datagram inside `packet`, decrypted in place. None when it wasn't
+    /// sealed with this key and mode, or has been opened before.
+    pub fn open<'a>(&mut self, packet: &'a mut [u8]) -> Option<&'a [u8]> {
+        if packet.len() < OVERHEAD || packet[0] != MAGIC || packet[1] != self.mode as u8 {
+            return None;
+        }
+        let counter = u64::from_le_bytes(packet[2..HEADER_SIZE].try_into().unwrap());
+        if !self.fresh(counter) {
+            return None;
+        }
+        let (sealed, tag) = packet.split_at_mut(packet.len() - TAG_SIZE);
+        let authentic = match self.mode {
+            Mode::Aead => {
+                let (header, body) = sealed.split_at_mut(HEADER_SIZE);
+                cipher::open(&self.key, &aead_nonce(counter), header, body, tag)
+            }
+            _ => cipher::verify_hmac_sha256(&self.key, sealed, tag),
+        };
+        if !authentic {
+            return None;
+        }
+        self.remember(counter);
+        let end = packet.len() - TAG_SIZE;
+        Some(&packet[HEADER_SIZE..end])
+    }
+
+    fn fresh(&self, counter: u64) -> bool {
+        match self.newest {
+            Some(newest) if counter <= newest => {
+                let behind = newest - counter;
+                behind < REPLAY_WINDOW && self.seen & (1 << behind) == 0
+            }
+            _ => true,
+        }
+    }
+
+    fn remember(&mut self, counter: u64) {
+        match self.newest {
+            Some(newest) if counter <= newest => self.seen |= 1 << (newest - counter),
+            newest => {
+                let ahead = newest.map_or(REPLAY_WINDOW, |newest| counter - newest);
+                self.seen = if ahead >= REPLAY_WINDOW { 1 } else { (self.seen << ahead) | 1 };
+                self.newest = Some(counter);
+            }
+        }
+    }
+}
+
+/// What the running session's protection costs, for Diagnostics
+#[derive(Clone, Default)]
+pub struct Stats {
+    /// What the PC asked for, whatever the phone agreed to
+    pub requested: Mode,
+    // Nanoseconds per packet, a moving average over the last hundred or so
+    average_ns: Option<f64>,
+    /// Datagrams dropped for not opening
+    pub rejected: u64,
+}
+
+impl Stats {
+    pub fn new(requested: Mode) -> Self {
+        Self { requested, ..Default::default() }
+    }
+
+    pub fn record(&mut self, elapsed: Duration) {
+        let ns = elapsed.as_nanos() as f64;
+        self.average_ns = Some(self.average_ns.map_or(ns, |average| average + (ns - average) / 100.0));
+    }
+
+    pub fn per_packet(&self) -> Option<Duration> {
+        self.average_ns.map(|ns| Duration::from_nanos(ns as u64))
+    }
+}
+
+/// CPU time to seal and open one `size`-byte packet in `mode`, on this machine
+pub fn measure(mode: Mode, size: usize) -> Duration {
+    if mode == Mode::Off {
+        return Duration::ZERO;
+    }
+    let (mut sealer, mut opener) = (Sealer::new(mode, [7; 32]), Opener::new(mode, [7; 32]));
+    let datagram = vec![0x55; size];
+    let start = Instant::now();
+    for _ in 0..MEASURE_ROUNDS {
+        let mut packet = sealer.seal(black_box(&datagram));
+        black_box(opener.open(&mut packet));
+    }
+    start.elapsed() / MEASURE_ROUNDS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn both_ends_agree_and_refuse_tampering_and_replays() {
+        let nonce = [3; 16];
+        let (pc, phone) = (KeyExchange::from_secret([1; 32]), KeyExchange::from_secret([2; 32]));
+        for mode in [Mode::Mac, Mode::Aead] {
+            let (mut pc_sealer, mut pc_opener) = pc.session(mode, phone.public(), &nonce).unwrap();
+            let (mut phone_sealer, mut phone_opener) = phone.answer(mode, pc.public(), &nonce).unwrap();
+
+            let mut down = pc_sealer.seal(b"PC audio");
+            assert_eq!(down.len(), 8 + OVERHEAD);
+            assert_eq!(mode == Mode::Aead, !down.windows(8).any(|w| w == b"PC audio"));
+            let replayed = down.clone();
+            assert_eq!(phone_opener.open(&mut down), Some(&b"PC audio"[..]));
+            assert_eq!(phone_opener.open(&mut replayed.clone()), None);
+
+            // Each direction has its own key
+            let mut up = phone_sealer.seal(b"mic");
+            assert_eq!(phone_opener.open(&mut up.clone()), None);
+            let mut tampered = up.clone();
+            tampered[HEADER_SIZE] ^= 1;
+            assert_eq!(pc_opener.open(&mut tampered), None);
+            assert_eq!(pc_opener.open(&mut up), Some(&b"mic"[..]));
+
+            // Late but within the window is fine, once
+            let late: Vec<Vec<u8>> = (0..3).map(|_| pc_sealer.seal(b"x")).collect();
+            for packet in [&late[2], &late[0], &late[1]] {
+                assert!(phone_opener.open(&mut packet.clone()).is_some());
+            }
+            assert!(phone_opener.open(&mut late[0].clone()).is_none());
+            let old = pc_sealer.seal(b"old");
+            for _ in 0..REPLAY_WINDOW {
+                pc_sealer.seal(b"x");
+            }
+            assert!(phone_opener.open(&mut pc_sealer.seal(b"new")).is_some());
+            assert!(phone_opener.open(&mut old.clone()).is_none());
+        }
+
+        // A sealed datagram doesn't open in the other mode, and bare audio doesn't open at all
+        let (mut mac, _) = pc.session(Mode::Mac, phone.public(), &nonce).unwrap();
+        let (_, mut aead) = phone.answer(Mode::Aead, pc.public(), &nonce).unwrap();
+        assert_eq!(aead.open(&mut mac.seal(b"audio")), None);
+        assert_eq!(aead.open(&mut [0u8; 40]), None);
+        // Nor does the all-zero key, which would leave nothing secret
+        assert!(pc.session(Mode::Aead, &[0; 32], &nonce).is_none());
+    }
+
+    #[test]
+    fn mode_round_trips_through_its_setting() {
+        for mode in Mode::ALL {
+            assert_eq!(Mode::parse(mode.as_str()), mode);
+        }
+        assert_eq!(Mode::parse(""), Mode::Off);
+    }
+}
@@ -0,0 +1,142 @@
+// Warm standby: a second saved device (the iPad next to the iPhone, say) that
+// gets hellos alongside the running session, so its codecs are agreed and its
+// identity checked before it's needed. "Switch target" then only changes where
+// the audio goes, which takes effect with the next packet instead of a new
+// session. The device switched away from stays warm as the next standby.
+//
+// The standby's own audio is dropped until it's switched to. Bridge keeps it
+// (set_standby, switch_to_standby).
+
+use crate::bridge::{HELLO_INTERVAL, HELLO_REFRESH_INTERVAL};
+use crate::identity::{self, Verdict};
+use crate::protocol::{self, Nonce, PcId, PublicKey, Session};
+use crate::seal::KeyExchange;
+use anyhow::{anyhow, Result};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Two refreshes unanswered and it's no longer counted on
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(12);
+
+/// Which saved device to keep warm, as the UI knows it
+pub struct Target {
+    pub address: String,
+    pub key: Option<PublicKey>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    Waiting,
+    Ready,
+    /// Failed its identity check; it stays out until the next session
+    Refused,
+}
+
+pub struct Standby {
+    pub addr: SocketAddr,
+    pub key: Option<PublicKey>,
+    pub nonce: Nonce,
+    /// Offered in the hello; the session's keys come from it once switched to
+    pub exchange: KeyExchange,
+    pub hello: String,
+    pub agreed: Option<Session>,
+    pub identity: Option<Verdict>,
+    last_hello: Option<Instant>,
+    last_answer: Option<Instant>,
+}
+
+impl Standby {
+    pub fn new(addr: SocketAddr, key: Option<PublicKey>, session: Session, pc: &PcId) -> Self {
+        let nonce = *uuid::Uuid::new_v4().as_bytes();
+        let exchange = KeyExchange::generate();
+        let hello = protocol::hello(session, Some(&nonce), Some(pc), Some(exchange.public()));
+        Self { addr, key, nonce, exchange, hello, agreed: None, identity: None, last_hello: None, last_answer: None }
+    }
+
+    /// The device the session just left, already agreed and checked
+    #[allow(clippy::too_many_arguments)]
+    pub fn warm(
+        addr: SocketAddr,
+        key: Option<PublicKey>,
+        nonce: Nonce,
+        exchange: KeyExchange,
+        hello: String,
+        agreed: Option<Session>,
+        identity: Option<Verdict>,
+        now: Instant,
+    ) -> Self {
+        let last_answer = agreed.map(|_| now);
+        Self { addr, key, nonce, exchange, hello, agreed, identity, last_hello: Some(now), last_answer }
+    }
+
+    /// The hello to send now, if one is due
+    pub fn hello_due(&mut self, now: Instant) -> Option<&str> {
+        // The session's pace: every second until answered, then a refresh
+        let interval = if self.agreed.is_some() { HELLO_REFRESH_INTERVAL } else { HELLO_INTERVAL };
+        if self.last_hello.is_some_and(|t| now.duration_since(t) < interval) {
+            return None;
+        }
+        self.last_hello = Some(now);
+        Some(&self.hello)
+    }
+
+    /// A hello ack from the standby's address. Errors once, when it fails the
+    /// identity check the session itself would have made.
+    pub fn answer(&mut self, packet: &[u8], now: Instant) -> Result<()> {
+        if self.identity.is_none() {
+            let verdict = identity::check(self.key, protocol::ack_identity(packet, &self.nonce)?);
+            self.identity = Some(verdict);
+            match verdict {
+                Verdict::Changed(_) => return Err(anyhow!("answered with a different identity key than before")),
+                Verdict::Missing => return Err(anyhow!("answered without the identity key it had before")),
+                _ => {}
+            }
+        }
+        if let Some(agreed) = protocol::parse_ack(packet) {
+            self.agreed = Some(agreed);
+            self.last_answer = Some(now);
+        }
+        Ok(())
+    }
+
+    pub fn status(&self, now: Instant) -> Status {
+        match self.identity {
+            Some(verdict) if verdict.is_alarm() => Status::Refused,
+            _ if self.last_answer.is_some_and(|t| now.duration_since(t) < ANSWER_TIMEOUT) => Status::Ready,
+            _ => Status::Waiting,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> Session {
+        Session { formats: protocol::Formats::default(), redundancy: 0, up_rate: None, framed: false, seal: Default::default(), ptime: None, fec: 0 }
+    }
+
+    #[test]
+    fn ready_once_answered_until_it_goes_quiet() {
+        let start = Instant::now();
+        let addr: SocketAddr = ([192, 168, 1, 30], 4810).into();
+        let mut standby = Standby::new(addr, None, session(), &[1; 16]);
+        assert!(standby.hello_due(start).is_some());
+        assert!(standby.hello_due(start + Duration::from_millis(500)).is_none());
+        assert_eq!(standby.status(start), Status::Waiting);
+
+        let ack = b"BBHELLO1 ACK down=pcm/1/768 up=pcm/1/768";
+        standby.answer(ack, start).unwrap();
+        assert_eq!(standby.identity, Some(Verdict::Unsigned));
+        assert_eq!(standby.status(start), Status::Ready);
+        // Answered, so the hellos slow down to refreshes
+        assert!(standby.hello_due(start + Duration::from_secs(2)).is_none());
+        assert_eq!(standby.status(start + ANSWER_TIMEOUT), Status::Waiting);
+
+        // A device that had a key and now answers without one is kept out
+        let mut impostor = Standby::new(addr, Some([7; 32]), session(), &[1; 16]);
+        assert!(impostor.answer(ack, start).is_err());
+        assert!(impostor.answer(ack, start).is_ok());
+        assert_eq!(impostor.status(start), Status::Refused);
+    }
+}
@@ -0,0 +1,158 @@
+// What a Bridge runs over. UdpTransport (udp.rs) is the usual link; FramedStream
+// carries the same datagrams over a byte stream, e.g. the app's Bluetooth RFCOMM
+// link, each prefixed with its length (u16 little-endian).
+//
+// A stream has one peer and no addresses, so it reports STREAM_PEER as both the
+// phone's address and the source of everything it reads.
+
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+/// The phone's address on a link that has none
+pub const STREAM_PEER: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0);
+
+pub trait Transport: Send {
+    /// Where the hello goes
+    fn phone(&self) -> SocketAddr;
+    /// Send one datagram, returning its size
+    fn send_to(&mut self, packet: &[u8], to: SocketAddr) -> io::Result<usize>;
+    /// Answer a datagram from `to` where it expects the PC to be
+    fn reply_to(&mut self, packet: &[u8], to: SocketAddr) -> io::Result<usize> {
+        self.send_to(packet, to)
+    }
+    /// Receive one datagram and where it came from if a whole one has arrived,
+    /// without waiting for it
+    fn try_recv(&mut self, buf: &mut [u8]) -> io::Result<Option<(usize, SocketAddr)>>;
+    /// Let the OS pick the route again after a network change
+    fn rebind(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn phone(&self) -> SocketAddr {
+        (**self).phone()
+    }
+
+    fn send_to(&mut self, packet: &[u8], to: SocketAddr) -> io::Result<usize> {
+        (**self).send_to(packet, to)
+    }
+
+    fn reply_to(&mut self, packet: &[u8], to: SocketAddr) -> io::Result<usize> {
+        (**self).reply_to(packet, to)
+    }
+
+    fn try_recv(&mut self, buf: &mut [u8]) -> io::Result<Option<(usize, SocketAddr)>> {
+        (**self).try_recv(buf)
+    }
+
+    fn rebind(&mut self) -> io::Result<()> {
+        (**self).rebind()
+    }
+}
+
+/// Datagrams on a byte stream. Reads must time out quickly so try_recv doesn't
+/// stall the send side.
+pub struct FramedStream<S> {
+    stream: S,
+    pending: Vec<u8>,
+}
+
+impl<S> FramedStream<S> {
+    pub fn new(stream: S) -> Self {
+        Self { stream, pending: Vec::new() }
+    }
+
+    fn has_frame(&self) -> bool {
+        self.pending.len() >= 2
+            && self.pending.len() >= 2 + u16::from_le_bytes([self.pending[0], self.pending[1]]) as usize
+    }
+}
+
+impl<S: Read + Write + Send> Transport for FramedStream<S> {
+    fn phone(&self) -> SocketAddr {
+        STREAM_PEER
+    }
+
+    fn send_to(&mut self, packet: &[u8], _to: SocketAddr) -> io::Result<usize> {
+        let len = u16::try_from(packet.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "packet too large"))?;
+        let mut frame = Vec::with_capacity(2 + packet.len());
+        frame.extend_from_slice(&len.to_le_bytes());
+        frame.extend_from_slice(packet);
+        self.stream.write_all(&frame)?;
+        Ok(packet.len())
+    }
+
+    fn try_recv(&mut self, buf: &mut [u8]) -> io::Result<Option<(usize, SocketAddr)>> {
+        if !self.has_frame() {
+            let mut chunk = [0u8; 4096];
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::ConnectionAborted, "link closed")),
+                Ok(n) => self.pending.extend_from_slice(&chunk[..n]),
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if !self.has_frame() {
+            return Ok(None);
+        }
+        let len = u16::from_le_bytes([self.pending[0], self.pending[1]]) as usize;
+        let n = len.min(buf.len());
+        buf[..n].copy_from_slice(&self.pending[2..2 + n]);
+        self.pending.drain(..2 + len);
+        Ok(Some((n, STREAM_PEER)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// A stream that hands out what was written a few bytes at a time
+    #[derive(Default)]
+    struct Trickle(VecDeque<u8>);
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            let n = buf.len().min(self.0.len()).min(3);
+            for byte in &mut buf[..n] {
+                *byte = self.0.pop_front().unwrap();
+            }
+            Ok(n)
+        }
+    }
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.extend(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn datagrams_survive_a_stream_that_splits_them() {
+        let mut link = FramedStream::new(Trickle::default());
+        assert_eq!(link.send_to(b"hello", STREAM_PEER).unwrap(), 5);
+        link.send_to(b"", STREAM_PEER).unwrap();
+        link.send_to(b"world!", STREAM_PEER).unwrap();
+
+        let mut buf = [0u8; 16];
+        let mut received = vec![];
+        for _ in 0..20 {
+            if let Some((n, src)) = link.try_recv(&mut buf).unwrap() {
+                assert_eq!(src, link.phone());
+                received.push(buf[..n].to_vec());
+            }
+        }
+        assert_eq!(received, [&b"hello"[..], b"", b"world!"]);
+    }
+}
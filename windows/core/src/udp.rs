@@ -0,0 +1,100 @@
+// The UDP link to the phone: one socket on the PC's receive port, where the
+// phone's acks and mic audio arrive, and another that sends to the phone. Both
+// are nonblocking, so a single loop can poll the link and feed it audio.
+//
+// Internet mode sends from the receive port instead (punched): that's the NAT
+// mapping the rendezvous server saw and the phone punches towards.
+
+use crate::transport::Transport;
+use anyhow::{anyhow, Result};
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+pub struct UdpTransport {
+    recv: UdpSocket,
+    send: UdpSocket,
+    phone: SocketAddr,
+    punched: bool,
+}
+
+impl UdpTransport {
+    /// Sends to `phone` ("192.168.1.20:4811" or a hostname, resolved once) and
+    /// receives on `port` (PC_PORT is where phones answer; 0 picks a free one)
+    pub fn connect(phone: &str, port: u16) -> Result<Self> {
+        let phone = phone
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow!("Could not resolve {}", phone))?;
+        Self::new(UdpSocket::bind(("0.0.0.0", port))?, phone)
+    }
+
+    /// Receives on `recv`, e.g. one given a larger buffer, and sends to `phone`
+    /// from a socket of its own
+    pub fn new(recv: UdpSocket, phone: SocketAddr) -> Result<Self> {
+        recv.set_nonblocking(true)?;
+        let send = UdpSocket::bind("0.0.0.0:0")?;
+        send.set_nonblocking(true)?;
+        Ok(Self { recv, send, phone, punched: false })
+    }
+
+    /// Sends and receives on `socket`, the one a rendezvous server saw
+    pub fn punched(socket: UdpSocket, phone: SocketAddr) -> Result<Self> {
+        socket.set_nonblocking(true)?;
+        let send = socket.try_clone()?;
+        Ok(Self { recv: socket, send, phone, punched: true })
+    }
+
+    /// The receive socket's address, for a phone told to answer somewhere else
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.recv.local_addr()?)
+    }
+
+    pub fn send(&self, packet: &[u8]) -> io::Result<usize> {
+        self.send.send_to(packet, self.phone)
+    }
+}
+
+impl Transport for UdpTransport {
+    fn phone(&self) -> SocketAddr {
+        self.phone
+    }
+
+    fn send_to(&mut self, packet: &[u8], to: SocketAddr) -> io::Result<usize> {
+        self.send.send_to(packet, to)
+    }
+
+    /// From the receive port, where a phone answering hellos expects the PC
+    fn reply_to(&mut self, packet: &[u8], to: SocketAddr) -> io::Result<usize> {
+        self.recv.send_to(packet, to)
+    }
+
+    /// A fresh send socket, so the OS picks the route again after a network
+    /// change. A punched link keeps its socket, since the mapping belongs to it.
+    fn rebind(&mut self) -> io::Result<()> {
+        if self.punched {
+            return Ok(());
+        }
+        let send = UdpSocket::bind("0.0.0.0:0")?;
+        send.set_nonblocking(true)?;
+        self.send = send;
+        Ok(())
+    }
+
+    /// One datagram and where it came from, if one is waiting. Replies to what
+    /// the send socket sent (clock probe echoes) come back to it, so it's read too.
+    fn try_recv(&mut self, buf: &mut [u8]) -> io::Result<Option<(usize, SocketAddr)>> {
+        match self.recv.recv_from(buf) {
+            Ok(received) => return Ok(Some(received)),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+        if self.punched {
+            return Ok(None);
+        }
+        match self.send.recv_from(buf) {
+            Ok(received) => Ok(Some(received)),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+// Whole sessions over loopback, with budbridge-client playing the phone: the
+// hello and its ack, then framed audio going down into the phone's jitter buffer.
+// MockBackend stands in for the sound card, so these run without one.
+
+use budbridge_core::identity::Verdict;
+use budbridge_core::protocol::{Formats, Session, StreamFormat};
+use budbridge_core::{AudioCapture, Bridge, Event, MockBackend, UdpTransport};
+use budbridge_client::Receiver;
+use std::net::UdpSocket;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A phone on loopback and a bridge offering it `session`, through the handshake
+fn connect(session: Session) -> (Receiver, Bridge) {
+    let mut phone = Receiver::bind(0).unwrap();
+    let link = UdpTransport::connect(&format!("127.0.0.1:{}", phone.local_addr().unwrap().port()), 0).unwrap();
+    phone.set_pc_port(link.local_addr().unwrap().port());
+    let mut bridge = Bridge::new(link, session, [0x10; 16]).unwrap();
+
+    // The first poll sends the hello; the phone acks it unsigned
+    assert_eq!(bridge.poll().unwrap(), None);
+    assert!(matches!(phone.receive().unwrap(), Some(budbridge_client::Event::Hello(_))));
+    assert_eq!(next_event(&mut bridge), Event::Identity(Verdict::Unsigned));
+    match next_event(&mut bridge) {
+        Event::Agreed(agreed) => assert!(agreed.formats.down == session.formats.down && agreed.framed),
+        other => panic!("expected the agreed session, got {:?}", other),
+    }
+    (phone, bridge)
+}
+
+/// What the phone plays once `frames` sample frames have arrived
+fn play(phone: &mut Receiver, frames: usize) -> Vec<f32> {
+    let mut received = 0;
+    while received < frames {
+        match phone.receive().unwrap() {
+            Some(budbridge_client::Event::Audio(n)) => received += n,
+            Some(_) => {}
+            None => panic!("the phone got {} of {} frames", received, frames),
+        }
+    }
+    let mut out = vec![0f32; frames * 2];
+    phone.buffer().lock().unwrap().pull(&mut out);
+    out
+}
+
+fn next_event(bridge: &mut Bridge) -> Event {
+    let deadline = Instant::now() + Duration::from_secs(2);
+    loop {
+        if let Some(event) = bridge.poll().unwrap() {
+            return event;
+        }
+        assert!(Instant::now() < deadline, "no event from the bridge");
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn a_phone_plays_what_the_bridge_sends() {
+    let down = StreamFormat::parse("opus/2/128").unwrap();
+    let (mut phone, mut bridge) = connect(Session { formats: Formats { down, up: StreamFormat::PCM_MONO }, ..Default::default() });
+
+    // 100 ms of a 440 Hz tone is five 20 ms Opus packets
+    let tone: Vec<f32> = (0..4800)
+        .flat_map(|i| {
+            let s = (i as f32 * 440.0 * std::f32::consts::TAU / 48000.0).sin() * 0.25;
+            [s, s]
+        })
+        .collect();
+    assert_eq!(bridge.send(&tone).unwrap(), 5);
+    let out = play(&mut phone, 4800);
+    assert!(out[..7680].iter().any(|s| s.abs() > 0.1));
+}
+
+#[test]
+fn a_phone_plays_what_a_mock_mic_captures() {
+    // A mono mic at 44.1 kHz: doubled to stereo and resampled on the way
+    let mic = MockBackend::new().with_device("Mock mic", 1, 44_100);
+    let capture = AudioCapture::start_with(&mic, None).unwrap();
+    assert_eq!(capture.device_name(), "Mock mic");
+    let (mut phone, mut bridge) = connect(Session::default());
+
+    // 100 ms in, less what the resampler holds back, in 10 ms frames of PCM
+    mic.deliver(4410);
+    let frames: Vec<Vec<f32>> = capture.frames().try_iter().collect();
+    assert_eq!(frames.len(), 9);
+    for frame in &frames {
+        assert_eq!(bridge.send(frame).unwrap(), 1);
+    }
+
+    // The mock's -6 dBFS tone arrives at the same level
+    let out = play(&mut phone, 9 * 480);
+    let peak = out.iter().fold(0f32, |peak, s| peak.max(s.abs()));
+    assert!((peak - 0.5).abs() < 0.01, "{}", peak);
+}
+
+#[test]
+fn a_phone_answering_another_pc_is_refused_until_it_answers_ours() {
+    // A bare socket as the phone, to ack with another PC's id
+    let phone = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let link = UdpTransport::connect(&phone.local_addr().unwrap().to_string(), 0).unwrap();
+    let pc = ("127.0.0.1", link.local_addr().unwrap().port());
+    let mut bridge = Bridge::new(link, Session::default(), [0x10; 16]).unwrap();
+    assert_eq!(bridge.poll().unwrap(), None);
+    let ack = |id: u8| format!("BBHELLO1 ACK down=pcm/1/768 up=pcm/1/768 pc={}", format!("{:02x}", id).repeat(16));
+
+    phone.send_to(ack(0x20).as_bytes(), pc).unwrap();
+    assert_eq!(next_event(&mut bridge), Event::Crossed(true));
+    phone.send_to(&[1, 0, 2, 0], pc).unwrap();
+    phone.send_to(ack(0x10).as_bytes(), pc).unwrap();
+    // The audio in between was dropped
+    assert_eq!(next_event(&mut bridge), Event::Identity(Verdict::Unsigned));
+    assert_eq!(next_event(&mut bridge), Event::Crossed(false));
+    assert!(matches!(next_event(&mut bridge), Event::Agreed(_)));
+
+    phone.send_to(&[1, 0, 2, 0], pc).unwrap();
+    assert_eq!(next_event(&mut bridge), Event::PeerAt(phone.local_addr().unwrap().ip()));
+    assert!(matches!(next_event(&mut bridge), Event::Arrived { len: 4, seq: None, .. }));
+    assert_eq!(next_event(&mut bridge), Event::Audio(vec![1, 2]));
+}
@@ -0,0 +1,85 @@
+// Who sent packets to the receive port this session, for Advanced diagnostics.
+// Anything on the LAN can send to it, so a misrouted app or a hostile host shows
+// up here next to the phone, with how much it sent and how much was dropped.
+
+use std::net::SocketAddr;
+use std::time::Instant;
+
+const MAX_SENDERS: usize = 32; // the longest-quiet sender makes room for a new one
+
+pub struct Sender {
+    pub addr: SocketAddr,
+    pub accepted: u64,
+    pub rejected: u64,
+    pub bytes: u64,
+    pub last_seen: Instant,
+}
+
+#[derive(Default)]
+pub struct AccessLog {
+    senders: Vec<Sender>,
+}
+
+impl AccessLog {
+    pub fn record(&mut self, addr: SocketAddr, bytes: usize, accepted: bool) {
+        let now = Instant::now();
+        let index = match self.senders.iter().position(|s| s.addr == addr) {
+            Some(index) => index,
+            None => {
+                if self.senders.len() == MAX_SENDERS {
+                    let quietest = (0..self.senders.len()).min_by_key(|&i| self.senders[i].last_seen).unwrap_or(0);
+                    self.senders.swap_remove(quietest);
+                }
+                self.senders.push(Sender { addr, accepted: 0, rejected: 0, bytes: 0, last_seen: now });
+                self.senders.len() - 1
+            }
+        };
+        let sender = &mut self.senders[index];
+        if accepted {
+            sender.accepted += 1;
+        } else {
+            sender.rejected += 1;
+        }
+        sender.bytes += bytes as u64;
+        sender.last_seen = now;
+    }
+
+    pub fn clear(&mut self) {
+        self.senders.clear();
+    }
+
+    /// Most recently heard first
+    pub fn senders(&self) -> Vec<&Sender> {
+        let mut senders: Vec<&Sender> = self.senders.iter().collect();
+        senders.sort_by_key(|s| std::cmp::Reverse(s.last_seen));
+        senders
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(last: u8) -> SocketAddr {
+        SocketAddr::from(([192, 168, 1, last], 4810))
+    }
+
+    #[test]
+    fn counts_per_sender_and_keeps_the_most_recent() {
+        let mut log = AccessLog::default();
+        log.record(addr(1), 100, true);
+        log.record(addr(1), 100, false);
+        for last in 2..=MAX_SENDERS as u8 + 1 {
+            log.record(addr(last), 10, false);
+        }
+        let senders = log.senders();
+        assert_eq!(senders.len(), MAX_SENDERS);
+        // The phone went quiet first, so it made room for the last newcomer
+        assert!(senders.iter().all(|s| s.addr != addr(1)));
+        assert!(senders.iter().any(|s| s.addr == addr(MAX_SENDERS as u8 + 1)));
+
+        log.record(addr(2), 10, true);
+        let two = log.senders().into_iter().find(|s| s.addr == addr(2)).map(|s| (s.accepted, s.rejected, s.bytes));
+        assert_eq!(two, Some((1, 1, 20)));
+    }
+}
@@ -0,0 +1,157 @@
+// Experimental AES67 sender: the PC capture as an RTP multicast stream (L24/48000/1,
+// 1 ms packets) announced via SAP, so Dante (in AES67 mode), Ravenna and other
+// AES67 receivers can subscribe to it.
+//
+// There is no PTP. RTP timestamps come from the system clock (TAI = UTC + 37 s),
+// which is only as good as NTP. Receivers locked to a PTP grandmaster will see it
+// drift and may resync now and then, which is fine for monitoring but not for
+// sample-accurate work.
+
+use anyhow::{anyhow, Result};
+use parking_lot::Mutex;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_GROUP: Ipv4Addr = Ipv4Addr::new(239, 69, 83, 67);
+pub const DEFAULT_PORT: u16 = 5004;
+const PAYLOAD_TYPE: u8 = 96;
+const SAMPLE_RATE: u64 = 48_000;
+const SAMPLES_PER_PACKET: usize = 48; // 1 ms, the AES67 default packet time
+const TTL: u32 = 16;
+const TAI_OFFSET_SECS: u64 = 37;
+// Dante listens for SAP on the administratively scoped group, not 224.2.127.254
+const SAP_ADDR: (Ipv4Addr, u16) = (Ipv4Addr::new(239, 255, 255, 255), 9875);
+const SAP_INTERVAL: Duration = Duration::from_secs(30);
+
+struct FeedState {
+    socket: UdpSocket,
+    dest: SocketAddr,
+    pending: Vec<i16>,
+    sequence: u16,
+    timestamp: u32,
+    ssrc: u32,
+}
+
+/// Where the capture pipeline hands audio to the multicast stream
+#[derive(Clone)]
+pub struct Aes67Feed(Arc<Mutex<FeedState>>);
+
+impl Aes67Feed {
+    /// Queue 48 kHz mono samples and multicast every full packet
+    pub fn publish(&self, samples: &[i16]) {
+        let mut feed = self.0.lock();
+        feed.pending.extend_from_slice(samples);
+
+        while feed.pending.len() >= SAMPLES_PER_PACKET {
+            let mut packet = Vec::with_capacity(12 + SAMPLES_PER_PACKET * 3);
+            packet.extend_from_slice(&[0x80, PAYLOAD_TYPE]);
+            packet.extend_from_slice(&feed.sequence.to_be_bytes());
+            packet.extend_from_slice(&feed.timestamp.to_be_bytes());
+            packet.extend_from_slice(&feed.ssrc.to_be_bytes());
+            // L24 big-endian: the 16-bit sample in the top two bytes
+            for s in feed.pending.drain(..SAMPLES_PER_PACKET) {
+                let [hi, lo] = s.to_be_bytes();
+                packet.extend_from_slice(&[hi, lo, 0]);
+            }
+            let _ = feed.socket.send_to(&packet, feed.dest);
+            feed.sequence = feed.sequence.wrapping_add(1);
+            feed.timestamp = feed.timestamp.wrapping_add(SAMPLES_PER_PACKET as u32);
+        }
+    }
+}
+
+/// Stops announcing (and sends a SAP deletion) when dropped
+pub struct Aes67Sender {
+    stop_flag: Arc<AtomicBool>,
+    feed: Aes67Feed,
+}
+
+impl Aes67Sender {
+    /// `local_ip` picks the network interface the stream goes out on
+    pub fn start(group: Ipv4Addr, port: u16, local_ip: Ipv4Addr) -> Result<Self> {
+        if !group.is_multicast() {
+            return Err(anyhow!("{} is not a multicast address", group));
+        }
+        // Binding to the interface's address makes Windows send multicast through it
+        let socket = UdpSocket::bind((local_ip, 0))?;
+        socket.set_multicast_ttl_v4(TTL)?;
+        socket.set_multicast_loop_v4(false)?;
+
+        let ssrc = uuid::Uuid::new_v4().as_u128() as u32;
+        let feed = Aes67Feed(Arc::new(Mutex::new(FeedState {
+            socket: socket.try_clone()?,
+            dest: SocketAddr::new(IpAddr::V4(group), port),
+            pending: Vec::new(),
+            sequence: 0,
+            timestamp: media_clock_now(),
+            ssrc,
+        })));
+
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        let stop = stop_flag.clone();
+        let announcement = sap_packet(ssrc as u16, local_ip, &sdp(ssrc, local_ip, group, port), false);
+        let deletion = sap_packet(ssrc as u16, local_ip, &sdp(ssrc, local_ip, group, port), true);
+        thread::spawn(move || {
+            let mut last_announce: Option<Instant> = None;
+            while !stop.load(Ordering::SeqCst) {
+                if last_announce.is_none_or(|t| t.elapsed() >= SAP_INTERVAL) {
+                    let _ = socket.send_to(&announcement, SAP_ADDR);
+                    last_announce = Some(Instant::now());
+                }
+                thread::sleep(Duration::from_millis(200));
+            }
+            let _ = socket.send_to(&deletion, SAP_ADDR);
+        });
+
+        Ok(Self { stop_flag, feed })
+    }
+
+    pub fn feed(&self) -> Aes67Feed {
+        self.feed.clone()
+    }
+}
+
+impl Drop for Aes67Sender {
+    fn drop(&mut self) {
+        self.stop_flag.store(true, Ordering::SeqCst);
+    }
+}
+
+/// RTP timestamp for "now" on a 48 kHz clock counted from the PTP epoch
+fn media_clock_now() -> u32 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = now.as_secs() + TAI_OFFSET_SECS;
+    (secs * SAMPLE_RATE + now.subsec_nanos() as u64 * SAMPLE_RATE / 1_000_000_000) as u32
+}
+
+fn sdp(session_id: u32, local_ip: Ipv4Addr, group: Ipv4Addr, port: u16) -> String {
+    format!(
+        "v=0\r\n\
+         o=- {session_id} 0 IN IP4 {local_ip}\r\n\
+         s=BudBridge PC Audio\r\n\
+         c=IN IP4 {group}/{TTL}\r\n\
+         t=0 0\r\n\
+         a=clock-domain:PTPv2 0\r\n\
+         m=audio {port} RTP/AVP {PAYLOAD_TYPE}\r\n\
+         i=Mono\r\n\
+         a=rtpmap:{PAYLOAD_TYPE} L24/48000/1\r\n\
+         a=recvonly\r\n\
+         a=ptime:1\r\n\
+         a=ts-refclk:ptp=IEEE1588-2008:traceable\r\n\
+         a=mediaclk:direct=0\r\n"
+    )
+}
+
+/// SAP (RFC 2974) announcement or deletion carrying `sdp`
+fn sap_packet(message_id: u16, origin: Ipv4Addr, sdp: &str, delete: bool) -> Vec<u8> {
+    let flags = if delete { 0x24 } else { 0x20 }; // version 1, IPv4, T bit for deletion
+    let mut packet = vec![flags, 0];
+    packet.extend_from_slice(&message_id.to_be_bytes());
+    packet.extend_from_slice(&origin.octets());
+    packet.extend_from_slice(b"application/sdp\0");
+    packet.extend_from_slice(sdp.as_bytes());
+    packet
+}
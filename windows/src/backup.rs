@@ -0,0 +1,146 @@
+// Backup and restore of the config files as a zip (stored, no compression).
+// A backup holds devices.txt, default.txt, settings.txt and profiles.txt as they
+// are on disk. Restoring checks the whole archive first: it migrates it to the
+// current schema and rejects it if any line would not load, so a bad zip never
+// replaces a working config.
+
+use crate::protocol::StreamFormat;
+use crate::config;
+use crate::migrate::{self, ConfigFiles};
+use crate::{DEFAULT_DEVICE_FILE, DEVICES_FILE, PROFILES_FILE, SETTINGS_FILE};
+use anyhow::{anyhow, bail, Context, Result};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+// Far more than any real config; stops a wrong zip from being read into memory
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+fn entries(files: &ConfigFiles) -> [(&'static str, &String); 4] {
+    [
+        (DEVICES_FILE, &files.devices),
+        (DEFAULT_DEVICE_FILE, &files.default_device),
+        (SETTINGS_FILE, &files.settings),
+        (PROFILES_FILE, &files.profiles),
+    ]
+}
+
+fn entry_mut<'a>(files: &'a mut ConfigFiles, name: &str) -> Option<&'a mut String> {
+    match name {
+        DEVICES_FILE => Some(&mut files.devices),
+        DEFAULT_DEVICE_FILE => Some(&mut files.default_device),
+        SETTINGS_FILE => Some(&mut files.settings),
+        PROFILES_FILE => Some(&mut files.profiles),
+        _ => None,
+    }
+}
+
+/// Write `files` to budbridge-config-<UTC date and time>.zip in `dir`
+pub fn create(files: &ConfigFiles, dir: &Path) -> Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let path = dir.join(format!("budbridge-config-{}.zip", utc_stamp(secs)));
+
+    let mut zip = ZipWriter::new(File::create(&path)?);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    for (name, content) in entries(files) {
+        zip.start_file(name, options)?;
+        zip.write_all(content.as_bytes())?;
+    }
+    zip.finish()?;
+    Ok(path)
+}
+
+/// Read a backup and upgrade it to the current schema, or explain why it can't be restored
+pub fn open(path: &Path) -> Result<ConfigFiles> {
+    let file = File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+    let mut zip = ZipArchive::new(file).map_err(|_| anyhow!("{} is not a zip file", path.display()))?;
+
+    let mut files = ConfigFiles::default();
+    let mut found = 0;
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i)?;
+        let name = entry.name().rsplit('/').next().unwrap_or_default().to_string();
+        let Some(content) = entry_mut(&mut files, &name) else {
+            continue;
+        };
+        if entry.size() > MAX_FILE_SIZE {
+            bail!("{} in the backup is too large to be a BudBridge config", name);
+        }
+        let mut bytes = Vec::new();
+        entry.by_ref().take(MAX_FILE_SIZE).read_to_end(&mut bytes)?;
+        *content = String::from_utf8(bytes).map_err(|_| anyhow!("{} in the backup is not text", name))?;
+        found += 1;
+    }
+    if found == 0 {
+        bail!("{} is not a BudBridge config backup", path.display());
+    }
+
+    if let migrate::Outcome::TooNew(version) = migrate::upgrade(&mut files) {
+        bail!("The backup is from a newer BudBridge (config version {})", version);
+    }
+    validate(&files)?;
+    Ok(files)
+}
+
+/// Check that every line loads the way main.rs reads it
+fn validate(files: &ConfigFiles) -> Result<()> {
+    let lines = |text: &'static str, content: &str| {
+        content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(move |(i, line)| (text, i + 1, line.to_string()))
+            .collect::<Vec<_>>()
+    };
+
+    let mut ids = Vec::new();
+    for (name, number, line) in lines(DEVICES_FILE, &files.devices) {
+        match config::split_fields(&line).as_slice() {
+            [device, ip, id] if !device.is_empty() && !ip.is_empty() && !id.is_empty() => ids.push(id.clone()),
+            _ => bail!("{} line {} is not a saved device", name, number),
+        }
+    }
+
+    let default_device = files.default_device.trim();
+    if !default_device.is_empty() && !ids.iter().any(|id| id == default_device) {
+        bail!("{} names a device that isn't in {}", DEFAULT_DEVICE_FILE, DEVICES_FILE);
+    }
+
+    for (name, number, line) in lines(SETTINGS_FILE, &files.settings) {
+        if !line.contains('=') {
+            bail!("{} line {} is not key=value", name, number);
+        }
+    }
+
+    for (name, number, line) in lines(PROFILES_FILE, &files.profiles) {
+        let fields = config::split_fields(&line);
+        let valid = fields.len() == 10
+            && !fields[0].is_empty()
+            && fields[4..6].iter().all(|gain| gain.parse::<f32>().is_ok())
+            && fields[8..10].iter().all(|format| StreamFormat::parse(format).is_some());
+        if !valid {
+            bail!("{} line {} is not a profile", name, number);
+        }
+    }
+    Ok(())
+}
+
+/// "2026-03-07-1405" for a Unix time, in UTC
+fn utc_stamp(secs: u64) -> String {
+    // Days to a civil date, from Howard Hinnant's date algorithms
+    let days = (secs / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    let minutes = secs % 86400 / 60;
+    format!("{:04}-{:02}-{:02}-{:02}{:02}", year, month, day, minutes / 60, minutes % 60)
+}
@@ -0,0 +1,152 @@
+// Battery saver, for streaming all day on a laptop. It sends Opus down in 40 ms
+// frames, so half the datagrams wake the Wi-Fi radio. It also caps both directions
+// at a lighter format and the UI at a few repaints a second. The network and UI
+// threads are marked for EcoQoS (SetThreadInformation), which lets Windows run
+// them slower or on efficient cores.
+//
+// It's off, always on, or on while Windows reports DC power (GetSystemPowerStatus,
+// polled by the UI). Formats and frame length are fixed at connect, so a switch
+// mid-session reconnects.
+
+use crate::protocol::{Codec, Session, StreamFormat};
+
+/// Frame length asked for going down while saving (see protocol::PTIMES). 40 ms
+/// rather than 60 keeps two frames inside the phone's 100 ms jitter buffer.
+pub const PTIME: u32 = 40;
+/// Repaint cap while saving, below repaint::MAX_FPS_RANGE's usual settings
+pub const MAX_FPS: u32 = 10;
+/// Heaviest formats kept while saving; anything heavier steps down to these
+const MAX_DOWN: StreamFormat = StreamFormat::DOWN_CHOICES[4];
+const MAX_UP: StreamFormat = StreamFormat::UP_CHOICES[2];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Mode {
+    #[default]
+    Off,
+    OnBattery,
+    Always,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 3] = [Mode::Off, Mode::OnBattery, Mode::Always];
+
+    /// Unknown settings are off
+    pub fn parse(text: &str) -> Self {
+        match text.trim() {
+            "battery" => Mode::OnBattery,
+            "always" => Mode::Always,
+            _ => Mode::Off,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Mode::Off => "off",
+            Mode::OnBattery => "battery",
+            Mode::Always => "always",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Mode::Off => "Off",
+            Mode::OnBattery => "On battery power",
+            Mode::Always => "Always",
+        }
+    }
+
+    pub fn active(&self, on_battery: bool) -> bool {
+        match self {
+            Mode::Off => false,
+            Mode::OnBattery => on_battery,
+            Mode::Always => true,
+        }
+    }
+}
+
+/// `session` as the saver streams it: formats no heavier than MAX_DOWN and
+/// MAX_UP, and longer frames going down when that's Opus
+pub fn lighten(mut session: Session) -> Session {
+    let cap = |format: StreamFormat, max: StreamFormat| if format.bitrate_kbps > max.bitrate_kbps { max } else { format };
+    session.formats.down = cap(session.formats.down, MAX_DOWN);
+    session.formats.up = cap(session.formats.up, MAX_UP);
+    session.ptime = (session.formats.down.codec == Codec::Opus).then_some(PTIME);
+    session
+}
+
+#[cfg(target_os = "windows")]
+pub use imp::{on_battery, set_eco_qos};
+
+#[cfg(not(target_os = "windows"))]
+pub use fallback::{on_battery, set_eco_qos};
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use std::ffi::c_void;
+    use windows_sys::Win32::System::Power::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+    use windows_sys::Win32::System::Threading::{
+        GetCurrentThread, SetThreadInformation, ThreadPowerThrottling, THREAD_POWER_THROTTLING_CURRENT_VERSION,
+        THREAD_POWER_THROTTLING_EXECUTION_SPEED, THREAD_POWER_THROTTLING_STATE,
+    };
+
+    /// Whether the PC runs on its battery. Desktops, and unknown states, aren't.
+    pub fn on_battery() -> bool {
+        let mut status: SYSTEM_POWER_STATUS = unsafe { std::mem::zeroed() };
+        let ok = unsafe { GetSystemPowerStatus(&mut status) };
+        ok != 0 && status.ACLineStatus == 0
+    }
+
+    /// Marks the calling thread for EcoQoS, or hands it back to Windows' own choice.
+    /// Best effort: Windows before 10 1709 doesn't know the setting.
+    pub fn set_eco_qos(eco: bool) {
+        let mask = if eco { THREAD_POWER_THROTTLING_EXECUTION_SPEED } else { 0 };
+        let state = THREAD_POWER_THROTTLING_STATE {
+            Version: THREAD_POWER_THROTTLING_CURRENT_VERSION,
+            ControlMask: mask,
+            StateMask: mask,
+        };
+        unsafe {
+            SetThreadInformation(
+                GetCurrentThread(),
+                ThreadPowerThrottling,
+                &state as *const THREAD_POWER_THROTTLING_STATE as *const c_void,
+                std::mem::size_of::<THREAD_POWER_THROTTLING_STATE>() as u32,
+            );
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod fallback {
+    pub fn on_battery() -> bool {
+        false
+    }
+
+    pub fn set_eco_qos(_eco: bool) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Formats;
+
+    #[test]
+    fn saving_lightens_both_ways_and_lengthens_opus_frames() {
+        for mode in Mode::ALL {
+            assert_eq!(Mode::parse(mode.as_str()), mode);
+        }
+        assert_eq!(Mode::parse("sometimes"), Mode::Off);
+        assert!(!Mode::OnBattery.active(false) && Mode::OnBattery.active(true) && Mode::Always.active(false));
+
+        let heavy = Session { formats: Formats { down: StreamFormat::DOWN_CHOICES[2], up: StreamFormat::PCM_MONO }, ..Default::default() };
+        let light = lighten(heavy);
+        assert_eq!(light.formats, Formats { down: MAX_DOWN, up: MAX_UP });
+        assert_eq!(light.ptime, Some(PTIME));
+
+        // Already light enough stays as it is; plain PCM is never light enough
+        let mono = Session { formats: Formats { down: StreamFormat::DOWN_CHOICES[5], up: StreamFormat::UP_CHOICES[3] }, ..Default::default() };
+        assert_eq!(lighten(mono).formats, mono.formats);
+        let pcm = Formats { down: StreamFormat::PCM_MONO, up: StreamFormat::UP_CHOICES[3] };
+        assert_eq!(lighten(Session { formats: pcm, ..Default::default() }).formats.down, MAX_DOWN);
+    }
+}
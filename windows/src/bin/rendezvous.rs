@@ -0,0 +1,137 @@
+// Rendezvous server for BudBridge's internet mode. Run it on any host with a
+// public IP (a small VPS is plenty). It introduces the PC and the phone to each
+// other, and they punch through their NATs to talk directly. When that fails
+// (a symmetric NAT on a carrier network), the PC asks for a relay and the audio
+// then goes through here, both ways, at its full bitrate.
+//
+//   rendezvous [port]        (default 4820/udp)
+//
+// Protocol (UTF-8 datagrams):
+//   client -> "BBRV1 REGISTER <code> pc|phone"   repeated every few seconds
+//   server -> "BBRV1 PEER <ip:port>"             once both roles registered a code
+//   client -> "BBRV1 RELAY <code> pc|phone"      from a registered address
+//   server -> "BBRV1 RELAYING"                   to both; from then on anything else
+//                                                one of them sends is passed to the other
+//
+// Only the two registered addresses are relayed between, and a relay lasts while
+// audio flows.
+
+use std::collections::HashMap;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+const DEFAULT_PORT: u16 = 4820;
+const EXPIRY: Duration = Duration::from_secs(60);
+const MAX_CODE_LEN: usize = 64;
+// Audio datagrams, sealed and with parity, stay well under this
+const MAX_DATAGRAM: usize = 2048;
+
+#[derive(Default)]
+struct Session {
+    pc: Option<(SocketAddr, Instant)>,
+    phone: Option<(SocketAddr, Instant)>,
+    relay: bool,
+}
+
+impl Session {
+    /// Where a relayed datagram from `src` goes, refreshing both ends
+    fn forward(&mut self, src: SocketAddr, now: Instant) -> Option<SocketAddr> {
+        let (Some(pc), Some(phone)) = (&mut self.pc, &mut self.phone) else {
+            return None;
+        };
+        if !self.relay || (src != pc.0 && src != phone.0) {
+            return None;
+        }
+        (pc.1, phone.1) = (now, now);
+        Some(if src == pc.0 { phone.0 } else { pc.0 })
+    }
+}
+
+fn main() -> std::io::Result<()> {
+    let port = std::env::args()
+        .nth(1)
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(DEFAULT_PORT);
+    let socket = UdpSocket::bind(("0.0.0.0", port))?;
+    println!("BudBridge rendezvous listening on udp/{}", port);
+
+    let mut sessions: HashMap<String, Session> = HashMap::new();
+    // Addresses of relayed sessions, for finding audio's session without a code
+    let mut relayed: HashMap<SocketAddr, String> = HashMap::new();
+    let mut buf = [0u8; MAX_DATAGRAM];
+
+    loop {
+        let (len, src) = match socket.recv_from(&mut buf) {
+            Ok(r) => r,
+            Err(e) => {
+                eprintln!("recv error: {}", e);
+                continue;
+            }
+        };
+        let now = Instant::now();
+        if !buf.starts_with(b"BBRV1 ") {
+            let to = relayed.get(&src).and_then(|code| sessions.get_mut(code)).and_then(|s| s.forward(src, now));
+            if let Some(to) = to {
+                let _ = socket.send_to(&buf[..len], to);
+            }
+            continue;
+        }
+        if let Some((code, role)) = parse(&buf[..len], "RELAY") {
+            let Some(session) = sessions.get_mut(code) else {
+                continue;
+            };
+            let registered = match role {
+                "pc" => session.pc,
+                "phone" => session.phone,
+                _ => None,
+            };
+            if let (Some((from, _)), Some((pc, _)), Some((phone, _))) = (registered, session.pc, session.phone) {
+                if from == src {
+                    session.relay = true;
+                    relayed.insert(pc, code.to_string());
+                    relayed.insert(phone, code.to_string());
+                    let _ = socket.send_to(b"BBRV1 RELAYING", pc);
+                    let _ = socket.send_to(b"BBRV1 RELAYING", phone);
+                    println!("session {}: relaying pc {} <-> phone {}", code, pc, phone);
+                }
+            }
+            continue;
+        }
+        let Some((code, role)) = parse(&buf[..len], "REGISTER") else {
+            continue;
+        };
+
+        sessions.retain(|_, s| {
+            s.pc = s.pc.filter(|(_, t)| now.duration_since(*t) < EXPIRY);
+            s.phone = s.phone.filter(|(_, t)| now.duration_since(*t) < EXPIRY);
+            s.pc.is_some() || s.phone.is_some()
+        });
+        relayed.retain(|addr, code| {
+            sessions.get(code).is_some_and(|s| s.relay && [s.pc, s.phone].iter().flatten().any(|(a, _)| a == addr))
+        });
+
+        let session = sessions.entry(code.to_string()).or_default();
+        match role {
+            "pc" => session.pc = Some((src, now)),
+            "phone" => session.phone = Some((src, now)),
+            _ => continue,
+        }
+
+        // Registering again starts over with punching
+        session.relay = false;
+        if let (Some((pc, _)), Some((phone, _))) = (session.pc, session.phone) {
+            let _ = socket.send_to(format!("BBRV1 PEER {}", phone).as_bytes(), pc);
+            let _ = socket.send_to(format!("BBRV1 PEER {}", pc).as_bytes(), phone);
+            println!("session {}: pc {} <-> phone {}", code, pc, phone);
+        }
+    }
+}
+
+/// Code and role of a "BBRV1 <verb> <code> <role>" message
+fn parse<'a>(data: &'a [u8], verb: &str) -> Option<(&'a str, &'a str)> {
+    let text = std::str::from_utf8(data).ok()?;
+    let mut parts = text.trim().strip_prefix("BBRV1 ")?.strip_prefix(verb)?.strip_prefix(' ')?.split(' ');
+    let code = parts.next().filter(|c| !c.is_empty() && c.len() <= MAX_CODE_LEN)?;
+    let role = parts.next()?;
+    Some((code, role))
+}
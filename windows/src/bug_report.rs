@@ -0,0 +1,52 @@
+// The error banner's log excerpt and its "Copy for bug report" text. Every
+// log_message line is also kept here in memory, whether or not debug logging is
+// writing a file, so the banner can show what led up to an error.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+const KEPT_LINES: usize = 200;
+pub const BANNER_LINES: usize = 20;
+
+static RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+pub fn remember(line: String) {
+    if let Ok(mut recent) = RECENT.lock() {
+        if recent.len() == KEPT_LINES {
+            recent.pop_front();
+        }
+        recent.push_back(line);
+    }
+}
+
+/// The last `count` log lines, oldest first
+pub fn recent(count: usize) -> Vec<String> {
+    RECENT
+        .lock()
+        .map(|recent| recent.iter().skip(recent.len().saturating_sub(count)).cloned().collect())
+        .unwrap_or_default()
+}
+
+pub struct Report {
+    pub error: String,
+    pub log: Vec<String>,
+    /// e.g. ("Capture", "Speakers (Loopback)")
+    pub details: Vec<(&'static str, String)>,
+}
+
+impl Report {
+    /// Markdown for pasting into a GitHub issue
+    pub fn to_markdown(&self) -> String {
+        let mut text = format!("### Error\n\n```\n{}\n```\n\n### Environment\n\n", self.error);
+        for (name, value) in &self.details {
+            text.push_str(&format!("- **{}:** {}\n", name, value));
+        }
+        text.push_str(&format!("\n### Last {} log lines\n\n```\n", self.log.len()));
+        for line in &self.log {
+            text.push_str(line);
+            text.push('\n');
+        }
+        text.push_str("```\n");
+        text
+    }
+}
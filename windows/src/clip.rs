@@ -0,0 +1,129 @@
+// Peak hold and clip latch for one direction, shared between an audio callback
+// and the UI. The callback records every buffer; the UI shows the highest peak
+// since the user last cleared it and a red light once anything reached full
+// scale, so crunchy audio has a visible cause.
+//
+// Between stages the audio is float with headroom: gain, the test chime and the
+// processing chain may all go past full scale without losing anything, and only
+// the last step before the encoder (or the playback device) clamps. Saturation
+// counts what that step had to cut.
+
+use crate::convert;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+/// The largest 16-bit sample, where the wire format clips
+pub const FULL_SCALE: f32 = 32767.0 / 32768.0;
+
+/// Peak magnitude as f32 bits. For non-negative floats the bit patterns order
+/// the same way as the values, so fetch_max keeps the highest.
+#[derive(Default)]
+pub struct PeakHold(AtomicU32);
+
+impl PeakHold {
+    pub fn record(&self, samples: &[f32]) {
+        let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        if peak.is_finite() {
+            self.0.fetch_max(peak.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    pub fn peak(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn clipped(&self) -> bool {
+        self.peak() >= FULL_SCALE
+    }
+
+    pub fn clear(&self) {
+        self.0.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Samples through the final clamp, and how many were over full scale
+#[derive(Default)]
+pub struct Saturation {
+    total: AtomicU64,
+    clipped: AtomicU64,
+}
+
+impl Saturation {
+    /// Clamps to what the output can carry, counting each sample that was cut
+    pub fn quantize(&self, samples: &mut [f32]) {
+        let mut clipped = 0;
+        for sample in samples.iter_mut() {
+            // NaN from a filter gone unstable is cut to silence
+            if sample.is_nan() || sample.abs() > 1.0 {
+                *sample = convert::clamp(*sample);
+                clipped += 1;
+            }
+        }
+        self.total.fetch_add(samples.len() as u64, Ordering::Relaxed);
+        self.clipped.fetch_add(clipped, Ordering::Relaxed);
+    }
+
+    pub fn clipped(&self) -> u64 {
+        self.clipped.load(Ordering::Relaxed)
+    }
+
+    /// Share of samples cut, 0 to 1
+    pub fn ratio(&self) -> f64 {
+        let total = self.total.load(Ordering::Relaxed);
+        if total == 0 {
+            0.0
+        } else {
+            self.clipped() as f64 / total as f64
+        }
+    }
+
+    pub fn clear(&self) {
+        self.total.store(0, Ordering::Relaxed);
+        self.clipped.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn holds_the_highest_peak_until_cleared() {
+        let hold = PeakHold::default();
+        hold.record(&[0.1, -0.5, 0.25]);
+        hold.record(&[0.2]);
+        assert_eq!(hold.peak(), 0.5);
+        assert!(!hold.clipped());
+
+        hold.record(&[-1.0]);
+        assert!(hold.clipped());
+        hold.record(&[0.3]);
+        assert!(hold.clipped());
+
+        hold.clear();
+        assert_eq!(hold.peak(), 0.0);
+        hold.record(&[32767.0 / 32768.0]);
+        assert!(hold.clipped());
+    }
+
+    #[test]
+    fn only_the_final_clamp_counts() {
+        // Two stages that would each have clipped alone cancel out with headroom
+        let mut samples = vec![0.8, -0.9, 0.5, 0.0];
+        samples.iter_mut().for_each(|s| *s *= 2.0);
+        samples.iter_mut().for_each(|s| *s *= 0.5);
+        let saturation = Saturation::default();
+        saturation.quantize(&mut samples);
+        assert_eq!(saturation.clipped(), 0);
+        assert_eq!(samples, [0.8, -0.9, 0.5, 0.0]);
+
+        let mut hot = vec![1.5, -2.0, 0.5, 1.0];
+        saturation.quantize(&mut hot);
+        assert_eq!(hot, [1.0, -1.0, 0.5, 1.0]);
+        assert_eq!(saturation.clipped(), 2);
+        assert_eq!(saturation.ratio(), 0.25);
+
+        let mut unstable = vec![f32::NAN, 0.5];
+        saturation.quantize(&mut unstable);
+        assert_eq!(unstable, [0.0, 0.5]);
+    }
+}
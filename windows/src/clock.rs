@@ -0,0 +1,165 @@
+// Network delay each way during a session, NTP style. Every second the PC sends
+// a clock probe stamped with its monotonic time (t1). The phone stamps when it
+// got it (t2) and when it echoed it (t3) on its own clock, and the PC notes when
+// the echo arrived (t4):
+//
+//   round trip = (t4 - t1) - (t3 - t2)
+//   offset     = ((t2 - t1) + (t3 - t4)) / 2    phone clock minus PC clock
+//
+// The offset assumes both ways took as long. The exchange with the smallest
+// round trip in the window is the one queueing disturbed least, so its offset is
+// kept (NTP's clock filter) and later exchanges are split with it. A queue
+// building up in one direction then shows in that direction only. The baseline
+// itself is still shared half and half, since no clock exchange can tell an
+// asymmetric path from an offset.
+//
+// Apps from before the stamps echo the probe unchanged, which still gives the
+// round trip.
+
+use crate::protocol::{self, Probe};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+pub const INTERVAL: Duration = Duration::from_secs(1);
+/// Exchanges the clock filter picks from
+const WINDOW: usize = 30;
+/// Recent exchanges the shown delays are the median of
+const RECENT: usize = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Estimate {
+    pub rtt_ms: f32,
+    /// PC → phone and phone → PC; None while the phone doesn't stamp
+    pub one_way_ms: Option<(f32, f32)>,
+}
+
+struct Exchange {
+    t1: i64,
+    t4: i64,
+    stamps: Option<(i64, i64)>,
+}
+
+impl Exchange {
+    fn rtt(&self) -> i64 {
+        let held = self.stamps.map_or(0, |(t2, t3)| t3 - t2);
+        (self.t4 - self.t1 - held).max(0)
+    }
+
+    fn offset(&self) -> Option<i64> {
+        self.stamps.map(|(t2, t3)| ((t2 - self.t1) + (t3 - self.t4)) / 2)
+    }
+}
+
+pub struct ClockSync {
+    start: Instant,
+    seq: u32,
+    last_sent: Option<Instant>,
+    exchanges: VecDeque<Exchange>,
+}
+
+impl ClockSync {
+    pub fn new(start: Instant) -> Self {
+        Self { start, seq: 0, last_sent: None, exchanges: VecDeque::new() }
+    }
+
+    fn micros(&self, at: Instant) -> u64 {
+        at.saturating_duration_since(self.start).as_micros() as u64
+    }
+
+    /// The next probe to send, once a second
+    pub fn request_due(&mut self, now: Instant) -> Option<Vec<u8>> {
+        if self.last_sent.is_some_and(|t| now.duration_since(t) < INTERVAL) {
+            return None;
+        }
+        self.last_sent = Some(now);
+        self.seq = self.seq.wrapping_add(1);
+        let mut packet = vec![0u8; protocol::CLOCK_PROBE_SIZE];
+        Probe { stage: protocol::CLOCK_STAGE, seq: self.seq, sent_at_us: self.micros(now) }.write(&mut packet);
+        Some(packet)
+    }
+
+    /// An echoed probe; anything else is left alone. True when it was one.
+    pub fn echo(&mut self, packet: &[u8], now: Instant) -> bool {
+        let Some(probe) = Probe::parse(packet).filter(|p| p.stage == protocol::CLOCK_STAGE) else {
+            return false;
+        };
+        let t4 = self.micros(now);
+        if probe.sent_at_us <= t4 {
+            let stamps = protocol::clock_stamps(packet).map(|(t2, t3)| (t2 as i64, t3 as i64));
+            self.exchanges.push_back(Exchange { t1: probe.sent_at_us as i64, t4: t4 as i64, stamps });
+            while self.exchanges.len() > WINDOW {
+                self.exchanges.pop_front();
+            }
+        }
+        true
+    }
+
+    pub fn estimate(&self) -> Option<Estimate> {
+        let recent: Vec<&Exchange> = self.exchanges.iter().rev().take(RECENT).collect();
+        let rtt_ms = median(recent.iter().map(|e| e.rtt()))? as f32 / 1000.0;
+        let one_way_ms = self
+            .exchanges
+            .iter()
+            .filter(|e| e.stamps.is_some())
+            .min_by_key(|e| e.rtt())
+            .and_then(Exchange::offset)
+            .and_then(|offset| {
+                let stamped = || recent.iter().filter_map(|e| Some((e, e.stamps?)));
+                let down = median(stamped().map(|(e, (t2, _))| (t2 - offset - e.t1).max(0)))?;
+                let up = median(stamped().map(|(e, (_, t3))| (e.t4 - (t3 - offset)).max(0)))?;
+                Some((down as f32 / 1000.0, up as f32 / 1000.0))
+            });
+        Some(Estimate { rtt_ms, one_way_ms })
+    }
+}
+
+fn median(values: impl Iterator<Item = i64>) -> Option<i64> {
+    let mut values: Vec<i64> = values.collect();
+    values.sort_unstable();
+    values.get(values.len() / 2).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The phone's echo of `request`, its clock `offset` µs ahead of the PC's
+    fn echo_from_phone(request: &[u8], arrived_us: u64, offset: u64) -> Vec<u8> {
+        let mut echo = request.to_vec();
+        echo[protocol::PROBE_HEADER_SIZE..][..8].copy_from_slice(&(arrived_us + offset).to_le_bytes());
+        echo[protocol::PROBE_HEADER_SIZE + 8..][..8].copy_from_slice(&(arrived_us + offset + 200).to_le_bytes());
+        echo
+    }
+
+    #[test]
+    fn splits_the_round_trip_with_the_quietest_exchange() {
+        let start = Instant::now();
+        let mut clock = ClockSync::new(start);
+        let offset = 3_600_000_000;
+        let ms = |n: u64| Duration::from_millis(n);
+
+        // 5 ms each way at first, then a queue builds on the way back only
+        for (second, up_ms) in [(0, 5), (1, 5), (2, 25), (3, 25), (4, 25)] {
+            let sent = start + Duration::from_secs(second);
+            let request = clock.request_due(sent).unwrap();
+            assert!(clock.request_due(sent + ms(500)).is_none());
+            let arrived = sent + ms(5);
+            let echo = echo_from_phone(&request, clock.micros(arrived), offset);
+            assert!(clock.echo(&echo, arrived + Duration::from_micros(200) + ms(up_ms)));
+        }
+        let estimate = clock.estimate().unwrap();
+        assert_eq!(estimate.rtt_ms, 30.0);
+        assert_eq!(estimate.one_way_ms, Some((5.0, 25.0)));
+
+        // An app that echoes unchanged still gives the round trip
+        let mut plain = ClockSync::new(start);
+        let request = plain.request_due(start).unwrap();
+        assert!(plain.echo(&request, start + ms(12)));
+        assert_eq!(plain.estimate(), Some(Estimate { rtt_ms: 12.0, one_way_ms: None }));
+
+        // Link test probes aren't clock echoes
+        let mut link = [0u8; 1200];
+        Probe { stage: 0, seq: 1, sent_at_us: 0 }.write(&mut link);
+        assert!(!plain.echo(&link, start));
+    }
+}
@@ -0,0 +1,124 @@
+// Default communications capture device switching.
+//
+// Discord/Teams/Zoom follow the Windows "default communications device" unless told
+// otherwise, so pointing it at the virtual cable's mic side saves a trip to Sound
+// settings. Windows has no documented API for changing default endpoints; this uses
+// the IPolicyConfig interface that the Sound control panel itself uses.
+
+#[cfg(target_os = "windows")]
+pub use imp::*;
+
+#[cfg(not(target_os = "windows"))]
+pub use fallback::*;
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use anyhow::{anyhow, Result};
+    use std::ffi::c_void;
+    use windows::core::{interface, IUnknown, IUnknown_Vtbl, GUID, HRESULT, PCWSTR, PWSTR};
+    use windows::Win32::Devices::FunctionDiscovery::PKEY_Device_FriendlyName;
+    use windows::Win32::Media::Audio::{
+        eCapture, eCommunications, ERole, IMMDevice, IMMDeviceEnumerator, MMDeviceEnumerator,
+        DEVICE_STATE_ACTIVE,
+    };
+    use windows::Win32::System::Com::{
+        CoCreateInstance, CoInitializeEx, CoTaskMemFree, CLSCTX_ALL, COINIT_MULTITHREADED, STGM_READ,
+    };
+
+    const CLSID_POLICY_CONFIG: GUID = GUID::from_u128(0x870af99c_171d_4f9e_af0d_e63df40c2bc9);
+
+    // Undocumented; vtable order must match the Windows 7+ layout exactly
+    #[interface("f8679f50-850a-41cf-9c72-430f290290c8")]
+    unsafe trait IPolicyConfig: IUnknown {
+        fn GetMixFormat(&self, id: PCWSTR, format: *mut *mut c_void) -> HRESULT;
+        fn GetDeviceFormat(&self, id: PCWSTR, default: i32, format: *mut *mut c_void) -> HRESULT;
+        fn ResetDeviceFormat(&self, id: PCWSTR) -> HRESULT;
+        fn SetDeviceFormat(&self, id: PCWSTR, endpoint: *mut c_void, mix: *mut c_void) -> HRESULT;
+        fn GetProcessingPeriod(&self, id: PCWSTR, default: i32, default_period: *mut i64, min_period: *mut i64) -> HRESULT;
+        fn SetProcessingPeriod(&self, id: PCWSTR, period: *mut i64) -> HRESULT;
+        fn GetShareMode(&self, id: PCWSTR, mode: *mut c_void) -> HRESULT;
+        fn SetShareMode(&self, id: PCWSTR, mode: *mut c_void) -> HRESULT;
+        fn GetPropertyValue(&self, id: PCWSTR, key: *const c_void, value: *mut c_void) -> HRESULT;
+        fn SetPropertyValue(&self, id: PCWSTR, key: *const c_void, value: *mut c_void) -> HRESULT;
+        fn SetDefaultEndpoint(&self, id: PCWSTR, role: ERole) -> HRESULT;
+        fn SetEndpointVisibility(&self, id: PCWSTR, visible: i32) -> HRESULT;
+    }
+
+    fn enumerator() -> Result<IMMDeviceEnumerator> {
+        unsafe {
+            // Already-initialized (e.g. STA on the UI thread) is fine; COM stays usable
+            let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+            Ok(CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?)
+        }
+    }
+
+    fn device_id(device: &IMMDevice) -> Result<String> {
+        unsafe {
+            let raw: PWSTR = device.GetId()?;
+            let id = raw.to_string();
+            CoTaskMemFree(Some(raw.0 as *const c_void));
+            Ok(id?)
+        }
+    }
+
+    fn friendly_name(device: &IMMDevice) -> Result<String> {
+        unsafe {
+            let store = device.OpenPropertyStore(STGM_READ)?;
+            Ok(store.GetValue(&PKEY_Device_FriendlyName)?.to_string())
+        }
+    }
+
+    /// Endpoint ID of the first active capture device whose name contains `name_match`
+    pub fn find_capture_device(name_match: &str) -> Result<Option<String>> {
+        let name_match = name_match.to_lowercase();
+        unsafe {
+            let devices = enumerator()?.EnumAudioEndpoints(eCapture, DEVICE_STATE_ACTIVE)?;
+            for i in 0..devices.GetCount()? {
+                let device = devices.Item(i)?;
+                if friendly_name(&device)?.to_lowercase().contains(&name_match) {
+                    return Ok(Some(device_id(&device)?));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn default_communications_capture() -> Result<Option<String>> {
+        unsafe {
+            match enumerator()?.GetDefaultAudioEndpoint(eCapture, eCommunications) {
+                Ok(device) => Ok(Some(device_id(&device)?)),
+                // No capture devices at all
+                Err(_) => Ok(None),
+            }
+        }
+    }
+
+    pub fn set_default_communications_capture(id: &str) -> Result<()> {
+        let wide: Vec<u16> = id.encode_utf16().chain(std::iter::once(0)).collect();
+        unsafe {
+            let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+            let policy: IPolicyConfig = CoCreateInstance(&CLSID_POLICY_CONFIG, None, CLSCTX_ALL)?;
+            policy
+                .SetDefaultEndpoint(PCWSTR(wide.as_ptr()), eCommunications)
+                .ok()
+                .map_err(|e| anyhow!("SetDefaultEndpoint failed: {}", e))
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod fallback {
+    use anyhow::{anyhow, Result};
+
+    pub fn find_capture_device(_name_match: &str) -> Result<Option<String>> {
+        Err(anyhow!("Default device switching is only supported on Windows"))
+    }
+
+    pub fn default_communications_capture() -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    pub fn set_default_communications_capture(_id: &str) -> Result<()> {
+        Err(anyhow!("Default device switching is only supported on Windows"))
+    }
+}
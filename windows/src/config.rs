@@ -0,0 +1,206 @@
+// Crash-safe config file access. Writes go to a temp file that is renamed over
+// the real one, keeping the previous version as "<file>.bak". Reads fall back to
+// that backup when the file is unreadable or has lines that don't parse.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// Problems found while loading or saving, shown once in the UI
+static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+pub fn warn(message: String) {
+    // Settings are re-read per key, so the same problem is reported repeatedly
+    if let Ok(mut warnings) = WARNINGS.lock() {
+        if !warnings.contains(&message) {
+            warnings.push(message);
+        }
+    }
+}
+
+pub fn take_warnings() -> Vec<String> {
+    WARNINGS.lock().map(|mut w| std::mem::take(&mut *w)).unwrap_or_default()
+}
+
+/// Join fields with '|', escaping '\\', '|' and line breaks so any text round-trips
+pub fn join_fields(fields: &[&str]) -> String {
+    let mut line = String::new();
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            line.push('|');
+        }
+        for c in field.chars() {
+            match c {
+                '\\' => line.push_str("\\\\"),
+                '|' => line.push_str("\\|"),
+                '\n' => line.push_str("\\n"),
+                '\r' => line.push_str("\\r"),
+                c => line.push(c),
+            }
+        }
+    }
+    line
+}
+
+/// Inverse of join_fields. Unknown escapes keep their backslash, so files written
+/// before escaping existed (e.g. a name like "Studio\PC") still read back unchanged.
+pub fn split_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        let field = fields.last_mut().expect("always at least one field");
+        match c {
+            '|' => fields.push(String::new()),
+            '\\' => match chars.peek() {
+                Some('\\') | Some('|') => field.push(chars.next().expect("peeked")),
+                Some('n') => {
+                    chars.next();
+                    field.push('\n');
+                }
+                Some('r') => {
+                    chars.next();
+                    field.push('\r');
+                }
+                _ => field.push('\\'),
+            },
+            c => field.push(c),
+        }
+    }
+    fields
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+fn display_name(path: &Path) -> String {
+    path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+}
+
+/// `backup` keeps the file being replaced as "<file>.bak"; not when it's the
+/// damaged copy the backup was just restored from
+fn write_atomic(path: &Path, content: &str, backup: bool) -> io::Result<()> {
+    let tmp = sibling(path, "tmp");
+    {
+        let mut file = File::create(&tmp)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+    }
+    if backup && path.exists() {
+        fs::copy(path, sibling(path, "bak"))?;
+    }
+    fs::rename(&tmp, path)
+}
+
+/// Replace `path` with `content` without ever leaving a half-written file behind
+pub fn save(path: &Path, content: &str) {
+    if let Err(e) = write_atomic(path, content, true) {
+        warn(format!("Error saving {}: {}", display_name(path), e));
+    }
+}
+
+/// Parse the file at `path`, or its backup if the file is damaged.
+///
+/// `parse` returns the entries it understood plus the number of lines it had to
+/// skip. A missing file is simply empty. A backup that's used is written back over
+/// the damaged file, so the next save can't copy the damage over the backup. If
+/// neither copy is clean, the entries that
+/// could be read from the main file are kept and a copy is saved as "<file>.corrupt"
+/// so the next save doesn't destroy what's left.
+pub fn load<T: Default>(path: &Path, mut parse: impl FnMut(&str) -> (T, usize)) -> T {
+    let name = display_name(path);
+    let (primary, problem) = match fs::read(path) {
+        Ok(bytes) => match String::from_utf8(bytes) {
+            Ok(text) => {
+                let (value, bad_lines) = parse(&text);
+                if bad_lines == 0 {
+                    return value;
+                }
+                (Some(value), format!("{} unreadable line(s)", bad_lines))
+            }
+            Err(e) => {
+                let text = String::from_utf8_lossy(e.as_bytes()).into_owned();
+                (Some(parse(&text).0), "invalid text encoding".to_string())
+            }
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return T::default(),
+        Err(e) => (None, e.to_string()),
+    };
+
+    if let Ok(text) = fs::read_to_string(sibling(path, "bak")) {
+        let (value, bad_lines) = parse(&text);
+        if bad_lines == 0 {
+            let _ = fs::copy(path, sibling(path, "corrupt"));
+            if let Err(e) = write_atomic(path, &text, false) {
+                warn(format!("Error restoring {}: {}", name, e));
+            }
+            warn(format!(
+                "{} was damaged ({}); restored the previous version from {}.bak",
+                name, problem, name
+            ));
+            return value;
+        }
+    }
+
+    let _ = fs::copy(path, sibling(path, "corrupt"));
+    warn(format!(
+        "{} is damaged ({}) and no usable backup exists; kept what could be read (original saved as {}.corrupt)",
+        name, problem, name
+    ));
+    primary.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn round_trips_delimiters_and_line_breaks() {
+        let fields = ["Kim's | iPhone", "line\nbreak\r\n", "back\\slash\\", "\\|", ""];
+        let line = join_fields(&fields);
+        assert!(!line.contains('\n') && !line.contains('\r'));
+        assert_eq!(split_fields(&line), fields);
+    }
+
+    #[test]
+    fn reads_legacy_lines_unchanged() {
+        assert_eq!(split_fields("Yusuf's iPhone|192.168.1.20"), ["Yusuf's iPhone", "192.168.1.20"]);
+        assert_eq!(split_fields("Studio\\PC|10.0.0.2"), ["Studio\\PC", "10.0.0.2"]);
+        assert_eq!(split_fields("trailing\\"), ["trailing\\"]);
+    }
+
+    #[test]
+    fn a_restored_backup_survives_the_next_save() {
+        let dir = std::env::temp_dir().join(format!("budbridge-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("devices.txt");
+        let parse = |text: &str| {
+            let bad = text.lines().filter(|l| !l.starts_with("ok")).count();
+            (text.lines().filter(|l| l.starts_with("ok")).count(), bad)
+        };
+        fs::write(&path, "ok\nbroken\n").unwrap();
+        fs::write(sibling(&path, "bak"), "ok\nok\n").unwrap();
+
+        assert_eq!(load(&path, parse), 2);
+        save(&path, "ok\nok\nok\n");
+        assert_eq!(fs::read_to_string(sibling(&path, "bak")).unwrap(), "ok\nok\n");
+        assert_eq!(fs::read_to_string(sibling(&path, "corrupt")).unwrap(), "ok\nbroken\n");
+        let _ = take_warnings();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    proptest! {
+        #[test]
+        fn round_trips_arbitrary_fields(fields in prop::collection::vec(any::<String>(), 1..6)) {
+            let refs: Vec<&str> = fields.iter().map(|f| f.as_str()).collect();
+            let line = join_fields(&refs);
+            prop_assert!(!line.contains('\n') && !line.contains('\r'));
+            prop_assert_eq!(split_fields(&line), fields);
+        }
+    }
+}
@@ -0,0 +1,230 @@
+// Local control API so hardware buttons (Stream Deck, gamepad mappers, scripts)
+// can drive BudBridge. Plain HTTP on 127.0.0.1, one request per connection:
+//
+//   GET|POST /action/<name>?token=<token>    (or "Authorization: Bearer <token>")
+//   GET      /status?token=<token>
+//
+// Commands are forwarded to the UI thread, which owns the connection state.
+
+use crate::AppState;
+use anyhow::Result;
+use crossbeam_channel::Sender;
+use eframe::egui;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+pub const CONTROL_PORT: u16 = 4812;
+const MAX_REQUEST_BYTES: usize = 8192;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ControlCommand {
+    Connect,
+    ConnectTo(String), // saved device name
+    ApplyProfile(String),
+    Disconnect,
+    ToggleConnection,
+    ToggleMuteMic,
+    ToggleMuteOut,
+    CycleDevice,
+    SetGainOut(f32),
+    SetGainMic(f32),
+    Show, // restore and focus the window
+}
+
+impl ControlCommand {
+    /// Actions available as buttons (no parameters)
+    pub const ALL: [ControlCommand; 7] = [
+        ControlCommand::Connect,
+        ControlCommand::Disconnect,
+        ControlCommand::ToggleConnection,
+        ControlCommand::ToggleMuteMic,
+        ControlCommand::ToggleMuteOut,
+        ControlCommand::CycleDevice,
+        ControlCommand::Show,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ControlCommand::Connect | ControlCommand::ConnectTo(_) => "connect",
+            ControlCommand::ApplyProfile(_) => "profile",
+            ControlCommand::Disconnect => "disconnect",
+            ControlCommand::ToggleConnection => "toggle",
+            ControlCommand::ToggleMuteMic => "mute-mic",
+            ControlCommand::ToggleMuteOut => "mute-out",
+            ControlCommand::CycleDevice => "cycle-device",
+            ControlCommand::SetGainOut(_) => "gain-out",
+            ControlCommand::SetGainMic(_) => "gain-mic",
+            ControlCommand::Show => "show",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().find(|c| c.name() == name).cloned()
+    }
+}
+
+/// 32 hex chars from a random (v4) UUID, which comes from the OS's CSPRNG
+pub fn generate_token() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// Compares without stopping at the first difference, so how long a 401 takes
+/// says nothing about how much of the token was right
+fn token_matches(given: &str, token: &str) -> bool {
+    given.len() == token.len() && given.bytes().zip(token.bytes()).fold(0u8, |d, (a, b)| d | (a ^ b)) == 0
+}
+
+/// Stops accepting requests when dropped
+pub struct ControlServer {
+    stop_flag: Arc<AtomicBool>,
+}
+
+impl ControlServer {
+    pub fn start(
+        token: String,
+        commands: Sender<ControlCommand>,
+        state: Arc<AppState>,
+        ctx: egui::Context,
+    ) -> Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", CONTROL_PORT))?;
+        listener.set_nonblocking(true)?;
+
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        let stop = stop_flag.clone();
+
+        thread::spawn(move || {
+            while !stop.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let _ = handle_client(stream, &token, &commands, &state, &ctx);
+                    }
+                    Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(50));
+                    }
+                    Err(_) => thread::sleep(Duration::from_millis(50)),
+                }
+            }
+        });
+
+        Ok(Self { stop_flag })
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.stop_flag.store(true, Ordering::SeqCst);
+    }
+}
+
+fn handle_client(
+    mut stream: TcpStream,
+    token: &str,
+    commands: &Sender<ControlCommand>,
+    state: &AppState,
+    ctx: &egui::Context,
+) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+
+    // Only the request head matters; bodies are ignored
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST_BYTES {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    let request = String::from_utf8_lossy(&request);
+
+    let (status, body) = route(&request, token, commands, state);
+    if status == 200 {
+        ctx.request_repaint();
+    }
+
+    let reason = match status {
+        200 => "OK",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        _ => "Bad Request",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
+
+fn route(request: &str, token: &str, commands: &Sender<ControlCommand>, state: &AppState) -> (u16, String) {
+    let mut lines = request.lines();
+    let target = match lines.next().and_then(|l| l.split_whitespace().nth(1)) {
+        Some(t) => t,
+        None => return (400, r#"{"error":"malformed request"}"#.to_string()),
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let query_token = query
+        .split('&')
+        .filter_map(|kv| kv.split_once('='))
+        .find(|(k, _)| *k == "token")
+        .map(|(_, v)| v);
+    let header_token = lines
+        .filter_map(|l| l.split_once(':'))
+        .find(|(k, _)| k.trim().eq_ignore_ascii_case("authorization"))
+        .and_then(|(_, v)| v.trim().strip_prefix("Bearer "))
+        .map(|v| v.trim());
+
+    if !query_token.or(header_token).is_some_and(|given| token_matches(given, token)) {
+        return (401, r#"{"error":"invalid token"}"#.to_string());
+    }
+
+    if path == "/status" {
+        return (
+            200,
+            format!(
+                r#"{{"connected":{},"mic_muted":{},"out_muted":{}}}"#,
+                state.is_connected.load(Ordering::Relaxed),
+                state.mute_mic.load(Ordering::Relaxed),
+                state.mute_out.load(Ordering::Relaxed)
+            ),
+        );
+    }
+
+    match path.strip_prefix("/action/").and_then(ControlCommand::from_name) {
+        Some(command) => {
+            let body = format!(r#"{{"ok":true,"action":"{}"}}"#, command.name());
+            let _ = commands.try_send(command);
+            (200, body)
+        }
+        None => (404, r#"{"error":"unknown action"}"#.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_random_hex_compared_whole() {
+        let token = generate_token();
+        assert_eq!(token.len(), 32);
+        assert!(token.bytes().all(|b| b.is_ascii_hexdigit()));
+        assert_ne!(token, generate_token());
+
+        assert!(token_matches(&token, &token));
+        assert!(!token_matches(&token[..31], &token));
+        assert!(!token_matches("", &token));
+        let mut wrong = token.clone().into_bytes();
+        wrong[31] ^= 1;
+        assert!(!token_matches(std::str::from_utf8(&wrong).unwrap(), &token));
+    }
+}
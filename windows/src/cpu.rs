@@ -0,0 +1,171 @@
+// CPU use per part of BudBridge, for Advanced diagnostics. Each thread stores its
+// own cumulative CPU time as it works; once a second the UI turns the change into
+// a percentage, next to BudBridge as a whole and the whole PC, so a stutter report
+// shows whether BudBridge or something else is keeping the CPU busy.
+//
+// Thread, process and system times come from GetThreadTimes, GetProcessTimes and
+// GetSystemTimes. Windows charges thread time a clock tick at a time, so readings
+// under a few percent are rough. Other platforms have no readings.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+const READING_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy)]
+pub enum Part {
+    Capture,
+    Playback,
+    Network,
+    Ui,
+}
+
+impl Part {
+    pub const ALL: [Part; 4] = [Part::Capture, Part::Playback, Part::Network, Part::Ui];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Part::Capture => "Capture callback",
+            Part::Playback => "Playback callback",
+            Part::Network => "Network thread",
+            Part::Ui => "UI",
+        }
+    }
+}
+
+/// Latest CPU time of the thread running each part, in nanoseconds
+#[derive(Default)]
+pub struct ThreadTimes {
+    times: [AtomicU64; 4],
+}
+
+impl ThreadTimes {
+    /// Called from the thread doing `part`'s work
+    pub fn record(&self, part: Part) {
+        if let Some(time) = thread_time() {
+            self.times[part as usize].store(time.as_nanos() as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Forget the audio and network threads of the last session
+    pub fn reset(&self) {
+        for part in [Part::Capture, Part::Playback, Part::Network] {
+            self.times[part as usize].store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+struct Sample {
+    at: Instant,
+    threads: [u64; 4],
+    process: Option<Duration>,
+    system: Option<(Duration, Duration)>,
+}
+
+/// Percentages over the last second. Parts and the process are % of one core
+/// (so several busy threads can add up past 100); the whole PC is % of all cores.
+#[derive(Default)]
+pub struct Usage {
+    pub parts: [Option<f32>; 4],
+    pub process: Option<f32>,
+    pub system: Option<f32>,
+}
+
+#[derive(Default)]
+pub struct CpuMeter {
+    last: Option<Sample>,
+    pub usage: Usage,
+}
+
+impl CpuMeter {
+    /// Called every UI frame; takes a new reading once a second
+    pub fn update(&mut self, threads: &ThreadTimes) {
+        if self.last.as_ref().is_some_and(|s| s.at.elapsed() < READING_INTERVAL) {
+            return;
+        }
+        let now = Sample {
+            at: Instant::now(),
+            threads: std::array::from_fn(|i| threads.times[i].load(Ordering::Relaxed)),
+            process: process_time(),
+            system: system_times(),
+        };
+        if let Some(last) = &self.last {
+            let wall = now.at.duration_since(last.at).as_secs_f32();
+            let percent = |busy: Duration| busy.as_secs_f32() / wall * 100.0;
+            self.usage = Usage {
+                // A thread that started since the last reading has no rate yet
+                parts: std::array::from_fn(|i| {
+                    (now.threads[i] != 0 && last.threads[i] != 0 && now.threads[i] >= last.threads[i])
+                        .then(|| percent(Duration::from_nanos(now.threads[i] - last.threads[i])))
+                }),
+                process: now.process.zip(last.process).map(|(now, last)| percent(now.saturating_sub(last))),
+                system: now.system.zip(last.system).and_then(|((busy, total), (last_busy, last_total))| {
+                    let total = total.saturating_sub(last_total).as_secs_f32();
+                    (total > 0.0).then(|| busy.saturating_sub(last_busy).as_secs_f32() / total * 100.0)
+                }),
+            };
+        }
+        self.last = Some(now);
+    }
+}
+
+#[cfg(target_os = "windows")]
+use imp::{process_time, system_times, thread_time};
+
+#[cfg(not(target_os = "windows"))]
+use fallback::{process_time, system_times, thread_time};
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use std::time::Duration;
+    use windows_sys::Win32::Foundation::FILETIME;
+    use windows_sys::Win32::System::Threading::{
+        GetCurrentProcess, GetCurrentThread, GetProcessTimes, GetSystemTimes, GetThreadTimes,
+    };
+
+    const ZERO: FILETIME = FILETIME { dwLowDateTime: 0, dwHighDateTime: 0 };
+
+    /// FILETIMEs count 100 ns ticks
+    fn duration(time: FILETIME) -> Duration {
+        let ticks = ((time.dwHighDateTime as u64) << 32) | time.dwLowDateTime as u64;
+        Duration::from_nanos(ticks * 100)
+    }
+
+    /// Kernel plus user time of the calling thread
+    pub fn thread_time() -> Option<Duration> {
+        let (mut created, mut exited, mut kernel, mut user) = (ZERO, ZERO, ZERO, ZERO);
+        let ok = unsafe { GetThreadTimes(GetCurrentThread(), &mut created, &mut exited, &mut kernel, &mut user) };
+        (ok != 0).then(|| duration(kernel) + duration(user))
+    }
+
+    pub fn process_time() -> Option<Duration> {
+        let (mut created, mut exited, mut kernel, mut user) = (ZERO, ZERO, ZERO, ZERO);
+        let ok = unsafe { GetProcessTimes(GetCurrentProcess(), &mut created, &mut exited, &mut kernel, &mut user) };
+        (ok != 0).then(|| duration(kernel) + duration(user))
+    }
+
+    /// (busy, total) summed over all cores. Kernel time includes idle time.
+    pub fn system_times() -> Option<(Duration, Duration)> {
+        let (mut idle, mut kernel, mut user) = (ZERO, ZERO, ZERO);
+        let ok = unsafe { GetSystemTimes(&mut idle, &mut kernel, &mut user) };
+        let total = duration(kernel) + duration(user);
+        (ok != 0).then(|| (total.saturating_sub(duration(idle)), total))
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod fallback {
+    use std::time::Duration;
+
+    pub fn thread_time() -> Option<Duration> {
+        None
+    }
+
+    pub fn process_time() -> Option<Duration> {
+        None
+    }
+
+    pub fn system_times() -> Option<(Duration, Duration)> {
+        None
+    }
+}
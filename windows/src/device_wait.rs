@@ -0,0 +1,81 @@
+// Holding back command-line actions until the audio devices they need are
+// listed. Started at logon with --connect, BudBridge can come up before a USB
+// interface has enumerated, and connecting then would capture from whatever
+// device happens to be first. With --wait-devices <seconds> the actions wait
+// for the active profile's capture and playback devices, rescanning every
+// second, and run with what's there once the time is up.
+
+use std::time::{Duration, Instant};
+
+pub const RESCAN_INTERVAL: Duration = Duration::from_secs(1);
+/// Longest --wait-devices takes
+pub const MAX_SECS: u64 = 600;
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Waiting,
+    Ready,
+    /// Names still missing when the time ran out
+    TimedOut(Vec<String>),
+}
+
+pub struct DeviceWait {
+    /// Device names as the lists show them; empty ones aren't waited for
+    pub capture: String,
+    pub playback: String,
+    timeout: Duration,
+    deadline: Instant,
+}
+
+impl DeviceWait {
+    pub fn new(now: Instant, timeout: Duration, capture: String, playback: String) -> Self {
+        Self { capture, playback, timeout, deadline: now + timeout }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// The wanted devices that aren't in the lists
+    pub fn missing(&self, inputs: &[String], outputs: &[String]) -> Vec<String> {
+        [(&self.capture, inputs), (&self.playback, outputs)]
+            .into_iter()
+            .filter(|(name, listed)| !name.is_empty() && !listed.contains(name))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    pub fn check(&self, now: Instant, inputs: &[String], outputs: &[String]) -> Outcome {
+        let missing = self.missing(inputs, outputs);
+        if missing.is_empty() {
+            Outcome::Ready
+        } else if now >= self.deadline {
+            Outcome::TimedOut(missing)
+        } else {
+            Outcome::Waiting
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_for_both_devices_until_the_deadline() {
+        let start = Instant::now();
+        let wait = DeviceWait::new(start, Duration::from_secs(30), "Scarlett 2i2 USB".into(), String::new());
+        let before = ["Microphone".to_string()];
+        let after = ["Microphone".to_string(), "Scarlett 2i2 USB".to_string()];
+
+        assert_eq!(wait.check(start + Duration::from_secs(5), &before, &[]), Outcome::Waiting);
+        assert_eq!(wait.check(start + Duration::from_secs(6), &after, &[]), Outcome::Ready);
+        assert_eq!(
+            wait.check(start + Duration::from_secs(30), &before, &[]),
+            Outcome::TimedOut(vec!["Scarlett 2i2 USB".to_string()])
+        );
+
+        let both = DeviceWait::new(start, Duration::from_secs(30), "Scarlett 2i2 USB".into(), "Speakers".into());
+        assert_eq!(both.missing(&after, &[]), vec!["Speakers".to_string()]);
+    }
+}
@@ -0,0 +1,356 @@
+// Optional processing of the PC → phone stream. It runs in the capture callback
+// on interleaved 48 kHz stereo, after the downmix mode and before muting and
+// encoding. The UI changes Settings at any time; the chain picks them up on the
+// next callback.
+//
+// Crossfeed comes first. Like Bauer's stereophonic-to-binaural filter, each ear
+// also gets the other channel low-passed and quieter, the way speakers reach both
+// ears, which takes the strain out of hard-panned mixes on earbuds. The direct
+// path gets the matching treble lift, so centred sound keeps a flat response.
+//
+// Loudness normalization measures short-term loudness (ITU-R BS.1770 K-weighting,
+// 3 s window) and steers a gain towards the target, so switching from a quiet
+// video to a loud game keeps about the same level. Boosts are slow and cuts are
+// quick, silence holds the gain where it is, and a peak limiter keeps boosted
+// audio from clipping.
+//
+// Night mode is a fixed compressor preset after that: quiet passages come up,
+// loud ones go down, and nothing goes above a cap well under full scale.
+
+use std::collections::VecDeque;
+
+const SAMPLE_RATE: f32 = 48_000.0;
+const BLOCK_FRAMES: usize = 4800; // 100 ms
+const SHORT_TERM_BLOCKS: usize = 30; // 3 s
+const SILENCE_LUFS: f32 = -50.0; // quieter than this: hold the gain
+const MAX_BOOST_DB: f32 = 12.0;
+const MAX_CUT_DB: f32 = -20.0;
+const BOOST_SECONDS: f32 = 2.0; // time constants of the normalizer gain
+const CUT_SECONDS: f32 = 0.3;
+const CEILING: f32 = 0.89; // -1 dBFS
+const LIMITER_RELEASE_SECONDS: f32 = 0.05;
+const CROSSFEED_CUTOFF_HZ: f32 = 700.0;
+// The other channel's level at intensity 0 and 1 (bs2b's presets span 9.5 to 4.5 dB)
+const CROSSFEED_LIGHTEST_DB: f32 = -12.0;
+const CROSSFEED_STRONGEST_DB: f32 = -3.0;
+
+/// Heavy compression with make-up gain, then a hard cap
+const NIGHT_MODE: Compression = Compression {
+    threshold_db: -36.0,
+    ratio: 6.0,
+    makeup_db: 12.0,
+    attack_seconds: 0.005,
+    release_seconds: 0.25,
+    ceiling: 0.32, // -10 dBFS
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct Settings {
+    /// Loudness target in LUFS, e.g. -16 or -23; None leaves levels alone
+    pub loudness_target: Option<f32>,
+    pub night_mode: bool,
+    /// 0 is off, 1 the strongest crossfeed
+    pub crossfeed: f32,
+}
+
+/// Loudness targets offered in the UI: (LUFS, description)
+pub const LOUDNESS_TARGETS: [(f32, &str); 2] = [(-16.0, "-16 LUFS (streaming)"), (-23.0, "-23 LUFS (broadcast)")];
+
+/// One-pole smoothing coefficient for a time constant
+fn smoothing(seconds: f32) -> f32 {
+    1.0 - (-1.0 / (seconds * SAMPLE_RATE)).exp()
+}
+
+fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+#[derive(Clone, Copy)]
+struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+    z: [f32; 2],
+}
+
+impl Biquad {
+    const fn new(b: [f32; 3], a: [f32; 2]) -> Self {
+        Self { b, a, z: [0.0; 2] }
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// BS.1770 K-weighting at 48 kHz: a high shelf for the head, then a high-pass
+fn k_weighting() -> [Biquad; 2] {
+    [
+        Biquad::new([1.535_124_9, -2.691_696_2, 1.198_392_8], [-1.690_659_3, 0.732_480_8]),
+        Biquad::new([1.0, -2.0, 1.0], [-1.990_047_5, 0.990_072_3]),
+    ]
+}
+
+pub struct LoudnessNormalizer {
+    filters: [[Biquad; 2]; 2],
+    block_energy: f64,
+    block_frames: usize,
+    blocks: VecDeque<f64>,
+    loudness: Option<f32>,
+    gain: f32,
+    limiter_gain: f32,
+}
+
+impl Default for LoudnessNormalizer {
+    fn default() -> Self {
+        Self {
+            filters: [k_weighting(), k_weighting()],
+            block_energy: 0.0,
+            block_frames: 0,
+            blocks: VecDeque::new(),
+            loudness: None,
+            gain: 1.0,
+            limiter_gain: 1.0,
+        }
+    }
+}
+
+impl LoudnessNormalizer {
+    /// Short-term loudness of the input in LUFS, once 3 s have been heard
+    pub fn loudness(&self) -> Option<f32> {
+        self.loudness
+    }
+
+    fn measure(&mut self, left: f32, right: f32) {
+        let [l, r] = &mut self.filters;
+        let l = l.iter_mut().fold(left, |x, f| f.process(x));
+        let r = r.iter_mut().fold(right, |x, f| f.process(x));
+        self.block_energy += (l * l + r * r) as f64;
+        self.block_frames += 1;
+        if self.block_frames == BLOCK_FRAMES {
+            self.blocks.push_back(self.block_energy / BLOCK_FRAMES as f64);
+            if self.blocks.len() > SHORT_TERM_BLOCKS {
+                self.blocks.pop_front();
+            }
+            if self.blocks.len() == SHORT_TERM_BLOCKS {
+                let mean = self.blocks.iter().sum::<f64>() / SHORT_TERM_BLOCKS as f64;
+                self.loudness = Some(-0.691 + 10.0 * (mean.max(1e-12)).log10() as f32);
+            }
+            self.block_energy = 0.0;
+            self.block_frames = 0;
+        }
+    }
+
+    pub fn process(&mut self, stereo: &mut [f32], target_lufs: f32) {
+        let boost = smoothing(BOOST_SECONDS);
+        let cut = smoothing(CUT_SECONDS);
+        let release = smoothing(LIMITER_RELEASE_SECONDS);
+        for frame in stereo.chunks_exact_mut(2) {
+            self.measure(frame[0], frame[1]);
+
+            let wanted = match self.loudness {
+                Some(loudness) if loudness > SILENCE_LUFS => {
+                    db_to_gain((target_lufs - loudness).clamp(MAX_CUT_DB, MAX_BOOST_DB))
+                }
+                _ => self.gain,
+            };
+            let rate = if wanted < self.gain { cut } else { boost };
+            self.gain += (wanted - self.gain) * rate;
+
+            let peak = frame[0].abs().max(frame[1].abs()) * self.gain;
+            if peak * self.limiter_gain > CEILING {
+                self.limiter_gain = CEILING / peak;
+            } else {
+                self.limiter_gain += (1.0 - self.limiter_gain) * release;
+            }
+            let gain = self.gain * self.limiter_gain;
+            frame[0] *= gain;
+            frame[1] *= gain;
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Crossfeed {
+    low_left: f32,
+    low_right: f32,
+}
+
+impl Crossfeed {
+    pub fn process(&mut self, stereo: &mut [f32], intensity: f32) {
+        let feed = db_to_gain(CROSSFEED_LIGHTEST_DB + (CROSSFEED_STRONGEST_DB - CROSSFEED_LIGHTEST_DB) * intensity);
+        let lowpass = 1.0 - (-std::f32::consts::TAU * CROSSFEED_CUTOFF_HZ / SAMPLE_RATE).exp();
+        for frame in stereo.chunks_exact_mut(2) {
+            let (left, right) = (frame[0], frame[1]);
+            self.low_left += (left - self.low_left) * lowpass;
+            self.low_right += (right - self.low_right) * lowpass;
+            // Treble lifted by (1 + feed) so that, after dividing, a centred
+            // signal comes out exactly as it went in
+            let direct_left = self.low_left + (left - self.low_left) * (1.0 + feed);
+            let direct_right = self.low_right + (right - self.low_right) * (1.0 + feed);
+            frame[0] = (direct_left + feed * self.low_right) / (1.0 + feed);
+            frame[1] = (direct_right + feed * self.low_left) / (1.0 + feed);
+        }
+    }
+}
+
+struct Compression {
+    threshold_db: f32,
+    ratio: f32,
+    makeup_db: f32,
+    attack_seconds: f32,
+    release_seconds: f32,
+    ceiling: f32,
+}
+
+/// Feed-forward compressor on the louder channel's envelope, with both channels
+/// getting the same gain so the stereo image stays put
+pub struct Compressor {
+    settings: &'static Compression,
+    envelope: f32,
+}
+
+impl Compressor {
+    fn new(settings: &'static Compression) -> Self {
+        Self { settings, envelope: 0.0 }
+    }
+
+    pub fn process(&mut self, stereo: &mut [f32]) {
+        let c = self.settings;
+        let attack = smoothing(c.attack_seconds);
+        let release = smoothing(c.release_seconds);
+        let makeup = db_to_gain(c.makeup_db);
+        for frame in stereo.chunks_exact_mut(2) {
+            let level = frame[0].abs().max(frame[1].abs());
+            let rate = if level > self.envelope { attack } else { release };
+            self.envelope += (level - self.envelope) * rate;
+
+            let level_db = 20.0 * self.envelope.max(1e-6).log10();
+            let over = level_db - c.threshold_db;
+            let reduction = if over > 0.0 { db_to_gain(-over * (1.0 - 1.0 / c.ratio)) } else { 1.0 };
+            let gain = reduction * makeup;
+            for sample in frame.iter_mut() {
+                *sample = (*sample * gain).clamp(-c.ceiling, c.ceiling);
+            }
+        }
+    }
+}
+
+/// Every stage, in order, with what each needs to keep between callbacks
+pub struct Chain {
+    crossfeed: Crossfeed,
+    normalizer: LoudnessNormalizer,
+    night: Compressor,
+}
+
+impl Default for Chain {
+    fn default() -> Self {
+        Self {
+            crossfeed: Crossfeed::default(),
+            normalizer: LoudnessNormalizer::default(),
+            night: Compressor::new(&NIGHT_MODE),
+        }
+    }
+}
+
+impl Chain {
+    /// Process interleaved stereo in place
+    pub fn process(&mut self, stereo: &mut [f32], settings: Settings) {
+        if settings.crossfeed > 0.0 {
+            self.crossfeed.process(stereo, settings.crossfeed);
+        }
+        if let Some(target) = settings.loudness_target {
+            self.normalizer.process(stereo, target);
+        }
+        if settings.night_mode {
+            self.night.process(stereo);
+        }
+    }
+
+    /// Short-term loudness of the capture, while normalization is on
+    pub fn loudness(&self) -> Option<f32> {
+        self.normalizer.loudness()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(seconds: f32, amplitude: f32) -> Vec<f32> {
+        (0..(seconds * SAMPLE_RATE) as usize)
+            .flat_map(|i| {
+                let s = (i as f32 * 1000.0 * std::f32::consts::TAU / SAMPLE_RATE).sin() * amplitude;
+                [s, s]
+            })
+            .collect()
+    }
+
+    fn measure(samples: &[f32]) -> f32 {
+        let mut meter = LoudnessNormalizer::default();
+        for frame in samples.chunks_exact(2) {
+            meter.measure(frame[0], frame[1]);
+        }
+        meter.loudness().expect("at least 3 s of audio")
+    }
+
+    #[test]
+    fn full_scale_sine_measures_as_bs1770_says() {
+        // A 0 dBFS 1 kHz sine in both channels reads about 0 LUFS
+        let loudness = measure(&tone(3.0, 1.0));
+        assert!((loudness - 0.0).abs() < 0.5, "{}", loudness);
+    }
+
+    #[test]
+    fn quiet_and_loud_sources_end_up_near_the_target() {
+        for amplitude in [0.06, 0.8] {
+            let mut normalizer = LoudnessNormalizer::default();
+            let mut samples = tone(20.0, amplitude);
+            normalizer.process(&mut samples, -16.0);
+            let settled = &samples[samples.len() - 3 * 2 * SAMPLE_RATE as usize..];
+            let loudness = measure(settled);
+            assert!((loudness + 16.0).abs() < 1.5, "amplitude {}: {} LUFS", amplitude, loudness);
+            assert!(settled.iter().all(|s| s.abs() <= CEILING + 1e-3));
+        }
+    }
+
+    #[test]
+    fn crossfeed_leaves_centred_sound_alone_and_blends_bass() {
+        let centred = tone(0.5, 0.5);
+        let mut processed = centred.clone();
+        Crossfeed::default().process(&mut processed, 1.0);
+        assert!(centred.iter().zip(&processed).all(|(a, b)| (a - b).abs() < 1e-4));
+
+        // Hard left: low notes reach the right ear, high ones mostly don't
+        let hard_left = |hz: f32| -> Vec<f32> {
+            (0..24_000)
+                .flat_map(|i| [(i as f32 * hz * std::f32::consts::TAU / SAMPLE_RATE).sin() * 0.5, 0.0])
+                .collect()
+        };
+        let right_peak = |mut samples: Vec<f32>| {
+            Crossfeed::default().process(&mut samples, 1.0);
+            samples.iter().skip(24_000).skip(1).step_by(2).fold(0.0f32, |m, s| m.max(s.abs()))
+        };
+        let bass = right_peak(hard_left(100.0));
+        let treble = right_peak(hard_left(8000.0));
+        assert!(bass > 0.15, "{}", bass);
+        assert!(treble < bass / 5.0, "{} vs {}", treble, bass);
+    }
+
+    #[test]
+    fn night_mode_lifts_dialogue_and_caps_explosions() {
+        let peak = |samples: &[f32]| samples.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        let mut night = Compressor::new(&NIGHT_MODE);
+        let mut dialogue = tone(2.0, 0.01);
+        let mut explosion = tone(1.0, 1.0);
+        night.process(&mut dialogue);
+        night.process(&mut explosion);
+
+        assert!(peak(&dialogue[dialogue.len() / 2..]) > 0.03);
+        assert!(peak(&explosion) <= NIGHT_MODE.ceiling);
+        // Sustained loud audio ends up well under the cap, not squashed flat against it
+        assert!(peak(&explosion[explosion.len() / 2..]) < NIGHT_MODE.ceiling);
+    }
+}
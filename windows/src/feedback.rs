@@ -0,0 +1,253 @@
+// Runtime feedback loop detection. Misrouted virtual cables can send audio round
+// the loop even when capture and playback are different devices, so the network
+// thread compares the loudness envelopes of what it sends and what it receives.
+// When one keeps following the other at some delay, audio is going round in a
+// circle and the UI offers to mute the direction that closes it.
+//
+// Envelopes (10 ms block levels in dB) survive codecs, resampling and gain
+// changes that would defeat a sample-level comparison, and are cheap enough to
+// correlate at every lag up to MAX_LAG twice a second.
+
+use crate::convert;
+use std::collections::VecDeque;
+
+const BLOCK_FRAMES: usize = 480; // 10 ms at 48 kHz
+const WINDOW: usize = 300; // blocks compared: 3 s
+const MAX_LAG: usize = 80; // longest loop delay looked for: 800 ms
+const CHECK_EVERY: usize = 50; // received blocks between checks
+const MATCH_THRESHOLD: f32 = 0.85;
+const CONFIRMATIONS: u32 = 3; // checks in a row above the threshold
+const MIN_LEVEL_DB: f32 = -60.0; // quieter than this on average is treated as silence
+const MIN_SPREAD_DB: f32 = 3.0; // a steady level (hum, silence) matches anything
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    /// The phone sends back what this PC sent it
+    Returned,
+    /// This PC sends the phone's own audio back to it (playback is being captured)
+    Resent,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Loop {
+    pub direction: Direction,
+    pub delay_ms: u32,
+    pub correlation: f32,
+}
+
+struct Envelope {
+    channels: usize,
+    levels: VecDeque<f32>,
+    energy: f64,
+    frames: usize,
+}
+
+impl Envelope {
+    fn new(channels: usize) -> Self {
+        Self { channels, levels: VecDeque::new(), energy: 0.0, frames: 0 }
+    }
+
+    /// Returns how many blocks were completed
+    fn push(&mut self, samples: &[f32]) -> usize {
+        let mut completed = 0;
+        for frame in samples.chunks(self.channels) {
+            self.energy += frame.iter().map(|&s| (s as f64).powi(2)).sum::<f64>() / self.channels as f64;
+            self.frames += 1;
+            if self.frames == BLOCK_FRAMES {
+                let mean = self.energy / BLOCK_FRAMES as f64;
+                self.levels.push_back((10.0 * (mean + 1e-10).log10()) as f32);
+                if self.levels.len() > WINDOW + MAX_LAG {
+                    self.levels.pop_front();
+                }
+                self.energy = 0.0;
+                self.frames = 0;
+                completed += 1;
+            }
+        }
+        completed
+    }
+
+    fn is_full(&self) -> bool {
+        self.levels.len() == WINDOW + MAX_LAG
+    }
+}
+
+pub struct FeedbackDetector {
+    sent: Envelope,
+    received: Envelope,
+    blocks_since_check: usize,
+    hits: u32,
+    detected: Option<Loop>,
+}
+
+impl FeedbackDetector {
+    pub fn new(sent_channels: usize, received_channels: usize) -> Self {
+        Self {
+            sent: Envelope::new(sent_channels),
+            received: Envelope::new(received_channels),
+            blocks_since_check: 0,
+            hits: 0,
+            detected: None,
+        }
+    }
+
+    /// Captured audio, as floats
+    pub fn push_sent(&mut self, samples: &[f32]) {
+        self.sent.push(samples);
+    }
+
+    /// Decoded audio from the phone
+    pub fn push_received(&mut self, samples: &[i16]) {
+        let samples: Vec<f32> = samples.iter().map(|&s| convert::from_i16(s)).collect();
+        self.blocks_since_check += self.received.push(&samples);
+        if self.blocks_since_check >= CHECK_EVERY && self.sent.is_full() && self.received.is_full() {
+            self.blocks_since_check = 0;
+            self.check();
+        }
+    }
+
+    /// A loop seen in the last few checks in a row
+    pub fn detected(&self) -> Option<Loop> {
+        self.detected
+    }
+
+    fn check(&mut self) {
+        let sent: Vec<f32> = self.sent.levels.iter().copied().collect();
+        let received: Vec<f32> = self.received.levels.iter().copied().collect();
+        // Received audio that follows what was sent, or the other way round
+        let candidates = [
+            best_lag(&received, &sent).map(|(lag, c)| (Direction::Returned, lag, c)),
+            best_lag(&sent, &received).map(|(lag, c)| (Direction::Resent, lag, c)),
+        ];
+        let best = candidates
+            .into_iter()
+            .flatten()
+            .max_by(|a, b| a.2.total_cmp(&b.2))
+            .filter(|(_, _, correlation)| *correlation >= MATCH_THRESHOLD);
+
+        match best {
+            Some((direction, lag, correlation)) => {
+                self.hits += 1;
+                if self.hits >= CONFIRMATIONS {
+                    self.detected = Some(Loop { direction, delay_ms: (lag * 10) as u32, correlation });
+                }
+            }
+            None => {
+                self.hits = 0;
+                self.detected = None;
+            }
+        }
+    }
+}
+
+/// The lag (in blocks) at which the latest WINDOW of `follower` best matches
+/// `leader` that many blocks earlier, with its correlation. None if either is
+/// too quiet or too steady to tell.
+fn best_lag(follower: &[f32], leader: &[f32]) -> Option<(usize, f32)> {
+    let recent = &follower[follower.len() - WINDOW..];
+    if !is_informative(recent) {
+        return None;
+    }
+    (0..=MAX_LAG)
+        .filter_map(|lag| {
+            let end = leader.len() - lag;
+            let earlier = &leader[end - WINDOW..end];
+            is_informative(earlier).then(|| (lag, correlation(recent, earlier)))
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+fn mean_and_spread(levels: &[f32]) -> (f32, f32) {
+    let mean = levels.iter().sum::<f32>() / levels.len() as f32;
+    let variance = levels.iter().map(|l| (l - mean).powi(2)).sum::<f32>() / levels.len() as f32;
+    (mean, variance.sqrt())
+}
+
+fn is_informative(levels: &[f32]) -> bool {
+    let (mean, spread) = mean_and_spread(levels);
+    mean >= MIN_LEVEL_DB && spread >= MIN_SPREAD_DB
+}
+
+/// Pearson correlation of two equally long series
+fn correlation(a: &[f32], b: &[f32]) -> f32 {
+    let (mean_a, spread_a) = mean_and_spread(a);
+    let (mean_b, spread_b) = mean_and_spread(b);
+    let covariance = a.iter().zip(b).map(|(x, y)| (x - mean_a) * (y - mean_b)).sum::<f32>() / a.len() as f32;
+    covariance / (spread_a * spread_b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Noise whose level changes every block, like speech or music
+    fn program(blocks: usize, seed: u64) -> Vec<i16> {
+        let mut state = seed;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % 20001) as f32 / 10000.0 - 1.0
+        };
+        let mut samples = Vec::new();
+        for _ in 0..blocks {
+            let level = 0.02 + 0.4 * (next() + 1.0) / 2.0;
+            samples.extend((0..BLOCK_FRAMES).map(|_| (next() * level * 32767.0) as i16));
+        }
+        samples
+    }
+
+    fn stereo(mono: &[i16]) -> Vec<f32> {
+        mono.iter().flat_map(|&s| [s as f32 / 32768.0; 2]).collect()
+    }
+
+    fn run(detector: &mut FeedbackDetector, sent: &[f32], received: &[i16]) {
+        // Fed in 20 ms packets, as the network thread does
+        for (out, back) in sent.chunks(2 * 2 * BLOCK_FRAMES).zip(received.chunks(2 * BLOCK_FRAMES)) {
+            detector.push_sent(out);
+            detector.push_received(back);
+        }
+    }
+
+    #[test]
+    fn detects_the_phone_returning_pc_audio() {
+        let pc = program(1000, 7);
+        let delay = 25 * BLOCK_FRAMES;
+        let mut echoed = vec![0i16; delay];
+        echoed.extend(pc.iter().map(|&s| s / 3));
+        echoed.truncate(pc.len());
+
+        let mut detector = FeedbackDetector::new(2, 1);
+        run(&mut detector, &stereo(&pc), &echoed);
+        let found = detector.detected().expect("loop not detected");
+        assert_eq!(found.direction, Direction::Returned);
+        assert_eq!(found.delay_ms, 250);
+    }
+
+    #[test]
+    fn detects_the_pc_resending_phone_audio() {
+        let phone = program(1000, 11);
+        let delay = 12 * BLOCK_FRAMES;
+        let mut captured = vec![0i16; delay];
+        captured.extend(&phone);
+        captured.truncate(phone.len());
+
+        let mut detector = FeedbackDetector::new(2, 1);
+        run(&mut detector, &stereo(&captured), &phone);
+        let found = detector.detected().expect("loop not detected");
+        assert_eq!(found.direction, Direction::Resent);
+        assert_eq!(found.delay_ms, 120);
+    }
+
+    #[test]
+    fn ignores_unrelated_audio_and_silence() {
+        let mut detector = FeedbackDetector::new(2, 1);
+        run(&mut detector, &stereo(&program(1000, 3)), &program(1000, 5));
+        assert_eq!(detector.detected(), None);
+
+        let mut detector = FeedbackDetector::new(2, 1);
+        let silence = vec![0i16; 1000 * BLOCK_FRAMES];
+        run(&mut detector, &stereo(&silence), &silence);
+        assert_eq!(detector.detected(), None);
+    }
+}
@@ -0,0 +1,67 @@
+// Volume each way, PC → iPhone and iPhone → PC. The Connection tab's sliders set
+// it in dB and save it (gain_out_db, gain_mic_db). The audio callbacks multiply
+// by the linear factor in an AtomicGain, read once per buffer, so a move is
+// heard straight away. OSC, MIDI and profiles set the same factor without saving.
+
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// +6 dB is osc::MAX_GAIN
+pub const RANGE_DB: RangeInclusive<f32> = -40.0..=6.0;
+
+/// Linear gain shared with the audio callbacks, stored as f32 bits
+pub struct AtomicGain(AtomicU32);
+
+impl Default for AtomicGain {
+    fn default() -> Self {
+        Self(AtomicU32::new(1.0f32.to_bits()))
+    }
+}
+
+impl AtomicGain {
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, gain: f32) {
+        self.0.store(gain.to_bits(), Ordering::Relaxed);
+    }
+}
+
+pub fn to_linear(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// For the slider: silence and anything quieter than its range sit at the bottom
+pub fn to_db(gain: f32) -> f32 {
+    (20.0 * gain.log10()).clamp(*RANGE_DB.start(), *RANGE_DB.end())
+}
+
+/// A saved setting, in dB; unset or unreadable is unity
+pub fn parse_db(text: Option<String>) -> f32 {
+    text.and_then(|v| v.trim().parse::<f32>().ok())
+        .filter(|db| db.is_finite())
+        .map_or(0.0, |db| db.clamp(*RANGE_DB.start(), *RANGE_DB.end()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decibels_map_to_the_shared_factor() {
+        let gain = AtomicGain::default();
+        assert_eq!(gain.get(), 1.0);
+        gain.set(to_linear(-6.0));
+        assert!((gain.get() - 0.501).abs() < 0.001);
+        assert!((to_db(gain.get()) + 6.0).abs() < 0.001);
+        assert!((to_linear(*RANGE_DB.end()) - crate::osc::MAX_GAIN).abs() < 0.01);
+
+        // A MIDI fader at zero shows at the bottom of the slider
+        assert_eq!(to_db(0.0), *RANGE_DB.start());
+        assert_eq!(parse_db(Some("-12.5".to_string())), -12.5);
+        assert_eq!(parse_db(Some("60".to_string())), *RANGE_DB.end());
+        assert_eq!(parse_db(Some("NaN".to_string())), 0.0);
+        assert_eq!(parse_db(None), 0.0);
+    }
+}
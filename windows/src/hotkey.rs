@@ -0,0 +1,137 @@
+// System-wide hotkey (e.g. "Ctrl+Alt+B") that works while a fullscreen game has focus.
+//
+// RegisterHotKey delivers WM_HOTKEY to the message queue of the thread that
+// registered it, so each listener owns a small thread running a message loop.
+
+use anyhow::{anyhow, Result};
+
+// Same values as the Win32 MOD_* flags
+const MOD_ALT: u32 = 0x0001;
+const MOD_CONTROL: u32 = 0x0002;
+const MOD_SHIFT: u32 = 0x0004;
+const MOD_WIN: u32 = 0x0008;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Hotkey {
+    modifiers: u32,
+    vk: u32,
+}
+
+/// Parse "Ctrl+Alt+B" style combinations (letters, digits, F1-F24)
+pub fn parse(text: &str) -> Result<Hotkey> {
+    let mut modifiers = 0;
+    let mut vk = None;
+
+    for part in text.split('+').map(|p| p.trim()) {
+        match part.to_lowercase().as_str() {
+            "ctrl" | "control" => modifiers |= MOD_CONTROL,
+            "alt" => modifiers |= MOD_ALT,
+            "shift" => modifiers |= MOD_SHIFT,
+            "win" | "windows" => modifiers |= MOD_WIN,
+            key => {
+                if vk.is_some() {
+                    return Err(anyhow!("Only one non-modifier key is allowed"));
+                }
+                vk = Some(parse_key(key).ok_or_else(|| anyhow!("Unknown key \"{}\"", part))?);
+            }
+        }
+    }
+
+    let vk = vk.ok_or_else(|| anyhow!("Missing key (e.g. Ctrl+Alt+B)"))?;
+    if modifiers == 0 {
+        return Err(anyhow!("Add at least one of Ctrl, Alt, Shift or Win"));
+    }
+    Ok(Hotkey { modifiers, vk })
+}
+
+fn parse_key(key: &str) -> Option<u32> {
+    let upper = key.to_uppercase();
+    let mut chars = upper.chars();
+    match (chars.next(), chars.next()) {
+        // Virtual-key codes for A-Z and 0-9 are their ASCII values
+        (Some(c), None) if c.is_ascii_alphanumeric() => Some(c as u32),
+        (Some('F'), Some(_)) => match upper[1..].parse::<u32>() {
+            Ok(n @ 1..=24) => Some(0x70 + n - 1), // VK_F1..VK_F24
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub use imp::HotkeyListener;
+
+#[cfg(not(target_os = "windows"))]
+pub use fallback::HotkeyListener;
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use super::Hotkey;
+    use anyhow::{anyhow, Result};
+    use crossbeam_channel::bounded;
+    use std::ptr;
+    use std::thread;
+    use windows_sys::Win32::System::Threading::GetCurrentThreadId;
+    use windows_sys::Win32::UI::Input::KeyboardAndMouse::{RegisterHotKey, UnregisterHotKey, MOD_NOREPEAT};
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        GetMessageW, PeekMessageW, PostThreadMessageW, MSG, PM_NOREMOVE, WM_HOTKEY, WM_QUIT, WM_USER,
+    };
+
+    const HOTKEY_ID: i32 = 1;
+
+    /// Unregisters the hotkey and stops its thread when dropped
+    pub struct HotkeyListener {
+        thread_id: u32,
+    }
+
+    impl HotkeyListener {
+        pub fn start(hotkey: Hotkey, on_press: impl Fn() + Send + 'static) -> Result<Self> {
+            let (ready_tx, ready_rx) = bounded::<Result<u32>>(1);
+
+            thread::spawn(move || unsafe {
+                let mut msg: MSG = std::mem::zeroed();
+                // Make sure this thread has a message queue before anyone posts to it
+                PeekMessageW(&mut msg, ptr::null_mut(), WM_USER, WM_USER, PM_NOREMOVE);
+
+                if RegisterHotKey(ptr::null_mut(), HOTKEY_ID, hotkey.modifiers | MOD_NOREPEAT, hotkey.vk) == 0 {
+                    let _ = ready_tx.send(Err(anyhow!("Hotkey is already in use by another application")));
+                    return;
+                }
+                let _ = ready_tx.send(Ok(GetCurrentThreadId()));
+
+                while GetMessageW(&mut msg, ptr::null_mut(), 0, 0) > 0 {
+                    if msg.message == WM_HOTKEY {
+                        on_press();
+                    }
+                }
+
+                UnregisterHotKey(ptr::null_mut(), HOTKEY_ID);
+            });
+
+            let thread_id = ready_rx.recv().map_err(|_| anyhow!("Hotkey thread exited"))??;
+            Ok(Self { thread_id })
+        }
+    }
+
+    impl Drop for HotkeyListener {
+        fn drop(&mut self) {
+            unsafe {
+                PostThreadMessageW(self.thread_id, WM_QUIT, 0, 0);
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod fallback {
+    use super::Hotkey;
+    use anyhow::{anyhow, Result};
+
+    pub struct HotkeyListener;
+
+    impl HotkeyListener {
+        pub fn start(_hotkey: Hotkey, _on_press: impl Fn() + Send + 'static) -> Result<Self> {
+            Err(anyhow!("Global hotkeys are only supported on Windows"))
+        }
+    }
+}
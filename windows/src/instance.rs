@@ -0,0 +1,145 @@
+// Command-line handling and forwarding to an already-running instance.
+//
+//   airpod-pc-audio.exe --connect "Yusuf's iPhone" --minimized
+//   airpod-pc-audio.exe --disconnect
+//   airpod-pc-audio.exe --profile Gaming
+//   airpod-pc-audio.exe --connect --minimized --wait-devices 60
+//
+// The first instance binds a localhost TCP port; later launches find it taken,
+// send their commands over it (one per line) and exit. A plain second launch
+// just brings the existing window to the front.
+
+use crate::control::ControlCommand;
+use crate::device_wait;
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use eframe::egui;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+pub const INSTANCE_PORT: u16 = 4813;
+
+#[derive(Default, Debug)]
+pub struct CliArgs {
+    pub commands: Vec<ControlCommand>,
+    pub minimized: bool,
+    /// Hold the commands until the profile's audio devices are listed, for at most this long
+    pub wait_devices: Option<Duration>,
+}
+
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<CliArgs> {
+    let mut parsed = CliArgs::default();
+    let mut args = args.into_iter().peekable();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--connect" => {
+                // Device name is optional; without it the default device is used
+                match args.next_if(|next| !next.starts_with("--")) {
+                    Some(name) => parsed.commands.push(ControlCommand::ConnectTo(name)),
+                    None => parsed.commands.push(ControlCommand::Connect),
+                }
+            }
+            "--disconnect" => parsed.commands.push(ControlCommand::Disconnect),
+            "--toggle" => parsed.commands.push(ControlCommand::ToggleConnection),
+            "--profile" => {
+                let name = args.next().ok_or_else(|| anyhow!("--profile needs a profile name"))?;
+                parsed.commands.push(ControlCommand::ApplyProfile(name));
+            }
+            "--minimized" => parsed.minimized = true,
+            "--wait-devices" => {
+                let secs: u64 = args
+                    .next()
+                    .and_then(|s| s.parse().ok())
+                    .ok_or_else(|| anyhow!("--wait-devices needs a number of seconds"))?;
+                parsed.wait_devices = Some(Duration::from_secs(secs.min(device_wait::MAX_SECS)));
+            }
+            other => return Err(anyhow!("Unknown argument: {}", other)),
+        }
+    }
+
+    Ok(parsed)
+}
+
+fn encode(command: &ControlCommand) -> Option<String> {
+    match command {
+        ControlCommand::ConnectTo(name) => Some(format!("connect\t{}", name)),
+        ControlCommand::ApplyProfile(name) => Some(format!("profile\t{}", name)),
+        ControlCommand::SetGainOut(_) | ControlCommand::SetGainMic(_) => None,
+        other => Some(other.name().to_string()),
+    }
+}
+
+fn decode(line: &str) -> Option<ControlCommand> {
+    match line.split_once('\t') {
+        Some(("connect", name)) => Some(ControlCommand::ConnectTo(name.to_string())),
+        Some(("profile", name)) => Some(ControlCommand::ApplyProfile(name.to_string())),
+        Some(_) => None,
+        None => ControlCommand::from_name(line),
+    }
+}
+
+/// Claim the instance port. Fails if another instance already holds it.
+pub fn claim() -> Result<TcpListener> {
+    Ok(TcpListener::bind(("127.0.0.1", INSTANCE_PORT))?)
+}
+
+/// Send commands to the running instance (or ask it to show itself if there are none)
+pub fn forward(commands: &[ControlCommand]) -> Result<()> {
+    let show = [ControlCommand::Show];
+    let commands = if commands.is_empty() { &show[..] } else { commands };
+
+    let mut stream = TcpStream::connect_timeout(&([127, 0, 0, 1], INSTANCE_PORT).into(), Duration::from_secs(2))?;
+    // We were just launched by the user, so we may pass foreground rights on
+    allow_foreground();
+    for line in commands.iter().filter_map(encode) {
+        writeln!(stream, "{}", line)?;
+    }
+    stream.flush()?;
+    Ok(())
+}
+
+/// Accept commands from later launches for the lifetime of the process
+pub fn serve(listener: TcpListener, commands: Sender<ControlCommand>, ctx: egui::Context) {
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let _ = stream.set_read_timeout(Some(Duration::from_secs(2)));
+            for line in BufReader::new(stream).lines().map_while(|l| l.ok()) {
+                if let Some(command) = decode(line.trim_end()) {
+                    let _ = commands.try_send(command);
+                    ctx.request_repaint();
+                }
+            }
+        }
+    });
+}
+
+#[cfg(target_os = "windows")]
+fn allow_foreground() {
+    use windows_sys::Win32::UI::WindowsAndMessaging::{AllowSetForegroundWindow, ASFW_ANY};
+    unsafe {
+        AllowSetForegroundWindow(ASFW_ANY);
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn allow_foreground() {}
+
+/// Tells the user why the command line was refused. The exe has no console, so
+/// on Windows this is a message box; elsewhere stderr.
+#[cfg(target_os = "windows")]
+pub fn report_bad_args(message: &str) {
+    use windows_sys::Win32::UI::WindowsAndMessaging::{MessageBoxW, MB_ICONERROR, MB_OK};
+    let text: Vec<u16> = message.encode_utf16().chain(std::iter::once(0)).collect();
+    let title: Vec<u16> = "BudBridge".encode_utf16().chain(std::iter::once(0)).collect();
+    unsafe {
+        MessageBoxW(std::ptr::null_mut(), text.as_ptr(), title.as_ptr(), MB_OK | MB_ICONERROR);
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn report_bad_args(message: &str) {
+    eprintln!("{}", message);
+}
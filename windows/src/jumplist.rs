@@ -0,0 +1,124 @@
+// Tasks in the taskbar button's jump list: "Connect to <device>" for each saved
+// device and "Disconnect". Each is a shortcut to this exe with the matching
+// command-line arguments, so picking one starts a second launch that hands the
+// command to the running instance (instance.rs), or starts BudBridge with it.
+
+use anyhow::Result;
+
+/// One jump list entry
+#[derive(Clone, Debug, PartialEq)]
+pub struct Task {
+    pub title: String,
+    pub arguments: String,
+}
+
+/// Connect tasks for the saved device names, in order and without repeats, then Disconnect
+pub fn tasks(device_names: &[String]) -> Vec<Task> {
+    let mut tasks: Vec<Task> = Vec::new();
+    for name in device_names {
+        // --connect takes the next argument as the name unless it looks like a flag
+        if name.is_empty() || name.starts_with("--") {
+            continue;
+        }
+        let title = format!("Connect to {}", name);
+        if tasks.iter().all(|task| task.title != title) {
+            tasks.push(Task { title, arguments: format!("--connect {}", quote(name)) });
+        }
+    }
+    tasks.push(Task { title: "Disconnect".to_string(), arguments: "--disconnect".to_string() });
+    tasks
+}
+
+/// Quotes an argument so Windows splits it back out unchanged (CommandLineToArgvW rules)
+fn quote(arg: &str) -> String {
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                // Backslashes before a quote are escapes, and so is the one added for it
+                quoted.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+                backslashes = 0;
+            }
+            _ => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes));
+                backslashes = 0;
+            }
+        }
+        if c != '\\' {
+            quoted.push(c);
+        }
+    }
+    // Before the closing quote they're escapes too
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/// Replaces the jump list's tasks; an empty list removes them
+#[cfg(target_os = "windows")]
+pub fn set_tasks(tasks: &[Task]) -> Result<()> {
+    use anyhow::anyhow;
+    use windows::core::{Interface, GUID, HSTRING, PCWSTR, PROPVARIANT};
+    use windows::Win32::System::Com::{CoCreateInstance, CoInitializeEx, CLSCTX_INPROC_SERVER, COINIT_APARTMENTTHREADED};
+    use windows::Win32::UI::Shell::Common::{IObjectArray, IObjectCollection};
+    use windows::Win32::UI::Shell::PropertiesSystem::{IPropertyStore, PROPERTYKEY};
+    use windows::Win32::UI::Shell::{DestinationList, EnumerableObjectCollection, ICustomDestinationList, IShellLinkW, ShellLink};
+
+    // System.Title, what the jump list shows for a shortcut
+    const PKEY_TITLE: PROPERTYKEY = PROPERTYKEY { fmtid: GUID::from_u128(0xf29f85e0_4ff9_1068_ab91_08002b27b3d9), pid: 2 };
+
+    let exe = HSTRING::from(std::env::current_exe()?.as_os_str());
+    unsafe {
+        // Called on a worker thread of its own
+        let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+        let list: ICustomDestinationList = CoCreateInstance(&DestinationList, None, CLSCTX_INPROC_SERVER)?;
+        if tasks.is_empty() {
+            return list.DeleteList(PCWSTR::null()).map_err(|e| anyhow!("Could not clear the jump list: {}", e));
+        }
+
+        let mut slots = 0u32;
+        let _removed: IObjectArray = list.BeginList(&mut slots)?;
+        let collection: IObjectCollection = CoCreateInstance(&EnumerableObjectCollection, None, CLSCTX_INPROC_SERVER)?;
+        for task in tasks {
+            let link: IShellLinkW = CoCreateInstance(&ShellLink, None, CLSCTX_INPROC_SERVER)?;
+            link.SetPath(&exe)?;
+            link.SetArguments(&HSTRING::from(task.arguments.as_str()))?;
+            link.SetIconLocation(&exe, 0)?;
+            link.SetDescription(&HSTRING::from(task.title.as_str()))?;
+            let store: IPropertyStore = link.cast()?;
+            store.SetValue(&PKEY_TITLE, &PROPVARIANT::from(task.title.as_str()))?;
+            store.Commit()?;
+            collection.AddObject(&link)?;
+        }
+        list.AddUserTasks(&collection.cast::<IObjectArray>()?)?;
+        list.CommitList().map_err(|e| anyhow!("Could not save the jump list: {}", e))
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn set_tasks(_tasks: &[Task]) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_tasks_that_parse_back() {
+        let names = ["Yusuf's iPhone", "Office \"desk\"", "Yusuf's iPhone", "--weird", r"C:\share\"].map(String::from);
+        let tasks = tasks(&names);
+        let titles: Vec<&str> = tasks.iter().map(|task| task.title.as_str()).collect();
+        assert_eq!(titles, ["Connect to Yusuf's iPhone", "Connect to Office \"desk\"", r"Connect to C:\share\", "Disconnect"]);
+
+        assert_eq!(tasks[0].arguments, "--connect \"Yusuf's iPhone\"");
+        assert_eq!(tasks[1].arguments, r#"--connect "Office \"desk\"""#);
+        assert_eq!(tasks[2].arguments, r#"--connect "C:\share\\""#);
+        assert_eq!(tasks[3].arguments, "--disconnect");
+
+        assert_eq!(quote(r#"a\"b"#), r#""a\\\"b""#);
+        assert_eq!(quote(r"a\b"), r#""a\b""#);
+    }
+}
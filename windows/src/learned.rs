@@ -0,0 +1,174 @@
+// Best settings per network. Each session of a minute or more on a LAN link is
+// folded into what's known about that network: the playback buffer grows when
+// audio ran dry or came in bursts it had to drop, and the PC → iPhone format
+// steps down to a lighter one when packets were lost. After a few sessions the
+// network's values are used on their own, until the user resets them.
+//
+// A network is its access point's BSSID on Wi-Fi, and the /24 subnet towards
+// the phone otherwise (Ethernet).
+
+use crate::config;
+use crate::protocol::StreamFormat;
+use std::net::IpAddr;
+
+const MAX_BUFFER_MS: u32 = 200;
+const BUFFER_STEP_MS: u32 = 25;
+/// Sessions on a network before its values are used
+pub const LEARN_AFTER: u32 = 3;
+const MIN_SESSION_SECS: u64 = 60;
+/// More buffer underruns and overruns a minute than this: the buffer was too small
+const TROUBLE_PER_MINUTE: f32 = 2.0;
+/// Average loss above this: the format was too heavy for the link
+const LOSSY_PCT: f32 = 3.0;
+
+/// What identifies the network the PC is on
+pub fn network_key(bssid: Option<[u8; 6]>, local_ip: Option<IpAddr>) -> Option<String> {
+    if let Some(bssid) = bssid {
+        let hex: Vec<String> = bssid.iter().map(|b| format!("{:02x}", b)).collect();
+        return Some(format!("bssid {}", hex.join(":")));
+    }
+    match local_ip? {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            Some(format!("subnet {}.{}.{}.0/24", a, b, c))
+        }
+        IpAddr::V6(_) => None,
+    }
+}
+
+/// How a finished session went, with the settings it ran on
+pub struct Outcome {
+    pub secs: u64,
+    pub loss_pct: Option<f32>,
+    /// Playback buffer underruns plus overruns
+    pub buffer_trouble: u64,
+    pub buffer_ms: u32,
+    pub down: StreamFormat,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Learned {
+    pub network: String,
+    pub sessions: u32,
+    pub buffer_ms: u32,
+    pub down: StreamFormat,
+}
+
+impl Learned {
+    /// Enough sessions to go by
+    pub fn ready(&self) -> bool {
+        self.sessions >= LEARN_AFTER
+    }
+
+    pub fn label(&self) -> String {
+        format!("{} ms buffer, {}", self.buffer_ms, self.down.label())
+    }
+}
+
+/// Folds a finished session into what's known about `network`. False when it
+/// was too short to say anything.
+pub fn record(learned: &mut Vec<Learned>, network: &str, outcome: &Outcome) -> bool {
+    if outcome.secs < MIN_SESSION_SECS {
+        return false;
+    }
+    let per_minute = outcome.buffer_trouble as f32 / (outcome.secs as f32 / 60.0);
+    let buffer_ms = if per_minute > TROUBLE_PER_MINUTE {
+        (outcome.buffer_ms + BUFFER_STEP_MS).min(MAX_BUFFER_MS)
+    } else {
+        outcome.buffer_ms
+    };
+    let down = if outcome.loss_pct.is_some_and(|loss| loss > LOSSY_PCT) { lighter(outcome.down) } else { outcome.down };
+    match learned.iter_mut().find(|l| l.network == network) {
+        Some(entry) => {
+            entry.sessions += 1;
+            entry.buffer_ms = buffer_ms;
+            entry.down = down;
+        }
+        None => learned.push(Learned { network: network.to_string(), sessions: 1, buffer_ms, down }),
+    }
+    true
+}
+
+/// The heaviest choice that still takes less of the network, or `format` at the bottom
+fn lighter(format: StreamFormat) -> StreamFormat {
+    let load = |f: &StreamFormat| f.network_kbps(0, 1400);
+    StreamFormat::DOWN_CHOICES
+        .iter()
+        .filter(|choice| load(choice) < load(&format))
+        .max_by_key(|choice| load(choice))
+        .copied()
+        .unwrap_or(format)
+}
+
+/// networks.txt: network|sessions|buffer_ms|down per line (escaped, see config::join_fields)
+pub fn parse(content: &str) -> (Vec<Learned>, usize) {
+    let mut bad_lines = 0;
+    let learned = content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            let fields = config::split_fields(line);
+            let entry = match fields.as_slice() {
+                [network, sessions, buffer_ms, down] => (|| {
+                    Some(Learned {
+                        network: network.clone(),
+                        sessions: sessions.parse().ok()?,
+                        buffer_ms: buffer_ms.parse().ok()?,
+                        down: StreamFormat::parse(down)?,
+                    })
+                })(),
+                _ => None,
+            };
+            if entry.is_none() {
+                bad_lines += 1;
+            }
+            entry
+        })
+        .collect();
+    (learned, bad_lines)
+}
+
+pub fn to_text(learned: &[Learned]) -> String {
+    learned
+        .iter()
+        .map(|l| {
+            let line = config::join_fields(&[&l.network, &l.sessions.to_string(), &l.buffer_ms.to_string(), &l.down.to_string()]);
+            line + "\n"
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome(loss_pct: f32, buffer_trouble: u64, buffer_ms: u32, down: StreamFormat) -> Outcome {
+        Outcome { secs: 600, loss_pct: Some(loss_pct), buffer_trouble, buffer_ms, down }
+    }
+
+    #[test]
+    fn learns_from_sessions_and_round_trips() {
+        let network = network_key(Some([0xaa, 0xbb, 0xcc, 0x01, 0x02, 0x03]), None).unwrap();
+        assert_eq!(network, "bssid aa:bb:cc:01:02:03");
+        assert_eq!(network_key(None, Some([192, 168, 1, 40].into())).as_deref(), Some("subnet 192.168.1.0/24"));
+
+        let opus_256 = StreamFormat::DOWN_CHOICES[3];
+        let mut learned = Vec::new();
+        // A clean session keeps what it ran on; a short one doesn't count
+        assert!(record(&mut learned, &network, &outcome(0.5, 3, 50, opus_256)));
+        assert!(!record(&mut learned, &network, &Outcome { secs: 30, ..outcome(50.0, 100, 50, opus_256) }));
+        assert_eq!(learned[0].label(), format!("50 ms buffer, {}", opus_256.label()));
+
+        // Dropouts grow the buffer, loss steps the format down, and after enough sessions it's used
+        assert!(record(&mut learned, &network, &outcome(6.0, 60, 50, opus_256)));
+        assert!(!learned[0].ready());
+        assert!(record(&mut learned, &network, &outcome(0.0, 0, 75, StreamFormat::DOWN_CHOICES[4])));
+        assert!(learned[0].ready());
+        assert_eq!((learned[0].buffer_ms, learned[0].down), (75, StreamFormat::DOWN_CHOICES[4]));
+        assert_eq!(lighter(opus_256), StreamFormat::DOWN_CHOICES[4]);
+        assert_eq!(lighter(StreamFormat::DOWN_CHOICES[5]), StreamFormat::DOWN_CHOICES[5]);
+
+        assert_eq!(parse(&to_text(&learned)), (learned, 0));
+        assert_eq!(parse("bssid x|three|50|pcm/1/768\n").1, 1);
+    }
+}
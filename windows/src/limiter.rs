@@ -0,0 +1,127 @@
+// Bandwidth limit, for a shared hotspot or a metered connection: a cap on what a
+// session takes both ways together, headers included (Session::network_kbps).
+// It applies at connect, to what the hello offers, and steps down only as far as
+// it has to:
+//
+// 1. Uncompressed formats become Opus, down and then up.
+// 2. Opus going down is sent in longer frames, so fewer datagrams, and fewer headers.
+// 3. Redundancy and FEC parity are dropped.
+// 4. The Opus bitrates shrink to share what's left. The phone's mic gets at most a
+//    quarter, and the rest goes down.
+//
+// Below what two streams at Opus's 6 kbps floor take, the cap can't be met.
+// Bluetooth has no handshake to limit, and a phone too old for Opus answers
+// with PCM anyway. Diagnostics compares what it agreed to against the cap.
+
+use crate::battery;
+use crate::protocol::{Codec, Session, StreamFormat, OPUS_KBPS};
+use std::ops::RangeInclusive;
+
+pub const KBPS_RANGE: RangeInclusive<u32> = 32..=2000;
+pub const DEFAULT_KBPS: u32 = 256;
+/// The phone's mic gets at most 1/UP_SHARE of the audio once bitrates shrink
+const UP_SHARE: u32 = 4;
+
+/// `session` fitted under `max_kbps`, and what fitting it changed. Nothing
+/// changes when it already fits.
+pub fn limit(session: Session, max_kbps: u32, max_payload: usize) -> (Session, Vec<String>) {
+    let total = |s: &Session| {
+        let (down, up) = s.network_kbps(max_payload);
+        down + up
+    };
+    let mut limited = session;
+    if total(&limited) > max_kbps {
+        limited.formats.down = opus(limited.formats.down, &StreamFormat::DOWN_CHOICES);
+    }
+    if total(&limited) > max_kbps {
+        limited.formats.up = opus(limited.formats.up, &StreamFormat::UP_CHOICES);
+    }
+    if total(&limited) > max_kbps && limited.down_ptime() < battery::PTIME {
+        limited.ptime = Some(battery::PTIME);
+    }
+    if total(&limited) > max_kbps {
+        limited.redundancy = 0;
+        limited.fec = 0;
+    }
+    if total(&limited) > max_kbps {
+        let (down, up) = (limited.formats.down.bitrate_kbps as u32, limited.formats.up.bitrate_kbps as u32);
+        let audio = max_kbps.saturating_sub(total(&limited) - down - up);
+        let floor = *OPUS_KBPS.start() as u32;
+        let up = up.min(audio / UP_SHARE).max(floor);
+        let down = down.min(audio.saturating_sub(up)).max(floor);
+        limited.formats.down.bitrate_kbps = down as u16;
+        limited.formats.up.bitrate_kbps = up as u16;
+    }
+    (limited, changes(&session, &limited))
+}
+
+/// The Opus choice with the channels `format` has, for an uncompressed `format`
+fn opus(format: StreamFormat, choices: &[StreamFormat]) -> StreamFormat {
+    if format.codec == Codec::Opus {
+        return format;
+    }
+    let mut opus = choices.iter().filter(|f| f.codec == Codec::Opus);
+    opus.clone().find(|f| f.channels == format.channels).or_else(|| opus.next()).copied().unwrap_or(format)
+}
+
+fn changes(before: &Session, after: &Session) -> Vec<String> {
+    let mut changes = Vec::new();
+    if after.formats.down != before.formats.down {
+        changes.push(format!("{} to the iPhone", after.formats.down.label()));
+    }
+    if after.formats.up != before.formats.up {
+        changes.push(format!("{} back", after.formats.up.label()));
+    }
+    if after.down_ptime() != before.down_ptime() {
+        changes.push(format!("{} ms frames", after.down_ptime()));
+    }
+    if after.redundancy != before.redundancy {
+        changes.push("no redundancy".to_string());
+    }
+    if after.fec != before.fec {
+        changes.push("no FEC".to_string());
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Formats;
+
+    fn total(session: &Session) -> u32 {
+        let (down, up) = session.network_kbps(1400);
+        down + up
+    }
+
+    #[test]
+    fn limits_step_down_only_as_far_as_needed() {
+        // Opus voice both ways fits; nothing changes
+        let light = Session { formats: Formats { down: StreamFormat::DOWN_CHOICES[5], up: StreamFormat::UP_CHOICES[2] }, ..Default::default() };
+        assert_eq!(limit(light, 256, 1400), (light, Vec::new()));
+
+        // PCM both ways only needs to become Opus
+        let (pcm, changes) = limit(Session::default(), 256, 1400);
+        assert_eq!(pcm.formats, Formats { down: StreamFormat::DOWN_CHOICES[5], up: StreamFormat::UP_CHOICES[1] });
+        assert_eq!((pcm.ptime, changes.len()), (None, 2));
+
+        // Stereo music with redundancy loses its frames, redundancy and some bitrate
+        let music = Session { formats: Formats { down: StreamFormat::DOWN_CHOICES[3], up: StreamFormat::UP_CHOICES[1] }, redundancy: 1, ..Default::default() };
+        let (limited, changes) = limit(music, 256, 1400);
+        assert!(total(&limited) <= 256, "{:?}", limited);
+        assert_eq!((limited.ptime, limited.redundancy), (Some(battery::PTIME), 0));
+        assert!(limited.formats.up.bitrate_kbps <= 256 / UP_SHARE as u16);
+        assert_eq!(limited.formats.down.channels, 2);
+        assert_eq!(changes.len(), 4);
+
+        // Parity goes with the redundancy
+        let corrected = Session { fec: 4, ..music };
+        assert!(total(&corrected) > total(&music));
+        let (limited, changes) = limit(corrected, 256, 1400);
+        assert_eq!((limited.fec, changes.len()), (0, 5));
+
+        // An impossible cap still leaves a stream, at the codec's floor
+        let (floor, _) = limit(music, 10, 1400);
+        assert_eq!((floor.formats.down.bitrate_kbps, floor.formats.up.bitrate_kbps), (6, 6));
+    }
+}
@@ -0,0 +1,178 @@
+// "Test connection": bursts of probe packets at increasing rates, echoed back by
+// the iPhone, to measure throughput, loss and round-trip time before streaming.
+//
+// Probes are protocol::Probe headers padded to PROBE_SIZE. The iPhone echoes any
+// packet starting with the tag unchanged (NetworkManager.swift).
+
+use crate::protocol::Probe;
+use anyhow::{anyhow, Result};
+use parking_lot::Mutex;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const PROBE_SIZE: usize = 1200;
+const STAGE_DURATION: Duration = Duration::from_millis(1000);
+const ECHO_GRACE: Duration = Duration::from_millis(400);
+/// Rates to try, stopping early once the link clearly can't keep up
+const STAGE_RATES_KBPS: [u32; 5] = [500, 1000, 2000, 4000, 8000];
+const GIVE_UP_LOSS_PCT: f32 = 25.0;
+const CLEAN_LOSS_PCT: f32 = 2.0;
+/// Audio in one direction: 48 kHz mono 16-bit
+pub const STREAM_KBPS: u32 = 768;
+
+#[derive(Clone, Debug)]
+pub struct StageResult {
+    pub rate_kbps: u32,
+    pub sent: u32,
+    pub received: u32,
+    pub rtt_ms: Option<f32>, // median
+}
+
+impl StageResult {
+    pub fn loss_pct(&self) -> f32 {
+        if self.sent == 0 {
+            return 0.0;
+        }
+        (1.0 - self.received as f32 / self.sent as f32) * 100.0
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TestReport {
+    pub stages: Vec<StageResult>,
+}
+
+impl TestReport {
+    /// Highest tested rate that came back with negligible loss
+    pub fn max_clean_kbps(&self) -> Option<u32> {
+        self.stages
+            .iter()
+            .filter(|s| s.loss_pct() <= CLEAN_LOSS_PCT)
+            .map(|s| s.rate_kbps)
+            .max()
+    }
+
+    /// RTT from the gentlest stage, where queueing doesn't inflate it
+    pub fn rtt_ms(&self) -> Option<f32> {
+        self.stages.iter().find_map(|s| s.rtt_ms)
+    }
+
+    pub fn verdict(&self) -> &'static str {
+        match self.max_clean_kbps() {
+            None if self.stages.iter().all(|s| s.received == 0) => {
+                "No echoes received. Is BudBridge streaming on the iPhone and on the same network?"
+            }
+            Some(k) if k >= STREAM_KBPS * 4 => "Plenty of headroom for streaming.",
+            Some(k) if k >= STREAM_KBPS * 2 => "Enough for streaming, with little headroom; consider a larger buffer.",
+            _ => "The link drops packets at streaming rates; expect dropouts. Try 5 GHz Wi-Fi or move closer.",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub enum TestState {
+    #[default]
+    Idle,
+    Running(String),
+    Done(TestReport),
+    Failed(String),
+}
+
+/// Runs the test on a background thread; `on_update` is called after each state change
+pub fn start(target: SocketAddr, state: Arc<Mutex<TestState>>, on_update: impl Fn() + Send + 'static) {
+    *state.lock() = TestState::Running("Starting...".to_string());
+    on_update();
+
+    thread::spawn(move || {
+        let result = run(target, |progress| {
+            *state.lock() = TestState::Running(progress);
+            on_update();
+        });
+        *state.lock() = match result {
+            Ok(report) => TestState::Done(report),
+            Err(e) => TestState::Failed(e.to_string()),
+        };
+        on_update();
+    });
+}
+
+fn run(target: SocketAddr, progress: impl Fn(String)) -> Result<TestReport> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_read_timeout(Some(Duration::from_millis(50)))?;
+    let recv_socket = socket.try_clone()?;
+
+    let start = Instant::now();
+    let echoes: Arc<Mutex<Vec<(u8, f32)>>> = Arc::new(Mutex::new(Vec::new()));
+    let done = Arc::new(AtomicBool::new(false));
+
+    let receiver = {
+        let echoes = echoes.clone();
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut buf = [0u8; 2048];
+            while !done.load(Ordering::SeqCst) {
+                if let Ok((len, _)) = recv_socket.recv_from(&mut buf) {
+                    if let Some(probe) = Probe::parse(&buf[..len]) {
+                        let rtt_ms = (start.elapsed().as_micros() as u64).saturating_sub(probe.sent_at_us) as f32 / 1000.0;
+                        echoes.lock().push((probe.stage, rtt_ms));
+                    }
+                }
+            }
+        })
+    };
+
+    let mut stages = Vec::new();
+    let mut seq = 0u32;
+    let mut packet = [0u8; PROBE_SIZE];
+
+    for (stage, &rate_kbps) in STAGE_RATES_KBPS.iter().enumerate() {
+        progress(format!("Testing {} kbps...", rate_kbps));
+
+        let interval = Duration::from_secs_f64((PROBE_SIZE * 8) as f64 / (rate_kbps as f64 * 1000.0));
+        let stage_start = Instant::now();
+        let mut next = stage_start;
+        let mut sent = 0u32;
+
+        while stage_start.elapsed() < STAGE_DURATION {
+            // Windows sleeps are coarse, so catch up with a short burst when late
+            while next <= Instant::now() {
+                let probe = Probe { stage: stage as u8, seq, sent_at_us: start.elapsed().as_micros() as u64 };
+                probe.write(&mut packet);
+                if socket.send_to(&packet, target).is_ok() {
+                    sent += 1;
+                }
+                seq = seq.wrapping_add(1);
+                next += interval;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        thread::sleep(ECHO_GRACE);
+
+        let mut rtts: Vec<f32> = echoes
+            .lock()
+            .iter()
+            .filter(|(s, _)| *s as usize == stage)
+            .map(|(_, rtt)| *rtt)
+            .collect();
+        rtts.sort_by(|a, b| a.total_cmp(b));
+
+        let result = StageResult {
+            rate_kbps,
+            sent,
+            received: rtts.len() as u32,
+            rtt_ms: rtts.get(rtts.len() / 2).copied(),
+        };
+        let give_up = result.loss_pct() > GIVE_UP_LOSS_PCT;
+        stages.push(result);
+        if give_up {
+            break;
+        }
+    }
+
+    done.store(true, Ordering::SeqCst);
+    receiver.join().map_err(|_| anyhow!("Receiver thread panicked"))?;
+    Ok(TestReport { stages })
+}
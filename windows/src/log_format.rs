@@ -0,0 +1,103 @@
+// How the debug log's lines are written. Text is "[ms] message", for reading;
+// JSON lines are one object per line, for log tools: the time, the source the
+// message names before its first colon ("Capture" in "Capture: opened"), and
+// the message. The format is taken when a log file is created, so a file
+// holds one format, and the in-memory lines for bug reports stay text.
+
+use crate::telemetry::json_string;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+static CURRENT: AtomicU8 = AtomicU8::new(0);
+
+/// Longest message prefix taken as its source
+const MAX_SOURCE_LEN: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    pub const ALL: [LogFormat; 2] = [LogFormat::Text, LogFormat::Json];
+
+    pub fn parse(text: &str) -> Self {
+        match text {
+            "json" => Self::Json,
+            _ => Self::Text,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Text => "text",
+            Self::Json => "json",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Text => "Text",
+            Self::Json => "JSON lines",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Text => "log",
+            Self::Json => "jsonl",
+        }
+    }
+
+    /// The format log files are written in from now on
+    pub fn current() -> Self {
+        Self::ALL.get(CURRENT.load(Ordering::Relaxed) as usize).copied().unwrap_or_default()
+    }
+
+    pub fn make_current(self) {
+        CURRENT.store(self as u8, Ordering::Relaxed);
+    }
+
+    pub fn line(self, timestamp_ms: u128, message: &str) -> String {
+        match self {
+            Self::Text => format!("[{}] {}", timestamp_ms, message),
+            Self::Json => match source(message) {
+                Some((source, rest)) => format!(
+                    "{{\"ts_ms\":{},\"source\":{},\"message\":{}}}",
+                    timestamp_ms,
+                    json_string(source),
+                    json_string(rest)
+                ),
+                None => format!("{{\"ts_ms\":{},\"message\":{}}}", timestamp_ms, json_string(message)),
+            },
+        }
+    }
+}
+
+/// "Receive buffer: asked for..." is ("Receive buffer", "asked for..."); a
+/// prefix with anything but words isn't a source
+fn source(message: &str) -> Option<(&str, &str)> {
+    let (source, rest) = message.split_once(": ")?;
+    let words = source.chars().all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_'));
+    (words && !source.is_empty() && source.len() <= MAX_SOURCE_LEN).then_some((source, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_lines_split_off_the_source() {
+        assert_eq!(LogFormat::Text.line(1500, "Capture: opened"), "[1500] Capture: opened");
+        assert_eq!(
+            LogFormat::Json.line(1500, "Receive buffer: asked for 1024 KB, got 512 KB"),
+            r#"{"ts_ms":1500,"source":"Receive buffer","message":"asked for 1024 KB, got 512 KB"}"#
+        );
+        assert_eq!(
+            LogFormat::Json.line(7, "AUDIO_CB #500: 960 samples in, max=\"0.5\""),
+            r#"{"ts_ms":7,"message":"AUDIO_CB #500: 960 samples in, max=\"0.5\""}"#
+        );
+        assert_eq!(LogFormat::Json.line(7, "Reconnecting"), r#"{"ts_ms":7,"message":"Reconnecting"}"#);
+    }
+}
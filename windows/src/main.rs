@@ -3,49 +3,254 @@
 use anyhow::{anyhow, Result};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::{Device, StreamConfig};
-use crossbeam_channel::{bounded, Receiver, Sender};
+use crossbeam_channel::{bounded, Receiver, RecvTimeoutError, Sender, TryRecvError};
 use eframe::egui;
 use parking_lot::Mutex;
 use std::fs::{self, File, OpenOptions};
 use std::io::Write;
-use std::net::UdpSocket;
-use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, Ordering};
 use std::sync::Arc;
 use std::thread;
 use std::collections::VecDeque;
-use std::time::{SystemTime, UNIX_EPOCH};
-
-const RECEIVE_PORT: u16 = 4810;
-const SEND_PORT: u16 = 4811;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+mod access_log;
+mod aes67;
+mod backup;
+mod battery;
+mod bug_report;
+mod clip;
+mod clock;
+mod comms_device;
+mod config;
+mod control;
+mod cpu;
+mod device_wait;
+mod dsp;
+mod feedback;
+mod gain;
+mod hotkey;
+mod instance;
+mod jumplist;
+mod learned;
+mod limiter;
+mod link_test;
+mod log_format;
+mod mdns;
+mod meter;
+mod midi;
+mod migrate;
+mod multicast;
+mod osc;
+mod playout;
+mod power;
+mod priority;
+mod process_loopback;
+mod rate_limit;
+mod receivers;
+mod redact;
+mod rendezvous;
+mod repaint;
+mod route;
+mod retry;
+mod rtsp;
+mod signal;
+mod socket_buffer;
+mod sources;
+mod stats;
+mod stream_info;
+mod taskbar;
+mod telemetry;
+mod test_sound;
+mod timeline;
+mod transport;
+mod tray;
+mod wasapi_loopback;
+mod wifi;
+mod window;
+
+use budbridge_core::{capture, codec, convert, downmix, framer, identity, protocol, resample, seal, standby};
+
+use protocol::{Formats, PcId, PublicKey, Session, StreamFormat};
+use control::{ControlCommand, ControlServer};
+use downmix::DownmixMode;
+use stats::{IntervalHistory, JitterBufferStats, LinkQuality, RateMeter, INTERVAL_BUCKETS_MS};
+
+const RECEIVE_PORT: u16 = budbridge_core::PC_PORT;
+const SEND_PORT: u16 = budbridge_core::PHONE_PORT;
 const CONFIG_FOLDER: &str = "budbridgeconfig";
 const LOGS_FOLDER: &str = "logs";
 const DEVICES_FILE: &str = "devices.txt";
 const DEFAULT_DEVICE_FILE: &str = "default.txt";
 const SETTINGS_FILE: &str = "settings.txt";
+const PROFILES_FILE: &str = "profiles.txt";
+const NETWORKS_FILE: &str = "networks.txt";
 const TARGET_SAMPLE_RATE: u32 = 48000;
+// Most phone audio the PC holds before dropping the oldest (50 ms keeps latency low),
+// unless a larger buffer was learned for the network
+const PLAYBACK_BUFFER_SAMPLES: usize = 48000 / 20;
+const DEFAULT_HOTKEY: &str = "Ctrl+Alt+B";
+const VB_CABLE_URL: &str = "https://vb-audio.com/Cable/";
+const WIFI_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+// How often the device lists are re-read in the background, so plugged-in headphones show up
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// No packets from a phone that was sending for this long goes on the timeline
+const PEER_QUIET_AFTER: Duration = Duration::from_secs(2);
+const RESUME_RECONNECT_DELAY: Duration = Duration::from_secs(3);
+// How often the battery saver looks at the power source
+const POWER_POLL_INTERVAL: Duration = Duration::from_secs(5);
+// How long Connect waits for the first packet from the phone before giving up
+const DEFAULT_CONNECT_TIMEOUT_SECS: u32 = 10;
+const CONNECT_TIMEOUT_RANGE_SECS: std::ops::RangeInclusive<u32> = 3..=120;
+const ROUTE_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_SEND_FAILURES: u32 = 50;
+const PUNCH_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(2);
+const PUNCH_TIMEOUT: Duration = Duration::from_secs(10);
+// Error correction choices: audio datagrams per parity datagram, 0 for off
+const FEC_CHOICES: [u8; 5] = [0, 10, 5, 4, 2];
+
+/// Packet sizing, keepalives, buffering and bandwidth for the path to the phone
+#[derive(Clone, Copy, Debug)]
+struct LinkPreset {
+    max_payload: usize,            // bytes of audio per UDP packet, must be even
+    keepalive: Option<Duration>,   // send a punch packet after this long without sending
+    buffer_samples: usize,         // phone audio held before dropping the oldest
+    max_down_kbps: Option<u32>,    // heavier PC → phone formats step down to fit
+}
+
+impl LinkPreset {
+    const LAN: Self = Self { max_payload: 1400, keepalive: None, buffer_samples: PLAYBACK_BUFFER_SAMPLES, max_down_kbps: None };
+    // Tailscale/WireGuard tunnels run at an MTU of 1280, so full-size packets get
+    // fragmented and dropped, and their NAT mappings expire while PC audio is silent.
+    // Routed paths also jitter more and have less bandwidth to spare.
+    const VPN: Self = Self {
+        max_payload: 1200,
+        keepalive: Some(Duration::from_secs(1)),
+        buffer_samples: PLAYBACK_BUFFER_SAMPLES * 2,
+        max_down_kbps: Some(300),
+    };
+
+    /// `format`, or the heaviest PC → phone choice that fits the preset
+    fn fit_down(&self, format: StreamFormat, redundancy: u8) -> StreamFormat {
+        let Some(max_kbps) = self.max_down_kbps else {
+            return format;
+        };
+        let kbps = |f: &StreamFormat| f.network_kbps(redundancy, self.max_payload);
+        if kbps(&format) <= max_kbps {
+            return format;
+        }
+        StreamFormat::DOWN_CHOICES
+            .iter()
+            .filter(|f| kbps(f) <= max_kbps)
+            .max_by_key(|f| kbps(f))
+            .copied()
+            .unwrap_or(format)
+    }
+
+    fn describe(&self) -> String {
+        let mut text = format!("{}-byte packets, {} ms buffer", self.max_payload, self.buffer_samples / 48);
+        if let Some(keepalive) = self.keepalive {
+            text += &format!(", keepalive after {} s", keepalive.as_secs());
+        }
+        if let Some(kbps) = self.max_down_kbps {
+            text += &format!(", PC → iPhone up to {} kbps", kbps);
+        }
+        text
+    }
+}
+
+// Virtual audio drivers that loop a playback endpoint back into a capture endpoint.
+// Windows has no user-mode API for creating audio endpoints, so instead of shipping
+// our own kernel driver we reuse whichever of these is already installed.
+struct VirtualCable {
+    playback_match: &'static str, // lowercase substring of the playback device name
+    mic_name: &'static str,       // capture device apps should use as their microphone
+}
+
+const VIRTUAL_CABLES: &[VirtualCable] = &[
+    VirtualCable { playback_match: "cable input", mic_name: "CABLE Output" },
+    VirtualCable { playback_match: "voicemeeter input", mic_name: "VoiceMeeter Output" },
+    VirtualCable { playback_match: "line 1 (virtual audio cable)", mic_name: "Line 1 (Virtual Audio Cable)" },
+    VirtualCable { playback_match: "steam streaming speakers", mic_name: "Steam Streaming Microphone" },
+];
 
 #[derive(Clone)]
 struct SavedDevice {
+    id: String,  // stable UUID; names can repeat and change
     name: String,
     ip: String,
+    key: Option<PublicKey>,  // identity key it first answered with
+}
+
+impl SavedDevice {
+    fn new(name: String, ip: String) -> Self {
+        Self { id: uuid::Uuid::new_v4().to_string(), name, ip, key: None }
+    }
+}
+
+// A re-pairing in progress: the device's key is forgotten, and the next one it
+// answers with is compared by eye before the old pairing is gone for good
+struct Repair {
+    device_id: String,
+    old_key: Option<PublicKey>,
+    answered_with_old_key: bool,
+}
+
+// A complete routing setup. The target is a saved device id; audio devices are
+// stored by name so they survive re-enumeration.
+#[derive(Clone)]
+struct Profile {
+    name: String,
+    target_device_id: String,
+    capture_device: String,
+    playback_device: String,
+    gain_out: f32,
+    gain_mic: f32,
+    mute_out: bool,
+    mute_mic: bool,
+    formats: Formats,
 }
 
 fn main() -> eframe::Result<()> {
     // Ensure config folder exists
     let _ = ensure_config_dirs();
 
-    let options = eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default()
-            .with_inner_size([400.0, 500.0])
-            .with_min_inner_size([350.0, 450.0]),
-        ..Default::default()
+    let cli = match instance::parse_args(std::env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(e) => {
+            instance::report_bad_args(&e.to_string());
+            std::process::exit(2);
+        }
+    };
+
+    // If another instance is running, hand it our commands (or just focus it) instead of
+    // starting a duplicate that would fail to bind the audio ports
+    let instance_listener = match instance::claim() {
+        Ok(listener) => Some(listener),
+        Err(_) if instance::forward(&cli.commands).is_ok() => return Ok(()),
+        Err(_) => None,
     };
 
+    migrate_config();
+
+    let mut viewport = egui::ViewportBuilder::default()
+        .with_inner_size(window::DEFAULT_SIZE)
+        .with_min_inner_size(window::MIN_SIZE);
+    if let Some(saved) = load_setting("window").and_then(|v| window::Geometry::parse(&v)) {
+        let (position, size) = saved.fit(window::desktop());
+        viewport = viewport.with_inner_size(size).with_maximized(saved.maximized);
+        if let Some(position) = position {
+            viewport = viewport.with_position(position);
+        }
+    }
+    let options = eframe::NativeOptions { viewport, ..Default::default() };
+
     eframe::run_native(
         "BudBridge",
         options,
-        Box::new(|cc| Ok(Box::new(BudBridgeApp::new(cc)))),
+        Box::new(|cc| Ok(Box::new(BudBridgeApp::new(cc, cli, instance_listener)))),
     )
 }
 
@@ -54,18 +259,100 @@ fn main() -> eframe::Result<()> {
 struct AppState {
     packets_sent: AtomicU64,
     packets_recv: AtomicU64,
-    packets_recv_with_audio: AtomicU64,
-    packets_sent_with_audio: AtomicU64,
+    level_recv: Mutex<signal::SignalLevel>,  // iPhone → PC as received
+    level_sent: Mutex<signal::SignalLevel>,  // PC → iPhone as sent
     audio_callbacks: AtomicU64,
-    last_packets_sent: AtomicU64,
-    last_packets_recv: AtomicU64,
     status_message: Mutex<String>,
     is_connected: AtomicBool,
+    mute_mic: AtomicBool,  // iPhone → PC playback
+    mute_out: AtomicBool,  // PC → iPhone capture
+    gain_mic: gain::AtomicGain,
+    gain_out: gain::AtomicGain,
+    peak_mic: clip::PeakHold, // iPhone → PC as played, after gain
+    peak_out: clip::PeakHold, // PC → iPhone as sent
+    meter_out: meter::Meter,  // PC → iPhone as the capture callback hands it on
+    meter_mic: meter::Meter,  // iPhone → PC as the playback callback plays it
+    saturation_mic: clip::Saturation,
+    saturation_out: clip::Saturation,
+    recv_intervals: Mutex<IntervalHistory>,
+    peer_ip: Mutex<Option<IpAddr>>,  // where the device's audio is actually coming from
+    send_intervals: Mutex<IntervalHistory>,
+    rtsp_feed: Mutex<Option<rtsp::RtspFeed>>,  // captured audio also goes here while the server runs
+    aes67_feed: Mutex<Option<aes67::Aes67Feed>>,
+    multicast_feed: Mutex<Option<multicast::MulticastFeed>>,
+    mix: receivers::Mix,  // each receiver's sources, mute and trim, applied before its encoder
+    ambiance: Mutex<Option<sources::Ambiance>>,
+    negotiated: Mutex<Option<Session>>,  // None until the phone answers the hello
+    peer_identity: Mutex<Option<identity::Verdict>>,  // None until the first ack
+    access_log: Mutex<access_log::AccessLog>,
+    test_sound: Mutex<test_sound::TestSound>,
+    red_recovered: AtomicU64,  // frames restored from redundant copies
+    fec_recovered: AtomicU64,  // iPhone → PC datagrams rebuilt from parity
+    recv_sequence: Mutex<stats::SequenceHistory>,  // iPhone → PC, once audio is framed
+    seal_stats: Mutex<seal::Stats>,  // what the session's audio protection costs
+    eco_qos: AtomicBool,  // the network thread runs under EcoQoS (battery saver)
+    pin_performance: AtomicBool,  // capture and network threads prefer performance cores
+    timeline: Mutex<timeline::Timeline>,  // session events for Diagnostics, from any thread
+    playback_buffer: JitterBufferStats,
+    playout: Mutex<Option<playout::AdaptiveDepth>>,  // None while the buffer is fixed
+    cpu_times: cpu::ThreadTimes,
+    feedback: Mutex<Option<feedback::Loop>>,  // set by the network thread while a loop is heard
+    downmix: AtomicU8,  // DownmixMode applied to the capture
+    resample_quality: AtomicU8,  // resample::Quality for devices not at 48 kHz, taken when streams open
+    mono_cancels: AtomicBool,  // the capture's channels cancel out in the mono mix
+    dsp: Mutex<dsp::Settings>,  // processing of the PC → iPhone stream
+    capture_lufs: Mutex<Option<f32>>,  // measured while loudness normalization is on
+    answered_status: Mutex<Option<String>>,  // status to show once the phone's first packet arrives
+    clock: Mutex<Option<clock::Estimate>>,  // network delay from the clock probes
+    streams: stream_info::StreamInfo,  // what the audio streams were opened with
+    repaint: repaint::Repaint,  // the bridge asks for a frame when something changed
+    // Warm standby (see standby.rs): the UI asks, the network thread swaps
+    switch_target: AtomicBool,
+    standby: Mutex<Option<standby::Status>>,
+    target_switches: AtomicU32,
 }
 
+#[derive(PartialEq)]
 struct AudioDeviceInfo {
     name: String,
     is_output: bool,  // true = output device (for loopback capture)
+    id: DeviceId,
+}
+
+/// A device the way the bridge finds it again: indices shift as devices come and
+/// go, so it's the name and which of the devices sharing that name it is
+#[derive(Clone, PartialEq, Debug)]
+struct DeviceId {
+    name: String,
+    nth: usize,
+}
+
+impl DeviceId {
+    /// Ids for device names in enumeration order
+    fn assign(names: Vec<String>) -> Vec<DeviceId> {
+        let mut ids: Vec<DeviceId> = Vec::new();
+        for name in names {
+            let nth = ids.iter().filter(|id| id.name == name).count();
+            ids.push(DeviceId { name, nth });
+        }
+        ids
+    }
+
+    fn find(&self, devices: impl Iterator<Item = Device>) -> Result<Device> {
+        devices
+            .filter(|d| device_label(d) == self.name)
+            .nth(self.nth)
+            .ok_or_else(|| anyhow!("\"{}\" is no longer connected", self.name))
+    }
+}
+
+type DeviceLists = (Vec<AudioDeviceInfo>, Vec<AudioDeviceInfo>);
+
+/// A device list being read on a worker thread; some drivers take seconds to answer
+struct DeviceScan {
+    rx: Receiver<DeviceLists>,
+    pick_cable: bool, // play into the virtual cable once the list is in
+    manual: bool,     // the user asked for it, so show that it's running
 }
 
 #[derive(PartialEq, Default, Clone, Copy)]
@@ -76,33 +363,215 @@ enum Tab {
     Settings,
 }
 
+impl Tab {
+    fn parse(text: &str) -> Self {
+        match text {
+            "devices" => Tab::Devices,
+            "settings" => Tab::Settings,
+            _ => Tab::Connection,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Tab::Connection => "connection",
+            Tab::Devices => "devices",
+            Tab::Settings => "settings",
+        }
+    }
+}
+
 struct BudBridgeApp {
     current_tab: Tab,
+    window_geometry: Option<window::Geometry>, // saved on exit
     iphone_ip: String,
     input_devices: Vec<AudioDeviceInfo>,
     output_devices: Vec<AudioDeviceInfo>,
     selected_input: usize,
     selected_output: usize,
+    device_scan: Option<DeviceScan>,
+    devices_scanned: Instant,
+    device_notices: Vec<String>, // selected devices that went away, until dismissed
+    device_wait: Option<(device_wait::DeviceWait, Vec<ControlCommand>)>, // --wait-devices, with the commands it holds
+    allow_feedback_loop: bool,  // connect even though playback goes into the captured device
+    feedback_dismissed: bool,
     state: Arc<AppState>,
     stop_flag: Arc<AtomicBool>,
     _audio_thread: Option<thread::JoinHandle<()>>,
+    audio_switch: Option<Sender<AudioSwitch>>, // to the running bridge
+    session_audio: (usize, usize),             // capture and playback it was last told about
     // Saved devices
     saved_devices: Vec<SavedDevice>,
     selected_device: Option<usize>,
     default_device: Option<usize>,
+    // Profiles
+    profiles: Vec<Profile>,
+    active_profile: Option<usize>,
+    new_profile_name: String,
+    // Wi-Fi advisory, refreshed periodically
+    wifi_info: Option<wifi::WifiInfo>,
+    wifi_checked: Option<Instant>,
+    // Buffer and format learned per network, applied once there are enough sessions
+    learn_networks: bool,
+    learned: Vec<learned::Learned>,
+    session_network: Option<String>,
+    learned_note: String,
+    // App left out of loopback capture, by executable name; empty captures everything
+    capture_exclude: String,
+    app_sessions: Vec<process_loopback::AppSession>,
+    link_test: Arc<Mutex<link_test::TestState>>,
+    config_warnings: Vec<String>,
+    // Banner for the latest connection error, until dismissed
+    error_banner: Option<bug_report::Report>,
+    cpu_meter: cpu::CpuMeter,
+    last_error: String,
+    // Config backup and restore
+    restore_path: String,
+    pending_restore: Option<migrate::ConfigFiles>,
+    backup_status: String,
+    // Internet mode (rendezvous + hole punching)
+    remote_mode: bool,
+    vpn_mode: bool,
+    detect_route: bool, // pick LAN or VPN defaults from the address instead
+    route_detected: Option<(String, route::Route)>, // and the address it was detected for
+    connection_advanced: bool, // the Connection tab's Advanced section is open
+    // Codec per direction, redundant frames and FEC parity, offered to the phone when connecting
+    formats: Formats,
+    redundancy: u8,
+    fec: u8, // audio datagrams per parity datagram, 0 for off
+    seal: seal::Mode, // audio protection asked of the phone
+    seal_costs: Option<[Duration; 3]>, // per seal::Mode, measured when the setting is first shown
+    rendezvous_server: String,
+    rendezvous_code: String,
+    pc_id: PcId,  // names this install in hellos (see protocol.rs)
+    // Saved IP follow-up when the phone shows up at a new address
+    auto_update_ip: bool,
+    ignored_peer_ip: Option<IpAddr>,
     // Add device form
     new_device_name: String,
     new_device_ip: String,
+    // Default communications mic switching (restored on disconnect)
+    set_comms_default: bool,
+    previous_comms_device: Option<String>,
+    // Global hotkey (toggles connection to the default device)
+    hotkey_enabled: bool,
+    hotkey_text: String,
+    hotkey_status: String,
+    hotkey_listener: Option<hotkey::HotkeyListener>,
+    egui_ctx: egui::Context,
+    // Local control API (Stream Deck, scripts)
+    control_enabled: bool,
+    control_token: String,
+    control_status: String,
+    control_server: Option<ControlServer>,
+    // OSC control surfaces
+    osc_enabled: bool,
+    osc_port: String,
+    osc_status: String,
+    osc_listener: Option<osc::OscListener>,
+    announce_enabled: bool, // advertise this PC over mDNS for the phone to find
+    announce_name: String,
+    announce_status: String,
+    announcer: Option<mdns::Announcer>,
+    rtsp_enabled: bool,
+    rtsp_port: String,
+    rtsp_status: String,
+    rtsp_server: Option<rtsp::RtspServer>,
+    aes67_enabled: bool,
+    aes67_group: String,
+    aes67_port: String,
+    aes67_status: String,
+    aes67_sender: Option<aes67::Aes67Sender>,
+    multicast_enabled: bool,
+    multicast_group: String,
+    multicast_port: String,
+    multicast_ttl: u32,
+    multicast_format: StreamFormat,
+    multicast_status: String,
+    multicast_sender: Option<multicast::MulticastSender>,
+    // A looping WAV file receivers can be routed to instead of, or with, the capture
+    ambiance_path: String,
+    ambiance_status: String,
+    // Opt-in anonymous session statistics
+    telemetry_mode: telemetry::Mode,
+    telemetry_url: String,
+    telemetry_status: Arc<Mutex<String>>,
+    session_tracker: Option<telemetry::SessionTracker>,
+    last_report: Option<String>,
+    // MIDI control surfaces
+    midi_enabled: bool,
+    midi_ports: Vec<String>,
+    midi_port: String,
+    midi_mapping: Arc<Mutex<midi::MidiMapping>>,
+    midi_saved_mapping: midi::MidiMapping,
+    midi_learn: Arc<Mutex<Option<midi::LearnTarget>>>,
+    midi_status: String,
+    midi_listener: Option<midi::MidiListener>,
+    // Commands from the hotkey thread and control API
+    command_tx: Sender<ControlCommand>,
+    command_rx: Receiver<ControlCommand>,
+    // Sleep/resume handling
+    power_rx: Receiver<power::PowerEvent>,
+    _power_listener: Option<power::PowerListener>,
+    reconnect_after_resume: bool,
+    reconnect_at: Option<Instant>,
+    // Set while waiting for the phone to answer Connect
+    connect_started: Option<Instant>,
+    connect_timeout_secs: u32,
+    recv_buffer_kb: u32, // SO_RCVBUF of the receive socket, for bursts after Wi-Fi power save
+    adaptive_buffer: bool, // the playback buffer follows the link's jitter instead of the preset
+    signal_threshold_db: f32, // diagnostics count a stream's level above this as audio
+    sent_rate: RateMeter,
+    recv_rate: RateMeter,
+    max_fps: u32, // repaints while audio flows are capped at this
+    battery_saver: battery::Mode,
+    saving: bool, // the battery saver is in effect
+    power_checked: Option<Instant>,
+    session_saving: Option<bool>, // how the session was started; None without a handshake
+    bandwidth_limit: Option<u32>, // kbps both ways, None for no limit
+    session_limit: Option<(u32, Vec<String>)>, // the limit the session started under, and what it changed
+    meter_bars: [meter::Ballistics; 2], // PC → iPhone, iPhone → PC
+    process_priority: priority::Priority,
+    pin_performance: bool,
+    core_counts: Option<Option<(usize, usize)>>, // (performance, all) cores, looked up once shown
+    taskbar_badge: bool, // connection state on the taskbar button
+    taskbar: taskbar::Overlay,
+    jump_list: bool, // connect tasks in the taskbar jump list
+    tray_icon: bool, // an icon in the notification area
+    minimize_to_tray: bool, // minimized, the window leaves the taskbar for the tray icon
+    tray: tray::Tray,
+    jump_list_names: Option<Vec<String>>, // what the jump list was last built from
+    // Retries after a failed attempt or a dropped session
+    retry_policy: retry::RetryPolicy,
+    retries: u32,
+    session_running: bool,
+    // The saved device this session is with, for its identity key
+    session_device: Option<String>,
+    standby_device: Option<String>, // saved device kept warm for "Switch to", by id
+    seen_switches: u32,
+    // What watch_session last saw: underruns, packets in and when they last grew, phone quiet
+    seen_underruns: u64,
+    seen_packets: (u64, Instant),
+    peer_quiet: bool,
+    repair: Option<Repair>,
     // Settings
     debug_logging: bool,
     debug_logging_flag: Arc<AtomicBool>,
+    log_format: log_format::LogFormat, // for the next log file
+    share_log: Option<PathBuf>, // picked for "Prepare logs for sharing"
+    share_status: String,
     log_file: Arc<Mutex<Option<File>>>,
 }
 
 impl BudBridgeApp {
-    fn new(_cc: &eframe::CreationContext<'_>) -> Self {
+    fn new(
+        cc: &eframe::CreationContext<'_>,
+        cli: instance::CliArgs,
+        instance_listener: Option<std::net::TcpListener>,
+    ) -> Self {
         let (input_devices, output_devices) = Self::enumerate_devices();
         let saved_devices = load_saved_devices();
+        let profiles = load_profiles(&saved_devices);
         let default_device = load_default_device(&saved_devices);
         let debug_logging = load_debug_setting();
 
@@ -115,483 +584,4211 @@ impl BudBridgeApp {
             None
         };
 
+        let (command_tx, command_rx) = bounded(16);
+        let rendezvous_code = load_setting("rendezvous_code").unwrap_or_else(|| {
+            let code = rendezvous::generate_code();
+            save_setting("rendezvous_code", &code);
+            code
+        });
+        let pc_id = load_setting("pc_id").and_then(|hex| protocol::parse_hex(&hex)).unwrap_or_else(|| {
+            let id = *uuid::Uuid::new_v4().as_bytes();
+            save_setting("pc_id", &protocol::to_hex(&id));
+            id
+        });
+        let (power_tx, power_rx) = bounded(4);
+        let power_ctx = cc.egui_ctx.clone();
+        // Not available off Windows; the session then just doesn't survive sleep
+        let power_listener = power::PowerListener::start(move |event| {
+            let _ = power_tx.try_send(event);
+            power_ctx.request_repaint();
+        })
+        .ok();
+        let midi_mapping = midi::MidiMapping {
+            gain_cc: load_setting("midi_gain_cc").and_then(|v| midi::parse_binding(&v)),
+            mute_note: load_setting("midi_mute_note").and_then(|v| midi::parse_binding(&v)),
+        };
+        let control_token = load_setting("control_token").unwrap_or_else(|| {
+            let token = control::generate_token();
+            save_setting("control_token", &token);
+            token
+        });
+
         let iphone_ip = selected_device
             .and_then(|i| saved_devices.get(i))
             .map(|d| d.ip.clone())
             .unwrap_or_default();
 
-        Self {
-            current_tab: Tab::default(),
+        let mut app = Self {
+            current_tab: Tab::parse(&load_setting("tab").unwrap_or_default()),
+            window_geometry: None,
             iphone_ip,
             input_devices,
             output_devices,
             selected_input: 0,
             selected_output: 0,
+            device_scan: None,
+            devices_scanned: Instant::now(),
+            device_notices: Vec::new(),
+            device_wait: None,
+            allow_feedback_loop: false,
+            feedback_dismissed: false,
             state: Arc::new(AppState::default()),
             stop_flag: Arc::new(AtomicBool::new(false)),
             _audio_thread: None,
+            audio_switch: None,
+            session_audio: (0, 0),
             saved_devices,
             selected_device,
             default_device,
+            profiles,
+            active_profile: None,
+            new_profile_name: String::new(),
+            wifi_info: None,
+            wifi_checked: None,
+            learn_networks: load_bool_setting("learn_networks"),
+            learned: load_learned(),
+            session_network: None,
+            learned_note: String::new(),
+            link_test: Arc::new(Mutex::new(link_test::TestState::Idle)),
+            config_warnings: Vec::new(),
+            error_banner: None,
+            cpu_meter: cpu::CpuMeter::default(),
+            last_error: String::new(),
+            restore_path: String::new(),
+            pending_restore: None,
+            backup_status: String::new(),
+            remote_mode: load_bool_setting("remote_mode"),
+            vpn_mode: load_bool_setting("vpn_mode"),
+            detect_route: load_bool_setting("detect_route"),
+            route_detected: None,
+            connection_advanced: load_bool_setting("connection_advanced"),
+            formats: Formats {
+                down: load_setting("down_format").and_then(|v| StreamFormat::parse(&v)).unwrap_or(StreamFormat::PCM_MONO),
+                up: load_setting("up_format").and_then(|v| StreamFormat::parse(&v)).unwrap_or(StreamFormat::PCM_MONO),
+            },
+            redundancy: load_setting("red_frames")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0)
+                .min(protocol::MAX_REDUNDANCY),
+            fec: load_setting("fec_group")
+                .and_then(|v| v.parse().ok())
+                .filter(|n| protocol::FEC_GROUPS.contains(n))
+                .unwrap_or(0),
+            seal: seal::Mode::parse(&load_setting("seal_mode").unwrap_or_default()),
+            seal_costs: None,
+            rendezvous_server: load_setting("rendezvous_server").unwrap_or_default(),
+            rendezvous_code,
+            pc_id,
+            auto_update_ip: load_bool_setting("auto_update_ip"),
+            ignored_peer_ip: None,
             new_device_name: String::new(),
             new_device_ip: String::new(),
+            set_comms_default: load_bool_setting("comms_default"),
+            previous_comms_device: None,
+            hotkey_enabled: load_bool_setting("hotkey_enabled"),
+            hotkey_text: load_setting("hotkey").unwrap_or_else(|| DEFAULT_HOTKEY.to_string()),
+            hotkey_status: String::new(),
+            hotkey_listener: None,
+            egui_ctx: cc.egui_ctx.clone(),
+            control_enabled: load_bool_setting("control_enabled"),
+            control_token,
+            control_status: String::new(),
+            control_server: None,
+            osc_enabled: load_bool_setting("osc_enabled"),
+            osc_port: load_setting("osc_port").unwrap_or_else(|| osc::DEFAULT_OSC_PORT.to_string()),
+            osc_status: String::new(),
+            osc_listener: None,
+            announce_enabled: load_bool_setting("announce_enabled"),
+            announce_name: load_setting("announce_name").filter(|n| !n.trim().is_empty()).unwrap_or_else(mdns::default_name),
+            announce_status: String::new(),
+            announcer: None,
+            rtsp_enabled: load_bool_setting("rtsp_enabled"),
+            rtsp_port: load_setting("rtsp_port").unwrap_or_else(|| rtsp::DEFAULT_RTSP_PORT.to_string()),
+            rtsp_status: String::new(),
+            rtsp_server: None,
+            aes67_enabled: load_bool_setting("aes67_enabled"),
+            aes67_group: load_setting("aes67_group").unwrap_or_else(|| aes67::DEFAULT_GROUP.to_string()),
+            aes67_port: load_setting("aes67_port").unwrap_or_else(|| aes67::DEFAULT_PORT.to_string()),
+            aes67_status: String::new(),
+            aes67_sender: None,
+            multicast_enabled: load_bool_setting("multicast_enabled"),
+            multicast_group: load_setting("multicast_group").unwrap_or_else(|| multicast::DEFAULT_GROUP.to_string()),
+            multicast_port: load_setting("multicast_port").unwrap_or_else(|| multicast::DEFAULT_PORT.to_string()),
+            multicast_ttl: load_setting("multicast_ttl")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(multicast::DEFAULT_TTL)
+                .clamp(*multicast::TTL_RANGE.start(), *multicast::TTL_RANGE.end()),
+            multicast_format: load_setting("multicast_format")
+                .and_then(|v| StreamFormat::parse(&v))
+                .unwrap_or(multicast::DEFAULT_FORMAT),
+            multicast_status: String::new(),
+            multicast_sender: None,
+            ambiance_path: load_setting("ambiance").unwrap_or_default(),
+            ambiance_status: String::new(),
+            telemetry_mode: telemetry::Mode::parse(&load_setting("telemetry").unwrap_or_default()),
+            telemetry_url: load_setting("telemetry_url").unwrap_or_default(),
+            telemetry_status: Arc::new(Mutex::new(String::new())),
+            session_tracker: None,
+            last_report: None,
+            midi_enabled: load_bool_setting("midi_enabled"),
+            midi_ports: midi::list_ports(),
+            midi_port: load_setting("midi_port").unwrap_or_default(),
+            midi_mapping: Arc::new(Mutex::new(midi_mapping)),
+            midi_saved_mapping: midi_mapping,
+            midi_learn: Arc::new(Mutex::new(None)),
+            midi_status: String::new(),
+            midi_listener: None,
+            command_tx,
+            command_rx,
+            power_rx,
+            _power_listener: power_listener,
+            reconnect_after_resume: false,
+            reconnect_at: None,
+            connect_started: None,
+            connect_timeout_secs: load_setting("connect_timeout")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS)
+                .clamp(*CONNECT_TIMEOUT_RANGE_SECS.start(), *CONNECT_TIMEOUT_RANGE_SECS.end()),
+            recv_buffer_kb: load_setting("recv_buffer_kb")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(socket_buffer::DEFAULT_KB)
+                .clamp(*socket_buffer::RANGE_KB.start(), *socket_buffer::RANGE_KB.end()),
+            adaptive_buffer: load_bool_setting("adaptive_buffer"),
+            signal_threshold_db: load_setting("signal_threshold_db")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(signal::DEFAULT_THRESHOLD_DB)
+                .clamp(*signal::THRESHOLD_RANGE_DB.start(), *signal::THRESHOLD_RANGE_DB.end()),
+            sent_rate: RateMeter::default(),
+            recv_rate: RateMeter::default(),
+            max_fps: load_setting("max_fps")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(repaint::DEFAULT_MAX_FPS)
+                .clamp(*repaint::MAX_FPS_RANGE.start(), *repaint::MAX_FPS_RANGE.end()),
+            battery_saver: battery::Mode::parse(&load_setting("battery_saver").unwrap_or_default()),
+            saving: false,
+            power_checked: None,
+            session_saving: None,
+            bandwidth_limit: load_setting("bandwidth_limit_kbps")
+                .and_then(|v| v.parse().ok())
+                .filter(|&kbps| kbps > 0)
+                .map(|kbps: u32| kbps.clamp(*limiter::KBPS_RANGE.start(), *limiter::KBPS_RANGE.end())),
+            session_limit: None,
+            meter_bars: Default::default(),
+            process_priority: priority::Priority::parse(&load_setting("process_priority").unwrap_or_default()),
+            pin_performance: load_bool_setting("pin_performance_cores"),
+            core_counts: None,
+            taskbar_badge: load_bool_setting("taskbar_badge"),
+            taskbar: taskbar::Overlay::default(),
+            jump_list: load_bool_setting("jump_list"),
+            tray_icon: load_bool_setting("tray_icon"),
+            minimize_to_tray: load_bool_setting("minimize_to_tray"),
+            tray: tray::Tray::default(),
+            capture_exclude: load_setting("capture_exclude").unwrap_or_default(),
+            app_sessions: Vec::new(),
+            jump_list_names: None,
+            retry_policy: load_retry_policy(),
+            retries: 0,
+            session_running: false,
+            session_device: None,
+            standby_device: load_setting("standby_device").filter(|id| !id.is_empty()),
+            seen_switches: 0,
+            seen_underruns: 0,
+            seen_packets: (0, Instant::now()),
+            peer_quiet: false,
+            repair: None,
             debug_logging,
             debug_logging_flag: Arc::new(AtomicBool::new(debug_logging)),
+            log_format: log_format::LogFormat::parse(&load_setting("log_format").unwrap_or_default()),
+            share_log: None,
+            share_status: String::new(),
             log_file: Arc::new(Mutex::new(None)),
+        };
+        DownmixMode::parse(&load_setting("downmix").unwrap_or_default()).store(&app.state.downmix);
+        resample::Quality::parse(&load_setting("resample_quality").unwrap_or_default()).store(&app.state.resample_quality);
+        *app.state.dsp.lock() = load_dsp_settings();
+        app.state.gain_out.set(gain::to_linear(gain::parse_db(load_setting("gain_out_db"))));
+        app.state.gain_mic.set(gain::to_linear(gain::parse_db(load_setting("gain_mic_db"))));
+        app.state.mix.load(&load_setting("receivers").unwrap_or_default());
+        app.state.repaint.attach(cc.egui_ctx.clone(), app.max_fps);
+        app.state.pin_performance.store(app.pin_performance, Ordering::Relaxed);
+        if let Err(e) = priority::set_process_priority(app.process_priority) {
+            log_message(&app.log_file, &app.debug_logging_flag, &format!("Process priority: {}", e));
+        }
+        if let Some(name) = load_setting("profile") {
+            if let Some(idx) = app.profiles.iter().position(|p| p.name == name) {
+                app.apply_profile(idx);
+            }
+        }
+        if let Some(listener) = instance_listener {
+            instance::serve(listener, app.command_tx.clone(), app.egui_ctx.clone());
+        }
+        // Command-line actions run on the first frame, after everything is loaded,
+        // or once the profile's devices are there if asked to wait for them
+        let profile = app.active_profile.and_then(|i| app.profiles.get(i));
+        match (cli.wait_devices, profile) {
+            (Some(timeout), Some(profile)) if !cli.commands.is_empty() => {
+                let wait = device_wait::DeviceWait::new(
+                    Instant::now(),
+                    timeout,
+                    profile.capture_device.clone(),
+                    profile.playback_device.clone(),
+                );
+                app.device_wait = Some((wait, cli.commands));
+            }
+            (wait, _) => {
+                if wait.is_some() && !cli.commands.is_empty() {
+                    log_message(&app.log_file, &app.debug_logging_flag, "Devices: no profile is active, so there are no devices to wait for");
+                }
+                for command in cli.commands {
+                    let _ = app.command_tx.try_send(command);
+                }
+            }
         }
+        if cli.minimized {
+            cc.egui_ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(true));
+        }
+        app.register_hotkey();
+        app.start_control_server();
+        app.start_osc_listener();
+        app.start_midi_listener();
+        app.start_rtsp_server();
+        app.start_announcer();
+        app.start_aes67_sender();
+        app.start_multicast_sender();
+        app.load_ambiance();
+        app
     }
 
-    fn enumerate_devices() -> (Vec<AudioDeviceInfo>, Vec<AudioDeviceInfo>) {
-        let host = cpal::default_host();
+    fn register_hotkey(&mut self) {
+        // Dropping the old listener unregisters it first
+        self.hotkey_listener = None;
+        if !self.hotkey_enabled {
+            self.hotkey_status.clear();
+            return;
+        }
 
-        // Input devices include both actual inputs AND output devices (for loopback capture)
-        let mut input_devices: Vec<AudioDeviceInfo> = Vec::new();
+        let tx = self.command_tx.clone();
+        let ctx = self.egui_ctx.clone();
+        let result = hotkey::parse(&self.hotkey_text).and_then(|key| {
+            hotkey::HotkeyListener::start(key, move || {
+                let _ = tx.try_send(ControlCommand::ToggleConnection);
+                ctx.request_repaint();
+            })
+        });
 
-        // Add regular input devices (microphones, Stereo Mix, etc.)
-        if let Ok(devices) = host.input_devices() {
-            for d in devices {
-                input_devices.push(AudioDeviceInfo {
-                    name: d.name().unwrap_or_else(|_| "Unknown".to_string()),
-                    is_output: false,
-                });
+        match result {
+            Ok(listener) => {
+                self.hotkey_listener = Some(listener);
+                self.hotkey_status = format!("{} toggles the connection", self.hotkey_text.trim());
             }
+            Err(e) => self.hotkey_status = format!("Error: {}", e),
         }
+    }
 
-        // Add output devices as loopback sources (for capturing PC audio)
-        if let Ok(devices) = host.output_devices() {
-            for d in devices {
-                input_devices.push(AudioDeviceInfo {
-                    name: format!("{} (Loopback)", d.name().unwrap_or_else(|_| "Unknown".to_string())),
-                    is_output: true,
-                });
+    fn start_control_server(&mut self) {
+        self.control_server = None;
+        if !self.control_enabled {
+            self.control_status.clear();
+            return;
+        }
+
+        match ControlServer::start(
+            self.control_token.clone(),
+            self.command_tx.clone(),
+            self.state.clone(),
+            self.egui_ctx.clone(),
+        ) {
+            Ok(server) => {
+                self.control_server = Some(server);
+                self.control_status = format!("Listening on http://127.0.0.1:{}", control::CONTROL_PORT);
             }
+            Err(e) => self.control_status = format!("Error: {}", e),
         }
+    }
 
-        // Output devices for playback
-        let output_devices: Vec<AudioDeviceInfo> = host
-            .output_devices()
-            .map(|devices| {
-                devices
-                    .map(|d| AudioDeviceInfo {
-                        name: d.name().unwrap_or_else(|_| "Unknown".to_string()),
-                        is_output: true,
-                    })
-                    .collect()
-            })
-            .unwrap_or_default();
+    fn start_osc_listener(&mut self) {
+        self.osc_listener = None;
+        if !self.osc_enabled {
+            self.osc_status.clear();
+            return;
+        }
 
-        (input_devices, output_devices)
+        let result = self
+            .osc_port
+            .trim()
+            .parse::<u16>()
+            .map_err(|_| anyhow!("Invalid port"))
+            .and_then(|port| osc::OscListener::start(port, self.command_tx.clone(), self.egui_ctx.clone()));
+
+        match result {
+            Ok(listener) => {
+                self.osc_listener = Some(listener);
+                self.osc_status = format!("Listening for OSC on UDP port {}", self.osc_port.trim());
+            }
+            Err(e) => self.osc_status = format!("Error: {}", e),
+        }
     }
 
-    fn refresh_devices(&mut self) {
-        let (input, output) = Self::enumerate_devices();
-        self.input_devices = input;
-        self.output_devices = output;
-        self.selected_input = 0;
-        self.selected_output = 0;
+    fn start_rtsp_server(&mut self) {
+        self.rtsp_server = None;
+        *self.state.rtsp_feed.lock() = None;
+        if !self.rtsp_enabled {
+            self.rtsp_status.clear();
+            return;
+        }
+
+        let result = self
+            .rtsp_port
+            .trim()
+            .parse::<u16>()
+            .map_err(|_| anyhow!("Invalid port"))
+            .and_then(rtsp::RtspServer::start);
+
+        match result {
+            Ok(server) => {
+                *self.state.rtsp_feed.lock() = Some(server.feed());
+                self.rtsp_server = Some(server);
+                self.rtsp_status = format!("Serving on TCP port {}", self.rtsp_port.trim());
+            }
+            Err(e) => self.rtsp_status = format!("Error: {}", e),
+        }
     }
 
-    fn start_logging(&mut self) {
-        if self.debug_logging {
-            let log_file = create_log_file();
-            *self.log_file.lock() = log_file;
+    fn start_announcer(&mut self) {
+        // Dropping the old one says goodbye first
+        self.announcer = None;
+        if !self.announce_enabled {
+            self.announce_status.clear();
+            return;
+        }
+        let service = mdns::Service { name: self.announce_name.trim().to_string(), port: RECEIVE_PORT };
+        match mdns::Announcer::start(service) {
+            Ok(announcer) => {
+                self.announcer = Some(announcer);
+                self.announce_status = format!("Announced as \"{}\"", self.announce_name.trim());
+            }
+            Err(e) => self.announce_status = format!("Error: {}", e),
         }
     }
 
-    fn stop_logging(&mut self) {
-        *self.log_file.lock() = None;
+    fn show_capture_only_button(&mut self, ui: &mut egui::Ui) {
+        let is_connected = self.state.is_connected.load(Ordering::SeqCst);
+        if ui
+            .add_enabled(!is_connected, egui::Button::new("Stream without iPhone"))
+            .on_hover_text("Capture for RTSP, AES67 or the classroom group without connecting to the iPhone")
+            .on_disabled_hover_text("Audio already flows to these outputs while connected")
+            .clicked()
+        {
+            self.start_session(true);
+        }
     }
 
-    fn connect(&mut self) {
-        if self.iphone_ip.trim().is_empty() {
-            *self.state.status_message.lock() = "Please select a device first".to_string();
+    fn start_aes67_sender(&mut self) {
+        self.aes67_sender = None;
+        *self.state.aes67_feed.lock() = None;
+        if !self.aes67_enabled {
+            self.aes67_status.clear();
             return;
         }
 
-        // Start logging if enabled
-        self.start_logging();
-
-        // Reset state
-        self.stop_flag.store(false, Ordering::SeqCst);
-        self.state.packets_sent.store(0, Ordering::SeqCst);
-        self.state.packets_recv.store(0, Ordering::SeqCst);
-        self.state.packets_recv_with_audio.store(0, Ordering::SeqCst);
-        self.state.packets_sent_with_audio.store(0, Ordering::SeqCst);
-        self.state.audio_callbacks.store(0, Ordering::SeqCst);
-        self.state.is_connected.store(true, Ordering::SeqCst);
-        *self.state.status_message.lock() = "Connecting...".to_string();
-
-        let iphone_ip = self.iphone_ip.clone();
-        let selected_input = self.selected_input;
-        let selected_output = self.selected_output;
-        let input_is_loopback = self.input_devices.get(selected_input).map(|d| d.is_output).unwrap_or(false);
-        let state = self.state.clone();
-        let stop_flag = self.stop_flag.clone();
-        let debug_flag = self.debug_logging_flag.clone();
-        let log_file = self.log_file.clone();
+        let result = (|| {
+            let group: Ipv4Addr = self.aes67_group.trim().parse().map_err(|_| anyhow!("Invalid group address"))?;
+            let port: u16 = self.aes67_port.trim().parse().map_err(|_| anyhow!("Invalid port"))?;
+            let local_ip = match local_ip_towards(SocketAddr::from((group, port))) {
+                Some(IpAddr::V4(ip)) => ip,
+                _ => return Err(anyhow!("No IPv4 network to send on")),
+            };
+            aes67::Aes67Sender::start(group, port, local_ip).map(|sender| (sender, local_ip))
+        })();
+
+        match result {
+            Ok((sender, local_ip)) => {
+                *self.state.aes67_feed.lock() = Some(sender.feed());
+                self.aes67_sender = Some(sender);
+                self.aes67_status = format!(
+                    "Announcing {}:{} via SAP from {}",
+                    self.aes67_group.trim(),
+                    self.aes67_port.trim(),
+                    local_ip
+                );
+            }
+            Err(e) => self.aes67_status = format!("Error: {}", e),
+        }
+    }
 
-        // Log connection start
-        log_message(&log_file, &debug_flag, &format!(
-            "Starting connection to {} (input device: {}, loopback: {}, output device: {})",
-            iphone_ip, selected_input, input_is_loopback, selected_output
-        ));
+    fn start_multicast_sender(&mut self) {
+        self.multicast_sender = None;
+        *self.state.multicast_feed.lock() = None;
+        if !self.multicast_enabled {
+            self.multicast_status.clear();
+            return;
+        }
 
-        self._audio_thread = Some(thread::spawn(move || {
-            if let Err(e) = run_bridge(
-                iphone_ip,
-                selected_input,
-                selected_output,
-                input_is_loopback,
-                state.clone(),
-                stop_flag,
-                debug_flag.clone(),
-                log_file.clone(),
-            ) {
-                log_message(&log_file, &debug_flag, &format!("Bridge error: {}", e));
-                *state.status_message.lock() = format!("Error: {}", e);
-                state.is_connected.store(false, Ordering::SeqCst);
+        let result = (|| {
+            let group: Ipv4Addr = self.multicast_group.trim().parse().map_err(|_| anyhow!("Invalid group address"))?;
+            let port: u16 = self.multicast_port.trim().parse().map_err(|_| anyhow!("Invalid port"))?;
+            let local_ip = match local_ip_towards(SocketAddr::from((group, port))) {
+                Some(IpAddr::V4(ip)) => ip,
+                _ => return Err(anyhow!("No IPv4 network to send on")),
+            };
+            multicast::MulticastSender::start(group, port, self.multicast_ttl, self.multicast_format, local_ip)
+                .map(|sender| (sender, local_ip))
+        })();
+
+        match result {
+            Ok((sender, local_ip)) => {
+                *self.state.multicast_feed.lock() = Some(sender.feed());
+                self.multicast_sender = Some(sender);
+                self.multicast_status = format!(
+                    "Sending to {}:{} from {} while streaming",
+                    self.multicast_group.trim(),
+                    self.multicast_port.trim(),
+                    local_ip
+                );
             }
-        }));
+            Err(e) => self.multicast_status = format!("Error: {}", e),
+        }
     }
 
-    fn disconnect(&mut self) {
-        log_message(&self.log_file, &self.debug_logging_flag, "Disconnecting...");
-        self.stop_flag.store(true, Ordering::SeqCst);
-        self.state.is_connected.store(false, Ordering::SeqCst);
-        *self.state.status_message.lock() = "Disconnected".to_string();
-        self._audio_thread = None;
-        self.stop_logging();
+    fn load_ambiance(&mut self) {
+        let path = self.ambiance_path.trim();
+        let result = if path.is_empty() { Ok(None) } else { sources::Ambiance::load(Path::new(path)).map(Some) };
+        match result {
+            Ok(ambiance) => {
+                self.ambiance_status = match &ambiance {
+                    Some(ambiance) => format!("Looping {:.1} s", ambiance.seconds()),
+                    None => String::new(),
+                };
+                *self.state.ambiance.lock() = ambiance;
+            }
+            Err(e) => {
+                *self.state.ambiance.lock() = None;
+                self.ambiance_status = format!("Error: {}", e);
+            }
+        }
     }
-}
-
-impl eframe::App for BudBridgeApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        ctx.request_repaint_after(std::time::Duration::from_millis(500));
 
-        egui::CentralPanel::default().show(ctx, |ui| {
-            ui.heading("BudBridge");
-            ui.add_space(5.0);
+    fn rtsp_url(&self) -> String {
+        // The address on the default route (192.0.2.1 is a documentation address)
+        let host = local_ip_towards(SocketAddr::from(([192, 0, 2, 1], 9)))
+            .map(|ip| ip.to_string())
+            .unwrap_or_else(|| "<this-pc>".to_string());
+        format!("rtsp://{}:{}/{}", host, self.rtsp_port.trim(), rtsp::STREAM_PATH)
+    }
 
-            ui.horizontal(|ui| {
-                ui.selectable_value(&mut self.current_tab, Tab::Connection, "Connection");
-                ui.selectable_value(&mut self.current_tab, Tab::Devices, "Devices");
-                ui.selectable_value(&mut self.current_tab, Tab::Settings, "Settings");
-            });
-            ui.separator();
-            ui.add_space(5.0);
+    fn start_midi_listener(&mut self) {
+        self.midi_listener = None;
+        if !self.midi_enabled || self.midi_port.is_empty() {
+            self.midi_status.clear();
+            return;
+        }
 
-            match self.current_tab {
-                Tab::Connection => self.show_connection_tab(ui),
-                Tab::Devices => self.show_devices_tab(ui),
-                Tab::Settings => self.show_settings_tab(ui),
+        match midi::MidiListener::start(
+            &self.midi_port,
+            self.midi_mapping.clone(),
+            self.midi_learn.clone(),
+            self.command_tx.clone(),
+            self.egui_ctx.clone(),
+        ) {
+            Ok(listener) => {
+                self.midi_listener = Some(listener);
+                self.midi_status = format!("Listening on {}", self.midi_port);
             }
-        });
+            Err(e) => self.midi_status = format!("Error: {}", e),
+        }
     }
-}
-
-impl BudBridgeApp {
-    fn show_connection_tab(&mut self, ui: &mut egui::Ui) {
-        let is_connected = self.state.is_connected.load(Ordering::SeqCst);
 
-        ui.group(|ui| {
-            ui.label("Target Device");
-            ui.add_space(5.0);
+    /// Persist bindings captured by learn mode on the MIDI thread
+    fn save_midi_mapping_if_changed(&mut self) {
+        let mapping = *self.midi_mapping.lock();
+        if mapping != self.midi_saved_mapping {
+            save_setting("midi_gain_cc", &midi::binding_setting(mapping.gain_cc));
+            save_setting("midi_mute_note", &midi::binding_setting(mapping.mute_note));
+            self.midi_saved_mapping = mapping;
+        }
+    }
 
-            let selected_name = self
-                .selected_device
-                .and_then(|i| self.saved_devices.get(i))
-                .map(|d| d.name.clone())
-                .unwrap_or_else(|| "Select a device...".to_string());
+    fn handle_command(&mut self, command: ControlCommand) {
+        log_message(&self.log_file, &self.debug_logging_flag, &format!("Command: {:?}", command));
+        let is_connected = self.state.is_connected.load(Ordering::SeqCst);
+        match command {
+            ControlCommand::Connect if !is_connected => self.connect_default(),
+            ControlCommand::ConnectTo(name) => self.connect_to(&name),
+            ControlCommand::ApplyProfile(name) => match self.profiles.iter().position(|p| p.name == name) {
+                Some(idx) => self.apply_profile(idx),
+                None => *self.state.status_message.lock() = format!("Error: unknown profile \"{}\"", name),
+            },
+            ControlCommand::Disconnect if is_connected => self.disconnect(),
+            ControlCommand::Connect | ControlCommand::Disconnect => {}
+            ControlCommand::ToggleConnection => self.toggle_connection(),
+            ControlCommand::ToggleMuteMic => {
+                self.state.mute_mic.fetch_xor(true, Ordering::SeqCst);
+            }
+            ControlCommand::ToggleMuteOut => {
+                self.state.mute_out.fetch_xor(true, Ordering::SeqCst);
+            }
+            ControlCommand::CycleDevice => self.cycle_device(),
+            ControlCommand::SetGainOut(gain) => self.state.gain_out.set(gain),
+            ControlCommand::SetGainMic(gain) => self.state.gain_mic.set(gain),
+            ControlCommand::Show => {
+                self.egui_ctx.send_viewport_cmd(egui::ViewportCommand::Visible(true));
+                self.egui_ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(false));
+                self.egui_ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+            }
+        }
+    }
 
-            let mut new_selection: Option<usize> = None;
+    /// Select the next saved device, reconnecting if a session is active
+    fn cycle_device(&mut self) {
+        if self.saved_devices.is_empty() {
+            return;
+        }
+        let next = self.selected_device.map(|i| (i + 1) % self.saved_devices.len()).unwrap_or(0);
+        self.selected_device = Some(next);
+        self.iphone_ip = self.saved_devices[next].ip.clone();
 
-            ui.horizontal(|ui| {
-                ui.label("Device:");
-                ui.add_enabled_ui(!is_connected, |ui| {
-                    egui::ComboBox::from_id_salt("saved_devices")
-                        .width(200.0)
-                        .selected_text(&selected_name)
-                        .show_ui(ui, |ui| {
-                            for (i, device) in self.saved_devices.iter().enumerate() {
-                                if ui.selectable_value(&mut self.selected_device, Some(i), &device.name).changed() {
-                                    new_selection = Some(i);
-                                }
-                            }
-                        });
-                });
-            });
+        if self.state.is_connected.load(Ordering::SeqCst) {
+            self.disconnect();
+            self.connect();
+        }
+    }
 
-            if self.saved_devices.is_empty() {
-                ui.label("No devices saved. Go to Devices tab to add one.");
+    /// Connect to a saved device by name, switching over if already connected elsewhere
+    fn connect_to(&mut self, name: &str) {
+        let Some(idx) = self.saved_devices.iter().position(|d| d.name == name) else {
+            *self.state.status_message.lock() = format!("Error: unknown device \"{}\"", name);
+            return;
+        };
+        if self.state.is_connected.load(Ordering::SeqCst) {
+            if self.selected_device == Some(idx) {
+                return;
             }
+            self.disconnect();
+        }
+        self.selected_device = Some(idx);
+        self.iphone_ip = self.saved_devices[idx].ip.clone();
+        self.connect();
+    }
 
-            if let Some(i) = new_selection {
-                if let Some(dev) = self.saved_devices.get(i) {
-                    self.iphone_ip = dev.ip.clone();
+    fn handle_power_event(&mut self, event: power::PowerEvent) {
+        match event {
+            power::PowerEvent::Suspend => {
+                if self.state.is_connected.load(Ordering::SeqCst) {
+                    log_message(&self.log_file, &self.debug_logging_flag, "System suspending, closing session");
+                    self.disconnect();
+                    self.reconnect_after_resume = true;
                 }
             }
-        });
+            power::PowerEvent::Resume => {
+                if std::mem::take(&mut self.reconnect_after_resume) {
+                    // Audio devices and Wi-Fi need a moment to come back
+                    self.reconnect_at = Some(Instant::now() + RESUME_RECONNECT_DELAY);
+                    *self.state.status_message.lock() = "Resuming after sleep...".to_string();
+                }
+            }
+        }
+    }
 
-        ui.add_space(10.0);
+    /// Turns the battery saver on or off with the power source, reconnecting a
+    /// session that streams the other way
+    fn follow_power_source(&mut self) {
+        if self.power_checked.is_some_and(|t| t.elapsed() < POWER_POLL_INTERVAL) {
+            return;
+        }
+        self.power_checked = Some(Instant::now());
+        let saving = self.battery_saver.active(battery::on_battery());
+        if saving == self.saving {
+            return;
+        }
+        self.saving = saving;
+        battery::set_eco_qos(saving);
+        self.state.eco_qos.store(saving, Ordering::Relaxed);
+        self.state.repaint.set_max_fps(self.fps_cap());
+        log_message(&self.log_file, &self.debug_logging_flag, if saving { "Battery saver on" } else { "Battery saver off" });
+        if self.state.is_connected.load(Ordering::SeqCst) && self.session_saving.is_some_and(|s| s != saving) {
+            self.disconnect();
+            self.reconnect_at = Some(Instant::now());
+        }
+    }
 
-        ui.group(|ui| {
-            ui.label("Audio Settings");
-            ui.add_space(5.0);
+    /// Takes what the audio callbacks metered since the last frame. Done every
+    /// frame, shown or not, so the sums never pile up.
+    fn update_meters(&mut self) {
+        let now = Instant::now();
+        let meters = [&self.state.meter_out, &self.state.meter_mic];
+        for (bars, meter) in self.meter_bars.iter_mut().zip(meters) {
+            bars.update(meter.take(), now);
+        }
+        // Audio asks for frames while it flows; the bars still have to fall after
+        if self.state.is_connected.load(Ordering::Relaxed) && self.meter_bars.iter().any(meter::Ballistics::settling) {
+            self.state.repaint.notify();
+        }
+    }
 
-            ui.horizontal(|ui| {
-                ui.label("PC Audio → iPhone:");
-                egui::ComboBox::from_id_salt("input_device")
-                    .width(200.0)
-                    .selected_text(
-                        self.input_devices
-                            .get(self.selected_input)
-                            .map(|d| d.name.as_str())
-                            .unwrap_or("None"),
-                    )
+    /// The repaint cap in effect, lower while saving battery
+    fn fps_cap(&self) -> u32 {
+        if self.saving {
+            self.max_fps.min(battery::MAX_FPS)
+        } else {
+            self.max_fps
+        }
+    }
+
+    fn connect_default(&mut self) {
+        if let Some(dev) = self.default_device.and_then(|i| self.saved_devices.get(i)) {
+            self.iphone_ip = dev.ip.clone();
+            self.selected_device = self.default_device;
+        }
+        self.connect();
+    }
+
+    fn toggle_connection(&mut self) {
+        if self.state.is_connected.load(Ordering::SeqCst) {
+            self.disconnect();
+        } else {
+            self.connect_default();
+        }
+    }
+
+    /// Blocks for as long as the drivers take; only called before the window is
+    /// up, and on the worker thread refresh_devices starts
+    fn enumerate_devices() -> DeviceLists {
+        let host = cpal::default_host();
+        let inputs = DeviceId::assign(host.input_devices().map(|ds| ds.map(|d| device_label(&d)).collect()).unwrap_or_default());
+        let outputs = DeviceId::assign(host.output_devices().map(|ds| ds.map(|d| device_label(&d)).collect()).unwrap_or_default());
+
+        // Input devices include both actual inputs (microphones, Stereo Mix, etc.)
+        // AND output devices as loopback sources (for capturing PC audio)
+        let mut input_devices: Vec<AudioDeviceInfo> = inputs
+            .into_iter()
+            .map(|id| AudioDeviceInfo { name: id.name.clone(), is_output: false, id })
+            .collect();
+        input_devices.extend(outputs.iter().map(|id| AudioDeviceInfo {
+            name: format!("{} (Loopback)", id.name),
+            is_output: true,
+            id: id.clone(),
+        }));
+
+        // Output devices for playback
+        let output_devices: Vec<AudioDeviceInfo> = outputs
+            .into_iter()
+            .map(|id| AudioDeviceInfo { name: id.name.clone(), is_output: true, id })
+            .collect();
+
+        (input_devices, output_devices)
+    }
+
+    /// First installed virtual cable, in order of preference, and its playback device index
+    fn find_virtual_cable(&self) -> Option<(usize, &'static VirtualCable)> {
+        VIRTUAL_CABLES.iter().find_map(|cable| {
+            self.output_devices
+                .iter()
+                .position(|d| d.name.to_lowercase().contains(cable.playback_match))
+                .map(|idx| (idx, cable))
+        })
+    }
+
+    fn capture_profile(&self, name: String) -> Profile {
+        let device_name = |devices: &[AudioDeviceInfo], idx: usize| {
+            devices.get(idx).map(|d| d.name.clone()).unwrap_or_default()
+        };
+        Profile {
+            name,
+            target_device_id: self
+                .selected_device
+                .and_then(|i| self.saved_devices.get(i))
+                .map(|d| d.id.clone())
+                .unwrap_or_default(),
+            capture_device: device_name(&self.input_devices, self.selected_input),
+            playback_device: device_name(&self.output_devices, self.selected_output),
+            gain_out: self.state.gain_out.get(),
+            gain_mic: self.state.gain_mic.get(),
+            mute_out: self.state.mute_out.load(Ordering::Relaxed),
+            mute_mic: self.state.mute_mic.load(Ordering::Relaxed),
+            formats: self.formats,
+        }
+    }
+
+    /// Selects the devices with these names, where they are listed
+    fn select_devices(&mut self, capture: &str, playback: &str) {
+        if let Some(i) = self.input_devices.iter().position(|d| d.name == capture) {
+            self.selected_input = i;
+        }
+        if let Some(i) = self.output_devices.iter().position(|d| d.name == playback) {
+            self.selected_output = i;
+        }
+    }
+
+    /// Apply a profile, reconnecting if it targets another phone mid-session
+    fn apply_profile(&mut self, idx: usize) {
+        let Some(profile) = self.profiles.get(idx).cloned() else {
+            return;
+        };
+        let before = self.selected_device;
+
+        if let Some(i) = self.saved_devices.iter().position(|d| d.id == profile.target_device_id) {
+            self.selected_device = Some(i);
+            self.iphone_ip = self.saved_devices[i].ip.clone();
+        }
+        self.select_devices(&profile.capture_device, &profile.playback_device);
+        self.state.gain_out.set(profile.gain_out);
+        self.state.gain_mic.set(profile.gain_mic);
+        self.state.mute_out.store(profile.mute_out, Ordering::Relaxed);
+        self.state.mute_mic.store(profile.mute_mic, Ordering::Relaxed);
+        self.set_formats(profile.formats);
+        self.active_profile = Some(idx);
+        save_setting("profile", &profile.name);
+
+        // Audio devices switch live (see switch_audio_devices); another phone needs a new session
+        if before != self.selected_device && self.state.is_connected.load(Ordering::SeqCst) {
+            self.disconnect();
+            self.connect();
+        }
+    }
+
+    /// Takes effect on the next connection
+    fn set_formats(&mut self, formats: Formats) {
+        self.formats = formats;
+        save_setting("down_format", &formats.down.to_string());
+        save_setting("up_format", &formats.up.to_string());
+    }
+
+    /// Re-reads the device lists in the background; a scan already running is reused
+    fn refresh_devices(&mut self, pick_cable: bool, manual: bool) {
+        self.devices_scanned = Instant::now();
+        if let Some(scan) = &mut self.device_scan {
+            scan.pick_cable |= pick_cable;
+            scan.manual |= manual;
+            return;
+        }
+        let (tx, rx) = bounded(1);
+        let ctx = self.egui_ctx.clone();
+        thread::spawn(move || {
+            let _ = tx.send(Self::enumerate_devices());
+            ctx.request_repaint();
+        });
+        self.device_scan = Some(DeviceScan { rx, pick_cable, manual });
+    }
+
+    fn poll_devices(&mut self) {
+        if self.device_scan.is_none() && self.devices_scanned.elapsed() >= DEVICE_POLL_INTERVAL {
+            self.refresh_devices(false, false);
+        }
+    }
+
+    fn apply_device_scan(&mut self) {
+        let Some(scan) = &self.device_scan else {
+            return;
+        };
+        let (input, output) = match scan.rx.try_recv() {
+            Ok(lists) => lists,
+            Err(TryRecvError::Empty) => return,
+            // The scan died in a driver; keep the lists we had
+            Err(TryRecvError::Disconnected) => {
+                self.device_scan = None;
+                return;
+            }
+        };
+        let pick_cable = scan.pick_cable;
+        self.device_scan = None;
+        if input == self.input_devices && output == self.output_devices && !pick_cable {
+            return;
+        }
+        log_message(&self.log_file, &self.debug_logging_flag, &format!(
+            "Devices changed: {} capture, {} playback", input.len(), output.len()
+        ));
+
+        // Indices shift when a device comes or goes, so follow the selections and
+        // the running session's devices; only one that is gone falls back to the first
+        let gone = |old: &[AudioDeviceInfo], index: usize, new: &[AudioDeviceInfo]| {
+            let name = &old.get(index)?.name;
+            rematch_device(old, index, new).is_none().then(|| name.clone())
+        };
+        let gone_input = gone(&self.input_devices, self.selected_input, &input);
+        let gone_output = gone(&self.output_devices, self.selected_output, &output);
+        let selected_input = rematch_device(&self.input_devices, self.selected_input, &input).unwrap_or(0);
+        let selected_output = rematch_device(&self.output_devices, self.selected_output, &output).unwrap_or(0);
+        if let Some(i) = rematch_device(&self.input_devices, self.session_audio.0, &input) {
+            self.session_audio.0 = i;
+        }
+        if let Some(i) = rematch_device(&self.output_devices, self.session_audio.1, &output) {
+            self.session_audio.1 = i;
+        }
+        self.input_devices = input;
+        self.output_devices = output;
+        self.selected_input = selected_input;
+        self.selected_output = selected_output;
+
+        let fallback = |devices: &[AudioDeviceInfo]| devices.first().map_or("nothing".to_string(), |d| format!("\"{}\"", d.name));
+        let notices = [
+            gone_input.map(|name| format!("\"{}\" is gone; PC audio now comes from {}", name, fallback(&self.input_devices))),
+            gone_output.map(|name| format!("\"{}\" is gone; iPhone audio now plays on {}", name, fallback(&self.output_devices))),
+        ];
+        for notice in notices.into_iter().flatten() {
+            log_message(&self.log_file, &self.debug_logging_flag, &notice);
+            self.state.timeline.lock().push(timeline::Kind::DeviceChanged, notice.clone());
+            self.device_notices.push(notice);
+        }
+        if let Some((cable_idx, _)) = self.find_virtual_cable().filter(|_| pick_cable) {
+            self.selected_output = cable_idx;
+        }
+    }
+
+    /// Rescans until the devices --wait-devices waits for are listed, then selects
+    /// them and runs the held commands; past the timeout, runs them anyway
+    fn wait_for_devices(&mut self) {
+        let Some((wait, _)) = &self.device_wait else {
+            return;
+        };
+        let names = |devices: &[AudioDeviceInfo]| devices.iter().map(|d| d.name.clone()).collect::<Vec<_>>();
+        let (inputs, outputs) = (names(&self.input_devices), names(&self.output_devices));
+        let message = match wait.check(Instant::now(), &inputs, &outputs) {
+            device_wait::Outcome::Waiting => {
+                let status = format!("Waiting for {}...", wait.missing(&inputs, &outputs).join(" and "));
+                if *self.state.status_message.lock() != status {
+                    log_message(&self.log_file, &self.debug_logging_flag, &format!("Devices: {}", status));
+                    *self.state.status_message.lock() = status;
+                }
+                if self.device_scan.is_none() && self.devices_scanned.elapsed() >= device_wait::RESCAN_INTERVAL {
+                    self.refresh_devices(false, false);
+                }
+                return;
+            }
+            device_wait::Outcome::Ready => "Devices: all there, running the command-line actions".to_string(),
+            device_wait::Outcome::TimedOut(missing) => {
+                let notice = format!(
+                    "{} didn't appear within {} s; going ahead with the devices that are there",
+                    missing.iter().map(|name| format!("\"{}\"", name)).collect::<Vec<_>>().join(" and "),
+                    wait.timeout().as_secs()
+                );
+                self.device_notices.push(notice.clone());
+                format!("Devices: {}", notice)
+            }
+        };
+        log_message(&self.log_file, &self.debug_logging_flag, &message);
+        let Some((wait, commands)) = self.device_wait.take() else {
+            return;
+        };
+        self.state.status_message.lock().clear();
+        self.select_devices(&wait.capture, &wait.playback);
+        for command in commands {
+            self.handle_command(command);
+        }
+    }
+
+    fn start_logging(&mut self) {
+        if self.debug_logging {
+            let log_file = create_log_file(self.log_format);
+            *self.log_file.lock() = log_file;
+        }
+    }
+
+    fn refresh_wifi(&mut self) {
+        self.wifi_info = wifi::current().unwrap_or(None);
+        self.wifi_checked = Some(Instant::now());
+    }
+
+    fn show_wifi_advisory(&mut self, ui: &mut egui::Ui) {
+        if self.wifi_checked.is_none_or(|t| t.elapsed() > WIFI_REFRESH_INTERVAL) {
+            self.refresh_wifi();
+        }
+        // Over a VPN the phone usually isn't on this Wi-Fi at all
+        let Some(info) = self.wifi_info.as_ref().filter(|_| !self.routed()) else {
+            return;
+        };
+        ui.label(format!("Wi-Fi: {}", info.summary()));
+        for warning in info.warnings() {
+            ui.colored_label(egui::Color32::YELLOW, format!("⚠ {}", warning));
+        }
+        ui.add_space(5.0);
+    }
+
+    fn stop_logging(&mut self) {
+        *self.log_file.lock() = None;
+    }
+
+    fn connect(&mut self) {
+        self.start_session(false);
+    }
+
+    /// Detects LAN or routed for the device address when that's turned on.
+    /// Names are only looked up when `resolve`, since that blocks.
+    fn update_route(&mut self, resolve: bool) {
+        let address = self.iphone_ip.trim();
+        if !self.detect_route {
+            self.route_detected = None;
+        } else if resolve || self.route_detected.as_ref().is_none_or(|(a, _)| a != address) {
+            self.route_detected = detect_route(address, resolve).map(|route| (address.to_string(), route));
+        }
+    }
+
+    /// Whether the VPN preset applies: as detected from the address when that's
+    /// turned on and worked, else the VPN mode checkbox
+    fn routed(&self) -> bool {
+        match &self.route_detected {
+            Some((address, route)) if self.detect_route && address == self.iphone_ip.trim() => *route == route::Route::Routed,
+            _ => self.vpn_mode,
+        }
+    }
+
+    fn route_label(&self) -> String {
+        match &self.route_detected {
+            Some((address, route)) if self.detect_route && address == self.iphone_ip.trim() => format!("Detected {}", route.label()),
+            _ if self.vpn_mode => "VPN mode".to_string(),
+            _ => "LAN".to_string(),
+        }
+    }
+
+    /// The device name when the iPhone is played into the same device that is
+    /// captured for it: its own audio goes straight back to it and screeches
+    fn feedback_device(&self) -> Option<&str> {
+        let input = self.input_devices.get(self.selected_input).filter(|d| d.is_output)?;
+        let output = self.output_devices.get(self.selected_output)?;
+        (input.name == format!("{} (Loopback)", output.name)).then_some(output.name.as_str())
+    }
+
+    /// `capture_only` feeds the RTSP/AES67 outputs without talking to a phone
+    fn start_session(&mut self, capture_only: bool) {
+        let rendezvous = (self.remote_mode && !capture_only).then(|| rendezvous::Rendezvous {
+            server: self.rendezvous_server.trim().to_string(),
+            code: self.rendezvous_code.trim().to_string(),
+        });
+        if rendezvous.is_none() && !capture_only && self.iphone_ip.trim().is_empty() {
+            *self.state.status_message.lock() = "Please select a device first".to_string();
+            return;
+        }
+        let muted = self.state.mute_mic.load(Ordering::Relaxed);
+        if let Some(device) = self.feedback_device().filter(|_| !capture_only && !muted && !self.allow_feedback_loop) {
+            *self.state.status_message.lock() = format!(
+                "Error: {} is both captured and played to, which feeds the iPhone's audio back to it. \
+                 Change iPhone → PC in Audio Settings.",
+                device
+            );
+            return;
+        }
+        // Resolved again by name at connect; the list may be older than the hardware
+        let (Some(input), Some(output)) = (self.input_devices.get(self.selected_input), self.output_devices.get(self.selected_output)) else {
+            *self.state.status_message.lock() = "Error: no audio device to capture from or play on. Plug one in, or click Refresh devices.".to_string();
+            return;
+        };
+        let (input, output, input_is_loopback) = (input.id.clone(), output.id.clone(), input.is_output);
+        let exclude_app = Some(self.capture_exclude.clone()).filter(|app| !app.is_empty());
+
+        // Start logging if enabled
+        self.start_logging();
+        self.update_route(true);
+        self.refresh_wifi();
+        if let Some(info) = self.wifi_info.as_ref().filter(|_| !self.routed()) {
+            log_message(&self.log_file, &self.debug_logging_flag, &format!("Wi-Fi: {}", info.summary()));
+            for warning in info.warnings() {
+                log_message(&self.log_file, &self.debug_logging_flag, &format!("Wi-Fi warning: {}", warning));
+            }
+        }
+
+        // Reset state
+        self.stop_flag.store(false, Ordering::SeqCst);
+        self.state.packets_sent.store(0, Ordering::SeqCst);
+        self.state.packets_recv.store(0, Ordering::SeqCst);
+        self.state.level_recv.lock().clear();
+        self.state.level_sent.lock().clear();
+        self.meter_bars = Default::default();
+        self.state.audio_callbacks.store(0, Ordering::SeqCst);
+        self.state.recv_intervals.lock().reset();
+        self.state.send_intervals.lock().reset();
+        *self.state.peer_ip.lock() = None;
+        *self.state.negotiated.lock() = None;
+        *self.state.peer_identity.lock() = None;
+        self.state.access_log.lock().clear();
+        *self.state.test_sound.lock() = Default::default();
+        self.state.peak_out.clear();
+        self.state.peak_mic.clear();
+        self.state.saturation_out.clear();
+        self.state.saturation_mic.clear();
+        self.state.red_recovered.store(0, Ordering::SeqCst);
+        self.state.fec_recovered.store(0, Ordering::SeqCst);
+        self.state.recv_sequence.lock().reset();
+        self.state.cpu_times.reset();
+        *self.state.feedback.lock() = None;
+        self.state.mono_cancels.store(false, Ordering::Relaxed);
+        self.feedback_dismissed = false;
+        *self.state.answered_status.lock() = None;
+        *self.state.clock.lock() = None;
+        self.state.streams.clear();
+        self.state.switch_target.store(false, Ordering::SeqCst);
+        *self.state.standby.lock() = None;
+        self.state.target_switches.store(0, Ordering::SeqCst);
+        self.seen_switches = 0;
+        self.state.is_connected.store(true, Ordering::SeqCst);
+        *self.state.status_message.lock() = "Connecting...".to_string();
+        // Internet mode waits for the phone to join first, with its own timeout
+        self.connect_started = (!capture_only && rendezvous.is_none()).then(Instant::now);
+        self.session_running = !capture_only;
+        self.reconnect_at = None;
+
+        let iphone_ip = if capture_only { String::new() } else { self.iphone_ip.clone() };
+        let device = self
+            .selected_device
+            .and_then(|i| self.saved_devices.get(i))
+            .filter(|d| rendezvous.is_none() && !capture_only && d.ip == iphone_ip.trim());
+        self.session_device = device.map(|d| d.id.clone());
+        let known_key = device.and_then(|d| d.key);
+        // Only between saved devices on an IP link, so the UI can tell which is which
+        let standby = self
+            .standby_device
+            .as_ref()
+            .and_then(|id| self.saved_devices.iter().find(|d| &d.id == id))
+            .filter(|d| device.is_some_and(|active| active.ip != d.ip))
+            .filter(|d| transport::parse_bluetooth_address(&d.ip).is_none() && transport::parse_bluetooth_address(&iphone_ip).is_none())
+            .map(|d| standby::Target { address: format!("{}:{}", d.ip, SEND_PORT), key: d.key });
+        let link = if rendezvous.is_some() {
+            "internet"
+        } else if transport::parse_bluetooth_address(&iphone_ip).is_some() {
+            "bluetooth"
+        } else if self.routed() {
+            "vpn"
+        } else {
+            "wifi"
+        };
+        self.session_tracker = (!capture_only).then(|| telemetry::SessionTracker::new(link));
+        let preset = if self.routed() { LinkPreset::VPN } else { LinkPreset::LAN };
+        let mut session = Session { formats: self.formats, redundancy: self.redundancy, seal: self.seal, fec: self.fec, ..Default::default() };
+        session.formats.down = preset.fit_down(session.formats.down, self.redundancy);
+        let mut buffer_samples = preset.buffer_samples;
+        if link == "wifi" || link == "vpn" {
+            log_message(&self.log_file, &self.debug_logging_flag, &format!("{}: {}", self.route_label(), preset.describe()));
+        }
+        // Only plain LAN sessions are learned; the network says little about the rest
+        self.session_network = None;
+        self.learned_note.clear();
+        if self.learn_networks && link == "wifi" && !capture_only {
+            let local_ip = format!("{}:{}", iphone_ip.trim(), SEND_PORT).parse().ok().and_then(local_ip_towards);
+            self.session_network = learned::network_key(self.wifi_info.as_ref().and_then(|w| w.bssid), local_ip);
+            let entry = self.session_network.as_ref().and_then(|n| self.learned.iter().find(|l| &l.network == n));
+            if let Some(entry) = entry.filter(|l| l.ready()) {
+                buffer_samples = entry.buffer_ms as usize * 48;
+                session.formats.down = entry.down;
+                self.learned_note = format!("Using what worked on this network: {}", entry.label());
+                log_message(&self.log_file, &self.debug_logging_flag, &format!("Learned for {}: {}", entry.network, entry.label()));
+            }
+        }
+        self.session_saving = (link != "bluetooth").then_some(self.saving);
+        if self.saving && link != "bluetooth" {
+            session = battery::lighten(session);
+            // A lightened session says nothing about what the network carries
+            self.session_network = None;
+            log_message(&self.log_file, &self.debug_logging_flag, &format!(
+                "Battery saver: down {} in {} ms frames, up {}",
+                session.formats.down, session.down_ptime(), session.formats.up
+            ));
+        }
+        self.session_limit = None;
+        if let Some(max_kbps) = self.bandwidth_limit.filter(|_| link != "bluetooth" && !capture_only) {
+            let (limited, changes) = limiter::limit(session, max_kbps, preset.max_payload);
+            if !changes.is_empty() {
+                session = limited;
+                // Nor does a limited one
+                self.session_network = None;
+                log_message(&self.log_file, &self.debug_logging_flag, &format!(
+                    "Bandwidth limit {} kbps: {}", max_kbps, changes.join(", ")
+                ));
+            }
+            self.session_limit = Some((max_kbps, changes));
+        }
+        self.state.playback_buffer.reset(buffer_samples, self.adaptive_buffer);
+        *self.state.playout.lock() = self
+            .adaptive_buffer
+            .then(|| playout::AdaptiveDepth::new(Instant::now(), (buffer_samples / 2) as f32 / 48.0));
+        let pc_id = self.pc_id;
+        let recv_buffer_kb = self.recv_buffer_kb;
+        let selected_input = self.selected_input;
+        let selected_output = self.selected_output;
+        let state = self.state.clone();
+        let stop_flag = self.stop_flag.clone();
+        let debug_flag = self.debug_logging_flag.clone();
+        let log_file = self.log_file.clone();
+        let (switch_tx, switches) = bounded(4);
+        self.audio_switch = Some(switch_tx);
+        self.session_audio = (selected_input, selected_output);
+
+        // Log connection start
+        log_message(&log_file, &debug_flag, &format!(
+            "Starting connection to {} (input device: {:?}, loopback: {}, output device: {:?}, {:?})",
+            iphone_ip, input, input_is_loopback, output, preset
+        ));
+
+        self.apply_comms_default();
+
+        self._audio_thread = Some(thread::spawn(move || {
+            if let Err(e) = run_bridge(
+                iphone_ip,
+                known_key,
+                rendezvous,
+                standby,
+                preset,
+                recv_buffer_kb,
+                session,
+                pc_id,
+                input,
+                output,
+                input_is_loopback,
+                exclude_app,
+                switches,
+                state.clone(),
+                stop_flag,
+                debug_flag.clone(),
+                log_file.clone(),
+            ) {
+                log_message(&log_file, &debug_flag, &format!("Bridge error: {}", e));
+                *state.status_message.lock() = format!("Error: {}", e);
+                state.is_connected.store(false, Ordering::SeqCst);
+                state.repaint.notify();
+            }
+        }));
+    }
+
+    fn disconnect(&mut self) {
+        log_message(&self.log_file, &self.debug_logging_flag, "Disconnecting...");
+        if self.session_running {
+            self.state.timeline.lock().push(timeline::Kind::Disconnected, "");
+        }
+        self.connect_started = None;
+        self.session_running = false;
+        self.stop_flag.store(true, Ordering::SeqCst);
+        self.audio_switch = None;
+        self.state.is_connected.store(false, Ordering::SeqCst);
+        *self.state.status_message.lock() = "Disconnected".to_string();
+        // Wait for the bridge to release its sockets so an immediate reconnect can bind them
+        if let Some(handle) = self._audio_thread.take() {
+            let _ = handle.join();
+        }
+        self.restore_comms_default();
+        self.stop_logging();
+    }
+
+    /// Hands a capture or playback change made while connected to the bridge,
+    /// which rebuilds that stream and leaves the network session alone
+    fn switch_audio_devices(&mut self) {
+        let Some(switches) = self.audio_switch.as_ref().filter(|_| self.state.is_connected.load(Ordering::SeqCst)) else {
+            return;
+        };
+        let (input, output) = self.session_audio;
+        if let Some(device) = self.input_devices.get(self.selected_input).filter(|_| self.selected_input != input) {
+            let _ = switches.try_send(AudioSwitch::Capture { id: device.id.clone(), loopback: device.is_output });
+        }
+        if let Some(device) = self.output_devices.get(self.selected_output).filter(|_| self.selected_output != output) {
+            let _ = switches.try_send(AudioSwitch::Playback(device.id.clone()));
+            // The communications mic follows the cable, if it still is one
+            self.restore_comms_default();
+            self.apply_comms_default();
+        }
+        self.session_audio = (self.selected_input, self.selected_output);
+    }
+
+    /// Ends a connection attempt once the phone answers, or fails it after the timeout
+    fn check_connect_attempt(&mut self) {
+        let is_connected = self.state.is_connected.load(Ordering::SeqCst);
+        if is_connected && self.state.packets_recv.load(Ordering::Relaxed) > 0 {
+            self.connect_started = None;
+            self.retries = 0;
+            if let Some(status) = self.state.answered_status.lock().take() {
+                let peer = status.trim_start_matches("Connected to ").to_string();
+                self.state.timeline.lock().push(timeline::Kind::Connected, peer);
+                *self.state.status_message.lock() = status;
+            }
+        }
+        if self.session_running && !is_connected {
+            // The bridge failed on its own and already said why
+            let error = self.state.status_message.lock().clone();
+            self.state.timeline.lock().push(timeline::Kind::Error, error.trim_start_matches("Error: "));
+            self.disconnect();
+            *self.state.status_message.lock() = error;
+            self.retry_after_failure();
+            return;
+        }
+        let Some(started) = self.connect_started else {
+            return;
+        };
+        if started.elapsed() >= Duration::from_secs(self.connect_timeout_secs as u64) {
+            let target = self.iphone_ip.trim().to_string();
+            log_message(&self.log_file, &self.debug_logging_flag, &format!(
+                "No answer from {} after {} s, giving up", target, self.connect_timeout_secs
+            ));
+            self.state.timeline.lock().push(timeline::Kind::Error, format!("no answer from {}", target));
+            self.disconnect();
+            *self.state.status_message.lock() = format!(
+                "Error: no answer from {} within {} s. Check the address, and that BudBridge is open on the iPhone.",
+                target, self.connect_timeout_secs
+            );
+            self.retry_after_failure();
+        }
+    }
+
+    /// Puts what the counters show on the timeline: playback running dry, and
+    /// the phone going quiet and coming back
+    fn watch_session(&mut self) {
+        let underruns = self.state.playback_buffer.underruns();
+        let packets = self.state.packets_recv.load(Ordering::Relaxed);
+        if !self.state.is_connected.load(Ordering::SeqCst) || packets < self.seen_packets.0 {
+            // A new session starts its counters over
+            (self.seen_underruns, self.seen_packets, self.peer_quiet) = (underruns, (packets, Instant::now()), false);
+            return;
+        }
+        let mut events = self.state.timeline.lock();
+        if packets > self.seen_packets.0 {
+            self.seen_packets = (packets, Instant::now());
+            if std::mem::take(&mut self.peer_quiet) {
+                events.push(timeline::Kind::PeerBack, "");
+            }
+        } else if packets > 0 && !self.peer_quiet && self.seen_packets.1.elapsed() >= PEER_QUIET_AFTER {
+            self.peer_quiet = true;
+            events.push(timeline::Kind::PeerLost, format!("no audio for {} s", PEER_QUIET_AFTER.as_secs()));
+        }
+        // Running dry because the phone stopped is the quiet line already
+        if !self.peer_quiet {
+            for _ in self.seen_underruns..underruns {
+                events.push(timeline::Kind::Dropout, "");
+            }
+        }
+        self.seen_underruns = underruns;
+    }
+
+    /// Saves the identity key a saved device answered with for the first time
+    fn remember_first_key(&mut self) {
+        let Some(identity::Verdict::FirstUse(key)) = *self.state.peer_identity.lock() else {
+            return;
+        };
+        let id = self.session_device.as_ref();
+        let Some(device) = self.saved_devices.iter_mut().find(|d| Some(&d.id) == id) else {
+            return;
+        };
+        device.key = Some(key);
+        log_message(&self.log_file, &self.debug_logging_flag, &format!(
+            "Remembered {}'s identity key {}", device.name, identity::fingerprint(&key)
+        ));
+        *self.state.peer_identity.lock() = Some(identity::Verdict::Known);
+        save_devices(&self.saved_devices);
+    }
+
+    /// The network thread swapped to the standby: it's the session's device now,
+    /// and the one it left is the standby
+    fn follow_target_switch(&mut self) {
+        let switches = self.state.target_switches.load(Ordering::SeqCst);
+        if switches == self.seen_switches {
+            return;
+        }
+        self.seen_switches = switches;
+        let Some(idx) = self.standby_device.as_ref().and_then(|id| self.saved_devices.iter().position(|d| &d.id == id)) else {
+            return;
+        };
+        self.standby_device = self.session_device.replace(self.saved_devices[idx].id.clone());
+        save_setting("standby_device", self.standby_device.as_deref().unwrap_or_default());
+        self.selected_device = Some(idx);
+        self.iphone_ip = self.saved_devices[idx].ip.clone();
+        log_message(&self.log_file, &self.debug_logging_flag, &format!("Now playing on {}", self.saved_devices[idx].name));
+        self.state.timeline.lock().push(timeline::Kind::TargetSwitched, self.saved_devices[idx].name.clone());
+    }
+
+    /// Which other saved device to keep warm; takes effect on the next connection
+    fn show_standby_choice(&mut self, ui: &mut egui::Ui, is_connected: bool) {
+        let active = self.selected_device.and_then(|i| self.saved_devices.get(i)).map(|d| d.id.clone());
+        let name = |id: &Option<String>| {
+            id.as_ref()
+                .and_then(|id| self.saved_devices.iter().find(|d| &d.id == id))
+                .map_or("None".to_string(), |d| d.name.clone())
+        };
+        let mut choice = self.standby_device.clone().filter(|id| Some(id) != active.as_ref());
+        ui.add_enabled_ui(!is_connected, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Warm standby:");
+                egui::ComboBox::from_id_salt("standby_device")
+                    .width(160.0)
+                    .selected_text(name(&choice))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut choice, None, "None");
+                        for device in self.saved_devices.iter().filter(|d| Some(&d.id) != active.as_ref()) {
+                            ui.selectable_value(&mut choice, Some(device.id.clone()), &device.name);
+                        }
+                    });
+            })
+            .response
+            .on_hover_text("Another saved device (an iPad, say) kept connected in the background while this one plays, so \"Switch to\" moves the audio over within a second. BudBridge has to be open on both.");
+        });
+        // A standby that is now the selected device is only hidden, not forgotten
+        let hidden = self.standby_device.is_some() && self.standby_device == active;
+        if choice != self.standby_device && !(hidden && choice.is_none()) {
+            save_setting("standby_device", choice.as_deref().unwrap_or_default());
+            self.standby_device = choice;
+        }
+    }
+
+    /// The standby and a button to switch to it, while connected
+    fn show_standby(&mut self, ui: &mut egui::Ui, is_connected: bool) {
+        let Some(status) = self.state.standby.lock().filter(|_| is_connected) else {
+            return;
+        };
+        let Some(device) = self.standby_device.as_ref().and_then(|id| self.saved_devices.iter().find(|d| &d.id == id)) else {
+            return;
+        };
+        match status {
+            standby::Status::Ready => {
+                let switching = self.state.switch_target.load(Ordering::SeqCst);
+                if ui
+                    .add_enabled(!switching, egui::Button::new(format!("Switch to {}", device.name)))
+                    .on_hover_text("Kept connected in the background, so the audio moves over right away")
+                    .clicked()
+                {
+                    self.state.switch_target.store(true, Ordering::SeqCst);
+                }
+            }
+            standby::Status::Waiting => {
+                ui.colored_label(egui::Color32::GRAY, format!("Standby: waiting for {} to answer", device.name));
+            }
+            standby::Status::Refused => {
+                ui.colored_label(
+                    egui::Color32::RED,
+                    format!("⚠ Standby {} failed its identity check and is left out of this session", device.name),
+                );
+            }
+        }
+    }
+
+    /// Schedules the next attempt after a failure, as the retry policy says
+    fn retry_after_failure(&mut self) {
+        // The same phone will give the same answer; the user has to decide first
+        if self.state.peer_identity.lock().is_some_and(|v| v.is_alarm()) {
+            return;
+        }
+        if let Some(delay) = self.retry_policy.delay(self.retries) {
+            self.retries += 1;
+            self.reconnect_at = Some(Instant::now() + delay);
+            log_message(&self.log_file, &self.debug_logging_flag, &format!(
+                "Retry {} in {} s", self.retries, delay.as_secs()
+            ));
+            return;
+        }
+        self.retries = 0;
+        if self.retry_policy.give_up == retry::GiveUp::NextDevice && self.saved_devices.len() > 1 {
+            self.cycle_device();
+            let delay = Duration::from_secs(self.retry_policy.initial_delay_secs as u64);
+            self.reconnect_at = Some(Instant::now() + delay);
+            if let Some(device) = self.selected_device.and_then(|i| self.saved_devices.get(i)) {
+                log_message(&self.log_file, &self.debug_logging_flag, &format!(
+                    "Out of retries, trying {} next", device.name
+                ));
+                let mut status = self.state.status_message.lock();
+                *status = format!("{} Trying {} next.", status, device.name);
+            }
+        }
+    }
+
+    /// Make the selected virtual cable's mic side the default communications device
+    fn apply_comms_default(&mut self) {
+        if !self.set_comms_default {
+            return;
+        }
+        let cable = match self.find_virtual_cable() {
+            Some((idx, cable)) if idx == self.selected_output => cable,
+            _ => return,
+        };
+
+        let result = comms_device::find_capture_device(cable.mic_name).and_then(|target| {
+            let target = target.ok_or_else(|| anyhow!("\"{}\" capture device not found", cable.mic_name))?;
+            let previous = comms_device::default_communications_capture()?;
+            if previous.as_deref() != Some(target.as_str()) {
+                comms_device::set_default_communications_capture(&target)?;
+                self.previous_comms_device = previous;
+            }
+            Ok(())
+        });
+
+        match result {
+            Ok(()) => log_message(&self.log_file, &self.debug_logging_flag, &format!(
+                "Set {} as default communications device", cable.mic_name
+            )),
+            Err(e) => log_message(&self.log_file, &self.debug_logging_flag, &format!(
+                "Could not set default communications device: {}", e
+            )),
+        }
+    }
+
+    fn restore_comms_default(&mut self) {
+        if let Some(previous) = self.previous_comms_device.take() {
+            if let Err(e) = comms_device::set_default_communications_capture(&previous) {
+                log_message(&self.log_file, &self.debug_logging_flag, &format!(
+                    "Could not restore default communications device: {}", e
+                ));
+            }
+        }
+    }
+}
+
+impl eframe::App for BudBridgeApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // Audio and status changes ask for frames themselves (repaint.rs)
+        self.state.repaint.frame_started();
+        ctx.request_repaint_after(repaint::IDLE_INTERVAL);
+        self.track_window(ctx);
+        self.follow_power_source();
+        self.update_meters();
+        self.update_tray(ctx);
+        self.update_taskbar_badge();
+        self.update_jump_list();
+        self.state.cpu_times.record(cpu::Part::Ui);
+        self.cpu_meter.update(&self.state.cpu_times);
+
+        while let Ok(command) = self.command_rx.try_recv() {
+            self.handle_command(command);
+        }
+        while let Ok(event) = self.power_rx.try_recv() {
+            self.handle_power_event(event);
+        }
+        if self.reconnect_at.is_some_and(|t| Instant::now() >= t) {
+            self.reconnect_at = None;
+            if !self.state.is_connected.load(Ordering::SeqCst) {
+                log_message(&self.log_file, &self.debug_logging_flag, "Reconnecting");
+                self.state.timeline.lock().push(timeline::Kind::Reconnecting, "");
+                self.connect();
+            }
+        }
+        self.check_connect_attempt();
+        self.watch_session();
+        self.poll_devices();
+        self.apply_device_scan();
+        self.wait_for_devices();
+        self.switch_audio_devices();
+        self.follow_target_switch();
+        self.remember_first_key();
+        self.save_midi_mapping_if_changed();
+        if self.auto_update_ip {
+            if let Some((idx, ip)) = self.moved_peer() {
+                self.update_device_ip(idx, ip);
+            }
+        }
+        if self.state.is_connected.load(Ordering::SeqCst) {
+            if let Some(loss) = self.link_quality().map(|q| q.loss_pct) {
+                if let Some(tracker) = &mut self.session_tracker {
+                    tracker.record_loss(loss);
+                }
+            }
+        } else if let Some(tracker) = self.session_tracker.take() {
+            self.report_session(&tracker);
+            self.learn_from_session(&tracker);
+        }
+        for warning in config::take_warnings() {
+            if !self.config_warnings.contains(&warning) {
+                self.config_warnings.push(warning);
+            }
+        }
+        let status = self.state.status_message.lock().clone();
+        if !status.starts_with("Error") {
+            self.last_error.clear();
+        } else if status != self.last_error {
+            self.error_banner = Some(self.bug_report(&status));
+            self.last_error = status;
+        }
+
+        if self.state.is_connected.load(Ordering::SeqCst) {
+            egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| self.show_status_bar(ui));
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.heading("BudBridge");
+            ui.add_space(5.0);
+
+            if !self.config_warnings.is_empty() {
+                ui.group(|ui| {
+                    for warning in &self.config_warnings {
+                        let color = if warning.starts_with("Error") {
+                            egui::Color32::RED
+                        } else {
+                            egui::Color32::YELLOW
+                        };
+                        ui.colored_label(color, format!("⚠ {}", warning));
+                    }
+                    if ui.small_button("Dismiss").clicked() {
+                        self.config_warnings.clear();
+                    }
+                });
+                ui.add_space(5.0);
+            }
+
+            if let Some(report) = &self.error_banner {
+                let mut dismissed = false;
+                ui.group(|ui| {
+                    ui.colored_label(egui::Color32::RED, format!("⚠ {}", report.error));
+                    egui::CollapsingHeader::new(format!("Last {} log lines", report.log.len())).show(ui, |ui| {
+                        if report.log.is_empty() {
+                            ui.label("Nothing logged yet.");
+                        }
+                        for line in &report.log {
+                            ui.monospace(line);
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.small_button("Copy for bug report").clicked() {
+                            ui.ctx().copy_text(report.to_markdown());
+                        }
+                        dismissed = ui.small_button("Dismiss").clicked();
+                    });
+                });
+                if dismissed {
+                    self.error_banner = None;
+                }
+                ui.add_space(5.0);
+            }
+
+            let tab = self.current_tab;
+            ui.horizontal(|ui| {
+                ui.selectable_value(&mut self.current_tab, Tab::Connection, "Connection");
+                ui.selectable_value(&mut self.current_tab, Tab::Devices, "Devices");
+                ui.selectable_value(&mut self.current_tab, Tab::Settings, "Settings");
+            });
+            if self.current_tab != tab {
+                save_setting("tab", self.current_tab.as_str());
+            }
+            ui.separator();
+            ui.add_space(5.0);
+
+            egui::ScrollArea::vertical().show(ui, |ui| match self.current_tab {
+                Tab::Connection => self.show_connection_tab(ui),
+                Tab::Devices => self.show_devices_tab(ui),
+                Tab::Settings => self.show_settings_tab(ui),
+            });
+        });
+    }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.restore_comms_default();
+        if let Some(geometry) = self.window_geometry {
+            save_setting("window", &geometry.to_setting());
+        }
+        // Best effort: a report being sent may not get out before the process ends
+        if let Some(tracker) = self.session_tracker.take() {
+            self.report_session(&tracker);
+            self.learn_from_session(&tracker);
+        }
+    }
+}
+
+impl BudBridgeApp {
+    /// Remembers where the window is for the next launch. While maximized or
+    /// minimized the last normal size and position are kept.
+    fn track_window(&mut self, ctx: &egui::Context) {
+        let (outer, inner, scale, maximized, minimized) = ctx.input(|i| {
+            let viewport = i.viewport();
+            (
+                viewport.outer_rect,
+                viewport.inner_rect,
+                viewport.native_pixels_per_point.unwrap_or(1.0),
+                viewport.maximized.unwrap_or(false),
+                viewport.minimized.unwrap_or(false),
+            )
+        });
+        if minimized {
+            return;
+        }
+        match (&mut self.window_geometry, outer, inner) {
+            (Some(geometry), _, _) if maximized => geometry.maximized = true,
+            (_, Some(outer), Some(inner)) if !maximized => {
+                self.window_geometry = Some(window::Geometry {
+                    position: [outer.min.x, outer.min.y],
+                    size: [inner.width(), inner.height()],
+                    scale,
+                    maximized: false,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    /// The routing matrix (which sources each receiver gets), with its mute and trim
+    fn show_receivers(&mut self, ui: &mut egui::Ui) {
+        use receivers::Receiver;
+        use sources::Source;
+        let running = |receiver: Receiver| match receiver {
+            Receiver::Phone => true,
+            Receiver::Rtsp => self.rtsp_server.is_some(),
+            Receiver::Aes67 => self.aes67_sender.is_some(),
+            Receiver::Classroom => self.multicast_sender.is_some(),
+        };
+        let active: Vec<Receiver> = Receiver::ALL.into_iter().filter(|&r| running(r)).collect();
+
+        let mix = &self.state.mix;
+        let mut changed = false;
+        let mut reload = false;
+        egui::CollapsingHeader::new("Receivers").default_open(active.len() > 1).show(ui, |ui| {
+            egui::Grid::new("receivers").num_columns(3 + Source::ALL.len()).show(ui, |ui| {
+                ui.label("");
+                for source in Source::ALL {
+                    ui.label(source.label());
+                }
+                ui.end_row();
+                for receiver in active {
+                    ui.label(receiver.label());
+                    for source in Source::ALL {
+                        let mut routed = mix.routed(receiver, source);
+                        if ui.checkbox(&mut routed, "").changed() {
+                            mix.set_routed(receiver, source, routed);
+                            changed = true;
+                        }
+                    }
+                    let mut muted = mix.muted(receiver);
+                    if ui.checkbox(&mut muted, "Mute").changed() {
+                        mix.set_muted(receiver, muted);
+                        changed = true;
+                    }
+                    let mut trim = mix.trim_db(receiver);
+                    let response = ui
+                        .add_enabled(!muted, egui::DragValue::new(&mut trim).range(receivers::TRIM_RANGE_DB).suffix(" dB"))
+                        .on_hover_text("Turns this receiver down without touching the others");
+                    if response.changed() {
+                        mix.set_trim_db(receiver, trim);
+                    }
+                    // Heard at once, saved when the drag or edit ends
+                    changed |= edit_finished(&response);
+                    ui.end_row();
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Ambiance loop:");
+                ui.add(egui::TextEdit::singleline(&mut self.ambiance_path).hint_text("path to a .wav").desired_width(180.0));
+                reload = ui.button("Load").on_hover_text("Up to two minutes, played round and round").clicked();
+            });
+            show_status_line(ui, &self.ambiance_status);
+        });
+        if changed {
+            save_setting("receivers", &mix.to_setting());
+        }
+        if reload {
+            save_setting("ambiance", self.ambiance_path.trim());
+            self.load_ambiance();
+        }
+    }
+
+    /// The status light's state: waiting for the phone, connected, or failed
+    fn connection_badge(&self) -> Option<taskbar::Badge> {
+        if self.state.is_connected.load(Ordering::SeqCst) {
+            Some(if self.connect_started.is_some() { taskbar::Badge::Waiting } else { taskbar::Badge::Connected })
+        } else if self.state.status_message.lock().starts_with("Error") {
+            Some(taskbar::Badge::Error)
+        } else {
+            None
+        }
+    }
+
+    fn update_taskbar_badge(&mut self) {
+        let badge = if self.taskbar_badge { self.connection_badge() } else { None };
+        if let Err(e) = self.taskbar.show(badge) {
+            log_message(&self.log_file, &self.debug_logging_flag, &format!("Taskbar badge: {}", e));
+        }
+    }
+
+    /// The tray icon follows the status light. Minimized to the tray, the window
+    /// gives up its taskbar button until it's restored.
+    fn update_tray(&mut self, ctx: &egui::Context) {
+        let state = self.tray_icon.then(|| tray::State {
+            badge: self.connection_badge(),
+            connected: self.state.is_connected.load(Ordering::SeqCst),
+            tooltip: tray::tooltip(&self.state.status_message.lock()),
+            profiles: self.profiles.iter().map(|p| p.name.clone()).collect(),
+        });
+        if let Err(e) = self.tray.show(state, &self.command_tx, ctx) {
+            log_message(&self.log_file, &self.debug_logging_flag, &format!("Tray icon: {}", e));
+        }
+        let minimized = ctx.input(|i| i.viewport().minimized.unwrap_or(false));
+        let in_tray = self.minimize_to_tray && self.tray.is_shown() && minimized;
+        if let Err(e) = self.taskbar.show_button(!in_tray) {
+            log_message(&self.log_file, &self.debug_logging_flag, &format!("Taskbar button: {}", e));
+        }
+    }
+
+    /// Rebuilds the jump list when the saved device names change, on a worker thread
+    /// since the shell can be slow. Turned off, the first frame clears any left over.
+    fn update_jump_list(&mut self) {
+        let names: Vec<String> =
+            if self.jump_list { self.saved_devices.iter().map(|d| d.name.clone()).collect() } else { Vec::new() };
+        if self.jump_list_names.as_ref() == Some(&names) {
+            return;
+        }
+        let tasks = if self.jump_list { jumplist::tasks(&names) } else { Vec::new() };
+        self.jump_list_names = Some(names);
+        let log_file = self.log_file.clone();
+        let debug_flag = self.debug_logging_flag.clone();
+        thread::spawn(move || {
+            if let Err(e) = jumplist::set_tasks(&tasks) {
+                log_message(&log_file, &debug_flag, &format!("Jump list: {}", e));
+            }
+        });
+    }
+
+    /// Link quality from the receive stream; None until a few seconds of packets arrived
+    fn link_quality(&self) -> Option<LinkQuality> {
+        let history = self.state.recv_intervals.lock();
+        let jitter = history.jitter_ms()?;
+        // The iPhone streams continuously, so any shortfall is loss: PCM at a fixed
+        // byte rate, Opus as one packet per 20 ms whatever its size
+        let agreed = self.state.negotiated.lock().unwrap_or_default();
+        let (rate, expected) = match agreed.formats.up.frame_bytes() {
+            Some(frame_bytes) => (
+                history.bytes_per_sec(Instant::now())?,
+                (agreed.up_sample_rate() as usize * frame_bytes) as f32,
+            ),
+            None => (history.packets_per_sec(Instant::now())?, 50.0),
+        };
+        // Framed packets count their own gaps, which beats inferring loss from the rate
+        let counted = agreed.framed.then(|| self.state.recv_sequence.lock().loss_pct()).flatten();
+        let loss_pct = counted.unwrap_or(((1.0 - rate / expected) * 100.0).clamp(0.0, 100.0));
+        let rtt = match &*self.link_test.lock() {
+            link_test::TestState::Done(report) => report.rtt_ms(),
+            _ => None,
+        };
+        let rtt = rtt.or_else(|| self.state.clock.lock().map(|c| c.rtt_ms));
+        Some(LinkQuality::assess(loss_pct, jitter, rtt))
+    }
+
+    /// The report for the session in progress, or else the last one
+    fn telemetry_preview(&self) -> Option<String> {
+        match &self.session_tracker {
+            Some(tracker) => Some(self.session_report(tracker).to_json()),
+            None => self.last_report.clone(),
+        }
+    }
+
+    fn session_report(&self, tracker: &telemetry::SessionTracker) -> telemetry::Report {
+        let agreed = self.state.negotiated.lock().unwrap_or_default();
+        tracker.report(agreed.formats.down.to_string(), agreed.formats.up.to_string(), agreed.redundancy)
+    }
+
+    fn report_session(&mut self, tracker: &telemetry::SessionTracker) {
+        let json = self.session_report(tracker).to_json();
+        self.last_report = Some(json.clone());
+        match self.telemetry_mode {
+            telemetry::Mode::Off => {}
+            telemetry::Mode::Local => {
+                *self.telemetry_status.lock() = match telemetry::save_local(&get_telemetry_path(), &json) {
+                    Ok(path) => format!("Saved {}", path.display()),
+                    Err(e) => format!("Error: {}", e),
+                };
+            }
+            telemetry::Mode::Send => {
+                let url = self.telemetry_url.clone();
+                let status = self.telemetry_status.clone();
+                *status.lock() = "Sending session report...".to_string();
+                thread::spawn(move || {
+                    *status.lock() = match telemetry::send(&url, &json) {
+                        Ok(()) => "Last session report sent".to_string(),
+                        Err(e) => format!("Error: {}", e),
+                    };
+                });
+            }
+        }
+    }
+
+    /// Folds the session that ended into what's learned about its network
+    fn learn_from_session(&mut self, tracker: &telemetry::SessionTracker) {
+        let (Some(network), Some(agreed)) = (self.session_network.take(), *self.state.negotiated.lock()) else {
+            return;
+        };
+        let buffer = self.state.playback_buffer.snapshot();
+        let outcome = learned::Outcome {
+            secs: tracker.session_secs(),
+            loss_pct: tracker.average_loss_pct(),
+            buffer_trouble: buffer.expansions + buffer.contractions,
+            buffer_ms: buffer.limit_ms.round() as u32,
+            down: agreed.formats.down,
+        };
+        if learned::record(&mut self.learned, &network, &outcome) {
+            save_learned(&self.learned);
+        }
+    }
+
+    fn bug_report(&self, error: &str) -> bug_report::Report {
+        let device = |devices: &[AudioDeviceInfo], index: usize| {
+            devices.get(index).map_or("none".to_string(), |d| d.name.clone())
+        };
+        let agreed = *self.state.negotiated.lock();
+        let codecs = match agreed {
+            Some(session) => format!("down {}, up {} (agreed)", session.formats.down, session.formats.up),
+            None => format!("down {}, up {} (offered)", self.formats.down, self.formats.up),
+        };
+        let mode = if self.remote_mode {
+            "internet"
+        } else if self.routed() {
+            "vpn"
+        } else {
+            "lan"
+        };
+        bug_report::Report {
+            error: error.to_string(),
+            log: bug_report::recent(bug_report::BANNER_LINES),
+            details: vec![
+                ("BudBridge", env!("CARGO_PKG_VERSION").to_string()),
+                ("OS", telemetry::os_version()),
+                ("Capture", device(&self.input_devices, self.selected_input)),
+                ("Playback", device(&self.output_devices, self.selected_output)),
+                ("Mode", mode.to_string()),
+                ("Codecs", codecs),
+            ]
+            .into_iter()
+            .chain(self.state.streams.lines())
+            .collect(),
+        }
+    }
+
+    fn show_status_bar(&self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Link quality:");
+            match self.link_quality() {
+                Some(quality) => {
+                    let color = match quality.score {
+                        5 | 4 => egui::Color32::GREEN,
+                        3 => egui::Color32::YELLOW,
+                        _ => egui::Color32::RED,
+                    };
+                    let bars = "▮".repeat(quality.score as usize) + &"▯".repeat(5 - quality.score as usize);
+                    ui.colored_label(color, format!("{} {}", bars, quality.label()))
+                        .on_hover_text(quality.explain().join("\n"));
+                }
+                None => {
+                    ui.colored_label(egui::Color32::GRAY, "measuring…")
+                        .on_hover_text("Waiting for a few seconds of audio from the device");
+                }
+            }
+        });
+    }
+
+    /// The connected saved device and the new address its audio arrives from,
+    /// if that differs from the stored IP (e.g. after a DHCP lease change)
+    fn moved_peer(&self) -> Option<(usize, IpAddr)> {
+        // VPN addresses don't change like DHCP leases do
+        if self.routed() || !self.state.is_connected.load(Ordering::SeqCst) {
+            return None;
+        }
+        let peer = (*self.state.peer_ip.lock())?;
+        let idx = self.selected_device?;
+        // A hostname resolves to whatever address the phone has
+        let saved: IpAddr = self.saved_devices.get(idx)?.ip.parse().ok()?;
+        (saved != peer && self.ignored_peer_ip != Some(peer)).then_some((idx, peer))
+    }
+
+    /// Store the device's new IP and reconnect so audio is sent there too
+    fn update_device_ip(&mut self, idx: usize, ip: IpAddr) {
+        let Some(device) = self.saved_devices.get_mut(idx) else {
+            return;
+        };
+        log_message(&self.log_file, &self.debug_logging_flag, &format!(
+            "Device \"{}\" moved from {} to {}, updating saved IP", device.name, device.ip, ip
+        ));
+        device.ip = ip.to_string();
+        save_devices(&self.saved_devices);
+        self.iphone_ip = ip.to_string();
+        self.disconnect();
+        self.connect();
+    }
+
+    fn show_moved_peer_prompt(&mut self, ui: &mut egui::Ui) {
+        let Some((idx, ip)) = self.moved_peer() else {
+            return;
+        };
+        let name = self.saved_devices[idx].name.clone();
+        ui.add_space(5.0);
+        ui.colored_label(
+            egui::Color32::YELLOW,
+            format!("\"{}\" is now sending from {} (saved IP is {})", name, ip, self.saved_devices[idx].ip),
+        );
+        ui.horizontal(|ui| {
+            if ui.button("Update saved IP").clicked() {
+                self.update_device_ip(idx, ip);
+            }
+            if ui.button("Ignore").clicked() {
+                self.ignored_peer_ip = Some(ip);
+            }
+            if ui.checkbox(&mut self.auto_update_ip, "Always update automatically").changed() {
+                save_bool_setting("auto_update_ip", self.auto_update_ip);
+            }
+        });
+    }
+
+    fn start_link_test(&mut self) {
+        let target = match self.iphone_ip.trim().parse::<std::net::IpAddr>() {
+            Ok(ip) => std::net::SocketAddr::new(ip, SEND_PORT),
+            Err(_) => {
+                *self.link_test.lock() = link_test::TestState::Failed("Select a device first".to_string());
+                return;
+            }
+        };
+        let ctx = self.egui_ctx.clone();
+        link_test::start(target, self.link_test.clone(), move || ctx.request_repaint());
+    }
+
+    fn show_link_test(&mut self, ui: &mut egui::Ui, is_connected: bool) {
+        let state = self.link_test.lock().clone();
+        let running = matches!(state, link_test::TestState::Running(_));
+
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(!is_connected && !running, egui::Button::new("Test connection"))
+                .on_hover_text("Start BudBridge on the iPhone first; the test needs it to echo packets back")
+                .on_disabled_hover_text("Disconnect to run the test")
+                .clicked()
+            {
+                self.start_link_test();
+            }
+            if let link_test::TestState::Running(progress) = &state {
+                ui.spinner();
+                ui.label(progress);
+            }
+        });
+
+        match state {
+            link_test::TestState::Done(report) => {
+                for stage in &report.stages {
+                    ui.small(format!(
+                        "{:>5} kbps: {}/{} echoed ({:.1}% loss){}",
+                        stage.rate_kbps,
+                        stage.received,
+                        stage.sent,
+                        stage.loss_pct(),
+                        stage.rtt_ms.map(|r| format!(", RTT {:.0} ms", r)).unwrap_or_default()
+                    ));
+                }
+                let throughput = report
+                    .max_clean_kbps()
+                    .map(|k| format!("{} kbps", k))
+                    .unwrap_or_else(|| "below the lowest test rate".to_string());
+                ui.label(format!(
+                    "Throughput: {} (streaming needs {} kbps each way)",
+                    throughput,
+                    link_test::STREAM_KBPS
+                ));
+                ui.label(report.verdict());
+            }
+            link_test::TestState::Failed(e) => show_status_line(ui, &format!("Error: {}", e)),
+            _ => {}
+        }
+    }
+
+    fn show_connection_tab(&mut self, ui: &mut egui::Ui) {
+        let is_connected = self.state.is_connected.load(Ordering::SeqCst);
+
+        self.show_connection_summary(ui, is_connected);
+
+        ui.add_space(10.0);
+
+        let advanced = egui::CollapsingHeader::new("Advanced")
+            .open(Some(self.connection_advanced))
+            .show(ui, |ui| self.show_connection_details(ui, is_connected));
+        if advanced.header_response.clicked() {
+            self.connection_advanced = !self.connection_advanced;
+            save_bool_setting("connection_advanced", self.connection_advanced);
+        }
+    }
+
+    /// All a casual user needs: which phone, one big button and how it's going
+    fn show_connection_summary(&mut self, ui: &mut egui::Ui, is_connected: bool) {
+        ui.group(|ui| {
+            let selected_name = self
+                .selected_device
+                .and_then(|i| self.saved_devices.get(i))
+                .map(|d| d.name.clone())
+                .unwrap_or_else(|| "Select a device...".to_string());
+
+            let mut new_selection: Option<usize> = None;
+
+            ui.horizontal(|ui| {
+                ui.label("Device:");
+                ui.add_enabled_ui(!is_connected, |ui| {
+                    egui::ComboBox::from_id_salt("saved_devices")
+                        .width(200.0)
+                        .selected_text(&selected_name)
+                        .show_ui(ui, |ui| {
+                            for (i, device) in self.saved_devices.iter().enumerate() {
+                                if ui.selectable_value(&mut self.selected_device, Some(i), &device.name).changed() {
+                                    new_selection = Some(i);
+                                }
+                            }
+                        });
+                });
+            });
+
+            if self.saved_devices.is_empty() {
+                ui.label("No devices saved. Go to Devices tab to add one.");
+            }
+            if let Some(i) = new_selection {
+                if let Some(dev) = self.saved_devices.get(i) {
+                    self.iphone_ip = dev.ip.clone();
+                }
+            }
+
+            ui.add_space(5.0);
+            self.show_connect_button(ui, is_connected);
+            ui.add_space(5.0);
+
+            let status = self.state.status_message.lock().clone();
+            let (light, status_color) = match self.connection_badge() {
+                Some(badge) => ("●", badge.color()),
+                None => ("○", egui::Color32::GRAY),
+            };
+            ui.horizontal(|ui| {
+                ui.label(egui::RichText::new(light).size(18.0).color(status_color));
+                ui.colored_label(status_color, &status);
+            });
+
+            self.show_test_sound(ui, is_connected);
+            self.show_standby(ui, is_connected);
+            self.show_identity_warning(ui);
+            self.show_feedback_warning(ui);
+            self.show_device_notices(ui);
+        });
+    }
+
+    /// Sends a chime down to check the PC → iPhone path, once the phone has answered
+    fn show_test_sound(&mut self, ui: &mut egui::Ui, is_connected: bool) {
+        let bluetooth = transport::parse_bluetooth_address(&self.iphone_ip).is_some();
+        if !is_connected || self.connect_started.is_some() || bluetooth {
+            return;
+        }
+        let mut test = self.state.test_sound.lock();
+        test.check_timeout(Instant::now());
+        ui.horizontal(|ui| {
+            let button = egui::Button::new("🔔 Play test sound on phone");
+            if ui
+                .add_enabled(!test.in_progress(), button)
+                .on_hover_text("A short chime through the whole PC → iPhone path, without starting any music")
+                .clicked()
+            {
+                test.start(Instant::now());
+            }
+            if test.in_progress() {
+                ui.spinner();
+            }
+        });
+        if let Some(outcome) = test.outcome {
+            let color = if outcome.is_success() { egui::Color32::GREEN } else { egui::Color32::YELLOW };
+            ui.colored_label(color, outcome.label());
+        }
+    }
+
+    /// Connect, Disconnect, Cancel or the retry countdown
+    fn show_connect_button(&mut self, ui: &mut egui::Ui, is_connected: bool) {
+        let big = |text: &str| egui::Button::new(egui::RichText::new(text).size(18.0)).min_size(egui::vec2(160.0, 36.0));
+        ui.horizontal(|ui| {
+            if let Some(at) = self.reconnect_at.filter(|_| !is_connected && self.retries > 0) {
+                if ui.add(big("Stop retrying")).clicked() {
+                    self.reconnect_at = None;
+                    self.retries = 0;
+                }
+                let of = self.retry_policy.max_retries.map_or(String::new(), |max| format!(" of {}", max));
+                ui.label(format!(
+                    "Retry {}{} in {} s",
+                    self.retries,
+                    of,
+                    at.saturating_duration_since(Instant::now()).as_secs() + 1
+                ));
+            } else if !is_connected {
+                if ui.add(big("Connect")).clicked() {
+                    self.connect();
+                }
+            } else if let Some(started) = self.connect_started {
+                if ui.add(big("Cancel")).clicked() {
+                    self.disconnect();
+                    *self.state.status_message.lock() = "Connection cancelled".to_string();
+                }
+                ui.label(format!(
+                    "Waiting for the iPhone... {} s",
+                    self.connect_timeout_secs.saturating_sub(started.elapsed().as_secs() as u32)
+                ));
+            } else if ui.add(big("Disconnect")).clicked() {
+                self.disconnect();
+            }
+        });
+    }
+
+    /// Profiles, connection options, audio routing and formats, processing and diagnostics
+    fn show_connection_details(&mut self, ui: &mut egui::Ui, is_connected: bool) {
+        self.show_profiles(ui);
+
+        ui.add_space(10.0);
+
+        ui.group(|ui| {
+            ui.label("Connection Options");
+            ui.add_space(5.0);
+
+            ui.add_enabled_ui(!is_connected && !self.rendezvous_server.trim().is_empty(), |ui| {
+                if ui
+                    .checkbox(&mut self.remote_mode, "Connect over the internet (session code)")
+                    .on_disabled_hover_text("Set up a rendezvous server under Settings → Internet Mode")
+                    .changed()
+                {
+                    save_bool_setting("remote_mode", self.remote_mode);
+                }
+            });
+
+            ui.add_enabled_ui(!is_connected, |ui| {
+                if ui
+                    .checkbox(&mut self.vpn_mode, "VPN mode (Tailscale, ZeroTier, WireGuard)")
+                    .on_hover_text("Smaller packets and keepalives so the stream survives tunnel MTUs and idle timeouts, plus a deeper buffer and a lighter format. Enable the same option for this PC on the iPhone.")
+                    .changed()
+                {
+                    save_bool_setting("vpn_mode", self.vpn_mode);
+                }
+                if ui
+                    .checkbox(&mut self.detect_route, "Pick LAN or VPN settings from the address")
+                    .on_hover_text("An address in this PC's subnet gets the LAN settings; a Tailscale address, another subnet or the internet gets the VPN ones. VPN mode above applies while a name hasn't been looked up yet.")
+                    .changed()
+                {
+                    save_bool_setting("detect_route", self.detect_route);
+                }
+            });
+            if !is_connected {
+                self.update_route(false);
+            }
+            if !self.remote_mode && transport::parse_bluetooth_address(&self.iphone_ip).is_none() {
+                let preset = if self.routed() { LinkPreset::VPN } else { LinkPreset::LAN };
+                ui.label(format!("   ↳ {}: {}", self.route_label(), preset.describe()));
+            }
+
+            if !self.remote_mode {
+                self.show_standby_choice(ui, is_connected);
+            }
+            if !self.remote_mode && transport::parse_bluetooth_address(&self.iphone_ip).is_none() {
+                self.show_link_test(ui, is_connected);
+                self.show_moved_peer_prompt(ui);
+            }
+        });
+
+        ui.add_space(10.0);
+
+        ui.group(|ui| {
+            ui.label("Audio Settings");
+            ui.add_space(5.0);
+            self.show_device_notices(ui);
+
+            ui.horizontal(|ui| {
+                ui.label("PC Audio → iPhone:");
+                egui::ComboBox::from_id_salt("input_device")
+                    .width(200.0)
+                    .selected_text(
+                        self.input_devices
+                            .get(self.selected_input)
+                            .map(|d| d.name.as_str())
+                            .unwrap_or("None"),
+                    )
+                    .show_ui(ui, |ui| {
+                        for (i, device) in self.input_devices.iter().enumerate() {
+                            ui.selectable_value(&mut self.selected_input, i, &device.name);
+                        }
+                    });
+            });
+            ui.label("   ↳ Select your speakers with (Loopback) to stream PC audio");
+            if self.input_devices.get(self.selected_input).is_some_and(|d| d.is_output) {
+                self.show_capture_exclude(ui);
+            }
+            let current = DownmixMode::load(&self.state.downmix);
+            let mut mode = current;
+            ui.horizontal(|ui| {
+                ui.label("Mono mix:");
+                egui::ComboBox::from_id_salt("downmix")
+                    .width(200.0)
+                    .selected_text(mode.label())
+                    .show_ui(ui, |ui| {
+                        for choice in DownmixMode::ALL {
+                            ui.selectable_value(&mut mode, choice, choice.label());
+                        }
+                    });
+            })
+            .response
+            .on_hover_text("The iPhone, RTSP and AES67 all play mono. Take one channel if mixing makes audio disappear.");
+            if mode != current {
+                self.set_downmix(mode);
+            }
+
+            ui.add_space(5.0);
+
+            ui.horizontal(|ui| {
+                ui.label("iPhone → PC:");
+                egui::ComboBox::from_id_salt("output_device")
+                    .width(200.0)
+                    .selected_text(
+                        self.output_devices
+                            .get(self.selected_output)
+                            .map(|d| d.name.as_str())
+                            .unwrap_or("None"),
+                    )
+                    .show_ui(ui, |ui| {
+                        for (i, device) in self.output_devices.iter().enumerate() {
+                            ui.selectable_value(&mut self.selected_output, i, &device.name);
+                        }
+                    });
+            });
+            ui.label("   ↳ For mic: use virtual cable (e.g., VB-Audio CABLE Input)");
+            self.show_virtual_cable_status(ui, is_connected);
+            if let Some(device) = self.feedback_device().map(str::to_string) {
+                ui.colored_label(
+                    egui::Color32::YELLOW,
+                    format!("⚠ Feedback loop: iPhone audio played on {} is captured and sent back to it.", device),
+                );
+                ui.label("   ↳ Pick a virtual cable for iPhone → PC, or mute iPhone → PC");
+                ui.checkbox(&mut self.allow_feedback_loop, "Connect anyway");
+            }
+
+            ui.add_space(5.0);
+
+            ui.horizontal(|ui| {
+                let mut mute_out = self.state.mute_out.load(Ordering::Relaxed);
+                if ui.checkbox(&mut mute_out, "Mute PC → iPhone").changed() {
+                    self.state.mute_out.store(mute_out, Ordering::Relaxed);
+                }
+                show_peak_hold(ui, &self.state.peak_out);
+                ui.add_space(10.0);
+                let mut mute_mic = self.state.mute_mic.load(Ordering::Relaxed);
+                if ui.checkbox(&mut mute_mic, "Mute iPhone → PC").changed() {
+                    self.state.mute_mic.store(mute_mic, Ordering::Relaxed);
+                }
+                show_peak_hold(ui, &self.state.peak_mic);
+            });
+            ui.horizontal(|ui| {
+                show_gain_slider(ui, "PC → iPhone", &self.state.gain_out, "gain_out_db");
+                ui.add_space(10.0);
+                show_gain_slider(ui, "iPhone → PC", &self.state.gain_mic, "gain_mic_db");
+            });
+            self.show_receivers(ui);
+
+            ui.add_space(5.0);
+
+            let mut formats = self.formats;
+            ui.horizontal(|ui| {
+                ui.label("Codec to iPhone:");
+                show_format_choice(ui, "down_format", &mut formats.down, &StreamFormat::DOWN_CHOICES);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Codec from iPhone:");
+                show_format_choice(ui, "up_format", &mut formats.up, &StreamFormat::UP_CHOICES);
+            });
+            if formats != self.formats {
+                self.set_formats(formats);
+            }
+            ui.horizontal(|ui| {
+                ui.label("Redundancy:");
+                let label = |n: u8| match n {
+                    0 => "Off".to_string(),
+                    1 => "Repeat 1 earlier frame".to_string(),
+                    n => format!("Repeat {} earlier frames", n),
+                };
+                egui::ComboBox::from_id_salt("red_frames")
+                    .width(200.0)
+                    .selected_text(label(self.redundancy))
+                    .show_ui(ui, |ui| {
+                        for n in 0..=protocol::MAX_REDUNDANCY {
+                            if ui.selectable_value(&mut self.redundancy, n, label(n)).changed() {
+                                save_setting("red_frames", &n.to_string());
+                            }
+                        }
+                    });
+            })
+            .response
+            .on_hover_text("For lossy links: each Opus packet also carries the frames before it, so a lost packet leaves no gap. Costs up to that many times the bandwidth.");
+            ui.horizontal(|ui| {
+                ui.label("Error correction:");
+                let label = |n: u8| match n {
+                    0 => "Off".to_string(),
+                    n => format!("1 parity packet per {} (+{}%)", n, 100 / n as u32),
+                };
+                egui::ComboBox::from_id_salt("fec_group")
+                    .width(200.0)
+                    .selected_text(label(self.fec))
+                    .show_ui(ui, |ui| {
+                        for n in FEC_CHOICES {
+                            if ui.selectable_value(&mut self.fec, n, label(n)).changed() {
+                                save_setting("fec_group", &n.to_string());
+                            }
+                        }
+                    });
+            })
+            .response
+            .on_hover_text("For Wi-Fi that drops packets, 2.4 GHz especially: after every few packets, both ways, a parity packet from which any one lost packet of them is rebuilt. Works with every codec. Audio after a loss waits for the parity, up to that many packets. Two lost together still leave a gap.");
+            ui.horizontal(|ui| {
+                ui.label("Audio protection:");
+                // Measured the first time this shows; it takes a few milliseconds
+                let costs = *self.seal_costs.get_or_insert_with(|| seal::Mode::ALL.map(|mode| seal::measure(mode, seal::MEASURE_SIZE)));
+                let label = |mode: seal::Mode| match mode {
+                    seal::Mode::Off => mode.label().to_string(),
+                    _ => format!("{}, {:.1} µs a packet", mode.label(), costs[mode as usize].as_secs_f64() * 1e6),
+                };
+                egui::ComboBox::from_id_salt("seal_mode")
+                    .width(300.0)
+                    .selected_text(label(self.seal))
+                    .show_ui(ui, |ui| {
+                        for mode in seal::Mode::ALL {
+                            if ui.selectable_value(&mut self.seal, mode, label(mode)).changed() {
+                                save_setting("seal_mode", mode.as_str());
+                            }
+                        }
+                    });
+            })
+            .response
+            .on_hover_text(format!(
+                "Authenticating keeps anyone else on the network from injecting or changing audio; encrypting also keeps them from listening. The times are what sealing and opening a {}-byte packet takes on this PC. A phone or a small receiver takes longer, and spends battery on every packet both ways.",
+                seal::MEASURE_SIZE
+            ));
+            ui.label("   ↳ Opus, 24-bit, float, protection and error correction need an up-to-date iPhone app; applies on the next connect");
+            let max_payload = if self.routed() { LinkPreset::VPN } else { LinkPreset::LAN }.max_payload;
+            let (down_kbps, up_kbps) =
+                Session { formats: self.formats, redundancy: self.redundancy, fec: self.fec, ..Default::default() }.network_kbps(max_payload);
+            ui.label(format!("   ↳ About {} kbps to the iPhone and {} kbps back, headers and parity included", down_kbps, up_kbps))
+            .on_hover_text("24-bit and float keep detail 16-bit PCM rounds off, for 1.5 and 2 times its bandwidth. Wi-Fi handles either, but a weak signal or a VPN may not.");
+            ui.horizontal(|ui| {
+                let mut limited = self.bandwidth_limit.is_some();
+                let mut kbps = self.bandwidth_limit.unwrap_or(limiter::DEFAULT_KBPS);
+                let toggled = ui.checkbox(&mut limited, "Limit bandwidth to").changed();
+                let response =
+                    ui.add_enabled(limited, egui::DragValue::new(&mut kbps).range(limiter::KBPS_RANGE).suffix(" kbps"));
+                self.bandwidth_limit = limited.then_some(kbps);
+                if toggled || edit_finished(&response) {
+                    save_setting("bandwidth_limit_kbps", &self.bandwidth_limit.unwrap_or(0).to_string());
+                }
+            })
+            .response
+            .on_hover_text("For a shared hotspot or a metered connection: both directions together, headers included. Over it, the formats step down to Opus, longer frames, no redundancy or FEC and lower bitrates, in that order, only as far as needed. Applies on the next connect.");
+            if let Some(kbps) = self.bandwidth_limit {
+                let session = Session { formats: self.formats, redundancy: self.redundancy, fec: self.fec, ..Default::default() };
+                let (_, changes) = limiter::limit(session, kbps, max_payload);
+                if !changes.is_empty() {
+                    ui.label(format!("   ↳ Over the limit, so sessions use: {}", changes.join(", ")));
+                }
+            }
+
+            ui.add_space(5.0);
+
+            ui.horizontal(|ui| {
+                let scanning = self.device_scan.as_ref().is_some_and(|scan| scan.manual);
+                if ui
+                    .add_enabled(!scanning, egui::Button::new("Refresh devices"))
+                    .on_hover_text("The lists also update by themselves every few seconds")
+                    .clicked()
+                {
+                    self.refresh_devices(false, true);
+                }
+                if scanning {
+                    ui.spinner();
+                    ui.label("Looking for devices...");
+                }
+            });
+        });
+
+        ui.add_space(10.0);
+        self.show_processing(ui);
+        ui.add_space(10.0);
+
+        ui.group(|ui| {
+            ui.label("Diagnostics");
+            ui.add_space(5.0);
+
+            self.show_mono_warning(ui);
+            self.show_wifi_advisory(ui);
+
+            if is_connected {
+                show_meter(ui, "PC → iPhone", &self.meter_bars[0]);
+                show_meter(ui, "iPhone → PC", &self.meter_bars[1]);
+                match *self.state.negotiated.lock() {
+                    Some(agreed) => {
+                        ui.label(format!(
+                            "Codecs: {} down, {} up",
+                            agreed.formats.down.label(),
+                            agreed.formats.up.label()
+                        ));
+                        if let Some((max_kbps, changes)) = &self.session_limit {
+                            let max_payload = if self.routed() { LinkPreset::VPN } else { LinkPreset::LAN }.max_payload;
+                            let (down, up) = agreed.network_kbps(max_payload);
+                            if down + up > *max_kbps {
+                                ui.colored_label(
+                                    egui::Color32::YELLOW,
+                                    format!("⚠ Over the {} kbps limit: the iPhone answered with formats taking about {} kbps. Updating the app lets it take lighter ones.", max_kbps, down + up),
+                                );
+                            } else if !changes.is_empty() {
+                                ui.label(format!("Bandwidth limit {} kbps in effect: {}", max_kbps, changes.join(", ")));
+                            }
+                        }
+                        if agreed.up_sample_rate() != protocol::NATIVE_RATE {
+                            ui.label(format!(
+                                "The iPhone sends {:.1} kHz audio, resampled here to 48 kHz",
+                                agreed.up_sample_rate() as f32 / 1000.0
+                            ));
+                        }
+                        if agreed.redundancy > 0 {
+                            ui.label(format!(
+                                "Redundancy: {} earlier frames per packet, {} lost frames recovered",
+                                agreed.redundancy,
+                                self.state.red_recovered.load(Ordering::Relaxed)
+                            ));
+                        }
+                        if let Some(group) = agreed.fec_group() {
+                            ui.label(format!(
+                                "Error correction: 1 parity packet per {} (+{}%), {} lost packets from iPhone rebuilt",
+                                group,
+                                100 / group as u32,
+                                self.state.fec_recovered.load(Ordering::Relaxed)
+                            ));
+                        }
+                        if agreed.framed {
+                            let sequence = self.state.recv_sequence.lock();
+                            let recent = sequence.loss_pct().map(|pct| format!(" ({:.1}% over 5 s)", pct)).unwrap_or_default();
+                            ui.label(format!(
+                                "Packets from iPhone: {} lost{}, {} out of order",
+                                sequence.lost(),
+                                recent,
+                                sequence.reordered()
+                            ));
+                        }
+                        let seal_stats = self.state.seal_stats.lock();
+                        if agreed.seal != seal::Mode::Off {
+                            let cost = seal_stats
+                                .per_packet()
+                                .map(|cost| format!(", {:.1} µs a packet here", cost.as_secs_f64() * 1e6))
+                                .unwrap_or_default();
+                            ui.label(format!("Protection: {}{}, {} packets refused", agreed.seal.label(), cost, seal_stats.rejected));
+                        } else if seal_stats.requested != seal::Mode::Off {
+                            ui.colored_label(
+                                egui::Color32::YELLOW,
+                                "⚠ Audio isn't protected: the iPhone app didn't take it up. Updating the app adds it.",
+                            );
+                        }
+                    }
+                    None => {
+                        ui.label("Codecs: PCM mono both ways");
+                    }
+                }
+                let buffer = self.state.playback_buffer.snapshot();
+                if buffer.adaptive {
+                    ui.label(format!(
+                        "Playback buffer: {:.0} ms now, aiming for {:.0} ms, {} underruns",
+                        buffer.depth_ms, buffer.target_ms, buffer.expansions
+                    ));
+                }
+            }
+
+            let sent = self.state.packets_sent.load(Ordering::Relaxed);
+            let recv = self.state.packets_recv.load(Ordering::Relaxed);
+            let callbacks = self.state.audio_callbacks.load(Ordering::Relaxed);
+
+            let sent_rate = self.sent_rate.update(sent, Instant::now());
+            let recv_rate = self.recv_rate.update(recv, Instant::now());
+
+            ui.label(format!("Packets Sent: {} (+{}/s)", sent, sent_rate));
+            show_signal_level(ui, "Sent level", self.state.level_sent.lock().level_db(), self.signal_threshold_db);
+            ui.label(format!("Packets Received: {} (+{}/s)", recv, recv_rate));
+            show_signal_level(ui, "Received level", self.state.level_recv.lock().level_db(), self.signal_threshold_db);
+            ui.horizontal(|ui| {
+                ui.label("Signal above");
+                let response = ui.add(
+                    egui::DragValue::new(&mut self.signal_threshold_db)
+                        .range(signal::THRESHOLD_RANGE_DB)
+                        .speed(0.5)
+                        .suffix(" dBFS"),
+                );
+                if edit_finished(&response) {
+                    save_setting("signal_threshold_db", &self.signal_threshold_db.to_string());
+                }
+            })
+            .response
+            .on_hover_text("RMS over the last 100 ms. Quieter than this counts as silence: a noise or dither floor sits around -90 dBFS, quiet music around -50.");
+            ui.label(format!("Audio Callbacks: {}", callbacks));
+            ui.label(format!(
+                "Clipped at output: PC → iPhone {} ({:.3}%), iPhone → PC {} ({:.3}%)",
+                self.state.saturation_out.clipped(),
+                self.state.saturation_out.ratio() * 100.0,
+                self.state.saturation_mic.clipped(),
+                self.state.saturation_mic.ratio() * 100.0
+            ))
+            .on_hover_text("Samples over full scale at the last step before the encoder or speakers. Lower the gain if this keeps climbing.");
+
+            ui.add_space(5.0);
+            egui::CollapsingHeader::new("Packet Timing (last 5s)").show(ui, |ui| {
+                let (recv_counts, recv_max) = self.state.recv_intervals.lock().histogram();
+                let (send_counts, send_max) = self.state.send_intervals.lock().histogram();
+                show_interval_histogram(ui, "Receive gaps", &recv_counts, recv_max);
+                ui.add_space(5.0);
+                show_interval_histogram(ui, "Send gaps", &send_counts, send_max);
+                ui.small("A tall right-hand tail means bursty Wi-Fi or phone power saving; raise the buffer size.");
+            });
+            egui::CollapsingHeader::new("Timeline").show(ui, |ui| {
+                let mut events = self.state.timeline.lock();
+                if events.is_empty() {
+                    ui.label("Nothing yet. Connects, device changes, dropouts and the phone going quiet show up here.");
+                }
+                egui::ScrollArea::vertical().max_height(160.0).id_salt("timeline").show(ui, |ui| {
+                    for entry in events.entries().rev() {
+                        let text = format!("{}  {}", entry.clock, entry.text());
+                        if entry.kind.is_problem() {
+                            ui.colored_label(egui::Color32::YELLOW, egui::RichText::new(text).monospace());
+                        } else {
+                            ui.monospace(text);
+                        }
+                    }
+                });
+                if !events.is_empty() && ui.small_button("Clear").clicked() {
+                    events.clear();
+                }
+            });
+            egui::CollapsingHeader::new("Stream info").show(ui, |ui| {
+                let lines = self.state.streams.lines();
+                if lines.is_empty() {
+                    ui.label("Not streaming.");
+                }
+                for (title, line) in lines {
+                    ui.label(format!("{}: {}", title, line));
+                }
+                ui.small("As opened, after any fallback. Included in the error banner's bug report.");
+            });
+
+            ui.add_space(5.0);
+            egui::CollapsingHeader::new("Advanced diagnostics").show(ui, |ui| {
+                let jitter = self.state.playback_buffer.snapshot();
+                ui.label(if jitter.adaptive { "Playback buffer (iPhone → PC), adaptive" } else { "Playback buffer (iPhone → PC)" });
+                ui.monospace(format!("Target depth    {:>6.1} ms", jitter.target_ms));
+                if jitter.adaptive {
+                    let arrival = self.state.playout.lock().as_ref().map_or(0.0, |depth| depth.jitter_ms());
+                    ui.monospace(format!("Limit           {:>6.1} ms", jitter.limit_ms));
+                    ui.monospace(format!("Arrival jitter  {:>6.1} ms", arrival));
+                }
+                ui.monospace(format!("Actual depth    {:>6.1} ms", jitter.depth_ms));
+                ui.monospace(format!("Expansions      {:>6}  (ran dry, silence played)", jitter.expansions));
+                ui.monospace(format!(
+                    "Contractions    {:>6}  ({:.0} ms of late audio dropped)",
+                    jitter.contractions, jitter.dropped_ms
+                ));
+                ui.monospace(format!(
+                    "Concealed       {:>6}  frames of 20 ms ({:.0} ms)",
+                    (jitter.concealed_ms / 20.0).round(),
+                    jitter.concealed_ms
+                ));
+                ui.small(if jitter.adaptive {
+                    "Counts since connecting. The target follows the arrival jitter and grows by 20 ms at each expansion; after 10 s without one it eases back."
+                } else {
+                    "Counts since connecting. Many expansions: the target is too small for this link. Many contractions: packets arrive in bursts."
+                });
+
+                ui.add_space(5.0);
+                ui.label("Network delay");
+                match *self.state.clock.lock() {
+                    Some(estimate) => {
+                        ui.monospace(format!("Round trip      {:>6.1} ms", estimate.rtt_ms));
+                        match estimate.one_way_ms {
+                            Some((down, up)) => {
+                                ui.monospace(format!("PC → iPhone     {:>6.1} ms", down));
+                                ui.monospace(format!("iPhone → PC     {:>6.1} ms", up));
+                            }
+                            None => {
+                                ui.monospace(format!("Each way       ~{:>6.1} ms  (half the round trip)", estimate.rtt_ms / 2.0));
+                                ui.small("Update the iPhone app to measure each direction.");
+                            }
+                        }
+                    }
+                    None => {
+                        ui.monospace("Waiting for the phone to answer clock probes");
+                    }
+                }
+                ui.small("From a probe stamped on both clocks every second. The steady part is split evenly; a queue building in one direction shows on that side.");
+
+                ui.add_space(5.0);
+                ui.label("CPU (last second)");
+                let usage = &self.cpu_meter.usage;
+                let percent = |value: Option<f32>| value.map_or("     -".to_string(), |v| format!("{:>5.1} %", v));
+                for part in cpu::Part::ALL {
+                    ui.monospace(format!("{:<18}{}", part.label(), percent(usage.parts[part as usize])));
+                }
+                ui.monospace(format!("{:<18}{}  (of one core)", "BudBridge total", percent(usage.process)));
+                ui.monospace(format!("{:<18}{}  (all cores)", "Whole PC", percent(usage.system)));
+                ui.small("If the whole PC is busy but BudBridge isn't, another program is starving the audio.");
+
+                ui.add_space(5.0);
+                self.show_access_log(ui);
+            });
+        });
+    }
+
+    /// Everyone who sent to the receive port this session
+    fn show_access_log(&self, ui: &mut egui::Ui) {
+        ui.label(format!("Senders to port {} (this session)", RECEIVE_PORT));
+        let log = self.state.access_log.lock();
+        let senders = log.senders();
+        if senders.is_empty() {
+            ui.small("Nothing received yet.");
+            return;
+        }
+        ui.monospace(format!("{:<22}{:>9}{:>9}{:>10}{:>9}", "Address", "Accepted", "Dropped", "KB", "Last"));
+        for sender in senders {
+            let line = format!(
+                "{:<22}{:>9}{:>9}{:>10}{:>7} s",
+                sender.addr.to_string(),
+                sender.accepted,
+                sender.rejected,
+                sender.bytes / 1024,
+                sender.last_seen.elapsed().as_secs()
+            );
+            let color = if sender.rejected > 0 { egui::Color32::YELLOW } else { ui.visuals().text_color() };
+            ui.label(egui::RichText::new(line).monospace().color(color));
+        }
+        ui.small("Anything besides the iPhone is another device or app sending to BudBridge's port.");
+    }
+
+    /// A saved device answered with a different identity key, or none
+    fn show_identity_warning(&mut self, ui: &mut egui::Ui) {
+        let Some(verdict) = self.state.peer_identity.lock().filter(|v| v.is_alarm()) else {
+            return;
+        };
+        let id = self.session_device.clone();
+        let Some(device) = self.saved_devices.iter_mut().find(|d| Some(&d.id) == id.as_ref()) else {
+            return;
+        };
+        let known = device.key.as_ref().map_or("none".to_string(), identity::fingerprint);
+        let (headline, offered) = match verdict {
+            identity::Verdict::Changed(key) => ("answered with a different identity key than before", Some(key)),
+            _ => ("answered without its identity key", None),
+        };
+        ui.colored_label(egui::Color32::RED, format!("⚠ {} {}", device.name, headline));
+        ui.label(
+            "If BudBridge wasn't reinstalled on the iPhone and the phone wasn't reset, something else \
+             on the network may be answering in its place. Compare with the key the iPhone app shows.",
+        );
+        ui.monospace(format!("Remembered: {}", known));
+        if let Some(key) = &offered {
+            ui.monospace(format!("Now:        {}", identity::fingerprint(key)));
+        }
+        let mut decided = false;
+        ui.horizontal(|ui| {
+            let trust = if offered.is_some() { "Trust the new key" } else { "Forget the key" };
+            if ui.small_button(trust).clicked() {
+                device.key = offered;
+                decided = true;
+            }
+            if ui.small_button("Dismiss").clicked() {
+                *self.state.peer_identity.lock() = None;
+            }
+        });
+        if decided {
+            log_message(&self.log_file, &self.debug_logging_flag, &format!(
+                "{}'s identity key replaced by the user", device.name
+            ));
+            save_devices(&self.saved_devices);
+            if !self.state.is_connected.load(Ordering::SeqCst) {
+                self.connect();
+            }
+        }
+        ui.add_space(5.0);
+    }
+
+    /// Offer to mute the direction that closes a loop the network thread heard
+    fn show_feedback_warning(&mut self, ui: &mut egui::Ui) {
+        let Some(found) = *self.state.feedback.lock() else {
+            return;
+        };
+        let (explanation, mute, mute_label) = match found.direction {
+            feedback::Direction::Returned => (
+                "the iPhone is sending back the audio it gets from this PC",
+                &self.state.mute_mic,
+                "Mute iPhone → PC",
+            ),
+            feedback::Direction::Resent => (
+                "this PC is capturing the iPhone's audio and sending it back",
+                &self.state.mute_out,
+                "Mute PC → iPhone",
+            ),
+        };
+        if self.feedback_dismissed || mute.load(Ordering::Relaxed) {
+            return;
+        }
+        ui.colored_label(
+            egui::Color32::YELLOW,
+            format!(
+                "⚠ Feedback loop: {} ({} ms later, {:.0}% match)",
+                explanation,
+                found.delay_ms,
+                found.correlation * 100.0
+            ),
+        );
+        ui.horizontal(|ui| {
+            if ui.small_button(mute_label).clicked() {
+                mute.store(true, Ordering::Relaxed);
+            }
+            if ui.small_button("Ignore").clicked() {
+                self.feedback_dismissed = true;
+            }
+        });
+        ui.add_space(5.0);
+    }
+
+    /// Loopback only: one app whose audio stays off the capture
+    fn show_capture_exclude(&mut self, ui: &mut egui::Ui) {
+        let before = self.capture_exclude.clone();
+        ui.horizontal(|ui| {
+            ui.label("Leave out:");
+            let selected = if self.capture_exclude.is_empty() { "Nothing" } else { self.capture_exclude.as_str() };
+            egui::ComboBox::from_id_salt("capture_exclude")
+                .width(200.0)
+                .selected_text(selected.to_string())
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.capture_exclude, String::new(), "Nothing");
+                    for session in &self.app_sessions {
+                        ui.selectable_value(&mut self.capture_exclude, session.name.clone(), &session.name);
+                    }
+                });
+            if ui.button("↻").on_hover_text("List the apps playing sound now").clicked() {
+                match process_loopback::sessions() {
+                    Ok(sessions) => self.app_sessions = sessions,
+                    Err(e) => log_message(&self.log_file, &self.debug_logging_flag, &format!("Audio sessions: {}", e)),
+                }
+            }
+            ui.weak("one app at most");
+        })
+        .response
+        .on_hover_text("Captures every app but this one, e.g. Spotify when the iPhone plays it already. Only one app can be left out: Windows' process loopback excludes a single app and its child processes. Pick from the apps playing sound (↻ refreshes the list). Needs Windows 10 2004 or later, and takes what apps play on any output rather than the selected speakers. Applies on the next connect; picking another capture device while connected captures that whole.");
+        if self.capture_exclude != before {
+            save_setting("capture_exclude", &self.capture_exclude);
+        }
+        if !self.capture_exclude.is_empty()
+            && !self.app_sessions.is_empty()
+            && process_loopback::find(&self.app_sessions, &self.capture_exclude).is_none()
+        {
+            ui.small(format!("   {} isn't playing sound now, so everything is captured until it does and you reconnect.", self.capture_exclude));
+        }
+    }
+
+    /// Selected devices that disappeared, and what replaced them
+    fn show_device_notices(&mut self, ui: &mut egui::Ui) {
+        if self.device_notices.is_empty() {
+            return;
+        }
+        for notice in &self.device_notices {
+            ui.colored_label(egui::Color32::YELLOW, format!("⚠ {}", notice));
+        }
+        if ui.small_button("Dismiss").clicked() {
+            self.device_notices.clear();
+        }
+        ui.add_space(5.0);
+    }
+
+    /// The capture's channels are out of phase, so the usual mix is near silent
+    fn show_mono_warning(&mut self, ui: &mut egui::Ui) {
+        if !self.state.mono_cancels.load(Ordering::Relaxed) || DownmixMode::load(&self.state.downmix) != DownmixMode::Mix
+        {
+            return;
+        }
+        ui.colored_label(
+            egui::Color32::YELLOW,
+            "⚠ PC audio cancels out in mono: its left and right channels are out of phase",
+        );
+        if ui.small_button("Use the left channel only").clicked() {
+            self.set_downmix(DownmixMode::Left);
+        }
+        ui.add_space(5.0);
+    }
+
+    fn set_downmix(&mut self, mode: DownmixMode) {
+        mode.store(&self.state.downmix);
+        save_setting("downmix", mode.as_str());
+    }
+
+    fn show_processing(&mut self, ui: &mut egui::Ui) {
+        ui.group(|ui| {
+            ui.label("PC Audio Processing");
+            ui.add_space(5.0);
+
+            let mut settings = *self.state.dsp.lock();
+            ui.horizontal(|ui| {
+                ui.label("Loudness:");
+                let label = |target: Option<f32>| {
+                    dsp::LOUDNESS_TARGETS
+                        .iter()
+                        .find(|(lufs, _)| Some(*lufs) == target)
+                        .map_or("Off", |(_, label)| label)
+                };
+                egui::ComboBox::from_id_salt("loudness_target")
+                    .width(200.0)
+                    .selected_text(label(settings.loudness_target))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut settings.loudness_target, None, "Off");
+                        for (lufs, name) in dsp::LOUDNESS_TARGETS {
+                            ui.selectable_value(&mut settings.loudness_target, Some(lufs), name);
+                        }
+                    });
+                if let Some(lufs) = *self.state.capture_lufs.lock() {
+                    ui.small(format!("source {:.0} LUFS", lufs));
+                }
+            })
+            .response
+            .on_hover_text("Evens out quiet and loud sources, so switching from a video to a game doesn't blast your ears.");
+
+            ui.checkbox(&mut settings.night_mode, "Night mode")
+                .on_hover_text("Strong compression and a level cap: quiet dialogue stays audible and explosions stay quiet.");
+
+            let crossfeed = ui.horizontal(|ui| {
+                ui.label("Crossfeed:");
+                let mut percent = (settings.crossfeed * 100.0).round();
+                let response = ui.add(egui::Slider::new(&mut percent, 0.0..=100.0).suffix(" %").custom_formatter(|v, _| {
+                    if v == 0.0 { "Off".to_string() } else { format!("{:.0}", v) }
+                }));
+                settings.crossfeed = percent / 100.0;
+                response
+            });
+            crossfeed
+                .response
+                .on_hover_text("Blends some of each channel into the other, like speakers do, so hard-panned stereo is less tiring on earbuds.");
+            let crossfeed = crossfeed.inner;
+            if settings.crossfeed > 0.0 {
+                ui.label("   ↳ Only changes stereo playback; the iPhone app plays mono for now");
+            }
+
+            let changed = settings != *self.state.dsp.lock();
+            if changed {
+                *self.state.dsp.lock() = settings;
+            }
+            // Applied live; a crossfeed drag is only saved once it ends
+            if (changed && !crossfeed.dragged()) || crossfeed.drag_stopped() {
+                save_dsp_settings(&settings);
+            }
+        });
+    }
+
+    fn show_profiles(&mut self, ui: &mut egui::Ui) {
+        ui.group(|ui| {
+            ui.label("Profile");
+            ui.add_space(5.0);
+
+            let mut to_apply: Option<usize> = None;
+            let mut to_delete: Option<usize> = None;
+
+            ui.horizontal_wrapped(|ui| {
+                for (i, profile) in self.profiles.iter().enumerate() {
+                    if ui.selectable_label(self.active_profile == Some(i), &profile.name).clicked() {
+                        to_apply = Some(i);
+                    }
+                }
+                if self.profiles.is_empty() {
+                    ui.label("No profiles yet - set things up below, then save them here.");
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut self.new_profile_name).hint_text("Profile name").desired_width(120.0));
+                let name = self.new_profile_name.trim().to_string();
+                if ui.add_enabled(!name.is_empty() && !name.contains('|'), egui::Button::new("Save current")).clicked() {
+                    let profile = self.capture_profile(name.clone());
+                    match self.profiles.iter().position(|p| p.name == name) {
+                        Some(i) => self.profiles[i] = profile,
+                        None => self.profiles.push(profile),
+                    }
+                    save_profiles(&self.profiles);
+                    self.active_profile = self.profiles.iter().position(|p| p.name == name);
+                    save_setting("profile", &name);
+                    self.new_profile_name.clear();
+                }
+                if let Some(i) = self.active_profile {
+                    if ui.button("Delete").clicked() {
+                        to_delete = Some(i);
+                    }
+                }
+            });
+
+            if let Some(i) = to_apply {
+                self.apply_profile(i);
+            }
+            if let Some(i) = to_delete {
+                self.profiles.remove(i);
+                save_profiles(&self.profiles);
+                self.active_profile = None;
+                save_setting("profile", "");
+            }
+        });
+    }
+
+    fn show_virtual_cable_status(&mut self, ui: &mut egui::Ui, is_connected: bool) {
+        match self.find_virtual_cable() {
+            Some((cable_idx, cable)) if cable_idx == self.selected_output => {
+                ui.colored_label(
+                    egui::Color32::GREEN,
+                    format!("   ✓ Virtual cable selected - pick \"{}\" as mic in Discord/Zoom", cable.mic_name),
+                );
+                ui.horizontal(|ui| {
+                    ui.add_space(12.0);
+                    let checkbox = egui::Checkbox::new(
+                        &mut self.set_comms_default,
+                        format!("Make \"{}\" the default communications mic while connected", cable.mic_name),
+                    );
+                    if ui.add_enabled(!is_connected, checkbox).changed() {
+                        save_bool_setting("comms_default", self.set_comms_default);
+                    }
+                });
+            }
+            Some((cable_idx, _)) => {
+                ui.horizontal(|ui| {
+                    ui.label("   ✓ Virtual cable detected");
+                    if ui.add_enabled(!is_connected, egui::Button::new("Use it")).clicked() {
+                        self.selected_output = cable_idx;
+                    }
+                });
+            }
+            None => {
+                egui::CollapsingHeader::new("Set up AirPods mic for PC apps")
+                    .id_salt("cable_setup")
+                    .show(ui, |ui| {
+                        ui.label("No virtual audio cable found. To use the AirPods mic in Discord/Zoom:");
+                        ui.horizontal(|ui| {
+                            ui.label("1. Install");
+                            ui.hyperlink_to("VB-Audio Virtual Cable", VB_CABLE_URL);
+                            ui.label("(free)");
+                        });
+                        ui.label("2. Reboot if the installer asks you to");
+                        ui.label("3. Click \"Check again\" below");
+                        ui.add_space(5.0);
+                        ui.horizontal(|ui| {
+                            let scanning = self.device_scan.as_ref().is_some_and(|scan| scan.manual);
+                            if ui.add_enabled(!is_connected && !scanning, egui::Button::new("Check again")).clicked() {
+                                self.refresh_devices(true, true);
+                            }
+                            if scanning {
+                                ui.spinner();
+                            }
+                        });
+                    });
+            }
+        }
+    }
+
+    fn start_repair(&mut self, idx: usize) {
+        // Only one at a time; one left unfinished keeps its old key
+        if let Some(previous) = self.repair.take() {
+            if let Some(device) = self.saved_devices.iter_mut().find(|d| d.id == previous.device_id && d.key.is_none()) {
+                device.key = previous.old_key;
+            }
+        }
+        let device = &mut self.saved_devices[idx];
+        log_message(&self.log_file, &self.debug_logging_flag, &format!("Re-pairing {}", device.name));
+        self.repair = Some(Repair {
+            device_id: device.id.clone(),
+            old_key: device.key.take(),
+            answered_with_old_key: false,
+        });
+        let id = device.id.clone();
+        save_devices(&self.saved_devices);
+        if self.state.is_connected.load(Ordering::SeqCst) && self.session_device.as_ref() == Some(&id) {
+            self.disconnect();
+        }
+    }
+
+    /// Walks through re-pairing: new key on the phone, connect, compare
+    fn show_repair(&mut self, ui: &mut egui::Ui) {
+        let Some(repair) = &mut self.repair else {
+            return;
+        };
+        let Some(idx) = self.saved_devices.iter().position(|d| d.id == repair.device_id) else {
+            self.repair = None;
+            return;
+        };
+        let device = &mut self.saved_devices[idx];
+        // Still the old key: the phone hasn't made a new one yet
+        if device.key.is_some() && device.key == repair.old_key {
+            device.key = None;
+            repair.answered_with_old_key = true;
+            save_devices(&self.saved_devices);
+        }
+        let device = &self.saved_devices[idx];
+        let name = device.name.clone();
+        let learned = device.key;
+        let is_connected = self.state.is_connected.load(Ordering::SeqCst);
+
+        let mut finish: Option<bool> = None; // Some(matched)
+        let mut connect = false;
+        ui.group(|ui| {
+            ui.label(format!("Re-pair {}", name));
+            ui.add_space(5.0);
+            match learned {
+                None => {
+                    if repair.answered_with_old_key {
+                        ui.colored_label(egui::Color32::YELLOW, "The iPhone answered with its old key.");
+                    }
+                    ui.label("1. On the iPhone, open BudBridge → PCs → Make a New Key");
+                    ui.label("2. Connect to it from here");
+                    ui.horizontal(|ui| {
+                        if ui.add_enabled(!is_connected, egui::Button::new("Connect")).clicked() {
+                            connect = true;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            finish = Some(false);
+                        }
+                    });
+                }
+                Some(key) => {
+                    ui.label("3. The iPhone answered with a new key:");
+                    ui.monospace(identity::fingerprint(&key));
+                    ui.label("Check that the iPhone shows the same under PCs → My iPhone → Identity Key.");
+                    ui.horizontal(|ui| {
+                        if ui.button("They match").clicked() {
+                            finish = Some(true);
+                        }
+                        if ui.button("They don't").clicked() {
+                            finish = Some(false);
+                        }
+                    });
+                }
+            }
+        });
+        ui.add_space(10.0);
+
+        if connect {
+            self.selected_device = Some(idx);
+            self.iphone_ip = self.saved_devices[idx].ip.clone();
+            self.connect();
+        }
+        match finish {
+            Some(true) => {
+                log_message(&self.log_file, &self.debug_logging_flag, &format!("Re-paired {}", name));
+                *self.state.status_message.lock() = format!("Re-paired {}", name);
+                self.repair = None;
+            }
+            Some(false) => {
+                // Cancelled before a new key arrived: the old pairing stands
+                let old_key = self.repair.take().and_then(|r| r.old_key);
+                let device = &mut self.saved_devices[idx];
+                device.key = if learned.is_none() { old_key } else { None };
+                save_devices(&self.saved_devices);
+                if learned.is_some() {
+                    self.disconnect();
+                    *self.state.status_message.lock() = format!(
+                        "Error: re-pairing {} stopped because the keys didn't match. Something else may be answering for it.",
+                        name
+                    );
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn show_devices_tab(&mut self, ui: &mut egui::Ui) {
+        ui.group(|ui| {
+            ui.label("Add New Device");
+            ui.add_space(5.0);
+
+            ui.horizontal(|ui| {
+                ui.label("Name:");
+                ui.text_edit_singleline(&mut self.new_device_name);
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("IP:");
+                ui.text_edit_singleline(&mut self.new_device_ip)
+                    .on_hover_text("Experimental: \"bt:AA:BB:CC:DD:EE:FF\" connects over Bluetooth RFCOMM to a paired phone running a BudBridge listener (not available in the iOS app)");
+            });
+
+            ui.add_space(5.0);
+
+            if ui.button("Add Device").clicked()
+                && !self.new_device_name.trim().is_empty()
+                && !self.new_device_ip.trim().is_empty()
+            {
+                let is_first = self.saved_devices.is_empty();
+                self.saved_devices.push(SavedDevice::new(
+                    self.new_device_name.trim().to_string(),
+                    self.new_device_ip.trim().to_string(),
+                ));
+                save_devices(&self.saved_devices);
+
+                if is_first {
+                    self.default_device = Some(0);
+                    self.selected_device = Some(0);
+                    self.iphone_ip = self.new_device_ip.trim().to_string();
+                    save_default_device(&self.saved_devices, Some(0));
+                }
+
+                self.new_device_name.clear();
+                self.new_device_ip.clear();
+            }
+        });
+
+        ui.add_space(10.0);
+
+        self.show_repair(ui);
+
+        ui.group(|ui| {
+            ui.label("Saved Devices");
+            ui.add_space(5.0);
+
+            if ui
+                .checkbox(&mut self.auto_update_ip, "Update a device's IP when it connects from a new address")
+                .changed()
+            {
+                save_bool_setting("auto_update_ip", self.auto_update_ip);
+            }
+            ui.add_space(5.0);
+
+            if self.saved_devices.is_empty() {
+                ui.label("No devices saved yet.");
+            } else {
+                let mut to_delete: Option<usize> = None;
+                let mut to_repair: Option<usize> = None;
+                let mut new_default: Option<Option<usize>> = None;
+
+                for (i, device) in self.saved_devices.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        let is_default = self.default_device == Some(i);
+                        if ui.radio(is_default, "").clicked() {
+                            new_default = Some(Some(i));
+                        }
+                        ui.label(format!("{} - {}", device.name, device.ip));
+                        if let Some(key) = &device.key {
+                            ui.label("🔑").on_hover_text(format!("Identity key {}", identity::fingerprint(key)));
+                        }
+                        if is_default {
+                            ui.label("(default)");
+                        }
+                        if ui
+                            .button("Re-pair")
+                            .on_hover_text("Forget this iPhone's identity key and learn its new one, after it was reset or changed hands")
+                            .clicked()
+                        {
+                            to_repair = Some(i);
+                        }
+                        if ui.button("Delete").clicked() {
+                            to_delete = Some(i);
+                        }
+                    });
+                }
+
+                if let Some(idx) = to_repair {
+                    self.start_repair(idx);
+                }
+
+                if let Some(new_def) = new_default {
+                    self.default_device = new_def;
+                    save_default_device(&self.saved_devices, self.default_device);
+                }
+
+                if let Some(idx) = to_delete {
+                    self.saved_devices.remove(idx);
+                    save_devices(&self.saved_devices);
+
+                    if self.selected_device == Some(idx) {
+                        self.selected_device = None;
+                        self.iphone_ip.clear();
+                    } else if let Some(sel) = self.selected_device {
+                        if sel > idx {
+                            self.selected_device = Some(sel - 1);
+                        }
+                    }
+
+                    if self.default_device == Some(idx) {
+                        self.default_device = None;
+                        save_default_device(&self.saved_devices, None);
+                    } else if let Some(def) = self.default_device {
+                        if def > idx {
+                            self.default_device = Some(def - 1);
+                            save_default_device(&self.saved_devices, self.default_device);
+                        }
+                    }
+
+                    if self.saved_devices.len() == 1 && self.default_device.is_none() {
+                        self.default_device = Some(0);
+                        save_default_device(&self.saved_devices, Some(0));
+                    }
+                }
+            }
+        });
+
+        ui.add_space(10.0);
+
+        ui.group(|ui| {
+            ui.label("Tips");
+            ui.add_space(5.0);
+            ui.label("• Find your iPhone's IP in Settings > Wi-Fi > (i)");
+            ui.label("• Make sure both devices are on the same network");
+        });
+    }
+
+    fn backup_config(&mut self) {
+        match backup::create(&read_config_files(), &get_backups_path()) {
+            Ok(path) => {
+                self.backup_status = format!("Saved {}", path.display());
+                self.restore_path = path.display().to_string();
+            }
+            Err(e) => self.backup_status = format!("Error: {}", e),
+        }
+    }
+
+    /// Validate the chosen backup; it is applied once confirmed
+    fn check_restore(&mut self) {
+        let path = PathBuf::from(self.restore_path.trim().trim_matches('"'));
+        match backup::open(&path) {
+            Ok(files) => {
+                self.backup_status.clear();
+                self.pending_restore = Some(files);
+            }
+            Err(e) => self.backup_status = format!("Error: {}", e),
+        }
+    }
+
+    fn apply_restore(&mut self) {
+        let Some(files) = self.pending_restore.take() else {
+            return;
+        };
+        write_config_files(&read_config_files(), &files);
+
+        self.saved_devices = load_saved_devices();
+        self.profiles = load_profiles(&self.saved_devices);
+        self.active_profile = None;
+        self.default_device = load_default_device(&self.saved_devices);
+        self.selected_device = self.default_device.or((self.saved_devices.len() == 1).then_some(0));
+        if let Some(device) = self.selected_device.and_then(|i| self.saved_devices.get(i)) {
+            self.iphone_ip = device.ip.clone();
+        }
+        self.backup_status = "Restored. Restart BudBridge to apply the restored settings.".to_string();
+    }
+
+    fn show_scheduling(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Process priority");
+            egui::ComboBox::from_id_salt("process_priority")
+                .selected_text(self.process_priority.label())
+                .show_ui(ui, |ui| {
+                    for priority in priority::Priority::ALL {
+                        if ui.selectable_value(&mut self.process_priority, priority, priority.label()).changed() {
+                            save_setting("process_priority", priority.as_str());
+                            if let Err(e) = priority::set_process_priority(priority) {
+                                log_message(&self.log_file, &self.debug_logging_flag, &format!("Process priority: {}", e));
+                            }
+                        }
+                    }
+                });
+        })
+        .response
+        .on_hover_text("Above normal or High lets BudBridge's threads run ahead of other programs when the CPU is busy. Applies right away.");
+
+        let counts = *self.core_counts.get_or_insert_with(priority::core_counts);
+        ui.add_enabled_ui(counts.is_some(), |ui| {
+            if ui
+                .checkbox(&mut self.pin_performance, "Keep capture and network on performance cores")
+                .on_hover_text("Hybrid CPUs can move the audio threads to a slower efficiency core, where they may fall behind. Applies on the next connect.")
+                .changed()
+            {
+                save_bool_setting("pin_performance_cores", self.pin_performance);
+                self.state.pin_performance.store(self.pin_performance, Ordering::Relaxed);
+            }
+        });
+        ui.label(match counts {
+            Some((performance, all)) => format!("   ↳ {} of this PC's {} logical processors are performance cores", performance, all),
+            None => "   ↳ This PC's cores are all alike, so there's nothing to pin to".to_string(),
+        });
+    }
+
+    fn show_retry_policy(&mut self, ui: &mut egui::Ui) {
+        let before = self.retry_policy;
+        let policy = &mut self.retry_policy;
+        // The DragValues, so a drag is saved once it ends rather than every frame
+        let mut values = Vec::new();
+        ui.horizontal(|ui| {
+            ui.label("Retries after a failure");
+            let mut forever = policy.max_retries.is_none();
+            let mut count = policy.max_retries.unwrap_or(3);
+            values.push(ui.add_enabled(!forever, egui::DragValue::new(&mut count).range(retry::MAX_RETRIES_RANGE)));
+            ui.checkbox(&mut forever, "Forever");
+            policy.max_retries = (!forever).then_some(count);
+        })
+        .response
+        .on_hover_text("0 fails at once. Covers a first Connect that gets no answer as well as a session that drops.");
+        ui.horizontal(|ui| {
+            ui.label("First retry after");
+            values.push(ui.add(egui::DragValue::new(&mut policy.initial_delay_secs).range(retry::DELAY_RANGE_SECS).suffix(" s")));
+            ui.label("doubling up to");
+            values.push(ui.add(egui::DragValue::new(&mut policy.max_delay_secs).range(retry::DELAY_RANGE_SECS).suffix(" s")));
+        });
+        ui.horizontal(|ui| {
+            ui.label("When retries run out");
+            egui::ComboBox::from_id_salt("retry_give_up")
+                .selected_text(policy.give_up.label())
+                .show_ui(ui, |ui| {
+                    for give_up in retry::GiveUp::ALL {
+                        ui.selectable_value(&mut policy.give_up, give_up, give_up.label());
+                    }
+                });
+        });
+        let dragging = values.iter().any(|response| response.dragged());
+        if (self.retry_policy != before && !dragging) || values.iter().any(edit_finished) {
+            save_retry_policy(&self.retry_policy);
+        }
+    }
+
+    /// A log file onto the clipboard with its addresses taken out (redact.rs)
+    fn show_log_sharing(&mut self, ui: &mut egui::Ui) {
+        let file_name = |path: &Path| path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        ui.horizontal(|ui| {
+            ui.label("Share a log:");
+            egui::ComboBox::from_id_salt("share_log")
+                .width(200.0)
+                .selected_text(self.share_log.as_deref().map_or("Pick a log file".to_string(), file_name))
+                .show_ui(ui, |ui| {
+                    let logs = list_log_files();
+                    if logs.is_empty() {
+                        ui.label("No logs yet");
+                    }
+                    for path in logs {
+                        let name = file_name(&path);
+                        ui.selectable_value(&mut self.share_log, Some(path), name);
+                    }
+                });
+            let prepare = ui
+                .add_enabled(self.share_log.is_some(), egui::Button::new("Prepare logs for sharing"))
+                .on_hover_text("Copies the log to the clipboard with IP addresses, host names and MAC addresses replaced by placeholders like <ip-1>, the same address getting the same one throughout");
+            if let Some(path) = self.share_log.as_ref().filter(|_| prepare.clicked()) {
+                self.share_status = match fs::read_to_string(path) {
+                    Ok(text) => {
+                        let mut redactor = redact::Redactor::default();
+                        ui.ctx().copy_text(redactor.redact(&text));
+                        format!(
+                            "Copied {} lines, with {} IP addresses, {} host names and {} MAC addresses replaced",
+                            text.lines().count(),
+                            redactor.replaced("ip"),
+                            redactor.replaced("host"),
+                            redactor.replaced("mac")
+                        )
+                    }
+                    Err(e) => format!("Error: could not read {}: {}", file_name(path), e),
+                };
+            }
+        });
+        show_status_line(ui, &self.share_status);
+    }
+
+    fn show_settings_tab(&mut self, ui: &mut egui::Ui) {
+        ui.group(|ui| {
+            ui.label("Connection");
+            ui.add_space(5.0);
+            ui.horizontal(|ui| {
+                ui.label("Give up connecting after");
+                let response = ui.add(
+                    egui::DragValue::new(&mut self.connect_timeout_secs)
+                        .range(CONNECT_TIMEOUT_RANGE_SECS)
+                        .suffix(" s"),
+                );
+                if edit_finished(&response) {
+                    save_setting("connect_timeout", &self.connect_timeout_secs.to_string());
+                }
+            })
+            .response
+            .on_hover_text("How long Connect waits for the first packet from the iPhone. Internet mode waits for the phone to join instead.");
+
+            ui.horizontal(|ui| {
+                ui.label("Receive buffer");
+                let response = ui.add(
+                    egui::DragValue::new(&mut self.recv_buffer_kb)
+                        .range(socket_buffer::RANGE_KB)
+                        .speed(16)
+                        .suffix(" KB"),
+                );
+                if edit_finished(&response) {
+                    save_setting("recv_buffer_kb", &self.recv_buffer_kb.to_string());
+                }
+            })
+            .response
+            .on_hover_text("Room the OS keeps for packets BudBridge hasn't read yet. A phone waking from Wi-Fi power save sends what it held in one burst, which overflows a small buffer. The size Windows granted is in the debug log. Applies on the next connect.");
+
+            if ui
+                .checkbox(&mut self.adaptive_buffer, "Adapt the playback buffer to the network")
+                .on_hover_text(format!(
+                    "Instead of a fixed 50 ms (100 ms over a VPN), the iPhone's audio is buffered by how unevenly it arrives, {:.0} to {:.0} ms, and more after each dropout. Adds latency on bursty Wi-Fi. Applies on the next connect.",
+                    playout::MIN_MS,
+                    playout::MAX_MS
+                ))
+                .changed()
+            {
+                save_bool_setting("adaptive_buffer", self.adaptive_buffer);
+            }
+
+            let current = resample::Quality::load(&self.state.resample_quality);
+            let mut quality = current;
+            ui.horizontal(|ui| {
+                ui.label("Resampling");
+                egui::ComboBox::from_id_salt("resample_quality")
+                    .width(180.0)
+                    .selected_text(quality.label())
                     .show_ui(ui, |ui| {
-                        for (i, device) in self.input_devices.iter().enumerate() {
-                            ui.selectable_value(&mut self.selected_input, i, &device.name);
+                        for choice in resample::Quality::ALL {
+                            ui.selectable_value(&mut quality, choice, choice.label());
                         }
                     });
+            })
+            .response
+            .on_hover_text("For audio devices that don't run at 48 kHz, like 44.1 kHz interfaces: how PC audio is converted to 48 kHz, and the iPhone's audio to the device's rate. Best filters most cleanly and costs the most CPU. Applies on the next connect or device switch.");
+            if quality != current {
+                quality.store(&self.state.resample_quality);
+                save_setting("resample_quality", quality.as_str());
+            }
+
+            if ui
+                .checkbox(&mut self.taskbar_badge, "Show connection state on the taskbar button")
+                .on_hover_text("A yellow, green or red dot on BudBridge's taskbar icon, like the status light")
+                .changed()
+            {
+                save_bool_setting("taskbar_badge", self.taskbar_badge);
+            }
+            if ui
+                .checkbox(&mut self.jump_list, "Connect tasks in the taskbar jump list")
+                .on_hover_text("Right-click BudBridge's taskbar button for \"Connect to\" each saved device and \"Disconnect\"")
+                .changed()
+            {
+                save_bool_setting("jump_list", self.jump_list);
+            }
+            if ui
+                .checkbox(&mut self.tray_icon, "Icon in the notification area")
+                .on_hover_text("A dot in the status light's colors by the clock. Click it to show BudBridge; right-click to connect, disconnect or quit.")
+                .changed()
+            {
+                save_bool_setting("tray_icon", self.tray_icon);
+            }
+            ui.add_enabled_ui(self.tray_icon, |ui| {
+                if ui
+                    .checkbox(&mut self.minimize_to_tray, "Minimize to the notification area")
+                    .on_hover_text("Minimized, BudBridge leaves the taskbar and only the tray icon shows. Click the icon to bring it back.")
+                    .changed()
+                {
+                    save_bool_setting("minimize_to_tray", self.minimize_to_tray);
+                }
             });
-            ui.label("   ↳ Select your speakers with (Loopback) to stream PC audio");
 
+            ui.horizontal(|ui| {
+                ui.label("Battery saver:");
+                egui::ComboBox::from_id_salt("battery_saver")
+                    .selected_text(self.battery_saver.label())
+                    .show_ui(ui, |ui| {
+                        for mode in battery::Mode::ALL {
+                            if ui.selectable_value(&mut self.battery_saver, mode, mode.label()).changed() {
+                                save_setting("battery_saver", mode.as_str());
+                                self.power_checked = None;
+                            }
+                        }
+                    });
+                if self.saving {
+                    ui.label("saving");
+                }
+            })
+            .response
+            .on_hover_text(format!(
+                "Sends the iPhone Opus in {} ms packets, half as many, and no more than Opus stereo 128 kbps down and Opus mono 32 kbps up. The window redraws at most {} times a second and Windows may run BudBridge on slower cores. A session reconnects when it switches.",
+                battery::PTIME,
+                battery::MAX_FPS
+            ));
+
+            egui::CollapsingHeader::new("Advanced: retries").show(ui, |ui| {
+                self.show_retry_policy(ui);
+            });
+            egui::CollapsingHeader::new("Advanced: scheduling").show(ui, |ui| {
+                self.show_scheduling(ui);
+            });
+        });
+
+        ui.add_space(10.0);
+
+        ui.group(|ui| {
+            ui.label("Debug Settings");
             ui.add_space(5.0);
 
+            if ui.checkbox(&mut self.debug_logging, "Enable debug logging").changed() {
+                self.debug_logging_flag.store(self.debug_logging, Ordering::SeqCst);
+                save_debug_setting(self.debug_logging);
+            }
+
+            let current = self.log_format;
             ui.horizontal(|ui| {
-                ui.label("iPhone → PC:");
-                egui::ComboBox::from_id_salt("output_device")
-                    .width(200.0)
-                    .selected_text(
-                        self.output_devices
-                            .get(self.selected_output)
-                            .map(|d| d.name.as_str())
-                            .unwrap_or("None"),
-                    )
+                ui.label("Log format");
+                egui::ComboBox::from_id_salt("log_format")
+                    .selected_text(self.log_format.label())
                     .show_ui(ui, |ui| {
-                        for (i, device) in self.output_devices.iter().enumerate() {
-                            ui.selectable_value(&mut self.selected_output, i, &device.name);
+                        for choice in log_format::LogFormat::ALL {
+                            ui.selectable_value(&mut self.log_format, choice, choice.label());
                         }
                     });
+            })
+            .response
+            .on_hover_text("JSON lines write one object per line (ts_ms, source, message) to a .jsonl file, for log analysis tools. Applies to the next log file, which starts with the next connect.");
+            if self.log_format != current {
+                save_setting("log_format", self.log_format.as_str());
+            }
+
+            ui.horizontal(|ui| {
+                ui.label("Redraw at most");
+                let response = ui.add(egui::DragValue::new(&mut self.max_fps).range(repaint::MAX_FPS_RANGE).suffix(" fps"));
+                if response.changed() {
+                    self.state.repaint.set_max_fps(self.fps_cap());
+                }
+                if edit_finished(&response) {
+                    save_setting("max_fps", &self.max_fps.to_string());
+                }
+            })
+            .response
+            .on_hover_text("The window redraws when audio or the connection changes, up to this often. Lower it to save CPU and GPU; with nothing happening it redraws once a second.");
+
+            ui.add_space(5.0);
+            ui.label("When enabled, logs are written to:");
+            let logs_path = get_logs_path();
+            ui.label(format!("  {}", logs_path.display()));
+            ui.add_space(5.0);
+            self.show_log_sharing(ui);
+
+            ui.add_space(10.0);
+
+            if ui.button("Open Config Folder").clicked() {
+                let config_path = get_config_folder();
+                let _ = open::that(&config_path);
+            }
+        });
+
+        ui.add_space(10.0);
+
+        ui.group(|ui| {
+            ui.label("Backup and Restore");
+            ui.add_space(5.0);
+            ui.label("Saved devices, default device, settings and profiles, as one zip.");
+
+            ui.horizontal(|ui| {
+                if ui.button("Backup config").clicked() {
+                    self.backup_config();
+                }
+                if ui.small_button("Open backups folder").clicked() {
+                    let path = get_backups_path();
+                    let _ = fs::create_dir_all(&path);
+                    let _ = open::that(&path);
+                }
             });
-            ui.label("   ↳ For mic: use virtual cable (e.g., VB-Audio CABLE Input)");
 
+            let is_connected = self.state.is_connected.load(Ordering::SeqCst);
+            ui.horizontal(|ui| {
+                ui.label("Restore from:");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.restore_path)
+                        .hint_text("path to a backup .zip")
+                        .desired_width(180.0),
+                );
+                let ready = !is_connected && !self.restore_path.trim().is_empty() && self.pending_restore.is_none();
+                if ui.add_enabled(ready, egui::Button::new("Restore...")).clicked() {
+                    self.check_restore();
+                }
+            });
+            if is_connected {
+                ui.label("   ↳ Disconnect to restore a backup");
+            }
+
+            if let Some(files) = &self.pending_restore {
+                let count = |text: &str| text.lines().filter(|l| !l.trim().is_empty()).count();
+                ui.colored_label(
+                    egui::Color32::YELLOW,
+                    format!(
+                        "Replace the current config with {} device(s) and {} profile(s)? The current files are kept as .bak.",
+                        count(&files.devices),
+                        count(&files.profiles)
+                    ),
+                );
+                ui.horizontal(|ui| {
+                    if ui.button("Replace").clicked() {
+                        self.apply_restore();
+                    }
+                    if ui.button("Cancel").clicked() {
+                        self.pending_restore = None;
+                    }
+                });
+            }
+
+            show_status_line(ui, &self.backup_status);
+        });
+
+        ui.add_space(10.0);
+
+        ui.group(|ui| {
+            ui.label("Global Hotkey");
             ui.add_space(5.0);
 
+            if ui.checkbox(&mut self.hotkey_enabled, "Toggle connection to default device").changed() {
+                save_bool_setting("hotkey_enabled", self.hotkey_enabled);
+                self.register_hotkey();
+            }
+
             ui.horizontal(|ui| {
-                if !is_connected {
-                    if ui.button("Connect").clicked() {
-                        self.connect();
+                ui.label("Shortcut:");
+                ui.add_enabled(self.hotkey_enabled, egui::TextEdit::singleline(&mut self.hotkey_text).desired_width(120.0));
+                if ui.add_enabled(self.hotkey_enabled, egui::Button::new("Apply")).clicked() {
+                    save_setting("hotkey", self.hotkey_text.trim());
+                    self.register_hotkey();
+                }
+            });
+
+            show_status_line(ui, &self.hotkey_status);
+        });
+
+        ui.add_space(10.0);
+
+        ui.group(|ui| {
+            ui.label("Control API (Stream Deck, scripts)");
+            ui.add_space(5.0);
+
+            if ui.checkbox(&mut self.control_enabled, "Enable local control API").changed() {
+                save_bool_setting("control_enabled", self.control_enabled);
+                self.start_control_server();
+            }
+
+            if self.control_enabled {
+                ui.horizontal(|ui| {
+                    ui.label("Token:");
+                    ui.monospace(&self.control_token);
+                    if ui.small_button("Copy").clicked() {
+                        ui.ctx().copy_text(self.control_token.clone());
                     }
-                } else {
-                    if ui.button("Disconnect").clicked() {
-                        self.disconnect();
+                    if ui.small_button("Regenerate").clicked() {
+                        self.control_token = control::generate_token();
+                        save_setting("control_token", &self.control_token);
+                        self.start_control_server();
+                    }
+                });
+                let actions: Vec<&str> = ControlCommand::ALL.iter().map(|c| c.name()).collect();
+                ui.label(format!("Actions: {}", actions.join(", ")));
+                ui.label(format!("e.g. http://127.0.0.1:{}/action/toggle?token=…", control::CONTROL_PORT));
+            }
+
+            show_status_line(ui, &self.control_status);
+        });
+
+        ui.add_space(10.0);
+
+        ui.group(|ui| {
+            ui.label("Internet Mode");
+            ui.add_space(5.0);
+
+            ui.label("Listen from cellular without router setup. Both ends meet at a rendezvous server");
+            ui.label("(run the bundled \"rendezvous\" program on any public host), then connect directly.");
+            ui.add_space(5.0);
+
+            ui.horizontal(|ui| {
+                ui.label("Server:");
+                if ui
+                    .add(egui::TextEdit::singleline(&mut self.rendezvous_server).hint_text("host[:port]").desired_width(180.0))
+                    .lost_focus()
+                {
+                    save_setting("rendezvous_server", self.rendezvous_server.trim());
+                    if self.rendezvous_server.trim().is_empty() {
+                        self.remote_mode = false;
+                        save_bool_setting("remote_mode", false);
                     }
                 }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Session code:");
+                ui.monospace(&self.rendezvous_code);
+                if ui.small_button("Copy").clicked() {
+                    ui.ctx().copy_text(self.rendezvous_code.clone());
+                }
+                if ui.small_button("Regenerate").clicked() {
+                    self.rendezvous_code = rendezvous::generate_code();
+                    save_setting("rendezvous_code", &self.rendezvous_code);
+                }
+            });
+            ui.label("Enter the same server and code on the iPhone (PCs tab → edit the PC → Internet Mode).");
+            ui.label("Carrier networks with symmetric NAT can't be punched; audio then goes through the server,");
+            ui.label("which adds delay and uses its bandwidth.");
+        });
+
+        ui.add_space(10.0);
+
+        ui.group(|ui| {
+            ui.label("OSC Control");
+            ui.add_space(5.0);
+
+            if ui.checkbox(&mut self.osc_enabled, "Enable OSC listener").changed() {
+                save_bool_setting("osc_enabled", self.osc_enabled);
+                self.start_osc_listener();
+            }
+
+            ui.horizontal(|ui| {
+                ui.label("UDP port:");
+                ui.add_enabled(self.osc_enabled, egui::TextEdit::singleline(&mut self.osc_port).desired_width(60.0));
+                if ui.add_enabled(self.osc_enabled, egui::Button::new("Apply")).clicked() {
+                    save_setting("osc_port", self.osc_port.trim());
+                    self.start_osc_listener();
+                }
+            });
+
+            if self.osc_enabled {
+                ui.label("/budbridge/connect, /disconnect, /toggle, /cycle");
+                ui.label("/budbridge/mute/mic, /mute/out, /gain/out <0-2>, /gain/mic <0-2>");
+            }
+
+            show_status_line(ui, &self.osc_status);
+        });
+
+        ui.add_space(10.0);
+
+        ui.group(|ui| {
+            ui.label("Discovery");
+            ui.add_space(5.0);
+
+            if ui
+                .checkbox(&mut self.announce_enabled, "Let iPhones on this network find this PC")
+                .on_hover_text("Announces BudBridge over Bonjour (mDNS), so the iPhone app lists this PC under \"On This Network\"")
+                .changed()
+            {
+                save_bool_setting("announce_enabled", self.announce_enabled);
+                self.start_announcer();
+            }
+
+            ui.horizontal(|ui| {
+                ui.label("Name:");
+                ui.add_enabled(self.announce_enabled, egui::TextEdit::singleline(&mut self.announce_name).desired_width(160.0));
+                let valid = !self.announce_name.trim().is_empty();
+                if ui.add_enabled(self.announce_enabled && valid, egui::Button::new("Apply")).clicked() {
+                    save_setting("announce_name", self.announce_name.trim());
+                    self.start_announcer();
+                }
+            });
+
+            show_status_line(ui, &self.announce_status);
+        });
+
+        ui.add_space(10.0);
+
+        ui.group(|ui| {
+            ui.label("Learned Network Settings");
+            ui.add_space(5.0);
+
+            if ui
+                .checkbox(&mut self.learn_networks, "Learn the buffer and format that work on each network")
+                .on_hover_text(format!(
+                    "After {} sessions of a minute or more on the same Wi-Fi or subnet, its playback buffer and PC → iPhone format are used automatically",
+                    learned::LEARN_AFTER
+                ))
+                .changed()
+            {
+                save_bool_setting("learn_networks", self.learn_networks);
+            }
+
+            if self.learned.is_empty() {
+                ui.label("Nothing learned yet.");
+            }
+            for entry in &self.learned {
+                let state = if entry.ready() { "in use" } else { "learning" };
+                ui.label(format!(
+                    "{}: {} ({} session{}, {})",
+                    entry.network,
+                    entry.label(),
+                    entry.sessions,
+                    if entry.sessions == 1 { "" } else { "s" },
+                    state
+                ));
+            }
+            if ui.add_enabled(!self.learned.is_empty(), egui::Button::new("Reset learned values")).clicked() {
+                self.learned.clear();
+                save_learned(&self.learned);
+            }
+
+            show_status_line(ui, &self.learned_note);
+        });
+
+        ui.add_space(10.0);
+
+        ui.group(|ui| {
+            ui.label("RTSP Server");
+            ui.add_space(5.0);
 
-                if ui.button("Refresh").clicked() {
-                    self.refresh_devices();
+            if ui.checkbox(&mut self.rtsp_enabled, "Serve PC audio over RTSP (VLC, smart TVs)").changed() {
+                save_bool_setting("rtsp_enabled", self.rtsp_enabled);
+                self.start_rtsp_server();
+            }
+
+            ui.horizontal(|ui| {
+                ui.label("TCP port:");
+                ui.add_enabled(self.rtsp_enabled, egui::TextEdit::singleline(&mut self.rtsp_port).desired_width(60.0));
+                if ui.add_enabled(self.rtsp_enabled, egui::Button::new("Apply")).clicked() {
+                    save_setting("rtsp_port", self.rtsp_port.trim());
+                    self.start_rtsp_server();
                 }
             });
+
+            if let Some(clients) = self.rtsp_server.as_ref().map(|s| s.feed().client_count()) {
+                let url = self.rtsp_url();
+                ui.horizontal(|ui| {
+                    ui.monospace(&url);
+                    if ui.small_button("Copy").clicked() {
+                        ui.ctx().copy_text(url.clone());
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label(format!("{} client(s) playing", clients));
+                    self.show_capture_only_button(ui);
+                });
+            }
+
+            show_status_line(ui, &self.rtsp_status);
         });
 
         ui.add_space(10.0);
 
         ui.group(|ui| {
-            ui.label("Diagnostics");
+            ui.label("AES67 Output (experimental)");
             ui.add_space(5.0);
 
-            let status = self.state.status_message.lock().clone();
-            let status_color = if is_connected {
-                egui::Color32::GREEN
-            } else if status.starts_with("Error") {
-                egui::Color32::RED
-            } else {
-                egui::Color32::GRAY
-            };
+            if ui.checkbox(&mut self.aes67_enabled, "Multicast PC audio as an AES67 stream (L24/48k mono)").changed() {
+                save_bool_setting("aes67_enabled", self.aes67_enabled);
+                self.start_aes67_sender();
+            }
 
             ui.horizontal(|ui| {
-                ui.label("Status:");
-                ui.colored_label(status_color, &status);
+                ui.label("Group:");
+                ui.add_enabled(self.aes67_enabled, egui::TextEdit::singleline(&mut self.aes67_group).desired_width(110.0));
+                ui.label("Port:");
+                ui.add_enabled(self.aes67_enabled, egui::TextEdit::singleline(&mut self.aes67_port).desired_width(50.0));
+                if ui.add_enabled(self.aes67_enabled, egui::Button::new("Apply")).clicked() {
+                    save_setting("aes67_group", self.aes67_group.trim());
+                    save_setting("aes67_port", self.aes67_port.trim());
+                    self.start_aes67_sender();
+                }
             });
 
-            ui.add_space(5.0);
-
-            let sent = self.state.packets_sent.load(Ordering::Relaxed);
-            let recv = self.state.packets_recv.load(Ordering::Relaxed);
-            let recv_audio = self.state.packets_recv_with_audio.load(Ordering::Relaxed);
-            let sent_audio = self.state.packets_sent_with_audio.load(Ordering::Relaxed);
-            let callbacks = self.state.audio_callbacks.load(Ordering::Relaxed);
-
-            let last_sent = self.state.last_packets_sent.swap(sent, Ordering::Relaxed);
-            let last_recv = self.state.last_packets_recv.swap(recv, Ordering::Relaxed);
-
-            let sent_rate = (sent - last_sent) * 2;
-            let recv_rate = (recv - last_recv) * 2;
+            if self.aes67_sender.is_some() {
+                ui.label("Shows up as \"BudBridge PC Audio\" in Dante Controller (AES67 mode) and other SAP browsers.");
+                ui.label("No PTP: timing follows this PC's clock, so PTP-locked receivers may drift and resync.");
+                self.show_capture_only_button(ui);
+            }
 
-            ui.label(format!("Packets Sent: {} (+{}/s)", sent, sent_rate));
-            ui.label(format!(
-                "Sent with Audio: {} / {} ({:.0}%)",
-                sent_audio,
-                sent,
-                if sent > 0 { sent_audio as f64 / sent as f64 * 100.0 } else { 0.0 }
-            ));
-            ui.label(format!("Packets Received: {} (+{}/s)", recv, recv_rate));
-            ui.label(format!(
-                "Recv with Audio: {} / {} ({:.0}%)",
-                recv_audio,
-                recv,
-                if recv > 0 { recv_audio as f64 / recv as f64 * 100.0 } else { 0.0 }
-            ));
-            ui.label(format!("Audio Callbacks: {}", callbacks));
+            show_status_line(ui, &self.aes67_status);
         });
-    }
 
-    fn show_devices_tab(&mut self, ui: &mut egui::Ui) {
+        ui.add_space(10.0);
+
         ui.group(|ui| {
-            ui.label("Add New Device");
+            ui.label("Classroom Multicast");
             ui.add_space(5.0);
 
-            ui.horizontal(|ui| {
-                ui.label("Name:");
-                ui.text_edit_singleline(&mut self.new_device_name);
-            });
+            if ui
+                .checkbox(&mut self.multicast_enabled, "Send PC audio to a multicast group for many phones")
+                .changed()
+            {
+                save_bool_setting("multicast_enabled", self.multicast_enabled);
+                self.start_multicast_sender();
+            }
 
-            ui.horizontal(|ui| {
-                ui.label("IP:");
-                ui.text_edit_singleline(&mut self.new_device_ip);
+            ui.add_enabled_ui(self.multicast_enabled, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Group:");
+                    ui.add(egui::TextEdit::singleline(&mut self.multicast_group).desired_width(110.0));
+                    ui.label("Port:");
+                    ui.add(egui::TextEdit::singleline(&mut self.multicast_port).desired_width(50.0));
+                    ui.label("TTL:");
+                    ui.add(egui::DragValue::new(&mut self.multicast_ttl).range(multicast::TTL_RANGE))
+                        .on_hover_text("Router hops the stream may cross. 1 keeps it on this subnet.");
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Format:");
+                    show_format_choice(ui, "multicast_format", &mut self.multicast_format, &StreamFormat::DOWN_CHOICES);
+                    if ui.button("Apply").clicked() {
+                        save_setting("multicast_group", self.multicast_group.trim());
+                        save_setting("multicast_port", self.multicast_port.trim());
+                        save_setting("multicast_ttl", &self.multicast_ttl.to_string());
+                        save_setting("multicast_format", &self.multicast_format.to_string());
+                        self.start_multicast_sender();
+                    }
+                });
             });
 
+            if self.multicast_enabled {
+                ui.label(format!(
+                    "About {} kbps on the air, the same for one phone or thirty. On each phone, enter the group in the PC's settings under Classroom Stream.",
+                    multicast::network_kbps(self.multicast_format)
+                ));
+                ui.colored_label(
+                    egui::Color32::YELLOW,
+                    "⚠ Wi-Fi sends multicast at its slowest rate, to every device at once, with no retries. Expect dropouts with uncompressed formats or a busy network. Prefer Opus and 5 GHz, and turn on multicast-to-unicast (multicast enhancement) on the access point if it has it.",
+                );
+            }
+            if self.multicast_sender.is_some() {
+                self.show_capture_only_button(ui);
+            }
+
+            show_status_line(ui, &self.multicast_status);
+        });
+
+        ui.add_space(10.0);
+
+        ui.group(|ui| {
+            ui.label("MIDI Control");
             ui.add_space(5.0);
 
-            if ui.button("Add Device").clicked() {
-                if !self.new_device_name.is_empty() && !self.new_device_ip.is_empty() {
-                    let is_first = self.saved_devices.is_empty();
-                    self.saved_devices.push(SavedDevice {
-                        name: self.new_device_name.clone(),
-                        ip: self.new_device_ip.clone(),
-                    });
-                    save_devices(&self.saved_devices);
+            if ui.checkbox(&mut self.midi_enabled, "Enable MIDI control").changed() {
+                save_bool_setting("midi_enabled", self.midi_enabled);
+                self.start_midi_listener();
+            }
 
-                    if is_first {
-                        self.default_device = Some(0);
-                        self.selected_device = Some(0);
-                        self.iphone_ip = self.new_device_ip.clone();
-                        save_default_device(&self.saved_devices, Some(0));
+            ui.add_enabled_ui(self.midi_enabled, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Port:");
+                    let selected = if self.midi_port.is_empty() { "Select a port..." } else { self.midi_port.as_str() };
+                    let mut new_port: Option<String> = None;
+                    egui::ComboBox::from_id_salt("midi_port")
+                        .width(180.0)
+                        .selected_text(selected)
+                        .show_ui(ui, |ui| {
+                            for port in &self.midi_ports {
+                                if ui.selectable_label(*port == self.midi_port, port).clicked() {
+                                    new_port = Some(port.clone());
+                                }
+                            }
+                        });
+                    if ui.button("Refresh").clicked() {
+                        self.midi_ports = midi::list_ports();
                     }
+                    if let Some(port) = new_port {
+                        self.midi_port = port;
+                        save_setting("midi_port", &self.midi_port);
+                        self.start_midi_listener();
+                    }
+                });
 
-                    self.new_device_name.clear();
-                    self.new_device_ip.clear();
+                let mapping = *self.midi_mapping.lock();
+                let learning = *self.midi_learn.lock();
+                let rows = [
+                    ("Gain (CC):", midi::LearnTarget::Gain, mapping.gain_cc, "Move a fader/knob..."),
+                    ("Mute (note):", midi::LearnTarget::Mute, mapping.mute_note, "Press a pad/key..."),
+                ];
+                for (label, target, binding, prompt) in rows {
+                    ui.horizontal(|ui| {
+                        ui.label(label);
+                        if learning == Some(target) {
+                            ui.colored_label(egui::Color32::YELLOW, prompt);
+                            if ui.small_button("Cancel").clicked() {
+                                *self.midi_learn.lock() = None;
+                            }
+                        } else {
+                            ui.label(midi::format_binding(binding));
+                            if ui.small_button("Learn").clicked() {
+                                *self.midi_learn.lock() = Some(target);
+                            }
+                            if binding.is_some() && ui.small_button("Clear").clicked() {
+                                let mut mapping = self.midi_mapping.lock();
+                                match target {
+                                    midi::LearnTarget::Gain => mapping.gain_cc = None,
+                                    midi::LearnTarget::Mute => mapping.mute_note = None,
+                                }
+                            }
+                        }
+                    });
                 }
-            }
+                ui.label("   ↳ CC controls PC → iPhone gain, note toggles its mute");
+            });
+
+            show_status_line(ui, &self.midi_status);
         });
 
         ui.add_space(10.0);
 
         ui.group(|ui| {
-            ui.label("Saved Devices");
+            ui.label("Telemetry (opt-in)");
             ui.add_space(5.0);
+            ui.label("Anonymous per-session statistics that help decide what to work on:");
+            ui.label("session length, average loss, codecs, link type, app and Windows version.");
 
-            if self.saved_devices.is_empty() {
-                ui.label("No devices saved yet.");
-            } else {
-                let mut to_delete: Option<usize> = None;
-                let mut new_default: Option<Option<usize>> = None;
+            let before = self.telemetry_mode;
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.telemetry_mode, telemetry::Mode::Off, "Off");
+                ui.radio_value(&mut self.telemetry_mode, telemetry::Mode::Local, "Save to disk only");
+                ui.radio_value(&mut self.telemetry_mode, telemetry::Mode::Send, "Send");
+            });
+            if self.telemetry_mode != before {
+                save_setting("telemetry", self.telemetry_mode.as_str());
+            }
 
-                for (i, device) in self.saved_devices.iter().enumerate() {
+            match self.telemetry_mode {
+                telemetry::Mode::Off => {}
+                telemetry::Mode::Local => {
                     ui.horizontal(|ui| {
-                        let is_default = self.default_device == Some(i);
-                        if ui.radio(is_default, "").clicked() {
-                            new_default = Some(Some(i));
-                        }
-                        ui.label(format!("{} - {}", device.name, device.ip));
-                        if is_default {
-                            ui.label("(default)");
+                        ui.label("Reports are written to the telemetry folder, nothing leaves this PC.");
+                        if ui.small_button("Open folder").clicked() {
+                            let path = get_telemetry_path();
+                            let _ = fs::create_dir_all(&path);
+                            let _ = open::that(&path);
                         }
-                        if ui.button("Delete").clicked() {
-                            to_delete = Some(i);
+                    });
+                }
+                telemetry::Mode::Send => {
+                    ui.horizontal(|ui| {
+                        ui.label("Collector:");
+                        let response = ui.add(
+                            egui::TextEdit::singleline(&mut self.telemetry_url)
+                                .hint_text("http://host:port/path")
+                                .desired_width(220.0),
+                        );
+                        if response.lost_focus() {
+                            save_setting("telemetry_url", self.telemetry_url.trim());
                         }
                     });
                 }
+            }
+            show_status_line(ui, &self.telemetry_status.lock().clone());
 
-                if let Some(new_def) = new_default {
-                    self.default_device = new_def;
-                    save_default_device(&self.saved_devices, self.default_device);
+            egui::CollapsingHeader::new("Preview what would be sent").show(ui, |ui| match self.telemetry_preview() {
+                Some(json) => {
+                    ui.monospace(json);
                 }
-
-                if let Some(idx) = to_delete {
-                    self.saved_devices.remove(idx);
-                    save_devices(&self.saved_devices);
-
-                    if self.selected_device == Some(idx) {
-                        self.selected_device = None;
-                        self.iphone_ip.clear();
-                    } else if let Some(sel) = self.selected_device {
-                        if sel > idx {
-                            self.selected_device = Some(sel - 1);
-                        }
-                    }
-
-                    if self.default_device == Some(idx) {
-                        self.default_device = None;
-                        save_default_device(&self.saved_devices, None);
-                    } else if let Some(def) = self.default_device {
-                        if def > idx {
-                            self.default_device = Some(def - 1);
-                            save_default_device(&self.saved_devices, self.default_device);
-                        }
-                    }
-
-                    if self.saved_devices.len() == 1 && self.default_device.is_none() {
-                        self.default_device = Some(0);
-                        save_default_device(&self.saved_devices, Some(0));
-                    }
+                None => {
+                    ui.label("Connect once to see a report; one is made when each session ends.");
                 }
-            }
+            });
         });
 
         ui.add_space(10.0);
 
         ui.group(|ui| {
-            ui.label("Tips");
+            ui.label("About");
             ui.add_space(5.0);
-            ui.label("• Find your iPhone's IP in Settings > Wi-Fi > (i)");
-            ui.label("• Make sure both devices are on the same network");
+            ui.label("BudBridge - Stream PC audio to iOS");
+            ui.label(format!("Sample rate: {} Hz", TARGET_SAMPLE_RATE));
+            ui.label(format!("Send port: {}", SEND_PORT));
+            ui.label(format!("Receive port: {}", RECEIVE_PORT));
         });
     }
+}
 
-    fn show_settings_tab(&mut self, ui: &mut egui::Ui) {
-        ui.group(|ui| {
-            ui.label("Debug Settings");
-            ui.add_space(5.0);
+/// Grey status text, red when it starts with "Error"
+fn show_status_line(ui: &mut egui::Ui, status: &str) {
+    if status.is_empty() {
+        return;
+    }
+    let color = if status.starts_with("Error") {
+        egui::Color32::RED
+    } else {
+        egui::Color32::GRAY
+    };
+    ui.colored_label(color, status);
+}
 
-            if ui.checkbox(&mut self.debug_logging, "Enable debug logging").changed() {
-                self.debug_logging_flag.store(self.debug_logging, Ordering::SeqCst);
-                save_debug_setting(self.debug_logging);
+/// Horizontal bar per interval bucket, scaled to the fullest bucket
+/// A preset, and for Opus any bitrate besides
+fn show_format_choice(ui: &mut egui::Ui, id: &str, format: &mut StreamFormat, choices: &[StreamFormat]) {
+    egui::ComboBox::from_id_salt(id)
+        .width(200.0)
+        .selected_text(format.label())
+        .show_ui(ui, |ui| {
+            for choice in choices {
+                ui.selectable_value(format, *choice, choice.label());
             }
+        });
+    if format.codec == protocol::Codec::Opus {
+        ui.add(egui::DragValue::new(&mut format.bitrate_kbps).range(protocol::OPUS_KBPS).suffix(" kbps"))
+            .on_hover_text("64 kbps is plenty for voice; 128 to 256 for music. Lower holds up better on a busy Wi-Fi.");
+    }
+}
 
-            ui.add_space(5.0);
-            ui.label("When enabled, logs are written to:");
-            let logs_path = get_logs_path();
-            ui.label(format!("  {}", logs_path.display()));
+/// Where the device at `index` in `old` is in `new`. Names can repeat (two of the
+/// same headset), so the n-th device of a name matches the n-th again, or the
+/// first of that name if fewer are left.
+fn rematch_device(old: &[AudioDeviceInfo], index: usize, new: &[AudioDeviceInfo]) -> Option<usize> {
+    let device = old.get(index)?;
+    let same_name = |d: &AudioDeviceInfo| d.is_output == device.is_output && d.id.name == device.id.name;
+    new.iter()
+        .position(|d| same_name(d) && d.id.nth == device.id.nth)
+        .or_else(|| new.iter().position(same_name))
+}
 
-            ui.add_space(10.0);
+/// A clip light and the held peak; clicking clears both
+fn show_signal_level(ui: &mut egui::Ui, title: &str, level_db: Option<f32>, threshold_db: f32) {
+    match level_db {
+        None => ui.label(format!("{}: no audio yet", title)),
+        Some(level) if level <= signal::FLOOR_DB => ui.label(format!("{}: digital silence", title)),
+        Some(level) => {
+            let verdict = if level >= threshold_db { "signal" } else { "silence" };
+            ui.label(format!("{}: {:.1} dBFS RMS ({})", title, level, verdict))
+        }
+    };
+}
 
-            if ui.button("Open Config Folder").clicked() {
-                let config_path = get_config_folder();
-                let _ = open::that(&config_path);
-            }
-        });
+fn show_peak_hold(ui: &mut egui::Ui, hold: &clip::PeakHold) {
+    let peak = hold.peak();
+    let (color, text) = if hold.clipped() {
+        (egui::Color32::RED, "● CLIP".to_string())
+    } else if peak > 0.0 {
+        (egui::Color32::DARK_GRAY, format!("● {:.1} dB", 20.0 * peak.log10()))
+    } else {
+        (egui::Color32::DARK_GRAY, "● -∞ dB".to_string())
+    };
+    let light = ui.add(egui::Label::new(egui::RichText::new(text).color(color).small()).sense(egui::Sense::click()));
+    if light.on_hover_text("Highest peak since cleared (dBFS); red once anything reached full scale. Click to clear.").clicked() {
+        hold.clear();
+    }
+}
 
-        ui.add_space(10.0);
+/// A dB slider over a gain the audio callbacks read, saved under `key` as it moves
+fn show_gain_slider(ui: &mut egui::Ui, label: &str, shared: &gain::AtomicGain, key: &str) {
+    let mut db = gain::to_db(shared.get());
+    let slider = egui::Slider::new(&mut db, gain::RANGE_DB).step_by(0.5).suffix(" dB").text(label);
+    let response = ui.add(slider).on_hover_text("Applies straight away. Above 0 dB, watch the peak light for clipping.");
+    if response.changed() {
+        shared.set(gain::to_linear(db));
+    }
+    if edit_finished(&response) {
+        save_setting(key, &format!("{:.1}", db));
+    }
+}
 
-        ui.group(|ui| {
-            ui.label("About");
-            ui.add_space(5.0);
-            ui.label("BudBridge - Stream PC audio to iOS");
-            ui.label(format!("Sample rate: {} Hz", TARGET_SAMPLE_RATE));
-            ui.label(format!("Send port: {}", SEND_PORT));
-            ui.label(format!("Receive port: {}", RECEIVE_PORT));
+/// A drag, click or keyboard edit of a slider or DragValue just ended. Settings
+/// are saved then rather than on every `changed()` frame of a drag, since each
+/// save rewrites settings.txt and its .bak.
+fn edit_finished(response: &egui::Response) -> bool {
+    response.drag_stopped() || response.clicked() || response.lost_focus()
+}
+
+/// A level meter: the RMS bar over the lighter peak bar, and a tick at the held peak
+fn show_meter(ui: &mut egui::Ui, label: &str, bars: &meter::Ballistics) {
+    let x_of = |rect: egui::Rect, db: f32| rect.left() + rect.width() * (1.0 - db / meter::FLOOR_DB).clamp(0.0, 1.0);
+    let color = |db: f32| match db {
+        db if db >= -1.0 => egui::Color32::RED,
+        db if db >= -12.0 => egui::Color32::YELLOW,
+        _ => egui::Color32::GREEN,
+    };
+    ui.horizontal(|ui| {
+        ui.add_sized([80.0, 14.0], egui::Label::new(egui::RichText::new(label).small()));
+        let (rect, response) = ui.allocate_exact_size(egui::vec2(200.0, 10.0), egui::Sense::hover());
+        let bar = |db: f32| egui::Rect::from_min_max(rect.min, egui::pos2(x_of(rect, db), rect.max.y));
+        let painter = ui.painter();
+        painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+        painter.rect_filled(bar(bars.peak_db), 0.0, color(bars.peak_db).gamma_multiply(0.4));
+        painter.rect_filled(bar(bars.rms_db), 0.0, color(bars.rms_db));
+        if bars.hold_db > meter::FLOOR_DB {
+            painter.vline(x_of(rect, bars.hold_db), rect.y_range(), egui::Stroke::new(2.0, color(bars.hold_db)));
+        }
+        response.on_hover_text(format!(
+            "{:.0} to 0 dBFS. The solid bar is the RMS level, the lighter one the peak; the tick holds the highest peak for a moment.",
+            meter::FLOOR_DB
+        ));
+        let reading = |db: f32| if db > meter::FLOOR_DB { format!("{:.1}", db) } else { "-∞".to_string() };
+        ui.small(format!("peak {} / RMS {} dB", reading(bars.peak_db), reading(bars.rms_db)));
+    });
+}
+
+fn show_interval_histogram(ui: &mut egui::Ui, title: &str, counts: &[u32], max_ms: f32) {
+    let total: u32 = counts.iter().sum();
+    ui.label(format!("{}: {} samples, worst {:.0} ms", title, total, max_ms));
+    let peak = counts.iter().copied().max().unwrap_or(0).max(1);
+
+    let mut lower = 0.0;
+    for (&count, &upper) in counts.iter().zip(INTERVAL_BUCKETS_MS.iter()) {
+        let label = if upper.is_finite() {
+            format!("{:>3.0}-{:.0} ms", lower, upper)
+        } else {
+            format!("> {:.0} ms", lower)
+        };
+        lower = upper;
+
+        ui.horizontal(|ui| {
+            ui.add_sized([80.0, 14.0], egui::Label::new(egui::RichText::new(label).small().monospace()));
+            let (rect, _) = ui.allocate_exact_size(egui::vec2(160.0, 10.0), egui::Sense::hover());
+            let fill = rect.width() * count as f32 / peak as f32;
+            let bar = egui::Rect::from_min_size(rect.min, egui::vec2(fill, rect.height()));
+            ui.painter().rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+            ui.painter().rect_filled(bar, 0.0, egui::Color32::from_rgb(90, 160, 230));
+            ui.small(count.to_string());
         });
     }
 }
@@ -621,88 +4818,347 @@ fn get_devices_path() -> PathBuf {
     get_config_folder().join(DEVICES_FILE)
 }
 
-fn get_default_device_path() -> PathBuf {
-    get_config_folder().join(DEFAULT_DEVICE_FILE)
+fn get_default_device_path() -> PathBuf {
+    get_config_folder().join(DEFAULT_DEVICE_FILE)
+}
+
+fn get_settings_path() -> PathBuf {
+    get_config_folder().join(SETTINGS_FILE)
+}
+
+// Upgrade the config files to migrate::CURRENT_VERSION before anything reads them.
+// config::save keeps each file's pre-migration contents as its .bak.
+fn migrate_config() {
+    let original = read_config_files();
+    let mut files = original.clone();
+    match migrate::upgrade(&mut files) {
+        migrate::Outcome::UpToDate => {}
+        migrate::Outcome::TooNew(version) => config::warn(format!(
+            "These settings are from a newer BudBridge (config version {}, this one knows {}); some may be ignored",
+            version,
+            migrate::CURRENT_VERSION
+        )),
+        migrate::Outcome::Upgraded(steps) => {
+            write_config_files(&original, &files);
+            // The window has no console, so this goes where load problems do
+            config::warn(format!("Settings upgraded from an older BudBridge: {}", steps.join("; ")));
+        }
+    }
+}
+
+fn read_config_files() -> migrate::ConfigFiles {
+    let read = |path: PathBuf| fs::read_to_string(path).unwrap_or_default();
+    migrate::ConfigFiles {
+        devices: read(get_devices_path()),
+        default_device: read(get_default_device_path()),
+        settings: read(get_settings_path()),
+        profiles: read(get_profiles_path()),
+    }
+}
+
+/// Save the files that differ from `current`
+fn write_config_files(current: &migrate::ConfigFiles, files: &migrate::ConfigFiles) {
+    let _ = ensure_config_dirs();
+    let changed = [
+        (get_devices_path(), &current.devices, &files.devices),
+        (get_default_device_path(), &current.default_device, &files.default_device),
+        (get_settings_path(), &current.settings, &files.settings),
+        (get_profiles_path(), &current.profiles, &files.profiles),
+    ];
+    for (path, before, after) in changed {
+        if before != after {
+            config::save(&path, after);
+        }
+    }
+}
+
+// devices.txt: name|ip|id (escaped, see config::join_fields). Files from before
+// ids existed have no id column; those devices get one and the file is rewritten.
+fn load_saved_devices() -> Vec<SavedDevice> {
+    let mut missing_ids = false;
+    let devices: Vec<SavedDevice> = config::load(&get_devices_path(), |content| {
+        let mut bad_lines = 0;
+        let devices = content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| match config::split_fields(line).as_slice() {
+                [name, ip, id, rest @ ..] if rest.len() <= 1 => Some(SavedDevice {
+                    id: id.clone(),
+                    name: name.clone(),
+                    ip: ip.clone(),
+                    key: rest.first().and_then(|key| protocol::parse_hex(key)),
+                }),
+                [name, ip] => {
+                    missing_ids = true;
+                    Some(SavedDevice::new(name.clone(), ip.clone()))
+                }
+                _ => {
+                    bad_lines += 1;
+                    None
+                }
+            })
+            .collect();
+        (devices, bad_lines)
+    });
+    if missing_ids {
+        save_devices(&devices);
+    }
+    devices
+}
+
+fn save_devices(devices: &[SavedDevice]) {
+    let _ = ensure_config_dirs();
+    let content: String = devices
+        .iter()
+        .map(|d| match &d.key {
+            Some(key) => config::join_fields(&[&d.name, &d.ip, &d.id, &protocol::to_hex(key)]),
+            None => config::join_fields(&[&d.name, &d.ip, &d.id]),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    config::save(&get_devices_path(), &content);
+}
+
+// default.txt holds the default device's id (older versions stored its name)
+fn load_default_device(devices: &[SavedDevice]) -> Option<usize> {
+    let path = get_default_device_path();
+    let stored = fs::read_to_string(&path).ok()?.trim().to_string();
+    if let Some(idx) = devices.iter().position(|d| d.id == stored) {
+        return Some(idx);
+    }
+    let idx = devices.iter().position(|d| d.name == stored)?;
+    save_default_device(devices, Some(idx));
+    Some(idx)
+}
+
+fn save_default_device(devices: &[SavedDevice], index: Option<usize>) {
+    let _ = ensure_config_dirs();
+    let path = get_default_device_path();
+    if let Some(idx) = index {
+        if let Some(device) = devices.get(idx) {
+            config::save(&path, &device.id);
+            return;
+        }
+    }
+    let _ = fs::remove_file(&path);
+}
+
+fn get_telemetry_path() -> PathBuf {
+    get_config_folder().join("telemetry")
+}
+
+fn get_backups_path() -> PathBuf {
+    get_config_folder().join("backups")
+}
+
+fn get_profiles_path() -> PathBuf {
+    get_config_folder().join(PROFILES_FILE)
+}
+
+fn get_networks_path() -> PathBuf {
+    get_config_folder().join(NETWORKS_FILE)
+}
+
+fn load_learned() -> Vec<learned::Learned> {
+    config::load(&get_networks_path(), learned::parse)
 }
 
-fn get_settings_path() -> PathBuf {
-    get_config_folder().join(SETTINGS_FILE)
+fn save_learned(learned: &[learned::Learned]) {
+    let _ = ensure_config_dirs();
+    config::save(&get_networks_path(), &learned::to_text(learned));
 }
 
-fn load_saved_devices() -> Vec<SavedDevice> {
-    let path = get_devices_path();
-    fs::read_to_string(&path)
-        .ok()
-        .map(|content| {
-            content
-                .lines()
-                .filter_map(|line| {
-                    let parts: Vec<&str> = line.splitn(2, '|').collect();
-                    if parts.len() == 2 {
-                        Some(SavedDevice {
-                            name: parts[0].to_string(),
-                            ip: parts[1].to_string(),
-                        })
-                    } else {
-                        None
+// profiles.txt: name|target_id|capture|playback|gain_out|gain_mic|mute_out|mute_mic|down|up
+// (escaped, see config::join_fields). Older files named the target device instead,
+// and had no codec fields.
+fn load_profiles(devices: &[SavedDevice]) -> Vec<Profile> {
+    let mut migrated = false;
+    let profiles: Vec<Profile> = config::load(&get_profiles_path(), |content| {
+        let mut bad_lines = 0;
+        let profiles = content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| {
+                let parts = config::split_fields(line);
+                if parts.len() != 8 && parts.len() != 10 {
+                    bad_lines += 1;
+                    return None;
+                }
+                let mut target_device_id = parts[1].clone();
+                if !devices.iter().any(|d| d.id == target_device_id) {
+                    if let Some(device) = devices.iter().find(|d| d.name == target_device_id) {
+                        target_device_id = device.id.clone();
+                        migrated = true;
                     }
+                }
+                Some(Profile {
+                    name: parts[0].clone(),
+                    target_device_id,
+                    capture_device: parts[2].clone(),
+                    playback_device: parts[3].clone(),
+                    gain_out: parts[4].parse().unwrap_or(1.0),
+                    gain_mic: parts[5].parse().unwrap_or(1.0),
+                    mute_out: parts[6] == "true",
+                    mute_mic: parts[7] == "true",
+                    formats: Formats {
+                        down: parts.get(8).and_then(|v| StreamFormat::parse(v)).unwrap_or(StreamFormat::PCM_MONO),
+                        up: parts.get(9).and_then(|v| StreamFormat::parse(v)).unwrap_or(StreamFormat::PCM_MONO),
+                    },
                 })
-                .collect()
-        })
-        .unwrap_or_default()
+            })
+            .collect();
+        (profiles, bad_lines)
+    });
+    if migrated {
+        save_profiles(&profiles);
+    }
+    profiles
 }
 
-fn save_devices(devices: &[SavedDevice]) {
+fn save_profiles(profiles: &[Profile]) {
     let _ = ensure_config_dirs();
-    let path = get_devices_path();
-    let content: String = devices
+    let content: String = profiles
         .iter()
-        .map(|d| format!("{}|{}", d.name, d.ip))
+        .map(|p| {
+            config::join_fields(&[
+                &p.name,
+                &p.target_device_id,
+                &p.capture_device,
+                &p.playback_device,
+                &p.gain_out.to_string(),
+                &p.gain_mic.to_string(),
+                &p.mute_out.to_string(),
+                &p.mute_mic.to_string(),
+                &p.formats.down.to_string(),
+                &p.formats.up.to_string(),
+            ])
+        })
         .collect::<Vec<_>>()
         .join("\n");
-    let _ = fs::write(&path, content);
+    config::save(&get_profiles_path(), &content);
 }
 
-fn load_default_device(devices: &[SavedDevice]) -> Option<usize> {
-    let path = get_default_device_path();
-    let default_name = fs::read_to_string(&path).ok()?.trim().to_string();
-    devices.iter().position(|d| d.name == default_name)
+// settings.txt holds one key=value pair per line
+fn load_settings() -> Vec<(String, String)> {
+    config::load(&get_settings_path(), |content| {
+        let mut bad_lines = 0;
+        let settings = content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| {
+                let pair = line.split_once('=');
+                if pair.is_none() {
+                    bad_lines += 1;
+                }
+                pair
+            })
+            .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+            .collect();
+        (settings, bad_lines)
+    })
 }
 
-fn save_default_device(devices: &[SavedDevice], index: Option<usize>) {
+fn load_setting(key: &str) -> Option<String> {
+    load_settings().into_iter().find(|(k, _)| k == key).map(|(_, v)| v)
+}
+
+fn save_setting(key: &str, value: &str) {
     let _ = ensure_config_dirs();
-    let path = get_default_device_path();
-    if let Some(idx) = index {
-        if let Some(device) = devices.get(idx) {
-            let _ = fs::write(&path, &device.name);
-            return;
-        }
+    let mut settings = load_settings();
+    match settings.iter_mut().find(|(k, _)| k == key) {
+        Some(entry) => entry.1 = value.to_string(),
+        None => settings.push((key.to_string(), value.to_string())),
     }
-    let _ = fs::remove_file(&path);
+    let content: String = settings
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("\n");
+    config::save(&get_settings_path(), &content);
+}
+
+fn load_bool_setting(key: &str) -> bool {
+    load_setting(key).map(|v| v == "true").unwrap_or(false)
+}
+
+fn save_bool_setting(key: &str, enabled: bool) {
+    save_setting(key, if enabled { "true" } else { "false" });
+}
+
+fn load_dsp_settings() -> dsp::Settings {
+    dsp::Settings {
+        loudness_target: load_setting("loudness_target").and_then(|v| v.parse().ok()),
+        night_mode: load_bool_setting("night_mode"),
+        crossfeed: load_setting("crossfeed").and_then(|v| v.parse().ok()).unwrap_or(0.0),
+    }
+}
+
+fn save_dsp_settings(settings: &dsp::Settings) {
+    save_setting("loudness_target", &settings.loudness_target.map_or("off".to_string(), |t| t.to_string()));
+    save_bool_setting("night_mode", settings.night_mode);
+    save_setting("crossfeed", &settings.crossfeed.to_string());
+}
+
+fn load_retry_policy() -> retry::RetryPolicy {
+    let defaults = retry::RetryPolicy::default();
+    let secs = |key: &str, default: u32| {
+        load_setting(key)
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default)
+            .clamp(*retry::DELAY_RANGE_SECS.start(), *retry::DELAY_RANGE_SECS.end())
+    };
+    retry::RetryPolicy {
+        max_retries: match load_setting("retry_max").as_deref() {
+            Some("forever") => None,
+            Some(v) => Some(v.parse().unwrap_or(0).min(*retry::MAX_RETRIES_RANGE.end())),
+            None => defaults.max_retries,
+        },
+        initial_delay_secs: secs("retry_initial", defaults.initial_delay_secs),
+        max_delay_secs: secs("retry_max_delay", defaults.max_delay_secs),
+        give_up: retry::GiveUp::parse(&load_setting("retry_give_up").unwrap_or_default()),
+    }
+}
+
+fn save_retry_policy(policy: &retry::RetryPolicy) {
+    save_setting("retry_max", &policy.max_retries.map_or("forever".to_string(), |n| n.to_string()));
+    save_setting("retry_initial", &policy.initial_delay_secs.to_string());
+    save_setting("retry_max_delay", &policy.max_delay_secs.to_string());
+    save_setting("retry_give_up", policy.give_up.as_str());
 }
 
 fn load_debug_setting() -> bool {
-    let path = get_settings_path();
-    fs::read_to_string(&path)
-        .ok()
-        .map(|s| s.trim() == "debug=true")
-        .unwrap_or(false)
+    load_bool_setting("debug")
 }
 
 fn save_debug_setting(enabled: bool) {
-    let _ = ensure_config_dirs();
-    let path = get_settings_path();
-    let _ = fs::write(&path, if enabled { "debug=true" } else { "debug=false" });
+    save_bool_setting("debug", enabled);
+}
+
+/// Log files in the logs folder, newest first
+fn list_log_files() -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(get_logs_path()) else {
+        return Vec::new();
+    };
+    let mut logs: Vec<(SystemTime, PathBuf)> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "log" || ext == "jsonl"))
+        .map(|path| (fs::metadata(&path).and_then(|m| m.modified()).unwrap_or(UNIX_EPOCH), path))
+        .collect();
+    logs.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    logs.into_iter().map(|(_, path)| path).collect()
 }
 
-fn create_log_file() -> Option<File> {
+fn create_log_file(format: log_format::LogFormat) -> Option<File> {
     let _ = ensure_config_dirs();
     let logs_path = get_logs_path();
     let timestamp = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .map(|d| d.as_secs())
         .unwrap_or(0);
-    let log_path = logs_path.join(format!("budbridge_{}.log", timestamp));
+    // Fixed for the file's lifetime; a change in Settings waits for the next one
+    format.make_current();
+    let log_path = logs_path.join(format!("budbridge_{}.{}", timestamp, format.extension()));
     OpenOptions::new()
         .create(true)
         .append(true)
@@ -711,25 +5167,35 @@ fn create_log_file() -> Option<File> {
 }
 
 fn log_message(log_file: &Arc<Mutex<Option<File>>>, debug_flag: &Arc<AtomicBool>, message: &str) {
-    if !debug_flag.load(Ordering::Relaxed) {
-        return;
-    }
-    if let Some(ref mut file) = *log_file.lock() {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .map(|d| d.as_millis())
-            .unwrap_or(0);
-        let _ = writeln!(file, "[{}] {}", timestamp, message);
-        let _ = file.flush();
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    if debug_flag.load(Ordering::Relaxed) {
+        if let Some(ref mut file) = *log_file.lock() {
+            let _ = writeln!(file, "{}", log_format::LogFormat::current().line(timestamp, message));
+            let _ = file.flush();
+        }
     }
+    bug_report::remember(log_format::LogFormat::Text.line(timestamp, message));
 }
 
 // Audio/Network bridge
+#[allow(clippy::too_many_arguments)]
 fn run_bridge(
     iphone_ip: String,
-    input_idx: usize,
-    output_idx: usize,
+    known_key: Option<PublicKey>,
+    rendezvous: Option<rendezvous::Rendezvous>,
+    standby: Option<standby::Target>,
+    preset: LinkPreset,
+    recv_buffer_kb: u32,
+    session: Session,
+    pc_id: PcId,
+    input: DeviceId,
+    output: DeviceId,
     input_is_loopback: bool,
+    exclude_app: Option<String>,
+    switches: Receiver<AudioSwitch>,
     state: Arc<AppState>,
     stop_flag: Arc<AtomicBool>,
     debug_flag: Arc<AtomicBool>,
@@ -737,44 +5203,100 @@ fn run_bridge(
 ) -> Result<()> {
     let host = cpal::default_host();
 
-    // Get the capture device - either from input devices or output devices (for loopback)
-    let (capture_device, capture_config) = if input_is_loopback {
-        // For loopback, we need to find the output device
-        // The input_idx for loopback devices is offset by the number of input devices
-        let num_input_devices = host.input_devices()?.count();
-        let output_loopback_idx = input_idx - num_input_devices;
+    let (capture_device, capture_config) = find_capture_device(&host, &input, input_is_loopback)?;
+    let (output_device, output_config) = find_output_device(&host, &output)?;
 
-        let device: Device = host
-            .output_devices()?
-            .nth(output_loopback_idx)
-            .ok_or_else(|| anyhow!("Loopback device not found"))?;
+    let (mic_tx, mic_rx): (Sender<Vec<f32>>, Receiver<Vec<f32>>) = bounded(framer::QUEUE_FRAMES);
+    let (pc_tx, pc_rx): (Sender<Vec<i16>>, Receiver<Vec<i16>>) = bounded(4);
 
-        // For loopback capture, use the output config but build an input stream
-        let config: StreamConfig = device.default_output_config()?.into();
-        (device, config)
-    } else {
-        // Regular input device
-        let device: Device = host
-            .input_devices()?
-            .nth(input_idx)
-            .ok_or_else(|| anyhow!("Input device not found"))?;
-        let config: StreamConfig = device.default_input_config()?.into();
-        (device, config)
+    let log = |line: &str| log_message(&log_file, &debug_flag, line);
+    let mut build_capture = |device: &Device, config: &StreamConfig| {
+        build_input_stream(
+            device,
+            config,
+            mic_tx.clone(),
+            config.channels,
+            config.sample_rate.0,
+            state.clone(),
+            debug_flag.clone(),
+            log_file.clone(),
+        )
+    };
+    // Loopback comes straight from WASAPI (wasapi_loopback.rs), or with an app
+    // left out from process loopback; cpal's stream is the fallback
+    let loopback_config = StreamConfig {
+        channels: wasapi_loopback::CHANNELS,
+        sample_rate: cpal::SampleRate(wasapi_loopback::SAMPLE_RATE),
+        buffer_size: cpal::BufferSize::Default,
+    };
+    let loopback_processor = || {
+        capture_processor(
+            mic_tx.clone(),
+            wasapi_loopback::CHANNELS,
+            wasapi_loopback::SAMPLE_RATE,
+            state.clone(),
+            debug_flag.clone(),
+            log_file.clone(),
+        )
+    };
+    let loopback_stopped = || {
+        let (state, debug_flag, log_file) = (state.clone(), debug_flag.clone(), log_file.clone());
+        move |e: String| {
+            log_message(&log_file, &debug_flag, &format!("Loopback capture stopped: {}", e));
+            *state.status_message.lock() = format!("Error: capture stopped: {}", e);
+            state.repaint.notify();
+        }
+    };
+    let open_device_loopback = |id: &DeviceId| match wasapi_loopback::start(&id.name, id.nth, loopback_processor(), loopback_stopped()) {
+        Ok(capture) => {
+            log(&format!("Capture: WASAPI loopback on {} opened", id.name));
+            Some(capture)
+        }
+        Err(e) => {
+            log(&format!("Capture: WASAPI loopback on {} failed, trying cpal: {}", id.name, e));
+            None
+        }
     };
 
-    let output_device: Device = host
-        .output_devices()?
-        .nth(output_idx)
-        .ok_or_else(|| anyhow!("Output device not found"))?;
+    // Leaving an app out swaps the device's loopback for process loopback, which
+    // takes what every app plays. An app that isn't playing has nothing to leave out.
+    let mut loopback_capture = None;
+    let mut loopback_name = device_label(&capture_device);
+    if let Some(app) = exclude_app.filter(|_| input_is_loopback) {
+        match process_loopback::sessions().map(|sessions| process_loopback::find(&sessions, &app)) {
+            Ok(Some(pid)) => match process_loopback::start(pid, loopback_processor(), loopback_stopped()) {
+                Ok(capture) => {
+                    log(&format!("Capture: everything but {} (process {})", app, pid));
+                    loopback_name = format!("All apps but {}", app);
+                    loopback_capture = Some(capture);
+                }
+                Err(e) => log(&format!("Capture: could not leave out {}, capturing everything: {}", app, e)),
+            },
+            Ok(None) => log(&format!("Capture: {} isn't playing anything, capturing everything", app)),
+            Err(e) => log(&format!("Capture: could not list audio sessions, capturing everything: {}", e)),
+        }
+    }
+    if input_is_loopback && loopback_capture.is_none() {
+        loopback_capture = open_device_loopback(&input);
+    }
+
+    let mut capture_stream = None;
+    let (capture_name, capture_config) = if loopback_capture.is_some() {
+        (loopback_name, loopback_config.clone())
+    } else {
+        let (device, config, stream) =
+            capture::open(&host, capture_device, capture_config, input_is_loopback, &mut build_capture, log)?;
+        capture_stream = Some(stream);
+        (device_label(&device), config)
+    };
 
-    let capture_name = capture_device.name().unwrap_or_else(|_| "Unknown".to_string());
-    let output_name = output_device.name().unwrap_or_else(|_| "Unknown".to_string());
+    let output_name = device_label(&output_device);
+    *state.streams.host.lock() = Some(host.id().name().to_string());
+    *state.streams.capture.lock() = Some(stream_info::Stream::from_config(capture_name.clone(), &capture_config, input_is_loopback));
 
     log_message(&log_file, &debug_flag, &format!("Capture device: {} (loopback: {})", capture_name, input_is_loopback));
     log_message(&log_file, &debug_flag, &format!("Output device: {}", output_name));
 
-    let output_config: StreamConfig = output_device.default_output_config()?.into();
-
     let capture_channels = capture_config.channels;
     let output_channels = output_config.channels;
     let capture_sample_rate = capture_config.sample_rate.0;
@@ -787,15 +5309,26 @@ fn run_bridge(
         "Output config: {} Hz, {} channels", output_sample_rate, output_channels
     ));
 
-    let (mic_tx, mic_rx): (Sender<Vec<i16>>, Receiver<Vec<i16>>) = bounded(4);
-    let (pc_tx, pc_rx): (Sender<Vec<i16>>, Receiver<Vec<i16>>) = bounded(4);
-
     let iphone_addr = format!("{}:{}", iphone_ip, SEND_PORT);
 
-    *state.status_message.lock() = format!(
-        "Connected to {} ({}Hz {}ch)",
-        iphone_ip, capture_sample_rate, capture_channels
-    );
+    let bluetooth = transport::parse_bluetooth_address(&iphone_ip);
+    let capture_only = rendezvous.is_none() && iphone_ip.trim().is_empty();
+    let peer_label = match (&rendezvous, bluetooth) {
+        (Some(rv), _) => format!("session {}", rv.code),
+        (None, None) if capture_only => "network outputs only".to_string(),
+        (None, Some(_)) => format!("{} over Bluetooth", iphone_ip.trim_start_matches(transport::BLUETOOTH_PREFIX)),
+        (None, None) => iphone_ip.clone(),
+    };
+    let connected = format!("Connected to {} ({}Hz {}ch)", peer_label, capture_sample_rate, capture_channels);
+    if capture_only {
+        *state.status_message.lock() = connected;
+    } else {
+        // Internet mode reports its own progress until the phone joins
+        if rendezvous.is_none() {
+            *state.status_message.lock() = format!("Connecting to {}...", peer_label);
+        }
+        *state.answered_status.lock() = Some(connected);
+    }
 
     let stop_net = stop_flag.clone();
     let state_net = state.clone();
@@ -803,36 +5336,102 @@ fn run_bridge(
     let debug_flag_net = debug_flag.clone();
     let log_file_net = log_file.clone();
     let net_handle = thread::spawn(move || {
-        let _ = run_network(stop_net, mic_rx, pc_tx, &iphone_addr_clone, state_net, debug_flag_net, log_file_net);
+        let result = match capture_only {
+            true => run_capture_only(stop_net.clone(), mic_rx, state_net.clone()),
+            false => run_network(
+                stop_net.clone(),
+                mic_rx,
+                pc_tx,
+                &iphone_addr_clone,
+                bluetooth,
+                known_key,
+                rendezvous,
+                standby,
+                preset,
+                recv_buffer_kb,
+                session,
+                pc_id,
+                state_net.clone(),
+                debug_flag_net.clone(),
+                log_file_net.clone(),
+            ),
+        };
+        if let Err(e) = result {
+            if stop_net.load(Ordering::SeqCst) {
+                return; // cancelled by the user
+            }
+            log_message(&log_file_net, &debug_flag_net, &format!("Network error: {}", e));
+            *state_net.status_message.lock() = format!("Error: {}", e);
+            state_net.is_connected.store(false, Ordering::SeqCst);
+            stop_net.store(true, Ordering::SeqCst);
+            state_net.repaint.notify();
+        }
     });
 
-    let state_audio = state.clone();
-    let debug_flag_audio = debug_flag.clone();
-    let log_file_audio = log_file.clone();
-    let capture_stream = build_input_stream(
-        &capture_device,
-        &capture_config,
-        mic_tx,
-        capture_channels,
-        capture_sample_rate,
-        state_audio,
-        debug_flag_audio,
-        log_file_audio,
-    )?;
-
-    let output_stream = build_output_stream(&output_device, &output_config, pc_rx, output_channels)?;
+    let build_playback = |device: &Device, config: &StreamConfig| {
+        build_output_stream(device, config, pc_rx.clone(), config.channels, state.clone(), debug_flag.clone(), log_file.clone())
+    };
+    let mut output_stream = build_playback(&output_device, &output_config)?;
+    *state.streams.playback.lock() = Some(stream_info::Stream::from_config(output_name, &output_config, false));
 
-    capture_stream.play()?;
+    if let Some(stream) = &capture_stream {
+        stream.play()?;
+    }
     output_stream.play()?;
 
     log_message(&log_file, &debug_flag, "Audio streams started");
 
+    // Device changes rebuild one stream; the network thread never notices
     while !stop_flag.load(Ordering::SeqCst) {
-        thread::sleep(std::time::Duration::from_millis(100));
+        let switched = match switches.recv_timeout(std::time::Duration::from_millis(100)) {
+            Ok(AudioSwitch::Capture { id, loopback }) => {
+                // A device picked by hand is captured whole; the old capture stops first
+                loopback_capture = None;
+                match loopback.then(|| open_device_loopback(&id)).flatten() {
+                    Some(capture) => {
+                        loopback_capture = Some(capture);
+                        capture_stream = None;
+                        *state.streams.capture.lock() = Some(stream_info::Stream::from_config(id.name.clone(), &loopback_config, true));
+                        Ok(format!("Capture switched to {} (WASAPI loopback)", id.name))
+                    }
+                    None => find_capture_device(&host, &id, loopback)
+                        .and_then(|(device, config)| capture::open(&host, device, config, loopback, &mut build_capture, log))
+                        .and_then(|(device, config, stream)| {
+                            stream.play()?;
+                            capture_stream = Some(stream);
+                            *state.streams.capture.lock() = Some(stream_info::Stream::from_config(device_label(&device), &config, loopback));
+                            Ok(format!("Capture switched to {} ({} Hz, {} ch)", device_label(&device), config.sample_rate.0, config.channels))
+                        }),
+                }
+            }
+            Ok(AudioSwitch::Playback(id)) => find_output_device(&host, &id).and_then(|(device, config)| {
+                let stream = build_playback(&device, &config)?;
+                stream.play()?;
+                output_stream = stream;
+                *state.streams.playback.lock() = Some(stream_info::Stream::from_config(device_label(&device), &config, false));
+                Ok(format!("Playback switched to {} ({} Hz, {} ch)", device_label(&device), config.sample_rate.0, config.channels))
+            }),
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => {
+                thread::sleep(std::time::Duration::from_millis(100));
+                continue;
+            }
+        };
+        match switched {
+            Ok(message) => {
+                log(&message);
+                state.timeline.lock().push(timeline::Kind::DeviceChanged, message);
+            }
+            Err(e) => {
+                log(&format!("Device switch failed: {}", e));
+                *state.status_message.lock() = format!("Error: could not switch devices, still on the old one: {}", e);
+            }
+        }
     }
 
     log_message(&log_file, &debug_flag, "Stopping audio streams");
 
+    drop(loopback_capture);
     drop(capture_stream);
     drop(output_stream);
     net_handle.join().ok();
@@ -842,81 +5441,391 @@ fn run_bridge(
     Ok(())
 }
 
+/// A device change for a running bridge
+enum AudioSwitch {
+    Capture { id: DeviceId, loopback: bool },
+    Playback(DeviceId),
+}
+
+fn device_label(device: &Device) -> String {
+    device.name().unwrap_or_else(|_| "Unknown".to_string())
+}
+
+/// The capture device at `index` in the input list, with its default config.
+/// Loopback entries follow the real inputs and capture an output device.
+fn find_capture_device(host: &cpal::Host, id: &DeviceId, loopback: bool) -> Result<(Device, StreamConfig)> {
+    if loopback {
+        let device = id.find(host.output_devices()?)?;
+        // For loopback capture, use the output config but build an input stream
+        let config: StreamConfig = device.default_output_config()?.into();
+        Ok((device, config))
+    } else {
+        let device = id.find(host.input_devices()?)?;
+        let config: StreamConfig = device.default_input_config()?.into();
+        Ok((device, config))
+    }
+}
+
+fn find_output_device(host: &cpal::Host, id: &DeviceId) -> Result<(Device, StreamConfig)> {
+    let device = id.find(host.output_devices()?)?;
+    let config: StreamConfig = device.default_output_config()?.into();
+    Ok((device, config))
+}
+
+/// The app's side of a session (see bridge.rs in core): the rate limit, the
+/// access log, clock probe echoes and the send counters
+struct NetworkHooks<'a> {
+    state: &'a AppState,
+    limiter: &'a mut rate_limit::RateLimiter,
+    clock: &'a mut clock::ClockSync,
+}
+
+impl budbridge_core::Hooks for NetworkHooks<'_> {
+    // Floods are dropped before anything looks at them
+    fn admit(&mut self, src: SocketAddr) -> bool {
+        self.limiter.allow(src.ip(), Instant::now())
+    }
+
+    fn intercept(&mut self, packet: &[u8]) -> bool {
+        let echo = self.clock.echo(packet, Instant::now());
+        if echo {
+            *self.state.clock.lock() = self.clock.estimate();
+        }
+        echo
+    }
+
+    fn received(&mut self, src: SocketAddr, len: usize, accepted: bool) {
+        self.state.access_log.lock().record(src, len, accepted);
+        // Handshakes and control frames change what the UI shows too
+        self.state.repaint.notify();
+    }
+
+    fn sent(&mut self, len: usize) {
+        self.state.packets_sent.fetch_add(1, Ordering::Relaxed);
+        self.state.send_intervals.lock().record(Instant::now(), len);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn run_network(
     stop_flag: Arc<AtomicBool>,
-    mic_rx: Receiver<Vec<i16>>,
+    mic_rx: Receiver<Vec<f32>>,
     pc_tx: Sender<Vec<i16>>,
     iphone_addr: &str,
+    bluetooth: Option<u64>,
+    known_key: Option<PublicKey>,
+    rendezvous: Option<rendezvous::Rendezvous>,
+    standby: Option<standby::Target>,
+    preset: LinkPreset,
+    recv_buffer_kb: u32,
+    session: Session,
+    pc_id: PcId,
     state: Arc<AppState>,
     debug_flag: Arc<AtomicBool>,
     log_file: Arc<Mutex<Option<File>>>,
 ) -> Result<()> {
-    let recv_socket = UdpSocket::bind(format!("0.0.0.0:{}", RECEIVE_PORT))?;
-    recv_socket.set_nonblocking(true)?;
+    // Bluetooth runs the same session over an RFCOMM stream (transport.rs)
+    let (link, punched): (Box<dyn budbridge_core::Transport>, Option<UdpSocket>) = match bluetooth {
+        Some(address) => (Box::new(transport::connect_bluetooth(address)?), None),
+        None => {
+            let recv_socket = UdpSocket::bind(format!("0.0.0.0:{}", RECEIVE_PORT))?;
+            // A failure only costs burst tolerance, so the session goes on
+            match socket_buffer::set_receive_buffer(&recv_socket, recv_buffer_kb as usize * 1024) {
+                Ok(granted) => log_message(&log_file, &debug_flag, &format!(
+                    "Receive buffer: asked for {} KB, got {} KB", recv_buffer_kb, granted / 1024
+                )),
+                Err(e) => log_message(&log_file, &debug_flag, &format!("Receive buffer: {}", e)),
+            }
 
-    let send_socket = UdpSocket::bind("0.0.0.0:0")?;
+            // Internet mode sends from the receive port: that's the NAT mapping the
+            // rendezvous server saw and the phone punches towards. A clone of the socket
+            // is kept to ask the server for a relay from the same port.
+            match &rendezvous {
+                Some(rv) => {
+                    recv_socket.set_nonblocking(true)?;
+                    *state.status_message.lock() = format!("Waiting for the phone to join session {}...", rv.code);
+                    let peer = rv.find_peer(&recv_socket, &stop_flag)?;
+                    log_message(&log_file, &debug_flag, &format!("Rendezvous: phone is at {}", peer));
+                    *state.status_message.lock() = format!("Punching through to {}...", peer);
+                    let punched = recv_socket.try_clone()?;
+                    (Box::new(budbridge_core::UdpTransport::punched(recv_socket, peer)?), Some(punched))
+                }
+                None => {
+                    // Resolved once: hostnames (e.g. Tailscale MagicDNS names) would otherwise
+                    // be looked up again for every packet
+                    let addr = iphone_addr
+                        .to_socket_addrs()?
+                        .next()
+                        .ok_or_else(|| anyhow!("Could not resolve {}", iphone_addr))?;
+                    (Box::new(budbridge_core::UdpTransport::new(recv_socket, addr)?), None)
+                }
+            }
+        }
+    };
 
-    log_message(&log_file, &debug_flag, &format!(
-        "Network started: sending to {}, receiving on port {}", iphone_addr, RECEIVE_PORT
-    ));
+    // Both directions start as PCM mono; the hello offers the configured codecs
+    // and asks the phone to sign its answer
+    let mut bridge = budbridge_core::Bridge::new(link, session, pc_id)?;
+    bridge.set_known_key(known_key);
+    bridge.set_max_payload(preset.max_payload);
+    bridge.set_keepalive(preset.keepalive.or(rendezvous.as_ref().map(|_| PUNCH_KEEPALIVE_INTERVAL)));
+    // An unresolvable standby is left out rather than failing the session
+    if let Some(target) = standby {
+        match target.address.to_socket_addrs().map(|mut a| a.next()) {
+            Ok(Some(addr)) => bridge.set_standby(addr, target.key),
+            _ => log_message(&log_file, &debug_flag, &format!("Standby: could not resolve {}", target.address)),
+        }
+    }
+    let mut local_ip = local_ip_towards(bridge.phone());
+    let mut punch_reported = false;
+    let mut peer_found_at = Instant::now();
+    // Internet mode, once the punch got no audio through: asking the rendezvous server to relay
+    let mut relay: Option<rendezvous::RelayRequest> = None;
+    let mut last_route_check = Instant::now();
+    let mut send_failures = 0u32;
+    let mut clock = clock::ClockSync::new(Instant::now());
+    // Pinned to performance cores, the battery saver's EcoQoS would only fight it
+    let pinned = state.pin_performance.load(Ordering::Relaxed) && priority::pin_to_performance_cores();
+    battery::set_eco_qos(state.eco_qos.load(Ordering::Relaxed) && !pinned);
+
+    log_message(&log_file, &debug_flag, &match bluetooth {
+        Some(_) => "Bluetooth link started".to_string(),
+        None => format!("Network started: sending to {}, receiving on port {}", bridge.phone(), RECEIVE_PORT),
+    });
 
-    let mut recv_buf = [0u8; 65536];
     let mut log_counter = 0u64;
+    let mut last_arrived: Option<(SocketAddr, usize)> = None;
+    let mut feedback = feedback::FeedbackDetector::new(2, 1);
+    // Set while the phone answers another PC's hellos, holding the status to go back to
+    let mut crossed: Option<String> = None;
+    let mut limiter = rate_limit::RateLimiter::default();
 
     while !stop_flag.load(Ordering::SeqCst) {
-        match recv_socket.recv_from(&mut recv_buf) {
-            Ok((len, src)) => {
-                state.packets_recv.fetch_add(1, Ordering::Relaxed);
-                let samples: Vec<i16> = recv_buf[..len]
-                    .chunks_exact(2)
-                    .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]))
-                    .collect();
-                let has_audio = samples.iter().any(|&s| s.abs() > 100);
-                if has_audio {
-                    state.packets_recv_with_audio.fetch_add(1, Ordering::Relaxed);
-                }
-
-                // Log every 100th packet to avoid spam
-                log_counter += 1;
-                if log_counter % 100 == 0 {
-                    let max_sample = samples.iter().map(|s| s.abs()).max().unwrap_or(0);
+        state.cpu_times.record(cpu::Part::Network);
+        let mut hooks = NetworkHooks { state: &state, limiter: &mut limiter, clock: &mut clock };
+        loop {
+            let event = match bridge.poll_with(&mut hooks) {
+                Ok(Some(event)) => event,
+                Ok(None) => break,
+                Err(e) => {
+                    // A failed identity check is shown with the error
+                    *state.peer_identity.lock() = bridge.identity();
+                    return Err(e);
+                }
+            };
+            match event {
+                budbridge_core::Event::Identity(verdict) => {
+                    *state.peer_identity.lock() = Some(verdict);
+                    log_message(&log_file, &debug_flag, &format!("Phone identity: {:?}", verdict));
+                }
+                budbridge_core::Event::Agreed(agreed) => {
+                    *state.negotiated.lock() = Some(agreed);
+                    log_message(&log_file, &debug_flag, &format!(
+                        "Codecs agreed: down {}, up {} at {} Hz, {} redundant frames, FEC every {} packets",
+                        agreed.formats.down, agreed.formats.up, agreed.up_sample_rate(), agreed.redundancy, agreed.fec_group().unwrap_or(0)
+                    ));
+                }
+                budbridge_core::Event::Sealed(sealed) => {
+                    let sealing = bridge.agreed().map_or(seal::Mode::Off, |agreed| agreed.seal);
+                    log_message(&log_file, &debug_flag, &match sealed {
+                        true => format!("Audio protection: {}", sealing.label()),
+                        false => "Audio protection: the phone's key isn't vouched for by its identity, refusing its audio".to_string(),
+                    });
+                }
+                budbridge_core::Event::Arrived { src, len, seq } => {
+                    let now = Instant::now();
+                    state.packets_recv.fetch_add(1, Ordering::Relaxed);
+                    state.recv_intervals.lock().record(now, len);
+                    if let Some(seq) = seq {
+                        state.recv_sequence.lock().record(now, seq);
+                    }
+                    state.fec_recovered.store(bridge.fec_recovered(), Ordering::Relaxed);
+                    last_arrived = Some((src, len));
+                }
+                budbridge_core::Event::Audio(samples) => {
+                    state.red_recovered.store(bridge.red_recovered(), Ordering::Relaxed);
+                    let level = record_received(&samples, &state);
+                    feedback.push_received(&samples);
+                    *state.feedback.lock() = feedback.detected();
+
+                    // Log every 100th packet to avoid spam
+                    log_counter += 1;
+                    if let Some((src, len)) = last_arrived.filter(|_| log_counter.is_multiple_of(100)) {
+                        let max_sample = samples.iter().map(|s| s.abs()).max().unwrap_or(0);
+                        log_message(&log_file, &debug_flag, &format!(
+                            "RECV from {}: {} bytes, {} samples, max_amp={}, level={:.1} dBFS",
+                            src, len, samples.len(), max_sample, level
+                        ));
+                    }
+
+                    let _ = pc_tx.try_send(samples);
+                }
+                budbridge_core::Event::NoAnswer => {
+                    log_message(&log_file, &debug_flag, "No codec answer from the phone, staying on PCM");
+                }
+                // The phone answered another PC's hello, so it's listening there while its
+                // mic comes here. The bridge refuses its audio until it answers ours.
+                budbridge_core::Event::Crossed(true) => {
                     log_message(&log_file, &debug_flag, &format!(
-                        "RECV from {}: {} bytes, {} samples, max_amp={}, has_audio={}",
-                        src, len, samples.len(), max_sample, has_audio
+                        "{} answered another PC's hello, refusing its audio until it answers ours", bridge.phone().ip()
+                    ));
+                    crossed = Some(std::mem::replace(
+                        &mut *state.status_message.lock(),
+                        "Error: the iPhone is listening to another PC. Waiting for it to switch to this one.".to_string(),
                     ));
                 }
+                budbridge_core::Event::Crossed(false) => {
+                    log_message(&log_file, &debug_flag, "The phone answered this PC's hello again");
+                    if let Some(status) = crossed.take() {
+                        *state.status_message.lock() = status;
+                    }
+                }
+                // Also a sender that may be the phone at a new address, which the UI
+                // offers to switch to (and checks its key there)
+                budbridge_core::Event::PeerAt(ip) => *state.peer_ip.lock() = Some(ip),
+                budbridge_core::Event::Control(packet) => {
+                    if relay.as_mut().is_some_and(|request| request.answered(&packet)) {
+                        log_message(&log_file, &debug_flag, "The rendezvous server relays");
+                    }
+                    if let Some((id, peak_db)) = protocol::parse_test_played(&packet) {
+                        state.test_sound.lock().played(id, peak_db);
+                        log_message(&log_file, &debug_flag, &format!("Test sound {} played at {} dBFS", id, peak_db));
+                    }
+                }
+                budbridge_core::Event::StandbyRefused(e) => {
+                    log_message(&log_file, &debug_flag, &format!("Standby {}, leaving it out", e));
+                }
+                budbridge_core::Event::ReceiveFailed(e) => {
+                    log_message(&log_file, &debug_flag, &format!("Recv error: {}", e));
+                }
+            }
+        }
 
-                let _ = pc_tx.try_send(samples);
+        if let Ok(mut samples) = mic_rx.try_recv() {
+            feedback.push_sent(&samples);
+            let marker = state.test_sound.lock().mix(&mut samples);
+            let (phone, level) = mix_for_phone(&mut samples, &state);
+            if let Some(id) = marker {
+                let _ = bridge.send_control(protocol::test_marker(id).as_bytes());
             }
-            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
-            Err(e) => {
-                log_message(&log_file, &debug_flag, &format!("Recv error: {}", e));
+            match bridge.send_with(&phone, &mut hooks) {
+                Ok(sent) => {
+                    send_failures = 0;
+                    if sent > 0 && log_counter.is_multiple_of(100) {
+                        let max_sample = samples.iter().map(|s| s.abs()).fold(0.0f32, f32::max);
+                        log_message(&log_file, &debug_flag, &format!(
+                            "SEND to {}: {} datagrams, max_amp={:.4}, level={:.1} dBFS",
+                            bridge.phone(), sent, max_sample, level
+                        ));
+                    }
+                }
+                Err(e) => {
+                    send_failures += 1;
+                    log_message(&log_file, &debug_flag, &format!("Send error: {}", e));
+                }
+            }
+        }
+
+        // Only phones that answer the hello know to echo probes rather than play them
+        if bridge.agreed().is_some() {
+            if let Some(probe) = clock.request_due(Instant::now()) {
+                let _ = bridge.send_control(&probe);
             }
         }
 
-        if let Ok(samples) = mic_rx.try_recv() {
-            let has_audio = samples.iter().any(|&s| s.abs() > 100);
-            if has_audio {
-                state.packets_sent_with_audio.fetch_add(1, Ordering::Relaxed);
+        *state.standby.lock() = bridge.standby_status();
+        *state.seal_stats.lock() = bridge.seal_stats().clone();
+        if state.switch_target.swap(false, Ordering::SeqCst) {
+            let from = bridge.phone();
+            if bridge.switch_to_standby()? {
+                state.recv_sequence.lock().reset();
+                *state.negotiated.lock() = bridge.agreed();
+                *state.peer_identity.lock() = bridge.identity();
+                log_message(&log_file, &debug_flag, &format!("Switched target {} -> {}", from, bridge.phone()));
+                crossed = None;
+                clock = clock::ClockSync::new(Instant::now());
+                *state.clock.lock() = None;
+                local_ip = local_ip_towards(bridge.phone());
+                *state.status_message.lock() = format!("Switched to {}", bridge.phone().ip());
+                state.target_switches.fetch_add(1, Ordering::SeqCst);
+            } else {
+                log_message(&log_file, &debug_flag, "Switch target asked for, but the standby isn't ready");
             }
+        }
 
-            let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
-            for chunk in bytes.chunks(1400) {
-                match send_socket.send_to(chunk, iphone_addr) {
-                    Ok(sent) => {
-                        state.packets_sent.fetch_add(1, Ordering::Relaxed);
-                        if log_counter % 100 == 0 {
-                            let max_sample = samples.iter().map(|s| s.abs()).max().unwrap_or(0);
+        if let (Some(rv), Some(socket)) = (&rendezvous, &punched) {
+            if let Some(request) = relay.as_mut() {
+                if let Err(e) = request.resend(socket) {
+                    log_message(&log_file, &debug_flag, &format!("Relay request: {}", e));
+                }
+            }
+            // No audio through the punch: relay through the server, and if that
+            // brings none either, tell the user
+            if !punch_reported
+                && state.packets_recv.load(Ordering::Relaxed) == 0
+                && peer_found_at.elapsed() >= PUNCH_TIMEOUT
+            {
+                match relay.as_ref().map(|request| (request.server(), request.relaying())) {
+                    None => match rv.relay(socket) {
+                        Ok(request) => {
+                            let server = request.server();
                             log_message(&log_file, &debug_flag, &format!(
-                                "SEND to {}: {} bytes, max_amp={}, has_audio={}",
-                                iphone_addr, sent, max_sample, has_audio
+                                "No audio through the punch to {}, relaying through {}", bridge.phone(), server
                             ));
+                            relay = Some(request);
+                            bridge.move_to(server);
+                            peer_found_at = Instant::now();
+                            local_ip = local_ip_towards(server);
+                            *state.status_message.lock() =
+                                format!("The phone's network blocks hole punching; relaying through {}...", server.ip());
                         }
+                        Err(e) => {
+                            punch_reported = true;
+                            *state.status_message.lock() = format!("Error: no audio from the phone, and no relay: {}", e);
+                        }
+                    },
+                    Some((server, true)) => {
+                        punch_reported = true;
+                        *state.status_message.lock() =
+                            format!("Error: no audio from the phone, directly or through {}", server.ip());
+                    }
+                    Some((server, false)) => {
+                        punch_reported = true;
+                        *state.status_message.lock() = format!(
+                            "Error: no audio from the phone, and {} never agreed to relay; the server may be too old to relay",
+                            server.ip()
+                        );
                     }
-                    Err(e) => {
-                        log_message(&log_file, &debug_flag, &format!("Send error: {}", e));
+                }
+            }
+        }
+
+        // Switching e.g. from Ethernet to Wi-Fi changes the address the iPhone sees us
+        // at. A fresh send socket makes the OS pick the new route; the iPhone follows
+        // the new source address (NetworkManager.swift). The receive socket is bound to
+        // all interfaces, so it keeps working as is. In internet mode the punched
+        // mapping belongs to the socket, so it's left alone, and Bluetooth has no route.
+        if rendezvous.is_none()
+            && bluetooth.is_none()
+            && (last_route_check.elapsed() >= ROUTE_CHECK_INTERVAL || send_failures >= MAX_SEND_FAILURES)
+        {
+            last_route_check = Instant::now();
+            let current_ip = local_ip_towards(bridge.phone());
+            if current_ip != local_ip || send_failures >= MAX_SEND_FAILURES {
+                log_message(&log_file, &debug_flag, &format!(
+                    "Network change (local address {:?} -> {:?}, {} send failures), rebinding",
+                    local_ip, current_ip, send_failures
+                ));
+                match bridge.link().rebind() {
+                    Ok(()) => {
+                        if let Some(ip) = current_ip {
+                            *state.status_message.lock() = format!("Network changed, now on {}", ip);
+                        }
                     }
+                    Err(e) => log_message(&log_file, &debug_flag, &format!("Rebind failed: {}", e)),
                 }
+                local_ip = current_ip;
+                send_failures = 0;
             }
         }
 
@@ -928,71 +5837,207 @@ fn run_network(
     Ok(())
 }
 
+/// No phone: just feed the capture to the RTSP/AES67 outputs
+fn run_capture_only(stop_flag: Arc<AtomicBool>, mic_rx: Receiver<Vec<f32>>, state: Arc<AppState>) -> Result<()> {
+    while !stop_flag.load(Ordering::SeqCst) {
+        state.cpu_times.record(cpu::Part::Network);
+        if let Ok(samples) = mic_rx.recv_timeout(Duration::from_millis(100)) {
+            let ambiance = next_ambiance(samples.len(), &state);
+            publish_capture(&samples, ambiance.as_deref(), &state);
+        }
+    }
+    Ok(())
+}
+
+/// The received level in dBFS of what the phone's mic sent
+fn record_received(samples: &[i16], state: &AppState) -> f32 {
+    let level = state.level_recv.lock().record(samples.iter().map(|&s| convert::from_i16(s)), 1);
+    state.repaint.notify();
+    level
+}
+
+/// The phone's mix of captured stereo samples, and the sent level in dBFS.
+/// Also hands the samples to the RTSP, AES67 and classroom outputs that are running.
+/// The one place the outgoing float bus is clamped to full scale.
+fn mix_for_phone(samples: &mut [f32], state: &AppState) -> (Vec<f32>, f32) {
+    state.peak_out.record(samples);
+    state.saturation_out.quantize(samples);
+    let ambiance = next_ambiance(samples.len(), state);
+    publish_capture(samples, ambiance.as_deref(), state);
+    let phone = state.mix.apply(receivers::Receiver::Phone, samples, ambiance.as_deref());
+    let level = state.level_sent.lock().record(phone.iter().copied(), 2);
+    state.repaint.notify();
+    (phone.into_owned(), level)
+}
+
+/// The ambiance loop's next frame, when one is loaded and routed somewhere.
+/// Advanced once per frame, whichever receivers take it.
+fn next_ambiance(len: usize, state: &AppState) -> Option<Vec<f32>> {
+    match state.ambiance.lock().as_mut() {
+        Some(ambiance) if state.mix.wanted(sources::Source::Ambiance) => Some(ambiance.next(len)),
+        _ => None,
+    }
+}
+
+/// The classroom group gets the float stereo bus to encode; the RTSP and AES67
+/// outputs are 16-bit mono. Each with its own sources, mute and trim.
+fn publish_capture(samples: &[f32], ambiance: Option<&[f32]>, state: &AppState) {
+    use receivers::Receiver;
+    if let Some(feed) = state.multicast_feed.lock().as_ref() {
+        feed.publish(&state.mix.apply(Receiver::Classroom, samples, ambiance));
+    }
+    let rtsp = state.rtsp_feed.lock();
+    let aes67 = state.aes67_feed.lock();
+    if rtsp.is_none() && aes67.is_none() {
+        return;
+    }
+    let mono = |receiver: Receiver| -> Vec<i16> {
+        let mixed = state.mix.apply(receiver, samples, ambiance);
+        convert::downmix(&mixed).into_iter().map(convert::to_i16).collect()
+    };
+    if let Some(feed) = rtsp.as_ref() {
+        feed.publish(&mono(Receiver::Rtsp));
+    }
+    if let Some(feed) = aes67.as_ref() {
+        feed.publish(&mono(Receiver::Aes67));
+    }
+}
+
+/// LAN or routed towards a device address; None for Bluetooth, and for names
+/// unless `resolve`
+fn detect_route(address: &str, resolve: bool) -> Option<route::Route> {
+    if transport::parse_bluetooth_address(address).is_some() {
+        return None;
+    }
+    let target = match address.parse::<IpAddr>() {
+        Ok(ip) => SocketAddr::new(ip, SEND_PORT),
+        Err(_) if resolve => (address, SEND_PORT).to_socket_addrs().ok()?.next()?,
+        Err(_) => return None,
+    };
+    let local = local_ip_towards(target)?;
+    Some(route::classify(target.ip(), local, route::prefix_len(local)))
+}
+
+/// The local address the OS would use to reach `addr` (no packets are sent)
+fn local_ip_towards(addr: SocketAddr) -> Option<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect(addr).ok()?;
+    socket.local_addr().ok().map(|a| a.ip())
+}
+
+/// cpal reports a failing device (unplugged, driver reset) here, on its own
+/// thread. The app has no console, so this goes to the log and the status line.
+fn stream_error_handler(
+    direction: &'static str,
+    state: Arc<AppState>,
+    debug_flag: Arc<AtomicBool>,
+    log_file: Arc<Mutex<Option<File>>>,
+) -> impl FnMut(cpal::StreamError) + Send + 'static {
+    move |err| {
+        log_message(&log_file, &debug_flag, &format!("The {} stream reported an error: {}", direction, err));
+        *state.status_message.lock() = match err {
+            cpal::StreamError::DeviceNotAvailable => {
+                format!("Error: the {} device went away. Pick another one under Audio Settings.", direction)
+            }
+            err => format!("Error: the {} device failed: {}", direction, err),
+        };
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn build_input_stream(
     device: &Device,
     config: &StreamConfig,
-    tx: Sender<Vec<i16>>,
+    tx: Sender<Vec<f32>>,
     channels: u16,
     input_sample_rate: u32,
     state: Arc<AppState>,
     debug_flag: Arc<AtomicBool>,
     log_file: Arc<Mutex<Option<File>>>,
 ) -> Result<cpal::Stream> {
-    let err_fn = move |err| {
-        eprintln!("Input stream error: {}", err);
-    };
+    let err_fn = stream_error_handler("capture", state.clone(), debug_flag.clone(), log_file.clone());
+    let mut process = capture_processor(tx, channels, input_sample_rate, state, debug_flag, log_file);
+    let stream = device.build_input_stream(config, move |data: &[f32], _: &cpal::InputCallbackInfo| process(data), err_fn, None)?;
+    Ok(stream)
+}
 
-    let downsample_ratio = if input_sample_rate > TARGET_SAMPLE_RATE {
-        input_sample_rate / TARGET_SAMPLE_RATE
-    } else {
-        1
-    };
+/// What the capture callback does with each buffer, whether it comes from cpal
+/// or from process loopback
+fn capture_processor(
+    tx: Sender<Vec<f32>>,
+    channels: u16,
+    input_sample_rate: u32,
+    state: Arc<AppState>,
+    debug_flag: Arc<AtomicBool>,
+    log_file: Arc<Mutex<Option<File>>>,
+) -> impl FnMut(&[f32]) + Send + 'static {
+    let quality = resample::Quality::load(&state.resample_quality);
+    let mut resampler = (input_sample_rate != TARGET_SAMPLE_RATE)
+        .then(|| resample::Resampler::new(2, input_sample_rate, TARGET_SAMPLE_RATE, quality));
 
     log_message(&log_file, &debug_flag, &format!(
-        "Building input stream: downsample_ratio={}", downsample_ratio
+        "Building input stream: {} Hz{}", input_sample_rate,
+        if resampler.is_some() { format!(", resampled to {} Hz ({})", TARGET_SAMPLE_RATE, quality.as_str()) } else { String::new() }
     ));
 
-    let log_file_cb = log_file.clone();
-    let debug_flag_cb = debug_flag.clone();
     let mut callback_counter = 0u64;
+    let mut framer = framer::Framer::new(framer::FRAME_MS);
+    let mut phase = downmix::PhaseMonitor::default();
+    let mut chain = dsp::Chain::default();
+    let mut pinned = false;
+
+    move |data: &[f32]| {
+        // The driver's thread, so only known once it calls
+        if !std::mem::replace(&mut pinned, true) && state.pin_performance.load(Ordering::Relaxed) {
+            priority::pin_to_performance_cores();
+        }
+        state.audio_callbacks.fetch_add(1, Ordering::Relaxed);
+        state.streams.capture_callback(data.len(), channels);
+        callback_counter += 1;
+
+        // Interleaved stereo: the first two channels, or mono doubled. Stays
+        // float until the encoder, so 24-bit and float transport lose nothing.
+        // Gain may go past full scale; only mix_for_phone clamps.
+        let gain = state.gain_out.get();
+        let mut stereo = convert::to_stereo(data, channels as usize);
+        stereo.iter_mut().for_each(|s| *s *= gain);
+        let stereo = match &mut resampler {
+            Some(resampler) => resampler.process(&stereo),
+            None => stereo,
+        };
 
-    let stream = device.build_input_stream(
-        config,
-        move |data: &[f32], _: &cpal::InputCallbackInfo| {
-            state.audio_callbacks.fetch_add(1, Ordering::Relaxed);
-            callback_counter += 1;
-
-            let mono_samples: Vec<f32> = if channels == 2 {
-                data.chunks(2)
-                    .map(|chunk| (chunk.get(0).unwrap_or(&0.0) + chunk.get(1).unwrap_or(&0.0)) / 2.0)
-                    .collect()
-            } else {
-                data.to_vec()
-            };
-
-            let downsampled: Vec<i16> = mono_samples
-                .iter()
-                .step_by(downsample_ratio as usize)
-                .map(|&s| (s.clamp(-1.0, 1.0) * 32767.0) as i16)
-                .collect();
-
-            // Log every 500th callback
-            if callback_counter % 500 == 0 {
-                let max_f32 = data.iter().map(|s| s.abs()).fold(0.0f32, |a, b| a.max(b));
-                let max_i16 = downsampled.iter().map(|s| s.abs()).max().unwrap_or(0);
-                log_message(&log_file_cb, &debug_flag_cb, &format!(
-                    "AUDIO_CB #{}: {} f32 samples, max_f32={:.6}, {} i16 samples, max_i16={}",
-                    callback_counter, data.len(), max_f32, downsampled.len(), max_i16
-                ));
+        // Everything after this sees fixed frames, however the driver buffers
+        let frames = framer.push(&stereo);
+        let frame_count = frames.len();
+        let mut max_out = 0.0f32;
+        for mut frame in frames {
+            phase.push(&frame);
+            state.mono_cancels.store(phase.cancelling(), Ordering::Relaxed);
+            DownmixMode::load(&state.downmix).apply(&mut frame);
+            let settings = *state.dsp.lock();
+            chain.process(&mut frame, settings);
+            *state.capture_lufs.lock() = settings.loudness_target.and(chain.loudness());
+
+            // Keep sending silence while muted so the phone's jitter buffer stays primed
+            if state.mute_out.load(Ordering::Relaxed) {
+                frame.fill(0.0);
             }
+            max_out = frame.iter().fold(max_out, |a, s| a.max(s.abs()));
+            state.meter_out.record(&frame);
+            let _ = tx.try_send(frame);
+        }
 
-            let _ = tx.try_send(downsampled);
-        },
-        err_fn,
-        None,
-    )?;
+        // Log every 500th callback
+        if callback_counter.is_multiple_of(500) {
+            let max_in = data.iter().map(|s| s.abs()).fold(0.0f32, |a, b| a.max(b));
+            log_message(&log_file, &debug_flag, &format!(
+                "AUDIO_CB #{}: {} samples in, max={:.6}, {} frames of {} ms out, max={:.6}",
+                callback_counter, data.len(), max_in, frame_count, framer::FRAME_MS, max_out
+            ));
+        }
 
-    Ok(stream)
+        state.cpu_times.record(cpu::Part::Capture);
+    }
 }
 
 fn build_output_stream(
@@ -1000,34 +6045,71 @@ fn build_output_stream(
     config: &StreamConfig,
     rx: Receiver<Vec<i16>>,
     channels: u16,
+    state: Arc<AppState>,
+    debug_flag: Arc<AtomicBool>,
+    log_file: Arc<Mutex<Option<File>>>,
 ) -> Result<cpal::Stream> {
-    let err_fn = |err| eprintln!("Output stream error: {}", err);
+    let err_fn = stream_error_handler("playback", state.clone(), debug_flag, log_file);
 
     // Use VecDeque for O(1) pop_front instead of Vec's O(n) remove(0)
     let buffer: Arc<std::sync::Mutex<VecDeque<f32>>> = Arc::new(std::sync::Mutex::new(VecDeque::new()));
-    let buffer_clone = buffer.clone();
+    // Weak, so the feeder stops once the stream is dropped for another device
+    let buffer_weak = Arc::downgrade(&buffer);
 
+    let state_rx = state.clone();
     thread::spawn(move || {
         while let Ok(samples) = rx.recv() {
-            let floats: Vec<f32> = samples.iter().map(|&s| s as f32 / 32768.0).collect();
-            if let Ok(mut buf) = buffer_clone.lock() {
-                buf.extend(floats);
-                // Keep max ~50ms of audio (2400 samples at 48kHz) to minimize latency
-                let max_samples = 48000 / 20;
-                while buf.len() > max_samples {
-                    buf.pop_front();
-                }
+            let Some(buffer) = buffer_weak.upgrade() else {
+                break;
+            };
+            if let Some(depth) = state_rx.playout.lock().as_mut() {
+                depth.arrival(Instant::now(), samples.len(), state_rx.playback_buffer.underruns());
+                state_rx.playback_buffer.set_target(depth.target(), depth.limit());
             }
+            let floats: Vec<f32> = samples.iter().map(|&s| convert::from_i16(s)).collect();
+            if let Ok(mut buf) = buffer.lock() {
+                buf.extend(floats);
+                // Keep max ~50ms of audio (or the adaptive limit) to minimize latency
+                let dropped = buf.len().saturating_sub(state_rx.playback_buffer.limit());
+                buf.drain(..dropped);
+                state_rx.playback_buffer.record_arrival(buf.len(), dropped);
+            };
         }
     });
 
+    // Set after running dry with an adaptive buffer, until it's back at its target
+    let mut refilling = false;
+    // The buffer stays at 48 kHz, so its depth means the same on any device; a
+    // device at another rate plays from `resampled`, topped up from it
+    let output_rate = config.sample_rate.0;
+    let mut resampler = (output_rate != TARGET_SAMPLE_RATE).then(|| {
+        resample::Resampler::new(1, TARGET_SAMPLE_RATE, output_rate, resample::Quality::load(&state.resample_quality))
+    });
+    let mut resampled: VecDeque<f32> = VecDeque::new();
     let stream = device.build_output_stream(
         config,
         move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+            state.streams.playback_callback(data.len(), channels);
+            // Still drain the buffer while muted so unmuting doesn't play stale audio
+            let gain = if state.mute_mic.load(Ordering::Relaxed) { 0.0 } else { state.gain_mic.get() };
             if let Ok(mut buf) = buffer.lock() {
-                if channels == 2 {
+                let needed = if channels == 2 { data.len().div_ceil(2) } else { data.len() };
+                let refill = state.playback_buffer.refill_depth();
+                refilling &= buf.len() < refill.max(needed);
+                if let Some(resampler) = resampler.as_mut().filter(|_| !refilling) {
+                    while resampled.len() < needed && !buf.is_empty() {
+                        let take = resampler.input_for(needed - resampled.len()).clamp(1, buf.len());
+                        resampled.extend(resampler.process(&buf.make_contiguous()[..take]));
+                        buf.drain(..take);
+                    }
+                }
+                let source = if resampler.is_some() { &mut resampled } else { &mut *buf };
+                let missing = if refilling { needed } else { needed.saturating_sub(source.len()) };
+                if refilling {
+                    data.fill(0.0);
+                } else if channels == 2 {
                     for chunk in data.chunks_mut(2) {
-                        let sample = buf.pop_front().unwrap_or(0.0);
+                        let sample = source.pop_front().unwrap_or(0.0) * gain;
                         chunk[0] = sample;
                         if chunk.len() > 1 {
                             chunk[1] = sample;
@@ -1035,10 +6117,16 @@ fn build_output_stream(
                     }
                 } else {
                     for sample in data.iter_mut() {
-                        *sample = buf.pop_front().unwrap_or(0.0);
+                        *sample = source.pop_front().unwrap_or(0.0) * gain;
                     }
                 }
+                refilling = missing > 0 && refill > 0;
+                state.playback_buffer.record_playback(buf.len(), missing);
             }
+            state.peak_mic.record(data);
+            state.meter_mic.record(data);
+            state.saturation_mic.quantize(data);
+            state.cpu_times.record(cpu::Part::Playback);
         },
         err_fn,
         None,
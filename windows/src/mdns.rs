@@ -0,0 +1,331 @@
+// Announces this PC on the LAN as a DNS-SD service over multicast DNS
+// (_budbridge._udp.local), so the iPhone app can list PCs instead of having an
+// address typed in. A minimal responder: it answers questions about the service,
+// its instance and its host name, re-announces every minute, and says goodbye
+// (TTL 0) when stopped. There is no probing for name conflicts; two PCs with the
+// same friendly name show up as one until one is renamed.
+//
+// The TXT record carries the friendly name and the IPv4 address the phone
+// should use, so it can save the PC without resolving anything.
+//
+// Windows runs its own mDNS responder on port 5353, so the socket is bound with
+// SO_REUSEADDR to share the port with it.
+
+use anyhow::{anyhow, Result};
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SERVICE: [&str; 3] = ["_budbridge", "_udp", "local"];
+const GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const PORT: u16 = 5353;
+const TTL: u32 = 120;
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(60);
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+const CACHE_FLUSH: u16 = 0x8000; // records only this PC answers for
+
+/// What the PC announces about itself
+#[derive(Clone)]
+pub struct Service {
+    pub name: String, // shown on the phone
+    pub port: u16,
+}
+
+impl Service {
+    /// A DNS label is at most 63 bytes
+    fn instance_label(&self) -> &str {
+        let mut end = self.name.len().min(63);
+        while !self.name.is_char_boundary(end) {
+            end -= 1;
+        }
+        &self.name[..end]
+    }
+
+    /// "<name>-budbridge.local", letters, digits and hyphens only. Not the
+    /// computer name, which Windows already answers for.
+    fn host_label(&self) -> String {
+        let mut host: String = self
+            .name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+            .collect();
+        host.truncate(40);
+        let host = host.trim_matches('-');
+        if host.is_empty() {
+            "budbridge".to_string()
+        } else {
+            format!("{}-budbridge", host)
+        }
+    }
+}
+
+/// The name this PC goes by unless the user picks one
+pub fn default_name() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_else(|_| "BudBridge PC".to_string())
+}
+
+fn write_name(out: &mut Vec<u8>, labels: &[&str]) {
+    for label in labels {
+        out.push(label.len() as u8);
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(0);
+}
+
+fn write_record(out: &mut Vec<u8>, name: &[&str], kind: u16, class: u16, ttl: u32, data: &[u8]) {
+    write_name(out, name);
+    out.extend_from_slice(&kind.to_be_bytes());
+    out.extend_from_slice(&class.to_be_bytes());
+    out.extend_from_slice(&ttl.to_be_bytes());
+    out.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    out.extend_from_slice(data);
+}
+
+/// All four records in one response; TTL 0 withdraws them
+pub fn response(service: &Service, addr: Ipv4Addr, ttl: u32) -> Vec<u8> {
+    let instance = service.instance_label();
+    let host = service.host_label();
+    let instance_name = [instance, SERVICE[0], SERVICE[1], SERVICE[2]];
+    let host_name = [host.as_str(), "local"];
+
+    let mut out = Vec::new();
+    // ID 0, authoritative answer, no questions, four answers
+    out.extend_from_slice(&[0, 0, 0x84, 0, 0, 0, 0, 4, 0, 0, 0, 0]);
+
+    let mut ptr = Vec::new();
+    write_name(&mut ptr, &instance_name);
+    write_record(&mut out, &SERVICE, TYPE_PTR, CLASS_IN, ttl, &ptr);
+
+    let mut srv = vec![0, 0, 0, 0]; // priority, weight
+    srv.extend_from_slice(&service.port.to_be_bytes());
+    write_name(&mut srv, &host_name);
+    write_record(&mut out, &instance_name, TYPE_SRV, CLASS_IN | CACHE_FLUSH, ttl, &srv);
+
+    let mut txt = Vec::new();
+    for entry in ["txtvers=1".to_string(), format!("name={}", instance), format!("addr={}", addr)] {
+        let entry = &entry.as_bytes()[..entry.len().min(255)];
+        txt.push(entry.len() as u8);
+        txt.extend_from_slice(entry);
+    }
+    write_record(&mut out, &instance_name, TYPE_TXT, CLASS_IN | CACHE_FLUSH, ttl, &txt);
+
+    write_record(&mut out, &host_name, TYPE_A, CLASS_IN | CACHE_FLUSH, ttl, &addr.octets());
+    out
+}
+
+/// The name at `pos`, lowercased, following compression pointers, and where the
+/// record continues after it
+fn read_name(packet: &[u8], mut pos: usize) -> Option<(Vec<String>, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    for _ in 0..64 {
+        let len = *packet.get(pos)? as usize;
+        match len {
+            0 => return Some((labels, end.unwrap_or(pos + 1))),
+            0xc0.. => {
+                let target = ((len & 0x3f) << 8) | *packet.get(pos + 1)? as usize;
+                end.get_or_insert(pos + 2);
+                pos = target;
+            }
+            _ => {
+                let label = packet.get(pos + 1..pos + 1 + len)?;
+                labels.push(String::from_utf8_lossy(label).to_lowercase());
+                pos += 1 + len;
+            }
+        }
+    }
+    None // a pointer loop
+}
+
+/// Whether a query asks about anything this PC answers for
+pub fn asks_for(packet: &[u8], service: &Service) -> bool {
+    if packet.len() < 12 || packet[2] & 0x80 != 0 {
+        return false; // too short, or a response
+    }
+    let lower = |labels: &[&str]| labels.iter().map(|l| l.to_lowercase()).collect::<Vec<_>>();
+    let service_name = lower(&SERVICE);
+    let instance_name = lower(&[service.instance_label(), SERVICE[0], SERVICE[1], SERVICE[2]]);
+    let host_name = lower(&[&service.host_label(), "local"]);
+
+    let questions = u16::from_be_bytes([packet[4], packet[5]]);
+    let mut pos = 12;
+    for _ in 0..questions {
+        let Some((name, next)) = read_name(packet, pos) else {
+            return false;
+        };
+        let Some(kind) = packet.get(next..next + 2).map(|b| u16::from_be_bytes([b[0], b[1]])) else {
+            return false;
+        };
+        pos = next + 4; // type and class
+        let wanted = |types: &[u16]| kind == TYPE_ANY || types.contains(&kind);
+        if (name == service_name && wanted(&[TYPE_PTR]))
+            || (name == instance_name && wanted(&[TYPE_SRV, TYPE_TXT]))
+            || (name == host_name && wanted(&[TYPE_A]))
+        {
+            return true;
+        }
+    }
+    false
+}
+
+/// The address other hosts on the LAN reach this PC at (no packets are sent)
+fn local_ipv4() -> Option<Ipv4Addr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect((GROUP, PORT)).ok()?;
+    match socket.local_addr().ok()?.ip() {
+        std::net::IpAddr::V4(ip) if !ip.is_unspecified() => Some(ip),
+        _ => None,
+    }
+}
+
+pub struct Announcer {
+    stop_flag: Arc<AtomicBool>,
+}
+
+impl Announcer {
+    pub fn start(service: Service) -> Result<Self> {
+        let socket = bind_shared(PORT)?;
+        socket.join_multicast_v4(&GROUP, &Ipv4Addr::UNSPECIFIED)?;
+        socket.set_multicast_ttl_v4(255)?;
+        socket.set_read_timeout(Some(Duration::from_millis(250)))?;
+
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        let stop = stop_flag.clone();
+        thread::spawn(move || {
+            let group = SocketAddr::from((GROUP, PORT));
+            let send = |ttl: u32| {
+                if let Some(addr) = local_ipv4() {
+                    let _ = socket.send_to(&response(&service, addr, ttl), group);
+                }
+            };
+            // Twice a second apart when starting, as RFC 6762 asks, then now and then
+            let mut announced = 0;
+            let mut next_announce = Instant::now();
+            let mut buf = [0u8; 9000];
+            while !stop.load(Ordering::SeqCst) {
+                if Instant::now() >= next_announce {
+                    send(TTL);
+                    announced += 1;
+                    let wait = if announced < 2 { Duration::from_secs(1) } else { ANNOUNCE_INTERVAL };
+                    next_announce = Instant::now() + wait;
+                }
+                if let Ok((len, _)) = socket.recv_from(&mut buf) {
+                    if asks_for(&buf[..len], &service) {
+                        send(TTL);
+                    }
+                }
+            }
+            send(0);
+        });
+        Ok(Self { stop_flag })
+    }
+}
+
+impl Drop for Announcer {
+    fn drop(&mut self) {
+        self.stop_flag.store(true, Ordering::SeqCst);
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn bind_shared(port: u16) -> Result<UdpSocket> {
+    use std::mem;
+    use std::os::windows::io::FromRawSocket;
+    use windows_sys::Win32::Networking::WinSock::{
+        bind, closesocket, setsockopt, socket, WSAGetLastError, WSAStartup, AF_INET, INVALID_SOCKET, IPPROTO_UDP,
+        SOCKADDR, SOCKADDR_IN, SOCK_DGRAM, SOL_SOCKET, SO_REUSEADDR, WSADATA,
+    };
+
+    unsafe {
+        // std only initializes Winsock when it creates a socket itself
+        let mut data: WSADATA = mem::zeroed();
+        let err = WSAStartup(0x0202, &mut data);
+        if err != 0 {
+            return Err(anyhow!("WSAStartup failed ({})", err));
+        }
+
+        let sock = socket(AF_INET as i32, SOCK_DGRAM, IPPROTO_UDP);
+        if sock == INVALID_SOCKET {
+            return Err(anyhow!("Could not create the mDNS socket ({})", WSAGetLastError()));
+        }
+        let on: u32 = 1;
+        let mut addr: SOCKADDR_IN = mem::zeroed();
+        addr.sin_family = AF_INET;
+        addr.sin_port = port.to_be();
+        let failed = setsockopt(sock, SOL_SOCKET, SO_REUSEADDR, &on as *const u32 as *const u8, 4) != 0
+            || bind(sock, &addr as *const SOCKADDR_IN as *const SOCKADDR, mem::size_of::<SOCKADDR_IN>() as i32) != 0;
+        if failed {
+            let code = WSAGetLastError();
+            closesocket(sock);
+            return Err(anyhow!("Could not listen on UDP port {} ({})", port, code));
+        }
+        Ok(UdpSocket::from_raw_socket(sock as _))
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn bind_shared(port: u16) -> Result<UdpSocket> {
+    UdpSocket::bind(("0.0.0.0", port)).map_err(|e| anyhow!("Could not listen on UDP port {} ({})", port, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A query for `first` labels before the service type and `kind`, the way
+    /// resolvers send it: the service type compressed behind a pointer to an
+    /// earlier question (which itself asks for nothing this PC has)
+    fn query(first: &[&str], kind: u16) -> Vec<u8> {
+        let mut packet = vec![0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0];
+        write_name(&mut packet, &SERVICE);
+        packet.extend_from_slice(&[0, TYPE_A as u8, 0, 1]);
+        for label in first {
+            packet.push(label.len() as u8);
+            packet.extend_from_slice(label.as_bytes());
+        }
+        packet.extend_from_slice(&[0xc0, 12]);
+        packet.extend_from_slice(&kind.to_be_bytes());
+        packet.extend_from_slice(&[0, 1]);
+        packet
+    }
+
+    #[test]
+    fn answers_questions_about_this_pc_only() {
+        let service = Service { name: "Studio PC".to_string(), port: 4810 };
+        assert!(asks_for(&query(&[], TYPE_PTR), &service));
+        assert!(asks_for(&query(&["studio pc"], TYPE_TXT), &service));
+        assert!(!asks_for(&query(&["Other PC"], TYPE_SRV), &service));
+
+        let mut other = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        write_name(&mut other, &["_airplay", "_tcp", "local"]);
+        other.extend_from_slice(&[0, TYPE_PTR as u8, 0, 1]);
+        assert!(!asks_for(&other, &service));
+        // Its own announcements are responses, not questions
+        assert!(!asks_for(&response(&service, Ipv4Addr::new(192, 168, 1, 5), TTL), &service));
+    }
+
+    #[test]
+    fn response_names_the_instance_host_and_address() {
+        let service = Service { name: "Studio PC".to_string(), port: 4810 };
+        let packet = response(&service, Ipv4Addr::new(192, 168, 1, 5), TTL);
+        assert_eq!(u16::from_be_bytes([packet[6], packet[7]]), 4);
+
+        let (name, next) = read_name(&packet, 12).unwrap();
+        assert_eq!(name, ["_budbridge", "_udp", "local"]);
+        let (instance, _) = read_name(&packet, next + 10).unwrap();
+        assert_eq!(instance, ["studio pc", "_budbridge", "_udp", "local"]);
+
+        let text = String::from_utf8_lossy(&packet);
+        assert!(text.contains("studio-pc-budbridge"));
+        assert!(text.contains("addr=192.168.1.5"));
+        assert!(packet.ends_with(&[192, 168, 1, 5]));
+    }
+}
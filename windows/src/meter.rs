@@ -0,0 +1,139 @@
+// Level meters for each direction, fed by the audio callbacks themselves: the
+// capture callback with the frames it hands the network thread (after gain,
+// processing and mute), the playback callback with what it gives the device.
+// signal.rs's levels come from the network thread and only say whether audio
+// flows; these show what the sound card sees, as it happens.
+//
+// A callback folds each buffer into a shared peak and sum of squares. The UI
+// takes both once a frame, so nothing between two repaints is missed, and gives
+// the bars meter ballistics. The peak bar falls back at 20 dB/s and leaves a tick
+// at the highest peak for 1.5 s. The RMS bar follows with a 300 ms time constant,
+// like a VU meter.
+
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// The bottom of the bars, and the reading for silence
+pub const FLOOR_DB: f32 = -60.0;
+const PEAK_FALL_DB_PER_SEC: f32 = 20.0;
+const PEAK_HOLD: Duration = Duration::from_millis(1500);
+const RMS_TIME_CONSTANT_SECS: f32 = 0.3;
+/// Squares are summed as fixed point, so callbacks can add them atomically
+const SQUARE_SCALE: f64 = (1u64 << 32) as f64;
+
+/// What the callbacks measured since the UI last took it
+#[derive(Default)]
+pub struct Meter {
+    peak: AtomicU32, // f32 bits; fetch_max works on them (see clip::PeakHold)
+    sum_squares: AtomicU64,
+    samples: AtomicU64,
+}
+
+impl Meter {
+    /// Called by an audio callback for each buffer; never blocks
+    pub fn record(&self, samples: &[f32]) {
+        let (mut peak, mut sum) = (0.0f32, 0.0f64);
+        for &sample in samples.iter().filter(|s| s.is_finite()) {
+            peak = peak.max(sample.abs());
+            sum += (sample as f64).powi(2);
+        }
+        self.peak.fetch_max(peak.to_bits(), Ordering::Relaxed);
+        self.sum_squares.fetch_add((sum * SQUARE_SCALE) as u64, Ordering::Relaxed);
+        self.samples.fetch_add(samples.len() as u64, Ordering::Relaxed);
+    }
+
+    /// Peak and RMS in dBFS since the last take; None when no buffer came
+    pub fn take(&self) -> Option<(f32, f32)> {
+        let samples = self.samples.swap(0, Ordering::Relaxed);
+        let peak = f32::from_bits(self.peak.swap(0, Ordering::Relaxed));
+        let sum = self.sum_squares.swap(0, Ordering::Relaxed) as f64 / SQUARE_SCALE;
+        (samples > 0).then(|| (to_db(peak), to_db((sum / samples as f64).sqrt() as f32)))
+    }
+}
+
+fn to_db(level: f32) -> f32 {
+    if level > 0.0 {
+        (20.0 * level.log10()).max(FLOOR_DB)
+    } else {
+        FLOOR_DB
+    }
+}
+
+/// What a meter's bars show, moving at meter speed rather than buffer speed
+#[derive(Clone, Copy, Debug)]
+pub struct Ballistics {
+    pub peak_db: f32,
+    pub rms_db: f32,
+    pub hold_db: f32,
+    hold_since: Option<Instant>,
+    last: Option<Instant>,
+}
+
+impl Default for Ballistics {
+    fn default() -> Self {
+        Self { peak_db: FLOOR_DB, rms_db: FLOOR_DB, hold_db: FLOOR_DB, hold_since: None, last: None }
+    }
+}
+
+impl Ballistics {
+    /// Moves the bars on to `now` with a reading from Meter::take. No reading
+    /// counts as silence, so the bars fall when the audio stops.
+    pub fn update(&mut self, reading: Option<(f32, f32)>, now: Instant) {
+        let first = self.last.is_none();
+        let elapsed = self.last.map_or(0.0, |last| now.duration_since(last).as_secs_f32());
+        self.last = Some(now);
+        let (peak, rms) = reading.unwrap_or((FLOOR_DB, FLOOR_DB));
+
+        self.peak_db = peak.max(self.peak_db - PEAK_FALL_DB_PER_SEC * elapsed).max(FLOOR_DB);
+        let held = self.hold_since.is_some_and(|since| now.duration_since(since) < PEAK_HOLD);
+        if peak >= self.hold_db || !held {
+            self.hold_db = peak;
+            self.hold_since = Some(now);
+        }
+        // Exponential approach in the dB domain
+        let follow = 1.0 - (-elapsed / RMS_TIME_CONSTANT_SECS).exp();
+        self.rms_db = if first { rms } else { self.rms_db + (rms - self.rms_db) * follow };
+    }
+
+    /// Still moving, so worth another frame once the audio has stopped
+    pub fn settling(&self) -> bool {
+        self.peak_db > FLOOR_DB || self.rms_db > FLOOR_DB + 0.5 || self.hold_db > FLOOR_DB
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn meters_catch_every_buffer_and_fall_back_slowly() {
+        let meter = Meter::default();
+        assert_eq!(meter.take(), None);
+
+        // Two buffers between frames: the peak of either, the RMS of both
+        meter.record(&[0.5, -0.5]);
+        meter.record(&[0.0, 0.0]);
+        let (peak, rms) = meter.take().unwrap();
+        assert!((peak + 6.02).abs() < 0.01 && (rms + 9.03).abs() < 0.01, "{} {}", peak, rms);
+        assert_eq!(meter.take(), None);
+        meter.record(&[0.0, f32::NAN]);
+        assert_eq!(meter.take(), Some((FLOOR_DB, FLOOR_DB)));
+
+        let start = Instant::now();
+        let mut bars = Ballistics::default();
+        bars.update(Some((-6.0, -9.0)), start);
+        assert_eq!((bars.peak_db, bars.rms_db, bars.hold_db), (-6.0, -9.0, -6.0));
+
+        // Half a second of silence: the peak falls 10 dB, the tick stays
+        bars.update(None, start + Duration::from_millis(500));
+        assert!((bars.peak_db + 16.0).abs() < 0.01, "{}", bars.peak_db);
+        assert_eq!(bars.hold_db, -6.0);
+        assert!(bars.rms_db < -9.0 && bars.rms_db > FLOOR_DB);
+
+        // After the hold, the tick drops to what's there now; in time it all settles
+        bars.update(None, start + Duration::from_secs(2));
+        assert_eq!(bars.hold_db, FLOOR_DB);
+        bars.update(None, start + Duration::from_secs(10));
+        assert!(!bars.settling());
+    }
+}
@@ -0,0 +1,131 @@
+// MIDI control surface mapping: one CC drives the PC → iPhone gain, one note
+// toggles its mute. Bindings are captured with a learn mode instead of typing
+// channel/controller numbers.
+
+use crate::control::ControlCommand;
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use eframe::egui;
+use midir::{Ignore, MidiInput, MidiInputConnection};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+const CLIENT_NAME: &str = "BudBridge";
+
+/// (channel 0-15, controller or note number)
+pub type MidiBinding = (u8, u8);
+
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub struct MidiMapping {
+    pub gain_cc: Option<MidiBinding>,
+    pub mute_note: Option<MidiBinding>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LearnTarget {
+    Gain,
+    Mute,
+}
+
+pub fn format_binding(binding: Option<MidiBinding>) -> String {
+    match binding {
+        Some((channel, number)) => format!("ch {} #{}", channel + 1, number),
+        None => "not set".to_string(),
+    }
+}
+
+/// Parse "channel:number" as stored in settings.txt
+pub fn parse_binding(text: &str) -> Option<MidiBinding> {
+    let (channel, number) = text.split_once(':')?;
+    Some((channel.parse().ok()?, number.parse().ok()?))
+}
+
+pub fn binding_setting(binding: Option<MidiBinding>) -> String {
+    binding.map(|(c, n)| format!("{}:{}", c, n)).unwrap_or_default()
+}
+
+pub fn list_ports() -> Vec<String> {
+    let Ok(input) = MidiInput::new(CLIENT_NAME) else {
+        return Vec::new();
+    };
+    input
+        .ports()
+        .iter()
+        .filter_map(|p| input.port_name(p).ok())
+        .collect()
+}
+
+/// Closes the MIDI port when dropped
+pub struct MidiListener {
+    _connection: MidiInputConnection<()>,
+}
+
+impl MidiListener {
+    pub fn start(
+        port_name: &str,
+        mapping: Arc<Mutex<MidiMapping>>,
+        learn: Arc<Mutex<Option<LearnTarget>>>,
+        commands: Sender<ControlCommand>,
+        ctx: egui::Context,
+    ) -> Result<Self> {
+        let mut input = MidiInput::new(CLIENT_NAME).map_err(|e| anyhow!("{}", e))?;
+        input.ignore(Ignore::All);
+
+        let port = input
+            .ports()
+            .into_iter()
+            .find(|p| input.port_name(p).ok().as_deref() == Some(port_name))
+            .ok_or_else(|| anyhow!("MIDI port \"{}\" not found", port_name))?;
+
+        let connection = input
+            .connect(
+                &port,
+                "budbridge-control",
+                move |_, message, _| {
+                    handle_message(message, &mapping, &learn, &commands);
+                    ctx.request_repaint();
+                },
+                (),
+            )
+            .map_err(|e| anyhow!("{}", e))?;
+
+        Ok(Self { _connection: connection })
+    }
+}
+
+fn handle_message(
+    message: &[u8],
+    mapping: &Mutex<MidiMapping>,
+    learn: &Mutex<Option<LearnTarget>>,
+    commands: &Sender<ControlCommand>,
+) {
+    let [status, number, value] = match message {
+        [s, n, v, ..] => [*s, *n, *v],
+        _ => return,
+    };
+    let channel = status & 0x0F;
+
+    match status & 0xF0 {
+        // Control change
+        0xB0 => {
+            let mut learn = learn.lock();
+            if *learn == Some(LearnTarget::Gain) {
+                mapping.lock().gain_cc = Some((channel, number));
+                *learn = None;
+            } else if mapping.lock().gain_cc == Some((channel, number)) {
+                let _ = commands.try_send(ControlCommand::SetGainOut(value as f32 / 127.0));
+            }
+        }
+        // Note on (velocity 0 is a note off in running-status streams)
+        0x90 if value > 0 => {
+            let mut learn = learn.lock();
+            if *learn == Some(LearnTarget::Mute) {
+                mapping.lock().mute_note = Some((channel, number));
+                *learn = None;
+            } else if mapping.lock().mute_note == Some((channel, number)) {
+                let _ = commands.try_send(ControlCommand::ToggleMuteOut);
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,246 @@
+// Versioned upgrades for the config folder. settings.txt records the schema as
+// "schema_version=N"; no such line means 0, from before versioning existed. At
+// startup every step from the stored version up to CURRENT_VERSION runs in order,
+// then the changed files are saved once.
+//
+// Steps are pure functions over the file contents, so each one is deterministic
+// and tested on its own. To change a file format: bump CURRENT_VERSION, append a
+// step to STEPS and add a test for it below. The loaders in main.rs keep their
+// lenient fallbacks for hand-edited files.
+
+use crate::config;
+
+pub const CURRENT_VERSION: u32 = 3;
+pub const VERSION_KEY: &str = "schema_version";
+
+/// The text of each config file; missing files are empty
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConfigFiles {
+    pub devices: String,
+    pub default_device: String,
+    pub settings: String,
+    pub profiles: String,
+}
+
+struct Step {
+    description: &'static str,
+    apply: fn(&mut ConfigFiles),
+}
+
+/// STEPS[n] upgrades version n to n + 1
+const STEPS: [Step; CURRENT_VERSION as usize] = [
+    Step { description: "give every saved device an id", apply: add_device_ids },
+    Step { description: "refer to devices by id instead of name", apply: reference_devices_by_id },
+    Step { description: "add per-direction codecs to profiles", apply: add_profile_codecs },
+];
+
+pub enum Outcome {
+    UpToDate,
+    /// The descriptions of the steps that ran
+    Upgraded(Vec<&'static str>),
+    /// Written by a newer BudBridge; left untouched
+    TooNew(u32),
+}
+
+pub fn stored_version(files: &ConfigFiles) -> u32 {
+    setting(&files.settings, VERSION_KEY).and_then(|v| v.parse().ok()).unwrap_or(0)
+}
+
+/// Bring `files` up to CURRENT_VERSION
+pub fn upgrade(files: &mut ConfigFiles) -> Outcome {
+    let version = stored_version(files);
+    if version > CURRENT_VERSION {
+        return Outcome::TooNew(version);
+    }
+    if version == CURRENT_VERSION {
+        return Outcome::UpToDate;
+    }
+    let mut applied = Vec::new();
+    for step in &STEPS[version as usize..] {
+        (step.apply)(files);
+        applied.push(step.description);
+    }
+    set_setting(&mut files.settings, VERSION_KEY, &CURRENT_VERSION.to_string());
+    Outcome::Upgraded(applied)
+}
+
+fn setting<'a>(settings: &'a str, key: &str) -> Option<&'a str> {
+    settings
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .find(|(k, _)| k.trim() == key)
+        .map(|(_, v)| v.trim())
+}
+
+fn set_setting(settings: &mut String, key: &str, value: &str) {
+    let mut lines: Vec<String> = settings
+        .lines()
+        .filter(|line| line.split_once('=').is_none_or(|(k, _)| k.trim() != key))
+        .map(str::to_string)
+        .collect();
+    lines.push(format!("{}={}", key, value));
+    *settings = lines.join("\n");
+}
+
+fn map_lines(text: &str, mut map: impl FnMut(Vec<String>) -> Vec<String>) -> String {
+    text.lines()
+        .map(|line| {
+            if line.trim().is_empty() {
+                return line.to_string();
+            }
+            let fields = map(config::split_fields(line));
+            let refs: Vec<&str> = fields.iter().map(String::as_str).collect();
+            config::join_fields(&refs)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// FNV-1a, for ids that come out the same on every run
+fn fnv1a(bytes: &[u8], seed: u64) -> u64 {
+    bytes.iter().fold(seed, |hash, &b| (hash ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+/// 0 → 1: devices.txt went from name|ip to name|ip|id. The id is derived from
+/// the line so the same file always migrates the same way.
+fn add_device_ids(files: &mut ConfigFiles) {
+    let mut index = 0u64;
+    files.devices = map_lines(&files.devices, |mut fields| {
+        index += 1;
+        if fields.len() == 2 {
+            let line = fields.join("|");
+            let high = fnv1a(line.as_bytes(), 0xcbf29ce484222325 ^ index);
+            let low = fnv1a(line.as_bytes(), 0x84222325cbf29ce4 ^ index);
+            let hex = format!("{:016x}{:016x}", high, low);
+            fields.push(format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..]));
+        }
+        fields
+    });
+}
+
+/// 1 → 2: default.txt and profile targets named a device; they now hold its id
+fn reference_devices_by_id(files: &mut ConfigFiles) {
+    let devices: Vec<(String, String)> = files
+        .devices
+        .lines()
+        .filter_map(|line| match config::split_fields(line).as_slice() {
+            [name, _, id] => Some((name.clone(), id.clone())),
+            _ => None,
+        })
+        .collect();
+    let to_id = |reference: &str| -> Option<String> {
+        if devices.iter().any(|(_, id)| id == reference) {
+            return None;
+        }
+        devices.iter().find(|(name, _)| name == reference).map(|(_, id)| id.clone())
+    };
+
+    if let Some(id) = to_id(files.default_device.trim()) {
+        files.default_device = id;
+    }
+    files.profiles = map_lines(&files.profiles, |mut fields| {
+        if let Some(id) = fields.get(1).and_then(|target| to_id(target)) {
+            fields[1] = id;
+        }
+        fields
+    });
+}
+
+/// 2 → 3: profiles gained the down and up codecs, which were PCM mono before
+fn add_profile_codecs(files: &mut ConfigFiles) {
+    files.profiles = map_lines(&files.profiles, |mut fields| {
+        if fields.len() == 8 {
+            fields.push("pcm/1/768".to_string());
+            fields.push("pcm/1/768".to_string());
+        }
+        fields
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v0_files() -> ConfigFiles {
+        ConfigFiles {
+            devices: "Kim's iPhone|192.168.1.20\nStudio|10.0.0.2".to_string(),
+            default_device: "Studio".to_string(),
+            settings: "debug=true".to_string(),
+            profiles: "Calls|Kim's iPhone|Mic|CABLE Input|1|1.5|false|false".to_string(),
+        }
+    }
+
+    #[test]
+    fn step_0_adds_stable_device_ids() {
+        let mut first = v0_files();
+        add_device_ids(&mut first);
+        let mut second = v0_files();
+        add_device_ids(&mut second);
+        assert_eq!(first, second);
+
+        let lines: Vec<Vec<String>> = first.devices.lines().map(config::split_fields).collect();
+        assert!(lines.iter().all(|fields| fields.len() == 3 && fields[2].len() == 36));
+        assert_ne!(lines[0][2], lines[1][2]);
+        assert_eq!(lines[0][..2], ["Kim's iPhone", "192.168.1.20"]);
+
+        // Devices that already have an id keep it
+        let mut files = ConfigFiles { devices: "Kim|1.2.3.4|abc".to_string(), ..Default::default() };
+        add_device_ids(&mut files);
+        assert_eq!(files.devices, "Kim|1.2.3.4|abc");
+    }
+
+    #[test]
+    fn step_1_replaces_device_names_with_ids() {
+        let mut files = ConfigFiles {
+            devices: "Kim's iPhone|192.168.1.20|id-kim\nStudio|10.0.0.2|id-studio".to_string(),
+            default_device: "Studio\n".to_string(),
+            profiles: "Calls|Kim's iPhone|Mic|CABLE Input|1|1.5|false|false\nMusic|id-studio|a|b|1|1|false|false"
+                .to_string(),
+            ..Default::default()
+        };
+        reference_devices_by_id(&mut files);
+        assert_eq!(files.default_device, "id-studio");
+        let targets: Vec<String> = files.profiles.lines().map(|l| config::split_fields(l)[1].clone()).collect();
+        assert_eq!(targets, ["id-kim", "id-studio"]);
+    }
+
+    #[test]
+    fn step_2_adds_pcm_codecs_to_profiles() {
+        let mut files = ConfigFiles {
+            profiles: "Calls|id|Mic|Out|1|1|false|false\nMusic|id|a|b|1|1|false|false|opus/2/128|opus/1/32".to_string(),
+            ..Default::default()
+        };
+        add_profile_codecs(&mut files);
+        let lines: Vec<Vec<String>> = files.profiles.lines().map(config::split_fields).collect();
+        assert_eq!(lines[0][8..], ["pcm/1/768", "pcm/1/768"]);
+        assert_eq!(lines[1][8..], ["opus/2/128", "opus/1/32"]);
+    }
+
+    #[test]
+    fn upgrades_from_unversioned_to_current() {
+        let mut files = v0_files();
+        match upgrade(&mut files) {
+            Outcome::Upgraded(steps) => assert_eq!(steps.len(), CURRENT_VERSION as usize),
+            _ => panic!("expected an upgrade"),
+        }
+        assert_eq!(stored_version(&files), CURRENT_VERSION);
+        assert_eq!(setting(&files.settings, "debug"), Some("true"));
+
+        let device_id = config::split_fields(files.devices.lines().nth(1).unwrap())[2].clone();
+        assert_eq!(files.default_device, device_id);
+        assert_eq!(config::split_fields(&files.profiles).len(), 10);
+
+        // A second run changes nothing
+        let upgraded = files.clone();
+        assert!(matches!(upgrade(&mut files), Outcome::UpToDate));
+        assert_eq!(files, upgraded);
+    }
+
+    #[test]
+    fn leaves_newer_configs_alone() {
+        let mut files = ConfigFiles { settings: format!("{}={}", VERSION_KEY, CURRENT_VERSION + 1), ..v0_files() };
+        let before = files.clone();
+        assert!(matches!(upgrade(&mut files), Outcome::TooNew(v) if v == CURRENT_VERSION + 1));
+        assert_eq!(files, before);
+    }
+}
@@ -0,0 +1,99 @@
+// Classroom multicast: the PC capture sent once to an IP multicast group that
+// any number of phones join, instead of to one phone. It is the usual PC → phone
+// stream with no handshake. The format rides along as a "BBCAST1" announcement
+// once a second (protocol.rs), and nothing comes back, so there is no mic audio,
+// identity check or retry.
+//
+// Wi-Fi is the catch. An access point sends multicast at its lowest basic rate,
+// to every client at once and without retransmission, so a stream that is light
+// over unicast can fill the air and still lose packets. Opus with a redundant
+// frame is the default for that reason, and the TTL of 1 keeps it on the local
+// subnet.
+
+use crate::codec;
+use crate::protocol::{self, Codec, StreamFormat};
+use anyhow::{anyhow, Result};
+use parking_lot::Mutex;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_GROUP: Ipv4Addr = Ipv4Addr::new(239, 66, 66, 1);
+pub const DEFAULT_PORT: u16 = 4830;
+pub const DEFAULT_TTL: u32 = 1;
+pub const TTL_RANGE: RangeInclusive<u32> = 1..=32;
+pub const DEFAULT_FORMAT: StreamFormat = StreamFormat { codec: Codec::Opus, channels: 1, bitrate_kbps: 64 };
+/// Each Opus packet repeats the frame before it, since nobody resends a lost one
+const REDUNDANCY: u8 = 1;
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+/// Keeps PCM datagrams unfragmented
+const MAX_PAYLOAD: usize = 1400;
+
+struct FeedState {
+    socket: UdpSocket,
+    dest: SocketAddr,
+    encoder: codec::Encoder,
+    chunk_size: usize,
+    announcement: String,
+    last_announce: Option<Instant>,
+}
+
+/// Where the capture pipeline hands audio to the group
+#[derive(Clone)]
+pub struct MulticastFeed(Arc<Mutex<FeedState>>);
+
+impl MulticastFeed {
+    /// Encode 48 kHz interleaved stereo and send it to the group, announcing the format first when due
+    pub fn publish(&self, stereo: &[f32]) {
+        let mut feed = self.0.lock();
+        if feed.last_announce.is_none_or(|t| t.elapsed() >= ANNOUNCE_INTERVAL) {
+            let _ = feed.socket.send_to(feed.announcement.as_bytes(), feed.dest);
+            feed.last_announce = Some(Instant::now());
+        }
+        for payload in feed.encoder.encode(stereo) {
+            for chunk in payload.chunks(feed.chunk_size) {
+                let _ = feed.socket.send_to(chunk, feed.dest);
+            }
+        }
+    }
+}
+
+pub struct MulticastSender {
+    feed: MulticastFeed,
+}
+
+impl MulticastSender {
+    /// `local_ip` picks the network interface the group is sent on
+    pub fn start(group: Ipv4Addr, port: u16, ttl: u32, format: StreamFormat, local_ip: Ipv4Addr) -> Result<Self> {
+        if !group.is_multicast() {
+            return Err(anyhow!("{} is not a multicast address", group));
+        }
+        // Binding to the interface's address makes Windows send multicast through it
+        let socket = UdpSocket::bind((local_ip, 0))?;
+        socket.set_multicast_ttl_v4(ttl.clamp(*TTL_RANGE.start(), *TTL_RANGE.end()))?;
+        socket.set_multicast_loop_v4(false)?;
+
+        let encoder = codec::Encoder::new(format, REDUNDANCY)?;
+        let chunk_size = encoder.chunk_size(MAX_PAYLOAD);
+        let feed = MulticastFeed(Arc::new(Mutex::new(FeedState {
+            socket,
+            dest: SocketAddr::new(IpAddr::V4(group), port),
+            encoder,
+            chunk_size,
+            announcement: protocol::cast_announce(format, REDUNDANCY),
+            last_announce: None,
+        })));
+        Ok(Self { feed })
+    }
+
+    pub fn feed(&self) -> MulticastFeed {
+        self.feed.clone()
+    }
+}
+
+/// What one group stream takes on the air, in kbps
+pub fn network_kbps(format: StreamFormat) -> u32 {
+    let redundancy = if format.codec == Codec::Opus { REDUNDANCY } else { 0 };
+    format.network_kbps(redundancy, MAX_PAYLOAD)
+}
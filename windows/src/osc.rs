@@ -0,0 +1,195 @@
+// OSC (Open Sound Control) listener for lighting/audio control surfaces.
+//
+//   /budbridge/connect | disconnect | toggle | cycle     buttons
+//   /budbridge/mute/mic | /budbridge/mute/out            buttons (toggle)
+//   /budbridge/gain/out | /budbridge/gain/mic  <float>   linear gain, 1.0 = unity
+//
+// Buttons fire when sent without arguments or with a value > 0.5, so surfaces that
+// send 1 on press and 0 on release don't trigger twice.
+
+use crate::control::ControlCommand;
+use anyhow::Result;
+use crossbeam_channel::Sender;
+use eframe::egui;
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+pub const DEFAULT_OSC_PORT: u16 = 9000;
+pub const MAX_GAIN: f32 = 2.0;
+
+#[derive(Debug, PartialEq)]
+enum OscArg {
+    Int(i32),
+    Float(f32),
+    Bool(bool),
+    Other,
+}
+
+impl OscArg {
+    fn as_f32(&self) -> Option<f32> {
+        match *self {
+            OscArg::Int(i) => Some(i as f32),
+            OscArg::Float(f) => Some(f),
+            OscArg::Bool(b) => Some(if b { 1.0 } else { 0.0 }),
+            OscArg::Other => None,
+        }
+    }
+}
+
+/// Stops listening when dropped
+pub struct OscListener {
+    stop_flag: Arc<AtomicBool>,
+}
+
+impl OscListener {
+    pub fn start(port: u16, commands: Sender<ControlCommand>, ctx: egui::Context) -> Result<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0", port))?;
+        socket.set_read_timeout(Some(Duration::from_millis(200)))?;
+
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        let stop = stop_flag.clone();
+
+        thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            while !stop.load(Ordering::SeqCst) {
+                if let Ok((len, _)) = socket.recv_from(&mut buf) {
+                    let mut messages = Vec::new();
+                    parse_packet(&buf[..len], &mut messages);
+                    for (address, args) in messages {
+                        if let Some(command) = map_message(&address, &args) {
+                            let _ = commands.try_send(command);
+                            ctx.request_repaint();
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(Self { stop_flag })
+    }
+}
+
+impl Drop for OscListener {
+    fn drop(&mut self) {
+        self.stop_flag.store(true, Ordering::SeqCst);
+    }
+}
+
+fn map_message(address: &str, args: &[OscArg]) -> Option<ControlCommand> {
+    let value = args.first().and_then(|a| a.as_f32());
+    // clamp keeps NaN, and anyone on the LAN can send one
+    if value.is_some_and(|v| !v.is_finite()) {
+        return None;
+    }
+    let pressed = value.is_none_or(|v| v > 0.5);
+
+    let command = match address {
+        "/budbridge/gain/out" => return value.map(|v| ControlCommand::SetGainOut(v.clamp(0.0, MAX_GAIN))),
+        "/budbridge/gain/mic" => return value.map(|v| ControlCommand::SetGainMic(v.clamp(0.0, MAX_GAIN))),
+        "/budbridge/connect" => ControlCommand::Connect,
+        "/budbridge/disconnect" => ControlCommand::Disconnect,
+        "/budbridge/toggle" => ControlCommand::ToggleConnection,
+        "/budbridge/cycle" => ControlCommand::CycleDevice,
+        "/budbridge/mute/mic" => ControlCommand::ToggleMuteMic,
+        "/budbridge/mute/out" => ControlCommand::ToggleMuteOut,
+        _ => return None,
+    };
+    pressed.then_some(command)
+}
+
+/// Parse a message or (possibly nested) bundle, appending every message found
+fn parse_packet(data: &[u8], out: &mut Vec<(String, Vec<OscArg>)>) {
+    if data.starts_with(b"#bundle\0") {
+        // "#bundle\0" + 8-byte timetag, then size-prefixed elements
+        let mut pos = 16;
+        while pos + 4 <= data.len() {
+            let size = u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
+            pos += 4;
+            if pos + size > data.len() {
+                return;
+            }
+            parse_packet(&data[pos..pos + size], out);
+            pos += size;
+        }
+    } else if let Some(message) = parse_message(data) {
+        out.push(message);
+    }
+}
+
+fn parse_message(data: &[u8]) -> Option<(String, Vec<OscArg>)> {
+    let (address, mut pos) = read_string(data, 0)?;
+    if !address.starts_with('/') {
+        return None;
+    }
+
+    let mut args = Vec::new();
+    // Type tags are optional in very old OSC senders
+    let Some((tags, next)) = read_string(data, pos).filter(|(t, _)| t.starts_with(',')) else {
+        return Some((address, args));
+    };
+    pos = next;
+
+    for tag in tags.chars().skip(1) {
+        let arg = match tag {
+            'i' | 'f' => {
+                let bytes: [u8; 4] = data.get(pos..pos + 4)?.try_into().ok()?;
+                pos += 4;
+                if tag == 'i' {
+                    OscArg::Int(i32::from_be_bytes(bytes))
+                } else {
+                    OscArg::Float(f32::from_be_bytes(bytes))
+                }
+            }
+            'T' => OscArg::Bool(true),
+            'F' => OscArg::Bool(false),
+            // Unsupported argument types end parsing; earlier args are still usable
+            _ => {
+                args.push(OscArg::Other);
+                break;
+            }
+        };
+        args.push(arg);
+    }
+
+    Some((address, args))
+}
+
+/// Null-terminated string padded to a 4-byte boundary
+fn read_string(data: &[u8], start: usize) -> Option<(String, usize)> {
+    let len = data.get(start..)?.iter().position(|&b| b == 0)?;
+    let text = std::str::from_utf8(&data[start..start + len]).ok()?.to_string();
+    let next = (start + len + 4) & !3;
+    Some((text, next))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One message with a single float argument
+    fn float_message(address: &str, value: f32) -> Vec<u8> {
+        let mut packet = address.as_bytes().to_vec();
+        packet.resize((address.len() / 4 + 1) * 4, 0);
+        packet.extend_from_slice(b",f\0\0");
+        packet.extend_from_slice(&value.to_be_bytes());
+        packet
+    }
+
+    fn commands(packet: &[u8]) -> Vec<ControlCommand> {
+        let mut messages = Vec::new();
+        parse_packet(packet, &mut messages);
+        messages.iter().filter_map(|(address, args)| map_message(address, args)).collect()
+    }
+
+    #[test]
+    fn gains_are_clamped_and_nan_is_dropped() {
+        assert_eq!(commands(&float_message("/budbridge/gain/out", 0.5)), [ControlCommand::SetGainOut(0.5)]);
+        assert_eq!(commands(&float_message("/budbridge/gain/mic", 100.0)), [ControlCommand::SetGainMic(MAX_GAIN)]);
+        assert!(commands(&float_message("/budbridge/gain/out", f32::NAN)).is_empty());
+        assert!(commands(&float_message("/budbridge/gain/mic", f32::INFINITY)).is_empty());
+        assert!(commands(&float_message("/budbridge/connect", f32::NAN)).is_empty());
+    }
+}
@@ -0,0 +1,113 @@
+// Sizes the playback (iPhone → PC) buffer to the link. On a quiet network the
+// phone's packets arrive every 20 ms and a small buffer is enough; on busy or
+// power-saving Wi-Fi they come in bursts, and the buffer has to cover the gaps
+// between them. The target depth follows the arrival jitter (RFC 3550's running
+// estimate of how far each gap strays from the audio it carried), plus a margin
+// that each underrun raises at once and that eases off again once the link has
+// been calm for a while. Up to twice the target is kept before the oldest audio
+// is dropped, so a burst after a gap isn't thrown away.
+
+use std::time::{Duration, Instant};
+
+pub const MIN_MS: f32 = 10.0;
+pub const MAX_MS: f32 = 300.0;
+/// Target depth in jitters: enough for all but the rarest gaps
+const JITTER_FACTOR: f32 = 3.0;
+const UNDERRUN_STEP_MS: f32 = 20.0;
+/// Without an underrun for this long, the margin starts easing off
+const CALM: Duration = Duration::from_secs(10);
+const EASE_MS_PER_SEC: f32 = 2.0;
+const SAMPLES_PER_MS: f32 = 48.0;
+
+pub struct AdaptiveDepth {
+    jitter_ms: f32,
+    margin_ms: f32,
+    last_arrival: Option<Instant>,
+    last_underrun: Instant,
+    underruns: u64,
+}
+
+impl AdaptiveDepth {
+    /// Starts at `start_ms` and adapts from there
+    pub fn new(now: Instant, start_ms: f32) -> Self {
+        Self { jitter_ms: 0.0, margin_ms: start_ms, last_arrival: None, last_underrun: now, underruns: 0 }
+    }
+
+    /// After `samples` of 48 kHz mono arrived; `underruns` is the running count
+    /// from the output callback, so any new ones raise the margin
+    pub fn arrival(&mut self, now: Instant, samples: usize, underruns: u64) {
+        if underruns > self.underruns {
+            self.margin_ms += UNDERRUN_STEP_MS * (underruns - self.underruns) as f32;
+            self.last_underrun = now;
+        }
+        self.underruns = underruns;
+
+        if let Some(last) = self.last_arrival {
+            let gap_ms = now.duration_since(last).as_secs_f32() * 1000.0;
+            let deviation = (gap_ms - samples as f32 / SAMPLES_PER_MS).abs();
+            self.jitter_ms += (deviation - self.jitter_ms) / 16.0;
+            if now.duration_since(self.last_underrun) > CALM {
+                self.margin_ms = (self.margin_ms - EASE_MS_PER_SEC * gap_ms / 1000.0).max(0.0);
+            }
+        }
+        self.last_arrival = Some(now);
+        // Past the cap it can't grow the target any further
+        self.margin_ms = self.margin_ms.min(MAX_MS);
+    }
+
+    pub fn jitter_ms(&self) -> f32 {
+        self.jitter_ms
+    }
+
+    pub fn target_ms(&self) -> f32 {
+        (JITTER_FACTOR * self.jitter_ms + self.margin_ms).clamp(MIN_MS, MAX_MS)
+    }
+
+    /// Depth to refill to after running dry, in samples
+    pub fn target(&self) -> usize {
+        (self.target_ms() * SAMPLES_PER_MS) as usize
+    }
+
+    /// Most audio held before the oldest is dropped, in samples
+    pub fn limit(&self) -> usize {
+        self.target() * 2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PACKET: usize = 960; // 20 ms
+
+    #[test]
+    fn grows_with_jitter_and_underruns_and_eases_off_when_calm() {
+        let start = Instant::now();
+        let mut depth = AdaptiveDepth::new(start, 20.0);
+        let mut now = start;
+        for _ in 0..50 {
+            now += Duration::from_millis(20);
+            depth.arrival(now, PACKET, 0);
+        }
+        assert!((depth.target_ms() - 20.0).abs() < 0.01);
+
+        // Bursts: nothing for 100 ms, then five packets at once
+        for i in 0..100 {
+            now += Duration::from_millis(if i % 5 == 0 { 100 } else { 0 });
+            depth.arrival(now, PACKET, 0);
+        }
+        let bursty = depth.target_ms();
+        assert!(bursty > 80.0, "{}", bursty);
+
+        depth.arrival(now, PACKET, 2);
+        assert!((depth.target_ms() - (bursty + 40.0)).abs() < 5.0);
+
+        // A minute of steady packets brings it back down to the floor
+        for _ in 0..3000 {
+            now += Duration::from_millis(20);
+            depth.arrival(now, PACKET, 2);
+        }
+        assert_eq!(depth.target_ms(), MIN_MS);
+        assert_eq!(depth.limit(), 2 * depth.target());
+    }
+}
@@ -0,0 +1,99 @@
+// Sleep/resume notifications. Audio streams and sockets don't survive a suspend,
+// so the app tears the session down before sleeping and reconnects on resume.
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))] // only raised by the Win32 listener
+pub enum PowerEvent {
+    Suspend,
+    Resume,
+}
+
+#[cfg(target_os = "windows")]
+pub use imp::PowerListener;
+
+#[cfg(not(target_os = "windows"))]
+pub use fallback::PowerListener;
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use super::PowerEvent;
+    use anyhow::{anyhow, Result};
+    use std::ffi::c_void;
+    use std::ptr;
+    use windows_sys::Win32::Foundation::ERROR_SUCCESS;
+    use windows_sys::Win32::System::Power::{
+        PowerRegisterSuspendResumeNotification, PowerUnregisterSuspendResumeNotification,
+        DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        DEVICE_NOTIFY_CALLBACK, PBT_APMRESUMEAUTOMATIC, PBT_APMSUSPEND,
+    };
+
+    type Handler = Box<dyn Fn(PowerEvent) + Send + Sync>;
+
+    /// Unregisters the notification when dropped
+    pub struct PowerListener {
+        registration: *mut c_void,
+        // Both are referenced by the OS until unregistered
+        _params: Box<DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS>,
+        _handler: Box<Handler>,
+    }
+
+    impl PowerListener {
+        pub fn start(on_event: impl Fn(PowerEvent) + Send + Sync + 'static) -> Result<Self> {
+            let handler: Box<Handler> = Box::new(Box::new(on_event));
+            let mut params = Box::new(DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS {
+                Callback: Some(callback),
+                Context: &*handler as *const Handler as *mut c_void,
+            });
+
+            let mut registration = ptr::null_mut();
+            let err = unsafe {
+                PowerRegisterSuspendResumeNotification(
+                    DEVICE_NOTIFY_CALLBACK,
+                    &mut *params as *mut DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS as *mut c_void,
+                    &mut registration,
+                )
+            };
+            if err != ERROR_SUCCESS {
+                return Err(anyhow!("PowerRegisterSuspendResumeNotification failed ({})", err));
+            }
+
+            Ok(Self { registration, _params: params, _handler: handler })
+        }
+    }
+
+    impl Drop for PowerListener {
+        fn drop(&mut self) {
+            unsafe {
+                PowerUnregisterSuspendResumeNotification(self.registration as isize);
+            }
+        }
+    }
+
+    unsafe extern "system" fn callback(context: *const c_void, event: u32, _setting: *const c_void) -> u32 {
+        let handler = &*(context as *const Handler);
+        match event {
+            PBT_APMSUSPEND => handler(PowerEvent::Suspend),
+            // Sent on every resume, including unattended ones; user-initiated resumes
+            // additionally send PBT_APMRESUMESUSPEND, which would double-trigger
+            PBT_APMRESUMEAUTOMATIC => handler(PowerEvent::Resume),
+            _ => {}
+        }
+        ERROR_SUCCESS
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod fallback {
+    use super::PowerEvent;
+    use anyhow::{anyhow, Result};
+
+    pub struct PowerListener;
+
+    impl PowerListener {
+        pub fn start(_on_event: impl Fn(PowerEvent) + Send + Sync + 'static) -> Result<Self> {
+            Err(anyhow!("Sleep/resume notifications are only supported on Windows"))
+        }
+    }
+}
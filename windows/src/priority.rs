@@ -0,0 +1,175 @@
+// Process priority and performance cores, for hybrid CPUs where the audio can
+// land on an efficiency core and glitch. Both are Advanced settings.
+//
+// The priority class (SetPriorityClass) covers every thread of the process and
+// applies as soon as it's picked. Pinning selects the CPU sets of the cores with
+// the highest efficiency class (GetSystemCpuSetInformation) for the capture
+// callback and the network thread (SetThreadSelectedCpuSets), so it takes effect
+// on the next connect. CPU sets are a preference rather than an affinity mask:
+// Windows can still run the thread elsewhere when it has to. A CPU whose cores
+// all share one class has nothing to pin to.
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Priority {
+    #[default]
+    Normal,
+    AboveNormal,
+    High,
+}
+
+impl Priority {
+    pub const ALL: [Priority; 3] = [Priority::Normal, Priority::AboveNormal, Priority::High];
+
+    /// Unknown settings are normal
+    pub fn parse(text: &str) -> Self {
+        match text.trim() {
+            "above_normal" => Priority::AboveNormal,
+            "high" => Priority::High,
+            _ => Priority::Normal,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Normal => "normal",
+            Priority::AboveNormal => "above_normal",
+            Priority::High => "high",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Priority::Normal => "Normal",
+            Priority::AboveNormal => "Above normal",
+            Priority::High => "High",
+        }
+    }
+}
+
+/// The ids of the CPU sets in the highest efficiency class, from (id, class)
+/// pairs. None when every core is in the same class.
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn performance_sets(cpus: &[(u32, u8)]) -> Option<Vec<u32>> {
+    let top = cpus.iter().map(|&(_, class)| class).max()?;
+    let ids: Vec<u32> = cpus.iter().filter(|&&(_, class)| class == top).map(|&(id, _)| id).collect();
+    (ids.len() < cpus.len()).then_some(ids)
+}
+
+#[cfg(target_os = "windows")]
+pub use imp::{core_counts, pin_to_performance_cores, set_process_priority};
+
+#[cfg(not(target_os = "windows"))]
+pub use fallback::{core_counts, pin_to_performance_cores, set_process_priority};
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use super::{performance_sets, Priority};
+    use anyhow::{anyhow, Result};
+    use std::ptr;
+    use windows_sys::Win32::System::SystemInformation::{
+        CpuSetInformation, GetSystemCpuSetInformation, SYSTEM_CPU_SET_INFORMATION,
+    };
+    use windows_sys::Win32::System::Threading::{
+        GetCurrentProcess, GetCurrentThread, SetPriorityClass, SetThreadSelectedCpuSets,
+        ABOVE_NORMAL_PRIORITY_CLASS, HIGH_PRIORITY_CLASS, NORMAL_PRIORITY_CLASS,
+    };
+
+    pub fn set_process_priority(priority: Priority) -> Result<()> {
+        let class = match priority {
+            Priority::Normal => NORMAL_PRIORITY_CLASS,
+            Priority::AboveNormal => ABOVE_NORMAL_PRIORITY_CLASS,
+            Priority::High => HIGH_PRIORITY_CLASS,
+        };
+        if unsafe { SetPriorityClass(GetCurrentProcess(), class) } == 0 {
+            return Err(anyhow!("SetPriorityClass failed ({})", std::io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+
+    /// (id, efficiency class) of every logical processor
+    fn cpu_sets() -> Vec<(u32, u8)> {
+        let mut length = 0u32;
+        unsafe { GetSystemCpuSetInformation(ptr::null_mut(), 0, &mut length, GetCurrentProcess(), 0) };
+        // Records vary in size; u64s keep them aligned
+        let mut buffer = vec![0u64; (length as usize).div_ceil(8)];
+        let ok = unsafe {
+            GetSystemCpuSetInformation(
+                buffer.as_mut_ptr() as *mut SYSTEM_CPU_SET_INFORMATION,
+                length,
+                &mut length,
+                GetCurrentProcess(),
+                0,
+            )
+        };
+        if ok == 0 {
+            return Vec::new();
+        }
+
+        let mut cpus = Vec::new();
+        let mut offset = 0usize;
+        while offset + std::mem::size_of::<SYSTEM_CPU_SET_INFORMATION>() <= length as usize {
+            let info = unsafe { &*((buffer.as_ptr() as *const u8).add(offset) as *const SYSTEM_CPU_SET_INFORMATION) };
+            if info.Size == 0 {
+                break;
+            }
+            if info.Type == CpuSetInformation {
+                let set = unsafe { &info.Anonymous.CpuSet };
+                cpus.push((set.Id, set.EfficiencyClass));
+            }
+            offset += info.Size as usize;
+        }
+        cpus
+    }
+
+    /// (performance, all) logical processors, None on a CPU without core classes
+    pub fn core_counts() -> Option<(usize, usize)> {
+        let cpus = cpu_sets();
+        performance_sets(&cpus).map(|ids| (ids.len(), cpus.len()))
+    }
+
+    /// Keeps the calling thread on the performance cores. False when there are
+    /// none to prefer or Windows refused.
+    pub fn pin_to_performance_cores() -> bool {
+        let Some(ids) = performance_sets(&cpu_sets()) else {
+            return false;
+        };
+        unsafe { SetThreadSelectedCpuSets(GetCurrentThread(), ids.as_ptr(), ids.len() as u32) != 0 }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod fallback {
+    use super::Priority;
+    use anyhow::Result;
+
+    pub fn set_process_priority(_priority: Priority) -> Result<()> {
+        Ok(())
+    }
+
+    pub fn core_counts() -> Option<(usize, usize)> {
+        None
+    }
+
+    pub fn pin_to_performance_cores() -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_hybrid_cpus_have_cores_to_pin_to() {
+        for priority in Priority::ALL {
+            assert_eq!(Priority::parse(priority.as_str()), priority);
+        }
+        assert_eq!(Priority::parse("realtime"), Priority::Normal);
+
+        // Two performance cores with two threads each, four efficiency cores
+        let hybrid = [(256, 1), (257, 1), (258, 1), (259, 1), (260, 0), (261, 0), (262, 0), (263, 0)];
+        assert_eq!(performance_sets(&hybrid), Some(vec![256, 257, 258, 259]));
+        assert_eq!(performance_sets(&[(256, 0), (257, 0)]), None);
+        assert_eq!(performance_sets(&[]), None);
+    }
+}
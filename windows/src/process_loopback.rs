@@ -0,0 +1,208 @@
+// "What you hear" minus one app, e.g. everything but Spotify when the phone
+// already plays Spotify itself. Plain loopback captures an output device's
+// whole mix; WASAPI process loopback (Windows 10 2004 and later) captures what
+// every process plays, leaving out one process and its children. The API takes
+// a single process tree, so one app can be left out, not several.
+//
+// The app is picked from the audio sessions on the default output, saved by
+// executable name, and looked up again at each connect. Its session's process
+// is the one that renders, which for multi-process apps like Spotify or a
+// browser is a child, so excluding its tree is enough. The capture itself runs
+// on wasapi_loopback.rs's thread.
+
+#[cfg(target_os = "windows")]
+pub use imp::*;
+
+#[cfg(not(target_os = "windows"))]
+pub use fallback::*;
+
+/// An app with an audio session on the default output
+#[derive(Clone, Debug, PartialEq)]
+pub struct AppSession {
+    pub pid: u32,
+    /// Executable file name, e.g. "Spotify.exe"
+    pub name: String,
+}
+
+/// The process to exclude for the app saved as `name`, matched case-insensitively
+pub fn find(sessions: &[AppSession], name: &str) -> Option<u32> {
+    sessions.iter().find(|s| s.name.eq_ignore_ascii_case(name)).map(|s| s.pid)
+}
+
+/// The file name from a full image path
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn exe_name(path: &str) -> &str {
+    path.rsplit(['\\', '/']).next().unwrap_or(path)
+}
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use super::{exe_name, AppSession};
+    use crate::wasapi_loopback::{self, Capture};
+    use anyhow::{anyhow, Result};
+    use std::mem::ManuallyDrop;
+    use std::sync::mpsc;
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use windows::core::{implement, IUnknown, Interface, HRESULT, PROPVARIANT};
+    use windows::Win32::Media::Audio::{
+        eConsole, eRender, ActivateAudioInterfaceAsync, IActivateAudioInterfaceAsyncOperation,
+        IActivateAudioInterfaceCompletionHandler, IActivateAudioInterfaceCompletionHandler_Impl, IAudioClient,
+        IAudioSessionControl2, IAudioSessionManager2, IMMDeviceEnumerator, MMDeviceEnumerator,
+        AUDIOCLIENT_ACTIVATION_PARAMS, AUDIOCLIENT_ACTIVATION_PARAMS_0, AUDIOCLIENT_ACTIVATION_TYPE_PROCESS_LOOPBACK,
+        AUDIOCLIENT_PROCESS_LOOPBACK_PARAMS, PROCESS_LOOPBACK_MODE_EXCLUDE_TARGET_PROCESS_TREE,
+        VIRTUAL_AUDIO_DEVICE_PROCESS_LOOPBACK,
+    };
+    use windows::Win32::System::Com::{CoCreateInstance, CoInitializeEx, CLSCTX_ALL, COINIT_MULTITHREADED};
+
+    const VT_BLOB: u16 = 65;
+
+    /// Apps with an audio session on the default output right now, by process
+    pub fn sessions() -> Result<Vec<AppSession>> {
+        unsafe {
+            let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+            let enumerator: IMMDeviceEnumerator = CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
+            let device = enumerator.GetDefaultAudioEndpoint(eRender, eConsole)?;
+            let manager: IAudioSessionManager2 = device.Activate(CLSCTX_ALL, None)?;
+            let list = manager.GetSessionEnumerator()?;
+            let mut sessions = Vec::new();
+            for i in 0..list.GetCount()? {
+                let control: IAudioSessionControl2 = list.GetSession(i)?.cast()?;
+                // 0 is the system sounds session, which belongs to no app
+                let pid = control.GetProcessId().unwrap_or(0);
+                if pid == 0 || sessions.iter().any(|s: &AppSession| s.pid == pid) {
+                    continue;
+                }
+                if let Some(name) = process_name(pid) {
+                    sessions.push(AppSession { pid, name });
+                }
+            }
+            sessions.sort_by_key(|s| s.name.to_lowercase());
+            Ok(sessions)
+        }
+    }
+
+    fn process_name(pid: u32) -> Option<String> {
+        use windows_sys::Win32::Foundation::CloseHandle;
+        use windows_sys::Win32::System::Threading::{
+            OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+        };
+        unsafe {
+            let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+            if process.is_null() {
+                return None;
+            }
+            let mut path = [0u16; 1024];
+            let mut len = path.len() as u32;
+            let ok = QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, path.as_mut_ptr(), &mut len) != 0;
+            CloseHandle(process);
+            ok.then(|| exe_name(&String::from_utf16_lossy(&path[..len as usize])).to_string())
+        }
+    }
+
+    #[implement(IActivateAudioInterfaceCompletionHandler)]
+    struct Activated(Mutex<mpsc::Sender<()>>);
+
+    impl IActivateAudioInterfaceCompletionHandler_Impl for Activated_Impl {
+        fn ActivateCompleted(&self, _: Option<&IActivateAudioInterfaceAsyncOperation>) -> windows::core::Result<()> {
+            let _ = self.0.lock().unwrap().send(());
+            Ok(())
+        }
+    }
+
+    /// An audio client capturing everything but `pid`'s process tree
+    fn activate(pid: u32) -> Result<IAudioClient> {
+        unsafe {
+            let params = AUDIOCLIENT_ACTIVATION_PARAMS {
+                ActivationType: AUDIOCLIENT_ACTIVATION_TYPE_PROCESS_LOOPBACK,
+                Anonymous: AUDIOCLIENT_ACTIVATION_PARAMS_0 {
+                    ProcessLoopbackParams: AUDIOCLIENT_PROCESS_LOOPBACK_PARAMS {
+                        TargetProcessId: pid,
+                        ProcessLoopbackMode: PROCESS_LOOPBACK_MODE_EXCLUDE_TARGET_PROCESS_TREE,
+                    },
+                },
+            };
+            // A VT_BLOB pointing at `params`; ManuallyDrop, as the blob isn't ours to free
+            let raw = windows::core::imp::PROPVARIANT {
+                Anonymous: windows::core::imp::PROPVARIANT_0 {
+                    Anonymous: windows::core::imp::PROPVARIANT_0_0 {
+                        vt: VT_BLOB,
+                        wReserved1: 0,
+                        wReserved2: 0,
+                        wReserved3: 0,
+                        Anonymous: windows::core::imp::PROPVARIANT_0_0_0 {
+                            blob: windows::core::imp::BLOB {
+                                cbSize: std::mem::size_of::<AUDIOCLIENT_ACTIVATION_PARAMS>() as u32,
+                                pBlobData: &params as *const _ as *mut u8,
+                            },
+                        },
+                    },
+                },
+            };
+            let activation = ManuallyDrop::new(PROPVARIANT::from_raw(raw));
+
+            let (done_tx, done_rx) = mpsc::channel();
+            let handler: IActivateAudioInterfaceCompletionHandler = Activated(Mutex::new(done_tx)).into();
+            let operation = ActivateAudioInterfaceAsync(
+                VIRTUAL_AUDIO_DEVICE_PROCESS_LOOPBACK,
+                &IAudioClient::IID,
+                Some(&*activation as *const PROPVARIANT),
+                &handler,
+            )?;
+            done_rx
+                .recv_timeout(Duration::from_secs(5))
+                .map_err(|_| anyhow!("Windows didn't finish setting up process loopback"))?;
+            let mut result = HRESULT(0);
+            let mut client: Option<IUnknown> = None;
+            operation.GetActivateResult(&mut result, &mut client)?;
+            result.ok()?;
+            Ok(client.ok_or_else(|| anyhow!("process loopback gave no audio client"))?.cast()?)
+        }
+    }
+
+    /// Captures what every app plays except `pid` and its children, handing
+    /// `on_audio` interleaved stereo at 48 kHz. `on_error` hears of a failure
+    /// after it started; the capture is over by then.
+    pub fn start(
+        pid: u32,
+        on_audio: impl FnMut(&[f32]) + Send + 'static,
+        on_error: impl FnOnce(String) + Send + 'static,
+    ) -> Result<Capture> {
+        wasapi_loopback::run(move || activate(pid), on_audio, on_error)
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod fallback {
+    use super::AppSession;
+    use crate::wasapi_loopback::Capture;
+    use anyhow::{anyhow, Result};
+
+    pub fn sessions() -> Result<Vec<AppSession>> {
+        Ok(Vec::new())
+    }
+
+    pub fn start(
+        _pid: u32,
+        _on_audio: impl FnMut(&[f32]) + Send + 'static,
+        _on_error: impl FnOnce(String) + Send + 'static,
+    ) -> Result<Capture> {
+        Err(anyhow!("leaving an app out of the capture needs Windows"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_saved_app_among_the_sessions() {
+        let sessions = [
+            AppSession { pid: 40, name: "chrome.exe".to_string() },
+            AppSession { pid: 7, name: exe_name(r"C:\Users\me\AppData\Roaming\Spotify\Spotify.exe").to_string() },
+        ];
+        assert_eq!(sessions[1].name, "Spotify.exe");
+        assert_eq!(find(&sessions, "spotify.exe"), Some(7));
+        assert_eq!(find(&sessions, "vlc.exe"), None);
+    }
+}
@@ -0,0 +1,102 @@
+// Per-source packet budget on the receive port, checked before a datagram is
+// looked at. The phone sends at most about 70 packets a second (16-bit PCM mono
+// split into 1400-byte datagrams), so anything far beyond that is a flood and is
+// dropped without parsing.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Instant;
+
+pub const PACKETS_PER_SECOND: f64 = 250.0;
+const BURST: f64 = 250.0; // a second's worth, for Wi-Fi that delivers in clumps
+const MAX_SOURCES: usize = 256; // idle, then stale sources are forgotten beyond this
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: HashMap<IpAddr, Bucket>,
+}
+
+impl RateLimiter {
+    /// Whether `source` may send another packet now
+    pub fn allow(&mut self, source: IpAddr, now: Instant) -> bool {
+        if self.buckets.len() >= MAX_SOURCES && !self.buckets.contains_key(&source) {
+            // Whoever has been idle long enough to be full again has nothing to remember
+            self.buckets.retain(|_, b| b.tokens + now.duration_since(b.refilled).as_secs_f64() * PACKETS_PER_SECOND < BURST);
+            // Otherwise the one heard from longest ago, so a flood from many spoofed
+            // addresses can't lock out a phone that connects after it. A source
+            // forgotten this way starts again with a full bucket.
+            if self.buckets.len() >= MAX_SOURCES {
+                if let Some(stalest) = self.buckets.iter().min_by_key(|(_, b)| b.refilled).map(|(ip, _)| *ip) {
+                    self.buckets.remove(&stalest);
+                }
+            }
+        }
+        let bucket = self.buckets.entry(source).or_insert(Bucket { tokens: BURST, refilled: now });
+        let elapsed = now.saturating_duration_since(bucket.refilled).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * PACKETS_PER_SECOND).min(BURST);
+        bucket.refilled = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn passes_the_phone_and_drops_a_flood() {
+        let mut limiter = RateLimiter::default();
+        let phone: IpAddr = [192, 168, 1, 20].into();
+        let flood: IpAddr = [192, 168, 1, 66].into();
+        let start = Instant::now();
+
+        // Ten seconds of both: the phone at 100 packets a second, the flood at 10,000
+        let mut passed = (0, 0);
+        for ms in 0..10_000u64 {
+            let now = start + Duration::from_millis(ms);
+            if ms % 10 == 0 && limiter.allow(phone, now) {
+                passed.0 += 1;
+            }
+            for _ in 0..10 {
+                if limiter.allow(flood, now) {
+                    passed.1 += 1;
+                }
+            }
+        }
+        assert_eq!(passed.0, 1000);
+        let budget = (BURST + 10.0 * PACKETS_PER_SECOND) as i32;
+        assert!((passed.1 - budget).abs() <= 2, "{} passed", passed.1);
+    }
+
+    #[test]
+    fn a_new_source_gets_in_when_every_slot_is_busy() {
+        let mut limiter = RateLimiter::default();
+        let start = Instant::now();
+        // 256 spoofed sources, each sending faster than its bucket refills
+        let spoofed = |i: usize| IpAddr::from([10, 0, (i / 256) as u8, (i % 256) as u8]);
+        for ms in 0..2_000u64 {
+            let now = start + Duration::from_millis(ms);
+            for i in 0..MAX_SOURCES {
+                limiter.allow(spoofed(i), now);
+            }
+        }
+        assert_eq!(limiter.buckets.len(), MAX_SOURCES);
+
+        let phone: IpAddr = [192, 168, 1, 20].into();
+        let now = start + Duration::from_millis(2_000);
+        assert!(limiter.allow(phone, now));
+        assert!(limiter.allow(phone, now));
+        assert_eq!(limiter.buckets.len(), MAX_SOURCES);
+    }
+}
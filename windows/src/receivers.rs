@@ -0,0 +1,229 @@
+// Per-receiver routing, mute and trim. Several receivers can be fed at once: the
+// phone session, RTSP clients, the AES67 stream and the classroom group. Each
+// gets its own mix of the sources routed to it (sources.rs), muted or turned
+// down here just before its encoder, so one can be silenced, trimmed or given
+// different audio without touching the others. Master gain and "Mute PC →
+// iPhone" apply to the capture source only.
+//
+// Trim only attenuates: the bus is already clamped to full scale by then, and a
+// boost would clip again. Kept in settings.txt as
+// receivers=phone:-6:0:capture,rtsp:0:1:capture+ambiance,... (name, trim in dB,
+// muted, sources).
+
+use crate::sources::Source;
+use std::borrow::Cow;
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU8, Ordering};
+
+pub const TRIM_RANGE_DB: RangeInclusive<i32> = -40..=0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Receiver {
+    Phone,
+    Rtsp,
+    Aes67,
+    Classroom,
+}
+
+impl Receiver {
+    pub const ALL: [Self; 4] = [Self::Phone, Self::Rtsp, Self::Aes67, Self::Classroom];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Receiver::Phone => "iPhone",
+            Receiver::Rtsp => "RTSP clients",
+            Receiver::Aes67 => "AES67 stream",
+            Receiver::Classroom => "Classroom group",
+        }
+    }
+
+    fn key(self) -> &'static str {
+        match self {
+            Receiver::Phone => "phone",
+            Receiver::Rtsp => "rtsp",
+            Receiver::Aes67 => "aes67",
+            Receiver::Classroom => "classroom",
+        }
+    }
+}
+
+struct Trim {
+    db: AtomicI32,
+    muted: AtomicBool,
+    sources: AtomicU8,  // Source::bit() of each source routed here
+}
+
+impl Default for Trim {
+    fn default() -> Self {
+        Self { db: AtomicI32::new(0), muted: AtomicBool::new(false), sources: AtomicU8::new(Source::Capture.bit()) }
+    }
+}
+
+/// Shared by the UI and the capture path
+#[derive(Default)]
+pub struct Mix {
+    trims: [Trim; 4],
+}
+
+impl Mix {
+    fn trim(&self, receiver: Receiver) -> &Trim {
+        &self.trims[receiver as usize]
+    }
+
+    pub fn muted(&self, receiver: Receiver) -> bool {
+        self.trim(receiver).muted.load(Ordering::Relaxed)
+    }
+
+    pub fn set_muted(&self, receiver: Receiver, muted: bool) {
+        self.trim(receiver).muted.store(muted, Ordering::Relaxed);
+    }
+
+    pub fn trim_db(&self, receiver: Receiver) -> i32 {
+        self.trim(receiver).db.load(Ordering::Relaxed)
+    }
+
+    pub fn set_trim_db(&self, receiver: Receiver, db: i32) {
+        self.trim(receiver).db.store(db.clamp(*TRIM_RANGE_DB.start(), *TRIM_RANGE_DB.end()), Ordering::Relaxed);
+    }
+
+    pub fn routed(&self, receiver: Receiver, source: Source) -> bool {
+        self.trim(receiver).sources.load(Ordering::Relaxed) & source.bit() != 0
+    }
+
+    pub fn set_routed(&self, receiver: Receiver, source: Source, routed: bool) {
+        let sources = &self.trim(receiver).sources;
+        if routed {
+            sources.fetch_or(source.bit(), Ordering::Relaxed);
+        } else {
+            sources.fetch_and(!source.bit(), Ordering::Relaxed);
+        }
+    }
+
+    /// Whether any receiver takes `source`, so it's worth producing
+    pub fn wanted(&self, source: Source) -> bool {
+        Receiver::ALL.into_iter().any(|r| self.routed(r, source))
+    }
+
+    /// Linear gain for `receiver`: 0 when muted
+    fn gain(&self, receiver: Receiver) -> f32 {
+        if self.muted(receiver) {
+            0.0
+        } else {
+            10f32.powf(self.trim_db(receiver) as f32 / 20.0)
+        }
+    }
+
+    /// What `receiver` gets from the capture bus and the ambiance loop, if one
+    /// plays; borrowed when that's the capture unchanged
+    pub fn apply<'a>(&self, receiver: Receiver, capture: &'a [f32], ambiance: Option<&[f32]>) -> Cow<'a, [f32]> {
+        let gain = self.gain(receiver);
+        let with_capture = self.routed(receiver, Source::Capture);
+        let ambiance = ambiance.filter(|_| self.routed(receiver, Source::Ambiance));
+        match ambiance {
+            None if with_capture && gain == 1.0 => Cow::Borrowed(capture),
+            None if with_capture => Cow::Owned(capture.iter().map(|s| s * gain).collect()),
+            None => Cow::Owned(vec![0.0; capture.len()]),
+            Some(ambiance) => Cow::Owned(
+                capture
+                    .iter()
+                    .zip(ambiance)
+                    .map(|(&c, &a)| ((if with_capture { c } else { 0.0 }) + a).clamp(-1.0, 1.0) * gain)
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn to_setting(&self) -> String {
+        Receiver::ALL
+            .iter()
+            .map(|&r| {
+                let sources: Vec<&str> = Source::ALL.into_iter().filter(|&s| self.routed(r, s)).map(Source::key).collect();
+                format!("{}:{}:{}:{}", r.key(), self.trim_db(r), self.muted(r) as u8, sources.join("+"))
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Applies a saved setting; unknown names and bad fields are skipped
+    pub fn load(&self, text: &str) {
+        for entry in text.split(',') {
+            let fields: Vec<&str> = entry.trim().split(':').collect();
+            // Settings from before routing have no sources field
+            let (key, db, muted, sources) = match fields.as_slice() {
+                [key, db, muted] => (key, db, muted, None),
+                [key, db, muted, sources] => (key, db, muted, Some(sources)),
+                _ => continue,
+            };
+            let Some(receiver) = Receiver::ALL.into_iter().find(|r| r.key() == *key) else {
+                continue;
+            };
+            if let Ok(db) = db.parse() {
+                self.set_trim_db(receiver, db);
+            }
+            self.set_muted(receiver, *muted == "1");
+            if let Some(sources) = sources {
+                let names: Vec<&str> = sources.split('+').collect();
+                for source in Source::ALL {
+                    self.set_routed(receiver, source, names.contains(&source.key()));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trims_one_receiver_without_touching_the_others() {
+        let mix = Mix::default();
+        let bus = [0.5f32, -0.5];
+        assert!(matches!(mix.apply(Receiver::Phone, &bus, None), Cow::Borrowed(_)));
+
+        mix.set_trim_db(Receiver::Classroom, -6);
+        mix.set_muted(Receiver::Rtsp, true);
+        let classroom = mix.apply(Receiver::Classroom, &bus, None);
+        assert!((classroom[0] - 0.2506).abs() < 0.001, "{}", classroom[0]);
+        assert_eq!(&*mix.apply(Receiver::Rtsp, &bus, None), &[0.0, -0.0]);
+        assert_eq!(&*mix.apply(Receiver::Phone, &bus, None), &bus);
+
+        // Trim never boosts
+        mix.set_trim_db(Receiver::Aes67, 12);
+        assert_eq!(mix.trim_db(Receiver::Aes67), 0);
+
+        let text = mix.to_setting();
+        assert_eq!(text, "phone:0:0:capture,rtsp:0:1:capture,aes67:0:0:capture,classroom:-6:0:capture");
+        let loaded = Mix::default();
+        loaded.load(&format!("{},bogus:1:1,aes67:x", text));
+        assert_eq!(loaded.to_setting(), text);
+    }
+
+    #[test]
+    fn routes_each_receiver_its_own_sources() {
+        let mix = Mix::default();
+        let (game, rain) = ([0.5f32, -0.5], [0.25f32, 0.75]);
+        assert!(!mix.wanted(Source::Ambiance));
+
+        // The phone keeps the game; the classroom gets rain only, AES67 both
+        mix.set_routed(Receiver::Classroom, Source::Capture, false);
+        mix.set_routed(Receiver::Classroom, Source::Ambiance, true);
+        mix.set_routed(Receiver::Aes67, Source::Ambiance, true);
+        assert!(mix.wanted(Source::Ambiance));
+        assert_eq!(&*mix.apply(Receiver::Phone, &game, Some(&rain)), &game);
+        assert_eq!(&*mix.apply(Receiver::Classroom, &game, Some(&rain)), &rain);
+        assert_eq!(&*mix.apply(Receiver::Aes67, &game, Some(&rain)), &[0.75, 0.25]);
+        // Nothing routed that plays is silence
+        assert_eq!(&*mix.apply(Receiver::Classroom, &game, None), &[0.0, 0.0]);
+
+        let text = mix.to_setting();
+        assert_eq!(text, "phone:0:0:capture,rtsp:0:0:capture,aes67:0:0:capture+ambiance,classroom:0:0:ambiance");
+        let loaded = Mix::default();
+        loaded.load(&text);
+        assert_eq!(loaded.to_setting(), text);
+        // The old three-field form leaves the routing alone
+        loaded.load("classroom:-3:0");
+        assert!(loaded.routed(Receiver::Classroom, Source::Ambiance));
+        assert_eq!(loaded.trim_db(Receiver::Classroom), -3);
+    }
+}
@@ -0,0 +1,124 @@
+// Takes addresses out of a log before it's shared. IP addresses (with any
+// port kept), host names and MAC addresses become numbered placeholders, the
+// same address getting the same one throughout, so the log can still be
+// followed: "<ip-1> stopped answering" after "sending to <ip-1>:4811".
+// Loopback and unspecified addresses say nothing about anyone and stay.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+
+/// Names ending in these are files, not hosts
+const FILE_EXTENSIONS: [&str; 12] = ["txt", "log", "jsonl", "json", "exe", "dll", "wav", "zip", "rs", "toml", "md", "cfg"];
+
+#[derive(Default)]
+pub struct Redactor {
+    seen: HashMap<(&'static str, String), usize>,
+    counts: HashMap<&'static str, usize>,
+}
+
+impl Redactor {
+    pub fn redact(&mut self, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut token = String::new();
+        for c in text.chars() {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | ':' | '-' | '_' | '%') {
+                token.push(c);
+            } else {
+                self.push_token(&mut out, &token);
+                token.clear();
+                out.push(c);
+            }
+        }
+        self.push_token(&mut out, &token);
+        out
+    }
+
+    /// Distinct addresses replaced so far, of each kind
+    pub fn replaced(&self, kind: &str) -> usize {
+        self.counts.get(kind).copied().unwrap_or(0)
+    }
+
+    fn push_token(&mut self, out: &mut String, token: &str) {
+        // A sentence can end right after an address
+        let trimmed = token.trim_end_matches(['.', ':', '-']);
+        let tail = &token[trimmed.len()..];
+        match self.replace(trimmed) {
+            Some(replacement) => out.push_str(&replacement),
+            None => out.push_str(trimmed),
+        }
+        out.push_str(tail);
+    }
+
+    fn replace(&mut self, token: &str) -> Option<String> {
+        if let Ok(ip) = token.parse::<IpAddr>() {
+            return self.ip(ip);
+        }
+        if let Ok(addr) = token.parse::<SocketAddr>() {
+            return self.ip(addr.ip()).map(|ip| format!("{}:{}", ip, addr.port()));
+        }
+        if is_mac(token) {
+            return Some(self.placeholder("mac", token.to_ascii_lowercase().replace('-', ":")));
+        }
+        let (host, port) = match token.rsplit_once(':') {
+            Some((host, port)) if port.parse::<u16>().is_ok() => (host, Some(port)),
+            _ => (token, None),
+        };
+        is_host(host).then(|| {
+            let host = self.placeholder("host", host.to_ascii_lowercase());
+            port.map_or(host.clone(), |port| format!("{}:{}", host, port))
+        })
+    }
+
+    fn ip(&mut self, ip: IpAddr) -> Option<String> {
+        (!ip.is_loopback() && !ip.is_unspecified()).then(|| self.placeholder("ip", ip.to_string()))
+    }
+
+    fn placeholder(&mut self, kind: &'static str, value: String) -> String {
+        let next = self.counts.get(kind).copied().unwrap_or(0) + 1;
+        let number = *self.seen.entry((kind, value)).or_insert_with(|| {
+            self.counts.insert(kind, next);
+            next
+        });
+        format!("<{}-{}>", kind, number)
+    }
+}
+
+fn is_mac(token: &str) -> bool {
+    let parts: Vec<&str> = token.split([':', '-']).collect();
+    parts.len() == 6 && parts.iter().all(|p| p.len() == 2 && p.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Dotted names like "yusufs-iphone.local" or "vpn.example.com"
+fn is_host(token: &str) -> bool {
+    let labels: Vec<&str> = token.split('.').collect();
+    let Some(last) = labels.last() else {
+        return false;
+    };
+    labels.len() >= 2
+        && labels.iter().all(|l| !l.is_empty() && l.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+        && last.len() >= 2
+        && last.chars().all(|c| c.is_ascii_alphabetic())
+        && !FILE_EXTENSIONS.contains(&last.to_ascii_lowercase().as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_addresses_consistently_and_keeps_the_rest() {
+        let log = "[1] Network started: sending to 192.168.1.20:4811, receiving on port 4810\n\
+                   [2] Rendezvous: phone is at [2001:db8::7]:50000 via rv.example.com\n\
+                   [3] Moved to 192.168.1.20. Bluetooth AA:BB:CC:DD:EE:0F, bound 0.0.0.0:4810 and 127.0.0.1\n\
+                   [4] Learned for Home-WiFi: 80 ms; wrote budbridge_17.log, v1.2, 12:30:05";
+        let mut redactor = Redactor::default();
+        assert_eq!(
+            redactor.redact(log),
+            "[1] Network started: sending to <ip-1>:4811, receiving on port 4810\n\
+             [2] Rendezvous: phone is at [<ip-2>]:50000 via <host-1>\n\
+             [3] Moved to <ip-1>. Bluetooth <mac-1>, bound 0.0.0.0:4810 and 127.0.0.1\n\
+             [4] Learned for Home-WiFi: 80 ms; wrote budbridge_17.log, v1.2, 12:30:05"
+        );
+        assert_eq!((redactor.replaced("ip"), redactor.replaced("host"), redactor.replaced("mac")), (2, 1, 1));
+    }
+}
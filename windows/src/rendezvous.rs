@@ -0,0 +1,128 @@
+// Internet mode: both ends register a shared session code with a small UDP
+// rendezvous server (src/bin/rendezvous.rs), learn each other's public address
+// and punch through their NATs by sending from the same port they registered from
+// (messages in protocol.rs).
+//
+// Symmetric NATs (common on some carriers) can't be punched. When no audio
+// comes through the punch, the PC asks the server to relay, and both ends then
+// send to the server instead, which passes the audio on.
+
+use crate::protocol::{self, PUNCH_MAGIC};
+use anyhow::{anyhow, Result};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_RENDEZVOUS_PORT: u16 = 4820;
+const REGISTER_INTERVAL: Duration = Duration::from_secs(3);
+const JOIN_TIMEOUT: Duration = Duration::from_secs(120);
+const PUNCH_COUNT: usize = 5;
+
+#[derive(Clone, Debug)]
+pub struct Rendezvous {
+    pub server: String, // host or host:port
+    pub code: String,
+}
+
+impl Rendezvous {
+    fn server_addr(&self) -> Result<SocketAddr> {
+        let with_port = if self.server.contains(':') {
+            self.server.clone()
+        } else {
+            format!("{}:{}", self.server, DEFAULT_RENDEZVOUS_PORT)
+        };
+        with_port
+            .to_socket_addrs()?
+            .find(|a| a.is_ipv4())
+            .ok_or_else(|| anyhow!("Could not resolve rendezvous server \"{}\"", self.server))
+    }
+
+    /// Register until the phone joins the same session, then punch towards it.
+    /// `socket` must be non-blocking and the one audio is sent and received on.
+    pub fn find_peer(&self, socket: &UdpSocket, stop: &AtomicBool) -> Result<SocketAddr> {
+        let server = self.server_addr()?;
+        let register = protocol::register(&self.code);
+        let started = Instant::now();
+        let mut last_register: Option<Instant> = None;
+        let mut buf = [0u8; 512];
+
+        while !stop.load(Ordering::SeqCst) {
+            if started.elapsed() > JOIN_TIMEOUT {
+                return Err(anyhow!("No phone joined session \"{}\"", self.code));
+            }
+            if last_register.is_none_or(|t| t.elapsed() >= REGISTER_INTERVAL) {
+                socket.send_to(register.as_bytes(), server)?;
+                last_register = Some(Instant::now());
+            }
+
+            if let Ok((len, src)) = socket.recv_from(&mut buf) {
+                if src == server {
+                    if let Some(peer) = protocol::parse_peer(&buf[..len]) {
+                        punch(socket, peer);
+                        return Ok(peer);
+                    }
+                }
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        Err(anyhow!("Cancelled"))
+    }
+
+    /// Asks the server to pass audio between us and the phone. The request's
+    /// server is the address to send to from now on.
+    pub fn relay(&self, socket: &UdpSocket) -> Result<RelayRequest> {
+        let server = self.server_addr()?;
+        let mut request = RelayRequest { server, message: protocol::relay(&self.code), last_sent: None, relaying: false };
+        request.resend(socket)?;
+        punch(socket, server);
+        Ok(request)
+    }
+}
+
+/// A RELAY request, repeated like REGISTER until the server answers RELAYING:
+/// it drops what the PC sends until then, so a lost request would lose the session
+pub struct RelayRequest {
+    server: SocketAddr,
+    message: String,
+    last_sent: Option<Instant>,
+    relaying: bool,
+}
+
+impl RelayRequest {
+    pub fn server(&self) -> SocketAddr {
+        self.server
+    }
+
+    /// Whether the server has said it relays
+    pub fn relaying(&self) -> bool {
+        self.relaying
+    }
+
+    /// Sends the request again if the server hasn't answered in REGISTER_INTERVAL
+    pub fn resend(&mut self, socket: &UdpSocket) -> Result<()> {
+        if !self.relaying && self.last_sent.is_none_or(|t| t.elapsed() >= REGISTER_INTERVAL) {
+            socket.send_to(self.message.as_bytes(), self.server)?;
+            self.last_sent = Some(Instant::now());
+        }
+        Ok(())
+    }
+
+    /// Takes note of the server's answer; true when `packet` is it
+    pub fn answered(&mut self, packet: &[u8]) -> bool {
+        let relaying = protocol::is_relaying(packet);
+        self.relaying |= relaying;
+        relaying
+    }
+}
+
+pub fn punch(socket: &UdpSocket, peer: SocketAddr) {
+    for _ in 0..PUNCH_COUNT {
+        let _ = socket.send_to(PUNCH_MAGIC, peer);
+    }
+}
+
+/// Short code to type on the phone
+pub fn generate_code() -> String {
+    uuid::Uuid::new_v4().simple().to_string()[..8].to_uppercase()
+}
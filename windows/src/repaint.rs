@@ -0,0 +1,51 @@
+// UI repaints driven by what the bridge does. The audio and network threads call
+// `notify` when something on screen changed (a packet came in or went out, a
+// status); the first notify after a frame asks egui for a repaint one refresh
+// interval later, and the rest fold into it. So meters move at the max refresh
+// rate while audio flows, and an idle window only redraws for its own timers.
+
+use eframe::egui;
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+pub const DEFAULT_MAX_FPS: u32 = 30;
+pub const MAX_FPS_RANGE: RangeInclusive<u32> = 5..=60;
+/// Housekeeping redraws with nothing happening: countdowns, device polling, reconnects
+pub const IDLE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+pub struct Repaint {
+    ctx: OnceLock<egui::Context>,
+    max_fps: AtomicU32,
+    /// A repaint is already on its way
+    requested: AtomicBool,
+}
+
+impl Repaint {
+    pub fn attach(&self, ctx: egui::Context, max_fps: u32) {
+        let _ = self.ctx.set(ctx);
+        self.set_max_fps(max_fps);
+    }
+
+    pub fn set_max_fps(&self, max_fps: u32) {
+        self.max_fps.store(max_fps.clamp(*MAX_FPS_RANGE.start(), *MAX_FPS_RANGE.end()), Ordering::Relaxed);
+    }
+
+    /// Something the UI shows changed; cheap enough for every packet
+    pub fn notify(&self) {
+        if self.requested.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        if let Some(ctx) = self.ctx.get() {
+            let fps = self.max_fps.load(Ordering::Relaxed).max(1);
+            ctx.request_repaint_after(Duration::from_secs(1) / fps);
+        }
+    }
+
+    /// At the start of each frame, so the next change asks again
+    pub fn frame_started(&self) {
+        self.requested.store(false, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,92 @@
+// What Connect does after an attempt fails or a session drops: try again after a
+// delay that doubles each time up to a ceiling, then either stop with the error or
+// move on to the next saved device. A kiosk PC can retry forever; a laptop is
+// usually better off failing at once (the default), so the user sees why.
+
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+pub const MAX_RETRIES_RANGE: RangeInclusive<u32> = 0..=100;
+pub const DELAY_RANGE_SECS: RangeInclusive<u32> = 1..=3600;
+
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum GiveUp {
+    /// Leave the error showing and stay disconnected
+    #[default]
+    Stop,
+    /// Select the next saved device and start its retries afresh
+    NextDevice,
+}
+
+impl GiveUp {
+    pub const ALL: [GiveUp; 2] = [GiveUp::Stop, GiveUp::NextDevice];
+
+    pub fn parse(text: &str) -> Self {
+        match text {
+            "next_device" => Self::NextDevice,
+            _ => Self::Stop,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Stop => "stop",
+            Self::NextDevice => "next_device",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Stop => "Stop and show the error",
+            Self::NextDevice => "Try the next saved device",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// None retries forever
+    pub max_retries: Option<u32>,
+    pub initial_delay_secs: u32,
+    pub max_delay_secs: u32,
+    pub give_up: GiveUp,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self { max_retries: Some(0), initial_delay_secs: 2, max_delay_secs: 60, give_up: GiveUp::Stop }
+    }
+}
+
+impl RetryPolicy {
+    /// The wait before retry number `retry` (counting from 0), or None once
+    /// the retries have run out
+    pub fn delay(&self, retry: u32) -> Option<Duration> {
+        if self.max_retries.is_some_and(|max| retry >= max) {
+            return None;
+        }
+        let secs = (self.initial_delay_secs as u64)
+            .saturating_mul(1 << retry.min(20))
+            .min(self.max_delay_secs.max(self.initial_delay_secs) as u64);
+        Some(Duration::from_secs(secs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_double_up_to_the_ceiling_then_run_out() {
+        let policy = RetryPolicy { max_retries: Some(5), initial_delay_secs: 2, max_delay_secs: 10, ..Default::default() };
+        let delays: Vec<_> = (0..6).map(|r| policy.delay(r).map(|d| d.as_secs())).collect();
+        assert_eq!(delays, [Some(2), Some(4), Some(8), Some(10), Some(10), None]);
+    }
+
+    #[test]
+    fn forever_never_runs_out_and_zero_never_retries() {
+        let forever = RetryPolicy { max_retries: None, ..Default::default() };
+        assert_eq!(forever.delay(1000), Some(Duration::from_secs(60)));
+        assert_eq!(RetryPolicy::default().delay(0), None);
+    }
+}
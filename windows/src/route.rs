@@ -0,0 +1,175 @@
+// LAN or routed, told from the phone's address and the PC's own address on the
+// way to it. A phone in the PC's subnet is on the LAN; anything a router sits in
+// front of (a VPN, the internet, another subnet) is routed and gets the VPN
+// preset's smaller packets, keepalives, deeper buffer and lighter format.
+//
+// The subnet is the PC address's on-link prefix, from the IP Helper API's
+// adapter list; elsewhere, or for an address no adapter has, it's taken as the
+// /24 (IPv4) or /64 (IPv6) home networks use. Tailscale's 100.64.0.0/10 is
+// routed even though both ends share it. ZeroTier networks use private ranges
+// and look like a LAN; VPN mode is still there for those.
+
+use std::net::IpAddr;
+
+#[cfg(target_os = "windows")]
+use imp::on_link_prefix;
+
+#[cfg(not(target_os = "windows"))]
+use fallback::on_link_prefix;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Route {
+    Lan,
+    Routed,
+}
+
+impl Route {
+    pub fn label(self) -> &'static str {
+        match self {
+            Route::Lan => "LAN",
+            Route::Routed => "routed (VPN or another network)",
+        }
+    }
+}
+
+/// The length of the subnet `local`, one of this PC's addresses, is on
+pub fn prefix_len(local: IpAddr) -> u8 {
+    on_link_prefix(local).unwrap_or(if local.is_ipv4() { 24 } else { 64 })
+}
+
+/// `local` is the PC's address that packets to `target` leave from, on a subnet
+/// `prefix` bits long
+pub fn classify(target: IpAddr, local: IpAddr, prefix: u8) -> Route {
+    if target.is_loopback() {
+        return Route::Lan;
+    }
+    match (target, local) {
+        (IpAddr::V4(target), IpAddr::V4(local)) => {
+            let shared = |ip: std::net::Ipv4Addr| ip.octets()[0] == 100 && (64..128).contains(&ip.octets()[1]);
+            if shared(target) || shared(local) {
+                return Route::Routed;
+            }
+            let private = target.is_private() || target.is_link_local();
+            let mask = u32::MAX.checked_shl(32 - prefix.min(32) as u32).unwrap_or(0);
+            if private && u32::from(target) & mask == u32::from(local) & mask {
+                Route::Lan
+            } else {
+                Route::Routed
+            }
+        }
+        (IpAddr::V6(target), IpAddr::V6(local)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix.min(128) as u32).unwrap_or(0);
+            if u128::from(target) & mask == u128::from(local) & mask {
+                Route::Lan
+            } else {
+                Route::Routed
+            }
+        }
+        _ => Route::Routed,
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use std::ptr;
+    use windows_sys::Win32::Foundation::{ERROR_BUFFER_OVERFLOW, ERROR_SUCCESS};
+    use windows_sys::Win32::NetworkManagement::IpHelper::{
+        GetAdaptersAddresses, GAA_FLAG_SKIP_ANYCAST, GAA_FLAG_SKIP_DNS_SERVER, GAA_FLAG_SKIP_FRIENDLY_NAME,
+        GAA_FLAG_SKIP_MULTICAST, IP_ADAPTER_ADDRESSES_LH, IP_ADAPTER_UNICAST_ADDRESS_LH,
+    };
+    use windows_sys::Win32::Networking::WinSock::{AF_INET, AF_INET6, AF_UNSPEC, SOCKADDR, SOCKADDR_IN, SOCKADDR_IN6};
+
+    /// The OnLinkPrefixLength of the adapter address equal to `local`
+    pub fn on_link_prefix(local: IpAddr) -> Option<u8> {
+        let flags =
+            GAA_FLAG_SKIP_ANYCAST | GAA_FLAG_SKIP_MULTICAST | GAA_FLAG_SKIP_DNS_SERVER | GAA_FLAG_SKIP_FRIENDLY_NAME;
+        let mut size: u32 = 16 * 1024;
+        // Another try if an adapter appeared between asking the size and filling it
+        for _ in 0..3 {
+            // u64s to keep the structs aligned
+            let mut buffer = vec![0u64; (size as usize).div_ceil(8)];
+            let first = buffer.as_mut_ptr() as *mut IP_ADAPTER_ADDRESSES_LH;
+            unsafe {
+                match GetAdaptersAddresses(AF_UNSPEC as u32, flags, ptr::null(), first, &mut size) {
+                    ERROR_SUCCESS => return find(first, local),
+                    ERROR_BUFFER_OVERFLOW => continue,
+                    _ => return None,
+                }
+            }
+        }
+        None
+    }
+
+    unsafe fn find(mut adapter: *const IP_ADAPTER_ADDRESSES_LH, local: IpAddr) -> Option<u8> {
+        while let Some(current) = adapter.as_ref() {
+            let mut unicast: *const IP_ADAPTER_UNICAST_ADDRESS_LH = current.FirstUnicastAddress;
+            while let Some(address) = unicast.as_ref() {
+                if ip(address.Address.lpSockaddr) == Some(local) {
+                    return Some(address.OnLinkPrefixLength);
+                }
+                unicast = address.Next;
+            }
+            adapter = current.Next;
+        }
+        None
+    }
+
+    unsafe fn ip(sockaddr: *const SOCKADDR) -> Option<IpAddr> {
+        match sockaddr.as_ref()?.sa_family {
+            AF_INET => {
+                let v4 = &*(sockaddr as *const SOCKADDR_IN);
+                Some(Ipv4Addr::from(v4.sin_addr.S_un.S_addr.to_ne_bytes()).into())
+            }
+            AF_INET6 => {
+                let v6 = &*(sockaddr as *const SOCKADDR_IN6);
+                Some(Ipv6Addr::from(v6.sin6_addr.u.Byte).into())
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod fallback {
+    use std::net::IpAddr;
+
+    pub fn on_link_prefix(_local: IpAddr) -> Option<u8> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_the_subnet_from_routed_paths() {
+        let route = |target: &str, local: &str| {
+            let local = local.parse().unwrap();
+            classify(target.parse().unwrap(), local, if matches!(local, IpAddr::V4(_)) { 24 } else { 64 })
+        };
+        assert_eq!(route("192.168.1.40", "192.168.1.20"), Route::Lan);
+        assert_eq!(route("169.254.7.9", "169.254.7.1"), Route::Lan);
+        assert_eq!(route("127.0.0.1", "127.0.0.1"), Route::Lan);
+        assert_eq!(route("fd00:1:2:3::9", "fd00:1:2:3::1"), Route::Lan);
+
+        // Another subnet, the internet, Tailscale
+        assert_eq!(route("192.168.2.40", "192.168.1.20"), Route::Routed);
+        assert_eq!(route("203.0.113.5", "203.0.113.9"), Route::Routed);
+        assert_eq!(route("100.101.102.103", "100.101.102.7"), Route::Routed);
+        assert_eq!(route("fd00:1:2:4::9", "fd00:1:2:3::1"), Route::Routed);
+        assert_eq!(route("192.168.1.40", "fd00:1:2:3::1"), Route::Routed);
+    }
+
+    #[test]
+    fn uses_the_adapters_prefix() {
+        let route = |target: &str, local: &str, prefix| classify(target.parse().unwrap(), local.parse().unwrap(), prefix);
+        // A /16 office network spans what a /24 would split, and a /25 splits a /24
+        assert_eq!(route("10.20.30.40", "10.20.7.1", 16), Route::Lan);
+        assert_eq!(route("10.20.30.40", "10.21.7.1", 16), Route::Routed);
+        assert_eq!(route("192.168.1.200", "192.168.1.20", 25), Route::Routed);
+        assert_eq!(route("192.168.1.100", "192.168.1.20", 25), Route::Lan);
+        assert_eq!(route("fd00:1:2:4::9", "fd00:1:2:3::1", 48), Route::Lan);
+    }
+}
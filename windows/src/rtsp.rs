@@ -0,0 +1,347 @@
+// Minimal RTSP server (RFC 2326) so VLC, ffplay, smart TVs etc. can pull the PC
+// capture without the BudBridge app: "rtsp://<pc>:8554/live".
+//
+// One stream of uncompressed L16/48000/1 (what the capture pipeline already
+// produces), sent as RTP over UDP or interleaved on the RTSP connection for
+// clients behind firewalls. Supports OPTIONS, DESCRIBE, SETUP, PLAY, PAUSE,
+// TEARDOWN and GET/SET_PARAMETER keepalives; RTCP is not generated.
+
+use anyhow::Result;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+pub const DEFAULT_RTSP_PORT: u16 = 8554;
+pub const STREAM_PATH: &str = "live";
+const PAYLOAD_TYPE: u8 = 96; // dynamic: the static L16 types are 44.1 kHz only
+const SAMPLES_PER_PACKET: usize = 480; // 10 ms at 48 kHz
+const SESSION_TIMEOUT_SECS: u32 = 60;
+const WRITE_TIMEOUT: Duration = Duration::from_millis(50);
+
+enum SinkTarget {
+    Udp { socket: UdpSocket, addr: SocketAddr },
+    Interleaved { stream: Arc<Mutex<TcpStream>>, channel: u8 },
+}
+
+struct Sink {
+    target: SinkTarget,
+    playing: bool,
+}
+
+#[derive(Default)]
+struct FeedState {
+    sinks: HashMap<u64, Sink>, // by session id
+    pending: Vec<i16>,
+    sequence: u16,
+    timestamp: u32,
+    ssrc: u32,
+}
+
+/// Where the capture pipeline hands audio to RTSP clients
+#[derive(Clone, Default)]
+pub struct RtspFeed(Arc<Mutex<FeedState>>);
+
+impl RtspFeed {
+    /// Queue 48 kHz mono samples and send every full packet to the playing clients
+    pub fn publish(&self, samples: &[i16]) {
+        let mut feed = self.0.lock();
+        if !feed.sinks.values().any(|s| s.playing) {
+            feed.pending.clear();
+            return;
+        }
+        feed.pending.extend_from_slice(samples);
+
+        while feed.pending.len() >= SAMPLES_PER_PACKET {
+            let mut packet = Vec::with_capacity(12 + SAMPLES_PER_PACKET * 2);
+            packet.extend_from_slice(&[0x80, PAYLOAD_TYPE]);
+            packet.extend_from_slice(&feed.sequence.to_be_bytes());
+            packet.extend_from_slice(&feed.timestamp.to_be_bytes());
+            packet.extend_from_slice(&feed.ssrc.to_be_bytes());
+            // L16 is big-endian on the wire
+            for s in feed.pending.drain(..SAMPLES_PER_PACKET) {
+                packet.extend_from_slice(&s.to_be_bytes());
+            }
+            feed.sequence = feed.sequence.wrapping_add(1);
+            feed.timestamp = feed.timestamp.wrapping_add(SAMPLES_PER_PACKET as u32);
+
+            // A client that can't keep up is dropped rather than stalling the stream
+            feed.sinks.retain(|_, sink| !sink.playing || send_rtp(&sink.target, &packet));
+        }
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.0.lock().sinks.values().filter(|s| s.playing).count()
+    }
+}
+
+fn send_rtp(target: &SinkTarget, packet: &[u8]) -> bool {
+    match target {
+        // UDP errors (e.g. ICMP port unreachable) are transient; the RTSP connection
+        // closing is what ends a UDP session
+        SinkTarget::Udp { socket, addr } => {
+            let _ = socket.send_to(packet, addr);
+            true
+        }
+        SinkTarget::Interleaved { stream, channel } => {
+            let mut frame = vec![b'$', *channel];
+            frame.extend_from_slice(&(packet.len() as u16).to_be_bytes());
+            frame.extend_from_slice(packet);
+            stream.lock().write_all(&frame).is_ok()
+        }
+    }
+}
+
+/// Stops accepting clients when dropped; connected clients end with their connection
+pub struct RtspServer {
+    stop_flag: Arc<AtomicBool>,
+    feed: RtspFeed,
+}
+
+impl RtspServer {
+    pub fn start(port: u16) -> Result<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        listener.set_nonblocking(true)?;
+
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        let feed = RtspFeed::default();
+        feed.0.lock().ssrc = uuid::Uuid::new_v4().as_u128() as u32;
+
+        let stop = stop_flag.clone();
+        let accept_feed = feed.clone();
+        thread::spawn(move || {
+            let next_session = Arc::new(AtomicU64::new(1));
+            while !stop.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let feed = accept_feed.clone();
+                        let session = next_session.fetch_add(1, Ordering::SeqCst);
+                        let stop = stop.clone();
+                        thread::spawn(move || {
+                            let _ = serve_client(stream, session, &feed, &stop);
+                            feed.0.lock().sinks.remove(&session);
+                        });
+                    }
+                    Err(_) => thread::sleep(Duration::from_millis(100)),
+                }
+            }
+        });
+
+        Ok(Self { stop_flag, feed })
+    }
+
+    pub fn feed(&self) -> RtspFeed {
+        self.feed.clone()
+    }
+}
+
+impl Drop for RtspServer {
+    fn drop(&mut self) {
+        self.stop_flag.store(true, Ordering::SeqCst);
+        self.feed.0.lock().sinks.clear();
+    }
+}
+
+struct Request {
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Next request, skipping interleaved RTCP the client sends on the same connection.
+/// None when the client hung up.
+fn read_request(reader: &mut BufReader<TcpStream>) -> Option<Request> {
+    loop {
+        let first = reader.fill_buf().ok()?.first().copied()?;
+        if first == b'$' {
+            let mut header = [0u8; 4];
+            reader.read_exact(&mut header).ok()?;
+            let len = u16::from_be_bytes([header[2], header[3]]) as usize;
+            reader.read_exact(&mut vec![0u8; len]).ok()?;
+            continue;
+        }
+
+        let mut line = String::new();
+        reader.read_line(&mut line).ok().filter(|&n| n > 0)?;
+        let mut parts = line.split_whitespace();
+        let (Some(method), Some(url)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+        let mut request = Request { method: method.to_string(), url: url.to_string(), headers: Vec::new() };
+
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).ok().filter(|&n| n > 0)?;
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((k, v)) = header.split_once(':') {
+                request.headers.push((k.trim().to_string(), v.trim().to_string()));
+            }
+        }
+
+        // GET/SET_PARAMETER keepalives may carry a body we don't need
+        let body_len: usize = request.header("Content-Length").and_then(|l| l.parse().ok()).unwrap_or(0);
+        reader.read_exact(&mut vec![0u8; body_len]).ok()?;
+        return Some(request);
+    }
+}
+
+fn serve_client(stream: TcpStream, session: u64, feed: &RtspFeed, stop: &AtomicBool) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_millis(500)))?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let local_ip = stream.local_addr()?.ip();
+    let peer_ip = stream.peer_addr()?.ip();
+    let writer = Arc::new(Mutex::new(stream.try_clone()?));
+    let mut reader = BufReader::new(stream);
+
+    while !stop.load(Ordering::SeqCst) {
+        let Some(request) = read_request(&mut reader) else {
+            // Read timeouts surface as None too; only a closed socket ends the session
+            if reader.get_ref().peek(&mut [0u8; 1]).is_ok_and(|n| n == 0) {
+                break;
+            }
+            continue;
+        };
+
+        let cseq = request.header("CSeq").unwrap_or("0").to_string();
+        let session_header = format!("Session: {};timeout={}", session, SESSION_TIMEOUT_SECS);
+        let (status, headers, body) = match request.method.as_str() {
+            "OPTIONS" => (
+                "200 OK",
+                vec!["Public: OPTIONS, DESCRIBE, SETUP, PLAY, PAUSE, TEARDOWN, GET_PARAMETER, SET_PARAMETER".to_string()],
+                String::new(),
+            ),
+            "DESCRIBE" if path_matches(&request.url) => {
+                let base = request.url.trim_end_matches('/');
+                (
+                    "200 OK",
+                    vec![
+                        format!("Content-Base: {}/", base),
+                        "Content-Type: application/sdp".to_string(),
+                    ],
+                    sdp(local_ip, session),
+                )
+            }
+            "DESCRIBE" => ("404 Not Found", Vec::new(), String::new()),
+            "SETUP" => match setup_sink(request.header("Transport").unwrap_or(""), peer_ip, &writer) {
+                Some((sink, transport)) => {
+                    feed.0.lock().sinks.insert(session, sink);
+                    ("200 OK", vec![transport, session_header], String::new())
+                }
+                None => ("461 Unsupported Transport", Vec::new(), String::new()),
+            },
+            "PLAY" | "PAUSE" => {
+                let playing = request.method == "PLAY";
+                match feed.0.lock().sinks.get_mut(&session) {
+                    Some(sink) => {
+                        sink.playing = playing;
+                        let mut headers = vec![session_header];
+                        if playing {
+                            headers.push("Range: npt=0.000-".to_string());
+                        }
+                        ("200 OK", headers, String::new())
+                    }
+                    None => ("454 Session Not Found", Vec::new(), String::new()),
+                }
+            }
+            "TEARDOWN" => {
+                feed.0.lock().sinks.remove(&session);
+                ("200 OK", vec![session_header], String::new())
+            }
+            "GET_PARAMETER" | "SET_PARAMETER" => ("200 OK", vec![session_header], String::new()),
+            _ => ("405 Method Not Allowed", Vec::new(), String::new()),
+        };
+
+        let mut response = format!("RTSP/1.0 {}\r\nCSeq: {}\r\nServer: BudBridge\r\n", status, cseq);
+        for header in headers {
+            response.push_str(&header);
+            response.push_str("\r\n");
+        }
+        if !body.is_empty() {
+            response.push_str(&format!("Content-Length: {}\r\n", body.len()));
+        }
+        response.push_str("\r\n");
+        response.push_str(&body);
+        writer.lock().write_all(response.as_bytes())?;
+
+        if request.method == "TEARDOWN" {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn path_matches(url: &str) -> bool {
+    let path = url.split_once("://").map_or(url, |(_, rest)| rest.split_once('/').map_or("", |(_, p)| p));
+    path.trim_end_matches('/') == STREAM_PATH
+}
+
+fn sdp(local_ip: IpAddr, session: u64) -> String {
+    format!(
+        "v=0\r\n\
+         o=- {session} 1 IN IP4 {local_ip}\r\n\
+         s=BudBridge\r\n\
+         c=IN IP4 0.0.0.0\r\n\
+         t=0 0\r\n\
+         m=audio 0 RTP/AVP {PAYLOAD_TYPE}\r\n\
+         a=rtpmap:{PAYLOAD_TYPE} L16/48000/1\r\n\
+         a=ptime:10\r\n\
+         a=control:track1\r\n"
+    )
+}
+
+/// Sink for a SETUP request's Transport header, and the Transport header to answer with
+fn setup_sink(transport: &str, peer_ip: IpAddr, writer: &Arc<Mutex<TcpStream>>) -> Option<(Sink, String)> {
+    let param = |name: &str| {
+        transport
+            .split(';')
+            .find_map(|p| p.trim().strip_prefix(name).and_then(|v| v.strip_prefix('=')))
+            .map(|v| v.to_string())
+    };
+    let range = |value: String| -> Option<(u16, u16)> {
+        let (a, b) = value.split_once('-').unwrap_or((&value, ""));
+        let a: u16 = a.parse().ok()?;
+        Some((a, b.parse().unwrap_or(a.wrapping_add(1))))
+    };
+
+    if transport.contains("RTP/AVP/TCP") || transport.contains("interleaved") {
+        let (rtp, rtcp) = param("interleaved").and_then(range).unwrap_or((0, 1));
+        let sink = Sink {
+            target: SinkTarget::Interleaved { stream: writer.clone(), channel: rtp as u8 },
+            playing: false,
+        };
+        return Some((sink, format!("Transport: RTP/AVP/TCP;unicast;interleaved={}-{}", rtp, rtcp)));
+    }
+
+    let (rtp_port, rtcp_port) = param("client_port").and_then(range)?;
+    let socket = UdpSocket::bind(("0.0.0.0", 0)).ok()?;
+    let server_port = socket.local_addr().ok()?.port();
+    let sink = Sink {
+        target: SinkTarget::Udp { socket, addr: SocketAddr::new(peer_ip, rtp_port) },
+        playing: false,
+    };
+    let reply = format!(
+        "Transport: RTP/AVP;unicast;client_port={}-{};server_port={}-{}",
+        rtp_port,
+        rtcp_port,
+        server_port,
+        server_port.wrapping_add(1)
+    );
+    Some((sink, reply))
+}
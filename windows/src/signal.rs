@@ -0,0 +1,94 @@
+// Signal level per direction for Advanced diagnostics: the RMS of the last
+// 100 ms in dBFS. Whether a stream carries audio is that level against a
+// threshold the user sets. Counting packets with any sample over 100 called
+// quiet music silence and a dithered noise floor audio, since one stray sample
+// decided it.
+
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+
+/// 100 ms at 48 kHz
+const WINDOW_FRAMES: usize = 4800;
+/// Where digital silence is shown, instead of -∞
+pub const FLOOR_DB: f32 = -120.0;
+pub const DEFAULT_THRESHOLD_DB: f32 = -60.0;
+pub const THRESHOLD_RANGE_DB: RangeInclusive<f32> = -96.0..=-20.0;
+
+/// Sliding RMS of the samples recorded; ±1 is full scale
+#[derive(Default)]
+pub struct SignalLevel {
+    /// Sum of squares and frame count of each recorded block, oldest first
+    blocks: VecDeque<(f64, usize)>,
+}
+
+impl SignalLevel {
+    /// Adds a block of `channels`-interleaved samples and returns the level
+    pub fn record(&mut self, samples: impl Iterator<Item = f32>, channels: usize) -> f32 {
+        let (mut sum, mut count) = (0.0, 0);
+        for sample in samples {
+            sum += (sample as f64).powi(2);
+            count += 1;
+        }
+        if count > 0 {
+            self.blocks.push_back((sum / channels as f64, count / channels));
+        }
+        // Whole blocks only, keeping at least the window
+        while self.blocks.len() > 1 && self.frames() - self.blocks[0].1 >= WINDOW_FRAMES {
+            self.blocks.pop_front();
+        }
+        self.level_db().unwrap_or(FLOOR_DB)
+    }
+
+    fn frames(&self) -> usize {
+        self.blocks.iter().map(|&(_, frames)| frames).sum()
+    }
+
+    /// dBFS RMS over the window; None until something was recorded
+    pub fn level_db(&self) -> Option<f32> {
+        let frames = self.frames();
+        if frames == 0 {
+            return None;
+        }
+        let mean_square = self.blocks.iter().map(|&(sum, _)| sum).sum::<f64>() / frames as f64;
+        Some(if mean_square > 0.0 { (10.0 * mean_square.log10() as f32).max(FLOOR_DB) } else { FLOOR_DB })
+    }
+
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(amplitude: f32, frames: usize) -> impl Iterator<Item = f32> {
+        (0..frames).map(move |i| amplitude * (i as f32 * 0.05).sin())
+    }
+
+    #[test]
+    fn measures_rms_over_the_last_100_ms() {
+        let mut level = SignalLevel::default();
+        assert_eq!(level.level_db(), None);
+
+        // A quiet passage with no sample near the old threshold of 100 counts
+        let quiet = level.record(sine(0.002, 4800), 1);
+        assert!((quiet - -57.0).abs() < 0.2, "{}", quiet);
+        assert!(quiet > DEFAULT_THRESHOLD_DB);
+
+        // A dithered floor of ±1 LSB doesn't, and the quiet passage leaves the window
+        let lsb = 1.0 / 32768.0;
+        for _ in 0..10 {
+            level.record((0..960).map(|i| if i % 2 == 0 { lsb } else { -lsb }), 1);
+        }
+        let dither = level.level_db().unwrap();
+        assert!((dither - -90.3).abs() < 0.1, "{}", dither);
+        assert!(dither < DEFAULT_THRESHOLD_DB);
+
+        // Stereo blocks count frames, and full-scale is 0 dBFS
+        level.clear();
+        assert_eq!(level.record([1.0f32; 1920].into_iter(), 2), 0.0);
+        level.clear();
+        assert_eq!(level.record([0.0f32; 960].into_iter(), 1), FLOOR_DB);
+    }
+}
@@ -0,0 +1,40 @@
+// Receive buffer size (SO_RCVBUF) of the socket the phone's audio arrives on.
+// An access point holds a power-saving phone's packets and flushes them in one
+// burst when it wakes. Windows gives a UDP socket 64 KB by default, under a
+// second of PCM, so the rest of the burst was dropped before BudBridge read it.
+// Windows may grant a different size than asked for, so the size it reports
+// back is the one logged.
+
+use anyhow::{anyhow, Result};
+use std::net::UdpSocket;
+use std::ops::RangeInclusive;
+
+pub const DEFAULT_KB: u32 = 1024;
+pub const RANGE_KB: RangeInclusive<u32> = 64..=16384;
+
+/// Asks for `bytes` of receive buffer and returns what was granted
+#[cfg(target_os = "windows")]
+pub fn set_receive_buffer(socket: &UdpSocket, bytes: usize) -> Result<usize> {
+    use std::mem;
+    use std::os::windows::io::AsRawSocket;
+    use windows_sys::Win32::Networking::WinSock::{getsockopt, setsockopt, WSAGetLastError, SOCKET, SOL_SOCKET, SO_RCVBUF};
+
+    let sock = socket.as_raw_socket() as SOCKET;
+    let size = bytes.min(i32::MAX as usize) as i32;
+    unsafe {
+        if setsockopt(sock, SOL_SOCKET, SO_RCVBUF, &size as *const i32 as *const u8, 4) != 0 {
+            return Err(anyhow!("Could not set the receive buffer ({})", WSAGetLastError()));
+        }
+        let mut granted: i32 = 0;
+        let mut len = mem::size_of::<i32>() as i32;
+        if getsockopt(sock, SOL_SOCKET, SO_RCVBUF, &mut granted as *mut i32 as *mut u8, &mut len) != 0 {
+            return Err(anyhow!("Could not read the receive buffer size ({})", WSAGetLastError()));
+        }
+        Ok(granted.max(0) as usize)
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn set_receive_buffer(_socket: &UdpSocket, _bytes: usize) -> Result<usize> {
+    Err(anyhow!("Setting the receive buffer is only supported on Windows"))
+}
@@ -0,0 +1,203 @@
+// Audio sources the receivers can be routed from (receivers.rs). The session's
+// capture device is always one. The other is an ambiance loop: a WAV file read
+// into memory and played round and round alongside the capture, so one receiver
+// can get the game while another gets rain sounds.
+//
+// The loop advances once per outgoing frame, so it only plays while the capture
+// runs (connected, or "Stream without iPhone"). It is converted to 48 kHz stereo
+// float once, when loaded.
+
+use crate::convert;
+use anyhow::{anyhow, Result};
+use std::path::Path;
+
+const SAMPLE_RATE: u32 = 48_000;
+/// Longer loops take too much memory as float: two minutes is 46 MB
+const MAX_SECONDS: u32 = 120;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Source {
+    Capture,
+    Ambiance,
+}
+
+impl Source {
+    pub const ALL: [Self; 2] = [Self::Capture, Self::Ambiance];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Source::Capture => "PC audio",
+            Source::Ambiance => "Ambiance",
+        }
+    }
+
+    pub(crate) fn key(self) -> &'static str {
+        match self {
+            Source::Capture => "capture",
+            Source::Ambiance => "ambiance",
+        }
+    }
+
+    pub(crate) fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// A WAV file on repeat
+pub struct Ambiance {
+    stereo: Vec<f32>,
+    position: usize,
+}
+
+impl Ambiance {
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path).map_err(|e| anyhow!("Could not read {}: {}", path.display(), e))?;
+        Self::from_wav(&bytes)
+    }
+
+    fn from_wav(bytes: &[u8]) -> Result<Self> {
+        let wav = parse_wav(bytes)?;
+        if wav.frames() > (MAX_SECONDS * wav.sample_rate) as usize {
+            return Err(anyhow!("The file is longer than {} seconds", MAX_SECONDS));
+        }
+        let stereo = resample(&convert::to_stereo(&wav.samples, wav.channels), wav.sample_rate);
+        if stereo.is_empty() {
+            return Err(anyhow!("The file has no audio"));
+        }
+        Ok(Self { stereo, position: 0 })
+    }
+
+    pub fn seconds(&self) -> f32 {
+        self.stereo.len() as f32 / 2.0 / SAMPLE_RATE as f32
+    }
+
+    /// The next `len` interleaved stereo samples, wrapping at the end
+    pub fn next(&mut self, len: usize) -> Vec<f32> {
+        let mut out = Vec::with_capacity(len);
+        while out.len() < len {
+            let take = (len - out.len()).min(self.stereo.len() - self.position);
+            out.extend_from_slice(&self.stereo[self.position..self.position + take]);
+            self.position = (self.position + take) % self.stereo.len();
+        }
+        out
+    }
+}
+
+struct Wav {
+    channels: usize,
+    sample_rate: u32,
+    samples: Vec<f32>,
+}
+
+impl Wav {
+    fn frames(&self) -> usize {
+        self.samples.len() / self.channels
+    }
+}
+
+/// 16- and 24-bit PCM and 32-bit float, any rate and channel count
+fn parse_wav(bytes: &[u8]) -> Result<Wav> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(anyhow!("Not a WAV file"));
+    }
+    let u16_at = |at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+    let u32_at = |at: usize| u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+
+    let mut format = None;
+    let mut at = 12;
+    while at + 8 <= bytes.len() {
+        let id = &bytes[at..at + 4];
+        let len = u32_at(at + 4) as usize;
+        let body = &bytes[at + 8..(at + 8 + len).min(bytes.len())];
+        match id {
+            b"fmt " if body.len() >= 16 => {
+                let mut tag = u16_at(at + 8);
+                // WAVE_FORMAT_EXTENSIBLE keeps the real tag at the start of the subformat GUID
+                if tag == 0xFFFE && body.len() >= 26 {
+                    tag = u16_at(at + 8 + 24);
+                }
+                format = Some((tag, u16_at(at + 10) as usize, u32_at(at + 12), u16_at(at + 22)));
+            }
+            b"data" => {
+                let (tag, channels, sample_rate, bits) = format.ok_or_else(|| anyhow!("The WAV file has no format"))?;
+                if channels == 0 || sample_rate == 0 {
+                    return Err(anyhow!("The WAV format is invalid"));
+                }
+                let samples = match (tag, bits) {
+                    (1, 16) => body.chunks_exact(2).map(|s| convert::from_i16(i16::from_le_bytes([s[0], s[1]]))).collect(),
+                    (1, 24) => body
+                        .chunks_exact(3)
+                        .map(|s| convert::from_i24(i32::from_le_bytes([0, s[0], s[1], s[2]]) >> 8))
+                        .collect(),
+                    (3, 32) => body.chunks_exact(4).map(|s| f32::from_le_bytes([s[0], s[1], s[2], s[3]])).collect(),
+                    _ => return Err(anyhow!("Only 16-bit, 24-bit and float WAV files are supported")),
+                };
+                return Ok(Wav { channels, sample_rate, samples });
+            }
+            _ => {}
+        }
+        // Chunks are padded to an even length
+        at += 8 + len + len % 2;
+    }
+    Err(anyhow!("The WAV file has no audio data"))
+}
+
+/// Linear interpolation to 48 kHz; plenty for background sound
+fn resample(stereo: &[f32], rate: u32) -> Vec<f32> {
+    if rate == SAMPLE_RATE {
+        return stereo.to_vec();
+    }
+    let frames = stereo.len() / 2;
+    let out_frames = (frames as u64 * SAMPLE_RATE as u64 / rate as u64) as usize;
+    let mut out = Vec::with_capacity(out_frames * 2);
+    for i in 0..out_frames {
+        let pos = i as f64 * rate as f64 / SAMPLE_RATE as f64;
+        let (index, frac) = (pos as usize, pos.fract() as f32);
+        let next = (index + 1).min(frames - 1);
+        for channel in 0..2 {
+            let (a, b) = (stereo[index * 2 + channel], stereo[next * 2 + channel]);
+            out.push(a + (b - a) * frac);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wav(tag: u16, channels: u16, rate: u32, bits: u16, data: &[u8]) -> Vec<u8> {
+        let mut bytes = b"RIFF\0\0\0\0WAVE".to_vec();
+        // An odd-sized chunk first, to check the padding is skipped
+        bytes.extend_from_slice(b"LIST\x03\0\0\0abc\0");
+        bytes.extend_from_slice(b"fmt \x10\0\0\0");
+        bytes.extend_from_slice(&tag.to_le_bytes());
+        bytes.extend_from_slice(&channels.to_le_bytes());
+        bytes.extend_from_slice(&rate.to_le_bytes());
+        bytes.extend_from_slice(&(rate * channels as u32 * bits as u32 / 8).to_le_bytes());
+        bytes.extend_from_slice(&(channels * bits / 8).to_le_bytes());
+        bytes.extend_from_slice(&bits.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn loads_a_wav_and_loops_it() {
+        // Mono 16-bit at 48 kHz: doubled to stereo
+        let data: Vec<u8> = [16384i16, -16384, 0].iter().flat_map(|s| s.to_le_bytes()).collect();
+        let mut ambiance = Ambiance::from_wav(&wav(1, 1, 48_000, 16, &data)).unwrap();
+        assert_eq!(ambiance.next(4), [0.5, 0.5, -0.5, -0.5]);
+        assert_eq!(ambiance.next(6), [0.0, 0.0, 0.5, 0.5, -0.5, -0.5]);
+
+        // Float stereo at 24 kHz: each frame followed by one halfway to the next
+        let data: Vec<u8> = [0.0f32, 1.0, 1.0, 0.0].iter().flat_map(|s| s.to_le_bytes()).collect();
+        let mut ambiance = Ambiance::from_wav(&wav(3, 2, 24_000, 32, &data)).unwrap();
+        assert_eq!(ambiance.next(8), [0.0, 1.0, 0.5, 0.5, 1.0, 0.0, 1.0, 0.0]);
+
+        assert!(Ambiance::from_wav(&wav(1, 2, 48_000, 8, &[0, 0])).is_err());
+        assert!(Ambiance::from_wav(&wav(1, 2, 48_000, 16, &[])).is_err());
+        assert!(Ambiance::from_wav(b"RIFF\0\0\0\0AVI ").is_err());
+    }
+}
@@ -0,0 +1,403 @@
+// Rolling network timing statistics shown in Diagnostics.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+// Upper edges (ms) of the histogram buckets; the last bucket catches everything above
+pub const INTERVAL_BUCKETS_MS: [f32; 8] = [2.0, 5.0, 10.0, 15.0, 25.0, 40.0, 80.0, f32::INFINITY];
+const HISTORY: Duration = Duration::from_secs(5);
+
+/// Gaps between consecutive packets (and their sizes) over the last few seconds
+#[derive(Default)]
+pub struct IntervalHistory {
+    last: Option<Instant>,
+    intervals: VecDeque<(Instant, f32, usize)>,
+}
+
+impl IntervalHistory {
+    pub fn record(&mut self, now: Instant, bytes: usize) {
+        if let Some(last) = self.last {
+            self.intervals.push_back((now, now.duration_since(last).as_secs_f32() * 1000.0, bytes));
+        }
+        self.last = Some(now);
+        while self
+            .intervals
+            .front()
+            .is_some_and(|(t, _, _)| now.duration_since(*t) > HISTORY)
+        {
+            self.intervals.pop_front();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.intervals.clear();
+    }
+
+    /// Standard deviation of the packet gaps, in ms
+    pub fn jitter_ms(&self) -> Option<f32> {
+        if self.intervals.len() < 2 {
+            return None;
+        }
+        let n = self.intervals.len() as f32;
+        let mean = self.intervals.iter().map(|(_, ms, _)| ms).sum::<f32>() / n;
+        let variance = self.intervals.iter().map(|(_, ms, _)| (ms - mean).powi(2)).sum::<f32>() / n;
+        Some(variance.sqrt())
+    }
+
+    /// Average payload rate since the oldest retained packet
+    pub fn bytes_per_sec(&self, now: Instant) -> Option<f32> {
+        let (oldest, _, _) = self.intervals.front()?;
+        let span = now.duration_since(*oldest).as_secs_f32();
+        if span < 1.0 {
+            return None;
+        }
+        Some(self.intervals.iter().map(|(_, _, b)| *b).sum::<usize>() as f32 / span)
+    }
+
+    /// Average packet rate since the oldest retained packet
+    pub fn packets_per_sec(&self, now: Instant) -> Option<f32> {
+        let (oldest, _, _) = self.intervals.front()?;
+        let span = now.duration_since(*oldest).as_secs_f32();
+        if span < 1.0 {
+            return None;
+        }
+        Some(self.intervals.len() as f32 / span)
+    }
+
+    /// Counts per bucket of INTERVAL_BUCKETS_MS, plus the largest gap seen
+    pub fn histogram(&self) -> ([u32; INTERVAL_BUCKETS_MS.len()], f32) {
+        let mut counts = [0u32; INTERVAL_BUCKETS_MS.len()];
+        let mut max = 0.0f32;
+        for &(_, ms, _) in &self.intervals {
+            let bucket = INTERVAL_BUCKETS_MS.iter().position(|&edge| ms <= edge).unwrap_or(counts.len() - 1);
+            counts[bucket] += 1;
+            max = max.max(ms);
+        }
+        (counts, max)
+    }
+}
+
+/// State of the playback (phone → PC) jitter buffer, for Advanced diagnostics.
+/// Written by the receive thread and the output callback, read by the UI.
+/// Depths and durations are in 48 kHz mono samples.
+#[derive(Default)]
+pub struct JitterBufferStats {
+    target: AtomicUsize,
+    limit: AtomicUsize,
+    adaptive: AtomicBool,    // the target follows the link (see playout.rs) and playback refills to it
+    depth: AtomicUsize,
+    expansions: AtomicU64,   // underruns: the buffer ran dry and silence was played
+    contractions: AtomicU64, // overruns: the oldest audio was dropped to get back to target
+    dropped: AtomicU64,
+    concealed: AtomicU64,    // silence played in place of late audio
+    primed: AtomicBool,      // audio has arrived, so running dry counts as an underrun
+    starved: AtomicBool,
+}
+
+/// One reading of JitterBufferStats, in milliseconds
+pub struct JitterBufferSnapshot {
+    pub target_ms: f32,
+    pub limit_ms: f32,
+    pub adaptive: bool,
+    pub depth_ms: f32,
+    pub expansions: u64,
+    pub contractions: u64,
+    pub dropped_ms: f32,
+    pub concealed_ms: f32,
+}
+
+fn samples_to_ms(samples: u64) -> f32 {
+    samples as f32 / 48.0
+}
+
+impl JitterBufferStats {
+    /// Fixed: `target` is also the limit. Adaptive: it's the limit to start
+    /// with, and playback refills to half of it after running dry.
+    pub fn reset(&self, target: usize, adaptive: bool) {
+        self.set_target(if adaptive { target / 2 } else { target }, target);
+        self.adaptive.store(adaptive, Ordering::Relaxed);
+        self.depth.store(0, Ordering::Relaxed);
+        self.expansions.store(0, Ordering::Relaxed);
+        self.contractions.store(0, Ordering::Relaxed);
+        self.dropped.store(0, Ordering::Relaxed);
+        self.concealed.store(0, Ordering::Relaxed);
+        self.primed.store(false, Ordering::Relaxed);
+        self.starved.store(false, Ordering::Relaxed);
+    }
+
+    pub fn set_target(&self, target: usize, limit: usize) {
+        self.target.store(target, Ordering::Relaxed);
+        self.limit.store(limit, Ordering::Relaxed);
+    }
+
+    /// Most audio held before the oldest is dropped, in samples
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    /// Depth playback waits for after running dry, in samples; 0 when fixed,
+    /// which plays whatever has arrived
+    pub fn refill_depth(&self) -> usize {
+        if self.adaptive.load(Ordering::Relaxed) {
+            self.target.load(Ordering::Relaxed)
+        } else {
+            0
+        }
+    }
+
+    /// Times playback ran dry since connecting
+    pub fn underruns(&self) -> u64 {
+        self.expansions.load(Ordering::Relaxed)
+    }
+
+    /// After new audio was queued and `dropped` old samples were trimmed
+    pub fn record_arrival(&self, depth: usize, dropped: usize) {
+        self.depth.store(depth, Ordering::Relaxed);
+        self.primed.store(true, Ordering::Relaxed);
+        if dropped > 0 {
+            self.contractions.fetch_add(1, Ordering::Relaxed);
+            self.dropped.fetch_add(dropped as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// After an output callback took what it needed, `missing` samples short
+    pub fn record_playback(&self, depth: usize, missing: usize) {
+        self.depth.store(depth, Ordering::Relaxed);
+        if !self.primed.load(Ordering::Relaxed) {
+            return;
+        }
+        if missing > 0 {
+            self.concealed.fetch_add(missing as u64, Ordering::Relaxed);
+            if !self.starved.swap(true, Ordering::Relaxed) {
+                self.expansions.fetch_add(1, Ordering::Relaxed);
+            }
+        } else {
+            self.starved.store(false, Ordering::Relaxed);
+        }
+    }
+
+    pub fn snapshot(&self) -> JitterBufferSnapshot {
+        JitterBufferSnapshot {
+            target_ms: samples_to_ms(self.target.load(Ordering::Relaxed) as u64),
+            limit_ms: samples_to_ms(self.limit.load(Ordering::Relaxed) as u64),
+            adaptive: self.adaptive.load(Ordering::Relaxed),
+            depth_ms: samples_to_ms(self.depth.load(Ordering::Relaxed) as u64),
+            expansions: self.expansions.load(Ordering::Relaxed),
+            contractions: self.contractions.load(Ordering::Relaxed),
+            dropped_ms: samples_to_ms(self.dropped.load(Ordering::Relaxed)),
+            concealed_ms: samples_to_ms(self.concealed.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Loss and reordering from the sequence numbers of framed packets. A gap counts
+/// as lost until the missing packet turns up late, then as reordered instead.
+#[derive(Default)]
+pub struct SequenceHistory {
+    highest: Option<u32>,
+    lost: u64,
+    reordered: u64,
+    // Per packet: how far it moved the sequence on, and the lost count's change
+    window: VecDeque<(Instant, u32, i64)>,
+}
+
+/// Further behind than this, the sender restarted rather than reordered
+const REORDER_LIMIT: u32 = 1000;
+
+impl SequenceHistory {
+    pub fn record(&mut self, now: Instant, seq: u32) {
+        let Some(highest) = self.highest else {
+            self.advance(now, seq, 1);
+            return;
+        };
+        let ahead = seq.wrapping_sub(highest);
+        let behind = highest.wrapping_sub(seq);
+        if ahead == 0 {
+            // Duplicate
+        } else if ahead < u32::MAX / 2 {
+            self.lost += ahead as u64 - 1;
+            self.advance(now, seq, ahead);
+        } else if behind < REORDER_LIMIT {
+            // Late: it was counted lost when the sequence skipped over it
+            if self.lost > 0 {
+                self.lost -= 1;
+                self.reordered += 1;
+                self.window.push_back((now, 0, -1));
+            }
+        } else {
+            // The sender started over
+            self.advance(now, seq, 1);
+        }
+        self.trim(now);
+    }
+
+    fn advance(&mut self, now: Instant, seq: u32, ahead: u32) {
+        self.highest = Some(seq);
+        self.window.push_back((now, ahead, ahead as i64 - 1));
+    }
+
+    fn trim(&mut self, now: Instant) {
+        while self.window.front().is_some_and(|(t, _, _)| now.duration_since(*t) > HISTORY) {
+            self.window.pop_front();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Packets that never arrived, this session
+    pub fn lost(&self) -> u64 {
+        self.lost
+    }
+
+    /// Packets that arrived after a later one, this session
+    pub fn reordered(&self) -> u64 {
+        self.reordered
+    }
+
+    /// Share of the packets sent over the last few seconds that never arrived
+    pub fn loss_pct(&self) -> Option<f32> {
+        let expected: u64 = self.window.iter().map(|&(_, advance, _)| advance as u64).sum();
+        if expected < 10 {
+            return None;
+        }
+        let lost: i64 = self.window.iter().map(|&(_, _, lost)| lost).sum();
+        Some((lost.max(0) as f32 / expected as f32 * 100.0).min(100.0))
+    }
+}
+
+/// Per-second rate of a running count, taken once a second however often the
+/// UI draws
+#[derive(Default)]
+pub struct RateMeter {
+    last: Option<(Instant, u64)>,
+    per_sec: u64,
+}
+
+impl RateMeter {
+    pub fn update(&mut self, total: u64, now: Instant) -> u64 {
+        match self.last {
+            Some((at, _)) if now.duration_since(at) < Duration::from_secs(1) => {}
+            Some((at, count)) => {
+                let secs = now.duration_since(at).as_secs_f64();
+                self.per_sec = (total.saturating_sub(count) as f64 / secs).round() as u64;
+                self.last = Some((now, total));
+            }
+            None => self.last = Some((now, total)),
+        }
+        self.per_sec
+    }
+}
+
+/// Overall link health from 1 (unusable) to 5 (excellent); the worst metric decides
+pub struct LinkQuality {
+    pub score: u8,
+    pub loss_pct: f32,
+    pub jitter_ms: f32,
+    pub rtt_ms: Option<f32>,
+}
+
+impl LinkQuality {
+    pub fn assess(loss_pct: f32, jitter_ms: f32, rtt_ms: Option<f32>) -> Self {
+        let score = loss_score(loss_pct)
+            .min(jitter_score(jitter_ms))
+            .min(rtt_ms.map(rtt_score).unwrap_or(5));
+        Self { score, loss_pct, jitter_ms, rtt_ms }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self.score {
+            5 => "Excellent",
+            4 => "Good",
+            3 => "Fair",
+            2 => "Poor",
+            _ => "Bad",
+        }
+    }
+
+    /// Plain-language explanation of each metric, worst first
+    pub fn explain(&self) -> Vec<String> {
+        let mut lines = vec![
+            (loss_score(self.loss_pct), format!(
+                "Missing audio: {:.1}% — {}",
+                self.loss_pct,
+                if loss_score(self.loss_pct) < 4 { "packets are being dropped, audio will crackle" } else { "fine" }
+            )),
+            (jitter_score(self.jitter_ms), format!(
+                "Timing jitter: {:.1} ms — {}",
+                self.jitter_ms,
+                if jitter_score(self.jitter_ms) < 4 { "packets arrive in bursts, raise the buffer size" } else { "fine" }
+            )),
+        ];
+        match self.rtt_ms {
+            Some(rtt) => lines.push((rtt_score(rtt), format!(
+                "Round trip: {:.0} ms — {}",
+                rtt,
+                if rtt_score(rtt) < 4 { "the network is slow, expect extra delay" } else { "fine" }
+            ))),
+            None => lines.push((5, "Round trip: not measured (use Test connection)".to_string())),
+        }
+        lines.sort_by_key(|(score, _)| *score);
+        lines.into_iter().map(|(_, line)| line).collect()
+    }
+}
+
+fn loss_score(loss_pct: f32) -> u8 {
+    match loss_pct {
+        l if l < 1.0 => 5,
+        l if l < 3.0 => 4,
+        l if l < 8.0 => 3,
+        l if l < 15.0 => 2,
+        _ => 1,
+    }
+}
+
+fn jitter_score(jitter_ms: f32) -> u8 {
+    match jitter_ms {
+        j if j < 5.0 => 5,
+        j if j < 10.0 => 4,
+        j if j < 20.0 => 3,
+        j if j < 40.0 => 2,
+        _ => 1,
+    }
+}
+
+fn rtt_score(rtt_ms: f32) -> u8 {
+    match rtt_ms {
+        r if r < 30.0 => 5,
+        r if r < 60.0 => 4,
+        r if r < 120.0 => 3,
+        r if r < 250.0 => 2,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequence_gaps_count_as_lost_until_the_packet_turns_up() {
+        let now = Instant::now();
+        let mut history = SequenceHistory::default();
+        for seq in (u32::MAX - 5..=u32::MAX).chain(0..10) {
+            if seq != 3 && seq != 4 {
+                history.record(now, seq);
+            }
+        }
+        assert_eq!((history.lost(), history.reordered()), (2, 0));
+        assert_eq!(history.loss_pct(), Some(12.5));
+
+        history.record(now, 3);
+        history.record(now, 9);
+        assert_eq!((history.lost(), history.reordered()), (1, 1));
+
+        // A restart far back isn't reordering
+        history.record(now, 100_000);
+        history.record(now, 5);
+        assert_eq!((history.lost(), history.reordered()), (100_000 - 10 + 1, 1));
+    }
+}
@@ -0,0 +1,125 @@
+// What the audio streams really run at, for the Stream info section and bug
+// reports: the device and config each stream was opened with (after the capture
+// ladder in capture.rs may have fallen back), and the callback size the driver
+// actually delivers, which the config usually leaves to the driver.
+
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// One opened stream
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stream {
+    pub device: String,
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Frames per callback asked for; None leaves it to the driver
+    pub buffer_frames: Option<u32>,
+    pub loopback: bool,
+}
+
+impl Stream {
+    pub fn from_config(device: String, config: &cpal::StreamConfig, loopback: bool) -> Self {
+        let buffer_frames = match config.buffer_size {
+            cpal::BufferSize::Fixed(frames) => Some(frames),
+            cpal::BufferSize::Default => None,
+        };
+        Self { device, sample_rate: config.sample_rate.0, channels: config.channels, buffer_frames, loopback }
+    }
+
+    /// `callback_frames` is the size of the latest callback, 0 before the first
+    pub fn describe(&self, callback_frames: usize) -> String {
+        let mut text = self.device.clone();
+        if self.loopback {
+            text += " (loopback)";
+        }
+        text += &format!(", {} Hz, {} ch", self.sample_rate, self.channels);
+        text += &match self.buffer_frames {
+            Some(frames) => format!(", {} frames asked", frames),
+            None => ", driver's buffer size".to_string(),
+        };
+        if callback_frames > 0 {
+            let ms = callback_frames as f32 * 1000.0 / self.sample_rate.max(1) as f32;
+            text += &format!(", {} frames per callback ({:.1} ms)", callback_frames, ms);
+        }
+        text
+    }
+}
+
+/// Written by the bridge and the stream callbacks, read by the UI
+#[derive(Default)]
+pub struct StreamInfo {
+    pub host: Mutex<Option<String>>,
+    pub capture: Mutex<Option<Stream>>,
+    pub playback: Mutex<Option<Stream>>,
+    capture_frames: AtomicUsize,
+    playback_frames: AtomicUsize,
+}
+
+impl StreamInfo {
+    pub fn clear(&self) {
+        *self.host.lock() = None;
+        *self.capture.lock() = None;
+        *self.playback.lock() = None;
+        self.capture_frames.store(0, Ordering::Relaxed);
+        self.playback_frames.store(0, Ordering::Relaxed);
+    }
+
+    /// From the capture callback, with the samples it got
+    pub fn capture_callback(&self, samples: usize, channels: u16) {
+        self.capture_frames.store(samples / channels.max(1) as usize, Ordering::Relaxed);
+    }
+
+    /// From the playback callback, with the samples it filled
+    pub fn playback_callback(&self, samples: usize, channels: u16) {
+        self.playback_frames.store(samples / channels.max(1) as usize, Ordering::Relaxed);
+    }
+
+    /// Host, capture and playback lines; empty until the streams are open
+    pub fn lines(&self) -> Vec<(&'static str, String)> {
+        let mut lines = Vec::new();
+        if let Some(host) = self.host.lock().clone() {
+            lines.push(("Audio host", host));
+        }
+        if let Some(capture) = &*self.capture.lock() {
+            lines.push(("Capture stream", capture.describe(self.capture_frames.load(Ordering::Relaxed))));
+        }
+        if let Some(playback) = &*self.playback.lock() {
+            lines.push(("Playback stream", playback.describe(self.playback_frames.load(Ordering::Relaxed))));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_what_the_driver_delivers() {
+        let info = StreamInfo::default();
+        assert!(info.lines().is_empty());
+
+        let config = cpal::StreamConfig {
+            channels: 2,
+            sample_rate: cpal::SampleRate(44_100),
+            buffer_size: cpal::BufferSize::Default,
+        };
+        *info.host.lock() = Some("WASAPI".to_string());
+        *info.capture.lock() = Some(Stream::from_config("Speakers".to_string(), &config, true));
+        assert_eq!(info.lines()[1].1, "Speakers (loopback), 44100 Hz, 2 ch, driver's buffer size");
+
+        info.capture_callback(882, 2);
+        assert_eq!(
+            info.lines()[1].1,
+            "Speakers (loopback), 44100 Hz, 2 ch, driver's buffer size, 441 frames per callback (10.0 ms)"
+        );
+
+        let fixed = cpal::StreamConfig { channels: 1, sample_rate: cpal::SampleRate(48_000), buffer_size: cpal::BufferSize::Fixed(256) };
+        *info.playback.lock() = Some(Stream::from_config("CABLE Input".to_string(), &fixed, false));
+        info.playback_callback(256, 1);
+        assert_eq!(info.lines()[2], ("Playback stream", "CABLE Input, 48000 Hz, 1 ch, 256 frames asked, 256 frames per callback (5.3 ms)".to_string()));
+
+        info.clear();
+        assert!(info.lines().is_empty());
+    }
+}
@@ -0,0 +1,264 @@
+// Connection state as a badge on BudBridge's taskbar button, in the status
+// light's colors: yellow while waiting for the phone, green connected, red after
+// an error. Minimized without a tray icon, the taskbar is the only place the
+// state can show. The badge is an overlay icon (ITaskbarList3), drawn here so
+// there are no icon files to ship. The tray icon (tray.rs) uses the same dot,
+// and has the button itself taken away while minimized to the tray.
+
+use anyhow::Result;
+use eframe::egui;
+
+#[cfg(target_os = "windows")]
+use imp::Taskbar;
+
+#[cfg(not(target_os = "windows"))]
+use fallback::Taskbar;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Badge {
+    Waiting,
+    Connected,
+    Error,
+}
+
+impl Badge {
+    pub fn color(self) -> egui::Color32 {
+        match self {
+            Badge::Waiting => egui::Color32::YELLOW,
+            Badge::Connected => egui::Color32::GREEN,
+            Badge::Error => egui::Color32::RED,
+        }
+    }
+
+    /// What screen readers say for the badge
+    #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+    fn description(self) -> &'static str {
+        match self {
+            Badge::Waiting => "Waiting for the iPhone",
+            Badge::Connected => "Connected",
+            Badge::Error => "Error",
+        }
+    }
+}
+
+/// Overlay icons are drawn at 16×16 whatever the display scale
+pub const SIZE: usize = 16;
+
+/// BGRA rows of a dot in `color` with a dark rim, so it reads on light and dark taskbars
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+pub fn dot_pixels(color: egui::Color32) -> Vec<u8> {
+    let center = SIZE as f32 / 2.0;
+    let radius = center - 0.5;
+    let mut pixels = Vec::with_capacity(SIZE * SIZE * 4);
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let distance = ((x as f32 + 0.5 - center).powi(2) + (y as f32 + 0.5 - center).powi(2)).sqrt();
+            // Antialiased edge, and the outer 1.5 px darkened for the rim
+            let alpha = (radius - distance + 0.5).clamp(0.0, 1.0);
+            let shade = if distance > radius - 1.5 { 0.35 } else { 1.0 };
+            let [r, g, b, _] = color.to_array();
+            let channel = |c: u8| (c as f32 * shade) as u8;
+            pixels.extend_from_slice(&[channel(b), channel(g), channel(r), (alpha * 255.0) as u8]);
+        }
+    }
+    pixels
+}
+
+/// Keeps the taskbar badge in step with the connection, touching the shell only on changes
+#[derive(Default)]
+pub struct Overlay {
+    shown: Option<Badge>,
+    button_removed: bool,
+    taskbar: Taskbar,
+}
+
+impl Overlay {
+    /// None removes the badge. An error is reported once per change, not retried every frame.
+    pub fn show(&mut self, badge: Option<Badge>) -> Result<()> {
+        if badge == self.shown {
+            return Ok(());
+        }
+        self.shown = badge;
+        self.taskbar.set(badge)
+    }
+
+    /// Takes BudBridge's taskbar button away or puts it back. A button put back
+    /// has lost its badge, so the next show() sets it again.
+    pub fn show_button(&mut self, shown: bool) -> Result<()> {
+        if shown != self.button_removed {
+            return Ok(());
+        }
+        self.button_removed = !shown;
+        if shown {
+            self.shown = None;
+        }
+        self.taskbar.set_button(shown)
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use super::{dot_pixels, Badge, SIZE};
+    use anyhow::{anyhow, Result};
+    use std::ffi::c_void;
+    use std::ptr;
+    use windows::core::{interface, IUnknown, IUnknown_Vtbl, GUID, HRESULT, PCWSTR};
+    use windows::Win32::System::Com::{CoCreateInstance, CoInitializeEx, CLSCTX_INPROC_SERVER, COINIT_APARTMENTTHREADED};
+    use windows_sys::Win32::UI::WindowsAndMessaging::{CreateIcon, DestroyIcon, FindWindowExW, GetWindowThreadProcessId};
+
+    const CLSID_TASKBAR_LIST: GUID = GUID::from_u128(0x56fdf344_fd6d_11d0_958a_006097c9a090);
+
+    // The windows crate's ITaskbarList3 only has SetOverlayIcon with its
+    // WindowsAndMessaging feature, for the HICON type; this takes windows-sys
+    // handles instead. Vtable order as in shobjidl.h, ITaskbarList and
+    // ITaskbarList2 folded in
+    #[interface("ea1afb91-9e28-4b86-90e9-9e9f8a5eefaf")]
+    unsafe trait ITaskbarList3: IUnknown {
+        fn HrInit(&self) -> HRESULT;
+        fn AddTab(&self, hwnd: *mut c_void) -> HRESULT;
+        fn DeleteTab(&self, hwnd: *mut c_void) -> HRESULT;
+        fn ActivateTab(&self, hwnd: *mut c_void) -> HRESULT;
+        fn SetActiveAlt(&self, hwnd: *mut c_void) -> HRESULT;
+        fn MarkFullscreenWindow(&self, hwnd: *mut c_void, fullscreen: i32) -> HRESULT;
+        fn SetProgressValue(&self, hwnd: *mut c_void, completed: u64, total: u64) -> HRESULT;
+        fn SetProgressState(&self, hwnd: *mut c_void, flags: i32) -> HRESULT;
+        fn RegisterTab(&self, tab: *mut c_void, mdi: *mut c_void) -> HRESULT;
+        fn UnregisterTab(&self, tab: *mut c_void) -> HRESULT;
+        fn SetTabOrder(&self, tab: *mut c_void, insert_before: *mut c_void) -> HRESULT;
+        fn SetTabActive(&self, tab: *mut c_void, mdi: *mut c_void, reserved: u32) -> HRESULT;
+        fn ThumbBarAddButtons(&self, hwnd: *mut c_void, count: u32, buttons: *const c_void) -> HRESULT;
+        fn ThumbBarUpdateButtons(&self, hwnd: *mut c_void, count: u32, buttons: *const c_void) -> HRESULT;
+        fn ThumbBarSetImageList(&self, hwnd: *mut c_void, images: *mut c_void) -> HRESULT;
+        fn SetOverlayIcon(&self, hwnd: *mut c_void, icon: *mut c_void, description: PCWSTR) -> HRESULT;
+    }
+
+    /// Created on first use, from the UI thread
+    #[derive(Default)]
+    pub struct Taskbar {
+        list: Option<ITaskbarList3>,
+        window: Option<*mut c_void>,
+        icons: Vec<(Badge, *mut c_void)>,
+    }
+
+    impl Taskbar {
+        pub fn set(&mut self, badge: Option<Badge>) -> Result<()> {
+            let window = self.window()?;
+            let icon = match badge {
+                Some(badge) => self.icon(badge)?,
+                None => ptr::null_mut(),
+            };
+            let description: Vec<u16> =
+                badge.map_or("", Badge::description).encode_utf16().chain(std::iter::once(0)).collect();
+            let list = self.list()?;
+            unsafe { list.SetOverlayIcon(window, icon, PCWSTR(description.as_ptr())) }
+                .ok()
+                .map_err(|e| anyhow!("SetOverlayIcon failed: {}", e))
+        }
+
+        pub fn set_button(&mut self, shown: bool) -> Result<()> {
+            let window = self.window()?;
+            let list = self.list()?;
+            let result = unsafe { if shown { list.AddTab(window) } else { list.DeleteTab(window) } };
+            result.ok().map_err(|e| anyhow!("{} failed: {}", if shown { "AddTab" } else { "DeleteTab" }, e))
+        }
+
+        fn list(&mut self) -> Result<&ITaskbarList3> {
+            if self.list.is_none() {
+                unsafe {
+                    // winit already made the UI thread an STA; that's fine too
+                    let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+                    let list: ITaskbarList3 = CoCreateInstance(&CLSID_TASKBAR_LIST, None, CLSCTX_INPROC_SERVER)?;
+                    list.HrInit().ok().map_err(|e| anyhow!("Taskbar not available: {}", e))?;
+                    self.list = Some(list);
+                }
+            }
+            Ok(self.list.as_ref().unwrap())
+        }
+
+        /// This process's top-level window titled BudBridge
+        fn window(&mut self) -> Result<*mut c_void> {
+            if let Some(window) = self.window {
+                return Ok(window);
+            }
+            let title: Vec<u16> = "BudBridge".encode_utf16().chain(std::iter::once(0)).collect();
+            let mut window = ptr::null_mut();
+            loop {
+                window = unsafe { FindWindowExW(ptr::null_mut(), window, ptr::null(), title.as_ptr()) };
+                if window.is_null() {
+                    return Err(anyhow!("BudBridge's window wasn't found"));
+                }
+                let mut pid = 0;
+                unsafe { GetWindowThreadProcessId(window, &mut pid) };
+                if pid == std::process::id() {
+                    self.window = Some(window);
+                    return Ok(window);
+                }
+            }
+        }
+
+        fn icon(&mut self, badge: Badge) -> Result<*mut c_void> {
+            if let Some(&(_, icon)) = self.icons.iter().find(|(b, _)| *b == badge) {
+                return Ok(icon);
+            }
+            // The 32-bit image carries its own alpha; the 1-bit mask only has to exist
+            let mask = [0u8; SIZE * SIZE / 8];
+            let pixels = dot_pixels(badge.color());
+            let icon = unsafe {
+                CreateIcon(ptr::null_mut(), SIZE as i32, SIZE as i32, 1, 32, mask.as_ptr(), pixels.as_ptr())
+            };
+            if icon.is_null() {
+                return Err(anyhow!("Could not create the badge icon"));
+            }
+            self.icons.push((badge, icon));
+            Ok(icon)
+        }
+    }
+
+    impl Drop for Taskbar {
+        fn drop(&mut self) {
+            for &(_, icon) in &self.icons {
+                unsafe { DestroyIcon(icon) };
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod fallback {
+    use super::Badge;
+    use anyhow::Result;
+
+    #[derive(Default)]
+    pub struct Taskbar;
+
+    impl Taskbar {
+        pub fn set(&mut self, _badge: Option<Badge>) -> Result<()> {
+            Ok(())
+        }
+
+        pub fn set_button(&mut self, _shown: bool) -> Result<()> {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_a_round_dot_with_a_dark_rim() {
+        let pixels = dot_pixels(Badge::Connected.color());
+        assert_eq!(pixels.len(), SIZE * SIZE * 4);
+        let at = |x: usize, y: usize| &pixels[(y * SIZE + x) * 4..][..4];
+
+        // Corners are clear, the middle is the color in BGRA, the edge darker
+        assert_eq!(at(0, 0)[3], 0);
+        assert_eq!(at(15, 15)[3], 0);
+        assert_eq!(at(8, 8), &[0, 255, 0, 255]);
+        assert_eq!(at(8, 1), &[0, 89, 0, 255]);
+
+        let red = dot_pixels(Badge::Error.color());
+        assert_eq!(&red[(8 * SIZE + 8) * 4..][..4], &[0, 0, 255, 255]);
+    }
+}
@@ -0,0 +1,236 @@
+// Opt-in anonymous session statistics, off unless chosen in Settings → Telemetry.
+// "Save locally" only writes each report as JSON under budbridgeconfig/telemetry/;
+// "Send" POSTs it to the collector URL the user enters instead. Reports hold
+// aggregates only: no addresses, device names, session codes or audio.
+//
+// Sending is plain HTTP/1.1 with no TLS, so point it at a collector you run
+// yourself (on the LAN, or behind a reverse proxy that does HTTPS).
+
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const LOSS_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Mode {
+    #[default]
+    Off,
+    Local,
+    Send,
+}
+
+impl Mode {
+    pub fn parse(text: &str) -> Self {
+        match text {
+            "local" => Self::Local,
+            "send" => Self::Send,
+            _ => Self::Off,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Local => "local",
+            Self::Send => "send",
+        }
+    }
+}
+
+/// Running aggregates for the session in progress
+pub struct SessionTracker {
+    started: Instant,
+    link: &'static str,
+    loss_sum: f64,
+    loss_samples: u32,
+    last_sample: Option<Instant>,
+}
+
+impl SessionTracker {
+    /// `link` is the kind of path, e.g. "wifi" or "vpn"
+    pub fn new(link: &'static str) -> Self {
+        Self { started: Instant::now(), link, loss_sum: 0.0, loss_samples: 0, last_sample: None }
+    }
+
+    /// Called every UI frame; keeps one reading per second
+    pub fn record_loss(&mut self, loss_pct: f32) {
+        if self.last_sample.is_some_and(|t| t.elapsed() < LOSS_SAMPLE_INTERVAL) {
+            return;
+        }
+        self.last_sample = Some(Instant::now());
+        self.loss_sum += loss_pct as f64;
+        self.loss_samples += 1;
+    }
+
+    pub fn session_secs(&self) -> u64 {
+        self.started.elapsed().as_secs()
+    }
+
+    pub fn average_loss_pct(&self) -> Option<f32> {
+        (self.loss_samples > 0).then(|| (self.loss_sum / self.loss_samples as f64) as f32)
+    }
+
+    pub fn report(&self, codec_down: String, codec_up: String, redundancy: u8) -> Report {
+        Report {
+            app_version: env!("CARGO_PKG_VERSION"),
+            os: os_version(),
+            link: self.link,
+            session_secs: self.session_secs(),
+            average_loss_pct: self.average_loss_pct(),
+            codec_down,
+            codec_up,
+            redundancy,
+        }
+    }
+}
+
+pub struct Report {
+    app_version: &'static str,
+    os: String,
+    link: &'static str,
+    session_secs: u64,
+    average_loss_pct: Option<f32>,
+    codec_down: String,
+    codec_up: String,
+    redundancy: u8,
+}
+
+impl Report {
+    pub fn to_json(&self) -> String {
+        let loss = self.average_loss_pct.map_or("null".to_string(), |l| format!("{:.2}", l));
+        format!(
+            "{{\n  \"app_version\": {},\n  \"os\": {},\n  \"link\": {},\n  \"session_secs\": {},\n  \
+             \"average_loss_pct\": {},\n  \"codec_down\": {},\n  \"codec_up\": {},\n  \"redundancy\": {}\n}}",
+            json_string(self.app_version),
+            json_string(&self.os),
+            json_string(self.link),
+            self.session_secs,
+            loss,
+            json_string(&self.codec_down),
+            json_string(&self.codec_up),
+            self.redundancy,
+        )
+    }
+}
+
+pub fn json_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Write a report to `dir` as session-<unix time>.json
+pub fn save_local(dir: &Path, json: &str) -> Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let path = dir.join(format!("session-{}.json", secs));
+    fs::write(&path, json)?;
+    Ok(path)
+}
+
+/// POST a report to an http:// collector and check for a 2xx answer
+pub fn send(url: &str, json: &str) -> Result<()> {
+    let rest = url
+        .trim()
+        .strip_prefix("http://")
+        .ok_or_else(|| anyhow!("Collector URL must start with http://"))?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let with_port = if authority.contains(':') { authority.to_string() } else { format!("{}:80", authority) };
+    let addr = with_port
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow!("Could not resolve {}", authority))?;
+
+    let mut stream = TcpStream::connect_timeout(&addr, SEND_TIMEOUT)?;
+    stream.set_read_timeout(Some(SEND_TIMEOUT))?;
+    stream.set_write_timeout(Some(SEND_TIMEOUT))?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        authority,
+        json.len(),
+        json
+    )?;
+
+    let mut response = [0u8; 64];
+    let n = stream.read(&mut response)?;
+    let status_line = String::from_utf8_lossy(&response[..n]);
+    match status_line.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        Some(code) => Err(anyhow!("Collector answered {}", code)),
+        None => Err(anyhow!("No answer from the collector")),
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub use imp::os_version;
+
+#[cfg(not(target_os = "windows"))]
+pub use fallback::os_version;
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use windows_sys::Win32::System::Registry::{RegGetValueW, HKEY_LOCAL_MACHINE, RRF_RT_REG_SZ};
+
+    fn wide(text: &str) -> Vec<u16> {
+        text.encode_utf16().chain(std::iter::once(0)).collect()
+    }
+
+    fn current_version_value(name: &str) -> Option<String> {
+        let key = wide(r"SOFTWARE\Microsoft\Windows NT\CurrentVersion");
+        let name = wide(name);
+        let mut buf = [0u16; 64];
+        let mut size = std::mem::size_of_val(&buf) as u32;
+        let err = unsafe {
+            RegGetValueW(
+                HKEY_LOCAL_MACHINE,
+                key.as_ptr(),
+                name.as_ptr(),
+                RRF_RT_REG_SZ,
+                std::ptr::null_mut(),
+                buf.as_mut_ptr().cast(),
+                &mut size,
+            )
+        };
+        if err != 0 {
+            return None;
+        }
+        let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+        Some(String::from_utf16_lossy(&buf[..len]))
+    }
+
+    /// e.g. "Windows build 22631 (23H2)". The build number, not ProductName,
+    /// because ProductName still says "Windows 10" on Windows 11.
+    pub fn os_version() -> String {
+        let build = current_version_value("CurrentBuildNumber").unwrap_or_else(|| "unknown".to_string());
+        match current_version_value("DisplayVersion") {
+            Some(release) => format!("Windows build {} ({})", build, release),
+            None => format!("Windows build {}", build),
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod fallback {
+    pub fn os_version() -> String {
+        std::env::consts::OS.to_string()
+    }
+}
@@ -0,0 +1,150 @@
+// "Play test sound on phone": a short chime mixed into the outgoing stream, so a
+// new user can hear the PC → iPhone path work without starting any music. A
+// marker goes out with the chime's first samples and the phone reports back the
+// loudest level it handed to playback in the second after it (see protocol.rs),
+// which tells "played", "played only silence" and "never answered" apart.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Long enough for the chime, the phone's jitter buffer and the report's way back
+pub const ANSWER_TIMEOUT: Duration = Duration::from_secs(3);
+/// Quieter than this at the phone counts as not played
+const HEARD_DB: i32 = -40;
+
+const SAMPLE_RATE: f32 = 48_000.0;
+const CHIME_SECS: f32 = 0.6;
+
+/// Two bell-like notes, a fifth apart, in 48 kHz interleaved stereo
+pub fn chime() -> Vec<f32> {
+    let note = |t: f32, start: f32, freq: f32| {
+        let t = t - start;
+        if t < 0.0 {
+            return 0.0;
+        }
+        let attack = (t / 0.005).min(1.0);
+        (t * freq * std::f32::consts::TAU).sin() * attack * (-t * 6.0).exp()
+    };
+    (0..(CHIME_SECS * SAMPLE_RATE) as usize)
+        .flat_map(|i| {
+            let t = i as f32 / SAMPLE_RATE;
+            let s = 0.3 * (note(t, 0.0, 880.0) + note(t, 0.15, 1320.0)) / 2.0;
+            [s, s]
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    /// The phone played it, this loud (dBFS)
+    Played(i32),
+    /// The phone answered, but what it played was near silence
+    Silent(i32),
+    /// No report; an app from before test sounds, or the audio never arrived
+    NoAnswer,
+}
+
+impl Outcome {
+    pub fn is_success(&self) -> bool {
+        matches!(self, Outcome::Played(_))
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Outcome::Played(peak) => format!("✓ The iPhone played the test sound (peak {} dBFS)", peak),
+            Outcome::Silent(peak) => format!(
+                "⚠ The iPhone answered but played near silence (peak {} dBFS). Check its volume and the AirPods route.",
+                peak
+            ),
+            Outcome::NoAnswer => {
+                "⚠ The iPhone didn't confirm the test sound. Update the app if you heard it, otherwise check the connection.".to_string()
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct TestSound {
+    pending: VecDeque<f32>,
+    marker_due: bool,
+    waiting: Option<(u32, Instant)>,
+    next_id: u32,
+    pub outcome: Option<Outcome>,
+}
+
+impl TestSound {
+    pub fn start(&mut self, now: Instant) {
+        self.next_id = self.next_id.wrapping_add(1);
+        self.pending = chime().into();
+        self.marker_due = true;
+        self.waiting = Some((self.next_id, now));
+        self.outcome = None;
+    }
+
+    pub fn in_progress(&self) -> bool {
+        self.waiting.is_some()
+    }
+
+    /// Adds the next stretch of chime to captured stereo samples. Returns the
+    /// marker id to send along when these are the chime's first samples.
+    pub fn mix(&mut self, samples: &mut [f32]) -> Option<u32> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let count = samples.len().min(self.pending.len());
+        for (sample, chime) in samples.iter_mut().zip(self.pending.drain(..count)) {
+            *sample += chime;
+        }
+        std::mem::take(&mut self.marker_due).then_some(self.waiting?.0)
+    }
+
+    /// The phone's report for marker `id`
+    pub fn played(&mut self, id: u32, peak_db: i32) {
+        if self.waiting.is_some_and(|(waiting, _)| waiting == id) {
+            self.waiting = None;
+            self.outcome = Some(if peak_db >= HEARD_DB { Outcome::Played(peak_db) } else { Outcome::Silent(peak_db) });
+        }
+    }
+
+    pub fn check_timeout(&mut self, now: Instant) {
+        if self.waiting.is_some_and(|(_, started)| now.duration_since(started) >= ANSWER_TIMEOUT) {
+            self.waiting = None;
+            self.pending.clear();
+            self.outcome = Some(Outcome::NoAnswer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_played_silent_or_no_answer() {
+        let start = Instant::now();
+        let mut test = TestSound::default();
+        test.start(start);
+
+        // The marker goes with the first samples only
+        let mut block = vec![0.0; 960];
+        let id = test.mix(&mut block).unwrap();
+        assert!(block.iter().any(|s| *s != 0.0));
+        assert_eq!(test.mix(&mut block), None);
+
+        // A stale report changes nothing; the right one settles it
+        test.played(id.wrapping_sub(1), -10);
+        assert!(test.in_progress());
+        test.played(id, -12);
+        assert_eq!(test.outcome, Some(Outcome::Played(-12)));
+
+        test.start(start);
+        let id = test.mix(&mut block).unwrap();
+        test.played(id, -70);
+        assert_eq!(test.outcome, Some(Outcome::Silent(-70)));
+
+        test.start(start);
+        test.check_timeout(start + ANSWER_TIMEOUT);
+        assert_eq!(test.outcome, Some(Outcome::NoAnswer));
+        assert_eq!(test.mix(&mut block), None);
+    }
+}
@@ -0,0 +1,149 @@
+// What happened during sessions, with the time of day, so "it glitched at
+// 14:32" can be matched to a cause in Diagnostics. Any thread pushes events to
+// AppState's timeline: the UI for connects, retries and what it sees in the
+// counters (dropouts, the phone going quiet), the bridge for device switches.
+// The same event again soon after folds into the line before with a count, so
+// a burst of dropouts is one line, not a screenful.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+const KEPT: usize = 100;
+/// Repeats closer together than this fold into one line
+const FOLD_WITHIN: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Connected,
+    Disconnected,
+    Reconnecting,
+    DeviceChanged,
+    TargetSwitched,
+    /// Playback ran out of the phone's audio
+    Dropout,
+    /// No packets from the phone for a while, and when they came back
+    PeerLost,
+    PeerBack,
+    Error,
+}
+
+impl Kind {
+    fn label(self) -> &'static str {
+        match self {
+            Kind::Connected => "Connected",
+            Kind::Disconnected => "Disconnected",
+            Kind::Reconnecting => "Reconnecting",
+            Kind::DeviceChanged => "Device changed",
+            Kind::TargetSwitched => "Switched to",
+            Kind::Dropout => "Dropout",
+            Kind::PeerLost => "Phone went quiet",
+            Kind::PeerBack => "Phone back",
+            Kind::Error => "Error",
+        }
+    }
+
+    pub fn is_problem(self) -> bool {
+        matches!(self, Kind::Dropout | Kind::PeerLost | Kind::Error)
+    }
+}
+
+pub struct Entry {
+    /// Local time of the first occurrence, e.g. "14:32:05"
+    pub clock: String,
+    pub kind: Kind,
+    pub detail: String,
+    pub count: u32,
+    last: Instant,
+}
+
+impl Entry {
+    pub fn text(&self) -> String {
+        let mut text = self.kind.label().to_string();
+        if !self.detail.is_empty() {
+            text = format!("{}: {}", text, self.detail);
+        }
+        if self.count > 1 {
+            text = format!("{} ×{}", text, self.count);
+        }
+        text
+    }
+}
+
+#[derive(Default)]
+pub struct Timeline {
+    entries: VecDeque<Entry>,
+}
+
+impl Timeline {
+    pub fn push(&mut self, kind: Kind, detail: impl Into<String>) {
+        self.push_at(Instant::now(), local_clock(), kind, detail.into());
+    }
+
+    fn push_at(&mut self, now: Instant, clock: String, kind: Kind, detail: String) {
+        if let Some(last) = self.entries.back_mut() {
+            if last.kind == kind && last.detail == detail && now.duration_since(last.last) < FOLD_WITHIN {
+                last.count += 1;
+                last.last = now;
+                return;
+            }
+        }
+        if self.entries.len() == KEPT {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(Entry { clock, kind, detail, count: 1, last: now });
+    }
+
+    /// Oldest first
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &Entry> {
+        self.entries.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn local_clock() -> String {
+    use windows_sys::Win32::System::SystemInformation::GetLocalTime;
+    let mut time = unsafe { std::mem::zeroed() };
+    unsafe { GetLocalTime(&mut time) };
+    format!("{:02}:{:02}:{:02}", time.wHour, time.wMinute, time.wSecond)
+}
+
+/// UTC elsewhere; there's no local offset without a time zone database
+#[cfg(not(target_os = "windows"))]
+fn local_clock() -> String {
+    let secs = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    format!("{:02}:{:02}:{:02} UTC", secs / 3600 % 24, secs / 60 % 60, secs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeats_close_together_fold_into_one_line() {
+        let start = Instant::now();
+        let mut timeline = Timeline::default();
+        timeline.push_at(start, "14:30:00".into(), Kind::Connected, "Yusuf's iPhone".into());
+        for i in 0..3 {
+            timeline.push_at(start + Duration::from_secs(120 + i * 5), "14:32:00".into(), Kind::Dropout, String::new());
+        }
+        timeline.push_at(start + Duration::from_secs(300), "14:35:00".into(), Kind::Dropout, String::new());
+
+        let lines: Vec<(String, String)> = timeline.entries().map(|e| (e.clock.clone(), e.text())).collect();
+        assert_eq!(
+            lines,
+            [
+                ("14:30:00".to_string(), "Connected: Yusuf's iPhone".to_string()),
+                ("14:32:00".to_string(), "Dropout ×3".to_string()),
+                ("14:35:00".to_string(), "Dropout".to_string()),
+            ]
+        );
+    }
+}
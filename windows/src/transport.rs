@@ -0,0 +1,101 @@
+// Bluetooth (experimental): an RFCOMM stream to a paired phone for when there is no
+// network at all, e.g. on a plane. Datagrams are length-prefixed on the stream
+// (core's FramedStream), and run_network's Bridge runs the same session over it
+// as over UDP: hello, identity check, protection, FEC and framing. The iOS app
+// can't accept it yet, since iOS only opens RFCOMM to MFi accessories; the phone
+// side needs a listener registered under BUDBRIDGE_SERVICE_UUID. Classic
+// Bluetooth carries 1-2 Mbit/s, so 768 kbit/s each way only fits a good link.
+
+/// Saved device address prefix that selects Bluetooth, e.g. "bt:AA:BB:CC:DD:EE:FF"
+pub const BLUETOOTH_PREFIX: &str = "bt:";
+/// RFCOMM service the phone-side listener registers
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))] // only used by the Win32 backend
+pub const BUDBRIDGE_SERVICE_UUID: u128 = 0x3502ff53_8d78_4525_8205_39b0c6174944;
+
+/// 48-bit address from "bt:AA:BB:CC:DD:EE:FF", or None for anything else
+pub fn parse_bluetooth_address(target: &str) -> Option<u64> {
+    let hex = target.trim().strip_prefix(BLUETOOTH_PREFIX)?;
+    let octets: Vec<&str> = hex.split(':').collect();
+    if octets.len() != 6 || octets.iter().any(|o| o.len() != 2) {
+        return None;
+    }
+    octets
+        .iter()
+        .try_fold(0u64, |addr, o| u8::from_str_radix(o, 16).ok().map(|b| addr << 8 | b as u64))
+}
+
+#[cfg(target_os = "windows")]
+pub use imp::connect_bluetooth;
+
+#[cfg(not(target_os = "windows"))]
+pub use fallback::connect_bluetooth;
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use super::BUDBRIDGE_SERVICE_UUID;
+    use anyhow::{anyhow, Result};
+    use budbridge_core::FramedStream;
+    use std::mem;
+    use std::net::TcpStream;
+    use std::os::windows::io::FromRawSocket;
+    use std::time::Duration;
+    use windows_sys::core::GUID;
+    use windows_sys::Win32::Devices::Bluetooth::{AF_BTH, BTHPROTO_RFCOMM, SOCKADDR_BTH};
+    use windows_sys::Win32::Networking::WinSock::{
+        closesocket, connect, socket, WSAGetLastError, WSAStartup, INVALID_SOCKET, SOCKADDR, SOCK_STREAM, WSADATA,
+    };
+
+    /// Connect to the BudBridge RFCOMM service on a paired device. Windows looks the
+    /// channel up via SDP from the service UUID.
+    pub fn connect_bluetooth(address: u64) -> Result<FramedStream<TcpStream>> {
+        unsafe {
+            // std only initializes Winsock when it creates a socket itself
+            let mut data: WSADATA = mem::zeroed();
+            let err = WSAStartup(0x0202, &mut data);
+            if err != 0 {
+                return Err(anyhow!("WSAStartup failed ({})", err));
+            }
+
+            let sock = socket(AF_BTH as i32, SOCK_STREAM, BTHPROTO_RFCOMM as i32);
+            if sock == INVALID_SOCKET {
+                return Err(anyhow!("Could not create a Bluetooth socket ({})", WSAGetLastError()));
+            }
+            let addr = SOCKADDR_BTH {
+                addressFamily: AF_BTH,
+                btAddr: address,
+                serviceClassId: GUID::from_u128(BUDBRIDGE_SERVICE_UUID),
+                port: 0,
+            };
+            let err = connect(
+                sock,
+                &addr as *const SOCKADDR_BTH as *const SOCKADDR,
+                mem::size_of::<SOCKADDR_BTH>() as i32,
+            );
+            if err != 0 {
+                let code = WSAGetLastError();
+                closesocket(sock);
+                return Err(anyhow!(
+                    "Bluetooth connect failed ({}); is the phone paired and running the BudBridge listener?",
+                    code
+                ));
+            }
+
+            // std's stream wrapper only issues send/recv/setsockopt, which work on
+            // any stream socket, and closes it on drop
+            let stream = TcpStream::from_raw_socket(sock as _);
+            stream.set_read_timeout(Some(Duration::from_millis(1)))?;
+            Ok(FramedStream::new(stream))
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod fallback {
+    use anyhow::{anyhow, Result};
+    use budbridge_core::FramedStream;
+    use std::net::TcpStream;
+
+    pub fn connect_bluetooth(_address: u64) -> Result<FramedStream<TcpStream>> {
+        Err(anyhow!("The Bluetooth transport is only supported on Windows"))
+    }
+}
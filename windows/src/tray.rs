@@ -0,0 +1,229 @@
+// BudBridge in the notification area, for leaving it running all day: a dot in
+// the status light's colors (gray when idle), a click to bring the window back,
+// and Show, Connect, Disconnect, the profiles and Quit on its right-click menu
+// (tray-icon). Menu picks and clicks are sent as ControlCommands, like a second
+// launch's; a profile picked there is applied as `--profile` would.
+//
+// Minimizing to the tray keeps the window minimized and takes away its taskbar
+// button (taskbar.rs) rather than hiding it: eframe stops calling update for a
+// hidden window, and that is where the commands are handled.
+
+use crate::control::ControlCommand;
+use crate::taskbar::Badge;
+use anyhow::Result;
+use crossbeam_channel::Sender;
+use eframe::egui;
+
+#[cfg(target_os = "windows")]
+use imp::Icon;
+
+#[cfg(not(target_os = "windows"))]
+use fallback::Icon;
+
+/// The shell cuts tray tooltips at 127 characters
+const TOOLTIP_MAX: usize = 127;
+
+/// What the icon shows
+#[derive(Clone, Debug, PartialEq)]
+pub struct State {
+    pub badge: Option<Badge>,
+    /// Picks which of Connect and Disconnect the menu offers
+    pub connected: bool,
+    pub tooltip: String,
+    /// Names for the Profiles submenu, in the Connection tab's order
+    pub profiles: Vec<String>,
+}
+
+/// The dot's color: the status light's, or gray with nothing to report
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn color(badge: Option<Badge>) -> egui::Color32 {
+    badge.map_or(egui::Color32::GRAY, Badge::color)
+}
+
+/// The status line, named and cut to what the shell shows
+pub fn tooltip(status: &str) -> String {
+    let text = if status.is_empty() { "BudBridge".to_string() } else { format!("BudBridge: {}", status) };
+    text.chars().take(TOOLTIP_MAX).collect()
+}
+
+/// Keeps the tray icon in step with the connection, touching the shell only on changes
+#[derive(Default)]
+pub struct Tray {
+    shown: Option<State>,
+    icon: Option<Icon>,
+}
+
+impl Tray {
+    /// None removes the icon. Clicks and menu picks go to `commands`, waking `ctx`.
+    /// An error is reported once per change, not retried every frame.
+    pub fn show(&mut self, state: Option<State>, commands: &Sender<ControlCommand>, ctx: &egui::Context) -> Result<()> {
+        if state == self.shown {
+            return Ok(());
+        }
+        self.shown = state.clone();
+        let Some(state) = state else {
+            self.icon = None;
+            return Ok(());
+        };
+        match &mut self.icon {
+            Some(icon) => icon.update(&state),
+            None => {
+                self.icon = Some(Icon::new(&state, commands.clone(), ctx.clone())?);
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether there's an icon to come back to from a minimized window
+    pub fn is_shown(&self) -> bool {
+        self.icon.is_some()
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use super::{color, State};
+    use crate::control::ControlCommand;
+    use crate::taskbar::{dot_pixels, SIZE};
+    use anyhow::{anyhow, Result};
+    use crossbeam_channel::Sender;
+    use eframe::egui;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+    use tray_icon::menu::{Menu, MenuEvent, MenuId, MenuItem, PredefinedMenuItem, Submenu};
+    use tray_icon::{MouseButton, MouseButtonState, TrayIcon, TrayIconBuilder, TrayIconEvent};
+
+    /// What each menu item sends; None is Quit, which closes the window the way
+    /// its X button does
+    type Actions = Arc<Mutex<Vec<(MenuId, Option<ControlCommand>)>>>;
+
+    pub struct Icon {
+        tray: TrayIcon,
+        connect: MenuItem,
+        disconnect: MenuItem,
+        profiles: Vec<String>,
+        actions: Actions,
+    }
+
+    /// The right-click menu, and the command behind each item
+    fn menu(state: &State) -> Result<(Menu, MenuItem, MenuItem, Vec<(MenuId, Option<ControlCommand>)>)> {
+        let show = MenuItem::new("Show BudBridge", true, None);
+        let connect = MenuItem::new("Connect", !state.connected, None);
+        let disconnect = MenuItem::new("Disconnect", state.connected, None);
+        let profiles = Submenu::new("Profiles", !state.profiles.is_empty());
+        let quit = MenuItem::new("Quit", true, None);
+        let mut actions = vec![
+            (show.id().clone(), Some(ControlCommand::Show)),
+            (connect.id().clone(), Some(ControlCommand::Connect)),
+            (disconnect.id().clone(), Some(ControlCommand::Disconnect)),
+            (quit.id().clone(), None),
+        ];
+        for name in &state.profiles {
+            let item = MenuItem::new(name, true, None);
+            profiles.append(&item).map_err(|e| anyhow!("Tray menu: {}", e))?;
+            actions.push((item.id().clone(), Some(ControlCommand::ApplyProfile(name.clone()))));
+        }
+        let menu = Menu::new();
+        menu.append_items(&[&show, &connect, &disconnect, &profiles, &PredefinedMenuItem::separator(), &quit])
+            .map_err(|e| anyhow!("Tray menu: {}", e))?;
+        Ok((menu, connect, disconnect, actions))
+    }
+
+    /// The taskbar badge's dot, its BGRA turned into the RGBA tray-icon takes
+    fn image(state: &State) -> Result<tray_icon::Icon> {
+        let mut pixels = dot_pixels(color(state.badge));
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+        tray_icon::Icon::from_rgba(pixels, SIZE as u32, SIZE as u32).map_err(|e| anyhow!("Bad tray icon: {}", e))
+    }
+
+    impl Icon {
+        /// Created on the UI thread, whose message loop the icon's events arrive on
+        pub fn new(state: &State, commands: Sender<ControlCommand>, ctx: egui::Context) -> Result<Self> {
+            let (menu, connect, disconnect, actions) = menu(state)?;
+            let actions: Actions = Arc::new(Mutex::new(actions));
+            let (menu_actions, menu_commands, menu_ctx) = (actions.clone(), commands.clone(), ctx.clone());
+            MenuEvent::set_event_handler(Some(move |event: MenuEvent| {
+                let action = menu_actions.lock().iter().find(|(id, _)| *id == event.id).map(|(_, command)| command.clone());
+                match action {
+                    Some(Some(command)) => {
+                        let _ = menu_commands.try_send(command);
+                    }
+                    Some(None) => menu_ctx.send_viewport_cmd(egui::ViewportCommand::Close),
+                    None => return,
+                }
+                menu_ctx.request_repaint();
+            }));
+            TrayIconEvent::set_event_handler(Some(move |event: TrayIconEvent| {
+                if let TrayIconEvent::Click { button: MouseButton::Left, button_state: MouseButtonState::Up, .. } = event {
+                    let _ = commands.try_send(ControlCommand::Show);
+                    ctx.request_repaint();
+                }
+            }));
+
+            let tray = TrayIconBuilder::new()
+                .with_menu(Box::new(menu))
+                .with_menu_on_left_click(false)
+                .with_tooltip(&state.tooltip)
+                .with_icon(image(state)?)
+                .build()
+                .map_err(|e| anyhow!("Could not add the tray icon: {}", e))?;
+            Ok(Self { tray, connect, disconnect, profiles: state.profiles.clone(), actions })
+        }
+
+        pub fn update(&mut self, state: &State) -> Result<()> {
+            // Profiles added, renamed or deleted: a new menu in place of the old
+            if state.profiles != self.profiles {
+                let (menu, connect, disconnect, actions) = menu(state)?;
+                self.tray.set_menu(Some(Box::new(menu)));
+                *self.actions.lock() = actions;
+                (self.connect, self.disconnect, self.profiles) = (connect, disconnect, state.profiles.clone());
+            }
+            self.connect.set_enabled(!state.connected);
+            self.disconnect.set_enabled(state.connected);
+            self.tray.set_icon(Some(image(state)?)).map_err(|e| anyhow!("Could not update the tray icon: {}", e))?;
+            self.tray
+                .set_tooltip(Some(&state.tooltip))
+                .map_err(|e| anyhow!("Could not update the tray tooltip: {}", e))
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod fallback {
+    use super::State;
+    use crate::control::ControlCommand;
+    use anyhow::Result;
+    use crossbeam_channel::Sender;
+    use eframe::egui;
+
+    pub struct Icon;
+
+    impl Icon {
+        pub fn new(_state: &State, _commands: Sender<ControlCommand>, _ctx: egui::Context) -> Result<Self> {
+            Ok(Self)
+        }
+
+        pub fn update(&mut self, _state: &State) -> Result<()> {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tooltip_names_the_app_and_fits_the_shell() {
+        assert_eq!(tooltip(""), "BudBridge");
+        assert_eq!(tooltip("Connected to Pixel"), "BudBridge: Connected to Pixel");
+        let long = tooltip(&"é".repeat(200));
+        assert_eq!(long.chars().count(), TOOLTIP_MAX);
+        assert!(long.starts_with("BudBridge: é"));
+
+        assert_eq!(color(None), egui::Color32::GRAY);
+        assert_eq!(color(Some(Badge::Error)), Badge::Error.color());
+    }
+}
@@ -0,0 +1,196 @@
+// Loopback capture straight from WASAPI. The "(Loopback)" entries used to go
+// through cpal, building an input stream on an output device, which some
+// drivers refuse or run at formats the capture can't take. Here the render
+// endpoint's own audio client is opened in loopback mode with Windows
+// converting to 48 kHz stereo float, which works on any render device. When it
+// fails the bridge falls back to cpal's stream.
+//
+// Process loopback (process_loopback.rs) shares the capture thread: only how
+// the audio client is made differs.
+
+#[cfg(target_os = "windows")]
+pub use imp::*;
+
+#[cfg(not(target_os = "windows"))]
+pub use fallback::*;
+
+/// What the capture hands over, whatever the device plays at
+pub const SAMPLE_RATE: u32 = 48_000;
+pub const CHANNELS: u16 = 2;
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use super::{CHANNELS, SAMPLE_RATE};
+    use anyhow::{anyhow, Result};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc;
+    use std::sync::Arc;
+    use std::thread::{self, JoinHandle};
+    use std::time::Duration;
+    use windows::Win32::Devices::FunctionDiscovery::PKEY_Device_FriendlyName;
+    use windows::Win32::Media::Audio::{
+        eRender, IAudioCaptureClient, IAudioClient, IMMDevice, IMMDeviceEnumerator, MMDeviceEnumerator,
+        AUDCLNT_BUFFERFLAGS_SILENT, AUDCLNT_SHAREMODE_SHARED, AUDCLNT_STREAMFLAGS_AUTOCONVERTPCM,
+        AUDCLNT_STREAMFLAGS_LOOPBACK, AUDCLNT_STREAMFLAGS_SRC_DEFAULT_QUALITY, DEVICE_STATE_ACTIVE, WAVEFORMATEX,
+    };
+    use windows::Win32::System::Com::{CoCreateInstance, CoInitializeEx, CLSCTX_ALL, COINIT_MULTITHREADED, STGM_READ};
+
+    const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+    /// 20 ms of shared-mode buffer, in 100 ns units
+    const BUFFER_DURATION: i64 = 200_000;
+    const POLL: Duration = Duration::from_millis(5);
+
+    /// A running capture; stops when dropped
+    pub struct Capture {
+        stop: Arc<AtomicBool>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl Drop for Capture {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::SeqCst);
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    /// The `nth` active render device named `name`, counted as cpal lists them
+    fn endpoint(name: &str, nth: usize) -> Result<IMMDevice> {
+        unsafe {
+            let enumerator: IMMDeviceEnumerator = CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
+            let devices = enumerator.EnumAudioEndpoints(eRender, DEVICE_STATE_ACTIVE)?;
+            let mut seen = 0;
+            for i in 0..devices.GetCount()? {
+                let device = devices.Item(i)?;
+                if device.OpenPropertyStore(STGM_READ)?.GetValue(&PKEY_Device_FriendlyName)?.to_string() == name {
+                    if seen == nth {
+                        return Ok(device);
+                    }
+                    seen += 1;
+                }
+            }
+            Err(anyhow!("no playback device named \"{}\"", name))
+        }
+    }
+
+    /// Captures what the render device plays, handing `on_audio` interleaved
+    /// stereo at 48 kHz. `on_error` hears of a failure after it started.
+    pub fn start(
+        name: &str,
+        nth: usize,
+        on_audio: impl FnMut(&[f32]) + Send + 'static,
+        on_error: impl FnOnce(String) + Send + 'static,
+    ) -> Result<Capture> {
+        let name = name.to_string();
+        run(move || unsafe { Ok(endpoint(&name, nth)?.Activate(CLSCTX_ALL, None)?) }, on_audio, on_error)
+    }
+
+    /// Starts a loopback capture thread on the audio client `activate` makes;
+    /// COM objects stay on the thread that made them
+    pub fn run(
+        activate: impl FnOnce() -> Result<IAudioClient> + Send + 'static,
+        mut on_audio: impl FnMut(&[f32]) + Send + 'static,
+        on_error: impl FnOnce(String) + Send + 'static,
+    ) -> Result<Capture> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let stop_thread = stop.clone();
+        let thread = thread::spawn(move || {
+            let setup = || -> Result<(IAudioClient, IAudioCaptureClient)> {
+                unsafe {
+                    let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+                    let client = activate()?;
+                    let block = CHANNELS * 4;
+                    let format = WAVEFORMATEX {
+                        wFormatTag: WAVE_FORMAT_IEEE_FLOAT,
+                        nChannels: CHANNELS,
+                        nSamplesPerSec: SAMPLE_RATE,
+                        nAvgBytesPerSec: SAMPLE_RATE * block as u32,
+                        nBlockAlign: block,
+                        wBitsPerSample: 32,
+                        cbSize: 0,
+                    };
+                    client.Initialize(
+                        AUDCLNT_SHAREMODE_SHARED,
+                        AUDCLNT_STREAMFLAGS_LOOPBACK | AUDCLNT_STREAMFLAGS_AUTOCONVERTPCM | AUDCLNT_STREAMFLAGS_SRC_DEFAULT_QUALITY,
+                        BUFFER_DURATION,
+                        0,
+                        &format,
+                        None,
+                    )?;
+                    let capture: IAudioCaptureClient = client.GetService()?;
+                    client.Start()?;
+                    Ok((client, capture))
+                }
+            };
+            let (client, capture) = match setup() {
+                Ok(ok) => {
+                    let _ = ready_tx.send(Ok(()));
+                    ok
+                }
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e));
+                    return;
+                }
+            };
+
+            let mut samples = Vec::new();
+            let result = (|| -> Result<()> {
+                while !stop_thread.load(Ordering::SeqCst) {
+                    unsafe {
+                        while capture.GetNextPacketSize()? > 0 {
+                            let mut data = std::ptr::null_mut();
+                            let (mut frames, mut flags) = (0u32, 0u32);
+                            capture.GetBuffer(&mut data, &mut frames, &mut flags, None, None)?;
+                            let len = frames as usize * CHANNELS as usize;
+                            samples.clear();
+                            if flags & AUDCLNT_BUFFERFLAGS_SILENT.0 as u32 != 0 || data.is_null() {
+                                samples.resize(len, 0.0);
+                            } else {
+                                samples.extend_from_slice(std::slice::from_raw_parts(data as *const f32, len));
+                            }
+                            capture.ReleaseBuffer(frames)?;
+                            on_audio(&samples);
+                        }
+                    }
+                    thread::sleep(POLL);
+                }
+                Ok(())
+            })();
+            unsafe {
+                let _ = client.Stop();
+            }
+            if let Err(e) = result {
+                on_error(e.to_string());
+            }
+        });
+
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok(Capture { stop, thread: Some(thread) }),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(anyhow!("the loopback capture thread ended before starting")),
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod fallback {
+    use anyhow::{anyhow, Result};
+
+    pub struct Capture;
+
+    // Like the Windows one, so the bridge can stop it before the other streams
+    impl Drop for Capture {
+        fn drop(&mut self) {}
+    }
+
+    pub fn start(
+        _name: &str,
+        _nth: usize,
+        _on_audio: impl FnMut(&[f32]) + Send + 'static,
+        _on_error: impl FnOnce(String) + Send + 'static,
+    ) -> Result<Capture> {
+        Err(anyhow!("WASAPI loopback needs Windows"))
+    }
+}
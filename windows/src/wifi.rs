@@ -0,0 +1,199 @@
+// Current Wi-Fi link (band, channel, signal) from the WLAN API. Most dropout
+// reports come from PCs on a crowded 2.4 GHz network or at the edge of range.
+
+const WEAK_SIGNAL_QUALITY: u32 = 50;
+const WEAK_RSSI_DBM: i32 = -70;
+
+#[derive(Clone, Debug)]
+pub struct WifiInfo {
+    pub ssid: String,
+    pub signal_quality: u32, // 0-100 as reported by Windows
+    pub rssi_dbm: Option<i32>,
+    pub frequency_mhz: Option<u32>,
+    pub bssid: Option<[u8; 6]>,
+}
+
+impl WifiInfo {
+    pub fn band(&self) -> Option<&'static str> {
+        match self.frequency_mhz? {
+            2400..=2500 => Some("2.4 GHz"),
+            5000..=5924 => Some("5 GHz"),
+            5925..=7125 => Some("6 GHz"),
+            _ => None,
+        }
+    }
+
+    /// None outside those bands, e.g. 4.9 GHz public safety or a 0 from the driver
+    pub fn channel(&self) -> Option<u32> {
+        let f = self.frequency_mhz?;
+        if f == 2484 {
+            return Some(14);
+        }
+        let base = match self.band()? {
+            "2.4 GHz" => 2407,
+            "5 GHz" => 5000,
+            _ => 5950,
+        };
+        f.checked_sub(base).map(|offset| offset / 5)
+    }
+
+    pub fn summary(&self) -> String {
+        let mut text = format!("{} — signal {}%", self.ssid, self.signal_quality);
+        if let Some(rssi) = self.rssi_dbm {
+            text += &format!(" ({} dBm)", rssi);
+        }
+        if let (Some(band), Some(channel)) = (self.band(), self.channel()) {
+            text += &format!(", {} channel {}", band, channel);
+        }
+        text
+    }
+
+    /// Reasons this link is likely to cause dropouts
+    pub fn warnings(&self) -> Vec<&'static str> {
+        let mut warnings = Vec::new();
+        if self.band() == Some("2.4 GHz") {
+            warnings.push("PC is on 2.4 GHz Wi-Fi, which is slow and crowded. Use a 5 GHz network or Ethernet if you can.");
+        }
+        if self.signal_quality < WEAK_SIGNAL_QUALITY || self.rssi_dbm.is_some_and(|r| r < WEAK_RSSI_DBM) {
+            warnings.push("Wi-Fi signal is weak. Move closer to the router or use Ethernet.");
+        }
+        warnings
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub use imp::current;
+
+#[cfg(not(target_os = "windows"))]
+pub use fallback::current;
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use super::WifiInfo;
+    use anyhow::{anyhow, Result};
+    use std::ptr;
+    use windows_sys::Win32::Foundation::{ERROR_SUCCESS, HANDLE};
+    use windows_sys::Win32::NetworkManagement::WiFi::{
+        wlan_interface_state_connected, wlan_intf_opcode_current_connection, WlanCloseHandle,
+        WlanEnumInterfaces, WlanFreeMemory, WlanGetNetworkBssList, WlanOpenHandle, WlanQueryInterface,
+        WLAN_BSS_LIST, WLAN_CONNECTION_ATTRIBUTES, WLAN_INTERFACE_INFO_LIST,
+    };
+
+    const WLAN_API_VERSION_2: u32 = 2;
+
+    /// The first connected wireless interface, or None when the PC isn't on Wi-Fi
+    pub fn current() -> Result<Option<WifiInfo>> {
+        unsafe {
+            let mut version = 0;
+            let mut handle: HANDLE = ptr::null_mut();
+            let err = WlanOpenHandle(WLAN_API_VERSION_2, ptr::null(), &mut version, &mut handle);
+            if err != ERROR_SUCCESS {
+                // No WLAN service means no Wi-Fi adapter
+                return Ok(None);
+            }
+
+            let result = query(handle);
+            WlanCloseHandle(handle, ptr::null());
+            result
+        }
+    }
+
+    unsafe fn query(handle: HANDLE) -> Result<Option<WifiInfo>> {
+        let mut list: *mut WLAN_INTERFACE_INFO_LIST = ptr::null_mut();
+        let err = WlanEnumInterfaces(handle, ptr::null(), &mut list);
+        if err != ERROR_SUCCESS {
+            return Err(anyhow!("WlanEnumInterfaces failed ({})", err));
+        }
+
+        let count = (*list).dwNumberOfItems as usize;
+        let interfaces = std::slice::from_raw_parts((*list).InterfaceInfo.as_ptr(), count);
+        let mut found = None;
+
+        for interface in interfaces.iter().filter(|i| i.isState == wlan_interface_state_connected) {
+            let mut size = 0;
+            let mut data: *mut core::ffi::c_void = ptr::null_mut();
+            let err = WlanQueryInterface(
+                handle,
+                &interface.InterfaceGuid,
+                wlan_intf_opcode_current_connection,
+                ptr::null(),
+                &mut size,
+                &mut data,
+                ptr::null_mut(),
+            );
+            if err != ERROR_SUCCESS {
+                continue;
+            }
+
+            let connection = &*(data as *const WLAN_CONNECTION_ATTRIBUTES);
+            let assoc = &connection.wlanAssociationAttributes;
+            let ssid_len = (assoc.dot11Ssid.uSSIDLength as usize).min(assoc.dot11Ssid.ucSSID.len());
+            let mut info = WifiInfo {
+                ssid: String::from_utf8_lossy(&assoc.dot11Ssid.ucSSID[..ssid_len]).into_owned(),
+                signal_quality: assoc.wlanSignalQuality,
+                rssi_dbm: None,
+                frequency_mhz: None,
+                bssid: Some(assoc.dot11Bssid),
+            };
+
+            // The connection attributes don't include the frequency; the BSS entry
+            // for the associated access point does
+            let mut bss_list: *mut WLAN_BSS_LIST = ptr::null_mut();
+            let err = WlanGetNetworkBssList(
+                handle,
+                &interface.InterfaceGuid,
+                &assoc.dot11Ssid,
+                assoc.dot11BssType,
+                connection.wlanSecurityAttributes.bSecurityEnabled,
+                ptr::null(),
+                &mut bss_list,
+            );
+            if err == ERROR_SUCCESS {
+                let entries = std::slice::from_raw_parts(
+                    (*bss_list).wlanBssEntries.as_ptr(),
+                    (*bss_list).dwNumberOfItems as usize,
+                );
+                if let Some(entry) = entries.iter().find(|e| e.dot11Bssid == assoc.dot11Bssid) {
+                    info.rssi_dbm = Some(entry.lRssi);
+                    info.frequency_mhz = Some(entry.ulChCenterFrequency / 1000);
+                }
+                WlanFreeMemory(bss_list as *const _);
+            }
+
+            WlanFreeMemory(data);
+            found = Some(info);
+            break;
+        }
+
+        WlanFreeMemory(list as *const _);
+        Ok(found)
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod fallback {
+    use super::WifiInfo;
+    use anyhow::Result;
+
+    pub fn current() -> Result<Option<WifiInfo>> {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(frequency_mhz: u32) -> WifiInfo {
+        WifiInfo { ssid: String::new(), signal_quality: 100, rssi_dbm: None, frequency_mhz: Some(frequency_mhz), bssid: None }
+    }
+
+    #[test]
+    fn channels_only_for_known_bands() {
+        assert_eq!((at(2412).channel(), at(2484).channel(), at(5180).channel(), at(5955).channel()), (Some(1), Some(14), Some(36), Some(1)));
+        assert_eq!((at(4940).band(), at(4940).channel()), (None, None));
+        assert_eq!((at(0).band(), at(0).channel()), (None, None));
+        // Below the first 2.4 GHz channel's center, but inside the band
+        assert_eq!(at(2401).channel(), None);
+    }
+}
@@ -0,0 +1,117 @@
+// Window size and position across launches, kept in settings.txt as
+// window=x,y,width,height,scale,maximized (points, and the display scale they
+// were measured at). A monitor unplugged since, or a smaller resolution, could
+// leave the saved spot off screen, so the title bar has to land on the desktop
+// for the position to be used, and the size shrinks to fit. The desktop is the
+// box around every monitor, so a gap between monitors of different sizes still
+// passes.
+
+pub const DEFAULT_SIZE: [f32; 2] = [400.0, 500.0];
+pub const MIN_SIZE: [f32; 2] = [350.0, 450.0];
+/// Pixels of title bar that must be on the desktop to grab the window by
+const GRAB_WIDTH: f32 = 100.0;
+const TITLE_HEIGHT: f32 = 30.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Geometry {
+    pub position: [f32; 2],
+    pub size: [f32; 2],
+    /// Pixels per point when saved
+    pub scale: f32,
+    pub maximized: bool,
+}
+
+/// The desktop in pixels: x, y, width, height
+pub type Desktop = [f32; 4];
+
+impl Geometry {
+    pub fn parse(text: &str) -> Option<Self> {
+        let fields: Vec<&str> = text.split(',').map(str::trim).collect();
+        let [x, y, width, height, scale, maximized] = fields.as_slice() else {
+            return None;
+        };
+        let number = |field: &str| field.parse::<f32>().ok().filter(|v| v.is_finite());
+        Some(Self {
+            position: [number(x)?, number(y)?],
+            size: [number(width)?, number(height)?],
+            scale: number(scale).filter(|s| *s > 0.0)?,
+            maximized: *maximized == "1",
+        })
+    }
+
+    pub fn to_setting(self) -> String {
+        format!(
+            "{},{},{},{},{},{}",
+            self.position[0], self.position[1], self.size[0], self.size[1], self.scale, self.maximized as u8
+        )
+    }
+
+    /// The size to open at, and the position unless its title bar would be off
+    /// the desktop. Without a desktop to check against, the position is kept.
+    pub fn fit(&self, desktop: Option<Desktop>) -> (Option<[f32; 2]>, [f32; 2]) {
+        let Some([left, top, width, height]) = desktop else {
+            return (Some(self.position), self.size);
+        };
+        let size = [
+            self.size[0].min(width / self.scale).max(MIN_SIZE[0]),
+            self.size[1].min(height / self.scale).max(MIN_SIZE[1]),
+        ];
+        let [x, y] = self.position.map(|v| v * self.scale);
+        let visible_width = (x + size[0] * self.scale).min(left + width) - x.max(left);
+        let visible_height = (y + TITLE_HEIGHT).min(top + height) - y.max(top);
+        let on_desktop = visible_width >= GRAB_WIDTH && visible_height >= TITLE_HEIGHT;
+        (on_desktop.then_some(self.position), size)
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub fn desktop() -> Option<Desktop> {
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        GetSystemMetrics, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN, SM_XVIRTUALSCREEN, SM_YVIRTUALSCREEN,
+    };
+    let metric = |index| unsafe { GetSystemMetrics(index) } as f32;
+    let desktop = [
+        metric(SM_XVIRTUALSCREEN),
+        metric(SM_YVIRTUALSCREEN),
+        metric(SM_CXVIRTUALSCREEN),
+        metric(SM_CYVIRTUALSCREEN),
+    ];
+    (desktop[2] > 0.0 && desktop[3] > 0.0).then_some(desktop)
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn desktop() -> Option<Desktop> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restores_only_what_fits_the_desktop() {
+        let saved = Geometry::parse("2100,140,520,640,1.5,0").unwrap();
+        assert_eq!(saved.to_setting(), "2100,140,520,640,1.5,0");
+        assert_eq!(Geometry::parse("1,2,3"), None);
+        assert_eq!(Geometry::parse("1,2,3,4,0,0"), None);
+
+        // Saved on a second monitor to the right of a 1920-pixel-wide one
+        let both = [0.0, 0.0, 5760.0, 2160.0];
+        assert_eq!(saved.fit(Some(both)), (Some([2100.0, 140.0]), [520.0, 640.0]));
+
+        // That monitor is gone: open where Windows puts it
+        let laptop = [0.0, 0.0, 1920.0, 1080.0];
+        assert_eq!(saved.fit(Some(laptop)), (None, [520.0, 640.0]));
+
+        // A monitor to the left has negative coordinates; a small screen shrinks the window
+        let left = Geometry { position: [-900.0, 20.0], size: [900.0, 1200.0], scale: 1.0, maximized: false };
+        assert_eq!(left.fit(Some([-1280.0, 0.0, 3200.0, 1024.0])), (Some([-900.0, 20.0]), [900.0, 1024.0]));
+
+        // Only a sliver of title bar showing, or the title bar above the top edge
+        let sliver = Geometry { position: [1880.0, 100.0], ..left };
+        assert_eq!(sliver.fit(Some(laptop)).0, None);
+        let above = Geometry { position: [100.0, -20.0], ..left };
+        assert_eq!(above.fit(Some(laptop)).0, None);
+        assert_eq!(above.fit(None).0, Some([100.0, -20.0]));
+    }
+}